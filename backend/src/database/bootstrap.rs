@@ -0,0 +1,289 @@
+//! Creates a new cohort database using an existing cohort as a template.
+//!
+//! Cohort databases follow the `classroom_<name>.db` naming used by the
+//! `migrate` binary. Structure and cohort-wide settings such as rubric notes
+//! and branding are copied, never participant data.
+
+use crate::database::paths::data_paths;
+use crate::database::pool::{open_connection, open_read_only};
+use crate::database::schema::run_migrations;
+use crate::utils::types::AppError;
+use log::info;
+use rusqlite::types::Value;
+use rusqlite::{Connection, params, params_from_iter};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// Core tables created by the migrate binary, copied schema-only
+const CORE_TABLES: &[&str] = &["participants", "students"];
+
+// Cohort-wide settings copied row for row from the template
+const TEMPLATE_TABLES: &[(&str, &str)] = &[
+    ("rubric_notes", "grading notes per rubric criterion"),
+    (
+        "cohort_branding",
+        "program name, logo, signature and certificate text",
+    ),
+];
+
+// Template parts kept outside the cohort database, the same for every cohort
+const SHARED: &[(&str, &str)] = &[
+    (
+        "exercise_catalog",
+        "Classroom assignments come from CLASSROOM_ASSIGNMENTS",
+    ),
+    (
+        "notification_templates",
+        "mail templates are built into this deployment",
+    ),
+];
+
+pub fn cohort_db_path(cohort: &str) -> PathBuf {
+    data_paths().cohort_db(cohort)
+}
+
+pub fn valid_cohort_name(cohort: &str) -> bool {
+    !cohort.is_empty()
+        && cohort
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlanItem {
+    pub item: String,
+    pub detail: String,
+}
+
+// What bootstrapping will create, returned for review before applying
+#[derive(Debug, Serialize)]
+pub struct BootstrapPlan {
+    pub cohort: String,
+    pub template: String,
+    pub database: String,
+    pub weeks: Vec<i32>,
+    pub ta_roster: BTreeMap<String, String>,
+    pub create: Vec<PlanItem>,
+    pub skipped: Vec<PlanItem>,
+    #[serde(skip)]
+    core_schema: Vec<String>,
+    #[serde(skip)]
+    copied: Vec<TemplateRows>,
+}
+
+// Rows of a template table, with the columns they hold
+#[derive(Debug)]
+struct TemplateRows {
+    table: &'static str,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+fn read_rows(conn: &Connection, table: &'static str) -> Result<TemplateRows, AppError> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {}", table))?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let rows = stmt
+        .query_map([], |row| {
+            (0..columns.len())
+                .map(|index| row.get(index))
+                .collect::<Result<Vec<Value>, _>>()
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(TemplateRows {
+        table,
+        columns,
+        rows,
+    })
+}
+
+pub(crate) fn table_exists(conn: &Connection, table: &str) -> Result<bool, AppError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+// Includes generated columns, which table_info leaves out
+pub(crate) fn column_exists(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+) -> Result<bool, AppError> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_xinfo(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+// Prefers the template's recorded schedule and roster, falling back to the
+// weeks and group assignments found in its student rows
+fn read_template(conn: &Connection) -> Result<(Vec<i32>, BTreeMap<String, String>), AppError> {
+    let mut weeks = Vec::new();
+    let mut roster = BTreeMap::new();
+
+    if table_exists(conn, "cohort_weeks")? {
+        let mut stmt = conn.prepare("SELECT week FROM cohort_weeks ORDER BY week")?;
+        weeks = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<i32>, _>>()?;
+    }
+    if table_exists(conn, "cohort_groups")? {
+        let mut stmt = conn.prepare("SELECT group_id, ta FROM cohort_groups")?;
+        roster = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<BTreeMap<String, String>, _>>()?;
+    }
+
+    if table_exists(conn, "students")? {
+        if weeks.is_empty() {
+            let mut stmt =
+                conn.prepare("SELECT DISTINCT week FROM students WHERE week >= 1 ORDER BY week")?;
+            weeks = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<i32>, _>>()?;
+        }
+        if roster.is_empty() {
+            let mut stmt = conn.prepare(
+                "SELECT group_id, MAX(ta) FROM students WHERE group_id IS NOT NULL AND ta IS NOT NULL GROUP BY group_id",
+            )?;
+            roster = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<BTreeMap<String, String>, _>>()?;
+        }
+    }
+
+    Ok((weeks, roster))
+}
+
+pub fn plan_bootstrap(template: &str, cohort: &str) -> Result<BootstrapPlan, AppError> {
+    let template_path = cohort_db_path(template);
+    let conn = open_read_only(&template_path)?;
+
+    let mut core_schema = Vec::new();
+    let mut create = Vec::new();
+    for table in CORE_TABLES {
+        let sql: Option<String> = conn
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |row| row.get(0),
+            )
+            .ok();
+        if let Some(sql) = sql {
+            create.push(PlanItem {
+                item: format!("table:{}", table),
+                detail: "empty table with the template schema".to_string(),
+            });
+            core_schema.push(sql);
+        }
+    }
+    create.push(PlanItem {
+        item: "schema_migrations".to_string(),
+        detail: "attendance, communications, checklist and security tables".to_string(),
+    });
+
+    let (weeks, ta_roster) = read_template(&conn)?;
+    create.push(PlanItem {
+        item: "week_schedule".to_string(),
+        detail: format!("{} week(s)", weeks.len()),
+    });
+    create.push(PlanItem {
+        item: "ta_roster".to_string(),
+        detail: format!("{} group(s) with assigned TAs", ta_roster.len()),
+    });
+
+    let mut copied = Vec::new();
+    let mut skipped: Vec<PlanItem> = SHARED
+        .iter()
+        .map(|(item, detail)| PlanItem {
+            item: item.to_string(),
+            detail: detail.to_string(),
+        })
+        .collect();
+    for (table, what) in TEMPLATE_TABLES {
+        let rows = match table_exists(&conn, table)? {
+            true => read_rows(&conn, table)?,
+            false => TemplateRows {
+                table,
+                columns: Vec::new(),
+                rows: Vec::new(),
+            },
+        };
+        if rows.rows.is_empty() {
+            skipped.push(PlanItem {
+                item: format!("table:{}", table),
+                detail: format!("the template has no {}", what),
+            });
+        } else {
+            create.push(PlanItem {
+                item: format!("table:{}", table),
+                detail: format!("{} row(s) of {} from the template", rows.rows.len(), what),
+            });
+            copied.push(rows);
+        }
+    }
+
+    Ok(BootstrapPlan {
+        cohort: cohort.to_string(),
+        template: template.to_string(),
+        database: cohort_db_path(cohort).display().to_string(),
+        weeks,
+        ta_roster,
+        create,
+        skipped,
+        core_schema,
+        copied,
+    })
+}
+
+pub fn apply_bootstrap(plan: &BootstrapPlan) -> Result<(), AppError> {
+    let path = PathBuf::from(&plan.database);
+    {
+        let mut conn = open_connection(&path)?;
+        let tx = conn.transaction()?;
+        for sql in &plan.core_schema {
+            tx.execute_batch(sql)?;
+        }
+        tx.commit()?;
+    }
+
+    run_migrations(&path)?;
+
+    let mut conn = open_connection(&path)?;
+    let tx = conn.transaction()?;
+    for week in &plan.weeks {
+        tx.execute(
+            "INSERT OR IGNORE INTO cohort_weeks (week) VALUES (?1)",
+            params![week],
+        )?;
+    }
+    for (group_id, ta) in &plan.ta_roster {
+        tx.execute(
+            "INSERT OR REPLACE INTO cohort_groups (group_id, ta) VALUES (?1, ?2)",
+            params![group_id, ta],
+        )?;
+    }
+    for copied in &plan.copied {
+        let sql = format!(
+            "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+            copied.table,
+            copied.columns.join(", "),
+            vec!["?"; copied.columns.len()].join(", ")
+        );
+        for row in &copied.rows {
+            tx.execute(&sql, params_from_iter(row))?;
+        }
+    }
+    tx.commit()?;
+
+    info!(
+        "Bootstrapped cohort {} from {} into {}",
+        plan.cohort, plan.template, plan.database
+    );
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! At-rest encryption for the `students.mail` column.
+//!
+//! Enabled by `MAIL_ENCRYPTION_KEY` (32 bytes, hex). Values are stored as
+//! `enc:v1:<hex nonce + ciphertext>` using AES-256-GCM. The nonce is derived
+//! from the address itself, so the same address always encrypts to the same
+//! value and equality lookups (`WHERE mail = ?`) keep working on encrypted
+//! columns. Unprefixed values are plaintext rows not yet migrated.
+
+use crate::utils::types::AppError;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use std::sync::OnceLock;
+
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+static MAIL_CIPHER: OnceLock<Option<FieldCipher>> = OnceLock::new();
+
+pub struct FieldCipher {
+    cipher: Aes256Gcm,
+    nonce_key: Vec<u8>,
+}
+
+impl FieldCipher {
+    pub fn from_hex_key(key: &str) -> Result<Self, String> {
+        let key = hex::decode(key.trim())
+            .ok()
+            .filter(|k| k.len() == 32)
+            .ok_or("MAIL_ENCRYPTION_KEY must be 32 bytes encoded as 64 hex characters")?;
+
+        // Separate key for nonce derivation so the AES key is never reused
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC accepts any key length");
+        mac.update(b"mail-nonce");
+        Ok(FieldCipher {
+            cipher: Aes256Gcm::new_from_slice(&key).expect("key length checked above"),
+            nonce_key: mac.finalize().into_bytes().to_vec(),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        if plaintext.is_empty() || plaintext.starts_with(PREFIX) {
+            return plaintext.to_string();
+        }
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.nonce_key)
+            .expect("HMAC accepts any key length");
+        mac.update(plaintext.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let nonce = Nonce::from_slice(&digest[..NONCE_LEN]);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(nonce, plaintext.as_bytes())
+            .expect("AES-GCM encryption of in-memory data cannot fail");
+        let mut stored = nonce.to_vec();
+        stored.extend(ciphertext);
+        format!("{}{}", PREFIX, hex::encode(stored))
+    }
+
+    pub fn decrypt(&self, stored: &str) -> Result<String, AppError> {
+        let Some(encoded) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let invalid = || AppError::Encryption("invalid encrypted mail value".to_string());
+        let bytes = hex::decode(encoded).map_err(|_| invalid())?;
+        if bytes.len() <= NONCE_LEN {
+            return Err(invalid());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| AppError::Encryption("mail decryption failed, wrong key?".to_string()))?;
+        String::from_utf8(plaintext).map_err(|_| invalid())
+    }
+}
+
+// Reads MAIL_ENCRYPTION_KEY once at startup; returns whether encryption is on
+pub fn init_mail_encryption() -> Result<bool, String> {
+    let cipher = match env::var("MAIL_ENCRYPTION_KEY") {
+        Ok(key) if !key.is_empty() => Some(FieldCipher::from_hex_key(&key)?),
+        _ => None,
+    };
+    let enabled = cipher.is_some();
+    let _ = MAIL_CIPHER.set(cipher);
+    Ok(enabled)
+}
+
+pub(crate) fn mail_cipher() -> Option<&'static FieldCipher> {
+    MAIL_CIPHER.get().and_then(Option::as_ref)
+}
+
+pub fn encrypt_mail(mail: &str) -> String {
+    match mail_cipher() {
+        Some(cipher) => cipher.encrypt(mail),
+        None => mail.to_string(),
+    }
+}
+
+pub fn decrypt_mail(stored: String) -> Result<String, AppError> {
+    match mail_cipher() {
+        Some(cipher) => cipher.decrypt(&stored),
+        None if stored.starts_with(PREFIX) => Err(AppError::Encryption(
+            "mail is encrypted but MAIL_ENCRYPTION_KEY is not set".to_string(),
+        )),
+        None => Ok(stored),
+    }
+}
@@ -31,10 +31,60 @@ use csv::Reader;
 use rusqlite::{Connection, params};
 use std::env;
 use std::error::Error;
+use std::path::Path;
+
+// The participants and students tables as this tool creates them. The
+// server only adds to them, see `migrate_core_tables`.
+pub const CORE_TABLES: &str = r#"
+    CREATE TABLE participants(
+        "ID"               TEXT PRIMARY KEY,
+        "Name"             TEXT,
+        "Token"            TEXT,
+        "Enrolled"         INTEGER,
+        "Role"             TEXT,
+        "Email"            TEXT,
+        "Describe Yourself" TEXT,
+        "Background"       TEXT,
+        "GitHub"           TEXT,
+        "Skills"           TEXT,
+        "Year"             TEXT,
+        "Books"            TEXT,
+        "Why"              TEXT,
+        "Time"             TEXT,
+        "Location"         TEXT,
+        "Version"          INTEGER,
+        "Cohort Name"      TEXT,
+        "Created At"       TEXT,
+        "Updated At"       TEXT
+    );
+    CREATE TABLE students (
+        name                        TEXT NOT NULL,
+        group_id                    TEXT,
+        ta                          TEXT,
+        attendance                  TEXT,
+        fa                          REAL,
+        fb                          REAL,
+        fc                          REAL,
+        fd                          REAL,
+        bonus_attempt               REAL,
+        bonus_answer_quality        REAL,
+        bonus_follow_up             REAL,
+        exercise_submitted          TEXT,
+        exercise_test_passing       TEXT,
+        exercise_good_documentation TEXT,
+        exercise_good_structure     TEXT,
+        total                       REAL,
+        mail                        TEXT, 
+        GitHub                      TEXT,
+        week                        INTEGER,
+        participant_id              TEXT REFERENCES participants("ID")
+    );
+"#;
 
 // A structure used to get participant information from the table
 #[allow(dead_code)]
 struct ParticipantInfo {
+    id: Option<String>,
     name: String,
     email: String,
     github: String,
@@ -72,12 +122,18 @@ impl Cohort {
         }
     }
 
+    // Next to the live database, where the server looks for cohort databases
     fn db_name(&self) -> String {
-        match self {
-            Cohort::BPD => "classroom_bpd.db".to_string(),
-            Cohort::PB => "classroom_pb.db".to_string(),
-            Cohort::LBTCL => "classroom_lbtcl.db".to_string(),
-            Cohort::MB => "classroom_mb.db".to_string(),
+        let file = match self {
+            Cohort::BPD => "classroom_bpd.db",
+            Cohort::PB => "classroom_pb.db",
+            Cohort::LBTCL => "classroom_lbtcl.db",
+            Cohort::MB => "classroom_mb.db",
+        };
+        let database = env::var("DATABASE_PATH").unwrap_or_default();
+        match Path::new(database.trim()).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join(file).display().to_string(),
+            _ => file.to_string(),
         }
     }
 
@@ -93,6 +149,8 @@ impl Cohort {
 
 #[allow(dead_code)]
 fn main() -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+
     // Parse command line arguments
     let args: Vec<String> = env::args().collect();
     if args.len() != 2 {
@@ -128,53 +186,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // TODO: Change fa, fb, fc to their actual values.
     // Create tables
-    conn.execute_batch(
-        r#"
-        CREATE TABLE participants(
-            "ID"               TEXT PRIMARY KEY,
-            "Name"             TEXT,
-            "Token"            TEXT,
-            "Enrolled"         INTEGER,
-            "Role"             TEXT,
-            "Email"            TEXT,
-            "Describe Yourself" TEXT,
-            "Background"       TEXT,
-            "GitHub"           TEXT,
-            "Skills"           TEXT,
-            "Year"             TEXT,
-            "Books"            TEXT,
-            "Why"              TEXT,
-            "Time"             TEXT,
-            "Location"         TEXT,
-            "Version"          INTEGER,
-            "Cohort Name"      TEXT,
-            "Created At"       TEXT,
-            "Updated At"       TEXT
-        );
-        CREATE TABLE students (
-            name                        TEXT NOT NULL,
-            group_id                    TEXT,
-            ta                          TEXT,
-            attendance                  TEXT,
-            fa                          REAL,
-            fb                          REAL,
-            fc                          REAL,
-            fd                          REAL,
-            bonus_attempt               REAL,
-            bonus_answer_quality        REAL,
-            bonus_follow_up             REAL,
-            exercise_submitted          TEXT,
-            exercise_test_passing       TEXT,
-            exercise_good_documentation TEXT,
-            exercise_good_structure     TEXT,
-            total                       REAL,
-            mail                        TEXT, 
-            GitHub                      TEXT,
-            week                        INTEGER
-        );
-
-    "#,
-    )?;
+    conn.execute_batch(CORE_TABLES)?;
     println!("Created tables: participants, students");
 
     // Read from cohort-specific CSV file
@@ -238,13 +250,14 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Fetch participant names and Email addresses from the participants table
     let mut stmt_fetch_participants =
-        conn.prepare("SELECT \"Name\", \"Email\", \"Github\" FROM participants")?;
+        conn.prepare("SELECT \"ID\", \"Name\", \"Email\", \"Github\" FROM participants")?;
     let participants_iter = stmt_fetch_participants
         .query_map([], |row| {
             Ok(ParticipantInfo {
-                name: row.get(0)?,
-                email: row.get(1)?,
-                github: row.get(2)?,
+                id: row.get(0)?,
+                name: row.get(1)?,
+                email: row.get(2)?,
+                github: row.get(3)?,
             })
         })?
         .collect::<Result<Vec<_>, _>>()?;
@@ -256,8 +269,8 @@ fn main() -> Result<(), Box<dyn Error>> {
             fa, fb, fc, fd,
             bonus_attempt, bonus_answer_quality, bonus_follow_up,
             exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure,
-            total, mail, github, week
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18 , ?19)
+            total, mail, github, week, participant_id
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18 , ?19, ?20)
         "#,
     )?;
 
@@ -284,7 +297,8 @@ fn main() -> Result<(), Box<dyn Error>> {
             0.0,
             participant.email,
             participant.github,
-            0
+            0,
+            participant.id
         ]) {
             Ok(count) if count > 0 => student_records_created += 1,
             Ok(_) => { /* Potentially a conflict, and ON CONFLICT DO NOTHING was triggered */ }
@@ -1,2 +1,10 @@
+pub mod bootstrap;
+pub mod encryption;
 pub mod migrate;
 pub mod operations;
+pub mod paths;
+pub mod pool;
+pub mod postgres;
+pub mod retention;
+pub mod schema;
+pub mod storage;
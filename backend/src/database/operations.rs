@@ -1,113 +1,1702 @@
-use crate::utils::types::{AppError, CohortParticipant, FeedbackResponse, RowData, Table};
-use chrono::Utc;
+use crate::database::bootstrap::{column_exists, table_exists};
+use crate::database::encryption::{decrypt_mail, encrypt_mail, mail_cipher};
+use crate::database::migrate::CORE_TABLES;
+use crate::database::pool::{DbPool, create_memory_pool, open_connection, open_read_only};
+use crate::database::schema::{apply_migrations, delete_orphaned_details, move_row_details};
+use crate::database::storage::{
+    Storage, checklist_items, contains_pattern, github_login, invite_pending, split_members,
+    split_points,
+};
+use crate::utils::ids::{is_public_id, new_public_id};
+use crate::utils::types::{
+    Announcement, AppError, BackgroundData, Branding, ChangedRows, ChecklistItem,
+    CohortParticipant, Communication, CommunicationKind, ConstraintKind, DeletedRow,
+    ExerciseAttempt, ExerciseOutcome, FeedbackResponse, GroupThread, GroupingConstraint,
+    IdempotentResponse, MaintenanceReport, Member, ParticipantMatch, ReviewSummary, RowChange,
+    RowData, RowHistoryEntry, RubricNote, SyncRun, TaInvite, TaProfile, TaSetup, Table,
+    VoiceAttendee, WeekLock, WeekTask,
+};
+use chrono::{DateTime, Utc};
 use log::info;
-use rusqlite::{Connection, Result, params};
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName, OptionalExtension, Result, TransactionBehavior, params};
 use serde_json;
-use std::path::PathBuf;
-
-pub fn read_from_db(path: &PathBuf) -> Result<Table, AppError> {
-    info!("Reading from DB at path: {:?}", path);
-    let conn = Connection::open(path)?;
-
-    let mut stmt = conn.prepare("SELECT name, group_id, ta, attendance, CAST(fa as INTEGER) as fa, CAST(fb as INTEGER) as fb, CAST(fc as INTEGER) as fc, CAST(fd as INTEGER) as fd, CAST(bonus_attempt as INTEGER) as bonus_attempt, CAST(bonus_answer_quality as INTEGER) as bonus_answer_quality, CAST(bonus_follow_up as INTEGER) as bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, CAST(total as INTEGER) as total, mail, week FROM students")?;
-
-    let rows = stmt.query_map([], |row| {
-        Ok(RowData {
-            name: row.get(0)?,
-            group_id: row.get(1)?,
-            ta: row.get(2)?,
-            attendance: row.get(3)?,
-            fa: row.get(4)?,
-            fb: row.get(5)?,
-            fc: row.get(6)?,
-            fd: row.get(7)?,
-            bonus_attempt: row.get(8)?,
-            bonus_answer_quality: row.get(9)?,
-            bonus_follow_up: row.get(10)?,
-            exercise_submitted: row.get(11)?,
-            exercise_test_passing: row.get(12)?,
-            exercise_good_documentation: row.get(13)?,
-            exercise_good_structure: row.get(14)?,
-            total: row.get(15)?,
-            mail: row.get(16)?,
-            week: row.get(17)?,
-        })
-    })?;
-
-    let rows_vec: Vec<RowData> = rows.collect::<Result<Vec<_>, _>>()?;
-    info!(
-        "Successfully read {} rows from the database.",
-        rows_vec.len()
-    );
-    Ok(Table { rows: rows_vec })
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Columns of a student row in `RowData` order, scores read as integers
+const STUDENT_COLUMNS: &str = "name, group_id, ta, attendance, CAST(fa as INTEGER) as fa, CAST(fb as INTEGER) as fb, CAST(fc as INTEGER) as fc, CAST(fd as INTEGER) as fd, CAST(bonus_attempt as INTEGER) as bonus_attempt, CAST(bonus_answer_quality as INTEGER) as bonus_answer_quality, CAST(bonus_follow_up as INTEGER) as bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, CAST(total as INTEGER) as total, mail, week";
+
+fn student_from_row(row: &rusqlite::Row) -> Result<RowData> {
+    Ok(RowData {
+        name: row.get(0)?,
+        group_id: row.get(1)?,
+        ta: row.get(2)?,
+        attendance: row.get(3)?,
+        fa: row.get(4)?,
+        fb: row.get(5)?,
+        fc: row.get(6)?,
+        fd: row.get(7)?,
+        bonus_attempt: row.get(8)?,
+        bonus_answer_quality: row.get(9)?,
+        bonus_follow_up: row.get(10)?,
+        exercise_submitted: row.get(11)?,
+        exercise_test_passing: row.get(12)?,
+        exercise_good_documentation: row.get(13)?,
+        exercise_good_structure: row.get(14)?,
+        total: row.get(15)?,
+        notes: None,
+        mail: row.get(16)?,
+        week: row.get(17)?,
+        participant_id: None,
+        public_id: None,
+        student_id: None,
+        version: None,
+        commit_count: None,
+        last_commit_at: None,
+        repo_url: None,
+    })
 }
 
-pub fn write_to_db(path: &PathBuf, table: &Table) -> Result<(), AppError> {
-    info!("Writing to DB at path: {:?}", path);
-    let mut conn = Connection::open(path)?;
-    let tx = conn.transaction()?;
+// A row of the live students table, selected as `STUDENT_COLUMNS,
+// participant_id, public_id, version, notes, commit_count, last_commit_at,
+// repo_url`. Archives of older cohorts may
+// not have the columns.
+fn live_student_from_row(row: &rusqlite::Row) -> Result<RowData> {
+    Ok(RowData {
+        participant_id: row.get(18)?,
+        public_id: row.get(19)?,
+        version: row.get(20)?,
+        notes: row.get(21)?,
+        commit_count: row.get(22)?,
+        last_commit_at: row.get(23)?,
+        repo_url: row.get(24)?,
+        ..student_from_row(row)?
+    })
+}
+
+// Sets the public id of each row's student, from its participant
+fn fill_student_ids(conn: &Connection, rows: &mut [RowData]) -> Result<(), AppError> {
+    if !table_exists(conn, "participants")?
+        || !column_exists(conn, "participants", "ID")?
+        || !column_exists(conn, "participants", "public_id")?
+    {
+        return Ok(());
+    }
+    let mut stmt =
+        conn.prepare("SELECT \"ID\", public_id FROM participants WHERE public_id IS NOT NULL")?;
+    let ids = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<HashMap<String, String>, _>>()?;
+    for row in rows {
+        row.student_id = row
+            .participant_id
+            .as_ref()
+            .and_then(|id| ids.get(id))
+            .cloned();
+    }
+    Ok(())
+}
+
+const UPDATE_STUDENT: &str = "UPDATE students SET group_id = ?2, ta = ?3, attendance = ?4, fa = ?5, fb = ?6, fc = ?7, fd = ?8, bonus_attempt = ?9, bonus_answer_quality = ?10, bonus_follow_up = ?11, exercise_submitted = ?12, exercise_test_passing = ?13, exercise_good_documentation = ?14, exercise_good_structure = ?15, total = ?16, mail = ?17, name = ?1, participant_id = COALESCE(?19, participant_id), public_id = COALESCE(public_id, ?20), version = COALESCE(?21, version + 1), notes = ?22, updated_at = ?23, commit_count = ?24, last_commit_at = ?25, repo_url = ?26, deleted_at = NULL";
+
+// Updates existing rows and inserts missing ones, keyed by (name, week) or
+// by (participant, week) when the row knows its participant, in which case
+// the stored name follows a rename. A row read back with its public id is
+// matched by that first. Writing a soft-deleted row brings it
+// back. The attendance, scores and exercise fields of linked rows go to
+// their own tables, see `move_row_details`.
+fn upsert_students(conn: &Connection, rows: &[RowData]) -> Result<(), AppError> {
+    let now = Utc::now().to_rfc3339();
+    for row in rows {
+        let mail = encrypt_mail(&row.mail);
+        // Kept by rows that already have one
+        let public_id = row
+            .public_id
+            .clone()
+            .filter(|id| is_public_id(id))
+            .unwrap_or_else(new_public_id);
+
+        let values = params![
+            row.name,
+            row.group_id,
+            row.ta,
+            row.attendance,
+            row.fa,
+            row.fb,
+            row.fc,
+            row.fd,
+            row.bonus_attempt,
+            row.bonus_answer_quality,
+            row.bonus_follow_up,
+            row.exercise_submitted,
+            row.exercise_test_passing,
+            row.exercise_good_documentation,
+            row.exercise_good_structure,
+            row.total,
+            mail,
+            row.week,
+            row.participant_id,
+            public_id,
+            row.version,
+            row.notes,
+            now,
+            row.commit_count,
+            row.last_commit_at,
+            row.repo_url
+        ];
+        // First, try to update existing record: the same row when it has a
+        // public id, so duplicate rows of a participant stay apart, else the
+        // row of that name or participant in the week
+        let known = row.public_id.as_deref().is_some_and(is_public_id);
+        let mut updated_rows = 0;
+        if known {
+            updated_rows = conn.execute(
+                &format!("{} WHERE public_id = ?20 AND week = ?18", UPDATE_STUDENT),
+                values,
+            )?;
+        }
+        if updated_rows == 0 {
+            updated_rows = conn.execute(
+                &format!(
+                    "{} WHERE (name = ?1 OR participant_id = ?19) AND week = ?18",
+                    UPDATE_STUDENT
+                ),
+                values,
+            )?;
+        }
+
+        if updated_rows == 0 {
+            conn.execute(
+                "INSERT INTO students (name, group_id, ta, attendance, fa, fb, fc, fd, bonus_attempt, bonus_answer_quality, bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, total, mail, week, participant_id, public_id, version, notes, updated_at, commit_count, last_commit_at, repo_url) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, COALESCE(?21, 1), ?22, ?23, ?24, ?25, ?26)",
+                values,
+            )?;
+        }
+        move_row_details(conn, "name = ?1 AND week = ?2", params![row.name, row.week])?;
+    }
+    Ok(())
+}
+
+fn insert_history(conn: &Connection, changes: &[RowChange], actor: &str) -> Result<(), AppError> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO row_history (name, week, field, old_value, new_value, actor, changed_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    for change in changes {
+        stmt.execute(params![
+            change.name,
+            change.week,
+            change.field,
+            change.old_value,
+            change.new_value,
+            actor,
+            change.changed_at
+        ])?;
+    }
+    Ok(())
+}
+
+// The default storage: the live cohort in a local SQLite file
+pub struct SqliteStorage {
+    pool: DbPool,
+    // Serves the read methods. Read-only connections when opened by the
+    // server, so a bug on a read path cannot change the cohort.
+    reader: DbPool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: DbPool) -> Self {
+        SqliteStorage {
+            reader: pool.clone(),
+            pool,
+        }
+    }
+
+    pub fn with_reader(self, reader: DbPool) -> Self {
+        SqliteStorage { reader, ..self }
+    }
+
+    fn memory_pool() -> Result<DbPool, AppError> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "cohort_{}_{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        create_memory_pool(&name)
+    }
+
+    // An empty cohort kept in memory, with the same schema and queries as
+    // the file, for tests that should not touch the disk and for a server
+    // that could not open its database
+    pub fn in_memory() -> Result<Self, AppError> {
+        let pool = Self::memory_pool()?;
+        let mut conn = pool.get()?;
+        conn.execute_batch(CORE_TABLES)?;
+        apply_migrations(&mut conn)?;
+        drop(conn);
+        Ok(SqliteStorage::new(pool))
+    }
+
+    // A copy of a backup kept in memory, brought up to the current schema.
+    // The file itself is only read.
+    pub fn in_memory_copy(snapshot: &Path) -> Result<Self, AppError> {
+        let pool = Self::memory_pool()?;
+        let mut conn = pool.get()?;
+        conn.restore(DatabaseName::Main, snapshot, None::<fn(Progress)>)?;
+        apply_migrations(&mut conn)?;
+        drop(conn);
+        Ok(SqliteStorage::new(pool))
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn read_from_db(&self) -> Result<Table, AppError> {
+        let conn = self.reader.get()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, participant_id, public_id, version, notes, commit_count, last_commit_at, repo_url FROM {} WHERE deleted_at IS NULL",
+            STUDENT_COLUMNS,
+            student_rows(&conn)?
+        ))?;
+
+        let mut rows_vec = stmt
+            .query_map([], live_student_from_row)?
+            .map(|row| {
+                let mut row = row?;
+                row.mail = decrypt_mail(row.mail)?;
+                Ok(row)
+            })
+            .collect::<Result<Vec<RowData>, AppError>>()?;
+        fill_student_ids(&conn, &mut rows_vec)?;
+        info!(
+            "Successfully read {} rows from the database.",
+            rows_vec.len()
+        );
+        Ok(Table::new(rows_vec))
+    }
+
+    fn read_changed_rows(
+        &self,
+        since: DateTime<Utc>,
+        week: Option<i32>,
+    ) -> Result<ChangedRows, AppError> {
+        let conn = self.reader.get()?;
+        let since = since.to_rfc3339();
+        let source = student_rows(&conn)?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, participant_id, public_id, version, notes, commit_count, last_commit_at, repo_url FROM {} WHERE deleted_at IS NULL AND updated_at > ?1 AND (?2 IS NULL OR week = ?2) ORDER BY updated_at",
+            STUDENT_COLUMNS, source
+        ))?;
+        let mut rows = stmt
+            .query_map(params![since, week], live_student_from_row)?
+            .map(|row| {
+                let mut row = row?;
+                row.mail = decrypt_mail(row.mail)?;
+                Ok(row)
+            })
+            .collect::<Result<Vec<RowData>, AppError>>()?;
+        fill_student_ids(&conn, &mut rows)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT name, week FROM students WHERE deleted_at > ?1 AND (?2 IS NULL OR week = ?2) ORDER BY deleted_at",
+        )?;
+        let deleted = stmt
+            .query_map(params![since, week], |row| {
+                Ok(DeletedRow {
+                    name: row.get(0)?,
+                    week: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<DeletedRow>>>()?;
+        Ok(ChangedRows { rows, deleted })
+    }
+
+    fn upsert_rows(&self, rows: &[RowData]) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        upsert_students(&tx, rows)?;
+        tx.commit()?;
+        info!("Successfully wrote {} rows to the database.", rows.len());
+        Ok(())
+    }
+
+    fn write_rows(
+        &self,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        upsert_students(&tx, rows)?;
+        insert_history(&tx, history, actor)?;
+        tx.commit()?;
+        info!("Successfully wrote {} rows to the database.", rows.len());
+        Ok(())
+    }
+
+    fn replace_week(
+        &self,
+        week: i32,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<usize, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let kept: HashSet<&str> = rows.iter().map(|row| row.name.as_str()).collect();
+        let stored: Vec<String> = tx
+            .prepare("SELECT name FROM students WHERE week = ?1 AND deleted_at IS NULL")?
+            .query_map(params![week], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        let now = Utc::now().to_rfc3339();
+        let mut deleted = 0;
+        for name in stored.iter().filter(|name| !kept.contains(name.as_str())) {
+            deleted += tx.execute(
+                "UPDATE students SET deleted_at = ?3 WHERE name = ?1 AND week = ?2 AND deleted_at IS NULL",
+                params![name, week, now],
+            )?;
+        }
+        upsert_students(&tx, rows)?;
+        insert_history(&tx, history, actor)?;
+        tx.commit()?;
+        info!(
+            "Replaced week {} with {} rows, deleting {}.",
+            week,
+            rows.len(),
+            deleted
+        );
+        Ok(deleted)
+    }
+
+    fn merge_rows(
+        &self,
+        duplicate: &str,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<usize, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let deleted = tx.execute("DELETE FROM students WHERE name = ?1", params![duplicate])?;
+        if table_exists(&tx, "participants")? && column_exists(&tx, "participants", "ID")? {
+            let participants: HashSet<(&str, &str)> = rows
+                .iter()
+                .filter_map(|row| Some((row.participant_id.as_deref()?, row.name.as_str())))
+                .collect();
+            for (participant, name) in participants {
+                tx.execute(
+                    "UPDATE participants SET Name = ?1 WHERE \"ID\" = ?2 AND Name = ?3 COLLATE NOCASE",
+                    params![name, participant, duplicate],
+                )?;
+            }
+        }
+        upsert_students(&tx, rows)?;
+        insert_history(&tx, history, actor)?;
+        delete_orphaned_details(&tx)?;
+        tx.commit()?;
+        info!(
+            "Merged {} rows of {} into {} rows.",
+            deleted,
+            duplicate,
+            rows.len()
+        );
+        Ok(deleted)
+    }
+
+    fn delete_rows(&self, name: &str, week: Option<i32>) -> Result<usize, AppError> {
+        let conn = self.pool.get()?;
+        let now = Utc::now().to_rfc3339();
+        let deleted = match week {
+            Some(week) => conn.execute(
+                "UPDATE students SET deleted_at = ?3 WHERE name = ?1 AND week = ?2 AND deleted_at IS NULL",
+                params![name, week, now],
+            )?,
+            None => conn.execute(
+                "UPDATE students SET deleted_at = ?2 WHERE name = ?1 AND deleted_at IS NULL",
+                params![name, now],
+            )?,
+        };
+        info!("Soft-deleted {} rows from the database.", deleted);
+        Ok(deleted)
+    }
+
+    fn purge_rows(&self, name: &str, week: Option<i32>) -> Result<usize, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let deleted = match week {
+            Some(week) => tx.execute(
+                "DELETE FROM students WHERE name = ?1 AND week = ?2",
+                params![name, week],
+            )?,
+            None => tx.execute("DELETE FROM students WHERE name = ?1", params![name])?,
+        };
+        delete_orphaned_details(&tx)?;
+        tx.commit()?;
+        info!("Deleted {} rows from the database.", deleted);
+        Ok(deleted)
+    }
+
+    fn delete_week(&self, week: i32, permanent: bool) -> Result<usize, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let deleted = if permanent {
+            let deleted = tx.execute("DELETE FROM students WHERE week = ?1", params![week])?;
+            delete_orphaned_details(&tx)?;
+            deleted
+        } else {
+            tx.execute(
+                "UPDATE students SET deleted_at = ?2 WHERE week = ?1 AND deleted_at IS NULL",
+                params![week, Utc::now().to_rfc3339()],
+            )?
+        };
+        tx.commit()?;
+        info!(
+            "Deleted {} rows of week {} from the database.",
+            deleted, week
+        );
+        Ok(deleted)
+    }
+
+    fn read_deleted_row(&self, name: &str, week: i32) -> Result<Option<RowData>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, participant_id, public_id, version, notes, commit_count, last_commit_at, repo_url FROM {} WHERE name = ?1 AND week = ?2 AND deleted_at IS NOT NULL",
+            STUDENT_COLUMNS,
+            student_rows(&conn)?
+        ))?;
+        let mut rows = stmt.query_map(params![name, week], live_student_from_row)?;
+        match rows.next().transpose()? {
+            Some(mut row) => {
+                row.mail = decrypt_mail(row.mail)?;
+                fill_student_ids(&conn, std::slice::from_mut(&mut row))?;
+                Ok(Some(row))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn restore_row(&self, name: &str, week: i32) -> Result<bool, AppError> {
+        let conn = self.pool.get()?;
+        let restored = conn.execute(
+            "UPDATE students SET deleted_at = NULL, updated_at = ?3 WHERE name = ?1 AND week = ?2 AND deleted_at IS NOT NULL",
+            params![name, week, Utc::now().to_rfc3339()],
+        )?;
+        Ok(restored > 0)
+    }
+
+    fn read_row_history(&self, name: &str, week: i32) -> Result<Vec<RowHistoryEntry>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, week, field, old_value, new_value, actor, changed_at FROM row_history WHERE name = ?1 AND week = ?2 ORDER BY changed_at DESC, id DESC",
+        )?;
+        let history = stmt
+            .query_map(params![name, week], |row| {
+                Ok(RowHistoryEntry {
+                    id: row.get(0)?,
+                    change: RowChange {
+                        name: row.get(1)?,
+                        week: row.get(2)?,
+                        field: row.get(3)?,
+                        old_value: row.get(4)?,
+                        new_value: row.get(5)?,
+                        changed_at: row.get(7)?,
+                    },
+                    actor: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(history)
+    }
+
+    fn read_duplicate_rows(&self) -> Result<Vec<(String, i32, usize)>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT name, week, COUNT(*) FROM students WHERE deleted_at IS NULL GROUP BY name, week HAVING COUNT(*) > 1 ORDER BY week, name",
+        )?;
+        let duplicates = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get::<_, i64>(2)? as usize))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(duplicates)
+    }
+
+    fn encrypt_existing_mail(&self) -> Result<usize, AppError> {
+        let Some(cipher) = mail_cipher() else {
+            return Ok(0);
+        };
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let plaintext: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT DISTINCT mail FROM students WHERE mail IS NOT NULL AND mail != '' AND mail NOT LIKE 'enc:v1:%'",
+            )?;
+            stmt.query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut updated = 0;
+        for mail in &plaintext {
+            updated += tx.execute(
+                "UPDATE students SET mail = ?1 WHERE mail = ?2",
+                params![cipher.encrypt(mail), mail],
+            )?;
+        }
+        tx.commit()?;
+
+        if updated > 0 {
+            info!("Encrypted mail for {} existing student row(s)", updated);
+        }
+        Ok(updated)
+    }
+
+    // Exact matches use the indexes; the suffix match is kept as a fallback
+    // for values the exact columns cannot normalize
+    fn github_to_name(&self, github_username: &str) -> Result<Option<String>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare("SELECT Name FROM participants WHERE github_login = ?1")?;
+        let mut rows = stmt.query_map([github_login(github_username)], |row| {
+            row.get::<_, String>(0)
+        })?;
+        if let Some(name) = rows.next().transpose()? {
+            return Ok(Some(name));
+        }
+
+        let mut stmt = conn.prepare("SELECT Name FROM Participants WHERE Github LIKE ?")?;
+        let pattern = format!("%{}", github_username);
+        let mut rows = stmt.query_map([&pattern], |row| row.get::<_, String>(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    // Rows linked to their participant are looked up through the link, so a
+    // name kept from before a rename still resolves
+    fn github_username(&self, name: &str) -> Result<Option<String>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT p.GitHub FROM students s JOIN participants p ON p.\"ID\" = s.participant_id WHERE s.name = ?1 LIMIT 1",
+        )?;
+        let mut rows = stmt.query_map([name], |row| row.get::<_, String>(0))?;
+        if let Some(github) = rows.next().transpose()? {
+            return Ok(Some(github));
+        }
+
+        let mut stmt =
+            conn.prepare("SELECT Github FROM participants WHERE Name = ?1 COLLATE NOCASE")?;
+        let mut rows = stmt.query_map([name], |row| row.get::<_, String>(0))?;
+        if let Some(github) = rows.next().transpose()? {
+            return Ok(Some(github));
+        }
+
+        let mut stmt = conn.prepare("SELECT Github FROM Participants WHERE Name LIKE ?")?;
+        let pattern = format!("%{}", name);
+        let mut rows = stmt.query_map([&pattern], |row| row.get::<_, String>(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    fn read_participant_names(&self) -> Result<Vec<String>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare("SELECT Name FROM participants WHERE Name IS NOT NULL")?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(names)
+    }
+
+    fn search_participants(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<ParticipantMatch>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT \"ID\", \"Name\", \"Email\", \"GitHub\", public_id FROM participants \
+             WHERE \"Name\" IS NOT NULL \
+               AND (\"Name\" LIKE ?1 ESCAPE '\\' OR \"Email\" LIKE ?1 ESCAPE '\\' OR \"GitHub\" LIKE ?1 ESCAPE '\\') \
+             ORDER BY \"Name\" COLLATE NOCASE LIMIT ?2",
+        )?;
+        let matches = stmt
+            .query_map(params![contains_pattern(query), limit as i64], |row| {
+                Ok(ParticipantMatch {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    email: row.get(2)?,
+                    github: row.get(3)?,
+                    public_id: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(matches)
+    }
+
+    fn read_participant_locations(&self) -> Result<Vec<String>, AppError> {
+        let conn = self.reader.get()?;
+        if !column_exists(&conn, "participants", "Location")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = conn.prepare(
+            "SELECT Location FROM participants WHERE Location IS NOT NULL AND trim(Location) != ''",
+        )?;
+        let locations = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(locations)
+    }
+
+    fn background_data(&self, email: &str) -> Result<Option<BackgroundData>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT  \"Describe Yourself\" , Background, Skills, Location, Year, Why, Books FROM participants WHERE Email = ?1",
+        )?;
+        let mut rows = stmt.query_map(params![email], |row| {
+            Ok(BackgroundData {
+                describe_yourself: row.get(0)?,
+                background: row.get(1)?,
+                skills: row.get(2)?,
+                location: row.get(3)?,
+                year: row.get(4)?,
+                why: row.get(5)?,
+                book: row.get(6)?,
+            })
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    // Columns are named after the sheet headers with spaces replaced
+    fn replace_responses(
+        &self,
+        headers: &[String],
+        records: &[Vec<String>],
+    ) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute("drop table if exists responses", [])?;
+
+        let columns = headers
+            .iter()
+            .map(|h| format!("\"{}\"", h.replace(' ', "_")))
+            .collect::<Vec<_>>();
+        let column_defs = columns
+            .iter()
+            .map(|c| format!("{} TEXT", c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tx.execute(
+            &format!("CREATE TABLE IF NOT EXISTS responses ({})", column_defs),
+            [],
+        )?;
+
+        let sql = format!(
+            "INSERT INTO responses ({}) VALUES ({})",
+            columns.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+        for record in records {
+            tx.execute(&sql, rusqlite::params_from_iter(record))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn read_all_responses(&self, _cohort_name: &str) -> Result<Vec<FeedbackResponse>, AppError> {
+        let conn = self.reader.get()?;
+
+        let mut stmt = conn.prepare("SELECT * FROM responses")?;
+        let response_iter = stmt.query_map(params![], |row| FeedbackResponse::from_row(row))?;
+
+        let mut responses = Vec::new();
+        for response in response_iter {
+            responses.push(response?);
+        }
+
+        Ok(responses)
+    }
+
+    fn record_voice_snapshot(&self, week: i32, members: &[Member]) -> Result<usize, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let taken_at = Utc::now().to_rfc3339();
+
+        let mut recorded = 0;
+        for member in members {
+            if let Some(discord_id) = member.discord_id() {
+                tx.execute(
+                    "INSERT INTO voice_snapshots (week, taken_at, discord_id, discord_name) VALUES (?1, ?2, ?3, ?4)",
+                    params![week, taken_at, discord_id, member.display_name()],
+                )?;
+                recorded += 1;
+            }
+        }
+
+        tx.commit()?;
+        info!(
+            "Recorded voice snapshot for week {} with {} member(s)",
+            week, recorded
+        );
+        Ok(recorded)
+    }
+
+    fn read_voice_attendees(&self, week: i32) -> Result<Vec<VoiceAttendee>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT discord_id, MAX(discord_name), COUNT(DISTINCT taken_at) FROM voice_snapshots WHERE week = ?1 GROUP BY discord_id",
+        )?;
+        let attendees = stmt
+            .query_map(params![week], |row| {
+                Ok(VoiceAttendee {
+                    discord_id: row.get(0)?,
+                    discord_name: row.get(1)?,
+                    snapshots_seen: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attendees)
+    }
+
+    fn read_discord_handles(&self) -> Result<HashMap<String, String>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare("SELECT discord_id, name FROM discord_handles")?;
+        let handles = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(handles)
+    }
+
+    fn upsert_discord_handle(&self, discord_id: &str, name: &str) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO discord_handles (discord_id, name) VALUES (?1, ?2) ON CONFLICT(discord_id) DO UPDATE SET name = excluded.name",
+            params![discord_id, name],
+        )?;
+        Ok(())
+    }
+
+    fn record_communication(
+        &self,
+        participant: &str,
+        kind: CommunicationKind,
+        subject: &str,
+        note: Option<&str>,
+        sent_by: &str,
+    ) -> Result<Communication, AppError> {
+        let conn = self.pool.get()?;
+        let sent_at = Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO communications (participant, kind, subject, note, sent_by, sent_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![participant, kind.as_str(), subject, note, sent_by, sent_at],
+        )?;
+        info!(
+            "Recorded {} to {} by {}",
+            kind.as_str(),
+            participant,
+            sent_by
+        );
+        Ok(Communication {
+            id: conn.last_insert_rowid(),
+            participant: participant.to_string(),
+            kind,
+            subject: subject.to_string(),
+            note: note.map(str::to_string),
+            sent_by: sent_by.to_string(),
+            sent_at,
+        })
+    }
+
+    fn read_communications(&self, participant: &str) -> Result<Vec<Communication>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, participant, kind, subject, note, sent_by, sent_at FROM communications WHERE participant = ?1 ORDER BY sent_at DESC, id DESC",
+        )?;
+        let communications = stmt
+            .query_map(params![participant], |row| {
+                Ok(Communication {
+                    id: row.get(0)?,
+                    participant: row.get(1)?,
+                    kind: CommunicationKind::parse(&row.get::<_, String>(2)?),
+                    subject: row.get(3)?,
+                    note: row.get(4)?,
+                    sent_by: row.get(5)?,
+                    sent_at: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(communications)
+    }
+
+    fn store_announcement(
+        &self,
+        title: &str,
+        body: &str,
+        critical: bool,
+        created_by: &str,
+    ) -> Result<Announcement, AppError> {
+        let conn = self.pool.get()?;
+        let created_at = Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO announcements (title, body, critical, created_by, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![title, body, critical, created_by, created_at],
+        )?;
+        Ok(Announcement {
+            id: conn.last_insert_rowid(),
+            title: title.to_string(),
+            body: body.to_string(),
+            critical,
+            created_by: created_by.to_string(),
+            created_at,
+        })
+    }
+
+    fn read_announcements(&self) -> Result<Vec<Announcement>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, title, body, critical, created_by, created_at FROM announcements ORDER BY created_at DESC, id DESC",
+        )?;
+        let announcements = stmt
+            .query_map([], |row| {
+                Ok(Announcement {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    body: row.get(2)?,
+                    critical: row.get(3)?,
+                    created_by: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(announcements)
+    }
+
+    fn read_announcement_reads(&self) -> Result<HashMap<i64, HashMap<String, String>>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt =
+            conn.prepare("SELECT announcement_id, name, read_at FROM announcement_reads")?;
+        let mut reads: HashMap<i64, HashMap<String, String>> = HashMap::new();
+        for read in stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })? {
+            let (id, name, read_at) = read?;
+            reads.entry(id).or_default().insert(name, read_at);
+        }
+        Ok(reads)
+    }
+
+    fn mark_announcements_read(&self, name: &str, ids: &[i64]) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let read_at = Utc::now().to_rfc3339();
+        for id in ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO announcement_reads (announcement_id, name, read_at) VALUES (?1, ?2, ?3)",
+                params![id, name, read_at],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn read_week_checklist(&self, week: i32) -> Result<Vec<ChecklistItem>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn
+            .prepare("SELECT task, completed_at, completed_by FROM week_tasks WHERE week = ?1")?;
+        let completed = stmt
+            .query_map(params![week], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    (row.get::<_, String>(1)?, row.get::<_, String>(2)?),
+                ))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()?;
 
-    // tx.execute("DELETE FROM students", [])?;
+        Ok(checklist_items(&completed))
+    }
 
-    for row in &table.rows {
-        // First, try to update existing record
-        let updated_rows = tx.execute(
-            "UPDATE students SET group_id = ?2, ta = ?3, attendance = ?4, fa = ?5, fb = ?6, fc = ?7, fd = ?8, bonus_attempt = ?9, bonus_answer_quality = ?10, bonus_follow_up = ?11, exercise_submitted = ?12, exercise_test_passing = ?13, exercise_good_documentation = ?14, exercise_good_structure = ?15, total = ?16, mail = ?17 WHERE name = ?1 AND week = ?18",
+    fn complete_week_task(
+        &self,
+        week: i32,
+        task: WeekTask,
+        completed_by: &str,
+    ) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO week_tasks (week, task, completed_at, completed_by) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(week, task) DO UPDATE SET completed_at = excluded.completed_at, completed_by = excluded.completed_by",
+            params![week, task.as_str(), Utc::now().to_rfc3339(), completed_by],
+        )?;
+        info!(
+            "Week {} task {} completed by {}",
+            week,
+            task.as_str(),
+            completed_by
+        );
+        Ok(())
+    }
+
+    fn reopen_week_task(&self, week: i32, task: WeekTask) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "DELETE FROM week_tasks WHERE week = ?1 AND task = ?2",
+            params![week, task.as_str()],
+        )?;
+        info!("Week {} task {} reopened", week, task.as_str());
+        Ok(())
+    }
+
+    fn read_admin_totp(&self) -> Result<Option<(String, bool)>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare("SELECT secret, confirmed FROM admin_totp WHERE id = 1")?;
+        let mut rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    fn store_admin_totp(&self, secret: &str) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO admin_totp (id, secret, confirmed, created_at) VALUES (1, ?1, 0, ?2) ON CONFLICT(id) DO UPDATE SET secret = excluded.secret, confirmed = 0, created_at = excluded.created_at",
+            params![secret, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn confirm_admin_totp(&self) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute("UPDATE admin_totp SET confirmed = 1 WHERE id = 1", [])?;
+        Ok(())
+    }
+
+    fn delete_admin_totp(&self) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM admin_totp", [])?;
+        Ok(())
+    }
+
+    fn read_branding(&self) -> Result<Option<Branding>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT program_name, logo, signature, certificate_text, updated_by, updated_at FROM cohort_branding WHERE id = 1",
+        )?;
+        let mut rows = stmt.query_map([], |row| {
+            Ok(Branding {
+                program_name: row.get(0)?,
+                logo: row.get(1)?,
+                signature: row.get(2)?,
+                certificate_text: row.get(3)?,
+                updated_by: row.get(4)?,
+                updated_at: row.get(5)?,
+            })
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    fn store_branding(&self, branding: &Branding, updated_by: &str) -> Result<Branding, AppError> {
+        let conn = self.pool.get()?;
+        let updated_at = Utc::now().to_rfc3339();
+        conn.execute(
+            "INSERT INTO cohort_branding (id, program_name, logo, signature, certificate_text, updated_by, updated_at) VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT(id) DO UPDATE SET program_name = excluded.program_name, logo = excluded.logo, signature = excluded.signature, certificate_text = excluded.certificate_text, updated_by = excluded.updated_by, updated_at = excluded.updated_at",
             params![
-                row.name,
-                row.group_id,
-                row.ta,
-                row.attendance,
-                row.fa,
-                row.fb,
-                row.fc,
-                row.fd,
-                row.bonus_attempt,
-                row.bonus_answer_quality,
-                row.bonus_follow_up,
-                row.exercise_submitted,
-                row.exercise_test_passing,
-                row.exercise_good_documentation,
-                row.exercise_good_structure,
-                row.total,
-                row.mail,
-                row.week
+                branding.program_name,
+                branding.logo,
+                branding.signature,
+                branding.certificate_text,
+                updated_by,
+                updated_at
             ],
         )?;
+        Ok(Branding {
+            updated_by: Some(updated_by.to_string()),
+            updated_at: Some(updated_at),
+            ..branding.clone()
+        })
+    }
 
-        if updated_rows == 0 {
+    fn read_grouping_constraints(&self) -> Result<Vec<GroupingConstraint>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, kind, first, second, created_by, created_at FROM grouping_constraints ORDER BY id",
+        )?;
+        let constraints = stmt
+            .query_map([], |row| {
+                Ok(GroupingConstraint {
+                    id: row.get(0)?,
+                    kind: ConstraintKind::parse(&row.get::<_, String>(1)?),
+                    first: row.get(2)?,
+                    second: row.get(3)?,
+                    created_by: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(constraints)
+    }
+
+    fn store_grouping_constraint(
+        &self,
+        kind: ConstraintKind,
+        first: &str,
+        second: &str,
+        created_by: &str,
+    ) -> Result<GroupingConstraint, AppError> {
+        let conn = self.pool.get()?;
+        let created_at = Utc::now().to_rfc3339();
+        let id = conn.query_row(
+            "INSERT INTO grouping_constraints (kind, first, second, created_by, created_at) VALUES (?1, ?2, ?3, ?4, ?5) ON CONFLICT(first, second) DO UPDATE SET kind = excluded.kind, created_by = excluded.created_by, created_at = excluded.created_at RETURNING id",
+            params![kind.as_str(), first, second, created_by, created_at],
+            |row| row.get(0),
+        )?;
+        Ok(GroupingConstraint {
+            id,
+            kind,
+            first: first.to_string(),
+            second: second.to_string(),
+            created_by: created_by.to_string(),
+            created_at,
+        })
+    }
+
+    fn delete_grouping_constraint(&self, id: i64) -> Result<bool, AppError> {
+        let conn = self.pool.get()?;
+        let deleted = conn.execute(
+            "DELETE FROM grouping_constraints WHERE id = ?1",
+            params![id],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    fn read_spoken_languages(&self) -> Result<HashMap<String, BTreeSet<String>>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare("SELECT name, language FROM spoken_languages")?;
+        let mut spoken: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for pair in stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })? {
+            let (name, language) = pair?;
+            spoken.entry(name).or_default().insert(language);
+        }
+        Ok(spoken)
+    }
+
+    fn replace_spoken_languages(
+        &self,
+        name: &str,
+        languages: &BTreeSet<String>,
+    ) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM spoken_languages WHERE name = ?1",
+            params![name],
+        )?;
+        for language in languages {
             tx.execute(
-                "INSERT INTO students (name, group_id, ta, attendance, fa, fb, fc, fd, bonus_attempt, bonus_answer_quality, bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, total, mail, week) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
-                params![
-                    row.name,
-                    row.group_id,
-                    row.ta,
-                    row.attendance,
-                    row.fa,
-                    row.fb,
-                    row.fc,
-                    row.fd,
-                    row.bonus_attempt,
-                    row.bonus_answer_quality,
-                    row.bonus_follow_up,
-                    row.exercise_submitted,
-                    row.exercise_test_passing,
-                    row.exercise_good_documentation,
-                    row.exercise_good_structure,
-                    row.total,
-                    row.mail,
-                    row.week
-                ],
+                "INSERT INTO spoken_languages (name, language) VALUES (?1, ?2)",
+                params![name, language],
             )?;
         }
+        tx.commit()?;
+        Ok(())
     }
 
-    info!(
-        "Successfully wrote {} rows to the database.",
-        table.rows.len()
-    );
-    tx.commit()?;
-    Ok(())
+    fn record_exercise_attempts(&self, attempts: &[ExerciseAttempt]) -> Result<usize, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let mut recorded = 0;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO exercise_attempts (week, github, submitted_at, passing, recorded_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for attempt in attempts {
+                recorded += stmt.execute(params![
+                    attempt.week,
+                    attempt.github,
+                    attempt.submitted_at,
+                    attempt.passing,
+                    attempt.recorded_at
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(recorded)
+    }
+
+    fn read_exercise_attempts(&self) -> Result<Vec<ExerciseAttempt>, AppError> {
+        let conn = self.reader.get()?;
+        read_attempts(&conn)
+    }
+
+    fn record_sync_run(&self, run: &SyncRun) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO sync_runs (week, started_at, finished_at, succeeded, error) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                run.week,
+                run.started_at,
+                run.finished_at,
+                run.succeeded,
+                run.error
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn read_sync_runs(&self) -> Result<Vec<SyncRun>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT week, started_at, finished_at, succeeded, error FROM sync_runs ORDER BY finished_at, id",
+        )?;
+        let runs = stmt
+            .query_map([], |row| {
+                Ok(SyncRun {
+                    week: row.get(0)?,
+                    started_at: row.get(1)?,
+                    finished_at: row.get(2)?,
+                    succeeded: row.get(3)?,
+                    error: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(runs)
+    }
+
+    fn read_rubric_notes(&self) -> Result<Vec<RubricNote>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT criterion, score, note, updated_by, updated_at FROM rubric_notes ORDER BY criterion, score",
+        )?;
+        let notes = stmt
+            .query_map([], |row| {
+                Ok(RubricNote {
+                    criterion: row.get(0)?,
+                    score: row.get(1)?,
+                    note: row.get(2)?,
+                    updated_by: row.get(3)?,
+                    updated_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(notes)
+    }
+
+    fn replace_rubric_notes(
+        &self,
+        criterion: &str,
+        notes: &BTreeMap<u64, String>,
+        updated_by: &str,
+    ) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let updated_at = Utc::now().to_rfc3339();
+        tx.execute(
+            "DELETE FROM rubric_notes WHERE criterion = ?1",
+            params![criterion],
+        )?;
+        for (score, note) in notes {
+            tx.execute(
+                "INSERT INTO rubric_notes (criterion, score, note, updated_by, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![criterion, score, note, updated_by, updated_at],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn read_group_threads(&self, week: i32) -> Result<Vec<GroupThread>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT week, group_id, thread_id, members, created_at, updated_at FROM group_threads WHERE week = ?1 ORDER BY group_id",
+        )?;
+        let threads = stmt
+            .query_map(params![week], |row| {
+                Ok(GroupThread {
+                    week: row.get(0)?,
+                    group_id: row.get(1)?,
+                    thread_id: row.get(2)?,
+                    members: split_members(&row.get::<_, String>(3)?),
+                    created_at: row.get(4)?,
+                    updated_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(threads)
+    }
+
+    fn store_group_thread(&self, thread: &GroupThread) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO group_threads (week, group_id, thread_id, members, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+             ON CONFLICT(week, group_id) DO UPDATE SET thread_id = excluded.thread_id, members = excluded.members, updated_at = excluded.updated_at",
+            params![
+                thread.week,
+                thread.group_id,
+                thread.thread_id,
+                thread.members.join(","),
+                thread.created_at,
+                thread.updated_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn read_review_summaries(&self) -> Result<Vec<ReviewSummary>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT week, group_id, ta, common_mistakes, standout_solutions, notes, submitted_at FROM review_summaries ORDER BY week, group_id",
+        )?;
+        let summaries = stmt
+            .query_map([], |row| {
+                Ok(ReviewSummary {
+                    week: row.get(0)?,
+                    group_id: row.get(1)?,
+                    ta: row.get(2)?,
+                    common_mistakes: split_points(&row.get::<_, String>(3)?),
+                    standout_solutions: split_points(&row.get::<_, String>(4)?),
+                    notes: row.get(5)?,
+                    submitted_at: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(summaries)
+    }
+
+    fn store_review_summary(&self, summary: &ReviewSummary) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO review_summaries (week, group_id, ta, common_mistakes, standout_solutions, notes, submitted_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+             ON CONFLICT(week, group_id) DO UPDATE SET ta = excluded.ta, common_mistakes = excluded.common_mistakes, standout_solutions = excluded.standout_solutions, notes = excluded.notes, submitted_at = excluded.submitted_at",
+            params![
+                summary.week,
+                summary.group_id,
+                summary.ta,
+                summary.common_mistakes.join("\n"),
+                summary.standout_solutions.join("\n"),
+                summary.notes,
+                summary.submitted_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn read_week_locks(&self) -> Result<Vec<WeekLock>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt =
+            conn.prepare("SELECT week, locked_by, locked_at FROM week_locks ORDER BY week")?;
+        let locks = stmt
+            .query_map([], |row| {
+                Ok(WeekLock {
+                    week: row.get(0)?,
+                    locked_by: row.get(1)?,
+                    locked_at: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(locks)
+    }
+
+    fn lock_week(&self, lock: &WeekLock) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO week_locks (week, locked_by, locked_at) VALUES (?1, ?2, ?3)",
+            params![lock.week, lock.locked_by, lock.locked_at],
+        )?;
+        Ok(())
+    }
+
+    fn unlock_week(&self, week: i32) -> Result<bool, AppError> {
+        let conn = self.pool.get()?;
+        let removed = conn.execute("DELETE FROM week_locks WHERE week = ?1", params![week])?;
+        Ok(removed > 0)
+    }
+
+    fn read_idempotent_response(
+        &self,
+        key: &str,
+        actor: &str,
+    ) -> Result<Option<IdempotentResponse>, AppError> {
+        let conn = self.reader.get()?;
+        let response = conn
+            .query_row(
+                "SELECT key, actor, fingerprint, status, body, created_at FROM idempotency_keys WHERE key = ?1 AND actor = ?2",
+                params![key, actor],
+                |row| {
+                    Ok(IdempotentResponse {
+                        key: row.get(0)?,
+                        actor: row.get(1)?,
+                        fingerprint: row.get(2)?,
+                        status: row.get(3)?,
+                        body: row.get(4)?,
+                        created_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(response)
+    }
+
+    fn store_idempotent_response(
+        &self,
+        response: &IdempotentResponse,
+        prune_before: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM idempotency_keys WHERE created_at < ?1",
+            params![prune_before.to_rfc3339()],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO idempotency_keys (key, actor, fingerprint, status, body, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                response.key,
+                response.actor,
+                response.fingerprint,
+                response.status,
+                response.body,
+                response.created_at
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_webhook_delivery(
+        &self,
+        id: &str,
+        received_at: DateTime<Utc>,
+        prune_before: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM webhook_deliveries WHERE received_at < ?1",
+            params![prune_before.to_rfc3339()],
+        )?;
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO webhook_deliveries (id, received_at) VALUES (?1, ?2)",
+            params![id, received_at.to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(inserted > 0)
+    }
+
+    fn forget_webhook_delivery(&self, id: &str) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM webhook_deliveries WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn read_inactive_tas(&self) -> Result<HashSet<String>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare("SELECT ta FROM inactive_tas")?;
+        let tas = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(tas)
+    }
+
+    fn deactivate_ta(&self, ta: &str, deactivated_by: &str) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO inactive_tas (ta, deactivated_by, deactivated_at) VALUES (?1, ?2, ?3)",
+            params![ta, deactivated_by, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn read_onboarded_tas(&self) -> Result<Vec<TaProfile>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT name, email, github, discord, notify_by_email, notify_by_discord, onboarded_at
+             FROM onboarded_tas ORDER BY onboarded_at, name",
+        )?;
+        let tas = stmt
+            .query_map([], |row| {
+                Ok(TaProfile {
+                    name: row.get(0)?,
+                    email: row.get(1)?,
+                    setup: TaSetup {
+                        github: row.get(2)?,
+                        discord: row.get(3)?,
+                        notify_by_email: row.get(4)?,
+                        notify_by_discord: row.get(5)?,
+                    },
+                    onboarded_at: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tas)
+    }
+
+    fn create_ta_invite(&self, token_hash: &str, invite: &TaInvite) -> Result<(), AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM ta_invites WHERE email = ?1 COLLATE NOCASE AND accepted_at IS NULL",
+            params![invite.email],
+        )?;
+        tx.execute(
+            "INSERT INTO ta_invites (token_hash, email, name, invited_by, created_at, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                token_hash,
+                invite.email,
+                invite.name,
+                invite.invited_by,
+                invite.created_at,
+                invite.expires_at
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn accept_ta_invite(
+        &self,
+        token_hash: &str,
+        setup: &TaSetup,
+    ) -> Result<Option<TaProfile>, AppError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let invite: Option<(String, String, String)> = tx
+            .query_row(
+                "SELECT email, name, expires_at FROM ta_invites
+                 WHERE token_hash = ?1 AND accepted_at IS NULL",
+                params![token_hash],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let Some((email, name, expires_at)) = invite else {
+            return Ok(None);
+        };
+        if !invite_pending(&expires_at) {
+            return Ok(None);
+        }
+        let now = Utc::now().to_rfc3339();
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO onboarded_tas
+                 (name, email, github, discord, notify_by_email, notify_by_discord, onboarded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                name,
+                email,
+                setup.github,
+                setup.discord,
+                setup.notify_by_email,
+                setup.notify_by_discord,
+                now
+            ],
+        )?;
+        if inserted == 0 {
+            return Ok(None);
+        }
+        tx.execute(
+            "UPDATE ta_invites SET accepted_at = ?1 WHERE token_hash = ?2",
+            params![now, token_hash],
+        )?;
+        tx.commit()?;
+        Ok(Some(TaProfile {
+            name,
+            email,
+            setup: setup.clone(),
+            onboarded_at: now,
+        }))
+    }
+
+    fn read_attention_dismissals(&self) -> Result<HashSet<String>, AppError> {
+        let conn = self.reader.get()?;
+        let mut stmt = conn.prepare("SELECT id FROM attention_dismissals")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(ids)
+    }
+
+    fn dismiss_attention_item(&self, id: &str, dismissed_by: &str) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO attention_dismissals (id, dismissed_by, dismissed_at) VALUES (?1, ?2, ?3)",
+            params![id, dismissed_by, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn read_revoked_tokens(&self) -> Result<HashSet<String>, AppError> {
+        let conn = self.pool.get()?;
+        let now = Utc::now().to_rfc3339();
+        conn.execute(
+            "DELETE FROM revoked_tokens WHERE expires_at IS NOT NULL AND expires_at <= ?1",
+            params![now],
+        )?;
+        let mut stmt = conn.prepare("SELECT token_hash FROM revoked_tokens")?;
+        let hashes = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(hashes)
+    }
+
+    fn revoke_token(
+        &self,
+        token_hash: &str,
+        revoked_by: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO revoked_tokens (token_hash, revoked_by, revoked_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                token_hash,
+                revoked_by,
+                Utc::now().to_rfc3339(),
+                expires_at.map(|t| t.to_rfc3339())
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn vacuum_and_analyze(&self) -> Result<MaintenanceReport, AppError> {
+        let conn = self.pool.get()?;
+        let size = |conn: &Connection| -> Result<u64, AppError> {
+            let pages: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+            let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+            Ok((pages * page_size) as u64)
+        };
+        let started_at = Utc::now().to_rfc3339();
+        let size_before = size(&conn)?;
+        conn.execute_batch("VACUUM; ANALYZE;")?;
+        // Fold the rewrite back into the main file so the WAL shrinks too
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        let size_after = size(&conn)?;
+        Ok(MaintenanceReport {
+            started_at,
+            finished_at: Utc::now().to_rfc3339(),
+            size_before,
+            size_after,
+            reclaimed_bytes: size_before.saturating_sub(size_after),
+        })
+    }
+}
+
+// Filter leaving out soft-deleted rows, for archives that have the column
+fn live_rows(conn: &Connection) -> Result<&'static str, AppError> {
+    Ok(if column_exists(conn, "students", "deleted_at")? {
+        " AND deleted_at IS NULL"
+    } else {
+        ""
+    })
+}
+
+// Where student rows are read from: the view joining attendance, scores and
+// exercise results back in, or the table itself in databases from before
+// they were split out
+fn student_rows(conn: &Connection) -> Result<&'static str, AppError> {
+    let view: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'view' AND name = 'student_rows'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(if view > 0 { "student_rows" } else { "students" })
+}
+
+// Attendance per week of another cohort database: week 0 counts enrolled
+// students, later weeks count students marked present
+pub fn read_cohort_attendance(path: &Path) -> Result<BTreeMap<i32, usize>, AppError> {
+    let conn = open_read_only(path)?;
+    if !table_exists(&conn, "students")? {
+        return Ok(BTreeMap::new());
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT week, COUNT(*) FROM {} WHERE (week = 0 OR attendance = 'yes'){} GROUP BY week",
+        student_rows(&conn)?,
+        live_rows(&conn)?
+    ))?;
+    let counts = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?
+        .collect::<Result<BTreeMap<i32, usize>, _>>()?;
+    Ok(counts)
+}
+
+// Weekly rows of another cohort database. Mail is left out, as it may be
+// encrypted under another key and reports do not need it.
+pub fn read_cohort_rows(path: &Path) -> Result<Vec<RowData>, AppError> {
+    let conn = open_read_only(path)?;
+    if !table_exists(&conn, "students")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM {} WHERE week > 0{}",
+        STUDENT_COLUMNS,
+        student_rows(&conn)?,
+        live_rows(&conn)?
+    ))?;
+    let rows = stmt
+        .query_map([], student_from_row)?
+        .map(|row| {
+            row.map(|row| RowData {
+                mail: String::new(),
+                ..row
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+fn read_attempts(conn: &Connection) -> Result<Vec<ExerciseAttempt>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT week, github, submitted_at, passing, recorded_at FROM exercise_attempts",
+    )?;
+    let attempts = stmt
+        .query_map([], |row| {
+            Ok(ExerciseAttempt {
+                week: row.get(0)?,
+                github: row.get(1)?,
+                submitted_at: row.get(2)?,
+                passing: row.get(3)?,
+                recorded_at: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(attempts)
+}
+
+// Graded exercise outcomes per week and recorded submission states of
+// another cohort database. Archives from before attempts were recorded only
+// have outcomes.
+pub fn read_cohort_exercises(
+    path: &Path,
+) -> Result<(BTreeMap<i32, ExerciseOutcome>, Vec<ExerciseAttempt>), AppError> {
+    let conn = open_read_only(path)?;
+    let mut outcomes = BTreeMap::new();
+    if table_exists(&conn, "students")? {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT week, COUNT(*), SUM(exercise_test_passing = 'yes') FROM {} WHERE week >= 1 AND exercise_submitted = 'yes'{} GROUP BY week",
+            student_rows(&conn)?,
+            live_rows(&conn)?
+        ))?;
+        outcomes = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    ExerciseOutcome {
+                        submitted: row.get::<_, i64>(1)? as usize,
+                        passing: row.get::<_, i64>(2)? as usize,
+                    },
+                ))
+            })?
+            .collect::<Result<BTreeMap<i32, ExerciseOutcome>, _>>()?;
+    }
+    let attempts = if table_exists(&conn, "exercise_attempts")? {
+        read_attempts(&conn)?
+    } else {
+        Vec::new()
+    };
+    Ok((outcomes, attempts))
 }
 
 pub fn register_cohort_participant(
@@ -115,7 +1704,7 @@ pub fn register_cohort_participant(
     participant: CohortParticipant,
 ) -> Result<(), AppError> {
     info!("Writing to DB at path: {:?}", path);
-    let mut conn = Connection::open(path)?;
+    let mut conn = open_connection(path)?;
     let tx = conn.transaction()?;
 
     // Create table if it doesn't exist
@@ -174,17 +1763,3 @@ pub fn register_cohort_participant(
     tx.commit()?;
     Ok(())
 }
-
-pub fn read_all_responses(db_path: &PathBuf, _cohort_name: &str) -> Result<Vec<FeedbackResponse>> {
-    let conn = Connection::open(db_path)?;
-
-    let mut stmt = conn.prepare("SELECT * FROM responses")?;
-    let response_iter = stmt.query_map(params![], |row| FeedbackResponse::from_row(row))?;
-
-    let mut responses = Vec::new();
-    for response in response_iter {
-        responses.push(response?);
-    }
-
-    Ok(responses)
-}
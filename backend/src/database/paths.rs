@@ -0,0 +1,75 @@
+//! Locations of the live database, cohort archives and backups.
+//!
+//! Cohort archives (`classroom_<name>.db`) are kept next to the live
+//! database. Backups go under a separate data directory, so the database can
+//! sit on fast storage while snapshots go to a larger or mounted volume.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static DATA_PATHS: OnceLock<DataPaths> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataPaths {
+    // The live cohort's SQLite database
+    pub database: PathBuf,
+    // Root for backups
+    pub data_dir: PathBuf,
+}
+
+impl Default for DataPaths {
+    fn default() -> Self {
+        DataPaths {
+            database: PathBuf::from("classroom.db"),
+            data_dir: PathBuf::from("."),
+        }
+    }
+}
+
+impl DataPaths {
+    // From DATABASE_PATH and DATA_DIR, each falling back to its default
+    pub fn from_env() -> Result<Self, String> {
+        let defaults = DataPaths::default();
+        let path = |var: &str, default: PathBuf| match env::var(var) {
+            Ok(value) if !value.trim().is_empty() => PathBuf::from(value.trim()),
+            _ => default,
+        };
+        let database = path("DATABASE_PATH", defaults.database);
+        if database.file_name().is_none() {
+            return Err("DATABASE_PATH must name a database file".to_string());
+        }
+        Ok(DataPaths {
+            database,
+            data_dir: path("DATA_DIR", defaults.data_dir),
+        })
+    }
+
+    // Directory holding the live database and the cohort archives
+    pub fn cohort_dir(&self) -> &Path {
+        match self.database.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        }
+    }
+
+    pub fn cohort_db(&self, cohort: &str) -> PathBuf {
+        self.cohort_dir()
+            .join(format!("classroom_{}.db", cohort.to_lowercase()))
+    }
+
+    pub fn backup_dir(&self) -> PathBuf {
+        self.data_dir.join("backup")
+    }
+}
+
+// Loads the paths once at startup; defaults apply until then
+pub fn init_data_paths() -> Result<DataPaths, String> {
+    let paths = DataPaths::from_env()?;
+    let _ = DATA_PATHS.set(paths.clone());
+    Ok(paths)
+}
+
+pub fn data_paths() -> &'static DataPaths {
+    DATA_PATHS.get_or_init(DataPaths::default)
+}
@@ -0,0 +1,155 @@
+use crate::utils::types::AppError;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags};
+use std::env;
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub type DbPool = Pool<SqliteConnectionManager>;
+
+const DEFAULT_POOL_SIZE: u32 = 8;
+
+const JOURNAL_MODES: &[&str] = &["DELETE", "TRUNCATE", "PERSIST", "MEMORY", "WAL", "OFF"];
+const SYNCHRONOUS_LEVELS: &[&str] = &["OFF", "NORMAL", "FULL", "EXTRA"];
+
+static SQLITE_SETTINGS: OnceLock<SqliteSettings> = OnceLock::new();
+
+// PRAGMAs applied to every SQLite connection. WAL lets readers proceed while
+// a large write is in progress, and the busy timeout makes a writer wait for
+// the lock instead of failing with "database is locked".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqliteSettings {
+    pub journal_mode: String,
+    pub busy_timeout_ms: u32,
+    pub synchronous: String,
+}
+
+impl Default for SqliteSettings {
+    fn default() -> Self {
+        SqliteSettings {
+            journal_mode: "WAL".to_string(),
+            busy_timeout_ms: 5000,
+            // Durable enough in WAL mode, a crash can only lose the last commits
+            synchronous: "NORMAL".to_string(),
+        }
+    }
+}
+
+impl SqliteSettings {
+    // From SQLITE_JOURNAL_MODE, SQLITE_BUSY_TIMEOUT_MS and SQLITE_SYNCHRONOUS,
+    // each falling back to its default when unset
+    pub fn from_env() -> Result<Self, String> {
+        let defaults = SqliteSettings::default();
+        let choice = |var: &str, allowed: &[&str], default: String| -> Result<String, String> {
+            match env::var(var) {
+                Ok(value) if !value.trim().is_empty() => {
+                    let value = value.trim().to_uppercase();
+                    if allowed.contains(&value.as_str()) {
+                        Ok(value)
+                    } else {
+                        Err(format!("{} must be one of {}", var, allowed.join(", ")))
+                    }
+                }
+                _ => Ok(default),
+            }
+        };
+        let busy_timeout_ms = match env::var("SQLITE_BUSY_TIMEOUT_MS") {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse()
+                .map_err(|_| "SQLITE_BUSY_TIMEOUT_MS must be a number of milliseconds")?,
+            _ => defaults.busy_timeout_ms,
+        };
+        Ok(SqliteSettings {
+            journal_mode: choice("SQLITE_JOURNAL_MODE", JOURNAL_MODES, defaults.journal_mode)?,
+            busy_timeout_ms,
+            synchronous: choice(
+                "SQLITE_SYNCHRONOUS",
+                SYNCHRONOUS_LEVELS,
+                defaults.synchronous,
+            )?,
+        })
+    }
+
+    pub fn apply(&self, conn: &Connection) -> rusqlite::Result<()> {
+        conn.pragma_update(None, "busy_timeout", self.busy_timeout_ms)?;
+        // Read-only connections cannot change the journal mode
+        if !conn.is_readonly(rusqlite::DatabaseName::Main)? {
+            // Reports the resulting mode, so it is read rather than executed
+            conn.pragma_update_and_check(None, "journal_mode", &self.journal_mode, |row| {
+                row.get::<_, String>(0)
+            })?;
+        }
+        conn.pragma_update(None, "synchronous", &self.synchronous)?;
+        Ok(())
+    }
+}
+
+// Loads the SQLite settings once at startup; defaults apply until then
+pub fn init_sqlite_settings() -> Result<SqliteSettings, String> {
+    let settings = SqliteSettings::from_env()?;
+    let _ = SQLITE_SETTINGS.set(settings.clone());
+    Ok(settings)
+}
+
+fn sqlite_settings() -> &'static SqliteSettings {
+    SQLITE_SETTINGS.get_or_init(SqliteSettings::default)
+}
+
+// Opens a cohort database with the configured PRAGMAs. Every SQLite
+// connection goes through here or through the pool.
+pub fn open_connection(path: &Path) -> Result<Connection, AppError> {
+    let conn = Connection::open(path)?;
+    sqlite_settings().apply(&conn)?;
+    Ok(conn)
+}
+
+pub fn open_read_only(path: &Path) -> Result<Connection, AppError> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    sqlite_settings().apply(&conn)?;
+    Ok(conn)
+}
+
+// Connections kept per pool, from DB_POOL_SIZE
+pub fn pool_size() -> u32 {
+    env::var("DB_POOL_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or(DEFAULT_POOL_SIZE)
+}
+
+// Shared connections to the cohort database, sized by DB_POOL_SIZE
+pub fn create_pool(path: &Path) -> Result<DbPool, AppError> {
+    let size = pool_size();
+    let manager =
+        SqliteConnectionManager::file(path).with_init(|conn| sqlite_settings().apply(conn));
+    Ok(Pool::builder().max_size(size).build(manager)?)
+}
+
+// Same database opened read-only, for the storage read methods. SQLite
+// rejects any write on these connections.
+pub fn create_read_only_pool(path: &Path) -> Result<DbPool, AppError> {
+    let manager = SqliteConnectionManager::file(path)
+        .with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .with_init(|conn| sqlite_settings().apply(conn));
+    Ok(Pool::builder().max_size(pool_size()).build(manager)?)
+}
+
+// A named database kept in memory and shared by the pool's connections. It
+// lasts as long as the pool, so connections are never retired.
+pub fn create_memory_pool(name: &str) -> Result<DbPool, AppError> {
+    let manager = SqliteConnectionManager::file(format!("file:{}?mode=memory&cache=shared", name))
+        .with_flags(
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+                | OpenFlags::SQLITE_OPEN_CREATE
+                | OpenFlags::SQLITE_OPEN_URI,
+        )
+        .with_init(|conn| sqlite_settings().apply(conn));
+    Ok(Pool::builder()
+        .max_size(pool_size())
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .build(manager)?)
+}
@@ -0,0 +1,2396 @@
+use crate::database::encryption::{decrypt_mail, encrypt_mail, mail_cipher};
+use crate::database::pool::pool_size;
+use crate::database::schema::SCORE_COLUMNS;
+use crate::database::storage::{
+    Storage, checklist_items, contains_pattern, github_login, invite_pending, split_members,
+    split_points,
+};
+use crate::utils::ids::{is_public_id, new_public_id};
+use crate::utils::types::{
+    Announcement, AppError, BackgroundData, Branding, ChangedRows, ChecklistItem, Communication,
+    CommunicationKind, ConstraintKind, DeletedRow, ExerciseAttempt, FeedbackResponse, GroupThread,
+    GroupingConstraint, IdempotentResponse, MaintenanceReport, Member, ParticipantMatch,
+    ReviewSummary, RowChange, RowData, RowHistoryEntry, RubricNote, SyncRun, TaInvite, TaProfile,
+    TaSetup, Table, VoiceAttendee, WeekLock, WeekTask,
+};
+use chrono::{DateTime, Utc};
+use log::info;
+use native_tls::TlsConnector;
+use postgres::types::ToSql;
+use postgres::{Client, GenericClient, Row, Transaction};
+use postgres_native_tls::MakeTlsConnector;
+use r2d2::Pool;
+use r2d2_postgres::PostgresConnectionManager;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::thread;
+
+type PgPool = Pool<PostgresConnectionManager<MakeTlsConnector>>;
+
+// Schema changes applied at startup, tracked in `schema_version`. Same rules
+// as the SQLite migrations: never edit or reorder an entry, append instead.
+// Timestamps are RFC 3339 text like in SQLite so both backends sort alike.
+const MIGRATIONS: &[&str] = &[
+    // 1: Live cohort tables matching SQLite schema version 10
+    r#"
+    CREATE TABLE IF NOT EXISTS students (
+        name                        TEXT NOT NULL,
+        group_id                    TEXT NOT NULL,
+        ta                          TEXT,
+        attendance                  TEXT,
+        fa                          BIGINT,
+        fb                          BIGINT,
+        fc                          BIGINT,
+        fd                          BIGINT,
+        bonus_attempt               BIGINT,
+        bonus_answer_quality        BIGINT,
+        bonus_follow_up             BIGINT,
+        exercise_submitted          TEXT,
+        exercise_test_passing       TEXT,
+        exercise_good_documentation TEXT,
+        exercise_good_structure     TEXT,
+        total                       BIGINT,
+        mail                        TEXT NOT NULL,
+        week                        INTEGER NOT NULL,
+        PRIMARY KEY (name, week)
+    );
+    CREATE TABLE IF NOT EXISTS participants (
+        email              TEXT PRIMARY KEY,
+        name               TEXT NOT NULL,
+        github             TEXT,
+        describe_yourself  TEXT,
+        background         TEXT,
+        skills             TEXT,
+        location           TEXT,
+        year               TEXT,
+        why                TEXT,
+        books              TEXT
+    );
+    -- One JSON object per sheet row, keyed by column name, since sheet
+    -- headers exceed the Postgres identifier length
+    CREATE TABLE IF NOT EXISTS responses (
+        id            BIGSERIAL PRIMARY KEY,
+        data          TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS voice_snapshots (
+        week          INTEGER NOT NULL,
+        taken_at      TEXT NOT NULL,
+        discord_id    TEXT NOT NULL,
+        discord_name  TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS discord_handles (
+        discord_id    TEXT PRIMARY KEY,
+        name          TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS communications (
+        id            BIGSERIAL PRIMARY KEY,
+        participant   TEXT NOT NULL,
+        kind          TEXT NOT NULL,
+        subject       TEXT NOT NULL,
+        note          TEXT,
+        sent_by       TEXT NOT NULL,
+        sent_at       TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_communications_participant
+        ON communications (participant);
+    CREATE TABLE IF NOT EXISTS week_tasks (
+        week          INTEGER NOT NULL,
+        task          TEXT NOT NULL,
+        completed_at  TEXT NOT NULL,
+        completed_by  TEXT NOT NULL,
+        PRIMARY KEY (week, task)
+    );
+    CREATE TABLE IF NOT EXISTS admin_totp (
+        id            INTEGER PRIMARY KEY CHECK (id = 1),
+        secret        TEXT NOT NULL,
+        confirmed     BOOLEAN NOT NULL DEFAULT FALSE,
+        created_at    TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS attention_dismissals (
+        id            TEXT PRIMARY KEY,
+        dismissed_by  TEXT NOT NULL,
+        dismissed_at  TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS revoked_tokens (
+        token_hash    TEXT PRIMARY KEY,
+        revoked_by    TEXT NOT NULL,
+        revoked_at    TEXT NOT NULL,
+        expires_at    TEXT
+    );
+    CREATE TABLE IF NOT EXISTS cohort_branding (
+        id                INTEGER PRIMARY KEY CHECK (id = 1),
+        program_name      TEXT NOT NULL,
+        logo              TEXT,
+        signature         TEXT,
+        certificate_text  TEXT,
+        updated_by        TEXT NOT NULL,
+        updated_at        TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS grouping_constraints (
+        id            BIGSERIAL PRIMARY KEY,
+        kind          TEXT NOT NULL CHECK (kind IN ('together', 'apart')),
+        first         TEXT NOT NULL,
+        second        TEXT NOT NULL,
+        created_by    TEXT NOT NULL,
+        created_at    TEXT NOT NULL,
+        UNIQUE (first, second)
+    );
+    "#,
+    // 2: Exercise submissions seen by the weekly sync (SQLite version 11)
+    r#"
+    CREATE TABLE IF NOT EXISTS exercise_attempts (
+        week          INTEGER NOT NULL,
+        github        TEXT NOT NULL,
+        submitted_at  TEXT NOT NULL,
+        passing       BOOLEAN NOT NULL,
+        recorded_at   TEXT NOT NULL,
+        PRIMARY KEY (week, github, submitted_at)
+    );
+    "#,
+    // 3: Grading guidance per rubric criterion and score (SQLite version 12)
+    r#"
+    CREATE TABLE IF NOT EXISTS rubric_notes (
+        criterion     TEXT NOT NULL,
+        score         BIGINT NOT NULL,
+        note          TEXT NOT NULL,
+        updated_by    TEXT NOT NULL,
+        updated_at    TEXT NOT NULL,
+        PRIMARY KEY (criterion, score)
+    );
+    "#,
+    // 4: Discord threads of published groups (SQLite version 13)
+    r#"
+    CREATE TABLE IF NOT EXISTS group_threads (
+        week          INTEGER NOT NULL,
+        group_id      TEXT NOT NULL,
+        thread_id     TEXT NOT NULL,
+        members       TEXT NOT NULL,
+        created_at    TEXT NOT NULL,
+        updated_at    TEXT NOT NULL,
+        PRIMARY KEY (week, group_id)
+    );
+    "#,
+    // 5: TAs offboarded mid-cohort (SQLite version 14)
+    r#"
+    CREATE TABLE IF NOT EXISTS inactive_tas (
+        ta              TEXT PRIMARY KEY,
+        deactivated_by  TEXT NOT NULL,
+        deactivated_at  TEXT NOT NULL
+    );
+    "#,
+    // 6: Soft deletion of weekly rows (SQLite adds the column at startup)
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS deleted_at TEXT;
+    "#,
+    // 7: Field-level edit history of weekly rows (SQLite version 15)
+    r#"
+    CREATE TABLE IF NOT EXISTS row_history (
+        id            BIGSERIAL PRIMARY KEY,
+        name          TEXT NOT NULL,
+        week          INTEGER NOT NULL,
+        field         TEXT NOT NULL,
+        old_value     TEXT,
+        new_value     TEXT,
+        actor         TEXT NOT NULL,
+        changed_at    TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_row_history_row
+        ON row_history (name, week);
+    "#,
+    // 8: Exact-match lookups of participants (SQLite adds these at startup)
+    r#"
+    ALTER TABLE participants ADD COLUMN IF NOT EXISTS github_login TEXT
+        GENERATED ALWAYS AS (lower(regexp_replace(rtrim(github, '/'), '^.*/', ''))) STORED;
+    CREATE INDEX IF NOT EXISTS idx_participants_github_login
+        ON participants (github_login);
+    CREATE INDEX IF NOT EXISTS idx_participants_name
+        ON participants (lower(name));
+    "#,
+    // 9: Languages each student speaks (SQLite version 16)
+    r#"
+    CREATE TABLE IF NOT EXISTS spoken_languages (
+        name          TEXT NOT NULL,
+        language      TEXT NOT NULL,
+        PRIMARY KEY (name, language)
+    );
+    "#,
+    // 10: Cohort-wide announcements and who has read them (SQLite version 17)
+    r#"
+    CREATE TABLE IF NOT EXISTS announcements (
+        id            BIGSERIAL PRIMARY KEY,
+        title         TEXT NOT NULL,
+        body          TEXT NOT NULL,
+        critical      BOOLEAN NOT NULL DEFAULT FALSE,
+        created_by    TEXT NOT NULL,
+        created_at    TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS announcement_reads (
+        announcement_id BIGINT NOT NULL,
+        name            TEXT NOT NULL,
+        read_at         TEXT NOT NULL,
+        PRIMARY KEY (announcement_id, name)
+    );
+    "#,
+    // 11: Outcome of every exercise sync run (SQLite version 18)
+    r#"
+    CREATE TABLE IF NOT EXISTS sync_runs (
+        id            BIGSERIAL PRIMARY KEY,
+        week          INTEGER NOT NULL,
+        started_at    TEXT NOT NULL,
+        finished_at   TEXT NOT NULL,
+        succeeded     BOOLEAN NOT NULL,
+        error         TEXT
+    );
+    "#,
+    // 12: Weekly rows linked to their participant, which is keyed by email
+    // here (SQLite links on the participant ID at startup)
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS participant_id TEXT
+        REFERENCES participants (email) ON UPDATE CASCADE ON DELETE SET NULL;
+    CREATE INDEX IF NOT EXISTS idx_students_participant
+        ON students (participant_id, week);
+    "#,
+    // 13: Exercise evaluation keyed by participant and week, joined back
+    // onto the rows by a view (SQLite version 19)
+    r#"
+    CREATE TABLE IF NOT EXISTS exercise_results (
+        participant_id      TEXT NOT NULL
+            REFERENCES participants (email) ON UPDATE CASCADE ON DELETE CASCADE,
+        week                INTEGER NOT NULL,
+        submitted           TEXT,
+        test_passing        TEXT,
+        good_documentation  TEXT,
+        good_structure      TEXT,
+        PRIMARY KEY (participant_id, week)
+    );
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta, s.attendance, s.fa, s.fb, s.fc, s.fd,
+           s.bonus_attempt, s.bonus_answer_quality, s.bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at
+    FROM students s
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 14: TA invites and onboarded TA profiles (SQLite version 20)
+    r#"
+    CREATE TABLE IF NOT EXISTS ta_invites (
+        token_hash   TEXT PRIMARY KEY,
+        email        TEXT NOT NULL,
+        name         TEXT NOT NULL,
+        invited_by   TEXT NOT NULL,
+        created_at   TEXT NOT NULL,
+        expires_at   TEXT NOT NULL,
+        accepted_at  TEXT
+    );
+    CREATE TABLE IF NOT EXISTS onboarded_tas (
+        name               TEXT PRIMARY KEY,
+        email              TEXT NOT NULL UNIQUE,
+        github             TEXT NOT NULL,
+        discord            TEXT NOT NULL,
+        notify_by_email    BOOLEAN NOT NULL,
+        notify_by_discord  BOOLEAN NOT NULL,
+        onboarded_at       TEXT NOT NULL
+    );
+    "#,
+    // 15: Attendance and per-criterion scores keyed by participant and week,
+    // joined back by the view (SQLite version 21)
+    r#"
+    CREATE TABLE IF NOT EXISTS attendance (
+        participant_id  TEXT NOT NULL
+            REFERENCES participants (email) ON UPDATE CASCADE ON DELETE CASCADE,
+        week            INTEGER NOT NULL,
+        status          TEXT,
+        PRIMARY KEY (participant_id, week)
+    );
+    CREATE TABLE IF NOT EXISTS scores (
+        participant_id  TEXT NOT NULL
+            REFERENCES participants (email) ON UPDATE CASCADE ON DELETE CASCADE,
+        week            INTEGER NOT NULL,
+        criterion       TEXT NOT NULL,
+        score           BIGINT NOT NULL,
+        PRIMARY KEY (participant_id, week, criterion)
+    );
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           COALESCE(fa.score, s.fa) AS fa,
+           COALESCE(fb.score, s.fb) AS fb,
+           COALESCE(fc.score, s.fc) AS fc,
+           COALESCE(fd.score, s.fd) AS fd,
+           COALESCE(bonus_attempt.score, s.bonus_attempt) AS bonus_attempt,
+           COALESCE(bonus_answer_quality.score, s.bonus_answer_quality) AS bonus_answer_quality,
+           COALESCE(bonus_follow_up.score, s.bonus_follow_up) AS bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at
+    FROM students s
+    LEFT JOIN attendance a ON a.participant_id = s.participant_id AND a.week = s.week
+    LEFT JOIN scores fa ON fa.participant_id = s.participant_id AND fa.week = s.week AND fa.criterion = 'fa'
+    LEFT JOIN scores fb ON fb.participant_id = s.participant_id AND fb.week = s.week AND fb.criterion = 'fb'
+    LEFT JOIN scores fc ON fc.participant_id = s.participant_id AND fc.week = s.week AND fc.criterion = 'fc'
+    LEFT JOIN scores fd ON fd.participant_id = s.participant_id AND fd.week = s.week AND fd.criterion = 'fd'
+    LEFT JOIN scores bonus_attempt ON bonus_attempt.participant_id = s.participant_id AND bonus_attempt.week = s.week AND bonus_attempt.criterion = 'bonus_attempt'
+    LEFT JOIN scores bonus_answer_quality ON bonus_answer_quality.participant_id = s.participant_id AND bonus_answer_quality.week = s.week AND bonus_answer_quality.criterion = 'bonus_answer_quality'
+    LEFT JOIN scores bonus_follow_up ON bonus_follow_up.participant_id = s.participant_id AND bonus_follow_up.week = s.week AND bonus_follow_up.criterion = 'bonus_follow_up'
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 16: TAs' exercise review summaries (SQLite version 22)
+    r#"
+    CREATE TABLE IF NOT EXISTS review_summaries (
+        week                INTEGER NOT NULL,
+        group_id            TEXT NOT NULL,
+        ta                  TEXT NOT NULL,
+        common_mistakes     TEXT NOT NULL,
+        standout_solutions  TEXT NOT NULL,
+        notes               TEXT,
+        submitted_at        TEXT NOT NULL,
+        PRIMARY KEY (week, group_id)
+    );
+    "#,
+    // 17: Public ids of rows and participants, assigned at startup
+    // (SQLite adds these at startup)
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS public_id TEXT UNIQUE;
+    ALTER TABLE participants ADD COLUMN IF NOT EXISTS public_id TEXT UNIQUE;
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           COALESCE(fa.score, s.fa) AS fa,
+           COALESCE(fb.score, s.fb) AS fb,
+           COALESCE(fc.score, s.fc) AS fc,
+           COALESCE(fd.score, s.fd) AS fd,
+           COALESCE(bonus_attempt.score, s.bonus_attempt) AS bonus_attempt,
+           COALESCE(bonus_answer_quality.score, s.bonus_answer_quality) AS bonus_answer_quality,
+           COALESCE(bonus_follow_up.score, s.bonus_follow_up) AS bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id
+    FROM students s
+    LEFT JOIN attendance a ON a.participant_id = s.participant_id AND a.week = s.week
+    LEFT JOIN scores fa ON fa.participant_id = s.participant_id AND fa.week = s.week AND fa.criterion = 'fa'
+    LEFT JOIN scores fb ON fb.participant_id = s.participant_id AND fb.week = s.week AND fb.criterion = 'fb'
+    LEFT JOIN scores fc ON fc.participant_id = s.participant_id AND fc.week = s.week AND fc.criterion = 'fc'
+    LEFT JOIN scores fd ON fd.participant_id = s.participant_id AND fd.week = s.week AND fd.criterion = 'fd'
+    LEFT JOIN scores bonus_attempt ON bonus_attempt.participant_id = s.participant_id AND bonus_attempt.week = s.week AND bonus_attempt.criterion = 'bonus_attempt'
+    LEFT JOIN scores bonus_answer_quality ON bonus_answer_quality.participant_id = s.participant_id AND bonus_answer_quality.week = s.week AND bonus_answer_quality.criterion = 'bonus_answer_quality'
+    LEFT JOIN scores bonus_follow_up ON bonus_follow_up.participant_id = s.participant_id AND bonus_follow_up.week = s.week AND bonus_follow_up.criterion = 'bonus_follow_up'
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 18: Row versions for optimistic concurrency
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           COALESCE(fa.score, s.fa) AS fa,
+           COALESCE(fb.score, s.fb) AS fb,
+           COALESCE(fc.score, s.fc) AS fc,
+           COALESCE(fd.score, s.fd) AS fd,
+           COALESCE(bonus_attempt.score, s.bonus_attempt) AS bonus_attempt,
+           COALESCE(bonus_answer_quality.score, s.bonus_answer_quality) AS bonus_answer_quality,
+           COALESCE(bonus_follow_up.score, s.bonus_follow_up) AS bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id, s.version
+    FROM students s
+    LEFT JOIN attendance a ON a.participant_id = s.participant_id AND a.week = s.week
+    LEFT JOIN scores fa ON fa.participant_id = s.participant_id AND fa.week = s.week AND fa.criterion = 'fa'
+    LEFT JOIN scores fb ON fb.participant_id = s.participant_id AND fb.week = s.week AND fb.criterion = 'fb'
+    LEFT JOIN scores fc ON fc.participant_id = s.participant_id AND fc.week = s.week AND fc.criterion = 'fc'
+    LEFT JOIN scores fd ON fd.participant_id = s.participant_id AND fd.week = s.week AND fd.criterion = 'fd'
+    LEFT JOIN scores bonus_attempt ON bonus_attempt.participant_id = s.participant_id AND bonus_attempt.week = s.week AND bonus_attempt.criterion = 'bonus_attempt'
+    LEFT JOIN scores bonus_answer_quality ON bonus_answer_quality.participant_id = s.participant_id AND bonus_answer_quality.week = s.week AND bonus_answer_quality.criterion = 'bonus_answer_quality'
+    LEFT JOIN scores bonus_follow_up ON bonus_follow_up.participant_id = s.participant_id AND bonus_follow_up.week = s.week AND bonus_follow_up.criterion = 'bonus_follow_up'
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 19: Responses to writes sent with an Idempotency-Key, kept for a day
+    r#"
+    CREATE TABLE IF NOT EXISTS idempotency_keys (
+        key         TEXT NOT NULL,
+        actor       TEXT NOT NULL,
+        fingerprint TEXT NOT NULL,
+        status      INTEGER NOT NULL,
+        body        TEXT NOT NULL,
+        created_at  TEXT NOT NULL,
+        PRIMARY KEY (key, actor)
+    );
+    "#,
+    // 20: Weeks closed for writes once grading is done
+    r#"
+    CREATE TABLE IF NOT EXISTS week_locks (
+        week      INTEGER PRIMARY KEY,
+        locked_by TEXT NOT NULL,
+        locked_at TEXT NOT NULL
+    );
+    "#,
+    // 21: TAs' freeform notes on weekly rows
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS notes TEXT;
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           COALESCE(fa.score, s.fa) AS fa,
+           COALESCE(fb.score, s.fb) AS fb,
+           COALESCE(fc.score, s.fc) AS fc,
+           COALESCE(fd.score, s.fd) AS fd,
+           COALESCE(bonus_attempt.score, s.bonus_attempt) AS bonus_attempt,
+           COALESCE(bonus_answer_quality.score, s.bonus_answer_quality) AS bonus_answer_quality,
+           COALESCE(bonus_follow_up.score, s.bonus_follow_up) AS bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id, s.version,
+           s.notes
+    FROM students s
+    LEFT JOIN attendance a ON a.participant_id = s.participant_id AND a.week = s.week
+    LEFT JOIN scores fa ON fa.participant_id = s.participant_id AND fa.week = s.week AND fa.criterion = 'fa'
+    LEFT JOIN scores fb ON fb.participant_id = s.participant_id AND fb.week = s.week AND fb.criterion = 'fb'
+    LEFT JOIN scores fc ON fc.participant_id = s.participant_id AND fc.week = s.week AND fc.criterion = 'fc'
+    LEFT JOIN scores fd ON fd.participant_id = s.participant_id AND fd.week = s.week AND fd.criterion = 'fd'
+    LEFT JOIN scores bonus_attempt ON bonus_attempt.participant_id = s.participant_id AND bonus_attempt.week = s.week AND bonus_attempt.criterion = 'bonus_attempt'
+    LEFT JOIN scores bonus_answer_quality ON bonus_answer_quality.participant_id = s.participant_id AND bonus_answer_quality.week = s.week AND bonus_answer_quality.criterion = 'bonus_answer_quality'
+    LEFT JOIN scores bonus_follow_up ON bonus_follow_up.participant_id = s.participant_id AND bonus_follow_up.week = s.week AND bonus_follow_up.criterion = 'bonus_follow_up'
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 22: When each weekly row was last written, for polling clients
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS updated_at TEXT;
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           COALESCE(fa.score, s.fa) AS fa,
+           COALESCE(fb.score, s.fb) AS fb,
+           COALESCE(fc.score, s.fc) AS fc,
+           COALESCE(fd.score, s.fd) AS fd,
+           COALESCE(bonus_attempt.score, s.bonus_attempt) AS bonus_attempt,
+           COALESCE(bonus_answer_quality.score, s.bonus_answer_quality) AS bonus_answer_quality,
+           COALESCE(bonus_follow_up.score, s.bonus_follow_up) AS bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id, s.version,
+           s.notes, s.updated_at
+    FROM students s
+    LEFT JOIN attendance a ON a.participant_id = s.participant_id AND a.week = s.week
+    LEFT JOIN scores fa ON fa.participant_id = s.participant_id AND fa.week = s.week AND fa.criterion = 'fa'
+    LEFT JOIN scores fb ON fb.participant_id = s.participant_id AND fb.week = s.week AND fb.criterion = 'fb'
+    LEFT JOIN scores fc ON fc.participant_id = s.participant_id AND fc.week = s.week AND fc.criterion = 'fc'
+    LEFT JOIN scores fd ON fd.participant_id = s.participant_id AND fd.week = s.week AND fd.criterion = 'fd'
+    LEFT JOIN scores bonus_attempt ON bonus_attempt.participant_id = s.participant_id AND bonus_attempt.week = s.week AND bonus_attempt.criterion = 'bonus_attempt'
+    LEFT JOIN scores bonus_answer_quality ON bonus_answer_quality.participant_id = s.participant_id AND bonus_answer_quality.week = s.week AND bonus_answer_quality.criterion = 'bonus_answer_quality'
+    LEFT JOIN scores bonus_follow_up ON bonus_follow_up.participant_id = s.participant_id AND bonus_follow_up.week = s.week AND bonus_follow_up.criterion = 'bonus_follow_up'
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 23: Commits in each row's exercise repo, as of the last sync
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS commit_count BIGINT;
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS last_commit_at TEXT;
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           COALESCE(fa.score, s.fa) AS fa,
+           COALESCE(fb.score, s.fb) AS fb,
+           COALESCE(fc.score, s.fc) AS fc,
+           COALESCE(fd.score, s.fd) AS fd,
+           COALESCE(bonus_attempt.score, s.bonus_attempt) AS bonus_attempt,
+           COALESCE(bonus_answer_quality.score, s.bonus_answer_quality) AS bonus_answer_quality,
+           COALESCE(bonus_follow_up.score, s.bonus_follow_up) AS bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id, s.version,
+           s.notes, s.updated_at, s.commit_count, s.last_commit_at
+    FROM students s
+    LEFT JOIN attendance a ON a.participant_id = s.participant_id AND a.week = s.week
+    LEFT JOIN scores fa ON fa.participant_id = s.participant_id AND fa.week = s.week AND fa.criterion = 'fa'
+    LEFT JOIN scores fb ON fb.participant_id = s.participant_id AND fb.week = s.week AND fb.criterion = 'fb'
+    LEFT JOIN scores fc ON fc.participant_id = s.participant_id AND fc.week = s.week AND fc.criterion = 'fc'
+    LEFT JOIN scores fd ON fd.participant_id = s.participant_id AND fd.week = s.week AND fd.criterion = 'fd'
+    LEFT JOIN scores bonus_attempt ON bonus_attempt.participant_id = s.participant_id AND bonus_attempt.week = s.week AND bonus_attempt.criterion = 'bonus_attempt'
+    LEFT JOIN scores bonus_answer_quality ON bonus_answer_quality.participant_id = s.participant_id AND bonus_answer_quality.week = s.week AND bonus_answer_quality.criterion = 'bonus_answer_quality'
+    LEFT JOIN scores bonus_follow_up ON bonus_follow_up.participant_id = s.participant_id AND bonus_follow_up.week = s.week AND bonus_follow_up.criterion = 'bonus_follow_up'
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 24: Each row's exercise repo, for graders to open
+    r#"
+    ALTER TABLE students ADD COLUMN IF NOT EXISTS repo_url TEXT;
+    CREATE OR REPLACE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           COALESCE(fa.score, s.fa) AS fa,
+           COALESCE(fb.score, s.fb) AS fb,
+           COALESCE(fc.score, s.fc) AS fc,
+           COALESCE(fd.score, s.fd) AS fd,
+           COALESCE(bonus_attempt.score, s.bonus_attempt) AS bonus_attempt,
+           COALESCE(bonus_answer_quality.score, s.bonus_answer_quality) AS bonus_answer_quality,
+           COALESCE(bonus_follow_up.score, s.bonus_follow_up) AS bonus_follow_up,
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id, s.version,
+           s.notes, s.updated_at, s.commit_count, s.last_commit_at,
+           s.repo_url
+    FROM students s
+    LEFT JOIN attendance a ON a.participant_id = s.participant_id AND a.week = s.week
+    LEFT JOIN scores fa ON fa.participant_id = s.participant_id AND fa.week = s.week AND fa.criterion = 'fa'
+    LEFT JOIN scores fb ON fb.participant_id = s.participant_id AND fb.week = s.week AND fb.criterion = 'fb'
+    LEFT JOIN scores fc ON fc.participant_id = s.participant_id AND fc.week = s.week AND fc.criterion = 'fc'
+    LEFT JOIN scores fd ON fd.participant_id = s.participant_id AND fd.week = s.week AND fd.criterion = 'fd'
+    LEFT JOIN scores bonus_attempt ON bonus_attempt.participant_id = s.participant_id AND bonus_attempt.week = s.week AND bonus_attempt.criterion = 'bonus_attempt'
+    LEFT JOIN scores bonus_answer_quality ON bonus_answer_quality.participant_id = s.participant_id AND bonus_answer_quality.week = s.week AND bonus_answer_quality.criterion = 'bonus_answer_quality'
+    LEFT JOIN scores bonus_follow_up ON bonus_follow_up.participant_id = s.participant_id AND bonus_follow_up.week = s.week AND bonus_follow_up.criterion = 'bonus_follow_up'
+    LEFT JOIN exercise_results e ON e.participant_id = s.participant_id AND e.week = s.week;
+    "#,
+    // 25: Webhook deliveries already handled, so an old signed one cannot be
+    // replayed
+    r#"
+    CREATE TABLE IF NOT EXISTS webhook_deliveries (
+        id          TEXT PRIMARY KEY,
+        received_at TEXT NOT NULL
+    );
+    "#,
+];
+
+fn run_migrations(client: &mut Client) -> Result<(), AppError> {
+    client.batch_execute("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")?;
+    let current: i32 = client
+        .query_opt("SELECT version FROM schema_version", &[])?
+        .map(|row| row.get(0))
+        .unwrap_or(0);
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as i32 + 1;
+        info!("Applying Postgres schema migration {}", version);
+        let mut tx = client.transaction()?;
+        tx.batch_execute(migration)?;
+        tx.execute("DELETE FROM schema_version", &[])?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES ($1)",
+            &[&version],
+        )?;
+        tx.commit()?;
+    }
+    assign_public_ids(client)?;
+    key_details_by_row(client)?;
+    link_participants(client)
+}
+
+// Same as the SQLite startup step: rebuilds the detail tables keyed by the
+// row's public id instead of participant and week, so duplicate rows of a
+// participant keep their own grades. Details are deleted with their row.
+fn key_details_by_row(client: &mut Client) -> Result<(), AppError> {
+    let keyed_by_participant = client
+        .query_opt(
+            "SELECT 1 FROM information_schema.columns WHERE table_name = 'attendance' AND column_name = 'participant_id'",
+            &[],
+        )?
+        .is_some();
+    if !keyed_by_participant {
+        return Ok(());
+    }
+    info!("Keying attendance, scores and exercise results by student row");
+    let score_columns = SCORE_COLUMNS
+        .map(|column| format!("COALESCE({0}.score, s.{0}) AS {0}", column))
+        .join(",\n           ");
+    let score_joins = SCORE_COLUMNS
+        .map(|column| {
+            format!(
+                "LEFT JOIN scores {0} ON {0}.student_id = s.public_id AND {0}.criterion = '{0}'",
+                column
+            )
+        })
+        .join("\n    ");
+    let mut tx = client.transaction()?;
+    tx.batch_execute(&format!(
+        "DROP VIEW IF EXISTS student_rows;
+    ALTER TABLE attendance RENAME TO attendance_by_participant;
+    ALTER TABLE scores RENAME TO scores_by_participant;
+    ALTER TABLE exercise_results RENAME TO exercise_results_by_participant;
+    CREATE TABLE attendance (
+        student_id  TEXT PRIMARY KEY
+            REFERENCES students (public_id) ON UPDATE CASCADE ON DELETE CASCADE,
+        status      TEXT
+    );
+    CREATE TABLE scores (
+        student_id  TEXT NOT NULL
+            REFERENCES students (public_id) ON UPDATE CASCADE ON DELETE CASCADE,
+        criterion   TEXT NOT NULL,
+        score       BIGINT NOT NULL,
+        PRIMARY KEY (student_id, criterion)
+    );
+    CREATE TABLE exercise_results (
+        student_id          TEXT PRIMARY KEY
+            REFERENCES students (public_id) ON UPDATE CASCADE ON DELETE CASCADE,
+        submitted           TEXT,
+        test_passing        TEXT,
+        good_documentation  TEXT,
+        good_structure      TEXT
+    );
+    INSERT INTO attendance (student_id, status)
+        SELECT s.public_id, d.status FROM attendance_by_participant d
+        JOIN students s ON s.participant_id = d.participant_id AND s.week = d.week
+        WHERE s.public_id IS NOT NULL;
+    INSERT INTO scores (student_id, criterion, score)
+        SELECT s.public_id, d.criterion, d.score FROM scores_by_participant d
+        JOIN students s ON s.participant_id = d.participant_id AND s.week = d.week
+        WHERE s.public_id IS NOT NULL;
+    INSERT INTO exercise_results (student_id, submitted, test_passing, good_documentation, good_structure)
+        SELECT s.public_id, d.submitted, d.test_passing, d.good_documentation, d.good_structure
+        FROM exercise_results_by_participant d
+        JOIN students s ON s.participant_id = d.participant_id AND s.week = d.week
+        WHERE s.public_id IS NOT NULL;
+    DROP TABLE attendance_by_participant;
+    DROP TABLE scores_by_participant;
+    DROP TABLE exercise_results_by_participant;
+    CREATE VIEW student_rows AS
+    SELECT s.name, s.group_id, s.ta,
+           COALESCE(a.status, s.attendance) AS attendance,
+           {},
+           COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+           COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+           COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+           COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+           s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id, s.version,
+           s.notes, s.updated_at, s.commit_count, s.last_commit_at,
+           s.repo_url
+    FROM students s
+    LEFT JOIN attendance a ON a.student_id = s.public_id
+    {}
+    LEFT JOIN exercise_results e ON e.student_id = s.public_id;",
+        score_columns, score_joins
+    ))?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Same as the SQLite startup step: gives rows and participants without a
+// public id a new one
+fn assign_public_ids(client: &mut Client) -> Result<(), AppError> {
+    let mut tx = client.transaction()?;
+    let rows: Vec<(String, i32)> = tx
+        .query(
+            "SELECT name, week FROM students WHERE public_id IS NULL",
+            &[],
+        )?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    for (name, week) in &rows {
+        tx.execute(
+            "UPDATE students SET public_id = $1 WHERE name = $2 AND week = $3",
+            &[&new_public_id(), name, week],
+        )?;
+    }
+    let participants: Vec<String> = tx
+        .query(
+            "SELECT email FROM participants WHERE public_id IS NULL",
+            &[],
+        )?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    for email in &participants {
+        tx.execute(
+            "UPDATE participants SET public_id = $1 WHERE email = $2",
+            &[&new_public_id(), email],
+        )?;
+    }
+    tx.commit()?;
+    if !rows.is_empty() || !participants.is_empty() {
+        info!(
+            "Assigned public ids to {} student row(s) and {} participant(s)",
+            rows.len(),
+            participants.len()
+        );
+    }
+    Ok(())
+}
+
+// Same as the SQLite startup step: links unlinked rows by name, then renames
+// rows after their participant unless the new name is taken that week
+fn link_participants(client: &mut Client) -> Result<(), AppError> {
+    let linked = client.execute(
+        "UPDATE students s SET participant_id = p.email FROM participants p
+         WHERE s.participant_id IS NULL AND lower(p.name) = lower(s.name)",
+        &[],
+    )?;
+    let renamed = client.execute(
+        "UPDATE students s SET name = p.name FROM participants p
+         WHERE p.email = s.participant_id AND p.name <> s.name
+           AND NOT EXISTS (SELECT 1 FROM students t WHERE t.name = p.name AND t.week = s.week)",
+        &[],
+    )?;
+    if linked > 0 {
+        info!("Linked {} student rows to their participant", linked);
+    }
+    if renamed > 0 {
+        info!("Renamed {} student rows after their participant", renamed);
+    }
+    let pending = std::iter::once("attendance")
+        .chain(SCORE_COLUMNS)
+        .chain([
+            "exercise_submitted",
+            "exercise_test_passing",
+            "exercise_good_documentation",
+            "exercise_good_structure",
+        ])
+        .collect::<Vec<_>>()
+        .join(", ");
+    let moved = move_row_details(client, &format!("COALESCE({}) IS NOT NULL", pending), &[])?;
+    if moved > 0 {
+        info!(
+            "Moved attendance, scores and exercise results of {} student rows",
+            moved
+        );
+    }
+    Ok(())
+}
+
+// Same as the SQLite helper: moves the attendance, score and exercise
+// columns of the linked rows matching `filter` into their own tables, keyed
+// by the row's public id
+fn move_row_details(
+    client: &mut impl GenericClient,
+    filter: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<u64, AppError> {
+    let moved = client.execute(
+        &format!(
+            "INSERT INTO attendance (student_id, status)
+             SELECT public_id, attendance
+             FROM students WHERE participant_id IS NOT NULL AND {}
+             ON CONFLICT (student_id) DO UPDATE SET status = excluded.status",
+            filter
+        ),
+        params,
+    )?;
+    client.execute(
+        &format!(
+            "DELETE FROM scores WHERE student_id IN
+             (SELECT public_id FROM students WHERE participant_id IS NOT NULL AND {})",
+            filter
+        ),
+        params,
+    )?;
+    let scores = SCORE_COLUMNS
+        .map(|column| {
+            format!(
+                "SELECT public_id, '{0}' AS criterion, {0} AS score
+                 FROM students WHERE participant_id IS NOT NULL AND {1}",
+                column, filter
+            )
+        })
+        .join(" UNION ALL ");
+    client.execute(
+        &format!(
+            "INSERT INTO scores (student_id, criterion, score)
+             SELECT public_id, criterion, score FROM ({}) AS pending WHERE score IS NOT NULL",
+            scores
+        ),
+        params,
+    )?;
+    client.execute(
+        &format!(
+            "INSERT INTO exercise_results (student_id, submitted, test_passing, good_documentation, good_structure)
+             SELECT public_id, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure
+             FROM students WHERE participant_id IS NOT NULL AND {}
+             ON CONFLICT (student_id) DO UPDATE SET submitted = excluded.submitted, test_passing = excluded.test_passing, good_documentation = excluded.good_documentation, good_structure = excluded.good_structure",
+            filter
+        ),
+        params,
+    )?;
+    client.execute(
+        &format!(
+            "UPDATE students SET attendance = NULL, {}, exercise_submitted = NULL, exercise_test_passing = NULL, exercise_good_documentation = NULL, exercise_good_structure = NULL
+             WHERE participant_id IS NOT NULL AND {}",
+            SCORE_COLUMNS.map(|column| format!("{} = NULL", column)).join(", "),
+            filter
+        ),
+        params,
+    )?;
+    Ok(moved)
+}
+
+// The synchronous client drives its own runtime, which cannot be entered
+// from an actix worker thread, so database calls run on a scoped thread
+fn off_runtime<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        scope
+            .spawn(f)
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+// Postgres stores scores as BIGINT, the table model uses unsigned values
+fn to_db(value: Option<u64>) -> Option<i64> {
+    value.map(|v| v as i64)
+}
+
+fn from_db(value: Option<i64>) -> Option<u64> {
+    value.map(|v| v.max(0) as u64)
+}
+
+fn student_from_row(row: &Row) -> Result<RowData, AppError> {
+    Ok(RowData {
+        name: row.get(0),
+        group_id: row.get(1),
+        ta: row.get(2),
+        attendance: row.get(3),
+        fa: from_db(row.get(4)),
+        fb: from_db(row.get(5)),
+        fc: from_db(row.get(6)),
+        fd: from_db(row.get(7)),
+        bonus_attempt: from_db(row.get(8)),
+        bonus_answer_quality: from_db(row.get(9)),
+        bonus_follow_up: from_db(row.get(10)),
+        exercise_submitted: row.get(11),
+        exercise_test_passing: row.get(12),
+        exercise_good_documentation: row.get(13),
+        exercise_good_structure: row.get(14),
+        total: from_db(row.get(15)),
+        mail: decrypt_mail(row.get(16))?,
+        week: row.get(17),
+        participant_id: row.get(18),
+        public_id: row.get(19),
+        student_id: None,
+        version: row.get(20),
+        notes: row.get(21),
+        commit_count: from_db(row.get(22)),
+        last_commit_at: row.get(23),
+        repo_url: row.get(24),
+    })
+}
+
+// Sets the public id of each row's student, from its participant
+fn fill_student_ids(client: &mut impl GenericClient, rows: &mut [RowData]) -> Result<(), AppError> {
+    let ids: HashMap<String, String> = client
+        .query(
+            "SELECT email, public_id FROM participants WHERE public_id IS NOT NULL",
+            &[],
+        )?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    for row in rows {
+        row.student_id = row
+            .participant_id
+            .as_ref()
+            .and_then(|id| ids.get(id))
+            .cloned();
+    }
+    Ok(())
+}
+
+// Inserts or updates rows keyed by (name, week). A row that knows its
+// participant is first renamed to the name it is written under, so a rename
+// updates it instead of adding another. Writing a soft-deleted row brings it
+// back. The attendance, scores and exercise fields of linked rows go to
+// their own tables.
+fn upsert_students(tx: &mut Transaction, rows: &[RowData]) -> Result<(), AppError> {
+    let now = Utc::now().to_rfc3339();
+    let rename = tx.prepare(
+        "UPDATE students s SET name = $1 WHERE participant_id = $2 AND week = $3 AND name <> $1
+         AND NOT EXISTS (SELECT 1 FROM students t WHERE t.name = $1 AND t.week = $3)",
+    )?;
+    let stmt = tx.prepare(
+        "INSERT INTO students (name, group_id, ta, attendance, fa, fb, fc, fd, bonus_attempt, bonus_answer_quality, bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, total, mail, week, participant_id, public_id, version, notes, updated_at, commit_count, last_commit_at, repo_url) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, COALESCE($21, 1), $22, $23, $24, $25, $26) ON CONFLICT (name, week) DO UPDATE SET participant_id = COALESCE(excluded.participant_id, students.participant_id), public_id = COALESCE(students.public_id, excluded.public_id), version = COALESCE($21, students.version + 1), group_id = excluded.group_id, ta = excluded.ta, attendance = excluded.attendance, fa = excluded.fa, fb = excluded.fb, fc = excluded.fc, fd = excluded.fd, bonus_attempt = excluded.bonus_attempt, bonus_answer_quality = excluded.bonus_answer_quality, bonus_follow_up = excluded.bonus_follow_up, exercise_submitted = excluded.exercise_submitted, exercise_test_passing = excluded.exercise_test_passing, exercise_good_documentation = excluded.exercise_good_documentation, exercise_good_structure = excluded.exercise_good_structure, total = excluded.total, notes = excluded.notes, mail = excluded.mail, updated_at = excluded.updated_at, commit_count = excluded.commit_count, last_commit_at = excluded.last_commit_at, repo_url = excluded.repo_url, deleted_at = NULL",
+    )?;
+    for row in rows {
+        if row.participant_id.is_some() {
+            tx.execute(&rename, &[&row.name, &row.participant_id, &row.week])?;
+        }
+        // Kept by rows that already have one
+        let public_id = row
+            .public_id
+            .clone()
+            .filter(|id| is_public_id(id))
+            .unwrap_or_else(new_public_id);
+        tx.execute(
+            &stmt,
+            &[
+                &row.name,
+                &row.group_id,
+                &row.ta,
+                &row.attendance,
+                &to_db(row.fa),
+                &to_db(row.fb),
+                &to_db(row.fc),
+                &to_db(row.fd),
+                &to_db(row.bonus_attempt),
+                &to_db(row.bonus_answer_quality),
+                &to_db(row.bonus_follow_up),
+                &row.exercise_submitted,
+                &row.exercise_test_passing,
+                &row.exercise_good_documentation,
+                &row.exercise_good_structure,
+                &to_db(row.total),
+                &encrypt_mail(&row.mail),
+                &row.week,
+                &row.participant_id,
+                &public_id,
+                &row.version,
+                &row.notes,
+                &now,
+                &to_db(row.commit_count),
+                &row.last_commit_at,
+                &row.repo_url,
+            ],
+        )?;
+        move_row_details(tx, "name = $1 AND week = $2", &[&row.name, &row.week])?;
+    }
+    Ok(())
+}
+
+fn insert_history(
+    tx: &mut Transaction,
+    changes: &[RowChange],
+    actor: &str,
+) -> Result<(), AppError> {
+    let stmt = tx.prepare(
+        "INSERT INTO row_history (name, week, field, old_value, new_value, actor, changed_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )?;
+    for change in changes {
+        tx.execute(
+            &stmt,
+            &[
+                &change.name,
+                &change.week,
+                &change.field,
+                &change.old_value,
+                &change.new_value,
+                &actor,
+                &change.changed_at,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// Hosted storage for running several cohorts against one Postgres server,
+// one database (or schema via `options=-csearch_path=...`) per cohort.
+// TLS is negotiated as the URL's `sslmode` asks, `prefer` by default.
+pub struct PostgresStorage {
+    pool: PgPool,
+    // Sessions with default_transaction_read_only on, for the read methods
+    reader: PgPool,
+}
+
+impl PostgresStorage {
+    pub fn connect(url: &str) -> Result<Self, AppError> {
+        let config: postgres::Config = url.parse()?;
+        let tls = TlsConnector::new().map_err(std::io::Error::other)?;
+        let mut read_config = config.clone();
+        let read_only = "-c default_transaction_read_only=on";
+        match config.get_options() {
+            Some(options) => read_config.options(&format!("{} {}", options, read_only)),
+            None => read_config.options(read_only),
+        };
+        let manager = PostgresConnectionManager::new(config, MakeTlsConnector::new(tls.clone()));
+        let read_manager = PostgresConnectionManager::new(read_config, MakeTlsConnector::new(tls));
+        let pool = off_runtime(|| Pool::builder().max_size(pool_size()).build(manager))?;
+        let reader = off_runtime(|| Pool::builder().max_size(pool_size()).build(read_manager))?;
+
+        let storage = PostgresStorage { pool, reader };
+        storage.run(run_migrations)?;
+        Ok(storage)
+    }
+
+    fn run<T: Send>(
+        &self,
+        f: impl FnOnce(&mut Client) -> Result<T, AppError> + Send,
+    ) -> Result<T, AppError> {
+        off_runtime(|| {
+            let mut client = self.pool.get()?;
+            f(&mut client)
+        })
+    }
+
+    // As `run`, on a session that rejects writes
+    fn read<T: Send>(
+        &self,
+        f: impl FnOnce(&mut Client) -> Result<T, AppError> + Send,
+    ) -> Result<T, AppError> {
+        off_runtime(|| {
+            let mut client = self.reader.get()?;
+            f(&mut client)
+        })
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn name(&self) -> &'static str {
+        "Postgres"
+    }
+
+    fn read_from_db(&self) -> Result<Table, AppError> {
+        let rows = self.read(|client| {
+            let mut rows = client
+                .query("SELECT name, group_id, ta, attendance, fa, fb, fc, fd, bonus_attempt, bonus_answer_quality, bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, total, mail, week, participant_id, public_id, version, notes, commit_count, last_commit_at, repo_url FROM student_rows WHERE deleted_at IS NULL", &[])?
+                .iter()
+                .map(student_from_row)
+                .collect::<Result<Vec<RowData>, AppError>>()?;
+            fill_student_ids(client, &mut rows)?;
+            Ok(rows)
+        })?;
+        info!("Successfully read {} rows from the database.", rows.len());
+        Ok(Table::new(rows))
+    }
+
+    fn read_changed_rows(
+        &self,
+        since: DateTime<Utc>,
+        week: Option<i32>,
+    ) -> Result<ChangedRows, AppError> {
+        let since = since.to_rfc3339();
+        self.read(|client| {
+            let mut rows = client
+                .query("SELECT name, group_id, ta, attendance, fa, fb, fc, fd, bonus_attempt, bonus_answer_quality, bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, total, mail, week, participant_id, public_id, version, notes, commit_count, last_commit_at, repo_url FROM student_rows WHERE deleted_at IS NULL AND updated_at > $1 AND ($2::INTEGER IS NULL OR week = $2) ORDER BY updated_at", &[&since, &week])?
+                .iter()
+                .map(student_from_row)
+                .collect::<Result<Vec<RowData>, AppError>>()?;
+            fill_student_ids(client, &mut rows)?;
+            let deleted = client
+                .query(
+                    "SELECT name, week FROM students WHERE deleted_at > $1 AND ($2::INTEGER IS NULL OR week = $2) ORDER BY deleted_at",
+                    &[&since, &week],
+                )?
+                .iter()
+                .map(|row| DeletedRow {
+                    name: row.get(0),
+                    week: row.get(1),
+                })
+                .collect();
+            Ok(ChangedRows { rows, deleted })
+        })
+    }
+
+    fn upsert_rows(&self, rows: &[RowData]) -> Result<(), AppError> {
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            upsert_students(&mut tx, rows)?;
+            tx.commit()?;
+            Ok(())
+        })?;
+        info!("Successfully wrote {} rows to the database.", rows.len());
+        Ok(())
+    }
+
+    fn write_rows(
+        &self,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<(), AppError> {
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            upsert_students(&mut tx, rows)?;
+            insert_history(&mut tx, history, actor)?;
+            tx.commit()?;
+            Ok(())
+        })?;
+        info!("Successfully wrote {} rows to the database.", rows.len());
+        Ok(())
+    }
+
+    fn replace_week(
+        &self,
+        week: i32,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<usize, AppError> {
+        let now = Utc::now().to_rfc3339();
+        let kept: Vec<&str> = rows.iter().map(|row| row.name.as_str()).collect();
+        let deleted = self.run(|client| {
+            let mut tx = client.transaction()?;
+            let deleted = tx.execute(
+                "UPDATE students SET deleted_at = $3
+                 WHERE week = $1 AND deleted_at IS NULL AND NOT (name = ANY($2))",
+                &[&week, &kept, &now],
+            )?;
+            upsert_students(&mut tx, rows)?;
+            insert_history(&mut tx, history, actor)?;
+            tx.commit()?;
+            Ok(deleted)
+        })?;
+        info!(
+            "Replaced week {} with {} rows, deleting {}.",
+            week,
+            rows.len(),
+            deleted
+        );
+        Ok(deleted as usize)
+    }
+
+    fn merge_rows(
+        &self,
+        duplicate: &str,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<usize, AppError> {
+        let participants: HashSet<(&str, &str)> = rows
+            .iter()
+            .filter_map(|row| Some((row.participant_id.as_deref()?, row.name.as_str())))
+            .collect();
+        let deleted = self.run(|client| {
+            let mut tx = client.transaction()?;
+            let deleted = tx.execute("DELETE FROM students WHERE name = $1", &[&duplicate])?;
+            for &(participant, name) in &participants {
+                tx.execute(
+                    "UPDATE participants SET name = $1 WHERE email = $2 AND lower(name) = lower($3)",
+                    &[&name, &participant, &duplicate],
+                )?;
+            }
+            upsert_students(&mut tx, rows)?;
+            insert_history(&mut tx, history, actor)?;
+            tx.commit()?;
+            Ok(deleted)
+        })?;
+        info!(
+            "Merged {} rows of {} into {} rows.",
+            deleted,
+            duplicate,
+            rows.len()
+        );
+        Ok(deleted as usize)
+    }
+
+    fn delete_rows(&self, name: &str, week: Option<i32>) -> Result<usize, AppError> {
+        let now = Utc::now().to_rfc3339();
+        let deleted = self.run(|client| {
+            Ok(match week {
+                Some(week) => client.execute(
+                    "UPDATE students SET deleted_at = $3 WHERE name = $1 AND week = $2 AND deleted_at IS NULL",
+                    &[&name, &week, &now],
+                )?,
+                None => client.execute(
+                    "UPDATE students SET deleted_at = $2 WHERE name = $1 AND deleted_at IS NULL",
+                    &[&name, &now],
+                )?,
+            })
+        })?;
+        info!("Soft-deleted {} rows from the database.", deleted);
+        Ok(deleted as usize)
+    }
+
+    // The rows' attendance, scores and exercise results are deleted with
+    // them, by the ON DELETE CASCADE of the detail tables
+    fn purge_rows(&self, name: &str, week: Option<i32>) -> Result<usize, AppError> {
+        let deleted = self.run(|client| {
+            Ok(match week {
+                Some(week) => client.execute(
+                    "DELETE FROM students WHERE name = $1 AND week = $2",
+                    &[&name, &week],
+                )?,
+                None => client.execute("DELETE FROM students WHERE name = $1", &[&name])?,
+            })
+        })?;
+        info!("Deleted {} rows from the database.", deleted);
+        Ok(deleted as usize)
+    }
+
+    fn delete_week(&self, week: i32, permanent: bool) -> Result<usize, AppError> {
+        let now = Utc::now().to_rfc3339();
+        let deleted = self.run(|client| {
+            let mut tx = client.transaction()?;
+            let deleted = if permanent {
+                tx.execute("DELETE FROM students WHERE week = $1", &[&week])?
+            } else {
+                tx.execute(
+                    "UPDATE students SET deleted_at = $2 WHERE week = $1 AND deleted_at IS NULL",
+                    &[&week, &now],
+                )?
+            };
+            tx.commit()?;
+            Ok(deleted)
+        })?;
+        info!(
+            "Deleted {} rows of week {} from the database.",
+            deleted, week
+        );
+        Ok(deleted as usize)
+    }
+
+    fn read_deleted_row(&self, name: &str, week: i32) -> Result<Option<RowData>, AppError> {
+        self.read(|client| {
+            client
+                .query_opt("SELECT name, group_id, ta, attendance, fa, fb, fc, fd, bonus_attempt, bonus_answer_quality, bonus_follow_up, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure, total, mail, week, participant_id, public_id, version, notes, commit_count, last_commit_at, repo_url FROM student_rows WHERE name = $1 AND week = $2 AND deleted_at IS NOT NULL", &[&name, &week])?
+                .as_ref()
+                .map(student_from_row)
+                .transpose()?
+                .map(|mut row| {
+                    fill_student_ids(client, std::slice::from_mut(&mut row))?;
+                    Ok(row)
+                })
+                .transpose()
+        })
+    }
+
+    fn restore_row(&self, name: &str, week: i32) -> Result<bool, AppError> {
+        let restored = self.run(|client| {
+            Ok(client.execute(
+                "UPDATE students SET deleted_at = NULL, updated_at = $3 WHERE name = $1 AND week = $2 AND deleted_at IS NOT NULL",
+                &[&name, &week, &Utc::now().to_rfc3339()],
+            )?)
+        })?;
+        Ok(restored > 0)
+    }
+
+    fn read_row_history(&self, name: &str, week: i32) -> Result<Vec<RowHistoryEntry>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT id, name, week, field, old_value, new_value, actor, changed_at FROM row_history WHERE name = $1 AND week = $2 ORDER BY changed_at DESC, id DESC",
+                    &[&name, &week],
+                )?
+                .iter()
+                .map(|row| RowHistoryEntry {
+                    id: row.get(0),
+                    change: RowChange {
+                        name: row.get(1),
+                        week: row.get(2),
+                        field: row.get(3),
+                        old_value: row.get(4),
+                        new_value: row.get(5),
+                        changed_at: row.get(7),
+                    },
+                    actor: row.get(6),
+                })
+                .collect())
+        })
+    }
+
+    fn read_duplicate_rows(&self) -> Result<Vec<(String, i32, usize)>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT name, week, COUNT(*) FROM students WHERE deleted_at IS NULL GROUP BY name, week HAVING COUNT(*) > 1 ORDER BY week, name",
+                    &[],
+                )?
+                .iter()
+                .map(|row| (row.get(0), row.get(1), row.get::<_, i64>(2) as usize))
+                .collect())
+        })
+    }
+
+    fn encrypt_existing_mail(&self) -> Result<usize, AppError> {
+        let Some(cipher) = mail_cipher() else {
+            return Ok(0);
+        };
+
+        let updated = self.run(|client| {
+            let mut tx = client.transaction()?;
+            let plaintext: Vec<String> = tx
+                .query(
+                    "SELECT DISTINCT mail FROM students WHERE mail != '' AND mail NOT LIKE 'enc:v1:%'",
+                    &[],
+                )?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+
+            let mut updated = 0;
+            for mail in &plaintext {
+                updated += tx.execute(
+                    "UPDATE students SET mail = $1 WHERE mail = $2",
+                    &[&cipher.encrypt(mail), mail],
+                )?;
+            }
+            tx.commit()?;
+            Ok(updated as usize)
+        })?;
+
+        if updated > 0 {
+            info!("Encrypted mail for {} existing student row(s)", updated);
+        }
+        Ok(updated)
+    }
+
+    // Exact matches use the indexes; the suffix match is kept as a fallback
+    // for values the exact columns cannot normalize
+    fn github_to_name(&self, github_username: &str) -> Result<Option<String>, AppError> {
+        let login = github_login(github_username);
+        let pattern = format!("%{}", github_username);
+        self.read(|client| {
+            let exact = client.query_opt(
+                "SELECT name FROM participants WHERE github_login = $1 LIMIT 1",
+                &[&login],
+            )?;
+            let row = match exact {
+                Some(row) => Some(row),
+                None => client.query_opt(
+                    "SELECT name FROM participants WHERE github ILIKE $1 LIMIT 1",
+                    &[&pattern],
+                )?,
+            };
+            Ok(row.map(|row| row.get(0)))
+        })
+    }
+
+    fn github_username(&self, name: &str) -> Result<Option<String>, AppError> {
+        let pattern = format!("%{}", name);
+        self.read(|client| {
+            let linked = client.query_opt(
+                "SELECT p.github FROM students s JOIN participants p ON p.email = s.participant_id WHERE s.name = $1 AND p.github IS NOT NULL LIMIT 1",
+                &[&name],
+            )?;
+            let exact = match linked {
+                Some(row) => Some(row),
+                None => client.query_opt(
+                    "SELECT github FROM participants WHERE lower(name) = lower($1) AND github IS NOT NULL LIMIT 1",
+                    &[&name],
+                )?,
+            };
+            let row = match exact {
+                Some(row) => Some(row),
+                None => client.query_opt(
+                    "SELECT github FROM participants WHERE name ILIKE $1 AND github IS NOT NULL LIMIT 1",
+                    &[&pattern],
+                )?,
+            };
+            Ok(row.map(|row| row.get(0)))
+        })
+    }
+
+    fn read_participant_names(&self) -> Result<Vec<String>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query("SELECT name FROM participants WHERE name IS NOT NULL", &[])?
+                .iter()
+                .map(|row| row.get(0))
+                .collect())
+        })
+    }
+
+    fn search_participants(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<ParticipantMatch>, AppError> {
+        let pattern = contains_pattern(query);
+        let limit = limit as i64;
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT email, name, email, github, public_id FROM participants \
+                     WHERE name ILIKE $1 OR email ILIKE $1 OR github ILIKE $1 \
+                     ORDER BY lower(name) LIMIT $2",
+                    &[&pattern, &limit],
+                )?
+                .iter()
+                .map(|row| ParticipantMatch {
+                    id: row.get(0),
+                    name: row.get(1),
+                    email: row.get(2),
+                    github: row.get(3),
+                    public_id: row.get(4),
+                })
+                .collect())
+        })
+    }
+
+    fn read_participant_locations(&self) -> Result<Vec<String>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT location FROM participants WHERE trim(location) <> ''",
+                    &[],
+                )?
+                .iter()
+                .map(|row| row.get(0))
+                .collect())
+        })
+    }
+
+    fn background_data(&self, email: &str) -> Result<Option<BackgroundData>, AppError> {
+        self.read(|client| {
+            let row = client.query_opt(
+                "SELECT describe_yourself, background, skills, location, year, why, books FROM participants WHERE email = $1",
+                &[&email],
+            )?;
+            Ok(row.map(|row| {
+                let text = |index: usize| row.get::<_, Option<String>>(index).unwrap_or_default();
+                BackgroundData {
+                    describe_yourself: text(0),
+                    background: text(1),
+                    skills: text(2),
+                    location: text(3),
+                    year: text(4),
+                    why: text(5),
+                    book: text(6),
+                }
+            }))
+        })
+    }
+
+    fn replace_responses(
+        &self,
+        headers: &[String],
+        records: &[Vec<String>],
+    ) -> Result<(), AppError> {
+        let columns: Vec<String> = headers.iter().map(|h| h.replace(' ', "_")).collect();
+        let rows: Vec<String> = records
+            .iter()
+            .map(|record| {
+                let object: serde_json::Map<String, serde_json::Value> = columns
+                    .iter()
+                    .cloned()
+                    .zip(record.iter().cloned().map(serde_json::Value::String))
+                    .collect();
+                serde_json::Value::Object(object).to_string()
+            })
+            .collect();
+
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute("DELETE FROM responses", &[])?;
+            for data in &rows {
+                tx.execute("INSERT INTO responses (data) VALUES ($1)", &[data])?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn read_all_responses(&self, _cohort_name: &str) -> Result<Vec<FeedbackResponse>, AppError> {
+        let rows: Vec<String> = self.read(|client| {
+            Ok(client
+                .query("SELECT data FROM responses ORDER BY id", &[])?
+                .iter()
+                .map(|row| row.get(0))
+                .collect())
+        })?;
+
+        Ok(rows
+            .iter()
+            .map(|data| {
+                let object: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(data).unwrap_or_default();
+                FeedbackResponse::from_fields(|column| {
+                    object
+                        .get(column)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                })
+            })
+            .collect())
+    }
+
+    fn record_voice_snapshot(&self, week: i32, members: &[Member]) -> Result<usize, AppError> {
+        let taken_at = Utc::now().to_rfc3339();
+        let recorded = self.run(|client| {
+            let mut tx = client.transaction()?;
+            let mut recorded = 0;
+            for member in members {
+                if let Some(discord_id) = member.discord_id() {
+                    tx.execute(
+                        "INSERT INTO voice_snapshots (week, taken_at, discord_id, discord_name) VALUES ($1, $2, $3, $4)",
+                        &[&week, &taken_at, &discord_id, &member.display_name()],
+                    )?;
+                    recorded += 1;
+                }
+            }
+            tx.commit()?;
+            Ok(recorded)
+        })?;
+        info!(
+            "Recorded voice snapshot for week {} with {} member(s)",
+            week, recorded
+        );
+        Ok(recorded)
+    }
+
+    fn read_voice_attendees(&self, week: i32) -> Result<Vec<VoiceAttendee>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT discord_id, MAX(discord_name), COUNT(DISTINCT taken_at) FROM voice_snapshots WHERE week = $1 GROUP BY discord_id",
+                    &[&week],
+                )?
+                .iter()
+                .map(|row| VoiceAttendee {
+                    discord_id: row.get(0),
+                    discord_name: row.get(1),
+                    snapshots_seen: row.get::<_, i64>(2) as u32,
+                })
+                .collect())
+        })
+    }
+
+    fn read_discord_handles(&self) -> Result<HashMap<String, String>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query("SELECT discord_id, name FROM discord_handles", &[])?
+                .iter()
+                .map(|row| (row.get(0), row.get(1)))
+                .collect())
+        })
+    }
+
+    fn upsert_discord_handle(&self, discord_id: &str, name: &str) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO discord_handles (discord_id, name) VALUES ($1, $2) ON CONFLICT (discord_id) DO UPDATE SET name = excluded.name",
+                &[&discord_id, &name],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn record_communication(
+        &self,
+        participant: &str,
+        kind: CommunicationKind,
+        subject: &str,
+        note: Option<&str>,
+        sent_by: &str,
+    ) -> Result<Communication, AppError> {
+        let sent_at = Utc::now().to_rfc3339();
+        let id: i64 = self.run(|client| {
+            Ok(client
+                .query_one(
+                    "INSERT INTO communications (participant, kind, subject, note, sent_by, sent_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+                    &[&participant, &kind.as_str(), &subject, &note, &sent_by, &sent_at],
+                )?
+                .get(0))
+        })?;
+        info!(
+            "Recorded {} to {} by {}",
+            kind.as_str(),
+            participant,
+            sent_by
+        );
+        Ok(Communication {
+            id,
+            participant: participant.to_string(),
+            kind,
+            subject: subject.to_string(),
+            note: note.map(str::to_string),
+            sent_by: sent_by.to_string(),
+            sent_at,
+        })
+    }
+
+    fn read_communications(&self, participant: &str) -> Result<Vec<Communication>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT id, participant, kind, subject, note, sent_by, sent_at FROM communications WHERE participant = $1 ORDER BY sent_at DESC, id DESC",
+                    &[&participant],
+                )?
+                .iter()
+                .map(|row| Communication {
+                    id: row.get(0),
+                    participant: row.get(1),
+                    kind: CommunicationKind::parse(row.get(2)),
+                    subject: row.get(3),
+                    note: row.get(4),
+                    sent_by: row.get(5),
+                    sent_at: row.get(6),
+                })
+                .collect())
+        })
+    }
+
+    fn store_announcement(
+        &self,
+        title: &str,
+        body: &str,
+        critical: bool,
+        created_by: &str,
+    ) -> Result<Announcement, AppError> {
+        let created_at = Utc::now().to_rfc3339();
+        let id: i64 = self.run(|client| {
+            Ok(client
+                .query_one(
+                    "INSERT INTO announcements (title, body, critical, created_by, created_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+                    &[&title, &body, &critical, &created_by, &created_at],
+                )?
+                .get(0))
+        })?;
+        Ok(Announcement {
+            id,
+            title: title.to_string(),
+            body: body.to_string(),
+            critical,
+            created_by: created_by.to_string(),
+            created_at,
+        })
+    }
+
+    fn read_announcements(&self) -> Result<Vec<Announcement>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT id, title, body, critical, created_by, created_at FROM announcements ORDER BY created_at DESC, id DESC",
+                    &[],
+                )?
+                .iter()
+                .map(|row| Announcement {
+                    id: row.get(0),
+                    title: row.get(1),
+                    body: row.get(2),
+                    critical: row.get(3),
+                    created_by: row.get(4),
+                    created_at: row.get(5),
+                })
+                .collect())
+        })
+    }
+
+    fn read_announcement_reads(&self) -> Result<HashMap<i64, HashMap<String, String>>, AppError> {
+        self.read(|client| {
+            let mut reads: HashMap<i64, HashMap<String, String>> = HashMap::new();
+            for row in client.query(
+                "SELECT announcement_id, name, read_at FROM announcement_reads",
+                &[],
+            )? {
+                reads
+                    .entry(row.get(0))
+                    .or_default()
+                    .insert(row.get(1), row.get(2));
+            }
+            Ok(reads)
+        })
+    }
+
+    fn mark_announcements_read(&self, name: &str, ids: &[i64]) -> Result<(), AppError> {
+        let read_at = Utc::now().to_rfc3339();
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            for id in ids {
+                tx.execute(
+                    "INSERT INTO announcement_reads (announcement_id, name, read_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                    &[id, &name, &read_at],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn read_week_checklist(&self, week: i32) -> Result<Vec<ChecklistItem>, AppError> {
+        let completed = self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT task, completed_at, completed_by FROM week_tasks WHERE week = $1",
+                    &[&week],
+                )?
+                .iter()
+                .map(|row| (row.get(0), (row.get(1), row.get(2))))
+                .collect())
+        })?;
+        Ok(checklist_items(&completed))
+    }
+
+    fn complete_week_task(
+        &self,
+        week: i32,
+        task: WeekTask,
+        completed_by: &str,
+    ) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO week_tasks (week, task, completed_at, completed_by) VALUES ($1, $2, $3, $4) ON CONFLICT (week, task) DO UPDATE SET completed_at = excluded.completed_at, completed_by = excluded.completed_by",
+                &[&week, &task.as_str(), &Utc::now().to_rfc3339(), &completed_by],
+            )?;
+            Ok(())
+        })?;
+        info!(
+            "Week {} task {} completed by {}",
+            week,
+            task.as_str(),
+            completed_by
+        );
+        Ok(())
+    }
+
+    fn reopen_week_task(&self, week: i32, task: WeekTask) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "DELETE FROM week_tasks WHERE week = $1 AND task = $2",
+                &[&week, &task.as_str()],
+            )?;
+            Ok(())
+        })?;
+        info!("Week {} task {} reopened", week, task.as_str());
+        Ok(())
+    }
+
+    fn read_admin_totp(&self) -> Result<Option<(String, bool)>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query_opt("SELECT secret, confirmed FROM admin_totp WHERE id = 1", &[])?
+                .map(|row| (row.get(0), row.get(1))))
+        })
+    }
+
+    fn store_admin_totp(&self, secret: &str) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO admin_totp (id, secret, confirmed, created_at) VALUES (1, $1, FALSE, $2) ON CONFLICT (id) DO UPDATE SET secret = excluded.secret, confirmed = FALSE, created_at = excluded.created_at",
+                &[&secret, &Utc::now().to_rfc3339()],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn confirm_admin_totp(&self) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute("UPDATE admin_totp SET confirmed = TRUE WHERE id = 1", &[])?;
+            Ok(())
+        })
+    }
+
+    fn delete_admin_totp(&self) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute("DELETE FROM admin_totp", &[])?;
+            Ok(())
+        })
+    }
+
+    fn read_branding(&self) -> Result<Option<Branding>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query_opt(
+                    "SELECT program_name, logo, signature, certificate_text, updated_by, updated_at FROM cohort_branding WHERE id = 1",
+                    &[],
+                )?
+                .map(|row| Branding {
+                    program_name: row.get(0),
+                    logo: row.get(1),
+                    signature: row.get(2),
+                    certificate_text: row.get(3),
+                    updated_by: row.get(4),
+                    updated_at: row.get(5),
+                }))
+        })
+    }
+
+    fn store_branding(&self, branding: &Branding, updated_by: &str) -> Result<Branding, AppError> {
+        let updated_at = Utc::now().to_rfc3339();
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO cohort_branding (id, program_name, logo, signature, certificate_text, updated_by, updated_at) VALUES (1, $1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO UPDATE SET program_name = excluded.program_name, logo = excluded.logo, signature = excluded.signature, certificate_text = excluded.certificate_text, updated_by = excluded.updated_by, updated_at = excluded.updated_at",
+                &[
+                    &branding.program_name,
+                    &branding.logo,
+                    &branding.signature,
+                    &branding.certificate_text,
+                    &updated_by,
+                    &updated_at,
+                ],
+            )?;
+            Ok(())
+        })?;
+        Ok(Branding {
+            updated_by: Some(updated_by.to_string()),
+            updated_at: Some(updated_at),
+            ..branding.clone()
+        })
+    }
+
+    fn read_grouping_constraints(&self) -> Result<Vec<GroupingConstraint>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT id, kind, first, second, created_by, created_at FROM grouping_constraints ORDER BY id",
+                    &[],
+                )?
+                .iter()
+                .map(|row| GroupingConstraint {
+                    id: row.get(0),
+                    kind: ConstraintKind::parse(row.get(1)),
+                    first: row.get(2),
+                    second: row.get(3),
+                    created_by: row.get(4),
+                    created_at: row.get(5),
+                })
+                .collect())
+        })
+    }
+
+    fn store_grouping_constraint(
+        &self,
+        kind: ConstraintKind,
+        first: &str,
+        second: &str,
+        created_by: &str,
+    ) -> Result<GroupingConstraint, AppError> {
+        let created_at = Utc::now().to_rfc3339();
+        let id: i64 = self.run(|client| {
+            Ok(client
+                .query_one(
+                    "INSERT INTO grouping_constraints (kind, first, second, created_by, created_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (first, second) DO UPDATE SET kind = excluded.kind, created_by = excluded.created_by, created_at = excluded.created_at RETURNING id",
+                    &[&kind.as_str(), &first, &second, &created_by, &created_at],
+                )?
+                .get(0))
+        })?;
+        Ok(GroupingConstraint {
+            id,
+            kind,
+            first: first.to_string(),
+            second: second.to_string(),
+            created_by: created_by.to_string(),
+            created_at,
+        })
+    }
+
+    fn delete_grouping_constraint(&self, id: i64) -> Result<bool, AppError> {
+        let deleted = self.run(|client| {
+            Ok(client.execute("DELETE FROM grouping_constraints WHERE id = $1", &[&id])?)
+        })?;
+        Ok(deleted > 0)
+    }
+
+    fn read_spoken_languages(&self) -> Result<HashMap<String, BTreeSet<String>>, AppError> {
+        self.read(|client| {
+            let mut spoken: HashMap<String, BTreeSet<String>> = HashMap::new();
+            for row in client.query("SELECT name, language FROM spoken_languages", &[])? {
+                spoken.entry(row.get(0)).or_default().insert(row.get(1));
+            }
+            Ok(spoken)
+        })
+    }
+
+    fn replace_spoken_languages(
+        &self,
+        name: &str,
+        languages: &BTreeSet<String>,
+    ) -> Result<(), AppError> {
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute("DELETE FROM spoken_languages WHERE name = $1", &[&name])?;
+            for language in languages {
+                tx.execute(
+                    "INSERT INTO spoken_languages (name, language) VALUES ($1, $2)",
+                    &[&name, language],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn record_exercise_attempts(&self, attempts: &[ExerciseAttempt]) -> Result<usize, AppError> {
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            let stmt = tx.prepare(
+                "INSERT INTO exercise_attempts (week, github, submitted_at, passing, recorded_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+            )?;
+            let mut recorded = 0;
+            for attempt in attempts {
+                recorded += tx.execute(
+                    &stmt,
+                    &[
+                        &attempt.week,
+                        &attempt.github,
+                        &attempt.submitted_at,
+                        &attempt.passing,
+                        &attempt.recorded_at,
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(recorded as usize)
+        })
+    }
+
+    fn read_exercise_attempts(&self) -> Result<Vec<ExerciseAttempt>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT week, github, submitted_at, passing, recorded_at FROM exercise_attempts",
+                    &[],
+                )?
+                .iter()
+                .map(|row| ExerciseAttempt {
+                    week: row.get(0),
+                    github: row.get(1),
+                    submitted_at: row.get(2),
+                    passing: row.get(3),
+                    recorded_at: row.get(4),
+                })
+                .collect())
+        })
+    }
+
+    fn record_sync_run(&self, run: &SyncRun) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO sync_runs (week, started_at, finished_at, succeeded, error) VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &run.week,
+                    &run.started_at,
+                    &run.finished_at,
+                    &run.succeeded,
+                    &run.error,
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn read_sync_runs(&self) -> Result<Vec<SyncRun>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT week, started_at, finished_at, succeeded, error FROM sync_runs ORDER BY finished_at, id",
+                    &[],
+                )?
+                .iter()
+                .map(|row| SyncRun {
+                    week: row.get(0),
+                    started_at: row.get(1),
+                    finished_at: row.get(2),
+                    succeeded: row.get(3),
+                    error: row.get(4),
+                })
+                .collect())
+        })
+    }
+
+    fn read_rubric_notes(&self) -> Result<Vec<RubricNote>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT criterion, score, note, updated_by, updated_at FROM rubric_notes ORDER BY criterion, score",
+                    &[],
+                )?
+                .iter()
+                .map(|row| RubricNote {
+                    criterion: row.get(0),
+                    score: from_db(row.get(1)).unwrap_or_default(),
+                    note: row.get(2),
+                    updated_by: row.get(3),
+                    updated_at: row.get(4),
+                })
+                .collect())
+        })
+    }
+
+    fn replace_rubric_notes(
+        &self,
+        criterion: &str,
+        notes: &BTreeMap<u64, String>,
+        updated_by: &str,
+    ) -> Result<(), AppError> {
+        let updated_at = Utc::now().to_rfc3339();
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute("DELETE FROM rubric_notes WHERE criterion = $1", &[&criterion])?;
+            for (score, note) in notes {
+                tx.execute(
+                    "INSERT INTO rubric_notes (criterion, score, note, updated_by, updated_at) VALUES ($1, $2, $3, $4, $5)",
+                    &[&criterion, &(*score as i64), note, &updated_by, &updated_at],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn read_group_threads(&self, week: i32) -> Result<Vec<GroupThread>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT week, group_id, thread_id, members, created_at, updated_at FROM group_threads WHERE week = $1 ORDER BY group_id",
+                    &[&week],
+                )?
+                .iter()
+                .map(|row| GroupThread {
+                    week: row.get(0),
+                    group_id: row.get(1),
+                    thread_id: row.get(2),
+                    members: split_members(row.get(3)),
+                    created_at: row.get(4),
+                    updated_at: row.get(5),
+                })
+                .collect())
+        })
+    }
+
+    fn store_group_thread(&self, thread: &GroupThread) -> Result<(), AppError> {
+        let members = thread.members.join(",");
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO group_threads (week, group_id, thread_id, members, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6) \
+                 ON CONFLICT (week, group_id) DO UPDATE SET thread_id = excluded.thread_id, members = excluded.members, updated_at = excluded.updated_at",
+                &[
+                    &thread.week,
+                    &thread.group_id,
+                    &thread.thread_id,
+                    &members,
+                    &thread.created_at,
+                    &thread.updated_at,
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn read_review_summaries(&self) -> Result<Vec<ReviewSummary>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT week, group_id, ta, common_mistakes, standout_solutions, notes, submitted_at FROM review_summaries ORDER BY week, group_id",
+                    &[],
+                )?
+                .iter()
+                .map(|row| ReviewSummary {
+                    week: row.get(0),
+                    group_id: row.get(1),
+                    ta: row.get(2),
+                    common_mistakes: split_points(row.get(3)),
+                    standout_solutions: split_points(row.get(4)),
+                    notes: row.get(5),
+                    submitted_at: row.get(6),
+                })
+                .collect())
+        })
+    }
+
+    fn store_review_summary(&self, summary: &ReviewSummary) -> Result<(), AppError> {
+        let common_mistakes = summary.common_mistakes.join("\n");
+        let standout_solutions = summary.standout_solutions.join("\n");
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO review_summaries (week, group_id, ta, common_mistakes, standout_solutions, notes, submitted_at) VALUES ($1, $2, $3, $4, $5, $6, $7) \
+                 ON CONFLICT (week, group_id) DO UPDATE SET ta = excluded.ta, common_mistakes = excluded.common_mistakes, standout_solutions = excluded.standout_solutions, notes = excluded.notes, submitted_at = excluded.submitted_at",
+                &[
+                    &summary.week,
+                    &summary.group_id,
+                    &summary.ta,
+                    &common_mistakes,
+                    &standout_solutions,
+                    &summary.notes,
+                    &summary.submitted_at,
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn read_week_locks(&self) -> Result<Vec<WeekLock>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT week, locked_by, locked_at FROM week_locks ORDER BY week",
+                    &[],
+                )?
+                .iter()
+                .map(|row| WeekLock {
+                    week: row.get(0),
+                    locked_by: row.get(1),
+                    locked_at: row.get(2),
+                })
+                .collect())
+        })
+    }
+
+    fn lock_week(&self, lock: &WeekLock) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO week_locks (week, locked_by, locked_at) VALUES ($1, $2, $3) ON CONFLICT (week) DO NOTHING",
+                &[&lock.week, &lock.locked_by, &lock.locked_at],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn unlock_week(&self, week: i32) -> Result<bool, AppError> {
+        self.run(|client| {
+            let removed = client.execute("DELETE FROM week_locks WHERE week = $1", &[&week])?;
+            Ok(removed > 0)
+        })
+    }
+
+    fn read_idempotent_response(
+        &self,
+        key: &str,
+        actor: &str,
+    ) -> Result<Option<IdempotentResponse>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query_opt(
+                    "SELECT key, actor, fingerprint, status, body, created_at FROM idempotency_keys WHERE key = $1 AND actor = $2",
+                    &[&key, &actor],
+                )?
+                .map(|row| IdempotentResponse {
+                    key: row.get(0),
+                    actor: row.get(1),
+                    fingerprint: row.get(2),
+                    status: row.get::<_, i32>(3) as u16,
+                    body: row.get(4),
+                    created_at: row.get(5),
+                }))
+        })
+    }
+
+    fn store_idempotent_response(
+        &self,
+        response: &IdempotentResponse,
+        prune_before: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let status = response.status as i32;
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute(
+                "DELETE FROM idempotency_keys WHERE created_at < $1",
+                &[&prune_before.to_rfc3339()],
+            )?;
+            tx.execute(
+                "INSERT INTO idempotency_keys (key, actor, fingerprint, status, body, created_at) VALUES ($1, $2, $3, $4, $5, $6) \
+                 ON CONFLICT (key, actor) DO UPDATE SET fingerprint = excluded.fingerprint, status = excluded.status, body = excluded.body, created_at = excluded.created_at",
+                &[
+                    &response.key,
+                    &response.actor,
+                    &response.fingerprint,
+                    &status,
+                    &response.body,
+                    &response.created_at,
+                ],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn record_webhook_delivery(
+        &self,
+        id: &str,
+        received_at: DateTime<Utc>,
+        prune_before: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute(
+                "DELETE FROM webhook_deliveries WHERE received_at < $1",
+                &[&prune_before.to_rfc3339()],
+            )?;
+            let inserted = tx.execute(
+                "INSERT INTO webhook_deliveries (id, received_at) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
+                &[&id, &received_at.to_rfc3339()],
+            )?;
+            tx.commit()?;
+            Ok(inserted > 0)
+        })
+    }
+
+    fn forget_webhook_delivery(&self, id: &str) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute("DELETE FROM webhook_deliveries WHERE id = $1", &[&id])?;
+            Ok(())
+        })
+    }
+
+    fn read_inactive_tas(&self) -> Result<HashSet<String>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query("SELECT ta FROM inactive_tas", &[])?
+                .iter()
+                .map(|row| row.get(0))
+                .collect())
+        })
+    }
+
+    fn deactivate_ta(&self, ta: &str, deactivated_by: &str) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO inactive_tas (ta, deactivated_by, deactivated_at) VALUES ($1, $2, $3) ON CONFLICT (ta) DO NOTHING",
+                &[&ta, &deactivated_by, &Utc::now().to_rfc3339()],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn read_onboarded_tas(&self) -> Result<Vec<TaProfile>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query(
+                    "SELECT name, email, github, discord, notify_by_email, notify_by_discord, onboarded_at
+                     FROM onboarded_tas ORDER BY onboarded_at, name",
+                    &[],
+                )?
+                .iter()
+                .map(|row| TaProfile {
+                    name: row.get(0),
+                    email: row.get(1),
+                    setup: TaSetup {
+                        github: row.get(2),
+                        discord: row.get(3),
+                        notify_by_email: row.get(4),
+                        notify_by_discord: row.get(5),
+                    },
+                    onboarded_at: row.get(6),
+                })
+                .collect())
+        })
+    }
+
+    fn create_ta_invite(&self, token_hash: &str, invite: &TaInvite) -> Result<(), AppError> {
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute(
+                "DELETE FROM ta_invites WHERE lower(email) = lower($1) AND accepted_at IS NULL",
+                &[&invite.email],
+            )?;
+            tx.execute(
+                "INSERT INTO ta_invites (token_hash, email, name, invited_by, created_at, expires_at)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &token_hash,
+                    &invite.email,
+                    &invite.name,
+                    &invite.invited_by,
+                    &invite.created_at,
+                    &invite.expires_at,
+                ],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn accept_ta_invite(
+        &self,
+        token_hash: &str,
+        setup: &TaSetup,
+    ) -> Result<Option<TaProfile>, AppError> {
+        self.run(|client| {
+            let mut tx = client.transaction()?;
+            let Some(row) = tx.query_opt(
+                "SELECT email, name, expires_at FROM ta_invites
+                 WHERE token_hash = $1 AND accepted_at IS NULL FOR UPDATE",
+                &[&token_hash],
+            )?
+            else {
+                return Ok(None);
+            };
+            let expires_at: String = row.get(2);
+            if !invite_pending(&expires_at) {
+                return Ok(None);
+            }
+            let now = Utc::now().to_rfc3339();
+            let profile = TaProfile {
+                name: row.get(1),
+                email: row.get(0),
+                setup: setup.clone(),
+                onboarded_at: now.clone(),
+            };
+            let inserted = tx.execute(
+                "INSERT INTO onboarded_tas
+                     (name, email, github, discord, notify_by_email, notify_by_discord, onboarded_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING",
+                &[
+                    &profile.name,
+                    &profile.email,
+                    &setup.github,
+                    &setup.discord,
+                    &setup.notify_by_email,
+                    &setup.notify_by_discord,
+                    &now,
+                ],
+            )?;
+            if inserted == 0 {
+                return Ok(None);
+            }
+            tx.execute(
+                "UPDATE ta_invites SET accepted_at = $1 WHERE token_hash = $2",
+                &[&now, &token_hash],
+            )?;
+            tx.commit()?;
+            Ok(Some(profile))
+        })
+    }
+
+    fn read_attention_dismissals(&self) -> Result<HashSet<String>, AppError> {
+        self.read(|client| {
+            Ok(client
+                .query("SELECT id FROM attention_dismissals", &[])?
+                .iter()
+                .map(|row| row.get(0))
+                .collect())
+        })
+    }
+
+    fn dismiss_attention_item(&self, id: &str, dismissed_by: &str) -> Result<(), AppError> {
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO attention_dismissals (id, dismissed_by, dismissed_at) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET dismissed_by = excluded.dismissed_by, dismissed_at = excluded.dismissed_at",
+                &[&id, &dismissed_by, &Utc::now().to_rfc3339()],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn read_revoked_tokens(&self) -> Result<HashSet<String>, AppError> {
+        let now = Utc::now().to_rfc3339();
+        self.run(|client| {
+            client.execute(
+                "DELETE FROM revoked_tokens WHERE expires_at IS NOT NULL AND expires_at <= $1",
+                &[&now],
+            )?;
+            Ok(client
+                .query("SELECT token_hash FROM revoked_tokens", &[])?
+                .iter()
+                .map(|row| row.get(0))
+                .collect())
+        })
+    }
+
+    fn revoke_token(
+        &self,
+        token_hash: &str,
+        revoked_by: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError> {
+        let expires_at = expires_at.map(|t| t.to_rfc3339());
+        self.run(|client| {
+            client.execute(
+                "INSERT INTO revoked_tokens (token_hash, revoked_by, revoked_at, expires_at) VALUES ($1, $2, $3, $4) ON CONFLICT (token_hash) DO UPDATE SET revoked_by = excluded.revoked_by, revoked_at = excluded.revoked_at, expires_at = excluded.expires_at",
+                &[&token_hash, &revoked_by, &Utc::now().to_rfc3339(), &expires_at],
+            )?;
+            Ok(())
+        })
+    }
+
+    // A plain VACUUM, which hands space back for reuse without locking out
+    // readers the way VACUUM FULL would
+    fn vacuum_and_analyze(&self) -> Result<MaintenanceReport, AppError> {
+        self.run(|client| {
+            let size = |client: &mut Client| -> Result<u64, AppError> {
+                let bytes: i64 = client
+                    .query_one("SELECT pg_database_size(current_database())", &[])?
+                    .get(0);
+                Ok(bytes.max(0) as u64)
+            };
+            let started_at = Utc::now().to_rfc3339();
+            let size_before = size(client)?;
+            client.batch_execute("VACUUM ANALYZE")?;
+            let size_after = size(client)?;
+            Ok(MaintenanceReport {
+                started_at,
+                finished_at: Utc::now().to_rfc3339(),
+                size_before,
+                size_after,
+                reclaimed_bytes: size_before.saturating_sub(size_after),
+            })
+        })
+    }
+}
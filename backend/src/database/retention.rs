@@ -0,0 +1,371 @@
+//! Retention policies for the databases of past cohorts.
+//!
+//! Once a cohort has an end date recorded, participant PII is anonymized and
+//! raw event logs are purged after the configured number of months. Only
+//! `classroom_<name>.db` cohort databases are considered, never the live
+//! `classroom.db`.
+
+use crate::database::bootstrap::{cohort_db_path, table_exists};
+use crate::database::paths::data_paths;
+use crate::database::pool::{open_connection, open_read_only};
+use crate::database::schema::run_migrations;
+use crate::utils::types::AppError;
+use chrono::{Months, NaiveDate, Utc};
+use log::{error, info};
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::env;
+use std::path::Path;
+
+// Participant columns holding personal data, across both participant schemas
+const PII_COLUMNS: &[&str] = &[
+    "Email",
+    "GitHub",
+    "Token",
+    "Location",
+    "Background",
+    "Describe Yourself",
+    "describe_yourself",
+    "Why",
+];
+
+// Raw per-event tables removed wholesale once they are due
+const EVENT_TABLES: &[&str] = &["voice_snapshots", "communications"];
+
+// Months after a cohort's end at which each policy applies; None disables it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicy {
+    pub anonymize_after_months: Option<u32>,
+    pub purge_events_after_months: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn from_env() -> Result<Self, String> {
+        let months = |var: &str| -> Result<Option<u32>, String> {
+            match env::var(var) {
+                Ok(value) if !value.trim().is_empty() => value
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| format!("{} must be a number of months", var)),
+                _ => Ok(None),
+            }
+        };
+        Ok(RetentionPolicy {
+            anonymize_after_months: months("RETENTION_ANONYMIZE_MONTHS")?,
+            purge_events_after_months: months("RETENTION_PURGE_EVENTS_MONTHS")?,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.anonymize_after_months.is_some() || self.purge_events_after_months.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionKind {
+    Anonymize,
+    PurgeEvents,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionAction {
+    pub cohort: String,
+    pub database: String,
+    pub kind: RetentionKind,
+    pub due: NaiveDate,
+    pub rows: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionSkip {
+    pub cohort: String,
+    pub reason: String,
+}
+
+// What a retention run will remove, returned for review before applying
+#[derive(Debug, Serialize)]
+pub struct RetentionReport {
+    pub generated_at: String,
+    pub actions: Vec<RetentionAction>,
+    pub skipped: Vec<RetentionSkip>,
+}
+
+#[derive(Debug, Default)]
+struct Lifecycle {
+    ended_at: Option<NaiveDate>,
+    anonymized: bool,
+    events_purged: bool,
+}
+
+// Cohort names of all `classroom_<name>.db` files in the working directory
+pub fn cohort_names() -> Vec<String> {
+    let dir = data_paths().cohort_dir().to_string_lossy().into_owned();
+    let pattern = Path::new(&glob::Pattern::escape(&dir)).join("classroom_*.db");
+    let mut names: Vec<String> = glob::glob(&pattern.to_string_lossy())
+        .map(|paths| {
+            paths
+                .filter_map(Result::ok)
+                .filter_map(|path| {
+                    path.file_stem()?
+                        .to_str()?
+                        .strip_prefix("classroom_")
+                        .map(str::to_string)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+fn read_lifecycle(conn: &Connection) -> Result<Lifecycle, AppError> {
+    if !table_exists(conn, "cohort_lifecycle")? {
+        return Ok(Lifecycle::default());
+    }
+    let row = conn.query_row(
+        "SELECT ended_at, anonymized_at IS NOT NULL, events_purged_at IS NOT NULL FROM cohort_lifecycle WHERE id = 1",
+        [],
+        |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, bool>(2)?,
+            ))
+        },
+    );
+    match row {
+        Ok((ended_at, anonymized, events_purged)) => Ok(Lifecycle {
+            ended_at: ended_at.and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok()),
+            anonymized,
+            events_purged,
+        }),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(Lifecycle::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn set_cohort_end(cohort: &str, ended_at: NaiveDate) -> Result<(), AppError> {
+    let path = cohort_db_path(cohort);
+    run_migrations(&path)?;
+    let conn = open_connection(&path)?;
+    conn.execute(
+        "INSERT INTO cohort_lifecycle (id, ended_at) VALUES (1, ?1)
+         ON CONFLICT(id) DO UPDATE SET ended_at = excluded.ended_at",
+        params![ended_at.format("%Y-%m-%d").to_string()],
+    )?;
+    Ok(())
+}
+
+fn count_rows(conn: &Connection, tables: &[&str]) -> Result<usize, AppError> {
+    let mut total = 0;
+    for table in tables {
+        if table_exists(conn, table)? {
+            let count: i64 =
+                conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })?;
+            total += count as usize;
+        }
+    }
+    Ok(total)
+}
+
+pub fn plan_retention(
+    policy: &RetentionPolicy,
+    today: NaiveDate,
+) -> Result<RetentionReport, AppError> {
+    let mut actions = Vec::new();
+    let mut skipped = Vec::new();
+
+    for cohort in cohort_names() {
+        let path = cohort_db_path(&cohort);
+        let conn = open_read_only(&path)?;
+        let lifecycle = read_lifecycle(&conn)?;
+        let Some(ended_at) = lifecycle.ended_at else {
+            skipped.push(RetentionSkip {
+                cohort,
+                reason: "no end date recorded".to_string(),
+            });
+            continue;
+        };
+
+        let steps = [
+            (
+                RetentionKind::Anonymize,
+                policy.anonymize_after_months,
+                lifecycle.anonymized,
+                &["participants", "students"][..],
+            ),
+            (
+                RetentionKind::PurgeEvents,
+                policy.purge_events_after_months,
+                lifecycle.events_purged,
+                EVENT_TABLES,
+            ),
+        ];
+        for (kind, months, done, tables) in steps {
+            let Some(due) = months.and_then(|m| ended_at.checked_add_months(Months::new(m))) else {
+                continue;
+            };
+            if done || due > today {
+                continue;
+            }
+            actions.push(RetentionAction {
+                cohort: cohort.clone(),
+                database: path.display().to_string(),
+                kind,
+                due,
+                rows: count_rows(&conn, tables)?,
+            });
+        }
+    }
+
+    Ok(RetentionReport {
+        generated_at: Utc::now().to_rfc3339(),
+        actions,
+        skipped,
+    })
+}
+
+// Replaces names with stable pseudonyms and clears contact details, keeping
+// grades so cohort statistics stay intact
+fn anonymize(conn: &mut Connection) -> Result<(), AppError> {
+    let tx = conn.transaction()?;
+    let mut names: Vec<String> = Vec::new();
+    for (table, column) in [("participants", "Name"), ("students", "name")] {
+        if table_exists(&tx, table)? {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT DISTINCT {column} FROM {table} WHERE {column} IS NOT NULL ORDER BY rowid"
+            ))?;
+            for name in stmt.query_map([], |row| row.get::<_, String>(0))? {
+                let name = name?;
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+
+    for (index, name) in names.iter().enumerate() {
+        let pseudonym = format!("Participant {}", index + 1);
+        for (table, column) in [
+            ("participants", "Name"),
+            ("students", "name"),
+            ("communications", "participant"),
+        ] {
+            if table_exists(&tx, table)? {
+                tx.execute(
+                    &format!("UPDATE {table} SET {column} = ?1 WHERE {column} = ?2"),
+                    params![pseudonym, name],
+                )?;
+            }
+        }
+    }
+
+    if table_exists(&tx, "participants")? {
+        let columns: Vec<String> = tx
+            .prepare("SELECT name FROM pragma_table_info('participants')")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for column in PII_COLUMNS
+            .iter()
+            .filter(|c| columns.iter().any(|n| n == *c))
+        {
+            // Email may be the primary key, keep it unique
+            let value = if column.eq_ignore_ascii_case("email") {
+                "'anonymized-' || rowid || '@invalid'"
+            } else {
+                "NULL"
+            };
+            tx.execute(
+                &format!("UPDATE participants SET \"{column}\" = {value}"),
+                [],
+            )?;
+        }
+    }
+    if table_exists(&tx, "students")? {
+        tx.execute("UPDATE students SET mail = ''", [])?;
+    }
+    if table_exists(&tx, "discord_handles")? {
+        tx.execute("DELETE FROM discord_handles", [])?;
+    }
+    if table_exists(&tx, "exercise_attempts")? {
+        // GitHub handles become pseudonyms, keeping attempts per student
+        let handles: Vec<String> = tx
+            .prepare("SELECT DISTINCT github FROM exercise_attempts ORDER BY github")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for (index, handle) in handles.iter().enumerate() {
+            tx.execute(
+                "UPDATE exercise_attempts SET github = ?1 WHERE github = ?2",
+                params![format!("anonymized-{}", index + 1), handle],
+            )?;
+        }
+    }
+    tx.execute(
+        "UPDATE cohort_lifecycle SET anonymized_at = ?1 WHERE id = 1",
+        params![Utc::now().to_rfc3339()],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn purge_events(conn: &mut Connection) -> Result<(), AppError> {
+    let tx = conn.transaction()?;
+    for table in EVENT_TABLES {
+        if table_exists(&tx, table)? {
+            tx.execute(&format!("DELETE FROM {}", table), [])?;
+        }
+    }
+    tx.execute(
+        "UPDATE cohort_lifecycle SET events_purged_at = ?1 WHERE id = 1",
+        params![Utc::now().to_rfc3339()],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn apply_retention(report: &RetentionReport) -> Result<(), AppError> {
+    for action in &report.actions {
+        let mut conn = open_connection(Path::new(&action.database))?;
+        match action.kind {
+            RetentionKind::Anonymize => anonymize(&mut conn)?,
+            RetentionKind::PurgeEvents => purge_events(&mut conn)?,
+        }
+        info!(
+            target: "audit",
+            "Retention: {:?} applied to cohort {} ({} row(s), due {})",
+            action.kind,
+            action.cohort,
+            action.rows,
+            action.due
+        );
+    }
+    Ok(())
+}
+
+fn run_retention(policy: &RetentionPolicy) -> Result<usize, AppError> {
+    let report = plan_retention(policy, Utc::now().date_naive())?;
+    apply_retention(&report)?;
+    Ok(report.actions.len())
+}
+
+// Applies due retention policies once a day
+pub fn start_retention_task(policy: RetentionPolicy) {
+    if !policy.is_enabled() {
+        return;
+    }
+    info!("Data retention enabled: {:?}", policy);
+    std::thread::spawn(move || {
+        loop {
+            match run_retention(&policy) {
+                Ok(0) => {}
+                Ok(applied) => info!("Applied {} retention action(s)", applied),
+                Err(e) => error!("Data retention run failed: {}", e),
+            }
+            std::thread::sleep(std::time::Duration::from_secs(60 * 60 * 24));
+        }
+    });
+}
@@ -0,0 +1,689 @@
+use crate::database::bootstrap::{column_exists, table_exists};
+use crate::database::pool::open_connection;
+use crate::utils::ids::new_public_id;
+use crate::utils::types::AppError;
+use log::info;
+use rusqlite::{Connection, ToSql, params};
+use std::path::Path;
+
+// Additive schema changes applied at server startup. Each entry runs once,
+// in order, and the applied count is tracked in `PRAGMA user_version`.
+// Never edit or reorder an existing entry - append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: Discord voice channel presence snapshots and handle mapping
+    r#"
+    CREATE TABLE IF NOT EXISTS voice_snapshots (
+        week          INTEGER NOT NULL,
+        taken_at      TEXT NOT NULL,
+        discord_id    TEXT NOT NULL,
+        discord_name  TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS discord_handles (
+        discord_id    TEXT PRIMARY KEY,
+        name          TEXT NOT NULL
+    );
+    "#,
+    // 2: Outbound communications log per participant
+    r#"
+    CREATE TABLE IF NOT EXISTS communications (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        participant   TEXT NOT NULL,
+        kind          TEXT NOT NULL,
+        subject       TEXT NOT NULL,
+        note          TEXT,
+        sent_by       TEXT NOT NULL,
+        sent_at       TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_communications_participant
+        ON communications (participant);
+    "#,
+    // 3: Weekly organizer checklist completions
+    r#"
+    CREATE TABLE IF NOT EXISTS week_tasks (
+        week          INTEGER NOT NULL,
+        task          TEXT NOT NULL,
+        completed_at  TEXT NOT NULL,
+        completed_by  TEXT NOT NULL,
+        PRIMARY KEY (week, task)
+    );
+    "#,
+    // 4: TOTP second factor for the admin token
+    r#"
+    CREATE TABLE IF NOT EXISTS admin_totp (
+        id            INTEGER PRIMARY KEY CHECK (id = 1),
+        secret        TEXT NOT NULL,
+        confirmed     INTEGER NOT NULL DEFAULT 0,
+        created_at    TEXT NOT NULL
+    );
+    "#,
+    // 5: Cohort template data copied when bootstrapping a new cohort
+    r#"
+    CREATE TABLE IF NOT EXISTS cohort_weeks (
+        week          INTEGER PRIMARY KEY
+    );
+    CREATE TABLE IF NOT EXISTS cohort_groups (
+        group_id      TEXT PRIMARY KEY,
+        ta            TEXT NOT NULL
+    );
+    "#,
+    // 6: Dismissed items of the admin attention queue
+    r#"
+    CREATE TABLE IF NOT EXISTS attention_dismissals (
+        id            TEXT PRIMARY KEY,
+        dismissed_by  TEXT NOT NULL,
+        dismissed_at  TEXT NOT NULL
+    );
+    "#,
+    // 7: Revoked auth tokens, stored as SHA-256 hashes
+    r#"
+    CREATE TABLE IF NOT EXISTS revoked_tokens (
+        token_hash    TEXT PRIMARY KEY,
+        revoked_by    TEXT NOT NULL,
+        revoked_at    TEXT NOT NULL,
+        expires_at    TEXT
+    );
+    "#,
+    // 8: Cohort end date and data retention progress
+    r#"
+    CREATE TABLE IF NOT EXISTS cohort_lifecycle (
+        id                INTEGER PRIMARY KEY CHECK (id = 1),
+        ended_at          TEXT,
+        anonymized_at     TEXT,
+        events_purged_at  TEXT
+    );
+    "#,
+    // 9: Cohort branding used by exports and certificates
+    r#"
+    CREATE TABLE IF NOT EXISTS cohort_branding (
+        id                INTEGER PRIMARY KEY CHECK (id = 1),
+        program_name      TEXT NOT NULL,
+        logo              TEXT,
+        signature         TEXT,
+        certificate_text  TEXT,
+        updated_by        TEXT NOT NULL,
+        updated_at        TEXT NOT NULL
+    );
+    "#,
+    // 10: Hard constraints honored when students are regrouped
+    r#"
+    CREATE TABLE IF NOT EXISTS grouping_constraints (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        kind          TEXT NOT NULL CHECK (kind IN ('together', 'apart')),
+        first         TEXT NOT NULL,
+        second        TEXT NOT NULL,
+        created_by    TEXT NOT NULL,
+        created_at    TEXT NOT NULL,
+        UNIQUE (first, second)
+    );
+    "#,
+    // 11: Exercise submissions seen by the weekly sync, for difficulty analytics
+    r#"
+    CREATE TABLE IF NOT EXISTS exercise_attempts (
+        week          INTEGER NOT NULL,
+        github        TEXT NOT NULL,
+        submitted_at  TEXT NOT NULL,
+        passing       INTEGER NOT NULL,
+        recorded_at   TEXT NOT NULL,
+        PRIMARY KEY (week, github, submitted_at)
+    );
+    "#,
+    // 12: Grading guidance per rubric criterion and score
+    r#"
+    CREATE TABLE IF NOT EXISTS rubric_notes (
+        criterion     TEXT NOT NULL,
+        score         INTEGER NOT NULL,
+        note          TEXT NOT NULL,
+        updated_by    TEXT NOT NULL,
+        updated_at    TEXT NOT NULL,
+        PRIMARY KEY (criterion, score)
+    );
+    "#,
+    // 13: Discord threads of published groups, members as comma separated ids
+    r#"
+    CREATE TABLE IF NOT EXISTS group_threads (
+        week          INTEGER NOT NULL,
+        group_id      TEXT NOT NULL,
+        thread_id     TEXT NOT NULL,
+        members       TEXT NOT NULL,
+        created_at    TEXT NOT NULL,
+        updated_at    TEXT NOT NULL,
+        PRIMARY KEY (week, group_id)
+    );
+    "#,
+    // 14: TAs offboarded mid-cohort, left out of the group rotation
+    r#"
+    CREATE TABLE IF NOT EXISTS inactive_tas (
+        ta              TEXT PRIMARY KEY,
+        deactivated_by  TEXT NOT NULL,
+        deactivated_at  TEXT NOT NULL
+    );
+    "#,
+    // 15: Field-level edit history of weekly rows
+    r#"
+    CREATE TABLE IF NOT EXISTS row_history (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        name          TEXT NOT NULL,
+        week          INTEGER NOT NULL,
+        field         TEXT NOT NULL,
+        old_value     TEXT,
+        new_value     TEXT,
+        actor         TEXT NOT NULL,
+        changed_at    TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_row_history_row
+        ON row_history (name, week);
+    "#,
+    // 16: Languages each student speaks, for the grouping language report
+    r#"
+    CREATE TABLE IF NOT EXISTS spoken_languages (
+        name          TEXT NOT NULL,
+        language      TEXT NOT NULL,
+        PRIMARY KEY (name, language)
+    );
+    "#,
+    // 17: Cohort-wide announcements and who has read them
+    r#"
+    CREATE TABLE IF NOT EXISTS announcements (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        title         TEXT NOT NULL,
+        body          TEXT NOT NULL,
+        critical      INTEGER NOT NULL DEFAULT 0,
+        created_by    TEXT NOT NULL,
+        created_at    TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS announcement_reads (
+        announcement_id INTEGER NOT NULL,
+        name            TEXT NOT NULL,
+        read_at         TEXT NOT NULL,
+        PRIMARY KEY (announcement_id, name)
+    );
+    "#,
+    // 18: Outcome of every exercise sync run, for the sync SLO report
+    r#"
+    CREATE TABLE IF NOT EXISTS sync_runs (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        week          INTEGER NOT NULL,
+        started_at    TEXT NOT NULL,
+        finished_at   TEXT NOT NULL,
+        succeeded     INTEGER NOT NULL,
+        error         TEXT
+    );
+    "#,
+    // 19: Exercise evaluation of weekly rows, keyed by participant and week
+    // (by student row since, see `key_details_by_row`)
+    r#"
+    CREATE TABLE IF NOT EXISTS exercise_results (
+        participant_id      TEXT NOT NULL,
+        week                INTEGER NOT NULL,
+        submitted           TEXT,
+        test_passing        TEXT,
+        good_documentation  TEXT,
+        good_structure      TEXT,
+        PRIMARY KEY (participant_id, week)
+    );
+    "#,
+    // 20: Invites for new TAs and the profiles of TAs who accepted one
+    r#"
+    CREATE TABLE IF NOT EXISTS ta_invites (
+        token_hash   TEXT PRIMARY KEY,
+        email        TEXT NOT NULL,
+        name         TEXT NOT NULL,
+        invited_by   TEXT NOT NULL,
+        created_at   TEXT NOT NULL,
+        expires_at   TEXT NOT NULL,
+        accepted_at  TEXT
+    );
+    CREATE TABLE IF NOT EXISTS onboarded_tas (
+        name               TEXT PRIMARY KEY,
+        email              TEXT NOT NULL UNIQUE,
+        github             TEXT NOT NULL,
+        discord            TEXT NOT NULL,
+        notify_by_email    INTEGER NOT NULL,
+        notify_by_discord  INTEGER NOT NULL,
+        onboarded_at       TEXT NOT NULL
+    );
+    "#,
+    // 21: Attendance and scores of weekly rows, keyed by participant and
+    // week, one score per criterion (by student row since)
+    r#"
+    CREATE TABLE IF NOT EXISTS attendance (
+        participant_id  TEXT NOT NULL,
+        week            INTEGER NOT NULL,
+        status          TEXT,
+        PRIMARY KEY (participant_id, week)
+    );
+    CREATE TABLE IF NOT EXISTS scores (
+        participant_id  TEXT NOT NULL,
+        week            INTEGER NOT NULL,
+        criterion       TEXT NOT NULL,
+        score           INTEGER NOT NULL,
+        PRIMARY KEY (participant_id, week, criterion)
+    );
+    "#,
+    // 22: TAs' summaries of their group's exercises, one per group and week
+    r#"
+    CREATE TABLE IF NOT EXISTS review_summaries (
+        week                INTEGER NOT NULL,
+        group_id            TEXT NOT NULL,
+        ta                  TEXT NOT NULL,
+        common_mistakes     TEXT NOT NULL,
+        standout_solutions  TEXT NOT NULL,
+        notes               TEXT,
+        submitted_at        TEXT NOT NULL,
+        PRIMARY KEY (week, group_id)
+    );
+    "#,
+    // 23: Responses to writes sent with an Idempotency-Key, kept for a day
+    r#"
+    CREATE TABLE IF NOT EXISTS idempotency_keys (
+        key         TEXT NOT NULL,
+        actor       TEXT NOT NULL,
+        fingerprint TEXT NOT NULL,
+        status      INTEGER NOT NULL,
+        body        TEXT NOT NULL,
+        created_at  TEXT NOT NULL,
+        PRIMARY KEY (key, actor)
+    );
+    "#,
+    // 24: Weeks closed for writes once grading is done
+    r#"
+    CREATE TABLE IF NOT EXISTS week_locks (
+        week      INTEGER PRIMARY KEY,
+        locked_by TEXT NOT NULL,
+        locked_at TEXT NOT NULL
+    );
+    "#,
+    // 25: Webhook deliveries already handled, so an old signed one cannot be
+    // replayed
+    r#"
+    CREATE TABLE IF NOT EXISTS webhook_deliveries (
+        id          TEXT PRIMARY KEY,
+        received_at TEXT NOT NULL
+    );
+    "#,
+];
+
+// Score columns of a weekly row, kept as one `scores` row per criterion
+pub(crate) const SCORE_COLUMNS: [&str; 7] = [
+    "fa",
+    "fb",
+    "fc",
+    "fd",
+    "bonus_attempt",
+    "bonus_answer_quality",
+    "bonus_follow_up",
+];
+
+pub fn run_migrations(path: &Path) -> Result<(), AppError> {
+    apply_migrations(&mut open_connection(path)?)
+}
+
+// Same as `run_migrations`, on an open connection
+pub fn apply_migrations(conn: &mut Connection) -> Result<(), AppError> {
+    let current: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = index + 1;
+        info!("Applying schema migration {}", version);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+
+    migrate_core_tables(conn)?;
+    Ok(())
+}
+
+// Last path segment of the participant's GitHub profile URL, lowercased,
+// so handles can be matched exactly. Kept in step with `github_login`.
+const GITHUB_LOGIN: &str = "lower(substr(rtrim(GitHub, '/'), length(rtrim(rtrim(GitHub, '/'), replace(rtrim(GitHub, '/'), '/', ''))) + 1))";
+
+// Links rows without a participant to the participant of the same name, then
+// renames rows whose participant has been renamed since. A row is left alone
+// if its new name is already taken that week.
+fn link_participants(conn: &Connection) -> Result<(), AppError> {
+    let linked = conn.execute(
+        "UPDATE students SET participant_id = (SELECT p.\"ID\" FROM participants p WHERE p.Name = students.name COLLATE NOCASE)
+         WHERE participant_id IS NULL
+           AND EXISTS (SELECT 1 FROM participants p WHERE p.Name = students.name COLLATE NOCASE)",
+        [],
+    )?;
+    let renamed = conn.execute(
+        "UPDATE students SET name = (SELECT p.Name FROM participants p WHERE p.\"ID\" = students.participant_id)
+         WHERE EXISTS (SELECT 1 FROM participants p WHERE p.\"ID\" = students.participant_id AND p.Name IS NOT NULL AND p.Name != students.name)
+           AND NOT EXISTS (SELECT 1 FROM students t, participants p WHERE p.\"ID\" = students.participant_id AND t.name = p.Name AND t.week = students.week)",
+        [],
+    )?;
+    if linked > 0 {
+        info!("Linked {} student rows to their participant", linked);
+    }
+    if renamed > 0 {
+        info!("Renamed {} student rows after their participant", renamed);
+    }
+    Ok(())
+}
+
+// Moves the attendance, score and exercise columns of the linked student
+// rows matching `filter` into their own tables, clearing them on the row.
+// The students table keeps who is enrolled in which group each week; rows
+// without a participant keep their own columns until they are linked.
+// Details are keyed by the row's public id, so two rows of one participant
+// in a week never share grades.
+pub(crate) fn move_row_details(
+    conn: &Connection,
+    filter: &str,
+    params: &[&dyn ToSql],
+) -> Result<usize, AppError> {
+    let moved = conn.execute(
+        &format!(
+            "INSERT INTO attendance (student_id, status)
+             SELECT public_id, attendance
+             FROM students WHERE participant_id IS NOT NULL AND {}
+             ON CONFLICT (student_id) DO UPDATE SET status = excluded.status",
+            filter
+        ),
+        params,
+    )?;
+    // Criteria left empty on the row are dropped, not kept from before
+    conn.execute(
+        &format!(
+            "DELETE FROM scores WHERE student_id IN
+             (SELECT public_id FROM students WHERE participant_id IS NOT NULL AND {})",
+            filter
+        ),
+        params,
+    )?;
+    let scores = SCORE_COLUMNS
+        .map(|column| {
+            format!(
+                "SELECT public_id, '{0}' AS criterion, CAST({0} AS INTEGER) AS score
+                 FROM students WHERE participant_id IS NOT NULL AND {1}",
+                column, filter
+            )
+        })
+        .join(" UNION ALL ");
+    conn.execute(
+        &format!(
+            "INSERT INTO scores (student_id, criterion, score)
+             SELECT public_id, criterion, score FROM ({}) WHERE score IS NOT NULL",
+            scores
+        ),
+        params,
+    )?;
+    conn.execute(
+        &format!(
+            "INSERT INTO exercise_results (student_id, submitted, test_passing, good_documentation, good_structure)
+             SELECT public_id, exercise_submitted, exercise_test_passing, exercise_good_documentation, exercise_good_structure
+             FROM students WHERE participant_id IS NOT NULL AND {}
+             ON CONFLICT (student_id) DO UPDATE SET submitted = excluded.submitted, test_passing = excluded.test_passing, good_documentation = excluded.good_documentation, good_structure = excluded.good_structure",
+            filter
+        ),
+        params,
+    )?;
+    // Cleared last, as the filter may test the columns being moved
+    conn.execute(
+        &format!(
+            "UPDATE students SET attendance = NULL, {}, exercise_submitted = NULL, exercise_test_passing = NULL, exercise_good_documentation = NULL, exercise_good_structure = NULL
+             WHERE participant_id IS NOT NULL AND {}",
+            SCORE_COLUMNS.map(|column| format!("{} = NULL", column)).join(", "),
+            filter
+        ),
+        params,
+    )?;
+    Ok(moved)
+}
+
+// Drops the details of rows that no longer exist. Called in the same
+// transaction as every hard delete of student rows.
+pub(crate) fn delete_orphaned_details(conn: &Connection) -> Result<usize, AppError> {
+    let mut deleted = 0;
+    for table in ["attendance", "scores", "exercise_results"] {
+        deleted += conn.execute(
+            &format!(
+                "DELETE FROM {} WHERE student_id NOT IN (SELECT public_id FROM students WHERE public_id IS NOT NULL)",
+                table
+            ),
+            [],
+        )?;
+    }
+    Ok(deleted)
+}
+
+// The detail tables were first keyed by participant and week, which made
+// duplicate rows of a participant share their grades. Rebuilds them keyed
+// by the row's public id, copying each participant's details to every row
+// that showed them.
+fn key_details_by_row(conn: &Connection) -> Result<(), AppError> {
+    if !column_exists(conn, "attendance", "participant_id")? {
+        return Ok(());
+    }
+    info!("Keying attendance, scores and exercise results by student row");
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(
+        "DROP VIEW IF EXISTS student_rows;
+         ALTER TABLE attendance RENAME TO attendance_by_participant;
+         ALTER TABLE scores RENAME TO scores_by_participant;
+         ALTER TABLE exercise_results RENAME TO exercise_results_by_participant;
+         CREATE TABLE attendance (
+             student_id  TEXT PRIMARY KEY,
+             status      TEXT
+         );
+         CREATE TABLE scores (
+             student_id  TEXT NOT NULL,
+             criterion   TEXT NOT NULL,
+             score       INTEGER NOT NULL,
+             PRIMARY KEY (student_id, criterion)
+         );
+         CREATE TABLE exercise_results (
+             student_id          TEXT PRIMARY KEY,
+             submitted           TEXT,
+             test_passing        TEXT,
+             good_documentation  TEXT,
+             good_structure      TEXT
+         );
+         INSERT INTO attendance (student_id, status)
+             SELECT s.public_id, d.status FROM attendance_by_participant d
+             JOIN students s ON s.participant_id = d.participant_id AND s.week = d.week
+             WHERE s.public_id IS NOT NULL;
+         INSERT INTO scores (student_id, criterion, score)
+             SELECT s.public_id, d.criterion, d.score FROM scores_by_participant d
+             JOIN students s ON s.participant_id = d.participant_id AND s.week = d.week
+             WHERE s.public_id IS NOT NULL;
+         INSERT INTO exercise_results (student_id, submitted, test_passing, good_documentation, good_structure)
+             SELECT s.public_id, d.submitted, d.test_passing, d.good_documentation, d.good_structure
+             FROM exercise_results_by_participant d
+             JOIN students s ON s.participant_id = d.participant_id AND s.week = d.week
+             WHERE s.public_id IS NOT NULL;
+         DROP TABLE attendance_by_participant;
+         DROP TABLE scores_by_participant;
+         DROP TABLE exercise_results_by_participant;",
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Moves the details of newly linked rows out and (re)creates the view the
+// storage reads student rows through, in the shape of the wide row
+fn split_row_details(conn: &Connection) -> Result<(), AppError> {
+    let pending = std::iter::once("attendance")
+        .chain(SCORE_COLUMNS)
+        .chain([
+            "exercise_submitted",
+            "exercise_test_passing",
+            "exercise_good_documentation",
+            "exercise_good_structure",
+        ])
+        .collect::<Vec<_>>()
+        .join(", ");
+    let moved = move_row_details(conn, &format!("COALESCE({}) IS NOT NULL", pending), &[])?;
+    if moved > 0 {
+        info!(
+            "Moved attendance, scores and exercise results of {} student rows",
+            moved
+        );
+    }
+
+    let score_columns = SCORE_COLUMNS
+        .map(|column| format!("COALESCE({0}.score, s.{0}) AS {0}", column))
+        .join(", ");
+    let score_joins = SCORE_COLUMNS
+        .map(|column| {
+            format!(
+                "LEFT JOIN scores {0} ON {0}.student_id = s.public_id AND {0}.criterion = '{0}'",
+                column
+            )
+        })
+        .join("\n");
+    conn.execute_batch(&format!(
+        "DROP VIEW IF EXISTS student_rows;
+         CREATE VIEW student_rows AS
+         SELECT s.name, s.group_id, s.ta,
+                COALESCE(a.status, s.attendance) AS attendance,
+                {},
+                COALESCE(e.submitted, s.exercise_submitted) AS exercise_submitted,
+                COALESCE(e.test_passing, s.exercise_test_passing) AS exercise_test_passing,
+                COALESCE(e.good_documentation, s.exercise_good_documentation) AS exercise_good_documentation,
+                COALESCE(e.good_structure, s.exercise_good_structure) AS exercise_good_structure,
+                s.total, s.mail, s.week, s.participant_id, s.deleted_at, s.public_id, s.version,
+                s.notes, s.updated_at, s.commit_count, s.last_commit_at, s.repo_url
+         FROM students s
+         LEFT JOIN attendance a ON a.student_id = s.public_id
+         {}
+         LEFT JOIN exercise_results e ON e.student_id = s.public_id;",
+        score_columns, score_joins
+    ))?;
+    Ok(())
+}
+
+// The participants and students tables are created by the migrate binary
+// rather than a migration, so changes to them are checked on every start
+fn migrate_core_tables(conn: &Connection) -> Result<(), AppError> {
+    if table_exists(conn, "students")? {
+        if !column_exists(conn, "students", "deleted_at")? {
+            info!("Adding deleted_at to students");
+            conn.execute("ALTER TABLE students ADD COLUMN deleted_at TEXT", [])?;
+        }
+        if !column_exists(conn, "students", "participant_id")? {
+            info!("Adding participant_id to students");
+            // SQLite will not add a reference to a table that does not exist
+            let reference = if table_exists(conn, "participants")? {
+                " REFERENCES participants(\"ID\")"
+            } else {
+                ""
+            };
+            conn.execute(
+                &format!(
+                    "ALTER TABLE students ADD COLUMN participant_id TEXT{}",
+                    reference
+                ),
+                [],
+            )?;
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_students_name_week ON students (name, week);
+             CREATE INDEX IF NOT EXISTS idx_students_participant ON students (participant_id, week);",
+        )?;
+        if table_exists(conn, "participants")? && column_exists(conn, "participants", "ID")? {
+            link_participants(conn)?;
+        }
+        assign_public_ids(conn, "students")?;
+        if !column_exists(conn, "students", "version")? {
+            info!("Adding version to students");
+            conn.execute(
+                "ALTER TABLE students ADD COLUMN version INTEGER NOT NULL DEFAULT 1",
+                [],
+            )?;
+        }
+        if !column_exists(conn, "students", "notes")? {
+            info!("Adding notes to students");
+            conn.execute("ALTER TABLE students ADD COLUMN notes TEXT", [])?;
+        }
+        if !column_exists(conn, "students", "updated_at")? {
+            info!("Adding updated_at to students");
+            conn.execute("ALTER TABLE students ADD COLUMN updated_at TEXT", [])?;
+        }
+        if !column_exists(conn, "students", "commit_count")? {
+            info!("Adding commit_count and last_commit_at to students");
+            conn.execute_batch(
+                "ALTER TABLE students ADD COLUMN commit_count INTEGER;
+                 ALTER TABLE students ADD COLUMN last_commit_at TEXT;",
+            )?;
+        }
+        if !column_exists(conn, "students", "repo_url")? {
+            info!("Adding repo_url to students");
+            conn.execute("ALTER TABLE students ADD COLUMN repo_url TEXT", [])?;
+        }
+        key_details_by_row(conn)?;
+        // Rows may have been removed outside the server, e.g. by a reseed
+        let orphaned = delete_orphaned_details(conn)?;
+        if orphaned > 0 {
+            info!("Deleted {} details of removed student rows", orphaned);
+        }
+        if column_exists(conn, "students", "exercise_submitted")?
+            && column_exists(conn, "students", "fa")?
+        {
+            split_row_details(conn)?;
+        }
+    }
+
+    if table_exists(conn, "participants")? && column_exists(conn, "participants", "GitHub")? {
+        if !column_exists(conn, "participants", "github_login")? {
+            info!("Adding github_login to participants");
+            conn.execute(
+                &format!(
+                    "ALTER TABLE participants ADD COLUMN github_login TEXT GENERATED ALWAYS AS ({}) VIRTUAL",
+                    GITHUB_LOGIN
+                ),
+                [],
+            )?;
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_participants_github_login ON participants (github_login);
+             CREATE INDEX IF NOT EXISTS idx_participants_name ON participants (Name COLLATE NOCASE);",
+        )?;
+    }
+    if table_exists(conn, "participants")? {
+        assign_public_ids(conn, "participants")?;
+    }
+    Ok(())
+}
+
+// Adds the public id column to a core table and gives every row without an
+// id a new one, see `utils::ids`
+fn assign_public_ids(conn: &Connection, table: &str) -> Result<(), AppError> {
+    if !column_exists(conn, table, "public_id")? {
+        info!("Adding public_id to {}", table);
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN public_id TEXT", table),
+            [],
+        )?;
+    }
+    conn.execute(
+        &format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_{0}_public_id ON {0} (public_id)",
+            table
+        ),
+        [],
+    )?;
+
+    let tx = conn.unchecked_transaction()?;
+    let missing: Vec<i64> = tx
+        .prepare(&format!(
+            "SELECT rowid FROM {} WHERE public_id IS NULL",
+            table
+        ))?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for rowid in &missing {
+        tx.execute(
+            &format!("UPDATE {} SET public_id = ?1 WHERE rowid = ?2", table),
+            params![new_public_id(), rowid],
+        )?;
+    }
+    tx.commit()?;
+    if !missing.is_empty() {
+        info!("Assigned public ids to {} {} row(s)", missing.len(), table);
+    }
+    Ok(())
+}
@@ -0,0 +1,504 @@
+use crate::database::operations::SqliteStorage;
+use crate::database::paths::data_paths;
+use crate::database::pool::{create_pool, create_read_only_pool};
+use crate::database::postgres::PostgresStorage;
+use crate::database::schema::run_migrations;
+use crate::utils::backup::{BackupInfo, Backups};
+use crate::utils::types::{
+    Announcement, AppError, BackgroundData, Branding, ChangedRows, ChecklistItem, Checkpoint,
+    Communication, CommunicationKind, ConstraintKind, ExerciseAttempt, FeedbackResponse,
+    GroupThread, GroupingConstraint, IdempotentResponse, MaintenanceReport, Member,
+    ParticipantMatch, ReviewSummary, RowChange, RowData, RowHistoryEntry, RubricNote, SyncRun,
+    TaInvite, TaProfile, TaSetup, Table, VoiceAttendee, WeekLock, WeekTask,
+};
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use log::warn;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Everything the server reads or writes about the live cohort. SQLite is the
+// default; hosted deployments can keep several cohorts in Postgres instead.
+// Cohort archives used by bootstrap and retention stay SQLite files.
+// The read methods run on read-only connections, so the GET handlers built
+// on them cannot write to the cohort even by mistake.
+pub trait Storage: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Students
+    fn read_from_db(&self) -> Result<Table, AppError>;
+    // Live rows written after `since` and rows deleted after it, optionally of one week only. Rows purged for good are not
+    // reported.
+    fn read_changed_rows(
+        &self,
+        since: DateTime<Utc>,
+        week: Option<i32>,
+    ) -> Result<ChangedRows, AppError>;
+    // Writes only the given rows, keyed by (name, week): existing rows are
+    // updated and missing ones inserted, in a single transaction
+    fn upsert_rows(&self, rows: &[RowData]) -> Result<(), AppError>;
+    // Soft-deletes a student's row for one week, or all of their rows.
+    // Deleted rows are left out of reads until restored or purged.
+    fn delete_rows(&self, name: &str, week: Option<i32>) -> Result<usize, AppError>;
+    // Permanently removes rows, including soft-deleted ones
+    fn purge_rows(&self, name: &str, week: Option<i32>) -> Result<usize, AppError>;
+    // Soft-deletes, or with `permanent` removes, every row of a week in one
+    // transaction
+    fn delete_week(&self, week: i32, permanent: bool) -> Result<usize, AppError>;
+    fn read_deleted_row(&self, name: &str, week: i32) -> Result<Option<RowData>, AppError>;
+    // Undeletes a soft-deleted row. Returns false if there was none.
+    fn restore_row(&self, name: &str, week: i32) -> Result<bool, AppError>;
+    // Upserts rows together with the history of the changes made to them,
+    // in one transaction: either all of it persists or none of it does
+    fn write_rows(
+        &self,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<(), AppError>;
+    // Makes `rows` the full set of live rows of a week: upserts them with
+    // their history and soft-deletes the week's other rows, in one
+    // transaction. Returns the number of rows deleted.
+    fn replace_week(
+        &self,
+        week: i32,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<usize, AppError>;
+    // Folds every row of the student `duplicate` into the merged `rows`,
+    // written under the canonical name, in one transaction. The duplicate's
+    // rows are deleted outright, and a participant of the merged rows still
+    // named after the duplicate takes the canonical name, so the rows are not
+    // renamed back at startup. Returns the number of duplicate rows deleted.
+    fn merge_rows(
+        &self,
+        duplicate: &str,
+        rows: &[RowData],
+        history: &[RowChange],
+        actor: &str,
+    ) -> Result<usize, AppError>;
+    // Field-level edit history of weekly rows, newest first
+    fn read_row_history(&self, name: &str, week: i32) -> Result<Vec<RowHistoryEntry>, AppError>;
+    // (name, week) pairs stored more than once among live rows, with counts
+    fn read_duplicate_rows(&self) -> Result<Vec<(String, i32, usize)>, AppError>;
+    // Encrypts any plaintext mail values left from before encryption was
+    // enabled. Returns the number of rows changed.
+    fn encrypt_existing_mail(&self) -> Result<usize, AppError>;
+
+    // Participants
+    fn github_to_name(&self, github_username: &str) -> Result<Option<String>, AppError>;
+    fn github_username(&self, name: &str) -> Result<Option<String>, AppError>;
+    fn background_data(&self, email: &str) -> Result<Option<BackgroundData>, AppError>;
+    fn read_participant_names(&self) -> Result<Vec<String>, AppError>;
+    // Participants whose name, email or GitHub contains `query`, ignoring
+    // case, by name
+    fn search_participants(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<ParticipantMatch>, AppError>;
+    // Locations participants gave on the sign-up form, where filled in
+    fn read_participant_locations(&self) -> Result<Vec<String>, AppError>;
+
+    // Feedback form responses, replaced wholesale on every import
+    fn replace_responses(
+        &self,
+        headers: &[String],
+        records: &[Vec<String>],
+    ) -> Result<(), AppError>;
+    fn read_all_responses(&self, cohort_name: &str) -> Result<Vec<FeedbackResponse>, AppError>;
+
+    // Voice attendance
+    fn record_voice_snapshot(&self, week: i32, members: &[Member]) -> Result<usize, AppError>;
+    fn read_voice_attendees(&self, week: i32) -> Result<Vec<VoiceAttendee>, AppError>;
+    fn read_discord_handles(&self) -> Result<HashMap<String, String>, AppError>;
+    fn upsert_discord_handle(&self, discord_id: &str, name: &str) -> Result<(), AppError>;
+
+    // Communications, newest first
+    fn record_communication(
+        &self,
+        participant: &str,
+        kind: CommunicationKind,
+        subject: &str,
+        note: Option<&str>,
+        sent_by: &str,
+    ) -> Result<Communication, AppError>;
+    fn read_communications(&self, participant: &str) -> Result<Vec<Communication>, AppError>;
+
+    // Announcements, newest first
+    fn store_announcement(
+        &self,
+        title: &str,
+        body: &str,
+        critical: bool,
+        created_by: &str,
+    ) -> Result<Announcement, AppError>;
+    fn read_announcements(&self) -> Result<Vec<Announcement>, AppError>;
+    // When each student first read each announcement, by announcement id
+    fn read_announcement_reads(&self) -> Result<HashMap<i64, HashMap<String, String>>, AppError>;
+    // Keeps the first read time of announcements already read
+    fn mark_announcements_read(&self, name: &str, ids: &[i64]) -> Result<(), AppError>;
+
+    // Every checklist task for the week, completed or not, in checklist order
+    fn read_week_checklist(&self, week: i32) -> Result<Vec<ChecklistItem>, AppError>;
+    fn complete_week_task(
+        &self,
+        week: i32,
+        task: WeekTask,
+        completed_by: &str,
+    ) -> Result<(), AppError>;
+    fn reopen_week_task(&self, week: i32, task: WeekTask) -> Result<(), AppError>;
+
+    // The admin TOTP secret (base32) and whether enrollment has been confirmed
+    fn read_admin_totp(&self) -> Result<Option<(String, bool)>, AppError>;
+    // Replaces any pending enrollment with a fresh unconfirmed secret
+    fn store_admin_totp(&self, secret: &str) -> Result<(), AppError>;
+    fn confirm_admin_totp(&self) -> Result<(), AppError>;
+    fn delete_admin_totp(&self) -> Result<(), AppError>;
+
+    fn read_branding(&self) -> Result<Option<Branding>, AppError>;
+    fn store_branding(&self, branding: &Branding, updated_by: &str) -> Result<Branding, AppError>;
+
+    fn read_grouping_constraints(&self) -> Result<Vec<GroupingConstraint>, AppError>;
+    // Adds or replaces the constraint for a pair; callers pass names sorted
+    fn store_grouping_constraint(
+        &self,
+        kind: ConstraintKind,
+        first: &str,
+        second: &str,
+        created_by: &str,
+    ) -> Result<GroupingConstraint, AppError>;
+    fn delete_grouping_constraint(&self, id: i64) -> Result<bool, AppError>;
+
+    // Lowercase languages each student speaks, by student name
+    fn read_spoken_languages(&self) -> Result<HashMap<String, BTreeSet<String>>, AppError>;
+    // Replaces the languages recorded for a student
+    fn replace_spoken_languages(
+        &self,
+        name: &str,
+        languages: &BTreeSet<String>,
+    ) -> Result<(), AppError>;
+
+    // Stores newly seen submission states, ignoring ones already recorded.
+    // Returns the number of new states.
+    fn record_exercise_attempts(&self, attempts: &[ExerciseAttempt]) -> Result<usize, AppError>;
+    fn read_exercise_attempts(&self) -> Result<Vec<ExerciseAttempt>, AppError>;
+
+    fn record_sync_run(&self, run: &SyncRun) -> Result<(), AppError>;
+    // Oldest first
+    fn read_sync_runs(&self) -> Result<Vec<SyncRun>, AppError>;
+
+    fn read_rubric_notes(&self) -> Result<Vec<RubricNote>, AppError>;
+    // Replaces all notes of a criterion, keyed by score
+    fn replace_rubric_notes(
+        &self,
+        criterion: &str,
+        notes: &BTreeMap<u64, String>,
+        updated_by: &str,
+    ) -> Result<(), AppError>;
+
+    // Threads of the week's published groups, by group
+    fn read_group_threads(&self, week: i32) -> Result<Vec<GroupThread>, AppError>;
+    // Adds or updates the thread of a group, keyed by (week, group_id)
+    fn store_group_thread(&self, thread: &GroupThread) -> Result<(), AppError>;
+
+    // TAs' exercise review summaries, by week and group
+    fn read_review_summaries(&self) -> Result<Vec<ReviewSummary>, AppError>;
+    // Adds or replaces the summary of a group, keyed by (week, group_id)
+    fn store_review_summary(&self, summary: &ReviewSummary) -> Result<(), AppError>;
+
+    // Weeks closed for writes, oldest first
+    fn read_week_locks(&self) -> Result<Vec<WeekLock>, AppError>;
+    // Locks a week, keeping the first lock if it already was
+    fn lock_week(&self, lock: &WeekLock) -> Result<(), AppError>;
+    // Whether the week was locked
+    fn unlock_week(&self, week: i32) -> Result<bool, AppError>;
+
+    // The response recorded for an Idempotency-Key sent by `actor`
+    fn read_idempotent_response(
+        &self,
+        key: &str,
+        actor: &str,
+    ) -> Result<Option<IdempotentResponse>, AppError>;
+    // Records a response, replacing one under the same key and actor, and
+    // drops those recorded before `prune_before`
+    fn store_idempotent_response(
+        &self,
+        response: &IdempotentResponse,
+        prune_before: DateTime<Utc>,
+    ) -> Result<(), AppError>;
+
+    // Records a handled webhook delivery, false when it already was, and
+    // drops those received before `prune_before`
+    fn record_webhook_delivery(
+        &self,
+        id: &str,
+        received_at: DateTime<Utc>,
+        prune_before: DateTime<Utc>,
+    ) -> Result<bool, AppError>;
+    // Lets a delivery that failed be redelivered
+    fn forget_webhook_delivery(&self, id: &str) -> Result<(), AppError>;
+
+    // Names of offboarded TAs
+    fn read_inactive_tas(&self) -> Result<HashSet<String>, AppError>;
+    // Keeps the original record when the TA is already inactive
+    fn deactivate_ta(&self, ta: &str, deactivated_by: &str) -> Result<(), AppError>;
+
+    // TAs who joined through an invite, in the order they joined
+    fn read_onboarded_tas(&self) -> Result<Vec<TaProfile>, AppError>;
+    // Replaces any pending invite for the same email
+    fn create_ta_invite(&self, token_hash: &str, invite: &TaInvite) -> Result<(), AppError>;
+    // Redeems a pending invite and records the TA's profile in one
+    // transaction. None when the invite is unknown, used, expired or its
+    // name or email was taken by another TA in the meantime.
+    fn accept_ta_invite(
+        &self,
+        token_hash: &str,
+        setup: &TaSetup,
+    ) -> Result<Option<TaProfile>, AppError>;
+
+    fn read_attention_dismissals(&self) -> Result<HashSet<String>, AppError>;
+    fn dismiss_attention_item(&self, id: &str, dismissed_by: &str) -> Result<(), AppError>;
+
+    // Hashes of revoked tokens that could still be presented. Rows past their
+    // expiry are pruned; tokens without an expiry stay revoked.
+    fn read_revoked_tokens(&self) -> Result<HashSet<String>, AppError>;
+    fn revoke_token(
+        &self,
+        token_hash: &str,
+        revoked_by: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError>;
+
+    // Rewrites the database to drop free space and refreshes the planner
+    // statistics. Writers wait until it is done.
+    fn vacuum_and_analyze(&self) -> Result<MaintenanceReport, AppError>;
+}
+
+// Whether an invite expiring at the given RFC 3339 time can still be accepted
+pub(crate) fn invite_pending(expires_at: &str) -> bool {
+    DateTime::parse_from_rfc3339(expires_at).is_ok_and(|expires| expires > Utc::now())
+}
+
+// Runs storage calls on actix's blocking thread pool so a slow query or a
+// long write does not stall the worker serving unrelated requests
+pub async fn blocking<T, F>(db: &web::Data<dyn Storage>, f: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn Storage) -> Result<T, AppError> + Send + 'static,
+{
+    let db = db.clone();
+    web::block(move || f(db.get_ref())).await?
+}
+
+// Persists a batch already applied to the table together with its history.
+// If the write fails the batch is undone in memory as well, so the table
+// never holds rows the database does not.
+pub async fn persist_batch(
+    state: &web::Data<Mutex<Table>>,
+    db: &web::Data<dyn Storage>,
+    rows: Vec<RowData>,
+    history: Vec<RowChange>,
+    actor: String,
+    checkpoint: Checkpoint,
+) -> Result<(), AppError> {
+    let written = blocking(db, move |db| db.write_rows(&rows, &history, &actor)).await;
+    if written.is_err() {
+        state.lock().unwrap().revert(checkpoint);
+    }
+    written
+}
+
+// Checklist in `WeekTask::ALL` order from the completed tasks of a week,
+// keyed by task with (completed_at, completed_by)
+pub fn checklist_items(completed: &HashMap<String, (String, String)>) -> Vec<ChecklistItem> {
+    WeekTask::ALL
+        .into_iter()
+        .map(|task| {
+            let done = completed.get(task.as_str());
+            ChecklistItem {
+                task,
+                completed_at: done.map(|(at, _)| at.clone()),
+                completed_by: done.map(|(_, by)| by.clone()),
+            }
+        })
+        .collect()
+}
+
+// GitHub handle as matched against `participants.github_login`: the last
+// path segment of a profile URL, lowercased
+pub fn github_login(value: &str) -> String {
+    let value = value.trim_end_matches('/');
+    value
+        .rsplit('/')
+        .next()
+        .unwrap_or(value)
+        .to_ascii_lowercase()
+}
+
+// LIKE pattern matching `query` anywhere, with its wildcards escaped by `\`
+pub fn contains_pattern(query: &str) -> String {
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// Discord ids stored comma separated in `group_threads.members`
+pub fn split_members(members: &str) -> Vec<String> {
+    members
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Points of a review summary, stored one per line
+pub fn split_points(points: &str) -> Vec<String> {
+    points
+        .lines()
+        .filter(|point| !point.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Where the live cohort is stored, from DATABASE_URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageBackend {
+    Sqlite(PathBuf),
+    Postgres(String),
+}
+
+impl StorageBackend {
+    // SQLite at DATABASE_PATH unless DATABASE_URL is a postgres:// URL
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("DATABASE_URL") {
+            Ok(url) if url.starts_with("postgres://") || url.starts_with("postgresql://") => {
+                Ok(StorageBackend::Postgres(url))
+            }
+            Ok(url) if !url.is_empty() => {
+                Err("Unsupported DATABASE_URL, expected a postgres:// URL".to_string())
+            }
+            _ => Ok(StorageBackend::Sqlite(data_paths().database.clone())),
+        }
+    }
+
+    // Connects and applies pending schema migrations. A missing SQLite file
+    // is an error rather than silently replaced by an empty database.
+    pub fn open(&self) -> Result<Arc<dyn Storage>, AppError> {
+        match self {
+            StorageBackend::Sqlite(path) => {
+                if !path.exists() {
+                    return Err(AppError::Io(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("Database file '{}' not found", path.display()),
+                    )));
+                }
+                run_migrations(path)?;
+                let storage = SqliteStorage::new(create_pool(path)?)
+                    .with_reader(create_read_only_pool(path)?);
+                Ok(Arc::new(storage))
+            }
+            StorageBackend::Postgres(url) => Ok(Arc::new(PostgresStorage::connect(url)?)),
+        }
+    }
+
+    // `open`, tried again with a doubling delay while the database is locked
+    // (e.g. by a backup) or not there yet. Returns the last error once the
+    // attempts run out.
+    pub fn open_with_retry(&self, retry: &StartupRetry) -> Result<Arc<dyn Storage>, AppError> {
+        let mut delay = retry.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match self.open() {
+                Ok(storage) => return Ok(storage),
+                Err(e) if attempt >= retry.attempts => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "Opening the database failed (attempt {} of {}): {}; retrying in {}ms",
+                        attempt,
+                        retry.attempts,
+                        e,
+                        delay.as_millis()
+                    );
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+// How often startup tries to open the database, from DB_STARTUP_ATTEMPTS
+// and DB_STARTUP_BACKOFF_MS (the delay before the second attempt)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartupRetry {
+    pub attempts: u32,
+    pub initial_delay: Duration,
+}
+
+impl Default for StartupRetry {
+    fn default() -> Self {
+        // Waits 0.5 + 1 + 2 + 4 + 8 seconds in all
+        StartupRetry {
+            attempts: 6,
+            initial_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl StartupRetry {
+    pub fn from_env() -> Result<Self, String> {
+        let defaults = StartupRetry::default();
+        let attempts = match env::var("DB_STARTUP_ATTEMPTS") {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|attempts| *attempts > 0)
+                .ok_or("DB_STARTUP_ATTEMPTS must be a number of at least 1")?,
+            _ => defaults.attempts,
+        };
+        let initial_delay = match env::var("DB_STARTUP_BACKOFF_MS") {
+            Ok(value) if !value.trim().is_empty() => Duration::from_millis(
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| "DB_STARTUP_BACKOFF_MS must be a number of milliseconds")?,
+            ),
+            _ => defaults.initial_delay,
+        };
+        Ok(StartupRetry {
+            attempts,
+            initial_delay,
+        })
+    }
+}
+
+// What a server that could not open its SQLite file serves instead: the
+// newest backup copied into memory, or an empty cohort without one. Writes
+// to it would be lost, so the server rejects them while degraded.
+pub fn degraded_storage(
+    backups: &Backups,
+) -> Result<(SqliteStorage, Option<BackupInfo>), AppError> {
+    let newest = match backups.list() {
+        Ok(list) => list.into_iter().next(),
+        Err(e) => {
+            warn!("Could not list backups: {}", e);
+            None
+        }
+    };
+    if let Some(backup) = newest {
+        match SqliteStorage::in_memory_copy(&backups.path(&backup)) {
+            Ok(storage) => return Ok((storage, Some(backup))),
+            Err(e) => warn!("Could not load backup {}: {}", backup.file, e),
+        }
+    }
+    Ok((SqliteStorage::in_memory()?, None))
+}
@@ -0,0 +1,290 @@
+//! Cohort-wide announcements. Each student is emailed a personal link to the
+//! announcements page, and opening it is what records their reads, so
+//! organizers can see who has not yet seen a critical notice.
+
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, AuthError, Caller, LockoutTracker, frontend_url, lockout_keys};
+use crate::handlers::communications::record_sent;
+use crate::utils::constants::get_auth_token;
+use crate::utils::discord_threads::{announcement_channel, post_channel_message};
+use crate::utils::mailer::Mailer;
+use crate::utils::types::{Announcement, CommunicationKind, Table};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, get, post, web};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeSet;
+use std::env;
+use std::sync::Mutex;
+
+// Signed per-student links to the announcements page: `<hex name>.<hmac>`.
+// They do not expire, as they only grant reading announcements.
+pub struct ReadLinks {
+    key: Vec<u8>,
+}
+
+impl ReadLinks {
+    // Signed with MAGIC_LINK_SECRET, falling back to the admin token
+    pub fn from_env() -> Self {
+        let key = env::var("MAGIC_LINK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .unwrap_or_else(get_auth_token);
+        ReadLinks {
+            key: format!("announcements:{}", key).into_bytes(),
+        }
+    }
+
+    fn signature(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    pub fn issue(&self, name: &str) -> String {
+        let payload = hex::encode(name);
+        let signature = hex::encode(self.signature(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    // The student the link was issued for
+    pub fn verify(&self, token: &str) -> Option<String> {
+        let (payload, signature) = token.split_once('.')?;
+        self.signature(payload)
+            .verify_slice(&hex::decode(signature).ok()?)
+            .ok()?;
+        String::from_utf8(hex::decode(payload).ok()?).ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewAnnouncement {
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub critical: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadLinkQuery {
+    pub token: String,
+}
+
+// An announcement as a student sees it. `read_at` is when they first opened
+// it, empty when it is new to them.
+#[derive(Debug, Serialize)]
+pub struct StudentAnnouncement {
+    #[serde(flatten)]
+    pub announcement: Announcement,
+    pub read_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnouncementReceipts {
+    #[serde(flatten)]
+    pub announcement: Announcement,
+    pub read: usize,
+    // Enrolled students who have not opened it yet
+    pub unread: Vec<String>,
+}
+
+// Enrolled students with their mail, from the week 0 rows
+fn enrolled(state: &Mutex<Table>) -> Vec<(String, String)> {
+    state
+        .lock()
+        .unwrap()
+        .rows
+        .iter()
+        .filter(|row| row.week == 0)
+        .map(|row| (row.name.clone(), row.mail.clone()))
+        .collect()
+}
+
+// Posts to the announcement channel and emails every student their link.
+// Failures are logged; the announcement is already stored and readable.
+async fn push_announcement(
+    announcement: Announcement,
+    recipients: Vec<(String, String)>,
+    mailer: Option<web::Data<Mailer>>,
+    links: web::Data<ReadLinks>,
+    db: web::Data<dyn Storage>,
+) {
+    if let Some(channel) = announcement_channel() {
+        let content = format!("**{}**\n{}", announcement.title, announcement.body);
+        if let Err(e) = post_channel_message(&channel, &content).await {
+            warn!(
+                "Failed to post announcement {} to Discord: {}",
+                announcement.id, e
+            );
+        }
+    }
+
+    let Some(mailer) = mailer else {
+        return;
+    };
+    let mut sent = 0;
+    for (name, mail) in recipients {
+        let link = format!(
+            "{}/announcements?token={}",
+            frontend_url(),
+            links.issue(&name)
+        );
+        let body = format!(
+            "{}\n\nRead this and earlier announcements here:\n{}",
+            announcement.body, link
+        );
+        match mailer.send(&mail, &announcement.title, body).await {
+            Ok(()) => {
+                sent += 1;
+                record_sent(
+                    &db,
+                    &name,
+                    CommunicationKind::Email,
+                    &announcement.title,
+                    Some(format!("Announcement {}", announcement.id)),
+                    &announcement.created_by,
+                )
+                .await;
+            }
+            Err(e) => warn!(
+                "Failed to email announcement {} to {}: {}",
+                announcement.id, name, e
+            ),
+        }
+    }
+    info!(
+        "Emailed announcement {} to {} student(s)",
+        announcement.id, sent
+    );
+}
+
+#[post("/announcements")]
+pub async fn create_announcement(
+    _admin: Admin,
+    body: web::Json<NewAnnouncement>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+    links: web::Data<ReadLinks>,
+    mailer: Option<web::Data<Mailer>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewAnnouncement {
+        title,
+        body,
+        critical,
+    } = body.into_inner();
+    let (title, body) = (title.trim().to_string(), body.trim().to_string());
+    if title.is_empty() || body.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "An announcement needs a title and a body",
+        ));
+    }
+
+    let announcement = blocking(&db, move |db| {
+        db.store_announcement(&title, &body, critical, &Caller::Admin.label())
+    })
+    .await?;
+    info!(
+        target: "audit",
+        "Announcement {} created: {}{}",
+        announcement.id,
+        announcement.title,
+        if critical { " (critical)" } else { "" }
+    );
+
+    let recipients: Vec<(String, String)> = enrolled(&state)
+        .into_iter()
+        .filter(|(_, mail)| !mail.trim().is_empty())
+        .collect();
+    let channels = serde_json::json!({
+        "discord": announcement_channel().is_some(),
+        "emails": if mailer.is_some() { recipients.len() } else { 0 },
+    });
+    actix_web::rt::spawn(push_announcement(
+        announcement.clone(),
+        recipients,
+        mailer,
+        links,
+        db,
+    ));
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "announcement": announcement,
+        "channels": channels
+    })))
+}
+
+// Student view, reached through the link in the announcement email.
+// Everything listed is marked as read by this student.
+#[get("/announcements")]
+pub async fn get_announcements(
+    query: web::Query<ReadLinkQuery>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+    links: web::Data<ReadLinks>,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let keys = lockout_keys(&req, None);
+    if let Some(until) = lockouts.lock().unwrap().locked_until(&keys) {
+        return Ok(AuthError::LockedOut { until }.error_response());
+    }
+    let name = links
+        .verify(&query.token)
+        .filter(|name| enrolled(&state).iter().any(|(student, _)| student == name));
+    let Some(name) = name else {
+        lockouts.lock().unwrap().record_failure(&keys);
+        return Ok(AuthError::Unauthorized.error_response());
+    };
+
+    let announcements = blocking(&db, |db| db.read_announcements()).await?;
+    let mut reads = blocking(&db, |db| db.read_announcement_reads()).await?;
+    let ids: Vec<i64> = announcements.iter().map(|a| a.id).collect();
+    let student = name.clone();
+    blocking(&db, move |db| db.mark_announcements_read(&student, &ids)).await?;
+
+    let announcements: Vec<StudentAnnouncement> = announcements
+        .into_iter()
+        .map(|announcement| StudentAnnouncement {
+            read_at: reads
+                .get_mut(&announcement.id)
+                .and_then(|readers| readers.remove(&name)),
+            announcement,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "name": name,
+        "announcements": announcements
+    })))
+}
+
+// Read counts per announcement and the enrolled students yet to open each
+#[get("/announcements/receipts")]
+pub async fn get_announcement_receipts(
+    _admin: Admin,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let students: BTreeSet<String> = enrolled(&state).into_iter().map(|(name, _)| name).collect();
+    let announcements = blocking(&db, |db| db.read_announcements()).await?;
+    let reads = blocking(&db, |db| db.read_announcement_reads()).await?;
+
+    let receipts: Vec<AnnouncementReceipts> = announcements
+        .into_iter()
+        .map(|announcement| {
+            let readers = reads.get(&announcement.id);
+            let has_read = |name: &str| readers.is_some_and(|r| r.contains_key(name));
+            AnnouncementReceipts {
+                read: students.iter().filter(|name| has_read(name)).count(),
+                unread: students
+                    .iter()
+                    .filter(|name| !has_read(name))
+                    .cloned()
+                    .collect(),
+                announcement,
+            }
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(receipts))
+}
@@ -0,0 +1,511 @@
+use crate::database::storage::{Storage, blocking, persist_batch};
+use crate::handlers::auth::{Admin, AuthError, Authenticated, Caller};
+use crate::handlers::cohort_window::WeekWindow;
+use crate::handlers::dry_run::DryRun;
+use crate::services::grouping::group_id;
+use crate::services::validation::{FieldError, InvalidRows, YES_NO};
+use crate::utils::discord_voice::{fetch_voice_members, match_participant};
+use crate::utils::types::{AppError, RowData, Table, VoiceAttendee};
+use actix_web::{HttpResponse, Responder, get, post, put, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+#[derive(Debug, Serialize)]
+pub struct AttendanceProposal {
+    pub name: String,
+    pub current_attendance: Option<String>,
+    pub proposed_attendance: String,
+    pub discord_name: Option<String>,
+    pub snapshots_seen: u32,
+    pub changed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttendanceProposals {
+    pub week: i32,
+    pub proposals: Vec<AttendanceProposal>,
+    pub unmatched: Vec<VoiceAttendee>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmAttendance {
+    pub names: Vec<String>,
+}
+
+// One student's attendance, as marked live during a session
+#[derive(Debug, Deserialize)]
+pub struct AttendanceMark {
+    // Name or public id
+    pub name: String,
+    pub attendance: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttendanceToggle {
+    pub attendance: String,
+}
+
+impl AttendanceToggle {
+    fn checked(self) -> Result<String, actix_web::Error> {
+        if !YES_NO.contains(&self.attendance.as_str()) {
+            return Err(actix_web::error::ErrorBadRequest(
+                "attendance must be \"yes\" or \"no\"",
+            ));
+        }
+        Ok(self.attendance)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscordHandle {
+    pub name: String,
+}
+
+// Builds attendance proposals for a week from the recorded voice snapshots.
+// Returns None when no snapshot has been taken for the week yet.
+fn build_proposals(
+    db: &dyn Storage,
+    week: i32,
+    week_rows: &[RowData],
+) -> Result<Option<AttendanceProposals>, AppError> {
+    let attendees = db.read_voice_attendees(week)?;
+    if attendees.is_empty() {
+        return Ok(None);
+    }
+    let handles = db.read_discord_handles()?;
+    let names: Vec<String> = week_rows.iter().map(|r| r.name.clone()).collect();
+
+    let mut seen: HashMap<String, &VoiceAttendee> = HashMap::new();
+    let mut unmatched = Vec::new();
+    for attendee in &attendees {
+        match match_participant(
+            &attendee.discord_id,
+            &attendee.discord_name,
+            &handles,
+            &names,
+        ) {
+            Some(name) => {
+                seen.insert(name.clone(), attendee);
+            }
+            None => unmatched.push(attendee.clone()),
+        }
+    }
+
+    let proposals = week_rows
+        .iter()
+        .map(|row| {
+            let attendee = seen.get(&row.name);
+            let proposed = if attendee.is_some() { "yes" } else { "no" }.to_string();
+            AttendanceProposal {
+                name: row.name.clone(),
+                changed: row.attendance.as_deref() != Some(proposed.as_str()),
+                current_attendance: row.attendance.clone(),
+                proposed_attendance: proposed,
+                discord_name: attendee.map(|a| a.discord_name.clone()),
+                snapshots_seen: attendee.map(|a| a.snapshots_seen).unwrap_or(0),
+            }
+        })
+        .collect();
+
+    Ok(Some(AttendanceProposals {
+        week,
+        proposals,
+        unmatched,
+    }))
+}
+
+fn week_rows(state: &Mutex<Table>, week: i32) -> Vec<RowData> {
+    let state_table = state.lock().unwrap();
+    state_table
+        .rows
+        .iter()
+        .filter(|row| row.week == week)
+        .cloned()
+        .collect()
+}
+
+#[post("/attendance/{week}/voice_snapshot")]
+pub async fn take_voice_snapshot(
+    _caller: Authenticated,
+    week: web::Path<i32>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
+    let week = week.into_inner();
+    let channel_id = match env::var("ATTENDANCE_VOICE_CHANNEL_ID") {
+        Ok(id) if !id.is_empty() => id,
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "ATTENDANCE_VOICE_CHANNEL_ID is not configured"
+            }));
+        }
+    };
+
+    let members = match fetch_voice_members(&channel_id).await {
+        Ok(members) => members,
+        Err(e) => {
+            warn!("Failed to fetch voice channel members: {}", e);
+            return HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch voice channel members from the bot"
+            }));
+        }
+    };
+
+    match blocking(&db, move |db| db.record_voice_snapshot(week, &members)).await {
+        Ok(recorded) => HttpResponse::Ok().json(serde_json::json!({
+            "week": week,
+            "recorded": recorded
+        })),
+        Err(e) => {
+            warn!("Failed to store voice snapshot: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to store voice snapshot"
+            }))
+        }
+    }
+}
+
+#[get("/attendance/{week}/proposals")]
+pub async fn get_attendance_proposals(
+    _caller: Authenticated,
+    week: web::Path<i32>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
+    let week = week.into_inner();
+    let rows = week_rows(&state, week);
+
+    match blocking(&db, move |db| build_proposals(db, week, &rows)).await {
+        Ok(Some(proposals)) => HttpResponse::Ok().json(proposals),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No voice snapshots recorded for week {}", week)
+        })),
+        Err(e) => {
+            warn!("Failed to build attendance proposals: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build attendance proposals"
+            }))
+        }
+    }
+}
+
+#[post("/attendance/{week}/proposals/confirm")]
+pub async fn confirm_attendance_proposals(
+    Authenticated(caller): Authenticated,
+    dry_run: DryRun,
+    window: WeekWindow,
+    week: web::Path<i32>,
+    body: web::Json<ConfirmAttendance>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    window.check([week])?;
+    let rows = week_rows(&state, week);
+
+    // Proposals are recomputed server side; the body only selects which to accept
+    let proposals = blocking(&db, move |db| build_proposals(db, week, &rows)).await?;
+    let Some(proposals) = proposals else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No voice snapshots recorded for week {}", week)
+        })));
+    };
+
+    let accepted: HashMap<&String, &AttendanceProposal> = proposals
+        .proposals
+        .iter()
+        .filter(|p| p.changed && body.names.contains(&p.name))
+        .map(|p| (&p.name, p))
+        .collect();
+
+    // Single lock scope for all updates; TAs only touch their own group.
+    // The changed rows are persisted once the lock is released.
+    let mut updated = Vec::new();
+    let (history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        if dry_run.is_set() {
+            let changes: Vec<&AttendanceProposal> = state_table
+                .rows
+                .iter()
+                .filter(|r| r.week == week && caller.can_write_row(r, Some(r)))
+                .filter_map(|r| accepted.get(&r.name).copied())
+                .collect();
+            return Ok(DryRun::preview(serde_json::json!({ "update": changes })));
+        }
+
+        let confirmed: Vec<RowData> = state_table
+            .rows
+            .iter()
+            .filter(|r| r.week == week && caller.can_write_row(r, Some(r)))
+            .filter_map(|r| {
+                let proposal = accepted.get(&r.name)?;
+                let mut row = r.clone();
+                row.attendance = Some(proposal.proposed_attendance.clone());
+                Some(row)
+            })
+            .collect();
+        let checkpoint = state_table.checkpoint(&confirmed);
+        for mut row in confirmed {
+            if state_table.insert_or_update(&mut row)? {
+                updated.push(row);
+            }
+        }
+        (state_table.take_history(), checkpoint)
+    }; // Lock released here
+
+    let count = updated.len();
+    if count > 0 {
+        persist_batch(&state, &db, updated, history, caller.label(), checkpoint).await?;
+    }
+
+    info!(
+        "Confirmed {} voice attendance proposal(s) for week {}",
+        count, week
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "week": week,
+        "updated": count
+    })))
+}
+
+// Marks attendance during a session without sending whole rows: only the
+// attendance of the listed students changes. Groups follow from it when the
+// next week is generated.
+#[post("/attendance/{week}")]
+pub async fn mark_attendance(
+    Authenticated(caller): Authenticated,
+    dry_run: DryRun,
+    window: WeekWindow,
+    week: web::Path<i32>,
+    marks: web::Json<Vec<AttendanceMark>>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    if marks.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("No attendance provided"));
+    }
+    window.check([week])?;
+
+    let marked = {
+        let state_table = state.lock().unwrap();
+        let mut invalid = Vec::new();
+        let mut forbidden = Vec::new();
+        let mut marked = Vec::new();
+        for (index, mark) in marks.iter().enumerate() {
+            let name = state_table.student_name(&mark.name);
+            let error = |field: &str, message: String| FieldError {
+                row: index,
+                name: name.clone(),
+                field: field.to_string(),
+                message,
+            };
+            if !YES_NO.contains(&mark.attendance.as_str()) {
+                invalid.push(error(
+                    "attendance",
+                    format!("must be \"yes\" or \"no\", not {:?}", mark.attendance),
+                ));
+                continue;
+            }
+            let Some(row) = state_table
+                .rows
+                .iter()
+                .find(|row| row.name == name && row.week == week)
+            else {
+                invalid.push(error("name", format!("has no row in week {}", week)));
+                continue;
+            };
+            if !caller.can_write_row(row, Some(row)) {
+                forbidden.push(name);
+                continue;
+            }
+            let mut row = row.clone();
+            row.attendance = Some(mark.attendance.clone());
+            marked.push(row);
+        }
+        if !invalid.is_empty() {
+            return Err(InvalidRows(invalid).into());
+        }
+        if !forbidden.is_empty() {
+            warn!(
+                target: "audit",
+                "{:?} attempted to mark attendance outside their group: {:?}",
+                caller, forbidden
+            );
+            return Err(AuthError::Forbidden(format!(
+                "students outside your assigned group: {}",
+                forbidden.join(", ")
+            ))
+            .into());
+        }
+        marked
+    }; // Lock released here
+
+    write_attendance(caller, dry_run, week, marked, &state, &db).await
+}
+
+// Sets the attendance of a whole group for the week, e.g. when a group's
+// call did not take place
+#[post("/attendance/{week}/group/{group}")]
+#[allow(clippy::too_many_arguments)]
+pub async fn mark_group_attendance(
+    Authenticated(caller): Authenticated,
+    dry_run: DryRun,
+    window: WeekWindow,
+    path: web::Path<(i32, String)>,
+    body: web::Json<AttendanceToggle>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (week, group) = path.into_inner();
+    let group_id = group_id(&group);
+    let attendance = body.into_inner().checked()?;
+    window.check([week])?;
+
+    let marked = {
+        let state_table = state.lock().unwrap();
+        let rows: Vec<&RowData> = state_table
+            .rows
+            .iter()
+            .filter(|row| row.week == week && row.group_id == group_id)
+            .collect();
+        if rows.is_empty() {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("No {} in week {}", group_id, week)
+            })));
+        }
+        if !rows.iter().all(|row| caller.can_write_row(row, Some(row))) {
+            warn!(
+                target: "audit",
+                "{:?} attempted to mark attendance of {} in week {}",
+                caller, group_id, week
+            );
+            return Err(AuthError::Forbidden(format!(
+                "{} of week {} is not your assigned group",
+                group_id, week
+            ))
+            .into());
+        }
+        with_attendance(rows, &attendance)
+    }; // Lock released here
+
+    write_attendance(caller, dry_run, week, marked, &state, &db).await
+}
+
+// Sets the attendance of every student for the week, e.g. for a session
+// that was cancelled
+#[post("/attendance/{week}/all")]
+pub async fn mark_cohort_attendance(
+    _admin: Admin,
+    dry_run: DryRun,
+    window: WeekWindow,
+    week: web::Path<i32>,
+    body: web::Json<AttendanceToggle>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    let attendance = body.into_inner().checked()?;
+    window.check([week])?;
+
+    let marked = {
+        let state_table = state.lock().unwrap();
+        with_attendance(
+            state_table
+                .rows
+                .iter()
+                .filter(|row| row.week == week)
+                .collect(),
+            &attendance,
+        )
+    }; // Lock released here
+    if marked.is_empty() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No rows in week {}", week)
+        })));
+    }
+
+    write_attendance(Caller::Admin, dry_run, week, marked, &state, &db).await
+}
+
+fn with_attendance(rows: Vec<&RowData>, attendance: &str) -> Vec<RowData> {
+    rows.into_iter()
+        .map(|row| RowData {
+            attendance: Some(attendance.to_string()),
+            ..row.clone()
+        })
+        .collect()
+}
+
+// Writes the attendance of the marked rows in one transaction, or previews
+// the rows whose attendance would change for a dry run
+async fn write_attendance(
+    caller: Caller,
+    dry_run: DryRun,
+    week: i32,
+    marked: Vec<RowData>,
+    state: &web::Data<Mutex<Table>>,
+    db: &web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (updated, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        if dry_run.is_set() {
+            let changes: Vec<&RowData> = marked
+                .iter()
+                .filter(|row| {
+                    state_table
+                        .rows
+                        .iter()
+                        .any(|r| r.same_row(row) && r.attendance != row.attendance)
+                })
+                .collect();
+            return Ok(DryRun::preview(serde_json::json!({ "update": changes })));
+        }
+
+        let checkpoint = state_table.checkpoint(&marked);
+        let mut updated = Vec::new();
+        for mut row in marked {
+            if state_table.insert_or_update(&mut row)? {
+                updated.push(row);
+            }
+        }
+        (updated, state_table.take_history(), checkpoint)
+    }; // Lock released here
+
+    let count = updated.len();
+    if count > 0 {
+        persist_batch(state, db, updated, history, caller.label(), checkpoint).await?;
+    }
+    info!(
+        "{} marked attendance of {} student(s) in week {}",
+        caller.label(),
+        count,
+        week
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "week": week,
+        "updated": count
+    })))
+}
+
+#[put("/discord/handles/{discord_id}")]
+pub async fn set_discord_handle(
+    _admin: Admin,
+    discord_id: web::Path<String>,
+    body: web::Json<DiscordHandle>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let discord_id = discord_id.into_inner();
+    let (id, name) = (discord_id.clone(), body.name.clone());
+    blocking(&db, move |db| db.upsert_discord_handle(&id, &name)).await?;
+    info!("Mapped Discord user {} to {}", discord_id, body.name);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "discord_id": discord_id,
+        "name": body.name
+    })))
+}
@@ -0,0 +1,173 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::Authenticated;
+use crate::handlers::sync::{SyncStatus, WeekSyncStatus};
+use crate::utils::forge::SyncWarningKind;
+use crate::utils::types::{RowData, Table};
+use actix_web::{HttpResponse, get, post, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Consecutive missed sessions after which a student is flagged
+const AT_RISK_MISSED_WEEKS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttentionKind {
+    UnmatchedGithub,
+    AtRisk,
+    Ungraded,
+    SyncError,
+}
+
+// An actionable item for admins. `id` is stable across requests so an item
+// stays dismissed; `link` is the frontend route where it can be resolved.
+#[derive(Debug, Clone, Serialize)]
+pub struct AttentionItem {
+    pub id: String,
+    pub kind: AttentionKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub week: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub student: Option<String>,
+    pub message: String,
+    pub link: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DismissAttention {
+    pub id: String,
+}
+
+fn student_link(name: &str) -> String {
+    format!("/student?student={}", name.replace(' ', "%20"))
+}
+
+fn attended(row: &RowData) -> bool {
+    row.attendance.as_deref() == Some("yes")
+}
+
+fn is_ungraded(row: &RowData) -> bool {
+    [row.fa, row.fb, row.fc, row.fd]
+        .iter()
+        .all(|score| score.unwrap_or(0) == 0)
+}
+
+// Derives the queue from the current table and the latest sync results
+pub fn build_attention_items(rows: &[RowData], sync: &[WeekSyncStatus]) -> Vec<AttentionItem> {
+    let mut items = Vec::new();
+
+    // Latest week each unmatched GitHub user was seen in
+    let mut unmatched: BTreeMap<&str, i32> = BTreeMap::new();
+    for status in sync {
+        for warning in &status.warnings {
+            match (warning.kind, warning.github_username.as_deref()) {
+                (SyncWarningKind::RosterMismatch, Some(user)) => {
+                    unmatched.insert(user, status.week);
+                }
+                (SyncWarningKind::ClassroomUnavailable, _) => items.push(AttentionItem {
+                    id: format!("sync_error:{}:{}", status.week, status.synced_at),
+                    kind: AttentionKind::SyncError,
+                    week: Some(status.week),
+                    student: None,
+                    message: warning.message.clone(),
+                    link: "/admin".to_string(),
+                }),
+                _ => {}
+            }
+        }
+    }
+    items.extend(unmatched.into_iter().map(|(user, week)| AttentionItem {
+        id: format!("unmatched_github:{}", user),
+        kind: AttentionKind::UnmatchedGithub,
+        week: Some(week),
+        student: None,
+        message: format!("GitHub user {} does not match any participant", user),
+        link: "/admin".to_string(),
+    }));
+
+    let mut weeks: Vec<i32> = rows.iter().map(|r| r.week).filter(|w| *w >= 1).collect();
+    weeks.sort_unstable();
+    weeks.dedup();
+    let Some(&latest_week) = weeks.last() else {
+        return items;
+    };
+
+    // A week's grading is overdue once the next week has started
+    items.extend(
+        rows.iter()
+            .filter(|r| r.week >= 1 && r.week < latest_week && attended(r) && is_ungraded(r))
+            .map(|r| AttentionItem {
+                id: format!("ungraded:{}:{}", r.name, r.week),
+                kind: AttentionKind::Ungraded,
+                week: Some(r.week),
+                student: Some(r.name.clone()),
+                message: format!("{} attended week {} but has no GD scores", r.name, r.week),
+                link: student_link(&r.name),
+            }),
+    );
+
+    if weeks.len() >= AT_RISK_MISSED_WEEKS {
+        let recent = &weeks[weeks.len() - AT_RISK_MISSED_WEEKS..];
+        let mut missed: BTreeMap<&str, usize> = BTreeMap::new();
+        for row in rows.iter().filter(|r| recent.contains(&r.week)) {
+            let count = missed.entry(row.name.as_str()).or_insert(0);
+            if !attended(row) {
+                *count += 1;
+            }
+        }
+        items.extend(
+            missed
+                .into_iter()
+                .filter(|(_, count)| *count >= AT_RISK_MISSED_WEEKS)
+                .map(|(name, _)| AttentionItem {
+                    id: format!("at_risk:{}:{}", name, latest_week),
+                    kind: AttentionKind::AtRisk,
+                    week: Some(latest_week),
+                    student: Some(name.to_string()),
+                    message: format!("{} missed the last {} sessions", name, AT_RISK_MISSED_WEEKS),
+                    link: student_link(name),
+                }),
+        );
+    }
+
+    items
+}
+
+#[get("/attention")]
+pub async fn get_attention(
+    _caller: Authenticated,
+    state: web::Data<Mutex<Table>>,
+    sync_status: web::Data<Mutex<SyncStatus>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let sync: Vec<WeekSyncStatus> = sync_status.lock().unwrap().weeks().cloned().collect();
+    let items = {
+        let state_table = state.lock().unwrap();
+        build_attention_items(&state_table.rows, &sync)
+    }; // Lock released here
+
+    let dismissed = blocking(&db, |db| db.read_attention_dismissals()).await?;
+    let items: Vec<AttentionItem> = items
+        .into_iter()
+        .filter(|item| !dismissed.contains(&item.id))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "count": items.len(),
+        "items": items
+    })))
+}
+
+#[post("/attention/dismiss")]
+pub async fn dismiss_attention(
+    Authenticated(caller): Authenticated,
+    body: web::Json<DismissAttention>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (id, label) = (body.id.clone(), caller.label());
+    blocking(&db, move |db| db.dismiss_attention_item(&id, &label)).await?;
+    info!("{} dismissed attention item {}", caller.label(), body.id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "dismissed": body.id })))
+}
@@ -1,60 +1,996 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::communications::record_sent;
+use crate::handlers::dry_run::DryRun;
+use crate::handlers::tas::is_active;
 use crate::utils::constants::{TA_EMAILS, get_auth_token};
-use crate::utils::types::TaLogin;
-use actix_web::{HttpResponse, Responder, post, web};
-use log::info;
+use crate::utils::ip_allowlist::request_ip;
+use crate::utils::mailer::Mailer;
+use crate::utils::types::{AppError, CommunicationKind, RowData, TaLogin};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError, delete,
+    get, post, web,
+};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::future::{Ready, ready};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub enum TA {
-    AnmolSharma,
-    Bala,
-    Raj,
-    Setu,
-    Delcin,
-    Beulah,
+// Failures allowed before a key gets locked out
+const LOCKOUT_THRESHOLD: u32 = 5;
+// Stale session tokens an address may present before it is locked out too.
+// Higher, as a restart leaves every open tab holding one.
+const STALE_SESSION_THRESHOLD: u32 = 20;
+// First lockout lasts this long and doubles with every further failure
+const LOCKOUT_BASE_SECS: i64 = 30;
+const LOCKOUT_MAX_SECS: i64 = 60 * 60;
+// Lifetime of a TA session token issued for a redeemed login link
+const SESSION_TTL_HOURS: i64 = 12;
+// How long an emailed login link stays valid
+const MAGIC_LINK_TTL_MINS: i64 = 15;
+
+// A TA, by the name stored in the `ta` column of weekly rows. The founding
+// TAs are built in; TAs who accept an invite join them at runtime.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TA(Arc<str>);
+
+// Every TA with the email they sign in with, founding TAs first and then
+// onboarded ones in the order they joined. Emails are kept normalized. The
+// server has one, behind the `TA` lookups; tests may build their own.
+pub struct Roster {
+    tas: RwLock<Vec<(String, TA)>>,
+}
+
+impl Default for Roster {
+    fn default() -> Self {
+        let founding = TA_EMAILS
+            .iter()
+            .map(|(email, name)| (normalize_email(email), TA(Arc::from(*name))))
+            .collect();
+        Roster {
+            tas: RwLock::new(founding),
+        }
+    }
+}
+
+impl Roster {
+    pub fn all(&self) -> Vec<TA> {
+        self.tas
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, ta)| ta.clone())
+            .collect()
+    }
+
+    pub fn by_email(&self, email: &str) -> Option<TA> {
+        let email = normalize_email(email);
+        self.tas
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(ta_email, _)| *ta_email == email)
+            .map(|(_, ta)| ta.clone())
+    }
+
+    // Adds an onboarded TA, returning the TA already known by that name if
+    // there is one
+    pub fn register(&self, email: &str, name: &str) -> TA {
+        let mut tas = self.tas.write().unwrap();
+        let known = tas
+            .iter()
+            .map(|(_, ta)| ta)
+            .find(|ta| ta.0.eq_ignore_ascii_case(name))
+            .cloned();
+        known.unwrap_or_else(|| {
+            let ta = TA(Arc::from(name));
+            tas.push((normalize_email(email), ta.clone()));
+            ta
+        })
+    }
+}
+
+static ROSTER: LazyLock<Roster> = LazyLock::new(Roster::default);
+
+// Emails are matched case-insensitively, so every lookup and registration
+// goes through here
+fn normalize_email(email: &str) -> String {
+    email.trim().to_ascii_lowercase()
 }
 
 impl TA {
-    pub fn all_variants() -> &'static [TA] {
-        &[
-            TA::AnmolSharma,
-            TA::Bala,
-            TA::Raj,
-            TA::Setu,
-            TA::Delcin,
-            TA::Beulah,
-        ]
+    // Founding TAs first, then onboarded ones in the order they joined
+    pub fn all_variants() -> Vec<TA> {
+        ROSTER.all()
     }
 
     pub fn from_email(email: &str) -> Option<Self> {
-        for (ta_email, ta_name) in TA_EMAILS {
-            if email == *ta_email {
-                return match *ta_name {
-                    "AnmolSharma" => Some(TA::AnmolSharma),
-                    "Bala" => Some(TA::Bala),
-                    "Raj" => Some(TA::Raj),
-                    "Setu" => Some(TA::Setu),
-                    "Delcin" => Some(TA::Delcin),
-                    "Beulah" => Some(TA::Beulah),
-                    _ => None,
-                };
+        ROSTER.by_email(email)
+    }
+
+    // Name as stored in the `ta` column of weekly rows
+    pub fn name(&self) -> String {
+        self.0.to_string()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        TA::all_variants()
+            .into_iter()
+            .find(|ta| ta.0.eq_ignore_ascii_case(name))
+    }
+
+    // Adds an onboarded TA to the server's roster
+    pub fn register(email: &str, name: &str) -> TA {
+        ROSTER.register(email, name)
+    }
+}
+
+impl std::fmt::Debug for TA {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// Identity behind an authenticated request. The shared AUTH_TOKEN identifies
+// organizers (admin); TAs get personal session tokens by redeeming a
+// magic login link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caller {
+    Admin,
+    Ta(TA),
+}
+
+impl Caller {
+    // Admins may write any row. TAs may only write rows of students in the
+    // group they are assigned to for that week, and may not move a student
+    // to another group or TA.
+    pub fn can_write_row(&self, incoming: &RowData, existing: Option<&RowData>) -> bool {
+        match self {
+            Caller::Admin => true,
+            Caller::Ta(ta) => {
+                let name = ta.name();
+                existing.is_some_and(|existing| {
+                    existing.ta.as_deref() == Some(name.as_str())
+                        && incoming.ta.as_deref() == Some(name.as_str())
+                        && incoming.group_id == existing.group_id
+                })
             }
         }
-        None
+    }
+
+    // Recorded as the author of audit entries and communications
+    pub fn label(&self) -> String {
+        match self {
+            Caller::Admin => "admin".to_string(),
+            Caller::Ta(ta) => ta.name(),
+        }
+    }
+}
+
+// Where a request came from, recorded against sessions for review
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn of(req: &HttpRequest) -> Self {
+        ClientInfo {
+            ip: request_ip(req).map(|ip| ip.to_string()),
+            user_agent: req
+                .headers()
+                .get(actix_web::http::header::USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_string),
+        }
     }
 }
 
+#[derive(Debug, Clone)]
+struct Session {
+    // Public identifier; the token itself is never listed
+    id: String,
+    ta: TA,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    client: ClientInfo,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub ta: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub last_seen: String,
+    #[serde(flatten)]
+    pub client: ClientInfo,
+}
+
+// Last use of the shared admin token, which has no session of its own
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminActivity {
+    pub last_seen: String,
+    #[serde(flatten)]
+    pub client: ClientInfo,
+}
+
+pub(crate) fn random_hex(len: usize) -> String {
+    (0..len)
+        .map(|_| format!("{:02x}", rand::random::<u8>()))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: HashMap<String, Session>,
+    admin_activity: Option<AdminActivity>,
+}
+
+impl SessionStore {
+    pub fn create(&mut self, ta: TA, client: ClientInfo) -> String {
+        let token = random_hex(32);
+        let now = Utc::now();
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                id: random_hex(8),
+                ta,
+                created_at: now,
+                expires_at: now + Duration::hours(SESSION_TTL_HOURS),
+                last_seen: now,
+                client,
+            },
+        );
+        token
+    }
+
+    pub fn lookup(&mut self, token: &str, client: ClientInfo) -> Option<TA> {
+        let now = Utc::now();
+        self.sessions.retain(|_, session| session.expires_at > now);
+        self.sessions.get_mut(token).map(|session| {
+            session.last_seen = now;
+            session.client = client;
+            session.ta.clone()
+        })
+    }
+
+    pub fn record_admin_activity(&mut self, client: ClientInfo) {
+        self.admin_activity = Some(AdminActivity {
+            last_seen: Utc::now().to_rfc3339(),
+            client,
+        });
+    }
+
+    // Active sessions, most recently used first
+    pub fn list(&mut self) -> Vec<SessionInfo> {
+        let now = Utc::now();
+        self.sessions.retain(|_, session| session.expires_at > now);
+        let mut sessions: Vec<&Session> = self.sessions.values().collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+        sessions
+            .into_iter()
+            .map(|session| SessionInfo {
+                id: session.id.clone(),
+                ta: session.ta.name(),
+                created_at: session.created_at.to_rfc3339(),
+                expires_at: session.expires_at.to_rfc3339(),
+                last_seen: session.last_seen.to_rfc3339(),
+                client: session.client.clone(),
+            })
+            .collect()
+    }
+
+    // Ends every session of a TA, returning how many were active
+    pub fn revoke_ta(&mut self, ta: TA) -> usize {
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| session.ta != ta);
+        before - self.sessions.len()
+    }
+
+    pub fn revoke(&mut self, id: &str) -> Option<TA> {
+        let token = self
+            .sessions
+            .iter()
+            .find(|(_, session)| session.id == id)
+            .map(|(token, _)| token.clone())?;
+        self.sessions.remove(&token).map(|session| session.ta)
+    }
+
+    // Ends the session for a token, returning when it would have expired
+    pub fn end(&mut self, token: &str) -> Option<DateTime<Utc>> {
+        self.sessions
+            .remove(token)
+            .map(|session| session.expires_at)
+    }
+}
+
+// Tokens invalidated through /auth/logout. Persisted as hashes so a leaked
+// token stays rejected across restarts, including the admin token until
+// AUTH_TOKEN is rotated.
+#[derive(Debug, Default)]
+pub struct RevocationList {
+    hashes: HashSet<String>,
+}
+
+pub(crate) fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl RevocationList {
+    pub fn load(db: &dyn Storage) -> Result<Self, AppError> {
+        Ok(RevocationList {
+            hashes: db.read_revoked_tokens()?,
+        })
+    }
+
+    pub fn is_revoked(&self, token: &str) -> bool {
+        !self.hashes.is_empty() && self.hashes.contains(&token_hash(token))
+    }
+
+    // Persisted before the in-memory list is updated, so the lock is not
+    // held while the database write is pending
+    pub async fn revoke(
+        list: &Mutex<Self>,
+        db: &web::Data<dyn Storage>,
+        token: &str,
+        revoked_by: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError> {
+        let hash = token_hash(token);
+        let (stored, revoked_by) = (hash.clone(), revoked_by.to_string());
+        blocking(db, move |db| {
+            db.revoke_token(&stored, &revoked_by, expires_at)
+        })
+        .await?;
+        list.lock().unwrap().hashes.insert(hash);
+        Ok(())
+    }
+}
+
+// Signed one-time login links: `<hex email>.<expiry>.<nonce>.<hmac>`.
+// Redeemed nonces are remembered until the link would have expired anyway.
+pub struct MagicLinks {
+    key: Vec<u8>,
+    redeemed: HashMap<String, DateTime<Utc>>,
+}
+
+impl MagicLinks {
+    // Signed with MAGIC_LINK_SECRET, falling back to the admin token
+    pub fn from_env() -> Self {
+        let key = env::var("MAGIC_LINK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .unwrap_or_else(get_auth_token);
+        MagicLinks {
+            key: key.into_bytes(),
+            redeemed: HashMap::new(),
+        }
+    }
+
+    fn signature(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    pub fn issue(&self, email: &str) -> String {
+        let expires = (Utc::now() + Duration::minutes(MAGIC_LINK_TTL_MINS)).timestamp();
+        let payload = format!("{}.{}.{}", hex::encode(email), expires, random_hex(16));
+        let signature = hex::encode(self.signature(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    // Returns the email the link was issued for, at most once per link
+    pub fn redeem(&mut self, token: &str) -> Option<String> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        self.signature(payload)
+            .verify_slice(&hex::decode(signature).ok()?)
+            .ok()?;
+
+        let mut parts = payload.split('.');
+        let email = String::from_utf8(hex::decode(parts.next()?).ok()?).ok()?;
+        let expires = DateTime::from_timestamp(parts.next()?.parse().ok()?, 0)?;
+        let nonce = parts.next()?;
+
+        let now = Utc::now();
+        self.redeemed.retain(|_, until| *until > now);
+        if expires <= now || self.redeemed.contains_key(nonce) {
+            return None;
+        }
+        self.redeemed.insert(nonce.to_string(), expires);
+        Some(email)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LockoutEntry {
+    pub key: String,
+    pub failures: u32,
+    pub stale_sessions: u32,
+    pub last_failure: String,
+    pub locked_until: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct FailureRecord {
+    failures: u32,
+    // Unknown session-shaped tokens, counted apart against the address
+    stale_sessions: u32,
+    last_failure: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl FailureRecord {
+    // Failures past the threshold of either counter, if any
+    fn excess(&self) -> Option<u32> {
+        let failures = self.failures.checked_sub(LOCKOUT_THRESHOLD);
+        let stale = self.stale_sessions.checked_sub(STALE_SESSION_THRESHOLD);
+        failures.max(stale)
+    }
+}
+
+// Tracks failed authorization attempts per client IP and per presented
+// credential, locking out offenders with exponential backoff
+#[derive(Debug, Default)]
+pub struct LockoutTracker {
+    records: HashMap<String, FailureRecord>,
+}
+
+impl LockoutTracker {
+    // Returns when the lockout ends if any of the keys is currently locked
+    pub fn locked_until(&self, keys: &[String]) -> Option<DateTime<Utc>> {
+        let now = Utc::now();
+        keys.iter()
+            .filter_map(|key| self.records.get(key))
+            .filter_map(|record| record.locked_until)
+            .filter(|until| *until > now)
+            .max()
+    }
+
+    pub fn record_failure(&mut self, keys: &[String]) {
+        self.record_failure_at(keys, Utc::now());
+    }
+
+    pub fn record_failure_at(&mut self, keys: &[String], now: DateTime<Utc>) {
+        self.record_at(keys, now, false);
+    }
+
+    // An unknown session-shaped token counts fully against itself, and
+    // against the address under the higher stale session threshold
+    pub fn record_stale_session(&mut self, keys: &[String]) {
+        self.record_stale_session_at(keys, Utc::now());
+    }
+
+    pub fn record_stale_session_at(&mut self, keys: &[String], now: DateTime<Utc>) {
+        self.record_at(keys, now, true);
+    }
+
+    // Records lapse once their lockout could no longer be running, so
+    // addresses seen once are not kept forever
+    fn record_at(&mut self, keys: &[String], now: DateTime<Utc>, stale_session: bool) {
+        let stale = now - Duration::seconds(LOCKOUT_MAX_SECS);
+        self.records.retain(|_, record| record.last_failure > stale);
+        for key in keys {
+            let record = self.records.entry(key.clone()).or_default();
+            if stale_session && key.starts_with("ip:") {
+                record.stale_sessions += 1;
+            } else {
+                record.failures += 1;
+            }
+            record.last_failure = now;
+
+            if let Some(excess) = record.excess() {
+                let secs = (LOCKOUT_BASE_SECS << excess.min(16)).min(LOCKOUT_MAX_SECS);
+                record.locked_until = Some(now + Duration::seconds(secs));
+                warn!(
+                    target: "audit",
+                    "Locked out {} for {}s after {} failed attempts",
+                    key, secs, record.failures + record.stale_sessions
+                );
+            }
+        }
+    }
+
+    pub fn record_success(&mut self, keys: &[String]) {
+        for key in keys {
+            self.records.remove(key);
+        }
+    }
+
+    pub fn entries(&self) -> Vec<LockoutEntry> {
+        let mut entries: Vec<LockoutEntry> = self
+            .records
+            .iter()
+            .map(|(key, record)| LockoutEntry {
+                key: key.clone(),
+                failures: record.failures,
+                stale_sessions: record.stale_sessions,
+                last_failure: record.last_failure.to_rfc3339(),
+                locked_until: record.locked_until.map(|t| t.to_rfc3339()),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    }
+
+    pub fn clear(&mut self, key: &str) -> bool {
+        self.records.remove(key).is_some()
+    }
+
+    pub fn clear_all(&mut self) -> usize {
+        let count = self.records.len();
+        self.records.clear();
+        count
+    }
+}
+
+// Session tokens are 32 random bytes in hex. One that is unknown has almost
+// certainly expired or outlived a restart rather than been guessed.
+fn is_session_shaped(token: &str) -> bool {
+    token.len() == 64 && token.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// Compared as SHA-256 digests so the time taken reveals nothing about how
+// much of the admin token a guess got right
+fn is_admin_token(token: &str) -> bool {
+    Sha256::digest(token.as_bytes()) == Sha256::digest(get_auth_token().as_bytes())
+}
+
+// Lockout keys for a request: the client IP and a fingerprint of the
+// presented credential (raw tokens are never stored)
+pub(crate) fn lockout_keys(req: &HttpRequest, credential: Option<&str>) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let Some(ip) = request_ip(req) {
+        keys.push(format!("ip:{}", ip));
+    }
+    if let Some(credential) = credential.filter(|c| !c.is_empty()) {
+        let mut hasher = DefaultHasher::new();
+        credential.hash(&mut hasher);
+        keys.push(format!("credential:{:016x}", hasher.finish()));
+    }
+    keys
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("Unauthorized: missing or invalid token")]
+    Unauthorized,
+    #[error("Too many failed attempts, try again later")]
+    LockedOut { until: DateTime<Utc> },
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("A valid TOTP code is required for this operation")]
+    SecondFactorRequired,
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AuthError::LockedOut { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AuthError::SecondFactorRequired => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AuthError::Unauthorized => HttpResponse::Unauthorized().json(serde_json::json!({
+                "status": "error",
+                "message": self.to_string()
+            })),
+            AuthError::Forbidden(_) => HttpResponse::Forbidden().json(serde_json::json!({
+                "status": "error",
+                "message": self.to_string()
+            })),
+            AuthError::SecondFactorRequired => {
+                HttpResponse::Unauthorized().json(serde_json::json!({
+                    "status": "error",
+                    "message": self.to_string(),
+                    "totp_required": true
+                }))
+            }
+            AuthError::LockedOut { until } => {
+                let retry_after = (*until - Utc::now()).num_seconds().max(1);
+                HttpResponse::TooManyRequests()
+                    .insert_header(("Retry-After", retry_after.to_string()))
+                    .json(serde_json::json!({
+                        "status": "error",
+                        "message": self.to_string(),
+                        "retry_after_secs": retry_after
+                    }))
+            }
+        }
+    }
+}
+
+// Resolves the caller from the Authorization header: the admin token or a
+// TA session token, unless it was revoked. Failures count towards lockouts.
+fn authenticate_caller(
+    req: &HttpRequest,
+    lockouts: &Mutex<LockoutTracker>,
+    sessions: &Mutex<SessionStore>,
+    revoked: &Mutex<RevocationList>,
+) -> Result<Caller, AuthError> {
+    let auth_header = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok());
+    let keys = lockout_keys(req, auth_header);
+
+    let mut lockouts = lockouts.lock().unwrap();
+    if let Some(until) = lockouts.locked_until(&keys) {
+        return Err(AuthError::LockedOut { until });
+    }
+
+    let client = ClientInfo::of(req);
+    let caller = match auth_header {
+        Some(token) if revoked.lock().unwrap().is_revoked(token) => None,
+        Some(token) if is_admin_token(token) => {
+            sessions.lock().unwrap().record_admin_activity(client);
+            Some(Caller::Admin)
+        }
+        Some(token) => sessions
+            .lock()
+            .unwrap()
+            .lookup(token, client)
+            .map(Caller::Ta),
+        None => None,
+    };
+
+    match caller {
+        Some(caller) => {
+            lockouts.record_success(&keys);
+            Ok(caller)
+        }
+        None => {
+            // A few open tabs holding stale sessions after a restart do not
+            // lock out everyone behind the IP, many of them still do
+            if auth_header.is_some_and(is_session_shaped) {
+                lockouts.record_stale_session(&keys);
+            } else {
+                lockouts.record_failure(&keys);
+            }
+            warn!(
+                target: "audit",
+                "Rejected unauthorized request to {} {}",
+                req.method(),
+                req.path()
+            );
+            Err(AuthError::Unauthorized)
+        }
+    }
+}
+
+// Routes reachable without a token. Webhooks authenticate by signature.
+const PUBLIC_ROUTES: &[&str] = &[
+    "/login",
+    "/login/magic",
+    "/login/magic/verify",
+    "/callback",
+    "/register",
+    "/tas/invites/accept",
+    "/webhooks/github",
+];
+
+// Public for GET only. The student announcements view is authorized by the
+// signed link in its query, while POST /announcements is for admins. The
+// public stats are anonymized aggregates for the website, and /healthz is
+// polled by uptime checks.
+const PUBLIC_READS: &[&str] = &["/announcements", "/public/stats", "/healthz"];
+
+// Authenticates every request except the public routes and CORS preflights,
+// storing the resolved Caller in the request extensions for the extractors
+pub async fn require_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if req.method() == Method::OPTIONS
+        || PUBLIC_ROUTES.contains(&req.path())
+        || (req.method() == Method::GET && PUBLIC_READS.contains(&req.path()))
+    {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let (Some(lockouts), Some(sessions), Some(revoked)) = (
+        req.app_data::<web::Data<Mutex<LockoutTracker>>>().cloned(),
+        req.app_data::<web::Data<Mutex<SessionStore>>>().cloned(),
+        req.app_data::<web::Data<Mutex<RevocationList>>>().cloned(),
+    ) else {
+        return Err(ErrorInternalServerError("Auth state not configured"));
+    };
+
+    match authenticate_caller(req.request(), &lockouts, &sessions, &revoked) {
+        Ok(caller) => {
+            req.extensions_mut().insert(caller);
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        Err(e) => {
+            let response = e.error_response();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+// Extractor for any authenticated caller (admin or TA)
+#[derive(Debug, Clone)]
+pub struct Authenticated(pub Caller);
+
+impl FromRequest for Authenticated {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Caller>()
+                .cloned()
+                .map(Authenticated)
+                .ok_or(AuthError::Unauthorized),
+        )
+    }
+}
+
+// Extractor that only admits organizers holding the admin token
+#[derive(Debug, Clone, Copy)]
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(match req.extensions().get::<Caller>() {
+            Some(Caller::Admin) => Ok(Admin),
+            Some(Caller::Ta(_)) => Err(AuthError::Forbidden("admin access required".to_string())),
+            None => Err(AuthError::Unauthorized),
+        })
+    }
+}
+
+// Email sign-in only ever sends a one-time link; sessions are created when
+// the link is redeemed. Kept as an alias of /login/magic for older clients.
 #[post("/login")]
-pub async fn login(item: web::Json<TaLogin>) -> impl Responder {
-    info!("TA login attempt: {:?}", item.gmail);
-    if let Some(_ta) = TA::from_email(&item.gmail) {
-        info!("TA login success.");
-        HttpResponse::Ok().json(serde_json::json!({
-        "token": get_auth_token()
-        }))
+pub async fn login(
+    item: web::Json<TaLogin>,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+    magic_links: web::Data<Mutex<MagicLinks>>,
+    mailer: Option<web::Data<Mailer>>,
+    db: web::Data<dyn Storage>,
+    req: HttpRequest,
+) -> impl Responder {
+    send_login_link(&item.gmail, &lockouts, &magic_links, mailer, &db, &req).await
+}
+
+#[get("/admin/lockouts")]
+pub async fn get_lockouts(
+    _admin: Admin,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+) -> impl Responder {
+    let entries = lockouts.lock().unwrap().entries();
+    HttpResponse::Ok().json(entries)
+}
+
+#[delete("/admin/lockouts")]
+pub async fn clear_all_lockouts(
+    _admin: Admin,
+    dry_run: DryRun,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+) -> impl Responder {
+    if dry_run.is_set() {
+        return DryRun::preview(serde_json::json!({
+            "clear": lockouts.lock().unwrap().entries()
+        }));
+    }
+
+    let cleared = lockouts.lock().unwrap().clear_all();
+    info!(target: "audit", "Cleared {} lockout record(s)", cleared);
+    HttpResponse::Ok().json(serde_json::json!({ "cleared": cleared }))
+}
+
+#[delete("/admin/lockouts/{key}")]
+pub async fn clear_lockout(
+    _admin: Admin,
+    key: web::Path<String>,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+) -> impl Responder {
+    let key = key.into_inner();
+    if lockouts.lock().unwrap().clear(&key) {
+        info!(target: "audit", "Cleared lockout for {}", key);
+        HttpResponse::Ok().json(serde_json::json!({ "cleared": key }))
     } else {
-        info!("TA login failed: not authorized.");
-        HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Access denied"
+        HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No lockout record for key"
         }))
     }
 }
+
+#[get("/auth/sessions")]
+pub async fn get_sessions(
+    _admin: Admin,
+    sessions: web::Data<Mutex<SessionStore>>,
+) -> impl Responder {
+    let mut sessions = sessions.lock().unwrap();
+    HttpResponse::Ok().json(serde_json::json!({
+        "sessions": sessions.list(),
+        "admin": sessions.admin_activity
+    }))
+}
+
+#[delete("/auth/sessions/{id}")]
+pub async fn revoke_session(
+    _admin: Admin,
+    id: web::Path<String>,
+    sessions: web::Data<Mutex<SessionStore>>,
+) -> impl Responder {
+    let id = id.into_inner();
+    match sessions.lock().unwrap().revoke(&id) {
+        Some(ta) => {
+            warn!(target: "audit", "Revoked session {} of {}", id, ta.name());
+            HttpResponse::Ok().json(serde_json::json!({ "revoked": id }))
+        }
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No active session with that id"
+        })),
+    }
+}
+
+// Revokes the token the request was made with. Revoking the admin token
+// locks everyone out of admin access until AUTH_TOKEN is changed.
+#[post("/auth/logout")]
+pub async fn logout(
+    Authenticated(caller): Authenticated,
+    sessions: web::Data<Mutex<SessionStore>>,
+    revoked: web::Data<Mutex<RevocationList>>,
+    db: web::Data<dyn Storage>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let Some(token) = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+    else {
+        return Err(AuthError::Unauthorized.into());
+    };
+
+    let expires_at = sessions.lock().unwrap().end(token);
+    RevocationList::revoke(&revoked, &db, token, &caller.label(), expires_at).await?;
+
+    match caller {
+        Caller::Admin => warn!(target: "audit", "Admin token revoked via logout"),
+        Caller::Ta(ta) => info!(target: "audit", "{} logged out", ta.name()),
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "logged_out": true })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkToken {
+    pub token: String,
+}
+
+pub(crate) fn frontend_url() -> String {
+    env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:5173".to_string())
+}
+
+#[post("/login/magic")]
+pub async fn request_magic_link(
+    item: web::Json<TaLogin>,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+    magic_links: web::Data<Mutex<MagicLinks>>,
+    mailer: Option<web::Data<Mailer>>,
+    db: web::Data<dyn Storage>,
+    req: HttpRequest,
+) -> impl Responder {
+    send_login_link(&item.gmail, &lockouts, &magic_links, mailer, &db, &req).await
+}
+
+// Emails a one-time login link to a TA. Always answers 202 so the endpoint
+// does not reveal which addresses belong to TAs.
+async fn send_login_link(
+    email: &str,
+    lockouts: &Mutex<LockoutTracker>,
+    magic_links: &Mutex<MagicLinks>,
+    mailer: Option<web::Data<Mailer>>,
+    db: &web::Data<dyn Storage>,
+    req: &HttpRequest,
+) -> HttpResponse {
+    let Some(mailer) = mailer else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Email login is not configured"
+        }));
+    };
+
+    let keys = lockout_keys(req, None);
+    if let Some(until) = lockouts.lock().unwrap().locked_until(&keys) {
+        return AuthError::LockedOut { until }.error_response();
+    }
+
+    if let Some(ta) = TA::from_email(email) {
+        let token = magic_links.lock().unwrap().issue(email);
+        let link = format!("{}/?magic={}", frontend_url(), token);
+        let body = format!(
+            "Use this link to sign in to the admin panel:\n\n{}\n\nIt expires in {} minutes and can only be used once.",
+            link, MAGIC_LINK_TTL_MINS
+        );
+        if let Err(e) = mailer.send(email, "Your sign-in link", body).await {
+            warn!("Failed to send magic link: {}", e);
+        } else {
+            info!(target: "audit", "Sent magic login link to {}", email);
+            // Requested by the TA, so it is recorded as sent by them
+            record_sent(
+                db,
+                &ta.name(),
+                CommunicationKind::Email,
+                "Your sign-in link",
+                None,
+                &ta.name(),
+            )
+            .await;
+        }
+    } else {
+        lockouts.lock().unwrap().record_failure(&keys);
+        info!("Magic link requested for unknown email");
+    }
+
+    HttpResponse::Accepted().json(serde_json::json!({
+        "message": "If the address belongs to a TA, a sign-in link is on its way"
+    }))
+}
+
+#[post("/login/magic/verify")]
+pub async fn verify_magic_link(
+    item: web::Json<MagicLinkToken>,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+    sessions: web::Data<Mutex<SessionStore>>,
+    magic_links: web::Data<Mutex<MagicLinks>>,
+    db: web::Data<dyn Storage>,
+    req: HttpRequest,
+) -> impl Responder {
+    let keys = lockout_keys(&req, None);
+    if let Some(until) = lockouts.lock().unwrap().locked_until(&keys) {
+        return AuthError::LockedOut { until }.error_response();
+    }
+
+    let ta = magic_links
+        .lock()
+        .unwrap()
+        .redeem(&item.token)
+        .and_then(|email| TA::from_email(&email));
+    let ta = match ta {
+        Some(ta) if is_active(&db, &ta).await => Some(ta),
+        _ => None,
+    };
+    match ta {
+        Some(ta) => {
+            lockouts.lock().unwrap().record_success(&keys);
+            let token = sessions
+                .lock()
+                .unwrap()
+                .create(ta.clone(), ClientInfo::of(&req));
+            info!(target: "audit", "{} signed in with a magic link", ta.name());
+            HttpResponse::Ok().json(serde_json::json!({
+                "token": token,
+                "ta": ta.name()
+            }))
+        }
+        None => {
+            lockouts.lock().unwrap().record_failure(&keys);
+            AuthError::Unauthorized.error_response()
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::database::storage::{Storage, blocking, persist_batch};
+use crate::handlers::auth::{Admin, Caller};
+use crate::handlers::cohort_window::WeekWindow;
+use crate::handlers::jobs::Jobs;
+use crate::handlers::students::get_github_to_name_mapping;
+use crate::services::exercises::backfilled_attempts;
+use crate::services::scoring::{apply_exercise_result, exercise_result};
+use crate::utils::forge::ForgeProvider;
+use crate::utils::types::{AppError, Table};
+use actix_web::{HttpResponse, post, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+
+// Most weeks one backfill may walk
+const MAX_BACKFILL_WEEKS: i32 = 52;
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillQuery {
+    // "3" or an inclusive range like "1..6"
+    pub weeks: String,
+}
+
+// What a backfill found and stored for one week
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BackfillWeek {
+    pub week: i32,
+    pub repos: usize,
+    pub attempts_recorded: usize,
+    pub rows_filled: usize,
+    pub error: Option<String>,
+}
+
+pub fn parse_week_range(value: &str) -> Result<RangeInclusive<i32>, String> {
+    let invalid = || format!("weeks must be a week or a range like 1..6, not {:?}", value);
+    let (start, end) = match value.split_once("..") {
+        Some((start, end)) => (start, end.strip_prefix('=').unwrap_or(end)),
+        None => (value, value),
+    };
+    let start: i32 = start.trim().parse().map_err(|_| invalid())?;
+    let end: i32 = end.trim().parse().map_err(|_| invalid())?;
+    if start < 1 || end < start {
+        return Err(invalid());
+    }
+    if end - start >= MAX_BACKFILL_WEEKS {
+        return Err(format!(
+            "At most {} weeks can be backfilled at once",
+            MAX_BACKFILL_WEEKS
+        ));
+    }
+    Ok(start..=end)
+}
+
+// Records the submission history of one past week and fills in the exercise
+// columns of rows that have none yet. Grades already entered are kept.
+async fn backfill_week(
+    week: i32,
+    state: &web::Data<Mutex<Table>>,
+    forge: &web::Data<dyn ForgeProvider>,
+    db: &web::Data<dyn Storage>,
+) -> Result<BackfillWeek, AppError> {
+    let assignments = match forge.fetch_week_submissions(week).await {
+        Ok(assignments) => assignments,
+        Err(e) => {
+            warn!("Backfill of week {} could not list repos: {}", week, e);
+            return Ok(BackfillWeek {
+                week,
+                error: Some(e.to_string()),
+                ..BackfillWeek::default()
+            });
+        }
+    };
+
+    let attempts = backfilled_attempts(&assignments, week);
+    let attempts_recorded = blocking(db, move |db| db.record_exercise_attempts(&attempts)).await?;
+
+    let mut results = HashMap::new();
+    for assignment in assignments.iter().filter(|a| a.is_submitted()) {
+        if let Some(result) = exercise_result(assignment, week)
+            && let Some(name) = get_github_to_name_mapping(db, &assignment.github_username).await
+        {
+            results.insert(name, result);
+        }
+    }
+
+    let (filled, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        let mut filled: Vec<_> = state_table
+            .rows
+            .iter()
+            .filter(|row| row.week == week && row.exercise_submitted.is_none())
+            .filter_map(|row| {
+                let result = results.get(&row.name)?;
+                let mut row = row.clone();
+                apply_exercise_result(&mut row, result);
+                Some(row)
+            })
+            .collect();
+        let checkpoint = state_table.checkpoint(&filled);
+        for row in &mut filled {
+            state_table.insert_or_update(row)?;
+        }
+        (filled, state_table.take_history(), checkpoint)
+    }; // Lock released here
+    let rows_filled = filled.len();
+    if !filled.is_empty() {
+        persist_batch(
+            state,
+            db,
+            filled,
+            history,
+            Caller::Admin.label(),
+            checkpoint,
+        )
+        .await?;
+    }
+
+    Ok(BackfillWeek {
+        week,
+        repos: assignments.len(),
+        attempts_recorded,
+        rows_filled,
+        error: None,
+    })
+}
+
+// Walks past weeks' Classroom repos to record submission timestamps and
+// test results for weeks synced before that history was kept. Runs in the
+// background; progress and the per-week outcome are served by `GET /jobs/{id}`.
+#[post("/sync/backfill")]
+pub async fn backfill_submissions(
+    _admin: Admin,
+    window: WeekWindow,
+    query: web::Query<BackfillQuery>,
+    jobs: web::Data<Mutex<Jobs>>,
+    state: web::Data<Mutex<Table>>,
+    forge: web::Data<dyn ForgeProvider>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let weeks = parse_week_range(&query.weeks).map_err(actix_web::error::ErrorBadRequest)?;
+    window.check(weeks.clone())?;
+
+    let total = weeks.clone().count();
+    let Some(job) = jobs.lock().unwrap().start("backfill", total) else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "A backfill is already running"
+        })));
+    };
+    info!(
+        target: "audit",
+        "Backfill of weeks {}..={} started as job {}",
+        weeks.start(),
+        weeks.end(),
+        job.id
+    );
+
+    let id = job.id.clone();
+    actix_web::rt::spawn(async move {
+        let mut done = Vec::new();
+        let mut outcome = Ok(());
+        for (index, week) in weeks.enumerate() {
+            jobs.lock()
+                .unwrap()
+                .progress(&id, index, format!("Backfilling week {}", week));
+            match backfill_week(week, &state, &forge, &db).await {
+                Ok(week) => done.push(week),
+                Err(e) => {
+                    outcome = Err(format!("Week {}: {}", week, e));
+                    break;
+                }
+            }
+        }
+        match &outcome {
+            Ok(()) => info!("Backfill job {} finished", id),
+            Err(e) => warn!("Backfill job {} failed: {}", id, e),
+        }
+        jobs.lock()
+            .unwrap()
+            .finish(&id, outcome.map(|()| serde_json::json!({ "weeks": done })));
+    });
+
+    Ok(HttpResponse::Accepted().json(job))
+}
@@ -0,0 +1,117 @@
+use crate::database::storage::Storage;
+use crate::handlers::auth::{Admin, TA};
+use crate::handlers::dry_run::DryRun;
+use crate::handlers::two_factor::SecondFactor;
+use crate::handlers::week_locks::WeekLocks;
+use crate::utils::backup::{BackupReason, Backups};
+use crate::utils::types::{AppError, Table};
+use actix_web::{HttpResponse, get, post, web};
+use chrono::Utc;
+use log::info;
+use std::sync::Mutex;
+
+// Snapshots the database before a destructive change; the change should
+// not go ahead when this fails
+pub async fn backup_before(
+    backups: &web::Data<Backups>,
+    reason: BackupReason,
+) -> Result<(), AppError> {
+    let backups = backups.clone();
+    if let Some(backup) = web::block(move || backups.snapshot(reason)).await?? {
+        info!(target: "audit", "Database backed up to {}", backup.file);
+    }
+    Ok(())
+}
+
+// Backups on disk, newest first, with the policy they are kept under
+#[get("/admin/backups")]
+pub async fn get_backups(
+    _admin: Admin,
+    backups: web::Data<Backups>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list = backups.clone();
+    let list = web::block(move || list.list()).await??;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "policy": backups.policy(),
+        "backups": list,
+    })))
+}
+
+// Downloads a consistent copy of the live SQLite database for offline
+// analysis. The admin TOTP secret is left out of the copy.
+#[get("/admin/export/sqlite")]
+pub async fn export_sqlite(
+    _admin: Admin,
+    backups: web::Data<Backups>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let exporter = backups.clone();
+    let bytes = web::block(move || exporter.export()).await??;
+    let file = format!("classroom_{}.db", Utc::now().format("%Y%m%dT%H%M%SZ"));
+    info!(target: "audit", "Database exported as {} ({} bytes)", file, bytes.len());
+    Ok(HttpResponse::Ok()
+        .content_type("application/vnd.sqlite3")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file),
+        ))
+        .body(bytes))
+}
+
+// Replaces the live database with a backup, listed by file name in
+// `/admin/backups`, and reloads the in-memory table and week locks from it.
+// The table stays locked from the restore until both are reloaded, so no
+// write lands in between.
+#[post("/admin/restore/{backup_id}")]
+#[allow(clippy::too_many_arguments)]
+pub async fn restore_backup(
+    _admin: Admin,
+    _totp: SecondFactor,
+    dry_run: DryRun,
+    backup_id: web::Path<String>,
+    backups: web::Data<Backups>,
+    state: web::Data<Mutex<Table>>,
+    week_locks: web::Data<Mutex<WeekLocks>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let backup_id = backup_id.into_inner();
+    let list = backups.clone();
+    let Some(backup) = web::block(move || list.list())
+        .await??
+        .into_iter()
+        .find(|backup| backup.file == backup_id)
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No backup named {}", backup_id)
+        })));
+    };
+    if dry_run.is_set() {
+        return Ok(DryRun::preview(serde_json::json!({ "restore": backup })));
+    }
+
+    let (restorer, target) = (backups.clone(), backup.clone());
+    let (previous, rows) = web::block(move || -> Result<_, AppError> {
+        let mut state_table = state.lock().unwrap();
+        let previous = restorer.restore(&target)?;
+        *state_table = db.read_from_db()?;
+        *week_locks.lock().unwrap() = WeekLocks::load(db.get_ref())?;
+        // Onboarded TAs are kept from the live database, see KEPT_ON_RESTORE
+        for ta in db.read_onboarded_tas()? {
+            TA::register(&ta.email, &ta.name);
+        }
+        Ok((previous, state_table.rows.len()))
+    })
+    .await??;
+
+    info!(
+        target: "audit",
+        "Database restored from backup {} ({} rows), previous state saved as {}",
+        backup.file,
+        rows,
+        previous.file
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "restored": backup,
+        "previous": previous,
+        "rows": rows,
+    })))
+}
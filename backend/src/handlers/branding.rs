@@ -0,0 +1,63 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, Authenticated, Caller};
+use crate::utils::types::Branding;
+use actix_web::{HttpResponse, get, put, web};
+use log::info;
+
+// Inline images are kept in the row, so cap them to keep reads cheap
+const MAX_IMAGE_LEN: usize = 512 * 1024;
+
+fn valid_image(image: &Option<String>) -> bool {
+    match image.as_deref() {
+        None => true,
+        Some(image) => {
+            image.len() <= MAX_IMAGE_LEN
+                && (image.starts_with("https://") || image.starts_with("data:image/"))
+        }
+    }
+}
+
+// Falls back to the default branding until an admin has saved one
+#[get("/branding")]
+pub async fn get_branding(
+    _caller: Authenticated,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let branding = blocking(&db, |db| db.read_branding())
+        .await?
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok().json(branding))
+}
+
+#[put("/branding")]
+pub async fn update_branding(
+    _admin: Admin,
+    body: web::Json<Branding>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if body.program_name.trim().is_empty() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Program name is required",
+        ));
+    }
+    if !valid_image(&body.logo) || !valid_image(&body.signature) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Images must be https URLs or data:image URIs of at most 512 KiB"
+        })));
+    }
+
+    let branding = Branding {
+        program_name: body.program_name.trim().to_string(),
+        ..body.into_inner()
+    };
+    let branding = blocking(&db, move |db| {
+        db.store_branding(&branding, &Caller::Admin.label())
+    })
+    .await?;
+    info!(
+        target: "audit",
+        "Cohort branding updated, program name '{}'",
+        branding.program_name
+    );
+    Ok(HttpResponse::Ok().json(branding))
+}
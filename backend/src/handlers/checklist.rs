@@ -0,0 +1,96 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, Authenticated};
+use crate::utils::types::{ChecklistItem, Table, WeekTask};
+use actix_web::{HttpResponse, delete, get, put, web};
+use serde::Serialize;
+use std::sync::Mutex;
+
+#[derive(Debug, Serialize)]
+pub struct WeekChecklist {
+    pub week: i32,
+    pub items: Vec<ChecklistItem>,
+    pub pending: usize,
+}
+
+async fn week_checklist(
+    db: &web::Data<dyn Storage>,
+    week: i32,
+) -> Result<WeekChecklist, actix_web::Error> {
+    let items = blocking(db, move |db| db.read_week_checklist(week)).await?;
+    let pending = items.iter().filter(|i| i.completed_at.is_none()).count();
+    Ok(WeekChecklist {
+        week,
+        items,
+        pending,
+    })
+}
+
+fn parse_task(task: &str) -> Result<WeekTask, actix_web::Error> {
+    WeekTask::parse(task)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("Unknown checklist task: {}", task)))
+}
+
+// Weeks up to the latest one in the table that still have pending tasks,
+// for the dashboard
+#[get("/checklist")]
+pub async fn get_pending_checklists(
+    _caller: Authenticated,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let latest_week = {
+        let state_table = state.lock().unwrap();
+        state_table.rows.iter().map(|r| r.week).max().unwrap_or(0)
+    };
+
+    let mut weeks = Vec::new();
+    for week in 1..=latest_week {
+        let checklist = week_checklist(&db, week).await?;
+        if checklist.pending > 0 {
+            weeks.push(checklist);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(weeks))
+}
+
+#[get("/checklist/{week}")]
+pub async fn get_week_checklist(
+    _caller: Authenticated,
+    week: web::Path<i32>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let checklist = week_checklist(&db, week.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(checklist))
+}
+
+// Marks a task done by the caller, who is recorded as responsible for it
+#[put("/checklist/{week}/{task}")]
+pub async fn complete_checklist_task(
+    Authenticated(caller): Authenticated,
+    path: web::Path<(i32, String)>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (week, task) = path.into_inner();
+    let task = parse_task(&task)?;
+
+    let completed_by = caller.label();
+    blocking(&db, move |db| {
+        db.complete_week_task(week, task, &completed_by)
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(week_checklist(&db, week).await?))
+}
+
+#[delete("/checklist/{week}/{task}")]
+pub async fn reopen_checklist_task(
+    _admin: Admin,
+    path: web::Path<(i32, String)>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (week, task) = path.into_inner();
+    let task = parse_task(&task)?;
+
+    blocking(&db, move |db| db.reopen_week_task(week, task)).await?;
+    Ok(HttpResponse::Ok().json(week_checklist(&db, week).await?))
+}
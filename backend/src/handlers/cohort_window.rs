@@ -0,0 +1,144 @@
+use crate::handlers::auth::Caller;
+use crate::handlers::week_locks::WeekLocks;
+use crate::services::cohort_window::CohortWindow;
+use crate::utils::reload::Reloadable;
+use actix_web::dev::Payload;
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::StatusCode;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError, web};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::future::{Ready, ready};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default, Deserialize)]
+struct WindowQuery {
+    #[serde(default)]
+    override_window: bool,
+    #[serde(default)]
+    override_lock: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WindowError {
+    #[error("{0}")]
+    OutOfRange(String),
+    #[error("{0}. An admin can write it anyway with ?override_window=true")]
+    Outside(String),
+    #[error("Only admins may override the cohort window")]
+    OverrideForbidden,
+    #[error("Week {0} is locked. An admin can write it anyway with ?override_lock=true")]
+    Locked(i32),
+    #[error("Only admins may override a week lock")]
+    LockOverrideForbidden,
+}
+
+impl ResponseError for WindowError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WindowError::OutOfRange(_) => StatusCode::BAD_REQUEST,
+            WindowError::Outside(_) => StatusCode::CONFLICT,
+            WindowError::OverrideForbidden | WindowError::LockOverrideForbidden => {
+                StatusCode::FORBIDDEN
+            }
+            WindowError::Locked(_) => StatusCode::LOCKED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "status": "error",
+            "message": self.to_string(),
+            "outside_window": matches!(self, WindowError::Outside(_)),
+            "locked": matches!(self, WindowError::Locked(_))
+        }))
+    }
+}
+
+// The cohort window and locked weeks as of this request, with
+// `?override_window=true` and `?override_lock=true` when given. Handlers that
+// write rows check the weeks they touch through it.
+pub struct WeekWindow {
+    window: Arc<CohortWindow>,
+    override_window: bool,
+    locked: BTreeSet<i32>,
+    override_lock: bool,
+    caller: Option<Caller>,
+}
+
+impl WeekWindow {
+    pub fn overridden(&self) -> bool {
+        self.override_window && matches!(self.caller, Some(Caller::Admin))
+    }
+
+    // Turns away weeks past the cohort's last one, as a typo rather than a
+    // write to override
+    pub fn check_range(&self, week: i32) -> Result<(), WindowError> {
+        self.window
+            .check_range(week)
+            .map_err(WindowError::OutOfRange)
+    }
+
+    // When the week's exercise is due, known once the cohort's start date is
+    pub fn deadline(&self, week: i32) -> Option<DateTime<Utc>> {
+        self.window.week_deadline(week)
+    }
+
+    pub fn check(&self, weeks: impl IntoIterator<Item = i32>) -> Result<(), WindowError> {
+        if self.override_window && !self.overridden() {
+            return Err(WindowError::OverrideForbidden);
+        }
+        let admin = matches!(self.caller, Some(Caller::Admin));
+        if self.override_lock && !admin {
+            return Err(WindowError::LockOverrideForbidden);
+        }
+        let today = Utc::now().date_naive();
+        for week in weeks {
+            self.check_range(week)?;
+            if self.locked.contains(&week) {
+                if !self.override_lock {
+                    return Err(WindowError::Locked(week));
+                }
+                info!(target: "audit", "admin overrode the lock of week {}", week);
+            }
+            if let Err(reason) = self.window.check(week, today) {
+                if !self.overridden() {
+                    return Err(WindowError::Outside(reason));
+                }
+                info!(target: "audit", "admin overrode the cohort window: {}", reason);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for WeekWindow {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let window = req
+            .app_data::<web::Data<Reloadable<CohortWindow>>>()
+            .map(|window| window.get())
+            .unwrap_or_default();
+        let locked = req
+            .app_data::<web::Data<Mutex<WeekLocks>>>()
+            .map(|locks| locks.lock().unwrap().weeks())
+            .unwrap_or_default();
+        ready(
+            web::Query::<WindowQuery>::from_query(req.query_string())
+                .map(|q| WeekWindow {
+                    window,
+                    override_window: q.override_window,
+                    locked,
+                    override_lock: q.override_lock,
+                    caller: req.extensions().get::<Caller>().cloned(),
+                })
+                .map_err(|_| {
+                    ErrorBadRequest("override_window and override_lock must be true or false")
+                }),
+        )
+    }
+}
@@ -0,0 +1,105 @@
+use crate::database::bootstrap::{
+    apply_bootstrap, cohort_db_path, plan_bootstrap, valid_cohort_name,
+};
+use crate::database::retention::{RetentionPolicy, plan_retention, set_cohort_end};
+use crate::handlers::auth::Admin;
+use crate::handlers::dry_run::DryRun;
+use actix_web::{HttpResponse, get, post, put, web};
+use chrono::{NaiveDate, Utc};
+use log::info;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct BootstrapRequest {
+    pub template: String,
+    pub name: String,
+    // Without confirmation only the plan is returned, like `?dry_run=true`
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CohortEnd {
+    // Defaults to today
+    pub ended_at: Option<NaiveDate>,
+}
+
+#[post("/cohorts/bootstrap")]
+pub async fn bootstrap_cohort(
+    _admin: Admin,
+    dry_run: DryRun,
+    body: web::Json<BootstrapRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !valid_cohort_name(&body.template) || !valid_cohort_name(&body.name) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Cohort names may only contain letters, digits, '-' and '_'"
+        })));
+    }
+    if !cohort_db_path(&body.template).exists() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Template cohort '{}' has no database", body.template)
+        })));
+    }
+    if cohort_db_path(&body.name).exists() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Cohort '{}' already exists", body.name)
+        })));
+    }
+
+    let (template, name) = (body.template.clone(), body.name.clone());
+    let plan = web::block(move || plan_bootstrap(&template, &name)).await??;
+    if dry_run.is_set() || !body.confirm {
+        return Ok(DryRun::preview(plan));
+    }
+
+    let plan = web::block(move || apply_bootstrap(&plan).map(|_| plan)).await??;
+    info!(
+        target: "audit",
+        "Cohort {} bootstrapped from template {}",
+        body.name,
+        body.template
+    );
+    Ok(HttpResponse::Created().json(plan))
+}
+
+// Records when a cohort ended, which starts its retention clock
+#[put("/cohorts/{name}/end")]
+pub async fn set_cohort_end_date(
+    _admin: Admin,
+    name: web::Path<String>,
+    body: web::Json<CohortEnd>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = name.into_inner();
+    if !valid_cohort_name(&name) || !cohort_db_path(&name).exists() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Cohort '{}' has no database", name)
+        })));
+    }
+
+    let ended_at = body.ended_at.unwrap_or_else(|| Utc::now().date_naive());
+    let cohort = name.clone();
+    web::block(move || set_cohort_end(&cohort, ended_at)).await??;
+    info!(target: "audit", "Cohort {} marked as ended on {}", name, ended_at);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "cohort": name,
+        "ended_at": ended_at
+    })))
+}
+
+// What the scheduled retention task would remove if it ran today
+#[get("/retention/preview")]
+pub async fn preview_retention(
+    _admin: Admin,
+    policy: web::Data<RetentionPolicy>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let today = Utc::now().date_naive();
+    let retention = *policy.get_ref();
+    let report = web::block(move || plan_retention(&retention, today)).await??;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "policy": {
+            "anonymize_after_months": policy.anonymize_after_months,
+            "purge_events_after_months": policy.purge_events_after_months
+        },
+        "report": report
+    })))
+}
@@ -0,0 +1,84 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::Authenticated;
+use crate::utils::types::{CommunicationKind, Table};
+use actix_web::{HttpResponse, get, post, web};
+use log::warn;
+use serde::Deserialize;
+use std::sync::Mutex;
+
+#[derive(Debug, Deserialize)]
+pub struct NewCommunication {
+    pub kind: CommunicationKind,
+    pub subject: String,
+    pub note: Option<String>,
+}
+
+#[get("/communications/{name}")]
+pub async fn get_communications(
+    _caller: Authenticated,
+    name: web::Path<String>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = state.lock().unwrap().student_name(&name);
+    let communications = blocking(&db, move |db| db.read_communications(&name)).await?;
+    Ok(HttpResponse::Ok().json(communications))
+}
+
+#[post("/communications/{name}")]
+pub async fn add_communication(
+    Authenticated(caller): Authenticated,
+    name: web::Path<String>,
+    body: web::Json<NewCommunication>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = state.lock().unwrap().student_name(&name);
+    if body.subject.trim().is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Subject is required"));
+    }
+
+    let body = body.into_inner();
+    let label = caller.label();
+    let communication = blocking(&db, move |db| {
+        db.record_communication(
+            &name,
+            body.kind,
+            body.subject.trim(),
+            body.note.as_deref(),
+            &label,
+        )
+    })
+    .await?;
+    Ok(HttpResponse::Created().json(communication))
+}
+
+// Adds a message the panel sent itself to the recipient's history, next to
+// the messages recorded by hand. The message is already out, so failing to
+// record it is only logged.
+pub async fn record_sent(
+    db: &web::Data<dyn Storage>,
+    recipient: &str,
+    kind: CommunicationKind,
+    subject: &str,
+    note: Option<String>,
+    sent_by: &str,
+) {
+    let (name, subject, sent_by) = (
+        recipient.to_string(),
+        subject.to_string(),
+        sent_by.to_string(),
+    );
+    if let Err(e) = blocking(db, move |db| {
+        db.record_communication(&name, kind, &subject, note.as_deref(), &sent_by)
+    })
+    .await
+    {
+        warn!(
+            "Failed to record the {} to {}: {}",
+            kind.as_str(),
+            recipient,
+            e
+        );
+    }
+}
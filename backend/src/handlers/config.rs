@@ -0,0 +1,25 @@
+use crate::handlers::auth::Admin;
+use crate::utils::reload::LiveConfig;
+use actix_web::{HttpResponse, post, web};
+use log::{info, warn};
+
+// Applies changes to the .env file without a restart, keeping the table,
+// sessions and lockouts in memory. An invalid value is rejected and the
+// running config kept.
+#[post("/admin/config/reload")]
+pub async fn reload_config(_admin: Admin, config: web::Data<LiveConfig>) -> HttpResponse {
+    let config = config.clone();
+    match web::block(move || config.reload()).await {
+        Ok(Ok(applied)) => {
+            info!(target: "audit", "Config reloaded: {}", applied.join(", "));
+            HttpResponse::Ok().json(serde_json::json!({ "reloaded": applied }))
+        }
+        Ok(Err(e)) => {
+            warn!("Config reload rejected: {}", e);
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() }))
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use actix_web::dev::Payload;
+use actix_web::error::ErrorBadRequest;
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use std::future::{Ready, ready};
+
+#[derive(Debug, Default, Deserialize)]
+struct DryRunQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// `?dry_run=true` on destructive endpoints. Handlers compute their changes
+// exactly as they would when applying them, then return them through
+// `preview` instead of writing anything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DryRun(pub bool);
+
+impl DryRun {
+    pub fn is_set(&self) -> bool {
+        self.0
+    }
+
+    pub fn preview<T: Serialize>(changes: T) -> HttpResponse {
+        HttpResponse::Ok().json(serde_json::json!({
+            "dry_run": true,
+            "changes": changes
+        }))
+    }
+}
+
+impl FromRequest for DryRun {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            web::Query::<DryRunQuery>::from_query(req.query_string())
+                .map(|q| DryRun(q.dry_run))
+                .map_err(|_| ErrorBadRequest("dry_run must be true or false")),
+        )
+    }
+}
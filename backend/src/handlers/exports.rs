@@ -0,0 +1,95 @@
+use crate::handlers::auth::Authenticated;
+use crate::services::export::{ExportBody, ExportTable, FORMATS, exporter};
+use crate::services::paging::RowsQuery;
+use crate::services::weekly::rows_for_week;
+use crate::utils::types::Table;
+use actix_web::{HttpResponse, get, web};
+use log::info;
+use serde::Deserialize;
+use std::sync::Mutex;
+
+// Columns of an exported weekly row, in the order of the admin table
+const ROW_COLUMNS: &[&str] = &[
+    "name",
+    "group_id",
+    "ta",
+    "attendance",
+    "fa",
+    "fb",
+    "fc",
+    "fd",
+    "bonus_attempt",
+    "bonus_answer_quality",
+    "bonus_follow_up",
+    "exercise_submitted",
+    "exercise_test_passing",
+    "exercise_good_documentation",
+    "exercise_good_structure",
+    "total",
+    "notes",
+    "mail",
+    "week",
+];
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: String,
+    // Comma separated, all columns when empty
+    #[serde(default)]
+    pub columns: String,
+}
+
+// `table` as a download named `<name>.<extension>`, in `format` and narrowed
+// to `columns`
+pub fn export_response(
+    table: ExportTable,
+    format: &str,
+    columns: &str,
+    name: &str,
+) -> Result<HttpResponse, actix_web::Error> {
+    let exporter = exporter(format).ok_or_else(|| {
+        actix_web::error::ErrorBadRequest(format!(
+            "format must be one of {}, not {:?}",
+            FORMATS.join(", "),
+            format
+        ))
+    })?;
+    let table = table
+        .select(columns)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    info!("Exporting {} rows as {}", table.rows.len(), format);
+    Ok(HttpResponse::Ok()
+        .content_type(exporter.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.{}\"", name, exporter.extension()),
+        ))
+        .body(ExportBody::new(exporter, table)))
+}
+
+// A week's rows as a file, filtered and sorted like `GET /weekly_data/{week}`
+#[get("/weekly_data/{week}/export")]
+pub async fn export_weekly_data(
+    _caller: Authenticated,
+    week: web::Path<i32>,
+    query: web::Query<ExportQuery>,
+    view: web::Query<RowsQuery>,
+    state: web::Data<Mutex<Table>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    view.validate().map_err(actix_web::error::ErrorBadRequest)?;
+    let rows = {
+        let state_table = state.lock().unwrap();
+        rows_for_week(&state_table.rows, week)
+    }; // Lock released here
+    let (rows, _) = view
+        .apply(&rows)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let table = ExportTable::from_items(ROW_COLUMNS, &rows)?;
+    export_response(
+        table,
+        &query.format,
+        &query.columns,
+        &format!("week_{}", week),
+    )
+}
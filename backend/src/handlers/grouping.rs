@@ -0,0 +1,350 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, Authenticated, Caller};
+use crate::handlers::dry_run::DryRun;
+use crate::handlers::tas::rotation;
+use crate::services::constraints::apply_constraints;
+use crate::services::group_threads::{ThreadPlan, default_agenda, plan_group_threads};
+use crate::services::grouping::assign_groups;
+use crate::services::languages::{language_report, normalize_languages};
+use crate::services::weekly::rows_for_week;
+use crate::utils::discord_threads::{
+    add_thread_member, create_private_thread, group_thread_channel, post_thread_message,
+    remove_thread_member,
+};
+use crate::utils::types::{ConstraintKind, GroupThread, Table};
+use actix_web::{HttpResponse, delete, get, post, put, web};
+use chrono::Utc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+#[derive(Debug, Deserialize)]
+pub struct NewConstraint {
+    pub kind: ConstraintKind,
+    pub first: String,
+    pub second: String,
+}
+
+#[get("/grouping/constraints")]
+pub async fn get_grouping_constraints(
+    _caller: Authenticated,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let constraints = blocking(&db, |db| db.read_grouping_constraints()).await?;
+    Ok(HttpResponse::Ok().json(constraints))
+}
+
+// Adds a constraint, replacing any existing one for the same pair
+#[post("/grouping/constraints")]
+pub async fn add_grouping_constraint(
+    _admin: Admin,
+    body: web::Json<NewConstraint>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut pair = [body.first.trim(), body.second.trim()];
+    if pair[0].is_empty() || pair[0] == pair[1] {
+        return Err(actix_web::error::ErrorBadRequest(
+            "A constraint needs two different students",
+        ));
+    }
+
+    let unknown: Vec<&str> = {
+        let state_table = state.lock().unwrap();
+        pair.iter()
+            .copied()
+            .filter(|name| !state_table.rows.iter().any(|r| r.name == *name))
+            .collect()
+    };
+    if !unknown.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown student(s): {}", unknown.join(", "))
+        })));
+    }
+
+    pair.sort();
+    let (kind, first, second) = (body.kind, pair[0].to_string(), pair[1].to_string());
+    let constraint = blocking(&db, move |db| {
+        db.store_grouping_constraint(kind, &first, &second, &Caller::Admin.label())
+    })
+    .await?;
+    info!(
+        target: "audit",
+        "Grouping constraint added: {} {} {}",
+        constraint.first,
+        constraint.kind.as_str(),
+        constraint.second
+    );
+    Ok(HttpResponse::Created().json(constraint))
+}
+
+#[delete("/grouping/constraints/{id}")]
+pub async fn remove_grouping_constraint(
+    _admin: Admin,
+    id: web::Path<i64>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    if !blocking(&db, move |db| db.delete_grouping_constraint(id)).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No grouping constraint with id {}", id)
+        })));
+    }
+    info!(target: "audit", "Grouping constraint {} removed", id);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Regroups `week` from the previous week's attendance the way the weekly
+// sync does and reports the constraints that cannot be honored
+#[get("/grouping/constraints/report/{week}")]
+pub async fn get_constraint_report(
+    _caller: Authenticated,
+    week: web::Path<i32>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    if week < 1 {
+        return Err(actix_web::error::ErrorBadRequest("Week must be at least 1"));
+    }
+    let constraints = blocking(&db, |db| db.read_grouping_constraints()).await?;
+    let tas = rotation(&db).await?;
+    let previous = rows_for_week(&state.lock().unwrap().rows, week - 1);
+    let mut grouped = assign_groups(previous, week, &tas);
+    let violations = apply_constraints(&mut grouped, &constraints);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "week": week,
+        "constraints": constraints.len(),
+        "violations": violations
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpokenLanguagesUpdate {
+    pub languages: Vec<String>,
+}
+
+#[get("/grouping/languages")]
+pub async fn get_spoken_languages(
+    _caller: Authenticated,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let spoken = blocking(&db, |db| db.read_spoken_languages()).await?;
+    Ok(HttpResponse::Ok().json(spoken))
+}
+
+// Replaces the languages a student speaks; an empty list clears them
+#[put("/grouping/languages/{name}")]
+pub async fn set_spoken_languages(
+    _admin: Admin,
+    name: web::Path<String>,
+    body: web::Json<SpokenLanguagesUpdate>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = state.lock().unwrap().student_name(&name.into_inner());
+    let known = state.lock().unwrap().rows.iter().any(|r| r.name == name);
+    if !known {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown student: {}", name)
+        })));
+    }
+
+    let languages = normalize_languages(&body.languages);
+    let (student, stored) = (name.clone(), languages.clone());
+    blocking(&db, move |db| {
+        db.replace_spoken_languages(&student, &stored)
+    })
+    .await?;
+    info!(
+        target: "audit",
+        "Spoken languages of {} set to [{}]",
+        name,
+        languages.iter().cloned().collect::<Vec<_>>().join(", ")
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "name": name,
+        "languages": languages
+    })))
+}
+
+// Language makeup of the week's generated groups, students who share no
+// language with the rest of their group, and together constraints that would
+// give them a partner. Suggestions can be added through /grouping/constraints.
+#[get("/grouping/languages/report/{week}")]
+pub async fn get_language_report(
+    _caller: Authenticated,
+    week: web::Path<i32>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    if week < 1 {
+        return Err(actix_web::error::ErrorBadRequest("Week must be at least 1"));
+    }
+    let spoken = blocking(&db, |db| db.read_spoken_languages()).await?;
+    let constraints = blocking(&db, |db| db.read_grouping_constraints()).await?;
+    let rows = rows_for_week(&state.lock().unwrap().rows, week);
+    let report = language_report(&rows, week, &spoken, &constraints);
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PublishGroups {
+    // First message of each new thread; defaults to the group's roster
+    pub agenda: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublishedThread {
+    pub group_id: String,
+    pub thread_id: Option<String>,
+    pub created: bool,
+    pub added: usize,
+    pub removed: usize,
+    pub unmapped: Vec<String>,
+    pub error: Option<String>,
+}
+
+// Creates the group's thread if it has none yet and brings its members in
+// line with the plan. The thread is stored with the members actually added,
+// also when a step fails, so the next publish picks up where this one stopped.
+async fn sync_thread(
+    db: &web::Data<dyn Storage>,
+    week: i32,
+    channel_id: &str,
+    agenda: &str,
+    plan: &ThreadPlan,
+    published: &mut PublishedThread,
+) -> Result<(), String> {
+    let (thread_id, mut members) = match &plan.thread_id {
+        Some(id) => {
+            let mut members: BTreeSet<String> = plan.members.iter().cloned().collect();
+            plan.add.iter().for_each(|id| {
+                members.remove(id);
+            });
+            members.extend(plan.remove.iter().cloned());
+            (id.clone(), members)
+        }
+        None => {
+            let name = format!("Week {} - {}", week, plan.group_id);
+            let id = create_private_thread(channel_id, &name)
+                .await
+                .map_err(|e| format!("Failed to create thread: {}", e))?;
+            published.created = true;
+            (id, BTreeSet::new())
+        }
+    };
+    published.thread_id = Some(thread_id.clone());
+
+    let mut result = Ok(());
+    for id in &plan.add {
+        if let Err(e) = add_thread_member(&thread_id, id).await {
+            result = Err(format!("Failed to add {}: {}", id, e));
+            break;
+        }
+        members.insert(id.clone());
+        published.added += 1;
+    }
+    if result.is_ok() {
+        for id in &plan.remove {
+            if let Err(e) = remove_thread_member(&thread_id, id).await {
+                result = Err(format!("Failed to remove {}: {}", id, e));
+                break;
+            }
+            members.remove(id);
+            published.removed += 1;
+        }
+    }
+    // Posted once members are in so they get notified
+    if result.is_ok()
+        && published.created
+        && let Err(e) = post_thread_message(&thread_id, agenda).await
+    {
+        result = Err(format!("Failed to post agenda: {}", e));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let thread = GroupThread {
+        week,
+        group_id: plan.group_id.clone(),
+        thread_id,
+        members: members.into_iter().collect(),
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    blocking(db, move |db| db.store_group_thread(&thread))
+        .await
+        .map_err(|e| format!("Failed to record thread: {}", e))?;
+    result
+}
+
+// Creates a private Discord thread per group of the week with its members
+// and TA, posting the agenda in new threads. Publishing again after groups
+// were regenerated reuses the threads and only adds or removes members.
+#[post("/grouping/{week}/publish")]
+pub async fn publish_groups(
+    _admin: Admin,
+    dry_run: DryRun,
+    week: web::Path<i32>,
+    body: Option<web::Json<PublishGroups>>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    let rows = rows_for_week(&state.lock().unwrap().rows, week);
+    if rows.iter().all(|row| row.group_id.trim().is_empty()) {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Week {} has no groups", week)
+        })));
+    }
+
+    let existing = blocking(&db, move |db| db.read_group_threads(week)).await?;
+    let handles = blocking(&db, |db| db.read_discord_handles()).await?;
+    let plans = plan_group_threads(&rows, &handles, &existing);
+    if dry_run.is_set() {
+        return Ok(DryRun::preview(serde_json::json!({
+            "week": week,
+            "threads": plans
+        })));
+    }
+    let Some(channel_id) = group_thread_channel() else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "DISCORD_GROUP_CHANNEL_ID is not configured"
+        })));
+    };
+
+    let agenda = body
+        .and_then(|body| body.into_inner().agenda)
+        .filter(|agenda| !agenda.trim().is_empty());
+    let mut published = Vec::with_capacity(plans.len());
+    for plan in &plans {
+        let mut thread = PublishedThread {
+            group_id: plan.group_id.clone(),
+            thread_id: plan.thread_id.clone(),
+            created: false,
+            added: 0,
+            removed: 0,
+            unmapped: plan.unmapped.clone(),
+            error: None,
+        };
+        let agenda = agenda.clone().unwrap_or_else(|| default_agenda(week, plan));
+        if let Err(e) = sync_thread(&db, week, &channel_id, &agenda, plan, &mut thread).await {
+            thread.error = Some(e);
+        }
+        published.push(thread);
+    }
+
+    info!(
+        target: "audit",
+        "Groups of week {} published to Discord: {} thread(s) created, {} failed",
+        week,
+        published.iter().filter(|t| t.created).count(),
+        published.iter().filter(|t| t.error.is_some()).count()
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "week": week,
+        "threads": published
+    })))
+}
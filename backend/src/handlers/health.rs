@@ -0,0 +1,92 @@
+use crate::database::storage::Storage;
+use crate::utils::backup::BackupInfo;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, get, web};
+use chrono::Utc;
+use log::warn;
+use serde::Serialize;
+
+// Writes still accepted while degraded, so organizers can sign in to read
+const WRITES_WHILE_DEGRADED: &[&str] = &["/login", "/login/magic", "/login/magic/verify"];
+
+// Why the server is serving from memory instead of its database file
+#[derive(Debug, Clone, Serialize)]
+pub struct Degraded {
+    pub reason: String,
+    pub since: String,
+    // The backup being served, or none for an empty cohort
+    pub serving: Option<BackupInfo>,
+}
+
+// Whether the server opened its database at startup. A degraded server stays
+// read-only until it is restarted with the database available.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    degraded: Option<Degraded>,
+}
+
+impl Health {
+    pub fn degraded(reason: String, serving: Option<BackupInfo>) -> Self {
+        Health {
+            degraded: Some(Degraded {
+                reason,
+                since: Utc::now().to_rfc3339(),
+                serving,
+            }),
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.is_some()
+    }
+}
+
+// 200 while the database is in use, 503 while degraded, so load balancers
+// and uptime checks notice
+#[get("/healthz")]
+pub async fn healthz(health: web::Data<Health>, db: web::Data<dyn Storage>) -> HttpResponse {
+    match &health.degraded {
+        None => HttpResponse::Ok().json(serde_json::json!({
+            "status": "ok",
+            "storage": db.name()
+        })),
+        Some(degraded) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "status": "degraded",
+            "storage": db.name(),
+            "read_only": true,
+            "reason": degraded.reason,
+            "since": degraded.since,
+            "serving": degraded.serving
+        })),
+    }
+}
+
+// Turns away writes while degraded, since they would only reach the copy in
+// memory and be lost on restart
+pub async fn reject_writes_when_degraded(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let degraded = req
+        .app_data::<web::Data<Health>>()
+        .is_some_and(|health| health.is_degraded());
+    let read = [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method());
+
+    if degraded && !read && !WRITES_WHILE_DEGRADED.contains(&req.path()) {
+        warn!(
+            "Rejected {} {} while the database is unavailable",
+            req.method(),
+            req.path()
+        );
+        let response = HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "status": "error",
+            "message": "The database is unavailable, the server is read-only until it is restarted"
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
@@ -0,0 +1,189 @@
+use crate::database::storage::{Storage, blocking};
+use crate::utils::types::IdempotentResponse;
+use actix_web::dev::Payload;
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::StatusCode;
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, web};
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::future::{Ready, ready};
+use std::sync::{LazyLock, Mutex};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+// Set on a response that was replayed instead of applied again
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+// How long a key is remembered; a retry later than this is applied again
+const KEY_LIFETIME_HOURS: i64 = 24;
+const MAX_KEY_LENGTH: usize = 255;
+
+// Keys of requests being applied, with their caller. A retry arriving while
+// the first try is still applied finds its key here before any response is
+// recorded.
+static IN_FLIGHT: LazyLock<Mutex<HashSet<(String, String)>>> = LazyLock::new(Default::default);
+
+// A client-chosen key from the `Idempotency-Key` header, so a write retried
+// after a timeout is answered with the first response instead of applied
+// twice. Keys are per caller.
+#[derive(Debug, Default)]
+pub struct IdempotencyKey {
+    key: Option<String>,
+    // Caller and key held in IN_FLIGHT until the request is answered
+    reserved: Option<(String, String)>,
+}
+
+impl IdempotencyKey {
+    // The recorded response if this key was already used for the same
+    // request, an error if it was used for another one or is still being
+    // applied, and none otherwise. With none the key is reserved until this
+    // request is answered.
+    pub async fn replay(
+        &mut self,
+        db: &web::Data<dyn Storage>,
+        actor: &str,
+        fingerprint: &str,
+    ) -> Result<Option<HttpResponse>, Error> {
+        let Some(key) = self.key.clone() else {
+            return Ok(None);
+        };
+        // Reserved before the lookup, so a first try that finishes in
+        // between is found recorded
+        let reservation = (actor.to_string(), key.clone());
+        if !IN_FLIGHT.lock().unwrap().insert(reservation.clone()) {
+            warn!(
+                "{} retried Idempotency-Key {:?} while it was being applied",
+                actor, key
+            );
+            return Ok(Some(HttpResponse::Conflict().json(serde_json::json!({
+                "status": "error",
+                "message": "A request with this Idempotency-Key is still being applied, retry later"
+            }))));
+        }
+        self.reserved = Some(reservation);
+
+        let lookup_actor = actor.to_string();
+        let Some(recorded) = blocking(db, move |db| {
+            db.read_idempotent_response(&key, &lookup_actor)
+        })
+        .await?
+        else {
+            return Ok(None);
+        };
+        let expired = chrono::DateTime::parse_from_rfc3339(&recorded.created_at)
+            .map(|at| at < Utc::now() - Duration::hours(KEY_LIFETIME_HOURS))
+            .unwrap_or(true);
+        if expired {
+            return Ok(None);
+        }
+        self.release();
+        if recorded.fingerprint != fingerprint {
+            warn!(
+                "{} reused Idempotency-Key {:?} for a different request",
+                actor, recorded.key
+            );
+            return Ok(Some(HttpResponse::UnprocessableEntity().json(
+                serde_json::json!({
+                    "status": "error",
+                    "message": "This Idempotency-Key was already used for a different request"
+                }),
+            )));
+        }
+
+        info!(
+            "Replaying response for Idempotency-Key {:?} from {}",
+            recorded.key, actor
+        );
+        let status = StatusCode::from_u16(recorded.status).unwrap_or(StatusCode::OK);
+        Ok(Some(
+            HttpResponse::build(status)
+                .content_type("application/json")
+                .insert_header((REPLAYED_HEADER, "true"))
+                .body(recorded.body),
+        ))
+    }
+
+    // Records the response to the request, for replays of this key. A
+    // failure is only logged, as the write itself already went through.
+    pub async fn remember(
+        &self,
+        db: &web::Data<dyn Storage>,
+        actor: &str,
+        fingerprint: String,
+        status: StatusCode,
+        body: &serde_json::Value,
+    ) {
+        let Some(key) = self.key.clone() else {
+            return;
+        };
+        let now = Utc::now();
+        let response = IdempotentResponse {
+            key,
+            actor: actor.to_string(),
+            fingerprint,
+            status: status.as_u16(),
+            body: body.to_string(),
+            created_at: now.to_rfc3339(),
+        };
+        let prune_before = now - Duration::hours(KEY_LIFETIME_HOURS);
+        if let Err(e) = blocking(db, move |db| {
+            db.store_idempotent_response(&response, prune_before)
+        })
+        .await
+        {
+            warn!(
+                "Failed to record Idempotency-Key response for {}: {}",
+                actor, e
+            );
+        }
+    }
+
+    fn release(&mut self) {
+        if let Some(reservation) = self.reserved.take() {
+            IN_FLIGHT.lock().unwrap().remove(&reservation);
+        }
+    }
+}
+
+// Answered, or failed before recording a response
+impl Drop for IdempotencyKey {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl FromRequest for IdempotencyKey {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(value) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+            return ready(Ok(IdempotencyKey::default()));
+        };
+        let key = value
+            .to_str()
+            .ok()
+            .map(str::trim)
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH);
+        ready(
+            key.map(|key| IdempotencyKey {
+                key: Some(key.to_string()),
+                reserved: None,
+            })
+            .ok_or_else(|| {
+                ErrorBadRequest(format!(
+                    "Idempotency-Key must be 1 to {} visible ASCII characters",
+                    MAX_KEY_LENGTH
+                ))
+            }),
+        )
+    }
+}
+
+// Hash of a request, to tell a retry of it from another request sent under
+// the same key
+pub fn fingerprint<T: Serialize>(request: &T) -> String {
+    let bytes = serde_json::to_vec(request).unwrap_or_default();
+    hex::encode(Sha256::digest(bytes))
+}
@@ -0,0 +1,35 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::Admin;
+use crate::services::integrity::integrity_report;
+use crate::utils::types::Table;
+use actix_web::{HttpResponse, get, web};
+use log::{info, warn};
+use std::sync::Mutex;
+
+// Read-only scan for rows without a participant, duplicate (name, week)
+// rows, enrolled students missing from generated weeks, and totals that do
+// not match their component scores
+#[get("/admin/integrity")]
+pub async fn get_integrity_report(
+    _admin: Admin,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let participants = blocking(&db, |db| db.read_participant_names()).await?;
+    let duplicates = blocking(&db, |db| db.read_duplicate_rows()).await?;
+    let rows = state.lock().unwrap().rows.clone();
+
+    let report = integrity_report(&rows, &participants, duplicates);
+    if report.problems > 0 {
+        warn!(
+            "Integrity check found {} problem(s) in {} row(s)",
+            report.problems, report.rows
+        );
+    } else {
+        info!(
+            "Integrity check found no problems in {} row(s)",
+            report.rows
+        );
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,135 @@
+use crate::handlers::auth::{Admin, random_hex};
+use actix_web::{HttpResponse, get, web};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// Finished jobs kept for `GET /jobs`, oldest dropped first
+const FINISHED_KEPT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+// A long-running task started by a request and finished in the background
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub kind: String,
+    pub state: JobState,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    // Steps done out of the total, e.g. weeks backfilled
+    pub done: usize,
+    pub total: usize,
+    // What the job is working on, or why it failed
+    pub message: Option<String>,
+    pub result: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: VecDeque<Job>,
+}
+
+impl Jobs {
+    // Registers a job of `total` steps, unless one of the same kind is
+    // still running
+    pub fn start(&mut self, kind: &str, total: usize) -> Option<Job> {
+        if self
+            .jobs
+            .iter()
+            .any(|job| job.kind == kind && job.state == JobState::Running)
+        {
+            return None;
+        }
+        let job = Job {
+            id: random_hex(8),
+            kind: kind.to_string(),
+            state: JobState::Running,
+            started_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+            done: 0,
+            total,
+            message: None,
+            result: None,
+        };
+        self.jobs.push_back(job.clone());
+        Some(job)
+    }
+
+    pub fn progress(&mut self, id: &str, done: usize, message: String) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.done = done;
+            job.message = Some(message);
+        }
+    }
+
+    pub fn finish(&mut self, id: &str, outcome: Result<serde_json::Value, String>) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.finished_at = Some(Utc::now().to_rfc3339());
+            match outcome {
+                Ok(result) => {
+                    job.state = JobState::Succeeded;
+                    job.done = job.total;
+                    job.message = None;
+                    job.result = Some(result);
+                }
+                Err(message) => {
+                    job.state = JobState::Failed;
+                    job.message = Some(message);
+                }
+            }
+        }
+        while self
+            .jobs
+            .iter()
+            .filter(|job| job.state != JobState::Running)
+            .count()
+            > FINISHED_KEPT
+        {
+            let Some(oldest) = self
+                .jobs
+                .iter()
+                .position(|job| job.state != JobState::Running)
+            else {
+                break;
+            };
+            self.jobs.remove(oldest);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    // Most recently started first
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.iter().rev().cloned().collect()
+    }
+}
+
+#[get("/jobs")]
+pub async fn get_jobs(_admin: Admin, jobs: web::Data<Mutex<Jobs>>) -> HttpResponse {
+    let jobs = jobs.lock().unwrap().list();
+    HttpResponse::Ok().json(serde_json::json!({ "jobs": jobs }))
+}
+
+#[get("/jobs/{id}")]
+pub async fn get_job(
+    _admin: Admin,
+    id: web::Path<String>,
+    jobs: web::Data<Mutex<Jobs>>,
+) -> HttpResponse {
+    match jobs.lock().unwrap().get(&id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No job with that id"
+        })),
+    }
+}
@@ -0,0 +1,71 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::Admin;
+use crate::utils::types::MaintenanceReport;
+use actix_web::{HttpResponse, get, post, web};
+use log::{info, warn};
+use serde::Serialize;
+use std::sync::Mutex;
+
+// The maintenance run in progress, if any, and the outcome of the last one
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DbMaintenance {
+    pub running: bool,
+    pub last: Option<MaintenanceReport>,
+    pub last_error: Option<String>,
+}
+
+// Starts a VACUUM and ANALYZE in the background, since rewriting a large
+// file can outlast the request. The space reclaimed is reported by
+// `GET /admin/db/maintenance` once it finishes.
+#[post("/admin/db/maintenance")]
+pub async fn start_db_maintenance(
+    _admin: Admin,
+    db: web::Data<dyn Storage>,
+    maintenance: web::Data<Mutex<DbMaintenance>>,
+) -> HttpResponse {
+    {
+        let mut status = maintenance.lock().unwrap();
+        if status.running {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Database maintenance is already running"
+            }));
+        }
+        status.running = true;
+    } // Lock released here
+
+    info!(target: "audit", "Database maintenance started on {}", db.name());
+    let status = maintenance.clone();
+    actix_web::rt::spawn(async move {
+        let outcome = blocking(&db, |db| db.vacuum_and_analyze()).await;
+        let mut status = status.lock().unwrap();
+        status.running = false;
+        match outcome {
+            Ok(report) => {
+                info!(
+                    target: "audit",
+                    "Database maintenance reclaimed {} bytes ({} -> {})",
+                    report.reclaimed_bytes,
+                    report.size_before,
+                    report.size_after
+                );
+                status.last = Some(report);
+                status.last_error = None;
+            }
+            Err(e) => {
+                warn!("Database maintenance failed: {}", e);
+                status.last_error = Some(e.to_string());
+            }
+        }
+    });
+
+    HttpResponse::Accepted().json(serde_json::json!({ "status": "started" }))
+}
+
+#[get("/admin/db/maintenance")]
+pub async fn get_db_maintenance(
+    _admin: Admin,
+    maintenance: web::Data<Mutex<DbMaintenance>>,
+) -> HttpResponse {
+    let status = maintenance.lock().unwrap().clone();
+    HttpResponse::Ok().json(status)
+}
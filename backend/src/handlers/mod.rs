@@ -1,2 +1,33 @@
+pub mod announcements;
+pub mod attendance;
+pub mod attention;
 pub mod auth;
+pub mod backfill;
+pub mod backups;
+pub mod branding;
+pub mod checklist;
+pub mod cohort_window;
+pub mod cohorts;
+pub mod communications;
+pub mod config;
+pub mod dry_run;
+pub mod exports;
+pub mod grouping;
+pub mod health;
+pub mod idempotency;
+pub mod integrity;
+pub mod jobs;
+pub mod maintenance;
+pub mod outbox;
+pub mod periodic_sync;
+pub mod public_stats;
+pub mod reviews;
+pub mod schema;
 pub mod students;
+pub mod sync;
+pub mod tas;
+pub mod two_factor;
+pub mod versions;
+pub mod webhooks;
+pub mod week_locks;
+pub mod weeks;
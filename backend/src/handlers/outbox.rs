@@ -0,0 +1,14 @@
+use crate::handlers::auth::Admin;
+use crate::utils::outbox::{OutboxMessage, outbox};
+use actix_web::{HttpResponse, get};
+
+// Notifications captured instead of delivered, newest first. Empty unless
+// NOTIFICATION_SINK=outbox.
+#[get("/admin/outbox")]
+pub async fn get_outbox(_admin: Admin) -> HttpResponse {
+    let messages: Vec<OutboxMessage> = outbox().map(|o| o.messages()).unwrap_or_default();
+    HttpResponse::Ok().json(serde_json::json!({
+        "enabled": outbox().is_some(),
+        "messages": messages,
+    }))
+}
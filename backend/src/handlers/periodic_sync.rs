@@ -0,0 +1,169 @@
+//! Background sync of the running week's submissions.
+//!
+//! Without it exercise columns only change when a week is generated or a
+//! webhook arrives. Every `SYNC_INTERVAL_MINS` the current week's submissions
+//! are fetched and written into the rows the week already has, the way a
+//! backfill fills past weeks. Students are never regrouped and no rows are
+//! added; locked weeks and weeks being generated are left alone. Off unless
+//! `SYNC_INTERVAL_MINS` is set.
+
+use crate::database::storage::{Storage, persist_batch};
+use crate::handlers::students::{WeekGenerations, sync_submissions};
+use crate::handlers::sync::SyncStatus;
+use crate::handlers::week_locks::WeekLocks;
+use crate::services::cohort_window::CohortWindow;
+use crate::services::weekly::apply_submissions;
+use crate::utils::forge::ForgeProvider;
+use crate::utils::reload::Reloadable;
+use crate::utils::types::{AppError, RowData, Table};
+use actix_web::web;
+use chrono::{NaiveDate, Utc};
+use log::{info, warn};
+use std::collections::BTreeSet;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const DEFAULT_INTERVAL_MINS: u64 = 0;
+
+// How often the background sync runs, None when SYNC_INTERVAL_MINS is unset
+// or 0
+pub fn sync_interval_from_env() -> Result<Option<Duration>, String> {
+    let mins = match env::var("SYNC_INTERVAL_MINS") {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map_err(|_| "SYNC_INTERVAL_MINS must be a number of minutes, 0 to disable")?,
+        _ => DEFAULT_INTERVAL_MINS,
+    };
+    Ok((mins > 0).then(|| Duration::from_secs(mins * 60)))
+}
+
+// The week to sync: the one running by the cohort dates, or else the latest
+// week with rows. Only weeks that were generated and are not locked qualify.
+pub fn week_to_sync(
+    window: &CohortWindow,
+    rows: &[RowData],
+    locked: &BTreeSet<i32>,
+    today: NaiveDate,
+) -> Option<i32> {
+    let week = match window.start {
+        Some(_) => window.current_week(today)?,
+        None => rows.iter().map(|row| row.week).max()?,
+    };
+    let generated = rows.iter().any(|row| row.week == week);
+    (week >= 1 && generated && !locked.contains(&week)).then_some(week)
+}
+
+// Syncs the current week once, returning how many rows changed
+async fn sync_current_week(
+    state: &web::Data<Mutex<Table>>,
+    sync_status: &web::Data<Mutex<SyncStatus>>,
+    generations: &web::Data<WeekGenerations>,
+    forge: &web::Data<dyn ForgeProvider>,
+    db: &web::Data<dyn Storage>,
+    cohort_window: &web::Data<Reloadable<CohortWindow>>,
+    week_locks: &web::Data<Mutex<WeekLocks>>,
+) -> Result<usize, AppError> {
+    let window = cohort_window.get();
+    let locked = week_locks.lock().unwrap().weeks();
+    let week = {
+        let state_table = state.lock().unwrap();
+        week_to_sync(&window, &state_table.rows, &locked, Utc::now().date_naive())
+    }; // Lock released here
+    let Some(week) = week else {
+        return Ok(0);
+    };
+    // Held until the rows are stored, so a generation of the week waits for
+    // this sync; one already running syncs the week itself
+    let slot = generations.slot(week);
+    let Ok(_generating) = slot.try_lock() else {
+        info!(
+            "Week {} is being generated, skipping the background sync",
+            week
+        );
+        return Ok(0);
+    };
+
+    let submissions = sync_submissions(
+        week,
+        window.week_deadline(week),
+        sync_status,
+        forge,
+        db,
+        true,
+    )
+    .await;
+    let (changed_rows, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        // The week may have been locked while its submissions were fetched
+        if week_locks.lock().unwrap().weeks().contains(&week) {
+            info!("Week {} was locked during the background sync", week);
+            return Ok(0);
+        }
+        let mut changed_rows = apply_submissions(&state_table.rows, week, &submissions.by_name);
+        let checkpoint = state_table.checkpoint(&changed_rows);
+        for row in &mut changed_rows {
+            state_table.insert_or_update(row)?;
+        }
+        (changed_rows, state_table.take_history(), checkpoint)
+    }; // Lock released here
+
+    let changed = changed_rows.len();
+    if changed > 0 {
+        persist_batch(
+            state,
+            db,
+            changed_rows,
+            history,
+            "sync:background".to_string(),
+            checkpoint,
+        )
+        .await?;
+    }
+    info!(
+        "Background sync of week {}: {} row(s) changed, {} warning(s)",
+        week,
+        changed,
+        submissions.warnings.len()
+    );
+    Ok(changed)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_periodic_sync(
+    interval: Duration,
+    state: web::Data<Mutex<Table>>,
+    sync_status: web::Data<Mutex<SyncStatus>>,
+    generations: web::Data<WeekGenerations>,
+    forge: web::Data<dyn ForgeProvider>,
+    db: web::Data<dyn Storage>,
+    cohort_window: web::Data<Reloadable<CohortWindow>>,
+    week_locks: web::Data<Mutex<WeekLocks>>,
+) {
+    info!(
+        "Syncing the current week's submissions every {:?}",
+        interval
+    );
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // A slow forge delays the next run rather than bunching runs up
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = sync_current_week(
+                &state,
+                &sync_status,
+                &generations,
+                &forge,
+                &db,
+                &cohort_window,
+                &week_locks,
+            )
+            .await
+            {
+                warn!("Background sync failed: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,79 @@
+use crate::database::storage::{Storage, blocking};
+use crate::services::program_stats::{ProgramStats, program_stats};
+use crate::utils::types::Table;
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::{HttpResponse, get, web};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_CACHE_SECS: u64 = 600;
+
+// The last computed stats, reused until they are older than the TTL. The
+// website widget may be loaded on every page view, the numbers change weekly.
+pub struct PublicStatsCache {
+    ttl: Duration,
+    current: Mutex<Option<(Instant, Arc<ProgramStats>)>>,
+}
+
+impl PublicStatsCache {
+    pub fn new(ttl: Duration) -> Self {
+        PublicStatsCache {
+            ttl,
+            current: Mutex::new(None),
+        }
+    }
+
+    // TTL from PUBLIC_STATS_CACHE_SECS
+    pub fn from_env() -> Result<Self, String> {
+        let secs = match env::var("PUBLIC_STATS_CACHE_SECS") {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse()
+                .map_err(|_| "PUBLIC_STATS_CACHE_SECS must be a number of seconds")?,
+            _ => DEFAULT_CACHE_SECS,
+        };
+        Ok(PublicStatsCache::new(Duration::from_secs(secs)))
+    }
+
+    fn fresh(&self) -> Option<Arc<ProgramStats>> {
+        self.current
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, stats)| stats.clone())
+    }
+
+    fn store(&self, stats: Arc<ProgramStats>) {
+        *self.current.lock().unwrap() = Some((Instant::now(), stats));
+    }
+}
+
+// Aggregate program numbers for embedding on the public website. No names,
+// mail or per-student data, so it needs no token.
+#[get("/public/stats")]
+pub async fn get_public_stats(
+    cache: web::Data<PublicStatsCache>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let stats = match cache.fresh() {
+        Some(stats) => stats,
+        None => {
+            let locations = blocking(&db, |db| db.read_participant_locations()).await?;
+            let stats = {
+                let state_table = state.lock().unwrap();
+                Arc::new(program_stats(&state_table.rows, &locations))
+            }; // Lock released here
+            cache.store(stats.clone());
+            stats
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            CACHE_CONTROL,
+            format!("public, max-age={}", cache.ttl.as_secs()),
+        ))
+        .json(&*stats))
+}
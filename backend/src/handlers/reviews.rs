@@ -0,0 +1,134 @@
+//! TAs' summaries of their group's exercises and the curriculum feedback
+//! report built from them.
+
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, AuthError, Authenticated, Caller};
+use crate::services::curriculum::{clean_points, curriculum_report};
+use crate::services::grouping::group_id;
+use crate::utils::types::{ReviewSummary, Table};
+use actix_web::{HttpResponse, get, post, web};
+use chrono::Utc;
+use log::info;
+use serde::Deserialize;
+use std::sync::Mutex;
+
+const MAX_NOTES_LENGTH: usize = 2000;
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewSubmission {
+    #[serde(default)]
+    pub common_mistakes: Vec<String>,
+    #[serde(default)]
+    pub standout_solutions: Vec<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CurriculumQuery {
+    pub week: Option<i32>,
+}
+
+// Stores the summary of a group's exercises for the week, replacing any
+// earlier one. TAs may only summarize the group they led that week.
+#[post("/weeks/{week}/groups/{group}/review_summary")]
+pub async fn submit_review_summary(
+    Authenticated(caller): Authenticated,
+    path: web::Path<(i32, String)>,
+    body: web::Json<ReviewSubmission>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (week, group) = path.into_inner();
+    let group_id = group_id(&group);
+    let ReviewSubmission {
+        common_mistakes,
+        standout_solutions,
+        notes,
+    } = body.into_inner();
+    let common_mistakes = clean_points("common_mistakes", common_mistakes)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let standout_solutions = clean_points("standout_solutions", standout_solutions)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let notes = notes
+        .map(|notes| notes.trim().to_string())
+        .filter(|notes| !notes.is_empty());
+    if notes
+        .as_ref()
+        .is_some_and(|notes| notes.chars().count() > MAX_NOTES_LENGTH)
+    {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "notes must be at most {} characters",
+            MAX_NOTES_LENGTH
+        )));
+    }
+    if common_mistakes.is_empty() && standout_solutions.is_empty() && notes.is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "A review summary needs a common mistake, a standout solution or notes",
+        ));
+    }
+
+    let group_ta = {
+        let state_table = state.lock().unwrap();
+        let Some(row) = state_table
+            .rows
+            .iter()
+            .find(|row| row.week == week && row.group_id == group_id)
+        else {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("No {} in week {}", group_id, week)
+            })));
+        };
+        row.ta.clone()
+    }; // Lock released here
+    if let Caller::Ta(ta) = &caller
+        && group_ta.as_deref() != Some(ta.name().as_str())
+    {
+        return Err(AuthError::Forbidden(format!(
+            "{} of week {} is not your assigned group",
+            group_id, week
+        ))
+        .into());
+    }
+
+    let summary = ReviewSummary {
+        week,
+        group_id,
+        ta: group_ta.unwrap_or_else(|| caller.label()),
+        common_mistakes,
+        standout_solutions,
+        notes,
+        submitted_at: Utc::now().to_rfc3339(),
+    };
+    let stored = summary.clone();
+    blocking(&db, move |db| db.store_review_summary(&stored)).await?;
+    info!(
+        target: "audit",
+        "{} submitted the week {} review summary of {}",
+        caller.label(),
+        week,
+        summary.group_id
+    );
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// Mistakes and standout solutions across each week's groups, most widely
+// raised first, for curriculum maintainers
+#[get("/reports/curriculum_feedback")]
+pub async fn get_curriculum_feedback(
+    _admin: Admin,
+    query: web::Query<CurriculumQuery>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let summaries = blocking(&db, |db| db.read_review_summaries()).await?;
+    let summaries: Vec<ReviewSummary> = summaries
+        .into_iter()
+        .filter(|summary| query.week.is_none_or(|week| summary.week == week))
+        .collect();
+    let weeks = {
+        let state_table = state.lock().unwrap();
+        curriculum_report(&summaries, &state_table.rows)
+    }; // Lock released here
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "weeks": weeks })))
+}
@@ -0,0 +1,96 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, Authenticated, Caller};
+use crate::services::scoring::{Criterion, criteria};
+use crate::utils::types::RubricNote;
+use actix_web::{HttpResponse, get, put, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize)]
+pub struct CriterionSchema {
+    #[serde(flatten)]
+    pub criterion: Criterion,
+    // What earns each score, keyed by score
+    pub notes: BTreeMap<u64, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RubricNotes {
+    pub notes: BTreeMap<u64, String>,
+}
+
+// The grading rubric with the guidance TAs grade against, so it lives with
+// the data model instead of a separate document
+#[get("/schema")]
+pub async fn get_schema(
+    _caller: Authenticated,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut notes: BTreeMap<String, BTreeMap<u64, String>> = BTreeMap::new();
+    for note in blocking(&db, |db| db.read_rubric_notes()).await? {
+        notes
+            .entry(note.criterion)
+            .or_default()
+            .insert(note.score, note.note);
+    }
+
+    let criteria: Vec<CriterionSchema> = criteria()
+        .into_iter()
+        .map(|criterion| CriterionSchema {
+            notes: notes.remove(criterion.key).unwrap_or_default(),
+            criterion,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "criteria": criteria })))
+}
+
+// Replaces the notes of one criterion; an empty map clears them
+#[put("/schema/rubric/{criterion}")]
+pub async fn update_rubric_notes(
+    _admin: Admin,
+    criterion: web::Path<String>,
+    body: web::Json<RubricNotes>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let key = criterion.into_inner();
+    let Some(criterion) = criteria().into_iter().find(|c| c.key == key) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown rubric criterion: {}", key)
+        })));
+    };
+
+    let max = criterion.scale.max_score();
+    if let Some(score) = body.notes.keys().find(|score| **score > max) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Score {} is out of range for {}, expected 0..={}",
+            score, key, max
+        )));
+    }
+    let notes: BTreeMap<u64, String> = body
+        .into_inner()
+        .notes
+        .into_iter()
+        .map(|(score, note)| (score, note.trim().to_string()))
+        .filter(|(_, note)| !note.is_empty())
+        .collect();
+
+    let stored = notes.clone();
+    blocking(&db, move |db| {
+        db.replace_rubric_notes(criterion.key, &stored, &Caller::Admin.label())
+    })
+    .await?;
+    info!(
+        target: "audit",
+        "Rubric notes for {} updated ({} score(s))",
+        key,
+        notes.len()
+    );
+
+    let notes: Vec<RubricNote> = blocking(&db, |db| db.read_rubric_notes())
+        .await?
+        .into_iter()
+        .filter(|note| note.criterion == key)
+        .collect();
+    Ok(HttpResponse::Ok().json(notes))
+}
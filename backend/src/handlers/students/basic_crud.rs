@@ -1,17 +1,47 @@
-use crate::database::operations::{read_all_responses, read_from_db, write_to_db};
-use crate::utils::types::RowData;
-use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, Authenticated, Caller};
+use crate::handlers::backups::backup_before;
+use crate::handlers::cohort_window::WeekWindow;
+use crate::handlers::dry_run::DryRun;
+use crate::handlers::two_factor::SecondFactor;
+use crate::handlers::versions::{IfMatch, check_versions};
+use crate::services::fields::FieldsQuery;
+use crate::services::merge::{MergeStrategy, merge_student_rows};
+use crate::services::search::with_latest_weeks;
+use crate::utils::backup::{BackupReason, Backups};
+use crate::utils::types::{RowData, Table};
+use actix_web::{HttpResponse, Responder, ResponseError, delete, get, post, put, web};
 use log::info;
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::sync::Mutex;
 
-#[get("/students")]
-pub async fn get_students() -> impl Responder {
-    let db_path = PathBuf::from("classroom.db");
+// Most students one search returns
+const SEARCH_LIMIT: usize = 25;
+// Shortest query searched, so a single letter does not list everyone
+const MIN_SEARCH_LENGTH: usize = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
 
-    match read_from_db(&db_path) {
+#[get("/students")]
+pub async fn get_students(
+    fields: web::Query<FieldsQuery>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
+    if let Err(message) = fields.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }));
+    }
+    match blocking(&db, |db| db.read_from_db()).await {
         Ok(table) => {
             info!("Successfully fetched {} students", table.rows.len());
-            HttpResponse::Ok().json(&table.rows)
+            match fields.select(&table.rows) {
+                Ok(rows) => HttpResponse::Ok().json(rows),
+                Err(message) => {
+                    HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+                }
+            }
         }
         Err(e) => {
             info!("Error fetching students: {:?}", e);
@@ -22,56 +52,90 @@ pub async fn get_students() -> impl Responder {
     }
 }
 
+// Participants whose name, email or GitHub handle contains `q`, ignoring
+// case, each with a summary of their latest week
+#[get("/search")]
+pub async fn search_students(
+    _caller: Authenticated,
+    query: web::Query<SearchQuery>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let q = query.into_inner().q.trim().to_string();
+    if q.chars().count() < MIN_SEARCH_LENGTH {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Search for at least {} characters",
+            MIN_SEARCH_LENGTH
+        )));
+    }
+
+    let matches = blocking(&db, move |db| db.search_participants(&q, SEARCH_LIMIT)).await?;
+    let students = {
+        let state_table = state.lock().unwrap();
+        with_latest_weeks(matches, &state_table.rows)
+    }; // Lock released here
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "students": students })))
+}
+
 #[post("/students")]
-pub async fn add_student(student_data: web::Json<RowData>) -> impl Responder {
-    let db_path = PathBuf::from("classroom.db");
-
-    match read_from_db(&db_path) {
-        Ok(mut table) => {
-            table.rows.push(student_data.into_inner());
-
-            match write_to_db(&db_path, &table) {
-                Ok(_) => {
-                    info!("Successfully added new student");
-                    HttpResponse::Ok().json(serde_json::json!({
-                        "message": "Student added successfully"
-                    }))
-                }
-                Err(e) => {
-                    info!("Error adding student: {:?}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Failed to add student"
-                    }))
-                }
-            }
+pub async fn add_student(
+    _admin: Admin,
+    window: WeekWindow,
+    student_data: web::Json<RowData>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
+    let student = student_data.into_inner();
+    if let Err(e) = window.check([student.week]) {
+        return e.error_response();
+    }
+    match blocking(&db, move |db| db.upsert_rows(&[student])).await {
+        Ok(_) => {
+            info!("Successfully added new student");
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Student added successfully"
+            }))
         }
         Err(e) => {
-            info!("Error reading database: {:?}", e);
+            info!("Error adding student: {:?}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to read database"
+                "error": "Failed to add student"
             }))
         }
     }
 }
 
+// Needs the row's current version like `POST /weekly_data/{week}`
 #[put("/students/{name}")]
 pub async fn update_student(
+    _admin: Admin,
+    window: WeekWindow,
+    if_match: IfMatch,
     path: web::Path<String>,
     student_data: web::Json<RowData>,
+    db: web::Data<dyn Storage>,
 ) -> impl Responder {
-    let student_name = path.into_inner();
-    let db_path = PathBuf::from("classroom.db");
-
-    match read_from_db(&db_path) {
-        Ok(mut table) => {
-            if let Some(student) = table.rows.iter_mut().find(|s| s.name == student_name) {
-                *student = student_data.into_inner();
+    let student = path.into_inner();
+    if let Err(e) = window.check([student_data.week]) {
+        return e.error_response();
+    }
 
-                match write_to_db(&db_path, &table) {
+    match blocking(&db, |db| db.read_from_db()).await {
+        Ok(table) => {
+            let student_name = table.student_name(&student);
+            if table.rows.iter().any(|s| s.name == student_name) {
+                let mut student = student_data.into_inner();
+                if_match.apply(std::slice::from_mut(&mut student));
+                if let Err(conflict) = check_versions(&table.rows, std::slice::from_ref(&student)) {
+                    return conflict.error_response();
+                }
+                student.version = student.version.map(|version| version + 1);
+                let version = student.version;
+                match blocking(&db, move |db| db.upsert_rows(&[student])).await {
                     Ok(_) => {
                         info!("Successfully updated student: {}", student_name);
                         HttpResponse::Ok().json(serde_json::json!({
-                            "message": "Student updated successfully"
+                            "message": "Student updated successfully",
+                            "version": version
                         }))
                     }
                     Err(e) => {
@@ -97,17 +161,46 @@ pub async fn update_student(
 }
 
 #[delete("/students/{name}")]
-pub async fn remove_student(path: web::Path<String>) -> impl Responder {
-    let student_name = path.into_inner();
-    let db_path = PathBuf::from("classroom.db");
+pub async fn remove_student(
+    _admin: Admin,
+    _totp: SecondFactor,
+    dry_run: DryRun,
+    window: WeekWindow,
+    path: web::Path<String>,
+    db: web::Data<dyn Storage>,
+    backups: web::Data<Backups>,
+) -> impl Responder {
+    let student = path.into_inner();
 
-    match read_from_db(&db_path) {
-        Ok(mut table) => {
-            let initial_len = table.rows.len();
-            table.rows.retain(|s| s.name != student_name);
+    match blocking(&db, |db| db.read_from_db()).await {
+        Ok(table) => {
+            let student_name = table.student_name(&student);
+            let weeks = table
+                .rows
+                .iter()
+                .filter(|s| s.name == student_name)
+                .map(|s| s.week);
+            if let Err(e) = window.check(weeks) {
+                return e.error_response();
+            }
+            if dry_run.is_set() {
+                let rows: Vec<&RowData> = table
+                    .rows
+                    .iter()
+                    .filter(|s| s.name == student_name)
+                    .collect();
+                return DryRun::preview(serde_json::json!({ "delete": rows }));
+            }
 
-            if table.rows.len() < initial_len {
-                match write_to_db(&db_path, &table) {
+            if table.rows.iter().any(|s| s.name == student_name) {
+                if let Err(e) = backup_before(&backups, BackupReason::BeforeStudentRemoval).await {
+                    info!("Error backing up before removing student: {:?}", e);
+                    return HttpResponse::InternalServerError().json(serde_json::json!({
+                        "error": "Failed to back up database, student not removed"
+                    }));
+                }
+                let name = student_name.clone();
+                match blocking(&db, move |db| db.delete_rows(&name, None)).await {
                     Ok(_) => {
                         info!("Successfully removed student: {}", student_name);
                         HttpResponse::Ok().json(serde_json::json!({
@@ -136,12 +229,135 @@ pub async fn remove_student(path: web::Path<String>) -> impl Responder {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MergeRowsRequest {
+    // Name or public id of the student entered by mistake
+    pub duplicate: String,
+    // Name or public id of the student kept
+    pub canonical: String,
+    #[serde(default)]
+    pub strategy: MergeStrategy,
+}
+
+// The canonical rows the merge writes and the duplicate rows it removes
+fn merge_plan(
+    table: &Table,
+    duplicate: &str,
+    request: &MergeRowsRequest,
+) -> (Vec<RowData>, Vec<RowData>) {
+    let canonical = table.student_name(&request.canonical);
+    let removed = table
+        .rows
+        .iter()
+        .filter(|row| row.name == duplicate)
+        .cloned()
+        .collect();
+    let merged = merge_student_rows(&table.rows, duplicate, &canonical, request.strategy);
+    (merged, removed)
+}
+
+// Folds a student entered twice, e.g. "Jon" next to "John", into the
+// canonical one across all weeks
+#[post("/admin/merge_rows")]
+#[allow(clippy::too_many_arguments)]
+pub async fn merge_rows(
+    _admin: Admin,
+    _totp: SecondFactor,
+    dry_run: DryRun,
+    window: WeekWindow,
+    request: web::Json<MergeRowsRequest>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+    backups: web::Data<Backups>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (duplicate, canonical) = {
+        let state_table = state.lock().unwrap();
+        let duplicate = state_table.student_name(&request.duplicate);
+        let canonical = state_table.student_name(&request.canonical);
+        if duplicate == canonical {
+            return Err(actix_web::error::ErrorBadRequest(
+                "A student cannot be merged into itself",
+            ));
+        }
+        for name in [&duplicate, &canonical] {
+            if !state_table.rows.iter().any(|row| &row.name == name) {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": format!("Student {} not found", name)
+                })));
+            }
+        }
+        let (merged, removed) = merge_plan(&state_table, &duplicate, &request);
+        window.check(removed.iter().map(|row| row.week))?;
+        if dry_run.is_set() {
+            return Ok(DryRun::preview(serde_json::json!({
+                "update": merged,
+                "delete": removed
+            })));
+        }
+        (duplicate, canonical)
+    }; // Lock released here
+
+    backup_before(&backups, BackupReason::BeforeStudentRemoval).await?;
+
+    // Both students' rows as they were, put back if the database write fails
+    let (merged, history, previous) = {
+        let mut state_table = state.lock().unwrap();
+        let (mut merged, _) = merge_plan(&state_table, &duplicate, &request);
+        let previous: Vec<RowData> = state_table
+            .rows
+            .iter()
+            .filter(|row| row.name == duplicate || row.name == canonical)
+            .cloned()
+            .collect();
+        state_table.rows.retain(|row| row.name != duplicate);
+        for row in merged.iter_mut() {
+            state_table.insert_or_update(row)?;
+        }
+        (merged, state_table.take_history(), previous)
+    }; // Lock released here
+
+    let rows = merged.clone();
+    let name = duplicate.clone();
+    let actor = Caller::Admin.label();
+    let deleted = match blocking(&db, move |db| db.merge_rows(&name, &rows, &history, &actor)).await
+    {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            let mut state_table = state.lock().unwrap();
+            state_table
+                .rows
+                .retain(|row| row.name != duplicate && row.name != canonical);
+            state_table.rows.extend(previous);
+            return Err(e.into());
+        }
+    };
+    info!(
+        target: "audit",
+        "admin merged {} into {} ({} rows, strategy {:?})",
+        duplicate,
+        canonical,
+        deleted,
+        request.strategy
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "duplicate": duplicate,
+        "canonical": canonical,
+        "strategy": request.strategy,
+        "merged": deleted,
+        "data": merged
+    })))
+}
+
 #[get("/feedback/{cohort_name}")]
-pub async fn get_cohort_feedback(cohort_name: web::Path<String>) -> impl Responder {
-    let db_path = PathBuf::from("classroom.db");
+pub async fn get_cohort_feedback(
+    cohort_name: web::Path<String>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
     let cohort_name = cohort_name.into_inner();
 
-    match read_all_responses(&db_path, &cohort_name) {
+    let cohort = cohort_name.clone();
+    match blocking(&db, move |db| db.read_all_responses(&cohort)).await {
         Ok(responses) => {
             info!("Successfully fetched feedback for cohort: {}", cohort_name);
             HttpResponse::Ok().json(responses)
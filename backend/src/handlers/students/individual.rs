@@ -1,80 +1,74 @@
 use crate::database::operations::register_cohort_participant;
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::communications::record_sent;
 use crate::handlers::students::weekly_data::{get_github_to_name_mapping, get_github_username};
-use crate::utils::classroom::{Assignment, get_submitted_assignments};
-use crate::utils::types::{CohortParticipant, RowData, Table};
-use actix_web::{HttpResponse, Responder, get, post, web};
+use crate::services::fields::FieldsQuery;
+use crate::services::grouping::{GroupWeek, group_history};
+use crate::utils::classroom::Assignment;
+use crate::utils::forge::{ForgeProvider, ForgeRateLimited};
+use crate::utils::outbox::{OutboxChannel, outbox};
+use crate::utils::types::{
+    AppError, BackgroundData, CohortParticipant, CommunicationKind, RowData, Table,
+};
+use actix_web::{HttpResponse, Responder, ResponseError, get, post, web};
 use log::{info, warn};
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Mutex;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BackgroundData {
-    pub describe_yourself: String,
-    pub background: String,
-    pub skills: String,
-    pub location: String,
-    pub why: String,
-    pub year: String,
-    pub book: String,
-}
-
-impl Default for BackgroundData {
-    fn default() -> Self {
-        BackgroundData {
-            describe_yourself: String::new(),
-            background: String::new(),
-            skills: String::new(),
-            location: String::new(),
-            why: String::new(),
-            year: String::new(),
-            book: String::new(),
-        }
-    }
-}
-
-pub fn get_background_data(path: &PathBuf, email: &str) -> BackgroundData {
-    use rusqlite::{Connection, params};
-
-    let conn = Connection::open(path).ok().unwrap();
-    let mut stmt = conn.prepare(
-        "SELECT  \"Describe Yourself\" , Background, Skills, Location, Year, Why, Books FROM participants WHERE Email = ?1",
-    ).ok().unwrap();
-
-    let rows = stmt
-        .query_map(params![email], |row| {
-            Ok(BackgroundData {
-                describe_yourself: row.get(0)?,
-                background: row.get(1)?,
-                skills: row.get(2)?,
-                location: row.get(3)?,
-                year: row.get(4)?,
-                why: row.get(5)?,
-                book: row.get(6)?,
-            })
-        })
+pub async fn get_background_data(db: &web::Data<dyn Storage>, email: &str) -> BackgroundData {
+    let email = email.to_string();
+    blocking(db, move |db| db.background_data(&email))
+        .await
         .ok()
-        .unwrap();
-
-    rows.flatten()
-        .next()
-        .unwrap_or_else(BackgroundData::default)
+        .flatten()
+        .unwrap_or_default()
 }
 
 #[get("/students/{week}/{student_name}")]
-pub async fn get_student_repo_link(info: web::Path<(i32, String)>) -> impl Responder {
-    let (week, student_name) = info.into_inner();
-    let assignments = get_submitted_assignments(week).await.unwrap();
+pub async fn get_student_repo_link(
+    info: web::Path<(i32, String)>,
+    state: web::Data<Mutex<Table>>,
+    forge: web::Data<dyn ForgeProvider>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
+    let (week, student) = info.into_inner();
+    let (student_name, stored_url) = {
+        let state_table = state.lock().unwrap();
+        let student_name = state_table.student_name(&student);
+        let stored_url = state_table
+            .rows
+            .iter()
+            .find(|row| row.week == week && row.name == student_name)
+            .and_then(|row| row.repo_url.clone());
+        (student_name, stored_url)
+    }; // Lock released here
+    // Rows synced since repo links were stored need no forge request
+    if let Some(url) = stored_url {
+        return HttpResponse::Ok().json(serde_json::json!({ "url": url }));
+    }
+    let assignments = match forge.fetch_week_submissions(week).await {
+        Ok(assignments) => assignments,
+        Err(e) => {
+            warn!("Failed to fetch week {} submissions: {}", week, e);
+            if let Some(retry_after) = e.retry_after() {
+                return ForgeRateLimited {
+                    forge: forge.name(),
+                    retry_after,
+                }
+                .error_response();
+            }
+            Vec::new()
+        }
+    };
     let submitted: Vec<&Assignment> = assignments.iter().filter(|a| a.is_submitted()).collect();
 
-    let db_path = PathBuf::from("classroom.db");
     let mut student_url = "".to_string();
 
     //for loops conclude to unit type ()
     for assignment in &submitted {
         if let Some(participant_name) =
-            get_github_to_name_mapping(&db_path, &assignment.github_username)
+            get_github_to_name_mapping(&db, &assignment.github_username).await
         {
             if participant_name == student_name {
                 student_url = (assignment.student_repository_url).to_string();
@@ -86,27 +80,33 @@ pub async fn get_student_repo_link(info: web::Path<(i32, String)>) -> impl Respo
 }
 
 #[get("/data/{student_email}")]
-pub async fn get_student_background_data(info: web::Path<String>) -> impl Responder {
+pub async fn get_student_background_data(
+    info: web::Path<String>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
     let student_email = info.into_inner();
-    let db_path = PathBuf::from("classroom.db");
-
-    let data = get_background_data(&db_path, &student_email);
+    let data = get_background_data(&db, &student_email).await;
 
     HttpResponse::Ok().json(data)
 }
 
 #[get("/student/github/{name}")]
-pub async fn get_student_github_username(info: web::Path<String>) -> impl Responder {
-    let student_name = info.into_inner();
-    let db_path = PathBuf::from("classroom.db");
-
-    let data = get_github_username(&db_path, &student_name);
+pub async fn get_student_github_username(
+    info: web::Path<String>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
+    let student_name = state.lock().unwrap().student_name(&info.into_inner());
+    let data = get_github_username(&db, &student_name).await;
 
     HttpResponse::Ok().json(data)
 }
 
 #[post("/register")]
-pub async fn register_user(data: web::Json<CohortParticipant>) -> impl Responder {
+pub async fn register_user(
+    data: web::Json<CohortParticipant>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
     let data = data.into_inner();
     info!("Registering cohort participant: {:?}", data.role);
 
@@ -114,7 +114,11 @@ pub async fn register_user(data: web::Json<CohortParticipant>) -> impl Responder
 
     let participant_data = data.clone();
 
-    if let Err(e) = register_cohort_participant(&db_path, data) {
+    let registered = web::block(move || register_cohort_participant(&db_path, data))
+        .await
+        .map_err(AppError::from)
+        .and_then(|result| result);
+    if let Err(e) = registered {
         warn!("Failed to register cohort participant: {e}");
         return HttpResponse::InternalServerError()
             .json(serde_json::json!({ "error": e.to_string() }));
@@ -124,12 +128,28 @@ pub async fn register_user(data: web::Json<CohortParticipant>) -> impl Responder
 
     // if participant is registered successfully, send data to external API
 
+    let name = participant_data.name.clone();
     let api_data = HashMap::from([
         ("name", participant_data.name),
         ("email", participant_data.email),
         ("role", participant_data.role),
     ]);
 
+    if let Some(outbox) = outbox() {
+        let invite = serde_json::to_string(&api_data).unwrap_or_default();
+        outbox.capture(OutboxChannel::Discord, "bot/invite", None, &invite);
+        record_sent(
+            &db,
+            &name,
+            CommunicationKind::InviteNudge,
+            "Discord invite",
+            None,
+            "registration",
+        )
+        .await;
+        return HttpResponse::Ok().json(serde_json::json!({ "status": "success" }));
+    }
+
     let client = reqwest::Client::new();
     client
         .post("http://localhost:8080/bot/invite")
@@ -139,6 +159,15 @@ pub async fn register_user(data: web::Json<CohortParticipant>) -> impl Responder
         .await
         .map_err(|_| HttpResponse::InternalServerError().finish())
         .unwrap();
+    record_sent(
+        &db,
+        &name,
+        CommunicationKind::InviteNudge,
+        "Discord invite",
+        None,
+        "registration",
+    )
+    .await;
 
     HttpResponse::Ok().json(serde_json::json!({ "status": "success" }))
 }
@@ -146,9 +175,10 @@ pub async fn register_user(data: web::Json<CohortParticipant>) -> impl Responder
 #[get("/students/{student_name}")]
 pub async fn get_individual_student_data(
     info: web::Path<String>,
+    fields: web::Query<FieldsQuery>,
     state: web::Data<Mutex<Table>>,
 ) -> impl Responder {
-    let student_name = info.into_inner();
+    let student_name = state.lock().unwrap().student_name(&info.into_inner());
 
     // Single lock scope for data collection
     let mut student_data: Vec<RowData> = {
@@ -164,5 +194,75 @@ pub async fn get_individual_student_data(
     // Sort by week after releasing the lock
     student_data.sort_by_key(|row| row.week);
 
-    HttpResponse::Ok().json(student_data)
+    match fields.select(&student_data) {
+        Ok(rows) => HttpResponse::Ok().json(rows),
+        Err(message) => HttpResponse::BadRequest().json(serde_json::json!({ "error": message })),
+    }
+}
+
+// One student's graded rows of every week, oldest first, for the student
+// drill-down. The week 0 enrollment row is left out.
+#[get("/students/{student_name}/weekly_data")]
+pub async fn get_student_weekly_data(
+    info: web::Path<String>,
+    fields: web::Query<FieldsQuery>,
+    state: web::Data<Mutex<Table>>,
+) -> impl Responder {
+    let student_name = state.lock().unwrap().student_name(&info.into_inner());
+
+    let (enrolled, mut weeks): (bool, Vec<RowData>) = {
+        let state_table = state.lock().unwrap();
+        let rows: Vec<&RowData> = state_table
+            .rows
+            .iter()
+            .filter(|row| row.name == student_name)
+            .collect();
+        (
+            !rows.is_empty(),
+            rows.into_iter()
+                .filter(|row| row.week >= 1)
+                .cloned()
+                .collect(),
+        )
+    }; // Lock released here
+
+    if !enrolled {
+        return HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": format!("No student named {}", student_name) }));
+    }
+    weeks.sort_by_key(|row| row.week);
+
+    match fields.select(&weeks) {
+        Ok(weeks) => HttpResponse::Ok().json(serde_json::json!({
+            "name": student_name,
+            "weeks": weeks
+        })),
+        Err(message) => HttpResponse::BadRequest().json(serde_json::json!({ "error": message })),
+    }
+}
+
+// Group, groupmates and TA for every week, e.g. for "who was in my group in
+// week 3?"
+#[get("/students/{student_name}/groups")]
+pub async fn get_student_groups(
+    info: web::Path<String>,
+    state: web::Data<Mutex<Table>>,
+) -> impl Responder {
+    let student_name = state.lock().unwrap().student_name(&info.into_inner());
+
+    let rows: Vec<RowData> = {
+        let state_table = state.lock().unwrap();
+        state_table.rows.clone()
+    }; // Lock released here
+
+    if !rows.iter().any(|row| row.name == student_name) {
+        return HttpResponse::NotFound()
+            .json(serde_json::json!({ "error": format!("No student named {}", student_name) }));
+    }
+    let history: Vec<GroupWeek> = group_history(&rows, &student_name);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "name": student_name,
+        "weeks": history
+    }))
 }
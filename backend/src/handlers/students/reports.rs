@@ -1,48 +1,46 @@
-use crate::utils::types::{RowData, Table};
-use actix_web::{HttpResponse, Responder, get, web};
-use log::info;
-use serde::Serialize;
-use std::collections::HashMap;
+use crate::database::bootstrap::{cohort_db_path, valid_cohort_name};
+use crate::database::operations::{
+    read_cohort_attendance, read_cohort_exercises, read_cohort_rows,
+};
+use crate::database::retention::cohort_names;
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, Authenticated};
+use crate::handlers::exports::export_response;
+use crate::handlers::tas::rotation;
+use crate::services::calibration::calibration_report;
+use crate::services::compensation::{CompensationRates, ta_workload, workload_table};
+use crate::services::exercises::{CohortExercises, exercise_outcomes, exercise_stats};
+use crate::services::forecast::{attendance_by_week, forecast_attendance};
+use crate::services::read_model::ReadModel;
+use crate::utils::reload::Reloadable;
+use crate::utils::types::Table;
+use actix_web::{HttpResponse, Responder, ResponseError, get, web};
+use log::{info, warn};
+use serde::Deserialize;
 use std::sync::Mutex;
 
-#[derive(Serialize)]
-struct StudentScoreResponse {
-    name: String,
-    email: String,
-    total_score: u64,
-    exercise_total_score: u8,
-}
-
+// Served from the read model, see services::read_model
 #[get("/students/count")]
-pub async fn get_total_student_count(state: web::Data<Mutex<Table>>) -> impl Responder {
+pub async fn get_total_student_count(read_model: web::Data<ReadModel>) -> impl Responder {
     info!("Fetching total student count");
-    let count = state
-        .lock()
-        .unwrap()
-        .rows
-        .iter()
-        .filter(|row| row.week == 0)
-        .count();
+    let count = read_model.stats().student_count;
     HttpResponse::Ok().json(serde_json::json!({ "count": count }))
 }
 
 #[get("/attendance/weekly_counts/{week}")]
 pub async fn get_weekly_attendance_count_for_week(
     week: web::Path<i32>,
-    state: web::Data<Mutex<Table>>,
+    read_model: web::Data<ReadModel>,
 ) -> impl Responder {
     let week_num = week.into_inner();
     info!("Fetching attendance count for week: {}", week_num);
 
-    // Single lock scope for the count operation
-    let count = {
-        let state_table = state.lock().unwrap();
-        state_table
-            .rows
-            .iter()
-            .filter(|row| row.week == week_num && row.attendance == Some("yes".to_string()))
-            .count()
-    }; // Lock released here
+    let count = read_model
+        .stats()
+        .attended_by_week
+        .get(&week_num)
+        .copied()
+        .unwrap_or(0);
 
     HttpResponse::Ok().json(serde_json::json!({
         "week": week_num,
@@ -51,51 +49,167 @@ pub async fn get_weekly_attendance_count_for_week(
 }
 
 #[get("/students/total_scores")]
-pub async fn get_students_by_total_score(state: web::Data<Mutex<Table>>) -> impl Responder {
+pub async fn get_students_by_total_score(read_model: web::Data<ReadModel>) -> impl Responder {
     info!("Fetching students ordered by total score (desc)");
-    let state_table = state.lock().unwrap();
-
-    let mut student_data: HashMap<String, (RowData, u64, u8)> = HashMap::new();
-
-    for row in &state_table.rows {
-        let total_score = row.total.unwrap_or(0);
-
-        student_data
-            .entry(row.name.clone())
-            .and_modify(|(existing_row, accumulated_total, exercise_total)| {
-                *accumulated_total += total_score;
-                *exercise_total += if row.exercise_test_passing == Some("yes".to_string()) {
-                    1
-                } else {
-                    0
-                };
-                if row.week > existing_row.week {
-                    *existing_row = row.clone();
-                }
-            })
-            .or_insert((
-                row.clone(),
-                total_score,
-                if row.exercise_test_passing == Some("yes".to_string()) {
-                    1
-                } else {
-                    0
+    HttpResponse::Ok().json(&read_model.stats().leaderboard)
+}
+
+// Expected attendance, groups and TAs for the next week, so organizers know
+// how many TAs and breakout rooms to prepare
+#[get("/analytics/forecast")]
+pub async fn get_attendance_forecast(
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> impl Responder {
+    let current = attendance_by_week(&state.lock().unwrap().rows);
+
+    // Past cohort archives are read on the blocking pool
+    let past = web::block(|| {
+        cohort_names()
+            .iter()
+            .filter_map(
+                |cohort| match read_cohort_attendance(&cohort_db_path(cohort)) {
+                    Ok(counts) => Some(counts),
+                    Err(e) => {
+                        warn!("Skipping cohort {} in attendance forecast: {}", cohort, e);
+                        None
+                    }
                 },
-            ));
-    }
+            )
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    let tas = match rotation(&db).await {
+        Ok(tas) => tas,
+        Err(e) => return e.error_response(),
+    };
+    let forecast = forecast_attendance(&current, &past, tas.len());
+    info!(
+        "Forecast {} attendee(s) in {} group(s) for week {}",
+        forecast.projected_attendance, forecast.groups, forecast.week
+    );
+    HttpResponse::Ok().json(forecast)
+}
+
+// Pass rate, CI attempts and time to submission per exercise, pooled over
+// the current and past cohorts, so curriculum maintainers can see which
+// weeks need better instructions
+#[get("/analytics/exercises")]
+pub async fn get_exercise_analytics(
+    _caller: Authenticated,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcomes = exercise_outcomes(&state.lock().unwrap().rows);
+    let attempts = blocking(&db, |db| db.read_exercise_attempts()).await?;
+    let mut cohorts = vec![CohortExercises { outcomes, attempts }];
 
-    let mut response: Vec<(RowData, u64, u8)> = student_data.into_values().collect();
-    response.sort_by(|a, b| b.1.cmp(&a.1)); // Sort by total score descending
+    // Past cohort archives are read on the blocking pool
+    let past = web::block(|| {
+        cohort_names()
+            .iter()
+            .filter_map(
+                |cohort| match read_cohort_exercises(&cohort_db_path(cohort)) {
+                    Ok((outcomes, attempts)) => Some(CohortExercises { outcomes, attempts }),
+                    Err(e) => {
+                        warn!("Skipping cohort {} in exercise analytics: {}", cohort, e);
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+    cohorts.extend(past);
 
-    let final_response: Vec<StudentScoreResponse> = response
+    let stats = exercise_stats(&cohorts);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "cohorts": cohorts.len(),
+        "exercises": stats
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompensationQuery {
+    // Archived cohort to report on instead of the current one
+    cohort: Option<String>,
+    // "csv" or "markdown" for the stipend export, the JSON report otherwise
+    format: Option<String>,
+    // Comma separated columns of the export, all when empty
+    #[serde(default)]
+    columns: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CalibrationQuery {
+    pub week: Option<i32>,
+}
+
+// Rows whose grades stand out from the rest of their week, per TA, for
+// organizers to review before scores are final
+#[get("/reports/calibration")]
+pub async fn get_calibration_report(
+    _admin: Admin,
+    query: web::Query<CalibrationQuery>,
+    state: web::Data<Mutex<Table>>,
+) -> impl Responder {
+    let flags = {
+        let state_table = state.lock().unwrap();
+        calibration_report(&state_table.rows)
+    }; // Lock released here
+    let flags: Vec<_> = flags
         .into_iter()
-        .map(|(row, total_sum, exercise_total)| StudentScoreResponse {
-            name: row.name,
-            email: row.mail,
-            total_score: total_sum,
-            exercise_total_score: exercise_total,
-        })
+        .filter(|flag| query.week.is_none_or(|week| flag.week == week))
         .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "flags": flags }))
+}
 
-    HttpResponse::Ok().json(final_response)
+// Sessions led, students graded and estimated hours per TA, priced at the
+// configured rates for stipend processing
+#[get("/reports/ta_compensation")]
+pub async fn get_ta_compensation(
+    _admin: Admin,
+    query: web::Query<CompensationQuery>,
+    state: web::Data<Mutex<Table>>,
+    rates: web::Data<Reloadable<CompensationRates>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let query = query.into_inner();
+    let rates = rates.get();
+    let rows = match &query.cohort {
+        None => state.lock().unwrap().rows.clone(),
+        Some(cohort) => {
+            if !valid_cohort_name(cohort) || !cohort_db_path(cohort).exists() {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": format!("Cohort '{}' has no database", cohort)
+                })));
+            }
+            let path = cohort_db_path(cohort);
+            web::block(move || read_cohort_rows(&path)).await??
+        }
+    };
+
+    let workload = ta_workload(&rows, &rates);
+    let cohort = query.cohort.as_deref().unwrap_or("current");
+    info!(
+        "Computed compensation for {} TA(s) in cohort {}",
+        workload.len(),
+        cohort
+    );
+
+    if let Some(format) = query.format.as_deref().filter(|format| *format != "json") {
+        return export_response(
+            workload_table(&workload, &rates),
+            format,
+            &query.columns,
+            &format!("ta_compensation_{}", cohort),
+        );
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "cohort": cohort,
+        "rates": rates.as_ref(),
+        "tas": workload
+    })))
 }
@@ -1,273 +1,754 @@
-use crate::database::operations::write_to_db;
-use crate::handlers::auth::TA;
-use crate::utils::classroom::{Assignment, get_submitted_assignments};
-use crate::utils::constants::get_auth_token;
-use crate::utils::types::{RowData, Table};
-use actix_web::{HttpResponse, Responder, Result, get, post, web};
+use crate::database::storage::{Storage, blocking, persist_batch};
+use crate::handlers::auth::{Admin, AuthError, Authenticated, Caller};
+use crate::handlers::backups::backup_before;
+use crate::handlers::cohort_window::WeekWindow;
+use crate::handlers::dry_run::DryRun;
+use crate::handlers::idempotency::{IdempotencyKey, fingerprint};
+use crate::handlers::sync::{SyncStatus, WeekSyncStatus};
+use crate::handlers::tas::rotation;
+use crate::handlers::two_factor::SecondFactor;
+use crate::handlers::versions::{IfMatch, VersionConflict, check_versions};
+use crate::services::calibration::grading_flags;
+use crate::services::constraints::ConstraintViolation;
+use crate::services::exercises::observed_attempts;
+use crate::services::fields::FieldsQuery;
+use crate::services::invariants::{self, check_grouping, check_totals};
+use crate::services::paging::{PageMeta, RowsQuery};
+use crate::services::scoring::{apply_exercise_result, exercise_result};
+use crate::services::validation::{FieldError, InvalidRows, row_failures, validate_rows};
+use crate::services::weekly::{build_week_rows, rows_by_week, rows_for_week};
+use crate::utils::backup::{BackupReason, Backups};
+use crate::utils::classroom::Assignment;
+use crate::utils::forge::{
+    ForgeProvider, ForgeRateLimited, SyncWarning, SyncWarningKind, late_submissions,
+    sync_week_assignments,
+};
+use crate::utils::types::{
+    AppError, RowChange, RowCounts, RowData, RowResult, RowStatus, SyncRun, Table, revert_changes,
+};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, Responder, ResponseError, Result, get, post, put, web};
+use chrono::{DateTime, Utc};
 use log::{info, warn};
-use rusqlite::Connection;
-use std::collections::HashMap;
-use std::path::PathBuf; // Add this import
-// Helper function for GitHub to name mapping
-pub fn get_github_to_name_mapping(path: &PathBuf, github_username: &String) -> Option<String> {
-    let conn = Connection::open(path).ok()?;
-    let mut stmt = conn
-        .prepare("SELECT Name FROM Participants WHERE Github LIKE ?")
-        .ok()?;
-
-    let pattern = format!("%{}", github_username);
-    let mut rows = stmt
-        .query_map([&pattern], |row| {
-            Ok(row.get::<_, String>(0)?) // Name
-        })
-        .ok()?;
-    if let Some(Ok(name)) = rows.next() {
-        Some(name)
-    } else {
-        None
-    }
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Debug, Serialize)]
+pub struct WeeklyMeta {
+    pub week: i32,
+    pub warnings: Vec<SyncWarning>,
+    // Grouping constraints the regrouping could not honor
+    pub constraint_violations: Vec<ConstraintViolation>,
 }
 
-pub fn get_github_username(path: &PathBuf, name: &String) -> String {
-    let conn = Connection::open(path).ok().unwrap();
+#[derive(Debug, Serialize)]
+pub struct WeeklyDataResponse {
+    pub data: Vec<RowData>,
+    pub meta: WeeklyMeta,
+}
 
-    let mut stmt = conn
-        .prepare("SELECT Github FROM Participants WHERE Name LIKE ?")
+// Rows generating a week added and rows it changed, or would for a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct WeekChanges {
+    pub insert: Vec<RowData>,
+    pub update: Vec<RowData>,
+}
+
+// One page of a week's rows, as `GET /weekly_data/{week}` returns it
+#[derive(Serialize)]
+struct WeeklyPage<'a> {
+    // Rows with the fields `?fields` selects
+    data: serde_json::Value,
+    meta: WeeklyPageMeta<'a>,
+}
+
+#[derive(Serialize)]
+struct WeeklyPageMeta<'a> {
+    #[serde(flatten)]
+    week: &'a WeeklyMeta,
+    #[serde(flatten)]
+    page: PageMeta,
+}
+
+impl WeeklyDataResponse {
+    // Sorts and pages the rows as the query asks, with the total count
+    fn paged(&self, view: &RowsQuery, fields: &FieldsQuery) -> HttpResponse {
+        match view
+            .apply(&self.data)
+            .and_then(|(rows, page)| Ok((fields.select(&rows)?, page)))
+        {
+            Ok((data, page)) => HttpResponse::Ok().json(WeeklyPage {
+                data,
+                meta: WeeklyPageMeta {
+                    week: &self.meta,
+                    page,
+                },
+            }),
+            Err(message) => HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": message
+            })),
+        }
+    }
+}
+
+// Helper function for GitHub to name mapping
+pub async fn get_github_to_name_mapping(
+    db: &web::Data<dyn Storage>,
+    github_username: &str,
+) -> Option<String> {
+    let github_username = github_username.to_string();
+    blocking(db, move |db| db.github_to_name(&github_username))
+        .await
         .ok()
-        .unwrap();
+        .flatten()
+}
 
-    let pattern = format!("%{}", name);
-    let mut result = stmt
-        .query_map([&pattern], |row| {
-            Ok(row.get::<_, String>(0)?) // Name
-        })
+pub async fn get_github_username(db: &web::Data<dyn Storage>, name: &str) -> String {
+    let name = name.to_string();
+    blocking(db, move |db| db.github_username(&name))
+        .await
         .ok()
-        .unwrap();
-    if let Some(Ok(name)) = result.next() {
-        name
-    } else {
-        "".to_string()
+        .flatten()
+        .unwrap_or_default()
+}
+
+// Serializes generation of each week. A request that waited for another one
+// generating the same week reuses its result instead of syncing again.
+#[derive(Default)]
+pub struct WeekGenerations {
+    weeks: std::sync::Mutex<HashMap<i32, Arc<tokio::sync::Mutex<Option<Generated>>>>>,
+}
+
+pub(crate) struct Generated {
+    finished_at: Instant,
+    response: WeeklyDataResponse,
+}
+
+impl WeekGenerations {
+    pub(crate) fn slot(&self, week: i32) -> Arc<tokio::sync::Mutex<Option<Generated>>> {
+        self.weeks.lock().unwrap().entry(week).or_default().clone()
     }
 }
 
+// The week's rows as stored. Nothing is synced or written; a week is
+// generated with `POST /weekly_data/{week}/generate`.
 #[get("/weekly_data/{week}")]
 pub async fn get_weekly_data_or_common(
+    _caller: Authenticated,
+    window: WeekWindow,
     week: web::Path<i32>,
+    view: web::Query<RowsQuery>,
+    fields: web::Query<FieldsQuery>,
     state: web::Data<std::sync::Mutex<Table>>,
-    req: actix_web::HttpRequest,
 ) -> impl Responder {
-    let auth_token = get_auth_token();
-
-    let auth_header = req
-        .headers()
-        .get(actix_web::http::header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok());
-
-    if auth_header != Some(auth_token.as_str()) {
-        return HttpResponse::Unauthorized().json(serde_json::json!({
+    let week = week.into_inner();
+    // Out of range rather than empty, so a mistyped week is not taken for
+    // one still to be generated
+    if let Err(e) = window.check_range(week) {
+        return e.error_response();
+    }
+    if let Err(message) = view.validate().and_then(|_| fields.validate()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
             "status": "error",
-            "message": "Unauthorized: missing or invalid token"
+            "message": message
         }));
     }
 
-    let week = week.into_inner();
-    info!("Getting and updating weekly data for week: {}", week);
+    let rows = {
+        let state_table = state.lock().unwrap();
+        rows_for_week(&state_table.rows, week)
+    }; // Lock released here
+    WeeklyDataResponse {
+        data: rows,
+        meta: WeeklyMeta {
+            week,
+            warnings: Vec::new(),
+            constraint_violations: Vec::new(),
+        },
+    }
+    .paged(&view, &fields)
+}
 
-    // Scope 1: Handle week == 0 case
-    {
+// Most weeks one range query spans, as every week is listed even when empty
+const MAX_RANGE_WEEKS: i32 = 52;
+
+#[derive(Debug, Deserialize)]
+pub struct WeekRangeQuery {
+    pub from: i32,
+    pub to: i32,
+}
+
+// The rows of a span of weeks grouped by week, e.g. for totals over the first
+// half of the cohort in one request
+#[get("/weekly_data")]
+pub async fn get_weekly_data_range(
+    _caller: Authenticated,
+    window: WeekWindow,
+    query: web::Query<WeekRangeQuery>,
+    fields: web::Query<FieldsQuery>,
+    state: web::Data<std::sync::Mutex<Table>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (from, to) = (query.from, query.to);
+    if to < from {
+        return Err(actix_web::error::ErrorBadRequest(
+            "to must not be before from",
+        ));
+    }
+    if to - from >= MAX_RANGE_WEEKS {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "At most {} weeks can be read at once",
+            MAX_RANGE_WEEKS
+        )));
+    }
+    window.check_range(from)?;
+    window.check_range(to)?;
+    fields
+        .validate()
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let by_week = {
         let state_table = state.lock().unwrap();
-        if week == 0 && !state_table.rows.is_empty() {
-            let week_0_rows: Vec<RowData> = state_table
-                .rows
-                .iter()
-                .filter(|row| row.week == 0)
-                .cloned()
-                .collect();
-            return HttpResponse::Ok().json(week_0_rows);
+        rows_by_week(&state_table.rows, from..=to)
+    }; // Lock released here
+    let rows: usize = by_week.values().map(Vec::len).sum();
+    let weeks = by_week
+        .into_iter()
+        .map(|(week, rows)| Ok((week.to_string(), fields.select(&rows)?)))
+        .collect::<Result<serde_json::Map<String, serde_json::Value>, String>>()
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "weeks": weeks,
+        "meta": {
+            "from": from,
+            "to": to,
+            "rows": rows
         }
-    } // Lock released here
+    })))
+}
 
-    // Handle week >= 1 case
-    if week >= 1 {
-        // Step 1: Do all async work FIRST (without holding any locks)
-        let assignments = get_submitted_assignments(week).await.unwrap();
-        let submitted: Vec<&Assignment> = assignments.iter().filter(|a| a.is_submitted()).collect();
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    // RFC 3339 timestamp, e.g. the `until` of the previous poll
+    pub since: String,
+    pub week: Option<i32>,
+}
 
-        let mut name_to_assignment: HashMap<String, &Assignment> = HashMap::new();
-        let db_path = PathBuf::from("classroom.db");
+// Rows written and rows deleted since a point in time, so the table can poll
+// for edits instead of fetching the whole week again. The next poll passes
+// the returned `until` as `since`.
+#[get("/weekly_data/changes")]
+pub async fn get_weekly_data_changes(
+    _caller: Authenticated,
+    query: web::Query<ChangesQuery>,
+    fields: web::Query<FieldsQuery>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let since = chrono::DateTime::parse_from_rfc3339(query.since.trim())
+        .map_err(|_| actix_web::error::ErrorBadRequest("since must be an RFC 3339 timestamp"))?
+        .with_timezone(&Utc);
+    fields
+        .validate()
+        .map_err(actix_web::error::ErrorBadRequest)?;
 
-        for assignment in &submitted {
-            if let Some(participant_name) =
-                get_github_to_name_mapping(&db_path, &assignment.github_username)
-            {
-                name_to_assignment.insert(participant_name, assignment);
+    // Taken before reading, so a write racing the read is reported again by
+    // the next poll rather than missed
+    let until = Utc::now();
+    let week = query.week;
+    let changes = blocking(&db, move |db| db.read_changed_rows(since, week)).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "data": fields
+            .select(&changes.rows)
+            .map_err(actix_web::error::ErrorBadRequest)?,
+        "deleted": changes.deleted,
+        "meta": {
+            "since": since.to_rfc3339(),
+            "until": until.to_rfc3339()
+        }
+    })))
+}
+
+// Syncs and regroups a week, see `generate_week`, and returns its rows like
+// the GET. With `?dry_run=true` the regrouping and synced exercise columns
+// are returned as a preview and nothing is written.
+#[post("/weekly_data/{week}/generate")]
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_weekly_data(
+    Authenticated(caller): Authenticated,
+    dry_run: DryRun,
+    window: WeekWindow,
+    week: web::Path<i32>,
+    view: web::Query<RowsQuery>,
+    fields: web::Query<FieldsQuery>,
+    state: web::Data<std::sync::Mutex<Table>>,
+    sync_status: web::Data<std::sync::Mutex<SyncStatus>>,
+    generations: web::Data<WeekGenerations>,
+    forge: web::Data<dyn ForgeProvider>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    if week < 1 {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Only weeks from 1 on are generated",
+        ));
+    }
+    view.validate()
+        .and_then(|_| fields.validate())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    window.check([week])?;
+
+    // Groups are built from the previous week's attendance, without it every
+    // student would land in the absent group
+    let previous_empty = {
+        let state_table = state.lock().unwrap();
+        !state_table.rows.iter().any(|row| row.week == week - 1)
+    }; // Lock released here
+    if previous_empty {
+        warn!(
+            "Refused to generate week {}: week {} has no rows",
+            week,
+            week - 1
+        );
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": match week {
+                1 => "No students are enrolled yet, week 1 is built from the enrollment rows".to_string(),
+                _ => format!(
+                    "Week {} has no rows yet, generate it before week {}",
+                    week - 1,
+                    week
+                ),
             }
+        })));
+    }
+
+    if dry_run.is_set() {
+        // Not shared with other requests, as nothing is written
+        let (response, changes) = generate_week(
+            caller,
+            week,
+            window.deadline(week),
+            &state,
+            &sync_status,
+            &forge,
+            &db,
+            dry_run,
+        )
+        .await?;
+        return Ok(DryRun::preview(serde_json::json!({
+            "insert": changes.insert,
+            "update": changes.update,
+            "data": response.data,
+            "meta": response.meta
+        })));
+    }
+
+    info!("Generating weekly data for week {}", week);
+    // Held for the whole generation; it is an async lock, so waiting
+    // requests yield instead of blocking a worker
+    let slot = generations.slot(week);
+    let requested_at = Instant::now();
+    let mut last = slot.lock().await;
+    if let Some(last) = last.as_ref().filter(|g| g.finished_at >= requested_at) {
+        info!(
+            "Week {} was generated while waiting, reusing the result",
+            week
+        );
+        return Ok(last.response.paged(&view, &fields));
+    }
+
+    let (response, _) = generate_week(
+        caller,
+        week,
+        window.deadline(week),
+        &state,
+        &sync_status,
+        &forge,
+        &db,
+        dry_run,
+    )
+    .await?;
+    let body = response.paged(&view, &fields);
+    *last = Some(Generated {
+        finished_at: Instant::now(),
+        response,
+    });
+    Ok(body)
+}
+
+// A week's submissions, each matched to the participant it came from
+pub struct WeekSubmissions {
+    // Submitted assignments by participant name
+    pub by_name: HashMap<String, Assignment>,
+    pub warnings: Vec<SyncWarning>,
+}
+
+// Fetches a week's submissions and matches them to participants by GitHub
+// login. Submissions last committed after the deadline are reported as late.
+// With `record` set, the sync run, the submission history and the outcome
+// served by /sync/status are recorded.
+pub async fn sync_submissions(
+    week: i32,
+    deadline: Option<DateTime<Utc>>,
+    sync_status: &web::Data<std::sync::Mutex<SyncStatus>>,
+    forge: &web::Data<dyn ForgeProvider>,
+    db: &web::Data<dyn Storage>,
+    record: bool,
+) -> WeekSubmissions {
+    let started_at = Utc::now().to_rfc3339();
+    let week_sync = sync_week_assignments(forge.get_ref(), week).await;
+    let run = SyncRun {
+        week,
+        started_at,
+        finished_at: Utc::now().to_rfc3339(),
+        succeeded: true,
+        error: None,
+    };
+    let run = match week_sync
+        .warnings
+        .iter()
+        .find(|w| w.kind == SyncWarningKind::ClassroomUnavailable)
+    {
+        Some(warning) => SyncRun {
+            succeeded: false,
+            error: Some(warning.message.clone()),
+            ..run
+        },
+        None => run,
+    };
+    if record && let Err(e) = blocking(db, move |db| db.record_sync_run(&run)).await {
+        warn!("Failed to record week {} sync run: {}", week, e);
+    }
+    let mut warnings = week_sync.warnings;
+    if let Some(deadline) = deadline {
+        warnings.extend(late_submissions(&week_sync.assignments, deadline));
+    }
+    let submitted: Vec<&Assignment> = week_sync
+        .assignments
+        .iter()
+        .filter(|a| a.is_submitted())
+        .collect();
+
+    let mut by_name: HashMap<String, Assignment> = HashMap::new();
+
+    for assignment in &submitted {
+        if let Some(participant_name) =
+            get_github_to_name_mapping(db, &assignment.github_username).await
+        {
+            by_name.insert(participant_name, (*assignment).clone());
+        } else {
+            warnings.push(SyncWarning::for_user(
+                SyncWarningKind::RosterMismatch,
+                format!(
+                    "Submission from {} does not match any participant",
+                    assignment.github_username
+                ),
+                &assignment.github_username,
+            ));
         }
+    }
 
-        // Step 2: Get previous week data (short lock scope)
-        let prev_week_rows = {
-            let state_table = state.lock().unwrap();
-            let mut prev_week_rows: Vec<RowData> = state_table
-                .rows
-                .iter()
-                .filter(|row| row.week == week - 1)
-                .cloned()
-                .collect();
-
-            // Sort by attendance
-            prev_week_rows.sort_by(|a, b| {
-                b.attendance
-                    .partial_cmp(&a.attendance)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-                    .then_with(|| {
-                        b.total
-                            .partial_cmp(&a.total)
-                            .unwrap_or(std::cmp::Ordering::Equal)
-                            .then_with(|| b.name.cmp(&a.name))
-                    })
-            });
+    // Keep the submission history for the exercise analytics
+    let attempts = observed_attempts(&week_sync.assignments, week, &Utc::now().to_rfc3339());
+    if record && let Err(e) = blocking(db, move |db| db.record_exercise_attempts(&attempts)).await {
+        warn!("Failed to record week {} exercise attempts: {}", week, e);
+    }
 
-            prev_week_rows
-        }; // Lock released here
+    // Record the outcome so partial data is visible in /sync/status
+    if record {
+        let mut status = WeekSyncStatus::new(week);
+        status.assignments_returned = week_sync.assignments.len();
+        status.submitted = submitted.len();
+        status.matched = by_name.len();
+        status.warnings = warnings.clone();
+        sync_status.lock().unwrap().record(status);
+    }
 
-        // Step 3: Process data (no locks needed)
-        let tas: Vec<TA> = TA::all_variants()
-            .iter()
-            .cloned()
-            .filter(|ta| *ta != TA::Setu)
-            .collect();
+    WeekSubmissions { by_name, warnings }
+}
+
+// Syncs a week's submissions, regroups it from the previous week and
+// persists what changed. Running it again on unchanged input changes
+// nothing, since grades already entered are carried over. A dry run applies
+// the changes to a copy of the week and records nothing, not even the sync.
+#[allow(clippy::too_many_arguments)]
+async fn generate_week(
+    caller: Caller,
+    week: i32,
+    deadline: Option<DateTime<Utc>>,
+    state: &web::Data<std::sync::Mutex<Table>>,
+    sync_status: &web::Data<std::sync::Mutex<SyncStatus>>,
+    forge: &web::Data<dyn ForgeProvider>,
+    db: &web::Data<dyn Storage>,
+    dry_run: DryRun,
+) -> Result<(WeeklyDataResponse, WeekChanges), AppError> {
+    // Step 1: Do all async work FIRST (without holding any locks)
+    let WeekSubmissions { by_name, warnings } =
+        sync_submissions(week, deadline, sync_status, forge, db, !dry_run.is_set()).await;
+    let name_to_assignment: HashMap<String, &Assignment> = by_name
+        .iter()
+        .map(|(name, assignment)| (name.clone(), assignment))
+        .collect();
+
+    // Step 2: Snapshot previous and current week rows (short lock scope)
+    let (prev_week_rows, current_week_rows) = {
+        let state_table = state.lock().unwrap();
+        (
+            rows_for_week(&state_table.rows, week - 1),
+            rows_for_week(&state_table.rows, week),
+        )
+    }; // Lock released here
+
+    // Step 3: Regroup and merge grades (no locks needed)
+    let constraints = blocking(db, |db| db.read_grouping_constraints()).await?;
+    let tas = rotation(db).await?;
+    let regroup = |current_week_rows: &[RowData]| {
+        build_week_rows(
+            prev_week_rows.clone(),
+            current_week_rows,
+            week,
+            &tas,
+            &constraints,
+            &name_to_assignment,
+        )
+    };
+    let (mut result_rows, mut constraint_violations) = regroup(&current_week_rows);
+
+    if invariants::enabled() {
+        let violations = [
+            check_grouping(&prev_week_rows, &result_rows),
+            check_totals(&result_rows),
+        ]
+        .concat();
+        invariants::enforce(&format!("week {} sync", week), violations)?;
+    }
+
+    // Step 4: Batch update all changes (single lock scope)
+    let (changed_rows, changes, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+
+        // Grades entered while the week was syncing are merged in again, so
+        // the sync never writes back the rows it read before them
+        let live_rows = rows_for_week(&state_table.rows, week);
+        if live_rows != current_week_rows {
+            info!(
+                "Week {} was edited during the sync, merging the edits",
+                week
+            );
+            (result_rows, constraint_violations) = regroup(&live_rows);
+        }
 
-        let mut result_rows: Vec<RowData> = Vec::new();
-        let mut group_id: isize = -1;
-        let mut data_changed = false;
-
-        // Process each row and prepare updates
-        let mut rows_to_update: Vec<RowData> = Vec::new();
-
-        for (index, mut row) in prev_week_rows.into_iter().enumerate() {
-            if row.attendance.as_deref() == Some("no") {
-                row.group_id = format!("Group {}", 6);
-                row.ta = Some("Setu".to_string());
-            } else if row.attendance.as_deref() == Some("yes") {
-                if index < 30 {
-                    if index % 6 == 0 {
-                        group_id += 1;
-                    }
+        let checkpoint = state_table.checkpoint(&result_rows);
+        let mut preview = dry_run.is_set().then(|| Table::new(live_rows));
+        let table: &mut Table = match preview.as_mut() {
+            Some(copy) => copy,
+            None => &mut state_table,
+        };
+        let mut changed_rows = Vec::new();
+        let mut changes = WeekChanges::default();
+        for row in &mut result_rows {
+            let existed = table.rows.iter().any(|r| r.same_row(row));
+            if table.insert_or_update(row).unwrap() {
+                changed_rows.push(row.clone());
+                if existed {
+                    changes.update.push(row.clone());
                 } else {
-                    group_id += 1;
+                    changes.insert.push(row.clone());
                 }
-                let index = (group_id as usize) % tas.len();
-                let assigned_ta = &tas[(index + week as usize - 1) % tas.len()];
-                row.group_id = format!("Group {}", index + 1);
-                row.ta = Some(format!("{:?}", assigned_ta));
             }
-            row.week = week;
+        }
+        (changed_rows, changes, table.take_history(), checkpoint)
+    }; // Lock released here
 
-            // Check for existing data (need to query state again)
-            let existing_row = {
-                let state_table = state.lock().unwrap();
-                state_table
-                    .rows
-                    .iter()
-                    .find(|r| r.name == row.name && r.week == week)
-                    .cloned()
-            }; // Lock released here
-
-            if let Some(existing_row) = existing_row {
-                row.attendance = existing_row.attendance.clone();
-                row.fa = existing_row.fa;
-                row.fb = existing_row.fb;
-                row.fc = existing_row.fc;
-                row.fd = existing_row.fd;
-                row.bonus_attempt = existing_row.bonus_attempt;
-                row.bonus_answer_quality = existing_row.bonus_answer_quality;
-                row.bonus_follow_up = existing_row.bonus_follow_up;
-                row.exercise_submitted = existing_row.exercise_submitted.clone();
-                row.exercise_test_passing = existing_row.exercise_test_passing.clone();
-                row.exercise_good_documentation = existing_row.exercise_good_documentation.clone();
-                row.exercise_good_structure = existing_row.exercise_good_structure.clone();
-                row.total = existing_row.total;
-            } else {
-                data_changed = true;
-                row.attendance = Some("no".to_string());
-                row.fa = Some(0);
-                row.fb = Some(0);
-                row.fc = Some(0);
-                row.fd = Some(0);
-                row.bonus_attempt = Some(0);
-                row.bonus_answer_quality = Some(0);
-                row.bonus_follow_up = Some(0);
-                row.exercise_submitted = Some("no".to_string());
-                row.exercise_test_passing = Some("no".to_string());
-                row.exercise_good_documentation = Some("no".to_string());
-                row.exercise_good_structure = Some("no".to_string());
-                row.total = Some(0);
-            }
+    for violation in &constraint_violations {
+        warn!(
+            "Week {} grouping constraint {} not honored: {}",
+            week, violation.constraint_id, violation.reason
+        );
+    }
 
-            // Check if assignment data changed
-            if let Some(matching_assignment) = name_to_assignment.get(&row.name) {
-                println!(
-                    "Found matching assignment for {} in week {}: {:#?}",
-                    row.name, week, matching_assignment
-                );
-                if matching_assignment.get_week_pattern() == Some(week as u32) {
-                    let new_exercise_submitted = Some("yes".to_string());
-                    let new_exercise_test_passing =
-                        Some(if matching_assignment.points_awarded == "100" {
-                            "yes".to_string()
-                        } else {
-                            "no".to_string()
-                        });
-
-                    if row.exercise_submitted != new_exercise_submitted
-                        || row.exercise_test_passing != new_exercise_test_passing
-                    {
-                        data_changed = true;
-                        row.exercise_submitted = new_exercise_submitted;
-                        row.exercise_test_passing = new_exercise_test_passing;
-                        println!("Data has changed for {} in week {}", row.name, week);
-                    }
-                }
-            }
+    // Step 5: Persist the changed rows off the worker thread
+    if dry_run.is_set() {
+        info!(
+            "Previewed week {}: {} row(s) would change",
+            week,
+            changed_rows.len()
+        );
+    } else if !changed_rows.is_empty() {
+        info!(
+            "{} row(s) changed - writing to database for week {}",
+            changed_rows.len(),
+            week
+        );
+        // Changes made by the sync are attributed to whoever loaded the week
+        let actor = format!("sync:{}", caller.label());
+        persist_batch(state, db, changed_rows, history, actor, checkpoint).await?;
+    } else {
+        info!(
+            "No data changes detected for week {} - skipping database write",
+            week
+        );
+    }
 
-            rows_to_update.push(row.clone());
-            result_rows.push(row);
-        }
+    if !warnings.is_empty() {
+        warn!(
+            "Week {} synced with {} classroom warning(s)",
+            week,
+            warnings.len()
+        );
+    }
 
-        // Step 4: Batch update all changes (single lock scope)
-        {
-            let mut state_table = state.lock().unwrap();
+    Ok((
+        WeeklyDataResponse {
+            data: result_rows,
+            meta: WeeklyMeta {
+                week,
+                warnings,
+                constraint_violations,
+            },
+        },
+        changes,
+    ))
+}
 
-            for row in &rows_to_update {
-                state_table.insert_or_update(row).unwrap();
-            }
+#[derive(Debug, Deserialize)]
+pub struct RecheckQuery {
+    pub week: i32,
+}
 
-            if data_changed {
-                info!("Data changed - writing to database for week {}", week);
-                write_to_db(&PathBuf::from("classroom.db"), &state_table).unwrap();
-            } else {
-                info!(
-                    "No data changes detected for week {} - skipping database write",
-                    week
-                );
+// Re-checks one student's exercise repo right away, for "I just pushed"
+// requests, without regrouping or syncing the rest of the week
+#[post("/sync/student/{name}")]
+pub async fn recheck_student_submission(
+    Authenticated(caller): Authenticated,
+    window: WeekWindow,
+    name: web::Path<String>,
+    query: web::Query<RecheckQuery>,
+    state: web::Data<std::sync::Mutex<Table>>,
+    forge: web::Data<dyn ForgeProvider>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (name, week) = (
+        state.lock().unwrap().student_name(&name.into_inner()),
+        query.week,
+    );
+    if week < 1 {
+        return Err(actix_web::error::ErrorBadRequest("Week must be at least 1"));
+    }
+    window.check([week])?;
+
+    {
+        let state_table = state.lock().unwrap();
+        let Some(row) = state_table
+            .rows
+            .iter()
+            .find(|row| row.name == name && row.week == week)
+        else {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("{} has no row in week {}", name, week)
+            })));
+        };
+        if !caller.can_write_row(row, Some(row)) {
+            return Err(
+                AuthError::Forbidden(format!("{} is not in your assigned group", name)).into(),
+            );
+        }
+    } // Lock released here
+
+    let github = get_github_username(&db, &name).await;
+    if github.is_empty() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("{} has no GitHub username", name)
+        })));
+    }
+    let assignment = forge
+        .fetch_student_submission(week, &github)
+        .await
+        .map_err(|e| {
+            warn!("Failed to re-check {} for week {}: {}", github, week, e);
+            match e.retry_after() {
+                Some(retry_after) => ForgeRateLimited {
+                    forge: forge.name(),
+                    retry_after,
+                }
+                .into(),
+                None => actix_web::error::ErrorBadGateway(format!(
+                    "Failed to fetch the repo from {}",
+                    forge.name()
+                )),
             }
-        } // Lock released here
+        })?;
+
+    let assignments: Vec<Assignment> = assignment.into_iter().collect();
+    let attempts = observed_attempts(&assignments, week, &Utc::now().to_rfc3339());
+    if let Err(e) = blocking(&db, move |db| db.record_exercise_attempts(&attempts)).await {
+        warn!("Failed to record exercise attempts for {}: {}", github, e);
+    }
+    let result = assignments
+        .iter()
+        .filter(|a| a.is_submitted())
+        .find_map(|a| exercise_result(a, week));
 
-        return HttpResponse::Ok().json(result_rows);
+    // Same rule as the weekly sync: only a submission changes the row
+    let changed = result.as_ref().and_then(|result| {
+        let mut state_table = state.lock().unwrap();
+        let mut row = state_table
+            .rows
+            .iter()
+            .find(|row| row.name == name && row.week == week)?
+            .clone();
+        apply_exercise_result(&mut row, result);
+        let checkpoint = state_table.checkpoint(std::slice::from_ref(&row));
+        let changed = state_table.insert_or_update(&mut row).unwrap();
+        changed.then(|| (row, state_table.take_history(), checkpoint))
+    }); // Lock released here
+
+    let updated = changed.is_some();
+    if let Some((row, history, checkpoint)) = changed {
+        persist_batch(&state, &db, vec![row], history, caller.label(), checkpoint).await?;
     }
+    info!(
+        "Re-checked week {} exercise of {}: {}",
+        week,
+        name,
+        match &result {
+            Some(result) if result.tests_passing => "submitted, tests passing",
+            Some(_) => "submitted, tests failing",
+            None => "not submitted",
+        }
+    );
 
-    warn!("something went wrong {}", week);
-    HttpResponse::BadRequest().json(serde_json::json!({
-        "status": "error",
-        "message": "Invalid week number"
-    }))
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "name": name,
+        "week": week,
+        "github": github,
+        "repo_found": !assignments.is_empty(),
+        "submitted": result.is_some(),
+        "tests_passing": result.as_ref().is_some_and(|r| r.tests_passing),
+        "updated": updated,
+    })))
 }
 
+// Rows that already exist are only updated from their current version, given
+// as each row's `version` or, for a single row, an If-Match header. A row
+// that is invalid, outside the caller's group or out of date is reported as
+// failed with the reason, and the other rows are written without it.
 #[post("/weekly_data/{week}")]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_weekly_data(
+    Authenticated(caller): Authenticated,
+    dry_run: DryRun,
+    window: WeekWindow,
+    if_match: IfMatch,
+    mut idempotency_key: IdempotencyKey,
     _week: web::Path<i32>,
-    student_data: web::Json<Vec<RowData>>,
+    mut student_data: web::Json<Vec<RowData>>,
     state: web::Data<std::sync::Mutex<Table>>,
+    db: web::Data<dyn Storage>,
 ) -> Result<HttpResponse, actix_web::Error> {
     // Validate input early (no locks needed)
     if student_data.is_empty() {
@@ -276,63 +757,448 @@ pub async fn add_weekly_data(
         ));
     }
 
-    let db_path = PathBuf::from("classroom.db");
     let week_num = _week.into_inner();
+    window.check([week_num])?;
+    let mut failures = row_failures(&validate_rows(&student_data, week_num));
+    if !failures.is_empty() {
+        warn!(
+            "Skipping {} invalid row(s) of weekly data for week {}",
+            failures.len(),
+            week_num
+        );
+    }
+
+    if invariants::enabled() {
+        let valid: Vec<RowData> = student_data
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !failures.contains_key(index))
+            .map(|(_, row)| row.clone())
+            .collect();
+        invariants::enforce("weekly data update", check_totals(&valid))?;
+    }
+
     let first_student_name = student_data[0].name.clone(); // Clone for logging
 
-    // Single lock scope for all operations
+    // A retry of a request already applied gets the first response back.
+    // Dry runs change nothing, so they are neither replayed nor recorded.
+    let request_fingerprint = fingerprint(&(week_num, if_match.0, &*student_data));
+    if !dry_run.is_set()
+        && let Some(replayed) = idempotency_key
+            .replay(&db, &caller.label(), &request_fingerprint)
+            .await?
     {
+        return Ok(replayed);
+    }
+    if_match.apply(&mut student_data);
+
+    // Single lock scope for all in-memory changes
+    let (results, written, changed_rows, history, checkpoint, warnings) = {
         let mut state_table = state.lock().unwrap();
 
-        // Update all rows in the table
-        for incoming_row in student_data.iter() {
-            state_table.insert_or_update(incoming_row)?;
+        // TAs may only write rows in their own group for the week, and rows
+        // that exist only from the version they were read at
+        for (index, incoming) in student_data.iter().enumerate() {
+            if failures.contains_key(&index) {
+                continue;
+            }
+            let existing = state_table
+                .rows
+                .iter()
+                .find(|r| r.name == incoming.name && r.week == incoming.week);
+            if !caller.can_write_row(incoming, existing) {
+                warn!(
+                    target: "audit",
+                    "{:?} attempted to write a row outside their group: {}",
+                    caller, incoming.name
+                );
+                failures.insert(index, "row outside your assigned group".to_string());
+            } else if let Err(conflict) =
+                check_versions(&state_table.rows, std::slice::from_ref(incoming))
+            {
+                let reason = match &conflict {
+                    VersionConflict::Stale(current) => format!(
+                        "{} (current version {})",
+                        conflict,
+                        current[0].version.unwrap_or(1)
+                    ),
+                    VersionConflict::Missing(_) => conflict.to_string(),
+                };
+                failures.insert(index, reason);
+            }
         }
+        let failed = |index: usize, row: &RowData| RowResult {
+            row: index,
+            name: row.name.clone(),
+            week: row.week,
+            status: RowStatus::Failed,
+            reason: failures.get(&index).cloned(),
+            version: None,
+        };
 
-        // Write to database while still holding the lock
-        // This ensures consistency between memory and disk
-        write_to_db(&db_path, &state_table)?;
-    } // Lock released here
+        if dry_run.is_set() {
+            let mut insert = Vec::new();
+            let mut update = Vec::new();
+            let mut rejected = Vec::new();
+            for (index, incoming) in student_data.iter().enumerate() {
+                if failures.contains_key(&index) {
+                    rejected.push(failed(index, incoming));
+                } else if state_table
+                    .rows
+                    .iter()
+                    .any(|r| r.name == incoming.name && r.week == incoming.week)
+                {
+                    update.push(incoming);
+                } else {
+                    insert.push(incoming);
+                }
+            }
+            return Ok(DryRun::preview(serde_json::json!({
+                "insert": insert,
+                "update": update,
+                "failed": rejected
+            })));
+        }
+
+        // Update the rows in the table, keeping the ones that changed
+        let accepted: Vec<RowData> = student_data
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !failures.contains_key(index))
+            .map(|(_, row)| row.clone())
+            .collect();
+        let checkpoint = state_table.checkpoint(&accepted);
+        let mut results = Vec::new();
+        let mut written = Vec::new();
+        let mut changed_rows = Vec::new();
+        for (index, incoming_row) in student_data.iter_mut().enumerate() {
+            if failures.contains_key(&index) {
+                results.push(failed(index, incoming_row));
+                continue;
+            }
+            let existed = state_table.rows.iter().any(|r| r.same_row(incoming_row));
+            let status = if !state_table.insert_or_update(incoming_row)? {
+                RowStatus::Unchanged
+            } else if existed {
+                RowStatus::Updated
+            } else {
+                RowStatus::Created
+            };
+            if status != RowStatus::Unchanged {
+                changed_rows.push(incoming_row.clone());
+            }
+            results.push(RowResult {
+                row: index,
+                name: incoming_row.name.clone(),
+                week: incoming_row.week,
+                status,
+                reason: None,
+                version: incoming_row.version,
+            });
+            written.push(incoming_row.clone());
+        }
+
+        // Judged after the update so the week holds every group's latest
+        // grades. Warnings only; the rows are written either way.
+        let weeks: BTreeSet<i32> = written.iter().map(|row| row.week).collect();
+        let warnings: Vec<_> = weeks
+            .into_iter()
+            .flat_map(|week| {
+                grading_flags(
+                    &rows_for_week(&state_table.rows, week),
+                    &rows_for_week(&written, week),
+                )
+            })
+            .collect();
+
+        (
+            results,
+            written,
+            changed_rows,
+            state_table.take_history(),
+            checkpoint,
+            warnings,
+        )
+    }; // Lock released here
+
+    // Write to database on the blocking pool; the lock is not held across
+    // the await so a slow write does not stall the worker. All rows persist
+    // or none do, and a failed write is undone in memory too.
+    persist_batch(
+        &state,
+        &db,
+        changed_rows,
+        history,
+        caller.label(),
+        checkpoint,
+    )
+    .await?;
 
     // Log after releasing the lock
-    info!("added data for {} in week {}", first_student_name, week_num);
+    let counts = RowCounts::of(&results);
+    info!(
+        "added data for {} in week {}: {:?}",
+        first_student_name, week_num, counts
+    );
+    if !warnings.is_empty() {
+        info!(
+            "{} grading warning(s) on data from {}",
+            warnings.len(),
+            caller.label()
+        );
+    }
 
-    Ok(HttpResponse::Ok().body("Weekly data inserted/updated successfully"))
+    // Only a batch with no row written at all is an error
+    let status = if counts.failed == results.len() {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    };
+    let message = if counts.failed == 0 {
+        "Weekly data inserted/updated successfully".to_string()
+    } else {
+        format!("{} of {} rows failed", counts.failed, results.len())
+    };
+    let body = serde_json::json!({
+        "message": message,
+        "data": written,
+        "results": results,
+        "meta": {
+            "week": week_num,
+            "warnings": warnings,
+            "counts": counts
+        }
+    });
+    idempotency_key
+        .remember(&db, &caller.label(), request_fingerprint, status, &body)
+        .await;
+
+    Ok(HttpResponse::build(status).json(body))
+}
+
+// Makes the posted rows the whole of a week: rows of the week missing from
+// the payload are deleted. Every row is validated before anything changes,
+// and the swap is applied in one lock scope and one transaction.
+#[put("/weekly_data/{week}")]
+pub async fn replace_weekly_data(
+    _admin: Admin,
+    dry_run: DryRun,
+    window: WeekWindow,
+    week: web::Path<i32>,
+    mut student_data: web::Json<Vec<RowData>>,
+    state: web::Data<std::sync::Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week_num = week.into_inner();
+    window.check([week_num])?;
+
+    let mut invalid = validate_rows(&student_data, week_num);
+    let mut seen = HashSet::new();
+    for (index, row) in student_data.iter().enumerate() {
+        if !seen.insert(row.name.as_str()) {
+            invalid.push(FieldError {
+                row: index,
+                name: row.name.clone(),
+                field: "name".to_string(),
+                message: "appears more than once in the week".to_string(),
+            });
+        }
+    }
+    if !invalid.is_empty() {
+        warn!(
+            "Rejected replacement of week {}: {} invalid field(s)",
+            week_num,
+            invalid.len()
+        );
+        return Err(InvalidRows(invalid).into());
+    }
+    if invariants::enabled() {
+        invariants::enforce("week replacement", check_totals(&student_data))?;
+    }
+
+    // Single lock scope for all in-memory changes
+    let (removed, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        if let Err(conflict) = check_versions(&state_table.rows, &student_data) {
+            warn!("Rejected replacement of week {}: {}", week_num, conflict);
+            return Err(conflict.into());
+        }
+
+        let removed: Vec<RowData> = state_table
+            .rows
+            .iter()
+            .filter(|row| row.week == week_num)
+            .filter(|row| {
+                !student_data
+                    .iter()
+                    .any(|incoming| incoming.name == row.name)
+            })
+            .cloned()
+            .collect();
+        if dry_run.is_set() {
+            let (update, insert): (Vec<&RowData>, Vec<&RowData>) =
+                student_data.iter().partition(|incoming| {
+                    state_table
+                        .rows
+                        .iter()
+                        .any(|r| r.name == incoming.name && r.week == week_num)
+                });
+            return Ok(DryRun::preview(serde_json::json!({
+                "insert": insert,
+                "update": update,
+                "delete": removed
+            })));
+        }
+
+        // Reverting the checkpoint also brings the removed rows back
+        let checkpoint = state_table.checkpoint(&[&student_data[..], &removed].concat());
+        state_table
+            .rows
+            .retain(|row| row.week != week_num || !removed.iter().any(|r| r.name == row.name));
+        for incoming_row in student_data.iter_mut() {
+            state_table.insert_or_update(incoming_row)?;
+        }
+        (removed, state_table.take_history(), checkpoint)
+    }; // Lock released here
+
+    let rows = student_data.clone();
+    let actor = Caller::Admin.label();
+    let replaced = blocking(&db, move |db| {
+        db.replace_week(week_num, &rows, &history, &actor)
+    })
+    .await;
+    if let Err(e) = replaced {
+        state.lock().unwrap().revert(checkpoint);
+        return Err(e.into());
+    }
+    info!(
+        target: "audit",
+        "admin replaced week {} ({} rows, {} deleted)",
+        week_num,
+        student_data.len(),
+        removed.len()
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Week replaced successfully",
+        "data": student_data.into_inner(),
+        "meta": {
+            "week": week_num,
+            "deleted": removed
+        }
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteQuery {
+    // Removes the row for good instead of marking it deleted
+    #[serde(default)]
+    pub permanent: bool,
 }
 
 #[post("/del/{week}")]
+#[allow(clippy::too_many_arguments)]
 pub async fn delete_data(
+    Authenticated(caller): Authenticated,
+    _totp: SecondFactor,
+    dry_run: DryRun,
+    window: WeekWindow,
+    query: web::Query<DeleteQuery>,
     row_to_delete: web::Json<RowData>,
     state: web::Data<std::sync::Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+    backups: web::Data<Backups>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let db_path = PathBuf::from("classroom.db");
-
     // Extract data for logging before acquiring lock
     let student_name = row_to_delete.name.clone();
     let student_week = row_to_delete.week;
+    window.check([student_week])?;
+    let matches = |row: &RowData| {
+        row.name == row_to_delete.name
+            && row.mail == row_to_delete.mail
+            && row.week == row_to_delete.week
+    };
 
-    // Track if deletion actually occurred
-    let deletion_occurred = {
-        let mut state_table = state.lock().unwrap();
+    let refuse = |existing: &RowData| -> actix_web::Error {
+        warn!(
+            target: "audit",
+            "{:?} attempted to delete {} outside their group",
+            caller, existing.name
+        );
+        AuthError::Forbidden(format!("{} is not in your assigned group", existing.name)).into()
+    };
 
-        // Find and remove the matching row
-        if let Some(pos) = state_table.rows.iter().position(|row| {
-            row.name == row_to_delete.name
-                && row.mail == row_to_delete.mail
-                && row.week == row_to_delete.week
-        }) {
-            state_table.rows.remove(pos);
+    // Check the row exists and may be deleted
+    let found = {
+        let state_table = state.lock().unwrap();
 
-            // Write to database while holding the lock to ensure consistency
-            write_to_db(&db_path, &state_table)?;
+        if let Some(existing) = state_table.rows.iter().find(|row| matches(row)) {
+            if !caller.can_write_row(existing, Some(existing)) {
+                return Err(refuse(existing));
+            }
+            if dry_run.is_set() {
+                return Ok(DryRun::preview(serde_json::json!({
+                    "delete": [existing]
+                })));
+            }
             true
+        } else if dry_run.is_set() {
+            return Ok(DryRun::preview(serde_json::json!({ "delete": [] })));
         } else {
             false
         }
     }; // Lock released here
 
-    if deletion_occurred {
-        info!("Deleted data for {} in week {}", student_name, student_week);
+    // Soft-deleted rows can be restored; snapshot before anything is
+    // removed for good, without holding the lock
+    let permanent = query.permanent;
+    if found && permanent {
+        backup_before(&backups, BackupReason::BeforeStudentRemoval).await?;
+    }
+
+    // Checked again under the lock it is removed with, as the row may have
+    // moved to another group during the backup
+    let removed = if found {
+        let mut state_table = state.lock().unwrap();
+        match state_table.rows.iter().position(matches) {
+            Some(pos) => {
+                let existing = &state_table.rows[pos];
+                if !caller.can_write_row(existing, Some(existing)) {
+                    return Err(refuse(existing));
+                }
+                let checkpoint = state_table.checkpoint(std::slice::from_ref(existing));
+                state_table.rows.remove(pos);
+                Some(checkpoint)
+            }
+            None => None,
+        }
+    } else {
+        None
+    }; // Lock released here
+
+    if let Some(checkpoint) = removed {
+        let name = student_name.clone();
+        let deleted = blocking(&db, move |db| {
+            if permanent {
+                db.purge_rows(&name, Some(student_week))
+            } else {
+                db.delete_rows(&name, Some(student_week))
+            }
+        })
+        .await;
+        // Put the row back if the database still holds it
+        if let Err(e) = deleted {
+            state.lock().unwrap().revert(checkpoint);
+            return Err(e.into());
+        }
+        info!(
+            target: "audit",
+            "{} {} data for {} in week {}",
+            caller.label(),
+            if permanent { "permanently deleted" } else { "deleted" },
+            student_name,
+            student_week
+        );
         Ok(HttpResponse::Ok().body("Weekly data deleted successfully"))
     } else {
         info!(
@@ -342,3 +1208,194 @@ pub async fn delete_data(
         Ok(HttpResponse::Ok().body("No matching data found to delete"))
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+    pub name: String,
+    pub week: i32,
+}
+
+// Undeletes a soft-deleted row and puts it back in the live table
+#[post("/weekly_data/restore")]
+pub async fn restore_data(
+    Authenticated(caller): Authenticated,
+    dry_run: DryRun,
+    window: WeekWindow,
+    request: web::Json<RestoreRequest>,
+    state: web::Data<std::sync::Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let RestoreRequest { name, week } = request.into_inner();
+    window.check([week])?;
+
+    let lookup = name.clone();
+    let Some(row) = blocking(&db, move |db| db.read_deleted_row(&lookup, week)).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No deleted data for {} in week {}", name, week)
+        })));
+    };
+    if !caller.can_write_row(&row, Some(&row)) {
+        warn!(
+            target: "audit",
+            "{:?} attempted to restore {} outside their group",
+            caller, row.name
+        );
+        return Err(
+            AuthError::Forbidden(format!("{} is not in your assigned group", row.name)).into(),
+        );
+    }
+    if dry_run.is_set() {
+        return Ok(DryRun::preview(serde_json::json!({ "restore": [row] })));
+    }
+
+    let target = name.clone();
+    if !blocking(&db, move |db| db.restore_row(&target, week)).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No deleted data for {} in week {}", name, week)
+        })));
+    }
+
+    {
+        let mut state_table = state.lock().unwrap();
+        state_table
+            .rows
+            .retain(|existing| !(existing.name == row.name && existing.week == row.week));
+        state_table.rows.push(row.clone());
+    } // Lock released here
+
+    info!(
+        target: "audit",
+        "{} restored data for {} in week {}",
+        caller.label(),
+        name,
+        week
+    );
+    Ok(HttpResponse::Ok().json(row))
+}
+
+// Field-level changes of one weekly row, newest first, for tracing disputed
+// grades. TAs may only see rows of their own group.
+#[get("/history/{name}/{week}")]
+pub async fn get_row_history(
+    Authenticated(caller): Authenticated,
+    path: web::Path<(String, i32)>,
+    state: web::Data<std::sync::Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (name, week) = path.into_inner();
+    let name = state.lock().unwrap().student_name(&name);
+
+    let allowed = {
+        let state_table = state.lock().unwrap();
+        let row = state_table
+            .rows
+            .iter()
+            .find(|row| row.name == name && row.week == week);
+        match row {
+            Some(row) => caller.can_write_row(row, Some(row)),
+            None => caller == Caller::Admin,
+        }
+    }; // Lock released here
+    if !allowed {
+        return Err(AuthError::Forbidden(format!("{} is not in your assigned group", name)).into());
+    }
+
+    let history = blocking(&db, move |db| db.read_row_history(&name, week)).await?;
+    Ok(HttpResponse::Ok().json(history))
+}
+
+// Reverts the most recent change to a weekly row, i.e. every field changed
+// by the last write to it. The reversal is a change of its own, recorded in
+// the history, so undoing twice restores the change.
+#[post("/weekly_data/{week}/{name}/undo")]
+pub async fn undo_row_change(
+    Authenticated(caller): Authenticated,
+    dry_run: DryRun,
+    window: WeekWindow,
+    path: web::Path<(i32, String)>,
+    state: web::Data<std::sync::Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (week, name) = path.into_inner();
+    let name = state.lock().unwrap().student_name(&name);
+    window.check([week])?;
+
+    let history_name = name.clone();
+    let history = blocking(&db, move |db| db.read_row_history(&history_name, week)).await?;
+    let Some(latest) = history.first() else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No changes to undo for {} in week {}", name, week)
+        })));
+    };
+    // Written together, so recorded with the same time and actor
+    let last_change: Vec<RowChange> = history
+        .iter()
+        .take_while(|entry| {
+            entry.change.changed_at == latest.change.changed_at && entry.actor == latest.actor
+        })
+        .map(|entry| entry.change.clone())
+        .collect();
+    if last_change
+        .iter()
+        .any(|change| change.field == "group_id" && change.old_value.is_none())
+    {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("The last change added {} to week {}, delete the row instead", name, week)
+        })));
+    }
+
+    let (reverted, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        let Some(row) = state_table
+            .rows
+            .iter()
+            .find(|row| row.name == name && row.week == week)
+        else {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("{} has no row in week {}", name, week)
+            })));
+        };
+        let mut reverted = revert_changes(row, &last_change)?;
+        reverted.version = None;
+        if !caller.can_write_row(&reverted, Some(row)) {
+            return Err(
+                AuthError::Forbidden(format!("{} is not in your assigned group", name)).into(),
+            );
+        }
+        if dry_run.is_set() {
+            return Ok(DryRun::preview(serde_json::json!({
+                "revert": last_change,
+                "update": [reverted]
+            })));
+        }
+
+        let checkpoint = state_table.checkpoint(std::slice::from_ref(&reverted));
+        state_table.insert_or_update(&mut reverted)?;
+        (reverted, state_table.take_history(), checkpoint)
+    }; // Lock released here
+
+    let actor = format!("undo:{}", caller.label());
+    persist_batch(
+        &state,
+        &db,
+        vec![reverted.clone()],
+        history,
+        actor,
+        checkpoint,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        "{} undid the change by {} to {} in week {} ({} field(s))",
+        caller.label(),
+        latest.actor,
+        name,
+        week,
+        last_change.len()
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "reverted": last_change,
+        "row": reverted
+    })))
+}
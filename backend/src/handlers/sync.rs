@@ -0,0 +1,114 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::Authenticated;
+use crate::services::sync_slo::{SyncSlo, slo_report};
+use crate::utils::forge::{ForgeCache, SyncWarning};
+use crate::utils::reload::Reloadable;
+use crate::utils::types::AppError;
+use actix_web::{HttpResponse, Responder, delete, get, web};
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Outcome of the most recent Classroom sync for a single week
+#[derive(Debug, Clone, Serialize)]
+pub struct WeekSyncStatus {
+    pub week: i32,
+    pub synced_at: String,
+    pub assignments_returned: usize,
+    pub submitted: usize,
+    pub matched: usize,
+    pub warnings: Vec<SyncWarning>,
+}
+
+impl WeekSyncStatus {
+    pub fn new(week: i32) -> Self {
+        WeekSyncStatus {
+            week,
+            synced_at: Utc::now().to_rfc3339(),
+            assignments_returned: 0,
+            submitted: 0,
+            matched: 0,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SyncStatus {
+    weeks: BTreeMap<i32, WeekSyncStatus>,
+}
+
+impl SyncStatus {
+    pub fn record(&mut self, status: WeekSyncStatus) {
+        self.weeks.insert(status.week, status);
+    }
+
+    pub fn weeks(&self) -> impl Iterator<Item = &WeekSyncStatus> {
+        self.weeks.values()
+    }
+}
+
+#[get("/sync/status")]
+pub async fn get_sync_status(sync_status: web::Data<Mutex<SyncStatus>>) -> impl Responder {
+    info!("Fetching classroom sync status");
+
+    let weeks: Vec<WeekSyncStatus> = {
+        let sync_status = sync_status.lock().unwrap();
+        sync_status.weeks().cloned().collect()
+    }; // Lock released here
+
+    let warning_count: usize = weeks.iter().map(|w| w.warnings.len()).sum();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "weeks": weeks,
+        "warning_count": warning_count
+    }))
+}
+
+// Success rate and freshness of the exercise sync over the rolling window
+#[get("/sync/slo")]
+pub async fn get_sync_slo(
+    db: web::Data<dyn Storage>,
+    slo: web::Data<Reloadable<SyncSlo>>,
+) -> Result<HttpResponse, AppError> {
+    info!("Fetching sync SLO report");
+
+    let runs = blocking(&db, |db| db.read_sync_runs()).await?;
+    let report = slo_report(&runs, &slo.get(), Utc::now());
+    for alert in &report.alerts {
+        warn!("Sync SLO: {}", alert);
+    }
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CacheQuery {
+    // Only this week; every week without it
+    pub week: Option<i32>,
+}
+
+// Forgets cached forge submissions, so the next sync asks the forge again,
+// e.g. right after a deadline instead of once the cache expires
+#[delete("/sync/cache")]
+pub async fn bust_forge_cache(
+    Authenticated(caller): Authenticated,
+    query: web::Query<CacheQuery>,
+    cache: web::Data<ForgeCache>,
+) -> HttpResponse {
+    let busted = cache.bust(query.week);
+    info!(
+        "{} cleared the forge cache ({}): {} week(s)",
+        caller.label(),
+        query
+            .week
+            .map_or_else(|| "all weeks".to_string(), |week| format!("week {}", week)),
+        busted
+    );
+    HttpResponse::Ok().json(serde_json::json!({
+        "week": query.week,
+        "cleared": busted
+    }))
+}
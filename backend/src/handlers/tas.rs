@@ -0,0 +1,280 @@
+use crate::database::storage::{Storage, blocking, github_login, persist_batch};
+use crate::handlers::auth::{
+    Admin, AuthError, Caller, ClientInfo, LockoutTracker, SessionStore, TA, frontend_url,
+    lockout_keys, random_hex, token_hash,
+};
+use crate::handlers::communications::record_sent;
+use crate::handlers::dry_run::DryRun;
+use crate::handlers::two_factor::SecondFactor;
+use crate::services::grouping::{absent_ta, active_tas, reassign_groups};
+use crate::services::weekly::rows_for_week;
+use crate::utils::mailer::Mailer;
+use crate::utils::types::{AppError, CommunicationKind, TaInvite, TaSetup, Table};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, post, web};
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use serde::Deserialize;
+use std::sync::Mutex;
+
+// How long a TA invite link stays valid
+const INVITE_TTL_DAYS: i64 = 7;
+
+// TAs that lead groups, without the offboarded ones
+pub async fn rotation(db: &web::Data<dyn Storage>) -> Result<Vec<TA>, AppError> {
+    let inactive = blocking(db, |db| db.read_inactive_tas()).await?;
+    Ok(active_tas(&inactive))
+}
+
+// Offboarded TAs cannot sign in; a failed read keeps them out as well
+pub async fn is_active(db: &web::Data<dyn Storage>, ta: &TA) -> bool {
+    match blocking(db, |db| db.read_inactive_tas()).await {
+        Ok(inactive) => !inactive.contains(&ta.name()),
+        Err(e) => {
+            warn!("Failed to read inactive TAs: {}", e);
+            false
+        }
+    }
+}
+
+// Takes a TA who leaves mid-cohort out of the rotation, hands the groups
+// they lead in the current week to the remaining TAs and ends their
+// sessions. Later weeks are grouped without them.
+#[post("/tas/{ta}/offboard")]
+pub async fn offboard_ta(
+    _admin: Admin,
+    _totp: SecondFactor,
+    dry_run: DryRun,
+    ta: web::Path<String>,
+    state: web::Data<Mutex<Table>>,
+    sessions: web::Data<Mutex<SessionStore>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(ta) = TA::from_name(&ta) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown TA: {}", ta)
+        })));
+    };
+    if ta == absent_ta() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} leads the absent group and cannot be offboarded", ta.name())
+        })));
+    }
+
+    let remaining: Vec<TA> = rotation(&db)
+        .await?
+        .into_iter()
+        .filter(|other| *other != ta)
+        .collect();
+    if remaining.is_empty() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "No other TA is left to take over the groups"
+        })));
+    }
+
+    let (week, mut reassigned) = {
+        let state_table = state.lock().unwrap();
+        let week = state_table
+            .rows
+            .iter()
+            .map(|row| row.week)
+            .max()
+            .unwrap_or(0);
+        let rows = rows_for_week(&state_table.rows, week);
+        (week, reassign_groups(&rows, &ta, &remaining))
+    }; // Lock released here
+    if dry_run.is_set() {
+        return Ok(DryRun::preview(serde_json::json!({
+            "ta": ta.name(),
+            "week": week,
+            "reassign": reassigned
+        })));
+    }
+
+    let (name, by) = (ta.name(), Caller::Admin.label());
+    blocking(&db, move |db| db.deactivate_ta(&name, &by)).await?;
+    let (changed, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        let checkpoint = state_table.checkpoint(&reassigned);
+        let mut changed = Vec::new();
+        for row in &mut reassigned {
+            if state_table.insert_or_update(row)? {
+                changed.push(row.clone());
+            }
+        }
+        (changed, state_table.take_history(), checkpoint)
+    }; // Lock released here
+    let updated = changed.len();
+    if !changed.is_empty() {
+        let actor = Caller::Admin.label();
+        persist_batch(&state, &db, changed, history, actor, checkpoint).await?;
+    }
+    let ended = sessions.lock().unwrap().revoke_ta(ta.clone());
+
+    info!(
+        target: "audit",
+        "TA {} offboarded: {} row(s) of week {} reassigned, {} session(s) ended",
+        ta.name(),
+        updated,
+        week,
+        ended
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "ta": ta.name(),
+        "week": week,
+        "reassigned": reassigned,
+        "sessions_ended": ended
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteRequest {
+    pub email: String,
+    // Name the TA gets in the `ta` column, e.g. "Priya"
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptInvite {
+    pub token: String,
+    #[serde(flatten)]
+    pub setup: TaSetup,
+}
+
+fn valid_ta_name(name: &str) -> bool {
+    (2..=32).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// Invites a new TA. The emailed link lets them set up their profile and
+// sign in once; accepting it adds them to the group rotation. Without a
+// mailer the link is returned for the admin to pass on.
+#[post("/tas/invites")]
+pub async fn invite_ta(
+    _admin: Admin,
+    _totp: SecondFactor,
+    dry_run: DryRun,
+    item: web::Json<InviteRequest>,
+    mailer: Option<web::Data<Mailer>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InviteRequest { email, name } = item.into_inner();
+    let (email, name) = (email.trim().to_string(), name.trim().to_string());
+    if !email.contains('@') || !valid_ta_name(&name) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "An email and a name of 2 to 32 letters or digits are required"
+        })));
+    }
+    if TA::from_name(&name).is_some() || TA::from_email(&email).is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("A TA named {} or with that email already exists", name)
+        })));
+    }
+
+    let now = Utc::now();
+    let invite = TaInvite {
+        email,
+        name,
+        invited_by: Caller::Admin.label(),
+        created_at: now.to_rfc3339(),
+        expires_at: (now + Duration::days(INVITE_TTL_DAYS)).to_rfc3339(),
+    };
+    if dry_run.is_set() {
+        return Ok(DryRun::preview(serde_json::json!({ "invite": invite })));
+    }
+
+    let token = random_hex(32);
+    let (hash, stored) = (token_hash(&token), invite.clone());
+    blocking(&db, move |db| db.create_ta_invite(&hash, &stored)).await?;
+
+    let link = format!("{}/?ta_invite={}", frontend_url(), token);
+    let emailed = match mailer {
+        Some(mailer) => {
+            let body = format!(
+                "You have been invited to join the cohort as a TA.\n\nUse this link to link your GitHub and Discord accounts and choose how you are notified:\n\n{}\n\nIt expires in {} days and can only be used once.",
+                link, INVITE_TTL_DAYS
+            );
+            match mailer.send(&invite.email, "Your TA invite", body).await {
+                Ok(()) => {
+                    record_sent(
+                        &db,
+                        &invite.name,
+                        CommunicationKind::Email,
+                        "Your TA invite",
+                        None,
+                        &invite.invited_by,
+                    )
+                    .await;
+                    true
+                }
+                Err(e) => {
+                    warn!("Failed to send TA invite: {}", e);
+                    false
+                }
+            }
+        }
+        None => false,
+    };
+
+    info!(
+        target: "audit",
+        "Invited {} as TA {}",
+        invite.email,
+        invite.name
+    );
+    let mut body = serde_json::json!({ "invite": invite, "emailed": emailed });
+    if !emailed {
+        body["link"] = serde_json::Value::String(link);
+    }
+    Ok(HttpResponse::Created().json(body))
+}
+
+// Redeems a TA invite: records the TA's GitHub, Discord and notification
+// preferences, adds them to the rotation and signs them in. The invite token
+// is the only credential and works once.
+#[post("/tas/invites/accept")]
+pub async fn accept_ta_invite(
+    item: web::Json<AcceptInvite>,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+    sessions: web::Data<Mutex<SessionStore>>,
+    db: web::Data<dyn Storage>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let keys = lockout_keys(&req, None);
+    if let Some(until) = lockouts.lock().unwrap().locked_until(&keys) {
+        return Ok(AuthError::LockedOut { until }.error_response());
+    }
+
+    let AcceptInvite { token, setup } = item.into_inner();
+    let setup = TaSetup {
+        github: github_login(setup.github.trim()),
+        discord: setup.discord.trim().to_string(),
+        ..setup
+    };
+    if setup.github.is_empty() || setup.discord.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "GitHub and Discord accounts are required"
+        })));
+    }
+
+    let hash = token_hash(&token);
+    let Some(profile) = blocking(&db, move |db| db.accept_ta_invite(&hash, &setup)).await? else {
+        lockouts.lock().unwrap().record_failure(&keys);
+        return Ok(AuthError::Unauthorized.error_response());
+    };
+    lockouts.lock().unwrap().record_success(&keys);
+
+    let ta = TA::register(&profile.email, &profile.name);
+    let session = sessions
+        .lock()
+        .unwrap()
+        .create(ta.clone(), ClientInfo::of(&req));
+    info!(
+        target: "audit",
+        "{} accepted their TA invite and joined the rotation",
+        ta.name()
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "token": session,
+        "ta": ta.name(),
+        "profile": profile
+    })))
+}
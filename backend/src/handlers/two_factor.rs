@@ -0,0 +1,195 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, AuthError, Caller, LockoutTracker, lockout_keys};
+use actix_web::dev::Payload;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, delete, get, post, web,
+};
+use log::{info, warn};
+use serde::Deserialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+// Header carrying the current code for operations guarded by SecondFactor
+pub const TOTP_HEADER: &str = "x-totp-code";
+
+const TOTP_ISSUER: &str = "admin-panel";
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+// Secret and time step of the last accepted code. Each step is accepted
+// once, so a code seen in use cannot be replayed within its window.
+static LAST_ACCEPTED: Mutex<Option<(String, u64)>> = Mutex::new(None);
+
+// RFC 6238 defaults understood by every authenticator app. Skew is handled
+// in matching_step, which needs to know the step a code belongs to.
+fn totp_for(secret: &str) -> Option<TOTP> {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+    TOTP::new(Algorithm::SHA1, 6, 0, 30, bytes).ok()
+}
+
+// Time step the code was generated for, allowing one step of clock skew
+fn matching_step(secret: &str, code: &str) -> Option<u64> {
+    let totp = totp_for(secret)?;
+    let current = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() / totp.step;
+    (current.saturating_sub(1)..=current + 1).find(|step| totp.check(code.trim(), step * totp.step))
+}
+
+fn accept_code(secret: &str, code: &str) -> bool {
+    let Some(step) = matching_step(secret, code) else {
+        return false;
+    };
+    let mut last = LAST_ACCEPTED.lock().unwrap();
+    if matches!(&*last, Some((used, last_step)) if used == secret && step <= *last_step) {
+        return false;
+    }
+    *last = Some((secret.to_string(), step));
+    true
+}
+
+// Checks a code against the confirmed secret. Wrong and replayed codes count
+// towards lockouts, like failed sign-ins.
+fn check_code(req: &HttpRequest, secret: &str, code: Option<&str>) -> Result<(), Error> {
+    let lockouts = req
+        .app_data::<web::Data<Mutex<LockoutTracker>>>()
+        .ok_or_else(|| ErrorInternalServerError("Auth state not configured"))?;
+    let keys = lockout_keys(req, None);
+    let mut lockouts = lockouts.lock().unwrap();
+    if let Some(until) = lockouts.locked_until(&keys) {
+        return Err(AuthError::LockedOut { until }.into());
+    }
+
+    match code {
+        Some(code) if accept_code(secret, code) => {
+            lockouts.record_success(&keys);
+            Ok(())
+        }
+        Some(_) => {
+            lockouts.record_failure(&keys);
+            warn!(
+                target: "audit",
+                "Rejected invalid TOTP code for {} {}",
+                req.method(),
+                req.path()
+            );
+            Err(AuthError::SecondFactorRequired.into())
+        }
+        None => Err(AuthError::SecondFactorRequired.into()),
+    }
+}
+
+// Extractor for destructive operations. Once the admin has confirmed TOTP
+// enrollment, admin requests must carry a valid code in `X-TOTP-Code`.
+// TA callers are not affected. Wrong codes count towards lockouts.
+pub struct SecondFactor;
+
+impl SecondFactor {
+    async fn check(req: HttpRequest) -> Result<Self, Error> {
+        if req.extensions().get::<Caller>() != Some(&Caller::Admin) {
+            return Ok(SecondFactor);
+        }
+
+        let db = req
+            .app_data::<web::Data<dyn Storage>>()
+            .ok_or_else(|| ErrorInternalServerError("Database not configured"))?;
+        // Read off the worker thread, like every other database call
+        let secret = match blocking(db, |db| db.read_admin_totp()).await {
+            Ok(Some((secret, true))) => secret,
+            Ok(_) => return Ok(SecondFactor),
+            Err(e) => {
+                warn!("Failed to read admin TOTP enrollment: {}", e);
+                return Err(ErrorInternalServerError("Failed to verify second factor"));
+            }
+        };
+
+        let code = req.headers().get(TOTP_HEADER).and_then(|h| h.to_str().ok());
+        check_code(&req, &secret, code)?;
+        Ok(SecondFactor)
+    }
+}
+
+impl FromRequest for SecondFactor {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        Box::pin(SecondFactor::check(req.clone()))
+    }
+}
+
+#[get("/admin/totp")]
+pub async fn get_totp_status(
+    _admin: Admin,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, Error> {
+    let enrollment = blocking(&db, |db| db.read_admin_totp()).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "enrolled": enrollment.is_some(),
+        "confirmed": enrollment.is_some_and(|(_, confirmed)| confirmed)
+    })))
+}
+
+// Starts enrollment with a fresh secret. The second factor is only enforced
+// after the secret is confirmed through /admin/totp/verify.
+#[post("/admin/totp/enroll")]
+pub async fn enroll_totp(_admin: Admin, db: web::Data<dyn Storage>) -> Result<HttpResponse, Error> {
+    if let Some((_, true)) = blocking(&db, |db| db.read_admin_totp()).await? {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "TOTP is already enabled, disable it before enrolling again"
+        })));
+    }
+
+    let bytes: [u8; 20] = rand::random();
+    let secret = Secret::Raw(bytes.to_vec()).to_encoded().to_string();
+    let stored = secret.clone();
+    blocking(&db, move |db| db.store_admin_totp(&stored)).await?;
+    info!(target: "audit", "Admin TOTP enrollment started");
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "secret": secret,
+        "otpauth_url": format!(
+            "otpauth://totp/{issuer}:admin?secret={secret}&issuer={issuer}&algorithm=SHA1&digits=6&period=30",
+            issuer = TOTP_ISSUER,
+            secret = secret
+        )
+    })))
+}
+
+#[post("/admin/totp/verify")]
+pub async fn verify_totp(
+    _admin: Admin,
+    req: HttpRequest,
+    body: web::Json<TotpCode>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, Error> {
+    let Some((secret, confirmed)) = blocking(&db, |db| db.read_admin_totp()).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No TOTP enrollment in progress"
+        })));
+    };
+
+    check_code(&req, &secret, Some(&body.code))?;
+    if !confirmed {
+        blocking(&db, |db| db.confirm_admin_totp()).await?;
+        info!(target: "audit", "Admin TOTP enabled");
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "confirmed": true })))
+}
+
+#[delete("/admin/totp")]
+pub async fn disable_totp(
+    _admin: Admin,
+    _totp: SecondFactor,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, Error> {
+    blocking(&db, |db| db.delete_admin_totp()).await?;
+    warn!(target: "audit", "Admin TOTP disabled");
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "enrolled": false })))
+}
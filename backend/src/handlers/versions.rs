@@ -0,0 +1,100 @@
+use crate::utils::types::RowData;
+use actix_web::dev::Payload;
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::{StatusCode, header};
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use std::future::{Ready, ready};
+
+// The row version a write is based on, from an `If-Match: "<version>"`
+// header. Only meaningful for a single row; rows in a batch carry their own
+// `version` instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IfMatch(pub Option<i32>);
+
+impl IfMatch {
+    // Fills in the version of a single row that did not give one
+    pub fn apply(&self, rows: &mut [RowData]) {
+        if let (Some(version), [row]) = (self.0, rows)
+            && row.version.is_none()
+        {
+            row.version = Some(version);
+        }
+    }
+}
+
+impl FromRequest for IfMatch {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(value) = req.headers().get(header::IF_MATCH) else {
+            return ready(Ok(IfMatch(None)));
+        };
+        let version = value.to_str().ok().and_then(|value| {
+            value
+                .trim()
+                .trim_start_matches("W/")
+                .trim_matches('"')
+                .parse()
+                .ok()
+        });
+        ready(
+            version
+                .map(|version| IfMatch(Some(version)))
+                .ok_or_else(|| ErrorBadRequest("If-Match must be a row version, e.g. \"3\"")),
+        )
+    }
+}
+
+// A write turned away because it was not based on the current version of the
+// rows it changes. Carries those rows as they are now, so the client can
+// merge and retry.
+#[derive(thiserror::Error, Debug)]
+pub enum VersionConflict {
+    #[error("Updating a row needs the version it was read at, in the row or an If-Match header")]
+    Missing(Vec<RowData>),
+    #[error("Rows were changed by someone else since they were read")]
+    Stale(Vec<RowData>),
+}
+
+impl ResponseError for VersionConflict {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            VersionConflict::Missing(_) => StatusCode::PRECONDITION_REQUIRED,
+            VersionConflict::Stale(_) => StatusCode::CONFLICT,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let (VersionConflict::Missing(current) | VersionConflict::Stale(current)) = self;
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "status": "error",
+            "message": self.to_string(),
+            "current": current
+        }))
+    }
+}
+
+// Checks each incoming row that updates an existing one against the existing
+// row's version. New rows need none.
+pub fn check_versions(rows: &[RowData], incoming: &[RowData]) -> Result<(), VersionConflict> {
+    let mut missing = Vec::new();
+    let mut stale = Vec::new();
+    for row in incoming {
+        let Some(current) = rows.iter().find(|r| r.same_row(row)) else {
+            continue;
+        };
+        match row.version {
+            None => missing.push(current.clone()),
+            Some(version) if Some(version) != current.version => stale.push(current.clone()),
+            Some(_) => {}
+        }
+    }
+    if !stale.is_empty() {
+        return Err(VersionConflict::Stale(stale));
+    }
+    if !missing.is_empty() {
+        return Err(VersionConflict::Missing(missing));
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use crate::database::storage::{Storage, blocking, persist_batch};
+use crate::handlers::cohort_window::WeekWindow;
+use crate::handlers::students::get_github_to_name_mapping;
+use crate::services::scoring::{ExerciseResult, apply_exercise_result, is_yes};
+use crate::services::submission_events::parse_submission_event;
+use crate::utils::classroom::record_ci_result;
+use crate::utils::forge::ForgeCache;
+use crate::utils::types::Table;
+use crate::utils::webhook::{SignedPayload, WebhookError, WebhookVerifier};
+use actix_web::{HttpRequest, HttpResponse, post, web};
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use std::env;
+use std::sync::Mutex;
+
+// Verifier for GitHub webhooks, enabled by GITHUB_WEBHOOK_SECRET
+pub fn github_verifier_from_env() -> Option<web::Data<Mutex<WebhookVerifier>>> {
+    env::var("GITHUB_WEBHOOK_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| web::Data::new(Mutex::new(WebhookVerifier::new(secret))))
+}
+
+// How long a handled delivery id is refused again. GitHub signs no
+// timestamp, so without this an old delivery could be replayed as soon as
+// the verifier forgot it.
+const DELIVERY_RETENTION_DAYS: i64 = 30;
+
+// Workflow whose runs grade an exercise, GitHub Classroom's by default
+const DEFAULT_AUTOGRADER_WORKFLOW: &str = "GitHub Classroom Workflow";
+
+// Name of the workflow whose runs say whether an exercise's tests pass.
+// Other workflows in the repo, such as a linter, do not grade it.
+#[derive(Debug, Clone)]
+pub struct AutograderWorkflow(pub String);
+
+impl AutograderWorkflow {
+    // From GITHUB_AUTOGRADER_WORKFLOW
+    pub fn from_env() -> Self {
+        AutograderWorkflow(
+            env::var("GITHUB_AUTOGRADER_WORKFLOW")
+                .ok()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| DEFAULT_AUTOGRADER_WORKFLOW.to_string()),
+        )
+    }
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|h| h.to_str().ok())
+}
+
+fn rejected(e: WebhookError) -> HttpResponse {
+    warn!(target: "audit", "Rejected GitHub webhook: {}", e);
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "status": "error",
+        "message": e.to_string()
+    }))
+}
+
+fn ignored(event: &str, reason: String) -> HttpResponse {
+    info!("Ignored GitHub {} webhook: {}", event, reason);
+    HttpResponse::Accepted().json(serde_json::json!({
+        "event": event,
+        "updated": false,
+        "reason": reason
+    }))
+}
+
+// Authenticated by the payload signature instead of a token. Pushes and
+// autograder runs in a student's exercise repo update the exercise columns
+// of their row right away; every other delivery is acknowledged and ignored.
+#[post("/webhooks/github")]
+#[allow(clippy::too_many_arguments)]
+pub async fn github_webhook(
+    req: HttpRequest,
+    body: web::Bytes,
+    window: WeekWindow,
+    verifier: Option<web::Data<Mutex<WebhookVerifier>>>,
+    autograder: web::Data<AutograderWorkflow>,
+    forge_cache: Option<web::Data<ForgeCache>>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> HttpResponse {
+    let Some(verifier) = verifier else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let payload = SignedPayload {
+        body: &body,
+        signature: header(&req, "X-Hub-Signature-256"),
+        timestamp: None,
+        nonce: header(&req, "X-GitHub-Delivery"),
+    };
+    let now = Utc::now();
+    let verified = verifier
+        .lock()
+        .unwrap()
+        .verify(&payload, now)
+        .and(payload.nonce.ok_or(WebhookError::MissingDeliveryId));
+    let delivery = match verified {
+        Ok(delivery) => delivery.to_string(),
+        Err(e) => return rejected(e),
+    };
+    let id = delivery.clone();
+    let prune_before = now - Duration::days(DELIVERY_RETENTION_DAYS);
+    match blocking(&db, move |db| {
+        db.record_webhook_delivery(&id, now, prune_before)
+    })
+    .await
+    {
+        Ok(true) => {}
+        Ok(false) => return rejected(WebhookError::Replayed),
+        Err(e) => {
+            warn!("Failed to record GitHub delivery {}: {}", delivery, e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": "Failed to record the delivery"
+            }));
+        }
+    }
+
+    let response = handle_delivery(&req, &body, window, &autograder, forge_cache, state, &db).await;
+    // A delivery that failed may be redelivered with the same id
+    if response.status().is_server_error() {
+        let id = delivery.clone();
+        if let Err(e) = blocking(&db, move |db| db.forget_webhook_delivery(&id)).await {
+            warn!("Failed to forget GitHub delivery {}: {}", delivery, e);
+        }
+    }
+    response
+}
+
+async fn handle_delivery(
+    req: &HttpRequest,
+    body: &web::Bytes,
+    window: WeekWindow,
+    autograder: &AutograderWorkflow,
+    forge_cache: Option<web::Data<ForgeCache>>,
+    state: web::Data<Mutex<Table>>,
+    db: &web::Data<dyn Storage>,
+) -> HttpResponse {
+    let event = header(req, "X-GitHub-Event").unwrap_or("unknown");
+    info!("Received GitHub {} webhook ({} bytes)", event, body.len());
+    let submission = match parse_submission_event(event, body, &autograder.0) {
+        Ok(Some(submission)) => submission,
+        Ok(None) => return ignored(event, "not a student submission".to_string()),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": format!("Invalid {} payload: {}", event, e)
+            }));
+        }
+    };
+    let week = submission.week;
+    // A cached listing of the week predates this push
+    if let Some(cache) = &forge_cache {
+        cache.bust(Some(week));
+    }
+    // Spares the next sync asking for the outcome of the same commit
+    if let (Some(url), Some(sha), Some(passing)) = (
+        &submission.repo_url,
+        &submission.head_sha,
+        submission.tests_passing,
+    ) {
+        record_ci_result(url, sha, passing);
+    }
+    let Some(name) = get_github_to_name_mapping(db, &submission.github).await else {
+        return ignored(
+            event,
+            format!("{} is not a known student", submission.github),
+        );
+    };
+    if let Err(e) = window.check([week]) {
+        return ignored(event, e.to_string());
+    }
+
+    // Same rule as the weekly sync: a row is only ever marked submitted
+    let changed = {
+        let mut state_table = state.lock().unwrap();
+        let Some(mut row) = state_table
+            .rows
+            .iter()
+            .find(|row| row.name == name && row.week == week)
+            .cloned()
+        else {
+            return ignored(event, format!("{} has no row in week {}", name, week));
+        };
+        let result = ExerciseResult {
+            submitted: true,
+            tests_passing: submission
+                .tests_passing
+                .unwrap_or_else(|| is_yes(&row.exercise_test_passing)),
+            commit_count: None,
+            last_commit_at: submission.committed_at.clone(),
+            repo_url: submission.repo_url.clone(),
+        };
+        apply_exercise_result(&mut row, &result);
+        let checkpoint = state_table.checkpoint(std::slice::from_ref(&row));
+        match state_table.insert_or_update(&mut row) {
+            Ok(changed) => changed.then(|| (row, state_table.take_history(), checkpoint)),
+            Err(e) => {
+                // A 5xx makes GitHub offer the delivery for redelivery
+                warn!(
+                    "Failed to apply the {} submission of {}: {}",
+                    event, name, e
+                );
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "status": "error",
+                    "message": "Failed to apply the submission"
+                }));
+            }
+        }
+    }; // Lock released here
+
+    let updated = changed.is_some();
+    if let Some((row, history, checkpoint)) = changed
+        && let Err(e) = persist_batch(
+            &state,
+            db,
+            vec![row],
+            history,
+            "github".to_string(),
+            checkpoint,
+        )
+        .await
+    {
+        // A 5xx makes GitHub offer the delivery for redelivery
+        warn!(
+            "Failed to store the {} submission of {}: {}",
+            event, name, e
+        );
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": "Failed to store the submission"
+        }));
+    }
+    info!(
+        target: "audit",
+        "GitHub {} of {} updated week {} exercise of {}: {}",
+        event, submission.repo, week, name, updated
+    );
+
+    HttpResponse::Accepted().json(serde_json::json!({
+        "event": event,
+        "updated": updated,
+        "name": name,
+        "week": week
+    }))
+}
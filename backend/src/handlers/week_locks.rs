@@ -0,0 +1,92 @@
+use crate::database::storage::{Storage, blocking};
+use crate::handlers::auth::{Admin, Authenticated};
+use crate::utils::types::{AppError, WeekLock};
+use actix_web::{HttpResponse, delete, get, post, web};
+use chrono::Utc;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+// Weeks closed for writes once grading is done. Every write checks the weeks
+// it touches through `WeekWindow`, which turns locked ones away with a 423
+// unless an admin overrides the lock.
+#[derive(Debug, Default)]
+pub struct WeekLocks {
+    locks: BTreeMap<i32, WeekLock>,
+}
+
+impl WeekLocks {
+    pub fn load(db: &dyn Storage) -> Result<Self, AppError> {
+        Ok(WeekLocks {
+            locks: db
+                .read_week_locks()?
+                .into_iter()
+                .map(|lock| (lock.week, lock))
+                .collect(),
+        })
+    }
+
+    pub fn weeks(&self) -> BTreeSet<i32> {
+        self.locks.keys().copied().collect()
+    }
+}
+
+#[get("/weeks/locks")]
+pub async fn get_week_locks(
+    _caller: Authenticated,
+    locks: web::Data<Mutex<WeekLocks>>,
+) -> HttpResponse {
+    let locks: Vec<WeekLock> = locks.lock().unwrap().locks.values().cloned().collect();
+    HttpResponse::Ok().json(locks)
+}
+
+// Closes a week for writes. Locking a locked week keeps the first lock.
+#[post("/weeks/{week}/lock")]
+pub async fn lock_week(
+    _admin: Admin,
+    week: web::Path<i32>,
+    locks: web::Data<Mutex<WeekLocks>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    if week < 0 {
+        return Err(actix_web::error::ErrorBadRequest("Invalid week number"));
+    }
+    if let Some(lock) = locks.lock().unwrap().locks.get(&week) {
+        return Ok(HttpResponse::Ok().json(lock));
+    }
+
+    let lock = WeekLock {
+        week,
+        locked_by: "admin".to_string(),
+        locked_at: Utc::now().to_rfc3339(),
+    };
+    let stored = lock.clone();
+    blocking(&db, move |db| db.lock_week(&stored)).await?;
+    locks.lock().unwrap().locks.insert(week, lock.clone());
+    info!(target: "audit", "admin locked week {}", week);
+
+    Ok(HttpResponse::Ok().json(lock))
+}
+
+#[delete("/weeks/{week}/lock")]
+pub async fn unlock_week(
+    _admin: Admin,
+    week: web::Path<i32>,
+    locks: web::Data<Mutex<WeekLocks>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    if !blocking(&db, move |db| db.unlock_week(week)).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Week {} is not locked", week)
+        })));
+    }
+    locks.lock().unwrap().locks.remove(&week);
+    info!(target: "audit", "admin unlocked week {}", week);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "week": week,
+        "locked": false
+    })))
+}
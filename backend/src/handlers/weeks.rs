@@ -0,0 +1,179 @@
+use crate::database::storage::{Storage, blocking, persist_batch};
+use crate::handlers::auth::{Admin, Caller};
+use crate::handlers::backups::backup_before;
+use crate::handlers::cohort_window::WeekWindow;
+use crate::handlers::dry_run::DryRun;
+use crate::handlers::idempotency::fingerprint;
+use crate::handlers::two_factor::SecondFactor;
+use crate::services::weekly::{copy_week_rows, rows_for_week};
+use crate::utils::backup::{BackupReason, Backups};
+use crate::utils::types::{RowData, Table};
+use actix_web::{HttpResponse, delete, post, web};
+use log::{error, info};
+use serde::Deserialize;
+use std::sync::Mutex;
+
+// Seeds a week from another one without calling the Classroom API, e.g. for
+// an offline session: same groups and TAs, scores reset. A week that already
+// has rows is left alone.
+#[post("/weeks/{week}/copy_from/{from}")]
+pub async fn copy_week(
+    _admin: Admin,
+    dry_run: DryRun,
+    window: WeekWindow,
+    path: web::Path<(i32, i32)>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (week, from) = path.into_inner();
+    if week < 1 || from < 0 || from == week {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Copy into a week from 1 on, from another week",
+        ));
+    }
+    window.check([week])?;
+
+    let (copied, history, checkpoint) = {
+        let mut state_table = state.lock().unwrap();
+        if state_table.rows.iter().any(|row| row.week == week) {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Week {} already has rows", week)
+            })));
+        }
+        let source = rows_for_week(&state_table.rows, from);
+        if source.is_empty() {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Week {} has no rows to copy", from)
+            })));
+        }
+        let mut copied = copy_week_rows(&source, week);
+        if dry_run.is_set() {
+            return Ok(DryRun::preview(serde_json::json!({ "insert": copied })));
+        }
+
+        let checkpoint = state_table.checkpoint(&copied);
+        for row in &mut copied {
+            state_table.insert_or_update(row)?;
+        }
+        (copied, state_table.take_history(), checkpoint)
+    }; // Lock released here
+
+    let count = copied.len();
+    persist_batch(
+        &state,
+        &db,
+        copied,
+        history,
+        Caller::Admin.label(),
+        checkpoint,
+    )
+    .await?;
+    info!(target: "audit", "admin copied week {} into week {} ({} rows)", from, week, count);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "week": week,
+        "copied_from": from,
+        "rows": count
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteWeekQuery {
+    // Token from the preview of the deletion; without it nothing is deleted
+    pub confirm: Option<String>,
+    // Removes the rows for good instead of marking them deleted
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+// Token confirming the deletion of a week as previewed: it changes as soon
+// as a row of the week is added, removed or edited
+fn confirmation_token(week: i32, rows: &[RowData]) -> String {
+    let rows: Vec<(&str, Option<i32>)> = rows
+        .iter()
+        .map(|row| (row.name.as_str(), row.version))
+        .collect();
+    fingerprint(&(week, rows))
+}
+
+// Removes every row of a week, e.g. one generated by mistake. The first call
+// only previews the rows with a confirmation token; sending the token back
+// as `?confirm=` deletes them, unless the week changed in between.
+#[delete("/weeks/{week}")]
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_week(
+    _admin: Admin,
+    _totp: SecondFactor,
+    dry_run: DryRun,
+    window: WeekWindow,
+    week: web::Path<i32>,
+    query: web::Query<DeleteWeekQuery>,
+    state: web::Data<Mutex<Table>>,
+    db: web::Data<dyn Storage>,
+    backups: web::Data<Backups>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let week = week.into_inner();
+    window.check([week])?;
+    let permanent = query.permanent;
+
+    {
+        let state_table = state.lock().unwrap();
+        let rows = rows_for_week(&state_table.rows, week);
+        if rows.is_empty() {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Week {} has no rows", week)
+            })));
+        }
+        let token = confirmation_token(week, &rows);
+        if dry_run.is_set() || query.confirm.as_deref() != Some(token.as_str()) {
+            return Ok(HttpResponse::Ok().json(serde_json::json!({
+                "week": week,
+                "deleted": false,
+                "permanent": permanent,
+                "delete": rows,
+                "confirm": token
+            })));
+        }
+    } // Lock released here
+
+    if permanent {
+        backup_before(&backups, BackupReason::BeforeWeekDeletion).await?;
+    }
+
+    // Take the rows out of memory first, so no write lands on them while the
+    // database deletes them; they are put back if that fails
+    let removed = {
+        let mut state_table = state.lock().unwrap();
+        let rows = rows_for_week(&state_table.rows, week);
+        if query.confirm.as_deref() != Some(confirmation_token(week, &rows).as_str()) {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Week {} changed since the preview; confirm again", week)
+            })));
+        }
+        state_table.rows.retain(|row| row.week != week);
+        rows
+    }; // Lock released here
+
+    if let Err(e) = blocking(&db, move |db| db.delete_week(week, permanent)).await {
+        error!(
+            "Failed to delete week {}, restoring it in memory: {}",
+            week, e
+        );
+        state.lock().unwrap().rows.extend(removed);
+        return Err(e.into());
+    }
+    info!(
+        target: "audit",
+        "admin deleted week {} ({} rows, permanent: {})",
+        week,
+        removed.len(),
+        permanent
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "week": week,
+        "deleted": true,
+        "permanent": permanent,
+        "rows": removed.len()
+    })))
+}
@@ -1,45 +1,124 @@
 use actix_cors::Cors;
-use actix_web::{App, HttpServer, http::header, middleware::Logger, web};
-use log::info;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use actix_web::{
+    App, HttpServer,
+    http::header,
+    middleware::{Compress, Condition, Logger, from_fn},
+    web,
+};
+use log::{error, info, warn};
+use std::sync::{Arc, Mutex};
 
 // Import our modules
 mod database;
 mod handlers;
+mod services;
 mod utils;
 
 // Import functions
-use database::operations::read_from_db;
-use utils::backup::start_backup_thread;
+use database::encryption::init_mail_encryption;
+use database::paths::init_data_paths;
+use database::pool::init_sqlite_settings;
+use database::retention::{RetentionPolicy, start_retention_task};
+use database::storage::{StartupRetry, Storage, StorageBackend, degraded_storage};
+use services::cohort_window::CohortWindow;
+use services::compensation::CompensationRates;
+use services::read_model::{ReadModel, refresh_interval_from_env, start_read_model_thread};
+use services::sync_slo::SyncSlo;
+use services::validation::json_error;
+use utils::backup::{BackupPolicy, Backups, start_backup_thread};
 use utils::csv_dump::csv_dump;
 
 // Import all handlers
-use handlers::auth::login; // Remove discord_callback
+use handlers::announcements::{
+    ReadLinks, create_announcement, get_announcement_receipts, get_announcements,
+};
+use handlers::attendance::{
+    confirm_attendance_proposals, get_attendance_proposals, mark_attendance,
+    mark_cohort_attendance, mark_group_attendance, set_discord_handle, take_voice_snapshot,
+};
+use handlers::attention::{dismiss_attention, get_attention};
+use handlers::auth::{
+    LockoutTracker, MagicLinks, RevocationList, SessionStore, TA, clear_all_lockouts,
+    clear_lockout, get_lockouts, get_sessions, login, logout, request_magic_link, require_auth,
+    revoke_session, verify_magic_link,
+}; // Remove discord_callback
+use handlers::backfill::backfill_submissions;
+use handlers::backups::{export_sqlite, get_backups, restore_backup};
+use handlers::branding::{get_branding, update_branding};
+use handlers::checklist::{
+    complete_checklist_task, get_pending_checklists, get_week_checklist, reopen_checklist_task,
+};
+use handlers::cohorts::{bootstrap_cohort, preview_retention, set_cohort_end_date};
+use handlers::communications::{add_communication, get_communications};
+use handlers::config::reload_config;
+use handlers::exports::export_weekly_data;
+use handlers::grouping::{
+    add_grouping_constraint, get_constraint_report, get_grouping_constraints, get_language_report,
+    get_spoken_languages, publish_groups, remove_grouping_constraint, set_spoken_languages,
+};
+use handlers::health::{Health, healthz, reject_writes_when_degraded};
+use handlers::idempotency::{IDEMPOTENCY_KEY_HEADER, REPLAYED_HEADER};
+use handlers::integrity::get_integrity_report;
+use handlers::jobs::{Jobs, get_job, get_jobs};
+use handlers::maintenance::{DbMaintenance, get_db_maintenance, start_db_maintenance};
+use handlers::outbox::get_outbox;
+use handlers::periodic_sync::{start_periodic_sync, sync_interval_from_env};
+use handlers::public_stats::{PublicStatsCache, get_public_stats};
+use handlers::reviews::{get_curriculum_feedback, submit_review_summary};
+use handlers::schema::{get_schema, update_rubric_notes};
 use handlers::students::{
+    WeekGenerations,
     add_student,
     add_weekly_data,
     delete_data,
+    // Weekly data
+    generate_weekly_data,
+    // Reports
+    get_attendance_forecast,
+    get_calibration_report,
     get_cohort_feedback,
+    get_exercise_analytics,
     get_individual_student_data,
+    get_row_history,
     get_student_background_data,
     //register
     get_student_github_username,
+    get_student_groups,
     // Individual
     get_student_repo_link,
+    get_student_weekly_data,
     // Basic CRUD
     get_students,
     get_students_by_total_score,
-    // Reports
+    get_ta_compensation,
     get_total_student_count,
     get_weekly_attendance_count_for_week,
-    // Weekly data
+    get_weekly_data_changes,
     get_weekly_data_or_common,
+    get_weekly_data_range,
+    merge_rows,
+    recheck_student_submission,
     register_user,
     remove_student,
+    replace_weekly_data,
+    restore_data,
+    search_students,
+    undo_row_change,
     update_student,
 };
+use handlers::sync::{SyncStatus, bust_forge_cache, get_sync_slo, get_sync_status};
+use handlers::tas::{accept_ta_invite, invite_ta, offboard_ta};
+use handlers::two_factor::{TOTP_HEADER, disable_totp, enroll_totp, get_totp_status, verify_totp};
+use handlers::webhooks::{AutograderWorkflow, github_verifier_from_env, github_webhook};
+use handlers::week_locks::{WeekLocks, get_week_locks, lock_week, unlock_week};
+use handlers::weeks::{copy_week, delete_week};
 use utils::discord_auth::discord_oauth;
+use utils::discord_voice::start_voice_snapshot_task;
+use utils::forge::{CachedForge, ForgeCache, ForgeProvider, forge_from_env};
+use utils::ip_allowlist::{IpAllowlist, enforce_ip_allowlist};
+use utils::mailer::Mailer;
+use utils::outbox::init_outbox;
+use utils::reload::{LiveConfig, start_sighup_reload};
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
@@ -50,17 +129,224 @@ async fn main() -> Result<(), std::io::Error> {
     log4rs::init_file("log4rs.yaml", Default::default()).unwrap();
     info!("Starting Bitshala Admin Server...");
 
-    if let Err(e) = csv_dump().await {
+    // PRAGMAs for every SQLite connection, including cohort archives
+    let sqlite = init_sqlite_settings()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    info!(
+        "SQLite journal mode {}, synchronous {}, busy timeout {}ms",
+        sqlite.journal_mode, sqlite.synchronous, sqlite.busy_timeout_ms
+    );
+
+    // Locations of the live database, cohort archives and backups
+    let paths =
+        init_data_paths().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    info!(
+        "Database path {}, data directory {}",
+        paths.database.display(),
+        paths.data_dir.display()
+    );
+
+    // Select where the live cohort is stored
+    let backend = StorageBackend::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let startup_retry = StartupRetry::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // Scheduled and pre-deletion snapshots (SQLite only; Postgres has its own tooling)
+    let backup_policy = BackupPolicy::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let backups = match &backend {
+        StorageBackend::Sqlite(path) => Backups::new(Some(path.clone()), backup_policy),
+        StorageBackend::Postgres(_) => Backups::new(None, backup_policy),
+    };
+
+    // Open it and apply pending migrations. A SQLite file that stays locked
+    // or missing leaves the server read-only, serving the newest backup.
+    let (db, health): (Arc<dyn Storage>, Health) = match backend.open_with_retry(&startup_retry) {
+        Ok(db) => {
+            if let StorageBackend::Sqlite(_) = backend {
+                start_backup_thread(backups.clone());
+            }
+            (db, Health::default())
+        }
+        Err(e) if matches!(backend, StorageBackend::Sqlite(_)) => {
+            let (storage, serving) = degraded_storage(&backups)?;
+            error!(
+                "Could not open the database: {}. Serving {} read-only until restarted",
+                e,
+                serving
+                    .as_ref()
+                    .map(|backup| format!("backup {}", backup.file))
+                    .unwrap_or_else(|| "an empty cohort".to_string())
+            );
+            (Arc::new(storage), Health::degraded(e.to_string(), serving))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let db = web::Data::from(db);
+    let health = web::Data::new(health);
+    let backups = web::Data::new(backups);
+    info!("Using {} for cohort storage", db.name());
+
+    if health.is_degraded() {
+        info!("Skipping the CSV dump while degraded");
+    } else if let Err(e) = csv_dump(db.get_ref()).await {
         eprintln!("Error during CSV dump: {:?}", e);
     }
 
-    // Start backup thread
-    start_backup_thread();
+    // Encrypt student mail at rest when a key is configured
+    let mail_encryption = init_mail_encryption()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if mail_encryption {
+        info!("Student mail encryption enabled");
+        db.encrypt_existing_mail()?;
+    }
+
+    // Scheduled anonymization and purging of past cohorts (off unless configured)
+    let retention = RetentionPolicy::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    start_retention_task(retention);
+    let retention = web::Data::new(retention);
 
     // Initialize database state
-    let table = read_from_db(&PathBuf::from("classroom.db"))?;
+    let table = db.read_from_db()?;
+    let read_model = web::Data::new(ReadModel::new(&table.rows));
     let state = web::Data::new(Mutex::new(table));
 
+    // Leaderboard and stats are served from a periodically rebuilt snapshot
+    let refresh_interval = refresh_interval_from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    start_read_model_thread(
+        state.clone().into_inner(),
+        read_model.clone().into_inner(),
+        refresh_interval,
+    );
+
+    // Start voice channel attendance snapshots (no-op unless configured)
+    start_voice_snapshot_task(state.clone(), db.clone());
+    let sync_status = web::Data::new(Mutex::new(SyncStatus::default()));
+    let generations = web::Data::new(WeekGenerations::default());
+
+    // Rates for the TA stipend report
+    let compensation_rates = CompensationRates::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // Targets for the sync success rate and exercise data freshness
+    let sync_slo = SyncSlo::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // Cohort dates, optionally closing weeks outside them to writes
+    let cohort_window = CohortWindow::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if cohort_window.enforce {
+        info!("Writes limited to the cohort window");
+    }
+    let lockouts = web::Data::new(Mutex::new(LockoutTracker::default()));
+    let maintenance = web::Data::new(Mutex::new(DbMaintenance::default()));
+    let jobs = web::Data::new(Mutex::new(Jobs::default()));
+    let sessions = web::Data::new(Mutex::new(SessionStore::default()));
+
+    // TAs who joined through an invite take part in logins and the rotation
+    for ta in db.read_onboarded_tas()? {
+        TA::register(&ta.email, &ta.name);
+    }
+    let revoked_tokens = web::Data::new(Mutex::new(RevocationList::load(db.get_ref())?));
+    let week_locks = web::Data::new(Mutex::new(WeekLocks::load(db.get_ref())?));
+
+    // Load optional IP allowlist
+    let allowlist = IpAllowlist::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if allowlist.is_enabled() {
+        info!("IP allowlist enabled");
+    }
+
+    // Settings POST /admin/config/reload and SIGHUP can change in place
+    let live_config = LiveConfig::new(compensation_rates, sync_slo, cohort_window, allowlist);
+    start_sighup_reload(live_config.clone());
+    let compensation_rates = live_config.compensation_rates.clone();
+    let sync_slo = live_config.sync_slo.clone();
+    let cohort_window = live_config.cohort_window.clone();
+    let allowlist = live_config.allowlist.clone();
+    let live_config = web::Data::new(live_config);
+
+    // Staging servers capture notifications instead of delivering them
+    let captured =
+        init_outbox().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if captured {
+        warn!("NOTIFICATION_SINK=outbox: email and Discord messages are captured, not sent");
+    }
+
+    // Outbound email and signed login links. TAs only sign in through
+    // emailed links, so there must be a way to send them.
+    let mailer = Mailer::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?
+        .map(web::Data::new)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "SMTP_HOST must be set for TA sign-in links, or NOTIFICATION_SINK=outbox to read them from GET /admin/outbox",
+            )
+        })?;
+    let magic_links = web::Data::new(Mutex::new(MagicLinks::from_env()));
+    let read_links = web::Data::new(ReadLinks::from_env());
+
+    // Anonymized stats for the public website, recomputed at most this often
+    let public_stats = web::Data::new(
+        PublicStatsCache::from_env()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+    );
+
+    let github_webhooks = github_verifier_from_env();
+    if github_webhooks.is_some() {
+        info!("GitHub webhook signature verification enabled");
+    }
+    let autograder_workflow = web::Data::new(AutograderWorkflow::from_env());
+
+    // Select where exercise submissions come from
+    let forge =
+        forge_from_env().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    info!("Using {} for exercise submissions", forge.name());
+    // Week listings are reused for a while, see FORGE_CACHE_SECS
+    let forge_cache = Arc::new(
+        ForgeCache::from_env()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+    );
+    let forge: Arc<dyn ForgeProvider> = Arc::new(CachedForge::new(forge, forge_cache.clone()));
+    let forge = web::Data::from(forge);
+    let forge_cache = web::Data::from(forge_cache);
+
+    // Keeps the running week's exercise columns fresh between generations
+    let sync_interval = sync_interval_from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    match sync_interval {
+        Some(interval) if !health.is_degraded() => start_periodic_sync(
+            interval,
+            state.clone(),
+            sync_status.clone(),
+            generations.clone(),
+            forge.clone(),
+            db.clone(),
+            cohort_window.clone(),
+            week_locks.clone(),
+        ),
+        _ => info!("Background submission sync disabled"),
+    }
+
+    // gzip/brotli/zstd for clients that accept it; a full week is several
+    // hundred KB of JSON. Off behind a proxy that already compresses.
+    let compress = match std::env::var("RESPONSE_COMPRESSION") {
+        Ok(value) if !value.trim().is_empty() => value.trim().parse().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "RESPONSE_COMPRESSION must be true or false",
+            )
+        })?,
+        _ => true,
+    };
+    if !compress {
+        info!("Response compression disabled");
+    }
+
     // Start HTTP server
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -70,14 +356,54 @@ async fn main() -> Result<(), std::io::Error> {
                 header::AUTHORIZATION,
                 header::ACCEPT,
                 header::CONTENT_TYPE,
+                header::IF_MATCH,
+                header::HeaderName::from_static(TOTP_HEADER),
+                header::HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
             ])
+            .expose_headers(vec![header::HeaderName::from_static(REPLAYED_HEADER)])
             .supports_credentials()
             .max_age(3600);
 
         App::new()
+            .app_data(web::JsonConfig::default().error_handler(json_error))
             .app_data(state.clone())
+            .app_data(db.clone())
+            .app_data(sync_status.clone())
+            .app_data(read_model.clone())
+            .app_data(generations.clone())
+            .app_data(week_locks.clone())
+            .app_data(compensation_rates.clone())
+            .app_data(sync_slo.clone())
+            .app_data(cohort_window.clone())
+            .app_data(maintenance.clone())
+            .app_data(jobs.clone())
+            .app_data(live_config.clone())
+            .app_data(lockouts.clone())
+            .app_data(sessions.clone())
+            .app_data(allowlist.clone())
+            .app_data(magic_links.clone())
+            .app_data(read_links.clone())
+            .app_data(public_stats.clone())
+            .app_data(revoked_tokens.clone())
+            .app_data(retention.clone())
+            .app_data(backups.clone())
+            .app_data(health.clone())
+            .configure(|cfg| {
+                if let Some(verifier) = &github_webhooks {
+                    cfg.app_data(verifier.clone());
+                }
+            })
+            .app_data(mailer.clone())
+            .app_data(autograder_workflow.clone())
+            .app_data(forge.clone())
+            .app_data(forge_cache.clone())
+            .wrap(from_fn(reject_writes_when_degraded))
+            .wrap(from_fn(require_auth))
+            .wrap(from_fn(enforce_ip_allowlist))
+            .wrap(Condition::new(compress, Compress::default()))
             .wrap(cors)
             .wrap(Logger::default())
+            .service(healthz)
             // Auth routes
             .service(login)
             .service(discord_oauth) // This is the actual discord handler
@@ -86,22 +412,116 @@ async fn main() -> Result<(), std::io::Error> {
             .service(add_student)
             .service(update_student)
             .service(remove_student)
+            .service(merge_rows)
             // Weekly data routes
+            // Before get_weekly_data_or_common, whose /weekly_data/{week} also matches
+            .service(get_weekly_data_changes)
             .service(get_weekly_data_or_common)
+            .service(get_weekly_data_range)
+            .service(generate_weekly_data)
+            .service(undo_row_change)
+            // Before add_weekly_data, whose /weekly_data/{week} also matches
+            .service(restore_data)
             .service(add_weekly_data)
+            .service(replace_weekly_data)
             .service(delete_data)
+            .service(export_weekly_data)
+            .service(get_row_history)
+            .service(get_grouping_constraints)
+            .service(add_grouping_constraint)
+            .service(remove_grouping_constraint)
+            .service(get_constraint_report)
+            .service(get_spoken_languages)
+            .service(set_spoken_languages)
+            .service(get_language_report)
+            .service(publish_groups)
+            // Attendance routes
+            .service(take_voice_snapshot)
+            .service(get_attendance_proposals)
+            .service(confirm_attendance_proposals)
+            .service(mark_attendance)
+            .service(mark_group_attendance)
+            .service(mark_cohort_attendance)
+            .service(set_discord_handle)
             // Report routes
             .service(get_total_student_count)
             .service(get_weekly_attendance_count_for_week)
             .service(get_students_by_total_score)
+            .service(get_attendance_forecast)
+            .service(get_exercise_analytics)
+            .service(get_ta_compensation)
+            .service(get_calibration_report)
+            .service(get_curriculum_feedback)
             // Individual student routes
+            // Before the /students/{week}/{student_name} route, which shares its shape
+            .service(get_student_groups)
+            .service(get_student_weekly_data)
             .service(get_student_repo_link)
             .service(get_student_background_data)
             .service(get_individual_student_data)
+            .service(search_students)
             .service(get_student_github_username)
             .service(get_cohort_feedback)
             //register
             .service(register_user)
+            // Sync routes
+            .service(get_pending_checklists)
+            .service(get_week_checklist)
+            .service(complete_checklist_task)
+            .service(reopen_checklist_task)
+            .service(bootstrap_cohort)
+            .service(set_cohort_end_date)
+            .service(get_branding)
+            .service(get_schema)
+            .service(update_rubric_notes)
+            .service(update_branding)
+            .service(preview_retention)
+            .service(offboard_ta)
+            .service(invite_ta)
+            .service(accept_ta_invite)
+            .service(get_outbox)
+            .service(get_public_stats)
+            .service(reload_config)
+            .service(get_backups)
+            .service(start_db_maintenance)
+            .service(get_db_maintenance)
+            .service(get_jobs)
+            .service(get_job)
+            .service(export_sqlite)
+            .service(restore_backup)
+            .service(get_integrity_report)
+            .service(github_webhook)
+            .service(get_communications)
+            .service(add_communication)
+            .service(get_announcements)
+            .service(get_announcement_receipts)
+            .service(create_announcement)
+            .service(get_sync_status)
+            .service(bust_forge_cache)
+            .service(get_sync_slo)
+            .service(backfill_submissions)
+            .service(submit_review_summary)
+            .service(get_week_locks)
+            .service(lock_week)
+            .service(unlock_week)
+            .service(copy_week)
+            .service(delete_week)
+            .service(recheck_student_submission)
+            .service(get_attention)
+            .service(get_sessions)
+            .service(logout)
+            .service(request_magic_link)
+            .service(verify_magic_link)
+            .service(revoke_session)
+            .service(dismiss_attention)
+            .service(get_totp_status)
+            .service(enroll_totp)
+            .service(verify_totp)
+            .service(disable_totp)
+            // Admin routes
+            .service(get_lockouts)
+            .service(clear_all_lockouts)
+            .service(clear_lockout)
     })
     .bind("127.0.0.1:8081")?
     .run()
@@ -0,0 +1,171 @@
+//! Soft checks of submitted grades against the rest of the week.
+//!
+//! The rows a TA grades are compared with the other present students of the
+//! same week. A discussion criterion scored 0 or the maximum for a whole
+//! batch, or a total far from the week's average, usually means a column was
+//! mis-entered. Flags never block a write: they come back as warnings and
+//! are listed in the calibration report until the grades are corrected.
+
+use crate::services::scoring::{MAX_CRITERION_SCORE, criteria};
+use crate::utils::types::RowData;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+// Graded rows a batch needs before a uniform criterion is suspicious
+const MIN_BATCH: usize = 3;
+// Other graded rows of the week needed to judge against
+const MIN_REFERENCE: usize = 5;
+// Standard deviations from the week's mean at which a total is flagged
+const MAX_DEVIATIONS: f64 = 2.5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GradingFlag {
+    pub name: String,
+    pub week: i32,
+    pub ta: Option<String>,
+    // Criterion key, or "total"
+    pub column: String,
+    pub value: u64,
+    pub week_mean: f64,
+    pub reason: String,
+}
+
+fn discussion_score(row: &RowData, key: &str) -> Option<u64> {
+    match key {
+        "fa" => row.fa,
+        "fb" => row.fb,
+        "fc" => row.fc,
+        "fd" => row.fd,
+        _ => None,
+    }
+}
+
+fn graded(row: &RowData) -> bool {
+    row.week >= 1 && row.attendance.as_deref() == Some("yes")
+}
+
+fn mean(values: &[u64]) -> f64 {
+    values.iter().sum::<u64>() as f64 / values.len() as f64
+}
+
+// Flags for `batch`, rows of one week graded together, judged against the
+// other present students in `week_rows`
+pub fn grading_flags(week_rows: &[RowData], batch: &[RowData]) -> Vec<GradingFlag> {
+    let in_batch: HashSet<(&str, i32)> = batch
+        .iter()
+        .map(|row| (row.name.as_str(), row.week))
+        .collect();
+    let reference: Vec<&RowData> = week_rows
+        .iter()
+        .filter(|row| graded(row) && !in_batch.contains(&(row.name.as_str(), row.week)))
+        .collect();
+    let batch: Vec<&RowData> = batch.iter().filter(|row| graded(row)).collect();
+    let flag =
+        |row: &RowData, column: &str, value: u64, week_mean: f64, reason: String| GradingFlag {
+            name: row.name.clone(),
+            week: row.week,
+            ta: row.ta.clone(),
+            column: column.to_string(),
+            value,
+            week_mean,
+            reason,
+        };
+
+    let mut flags = Vec::new();
+    for criterion in criteria()
+        .into_iter()
+        .filter(|c| c.section == "group_discussion")
+    {
+        let scores: Vec<u64> = batch
+            .iter()
+            .filter_map(|row| discussion_score(row, criterion.key))
+            .collect();
+        let others: Vec<u64> = reference
+            .iter()
+            .filter_map(|row| discussion_score(row, criterion.key))
+            .collect();
+        if scores.len() < MIN_BATCH || others.len() < MIN_REFERENCE {
+            continue;
+        }
+        let week_mean = mean(&others);
+        let Some(extreme) = [0, MAX_CRITERION_SCORE]
+            .into_iter()
+            .find(|extreme| scores.iter().all(|score| score == extreme))
+        else {
+            continue;
+        };
+        // The rest of the week scoring the same is no sign of a mistake
+        if (week_mean - extreme as f64).abs() < 1.0 {
+            continue;
+        }
+        for row in &batch {
+            flags.push(flag(
+                row,
+                criterion.key,
+                extreme,
+                week_mean,
+                format!(
+                    "{} is {} for all {} graded rows, the week averages {:.1}",
+                    criterion.label,
+                    extreme,
+                    scores.len(),
+                    week_mean
+                ),
+            ));
+        }
+    }
+
+    let totals: Vec<u64> = reference.iter().filter_map(|row| row.total).collect();
+    if totals.len() >= MIN_REFERENCE {
+        let week_mean = mean(&totals);
+        let deviation = (totals
+            .iter()
+            .map(|total| (*total as f64 - week_mean).powi(2))
+            .sum::<f64>()
+            / totals.len() as f64)
+            .sqrt();
+        for row in &batch {
+            let Some(total) = row.total else {
+                continue;
+            };
+            let off = (total as f64 - week_mean) / deviation;
+            if deviation > 0.0 && off.abs() > MAX_DEVIATIONS {
+                flags.push(flag(
+                    row,
+                    "total",
+                    total,
+                    week_mean,
+                    format!(
+                        "Total {} is {:.1} standard deviations {} the week average {:.1}",
+                        total,
+                        off.abs(),
+                        if off < 0.0 { "below" } else { "above" },
+                        week_mean
+                    ),
+                ));
+            }
+        }
+    }
+    flags
+}
+
+// Flags of every week, each TA's rows judged as one batch against the rest
+pub fn calibration_report(rows: &[RowData]) -> Vec<GradingFlag> {
+    let mut batches: BTreeMap<(i32, Option<&str>), Vec<RowData>> = BTreeMap::new();
+    for row in rows.iter().filter(|row| row.week >= 1) {
+        batches
+            .entry((row.week, row.ta.as_deref()))
+            .or_default()
+            .push(row.clone());
+    }
+    let mut flags = Vec::new();
+    for ((week, _), batch) in &batches {
+        let week_rows: Vec<RowData> = rows
+            .iter()
+            .filter(|row| row.week == *week)
+            .cloned()
+            .collect();
+        flags.extend(grading_flags(&week_rows, batch));
+    }
+    flags
+}
@@ -0,0 +1,141 @@
+//! Dates a cohort runs between, and which weeks may still be written.
+//!
+//! Week 1 starts on the cohort's start date and each week runs for seven
+//! days. With enforcement on, rows of an ended cohort are read-only, and
+//! weeks more than one week ahead or past the end date cannot be written,
+//! so a mistyped week number does not create rows that no session covers.
+//! Weeks past the cohort's last week are out of range whether or not the
+//! window is enforced.
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use serde::Serialize;
+use std::env;
+
+// How far ahead a week may be written, so the next one can be prepared
+const LEAD_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CohortWindow {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+    // Without it the dates are informational only
+    pub enforce: bool,
+    // Last week the program has, if configured
+    pub max_week: Option<i32>,
+}
+
+impl CohortWindow {
+    // From COHORT_START_DATE and COHORT_END_DATE (YYYY-MM-DD), enforced when
+    // COHORT_WINDOW_ENFORCE is true, and the last week from COHORT_MAX_WEEK
+    pub fn from_env() -> Result<Self, String> {
+        fn date(var: &str) -> Result<Option<NaiveDate>, String> {
+            match env::var(var) {
+                Ok(value) if !value.trim().is_empty() => {
+                    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                        .map(Some)
+                        .map_err(|_| format!("{} must be a date like 2025-01-31", var))
+                }
+                _ => Ok(None),
+            }
+        }
+
+        let start = date("COHORT_START_DATE")?;
+        let end = date("COHORT_END_DATE")?;
+        if let (Some(start), Some(end)) = (start, end)
+            && end < start
+        {
+            return Err("COHORT_END_DATE must not be before COHORT_START_DATE".to_string());
+        }
+        let enforce = match env::var("COHORT_WINDOW_ENFORCE") {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse()
+                .map_err(|_| "COHORT_WINDOW_ENFORCE must be true or false")?,
+            _ => false,
+        };
+        let max_week = match env::var("COHORT_MAX_WEEK") {
+            Ok(value) if !value.trim().is_empty() => match value.trim().parse() {
+                Ok(week) if week >= 1 => Some(week),
+                _ => return Err("COHORT_MAX_WEEK must be a positive week number".to_string()),
+            },
+            _ => None,
+        };
+        Ok(CohortWindow {
+            start,
+            end,
+            enforce,
+            max_week,
+        })
+    }
+
+    // Why the week does not exist in this cohort, if it does not
+    pub fn check_range(&self, week: i32) -> Result<(), String> {
+        if week < 0 {
+            return Err("Invalid week number".to_string());
+        }
+        match self.max_week {
+            Some(max_week) if week > max_week => Err(format!(
+                "Week {} is out of range, the cohort's last week is {}",
+                week, max_week
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // First day of a week, known once the start date is
+    pub fn week_start(&self, week: i32) -> Option<NaiveDate> {
+        let start = self.start?;
+        (week >= 1).then(|| start + Duration::weeks(i64::from(week - 1)))
+    }
+
+    // The week running on `today`, known once the start date is. None before
+    // the cohort starts and after its end date or last week.
+    pub fn current_week(&self, today: NaiveDate) -> Option<i32> {
+        let days = (today - self.start?).num_days();
+        if days < 0 || self.end.is_some_and(|end| today > end) {
+            return None;
+        }
+        let week = i32::try_from(days / 7 + 1).ok()?;
+        self.check_range(week).ok().map(|_| week)
+    }
+
+    // When the week's exercise is due: the end of its last day, in UTC
+    pub fn week_deadline(&self, week: i32) -> Option<DateTime<Utc>> {
+        let next_week = self.week_start(week)? + Duration::weeks(1);
+        Some(next_week.and_time(NaiveTime::MIN).and_utc())
+    }
+
+    // Why rows of the week may not be written today, if they may not.
+    // Week 0 holds enrollment and is only closed once the cohort ends.
+    pub fn check(&self, week: i32, today: NaiveDate) -> Result<(), String> {
+        if !self.enforce {
+            return Ok(());
+        }
+        if let Some(end) = self.end
+            && today > end
+        {
+            return Err(format!(
+                "The cohort ended on {}, its rows are archived",
+                end
+            ));
+        }
+        let Some(starts) = self.week_start(week) else {
+            return Ok(());
+        };
+        if let Some(end) = self.end
+            && starts > end
+        {
+            return Err(format!(
+                "Week {} would start on {}, after the cohort ends on {}",
+                week, starts, end
+            ));
+        }
+        if starts > today + Duration::days(LEAD_DAYS) {
+            return Err(format!(
+                "Week {} starts on {}, too far ahead to be written",
+                week, starts
+            ));
+        }
+        Ok(())
+    }
+}
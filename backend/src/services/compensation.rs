@@ -0,0 +1,148 @@
+//! Grading workload per TA, priced for the program's stipend processing.
+//!
+//! A TA is counted for a session in every week they led a group with at least
+//! one student present. Hours are estimated from the session length plus a
+//! fixed grading time per present student.
+
+use crate::services::export::{CsvExporter, ExportBody, ExportTable};
+use crate::services::grouping::{ABSENT_GROUP, absent_ta};
+use crate::utils::types::{AppError, RowData};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompensationRates {
+    pub session_hours: f64,
+    pub grading_minutes_per_student: f64,
+    pub hourly_rate: f64,
+    pub currency: String,
+}
+
+impl Default for CompensationRates {
+    fn default() -> Self {
+        CompensationRates {
+            session_hours: 2.0,
+            grading_minutes_per_student: 10.0,
+            hourly_rate: 0.0,
+            currency: "USD".to_string(),
+        }
+    }
+}
+
+impl CompensationRates {
+    pub fn from_env() -> Result<Self, String> {
+        fn number(var: &str, default: f64) -> Result<f64, String> {
+            match env::var(var) {
+                Ok(value) if !value.trim().is_empty() => match value.trim().parse::<f64>() {
+                    Ok(n) if n.is_finite() && n >= 0.0 => Ok(n),
+                    _ => Err(format!("{} must be a non-negative number", var)),
+                },
+                _ => Ok(default),
+            }
+        }
+
+        let defaults = CompensationRates::default();
+        Ok(CompensationRates {
+            session_hours: number("TA_SESSION_HOURS", defaults.session_hours)?,
+            grading_minutes_per_student: number(
+                "TA_GRADING_MINUTES_PER_STUDENT",
+                defaults.grading_minutes_per_student,
+            )?,
+            hourly_rate: number("TA_HOURLY_RATE", defaults.hourly_rate)?,
+            currency: match env::var("TA_RATE_CURRENCY") {
+                Ok(currency) if !currency.trim().is_empty() => currency.trim().to_string(),
+                _ => defaults.currency,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaWorkload {
+    pub ta: String,
+    pub sessions: usize,
+    pub students_graded: usize,
+    pub hours: f64,
+    pub amount: f64,
+}
+
+fn cents(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+// Workload per TA, by TA name. The absent group is not a session.
+pub fn ta_workload(rows: &[RowData], rates: &CompensationRates) -> Vec<TaWorkload> {
+    let absent_ta = absent_ta().name();
+    let mut graded: BTreeMap<&str, (BTreeSet<i32>, usize)> = BTreeMap::new();
+    for row in rows {
+        let Some(ta) = row.ta.as_deref() else {
+            continue;
+        };
+        if row.week < 1
+            || row.group_id == ABSENT_GROUP
+            || ta == absent_ta.as_str()
+            || row.attendance.as_deref() != Some("yes")
+        {
+            continue;
+        }
+        let (weeks, students) = graded.entry(ta).or_default();
+        weeks.insert(row.week);
+        *students += 1;
+    }
+
+    graded
+        .into_iter()
+        .map(|(ta, (weeks, students_graded))| {
+            let hours = weeks.len() as f64 * rates.session_hours
+                + students_graded as f64 * rates.grading_minutes_per_student / 60.0;
+            TaWorkload {
+                ta: ta.to_string(),
+                sessions: weeks.len(),
+                students_graded,
+                hours: cents(hours),
+                amount: cents(hours * rates.hourly_rate),
+            }
+        })
+        .collect()
+}
+
+// One line per TA, in the layout the stipend spreadsheet imports
+pub fn workload_table(workload: &[TaWorkload], rates: &CompensationRates) -> ExportTable {
+    ExportTable {
+        columns: [
+            "ta",
+            "sessions",
+            "students_graded",
+            "hours",
+            "hourly_rate",
+            "amount",
+            "currency",
+        ]
+        .map(str::to_string)
+        .to_vec(),
+        rows: workload
+            .iter()
+            .map(|ta| {
+                vec![
+                    Value::from(ta.ta.as_str()),
+                    Value::from(ta.sessions),
+                    Value::from(ta.students_graded),
+                    Value::from(format!("{:.2}", ta.hours)),
+                    Value::from(format!("{:.2}", rates.hourly_rate)),
+                    Value::from(format!("{:.2}", ta.amount)),
+                    Value::from(rates.currency.as_str()),
+                ]
+            })
+            .collect(),
+    }
+}
+
+#[allow(dead_code)] // Only used by the tests
+pub fn workload_csv(
+    workload: &[TaWorkload],
+    rates: &CompensationRates,
+) -> Result<String, AppError> {
+    ExportBody::new(Box::new(CsvExporter), workload_table(workload, rates)).into_string()
+}
@@ -0,0 +1,140 @@
+use crate::services::grouping::ABSENT_GROUP;
+use crate::utils::types::{ConstraintKind, GroupingConstraint, RowData};
+use serde::Serialize;
+
+// A constraint the grouping for a week does not honor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstraintViolation {
+    pub constraint_id: i64,
+    pub kind: ConstraintKind,
+    pub first: String,
+    pub second: String,
+    pub reason: String,
+}
+
+fn is_seated(row: &RowData) -> bool {
+    row.attendance.as_deref() == Some("yes") && row.group_id != ABSENT_GROUP
+}
+
+fn seated(rows: &[RowData], name: &str) -> Option<usize> {
+    rows.iter()
+        .position(|row| row.name == name && is_seated(row))
+}
+
+// Constraints only apply when both students are seated in a regular group
+fn is_broken(rows: &[RowData], constraint: &GroupingConstraint) -> bool {
+    match (
+        seated(rows, &constraint.first),
+        seated(rows, &constraint.second),
+    ) {
+        (Some(a), Some(b)) => match constraint.kind {
+            ConstraintKind::Together => rows[a].group_id != rows[b].group_id,
+            ConstraintKind::Apart => rows[a].group_id == rows[b].group_id,
+        },
+        _ => false,
+    }
+}
+
+fn broken_count(rows: &[RowData], constraints: &[GroupingConstraint]) -> usize {
+    constraints.iter().filter(|c| is_broken(rows, c)).count()
+}
+
+// Exchanges the seats of two students, keeping every group's size and TA
+fn swap_seats(rows: &mut [RowData], a: usize, b: usize) {
+    let group_id = rows[a].group_id.clone();
+    let ta = rows[a].ta.clone();
+    rows[a].group_id = rows[b].group_id.clone();
+    rows[a].ta = rows[b].ta.clone();
+    rows[b].group_id = group_id;
+    rows[b].ta = ta;
+}
+
+pub fn check_constraints(
+    rows: &[RowData],
+    constraints: &[GroupingConstraint],
+) -> Vec<ConstraintViolation> {
+    constraints
+        .iter()
+        .filter(|c| is_broken(rows, c))
+        .map(|c| {
+            let group_of = |name: &str| {
+                seated(rows, name)
+                    .map(|i| rows[i].group_id.clone())
+                    .unwrap_or_default()
+            };
+            let reason = match c.kind {
+                ConstraintKind::Together => format!(
+                    "{} is in {} but {} is in {}",
+                    c.first,
+                    group_of(&c.first),
+                    c.second,
+                    group_of(&c.second)
+                ),
+                ConstraintKind::Apart => format!(
+                    "{} and {} are both in {}",
+                    c.first,
+                    c.second,
+                    group_of(&c.first)
+                ),
+            };
+            ConstraintViolation {
+                constraint_id: c.id,
+                kind: c.kind,
+                first: c.first.clone(),
+                second: c.second.clone(),
+                reason,
+            }
+        })
+        .collect()
+}
+
+// Adjusts groups produced by any grouping strategy so they honor the
+// constraints, by swapping seated students between groups. A swap is only
+// kept when it reduces the number of broken constraints, so group sizes and
+// TAs are preserved and the repair always terminates. Whatever is still
+// broken afterwards cannot be satisfied by swaps and is reported.
+pub fn apply_constraints(
+    rows: &mut [RowData],
+    constraints: &[GroupingConstraint],
+) -> Vec<ConstraintViolation> {
+    let mut broken = broken_count(rows, constraints);
+
+    'repair: while broken > 0 {
+        for constraint in constraints {
+            if !is_broken(rows, constraint) {
+                continue;
+            }
+            let (Some(a), Some(b)) = (
+                seated(rows, &constraint.first),
+                seated(rows, &constraint.second),
+            ) else {
+                continue;
+            };
+
+            // Move either student of the pair into (or out of) the other's group
+            for (moving, anchor) in [(b, a), (a, b)] {
+                let anchor_group = rows[anchor].group_id.clone();
+                let candidates: Vec<usize> = (0..rows.len())
+                    .filter(|&i| i != moving && i != anchor && is_seated(&rows[i]))
+                    .filter(|&i| match constraint.kind {
+                        ConstraintKind::Together => rows[i].group_id == anchor_group,
+                        ConstraintKind::Apart => rows[i].group_id != anchor_group,
+                    })
+                    .collect();
+
+                for other in candidates {
+                    swap_seats(rows, moving, other);
+                    let now = broken_count(rows, constraints);
+                    if now < broken {
+                        broken = now;
+                        continue 'repair;
+                    }
+                    swap_seats(rows, moving, other);
+                }
+            }
+        }
+        break;
+    }
+
+    check_constraints(rows, constraints)
+}
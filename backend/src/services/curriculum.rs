@@ -0,0 +1,139 @@
+//! Weekly curriculum feedback from TAs' exercise review summaries.
+//!
+//! Each TA lists the common mistakes and standout solutions they saw in their
+//! group's exercises. Points raised by several groups are merged, ignoring
+//! case and trailing punctuation, and listed first, so maintainers can tell a
+//! problem with the exercise from a problem of one group.
+
+use crate::utils::types::{ReviewSummary, RowData};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Points a summary may list per section, and the length of each
+const MAX_POINTS: usize = 10;
+const MAX_POINT_LENGTH: usize = 280;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReviewPoint {
+    // As first written by a TA
+    pub text: String,
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupNote {
+    pub group_id: String,
+    pub ta: String,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CurriculumWeek {
+    pub week: i32,
+    pub groups_reviewed: usize,
+    // Groups of the week without a summary yet
+    pub missing: Vec<String>,
+    pub common_mistakes: Vec<ReviewPoint>,
+    pub standout_solutions: Vec<ReviewPoint>,
+    pub notes: Vec<GroupNote>,
+}
+
+// The points of one section of a submitted summary, trimmed and on one line
+// each. Blank points are dropped.
+pub fn clean_points(section: &str, points: Vec<String>) -> Result<Vec<String>, String> {
+    let points: Vec<String> = points
+        .iter()
+        .map(|point| point.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|point| !point.is_empty())
+        .collect();
+    if points.len() > MAX_POINTS {
+        return Err(format!(
+            "At most {} {} can be listed",
+            MAX_POINTS,
+            section.replace('_', " ")
+        ));
+    }
+    if let Some(point) = points
+        .iter()
+        .find(|point| point.chars().count() > MAX_POINT_LENGTH)
+    {
+        return Err(format!(
+            "{} must be at most {} characters each: {:?}",
+            section.replace('_', " "),
+            MAX_POINT_LENGTH,
+            point
+        ));
+    }
+    Ok(points)
+}
+
+// Key under which the same point from different groups is merged
+fn point_key(point: &str) -> String {
+    point
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase()
+}
+
+fn merge_points<'a>(
+    summaries: &[&'a ReviewSummary],
+    section: impl Fn(&'a ReviewSummary) -> &'a [String],
+) -> Vec<ReviewPoint> {
+    let mut merged: Vec<ReviewPoint> = Vec::new();
+    let mut index: BTreeMap<String, usize> = BTreeMap::new();
+    for summary in summaries {
+        for point in section(summary) {
+            let at = *index.entry(point_key(point)).or_insert_with(|| {
+                merged.push(ReviewPoint {
+                    text: point.clone(),
+                    groups: Vec::new(),
+                });
+                merged.len() - 1
+            });
+            if !merged[at].groups.contains(&summary.group_id) {
+                merged[at].groups.push(summary.group_id.clone());
+            }
+        }
+    }
+    // Stable, so points raised by as many groups keep their first-seen order
+    merged.sort_by_key(|point| std::cmp::Reverse(point.groups.len()));
+    merged
+}
+
+// One entry per week with at least one summary, oldest first
+pub fn curriculum_report(summaries: &[ReviewSummary], rows: &[RowData]) -> Vec<CurriculumWeek> {
+    let mut by_week: BTreeMap<i32, Vec<&ReviewSummary>> = BTreeMap::new();
+    for summary in summaries {
+        by_week.entry(summary.week).or_default().push(summary);
+    }
+
+    by_week
+        .into_iter()
+        .map(|(week, mut summaries)| {
+            summaries.sort_by(|a, b| a.group_id.cmp(&b.group_id));
+            let reviewed: BTreeSet<&str> = summaries.iter().map(|s| s.group_id.as_str()).collect();
+            let missing: BTreeSet<String> = rows
+                .iter()
+                .filter(|row| row.week == week && !row.group_id.is_empty())
+                .filter(|row| !reviewed.contains(row.group_id.as_str()))
+                .map(|row| row.group_id.clone())
+                .collect();
+            CurriculumWeek {
+                week,
+                groups_reviewed: summaries.len(),
+                missing: missing.into_iter().collect(),
+                common_mistakes: merge_points(&summaries, |s| &s.common_mistakes),
+                standout_solutions: merge_points(&summaries, |s| &s.standout_solutions),
+                notes: summaries
+                    .iter()
+                    .filter_map(|s| {
+                        Some(GroupNote {
+                            group_id: s.group_id.clone(),
+                            ta: s.ta.clone(),
+                            note: s.notes.clone()?,
+                        })
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
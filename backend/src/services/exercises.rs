@@ -0,0 +1,152 @@
+use crate::services::scoring::{exercise_result, is_yes};
+use crate::utils::classroom::Assignment;
+use crate::utils::types::{ExerciseAttempt, ExerciseOutcome, RowData};
+use chrono::DateTime;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// What one cohort contributes to the exercise analytics
+#[derive(Debug, Default)]
+pub struct CohortExercises {
+    pub outcomes: BTreeMap<i32, ExerciseOutcome>,
+    pub attempts: Vec<ExerciseAttempt>,
+}
+
+// Submissions of one student and hours to their first submission
+type StudentAttempts = (usize, Option<f64>);
+
+#[derive(Debug, Serialize)]
+pub struct ExerciseStats {
+    pub week: i32,
+    pub cohorts: usize,
+    pub submitted: usize,
+    pub passing: usize,
+    // Share of submissions whose tests pass
+    pub pass_rate: Option<f64>,
+    // Submissions per student, each one a CI run
+    pub avg_ci_attempts: Option<f64>,
+    // Hours from the sync first seeing a student's repo to their first submission
+    pub avg_hours_to_submission: Option<f64>,
+}
+
+// Week 0 is enrollment and has no exercise
+pub fn exercise_outcomes(rows: &[RowData]) -> BTreeMap<i32, ExerciseOutcome> {
+    let mut outcomes: BTreeMap<i32, ExerciseOutcome> = BTreeMap::new();
+    for row in rows.iter().filter(|row| row.week >= 1) {
+        let outcome = outcomes.entry(row.week).or_default();
+        if is_yes(&row.exercise_submitted) {
+            outcome.submitted += 1;
+            if is_yes(&row.exercise_test_passing) {
+                outcome.passing += 1;
+            }
+        }
+    }
+    outcomes
+}
+
+// Submission states of the week's repos as seen by a sync at `now`
+pub fn observed_attempts(assignments: &[Assignment], week: i32, now: &str) -> Vec<ExerciseAttempt> {
+    assignments
+        .iter()
+        .filter_map(|assignment| {
+            let result = exercise_result(assignment, week)?;
+            let submitted_at = assignment.submission_timestamp.clone().unwrap_or_default();
+            Some(ExerciseAttempt {
+                week,
+                github: assignment.github_username.clone(),
+                passing: result.tests_passing && !submitted_at.is_empty(),
+                submitted_at,
+                recorded_at: now.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Submission states of a past week's repos for a backfill. No sync saw them
+// at the time, so each is dated by its submission; unsubmitted repos have no
+// state worth keeping.
+pub fn backfilled_attempts(assignments: &[Assignment], week: i32) -> Vec<ExerciseAttempt> {
+    observed_attempts(assignments, week, "")
+        .into_iter()
+        .filter(|attempt| !attempt.submitted_at.is_empty())
+        .map(|attempt| ExerciseAttempt {
+            recorded_at: attempt.submitted_at.clone(),
+            ..attempt
+        })
+        .collect()
+}
+
+// Attempts and hours to first submission for each student who submitted,
+// keyed by week. Hours are unknown when the repo was first seen already
+// submitted or a timestamp does not parse.
+fn student_attempts(attempts: &[ExerciseAttempt]) -> BTreeMap<i32, Vec<StudentAttempts>> {
+    let mut by_student: HashMap<(i32, &str), Vec<&ExerciseAttempt>> = HashMap::new();
+    for attempt in attempts {
+        by_student
+            .entry((attempt.week, &attempt.github))
+            .or_default()
+            .push(attempt);
+    }
+
+    let mut by_week: BTreeMap<i32, Vec<StudentAttempts>> = BTreeMap::new();
+    for ((week, _), seen) in by_student {
+        let submissions: Vec<&str> = seen
+            .iter()
+            .map(|a| a.submitted_at.as_str())
+            .filter(|at| !at.is_empty())
+            .collect();
+        if submissions.is_empty() {
+            continue;
+        }
+
+        let parse = |at: &str| DateTime::parse_from_rfc3339(at).ok();
+        let first_seen = seen.iter().filter_map(|a| parse(&a.recorded_at)).min();
+        let first_submission = submissions.iter().filter_map(|at| parse(at)).min();
+        let hours = match (first_seen, first_submission) {
+            (Some(seen), Some(submitted)) if submitted >= seen => {
+                Some((submitted - seen).num_minutes() as f64 / 60.0)
+            }
+            _ => None,
+        };
+        by_week
+            .entry(week)
+            .or_default()
+            .push((submissions.len(), hours));
+    }
+    by_week
+}
+
+fn average(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+// Per exercise statistics pooled across cohorts, by week
+pub fn exercise_stats(cohorts: &[CohortExercises]) -> Vec<ExerciseStats> {
+    let mut weeks: BTreeMap<i32, (usize, ExerciseOutcome, Vec<StudentAttempts>)> = BTreeMap::new();
+    for cohort in cohorts {
+        for (&week, outcome) in &cohort.outcomes {
+            let (count, total, _) = weeks.entry(week).or_default();
+            *count += 1;
+            total.submitted += outcome.submitted;
+            total.passing += outcome.passing;
+        }
+        for (week, students) in student_attempts(&cohort.attempts) {
+            weeks.entry(week).or_default().2.extend(students);
+        }
+    }
+
+    weeks
+        .into_iter()
+        .map(|(week, (cohorts, outcome, students))| ExerciseStats {
+            week,
+            cohorts,
+            submitted: outcome.submitted,
+            passing: outcome.passing,
+            pass_rate: (outcome.submitted > 0)
+                .then(|| outcome.passing as f64 / outcome.submitted as f64),
+            avg_ci_attempts: average(students.iter().map(|(attempts, _)| *attempts as f64)),
+            avg_hours_to_submission: average(students.iter().filter_map(|(_, hours)| *hours)),
+        })
+        .collect()
+}
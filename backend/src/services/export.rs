@@ -0,0 +1,279 @@
+//! Downloadable exports of tabular reports.
+//!
+//! A report is turned into an `ExportTable` once, narrowed to the columns the
+//! caller asked for, and written out by the `Exporter` of the requested
+//! format one row at a time. Adding a format is one more `Exporter`.
+
+use crate::utils::types::AppError;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use serde::Serialize;
+use serde_json::Value;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// Formats `exporter` knows, for error messages
+pub const FORMATS: &[&str] = &["csv", "json", "markdown"];
+
+// Column names and, per row, one value for each column
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl ExportTable {
+    // The given fields of each item, as it serializes to JSON. Fields an
+    // item leaves out are empty.
+    pub fn from_items<T: Serialize>(columns: &[&str], items: &[T]) -> Result<Self, AppError> {
+        let rows = items
+            .iter()
+            .map(|item| {
+                let value = serde_json::to_value(item)
+                    .map_err(|e| AppError::Io(std::io::Error::other(e)))?;
+                Ok(columns
+                    .iter()
+                    .map(|column| value.get(column).cloned().unwrap_or(Value::Null))
+                    .collect())
+            })
+            .collect::<Result<_, AppError>>()?;
+        Ok(ExportTable {
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            rows,
+        })
+    }
+
+    // Only the `wanted` columns, comma separated, in the order given. All
+    // columns when `wanted` is empty.
+    pub fn select(self, wanted: &str) -> Result<Self, String> {
+        let wanted: Vec<&str> = wanted
+            .split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .collect();
+        if wanted.is_empty() {
+            return Ok(self);
+        }
+        let indexes = wanted
+            .iter()
+            .map(|column| {
+                self.columns
+                    .iter()
+                    .position(|c| c == column)
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown column {:?}, expected one of {}",
+                            column,
+                            self.columns.join(", ")
+                        )
+                    })
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        Ok(ExportTable {
+            columns: wanted.iter().map(|column| column.to_string()).collect(),
+            rows: self
+                .rows
+                .into_iter()
+                .map(|row| indexes.iter().map(|&i| row[i].clone()).collect())
+                .collect(),
+        })
+    }
+}
+
+// Writes a table in one format, as a header chunk, a chunk per row and a
+// closing chunk
+pub trait Exporter: Send {
+    fn content_type(&self) -> &'static str;
+    fn extension(&self) -> &'static str;
+    fn begin(&mut self, columns: &[String]) -> Result<Vec<u8>, AppError>;
+    fn row(&mut self, values: &[Value]) -> Result<Vec<u8>, AppError>;
+    fn end(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+// The exporter for `?format=`, if there is one
+pub fn exporter(format: &str) -> Option<Box<dyn Exporter>> {
+    match format.to_ascii_lowercase().as_str() {
+        "csv" => Some(Box::new(CsvExporter)),
+        "json" => Some(Box::new(JsonExporter::default())),
+        "markdown" | "md" => Some(Box::new(MarkdownExporter)),
+        _ => None,
+    }
+}
+
+// A value as text: strings as they are, empty for no value
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub struct CsvExporter;
+
+impl CsvExporter {
+    fn record<I: IntoIterator<Item = String>>(fields: I) -> Result<Vec<u8>, AppError> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(fields)?;
+        writer
+            .into_inner()
+            .map_err(|e| AppError::Io(e.into_error()))
+    }
+}
+
+impl Exporter for CsvExporter {
+    fn content_type(&self) -> &'static str {
+        "text/csv; charset=utf-8"
+    }
+
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn begin(&mut self, columns: &[String]) -> Result<Vec<u8>, AppError> {
+        Self::record(columns.iter().cloned())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<Vec<u8>, AppError> {
+        Self::record(values.iter().map(text))
+    }
+}
+
+// An array of objects with the columns as keys, in column order
+#[derive(Default)]
+pub struct JsonExporter {
+    columns: Vec<String>,
+    rows_written: usize,
+}
+
+impl Exporter for JsonExporter {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn begin(&mut self, columns: &[String]) -> Result<Vec<u8>, AppError> {
+        self.columns = columns.to_vec();
+        Ok(b"[".to_vec())
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<Vec<u8>, AppError> {
+        let fields: Vec<String> = self
+            .columns
+            .iter()
+            .zip(values)
+            .map(|(column, value)| format!("{}:{}", Value::from(column.as_str()), value))
+            .collect();
+        let separator = if self.rows_written == 0 { "" } else { "," };
+        self.rows_written += 1;
+        Ok(format!("{}{{{}}}", separator, fields.join(",")).into_bytes())
+    }
+
+    fn end(&mut self) -> Vec<u8> {
+        b"]".to_vec()
+    }
+}
+
+// A pipe table, for pasting into issues and docs
+pub struct MarkdownExporter;
+
+impl MarkdownExporter {
+    fn line<I: IntoIterator<Item = String>>(cells: I) -> Vec<u8> {
+        let cells: Vec<String> = cells
+            .into_iter()
+            .map(|cell| cell.replace('|', "\\|").replace(['\r', '\n'], " "))
+            .collect();
+        format!("| {} |\n", cells.join(" | ")).into_bytes()
+    }
+}
+
+impl Exporter for MarkdownExporter {
+    fn content_type(&self) -> &'static str {
+        "text/markdown; charset=utf-8"
+    }
+
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+
+    fn begin(&mut self, columns: &[String]) -> Result<Vec<u8>, AppError> {
+        let mut header = Self::line(columns.iter().cloned());
+        header.extend(Self::line(columns.iter().map(|_| "---".to_string())));
+        Ok(header)
+    }
+
+    fn row(&mut self, values: &[Value]) -> Result<Vec<u8>, AppError> {
+        Ok(Self::line(values.iter().map(text)))
+    }
+}
+
+// A table written by an exporter as the response body, a row at a time, so
+// a large export is not first built up as one buffer
+pub struct ExportBody {
+    exporter: Box<dyn Exporter>,
+    columns: Vec<String>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+    started: bool,
+    finished: bool,
+}
+
+impl ExportBody {
+    pub fn new(exporter: Box<dyn Exporter>, table: ExportTable) -> Self {
+        ExportBody {
+            exporter,
+            columns: table.columns,
+            rows: table.rows.into_iter(),
+            started: false,
+            finished: false,
+        }
+    }
+
+    // The next chunk, or none once the closing chunk was written
+    fn next_chunk(&mut self) -> Option<Result<Vec<u8>, AppError>> {
+        if !self.started {
+            self.started = true;
+            return Some(self.exporter.begin(&self.columns));
+        }
+        if let Some(row) = self.rows.next() {
+            return Some(self.exporter.row(&row));
+        }
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        Some(Ok(self.exporter.end()))
+    }
+
+    // The whole export at once, for callers that need it as a value
+    pub fn into_string(mut self) -> Result<String, AppError> {
+        let mut out = Vec::new();
+        while let Some(chunk) = self.next_chunk() {
+            out.extend(chunk?);
+        }
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+}
+
+impl MessageBody for ExportBody {
+    type Error = AppError;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Poll::Ready(
+            self.get_mut()
+                .next_chunk()
+                .map(|chunk| chunk.map(Bytes::from)),
+        )
+    }
+}
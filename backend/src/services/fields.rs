@@ -0,0 +1,95 @@
+//! Sparse field selection of weekly rows: `?fields=name,attendance,total`
+//! keeps only those fields of each row, so summary views need not download
+//! every exercise and bonus column of every student.
+
+use crate::utils::types::RowData;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// Fields of a row as named in its JSON
+pub const ROW_FIELDS: &[&str] = &[
+    "name",
+    "group_id",
+    "ta",
+    "attendance",
+    "fa",
+    "fb",
+    "fc",
+    "fd",
+    "bonus_attempt",
+    "bonus_answer_quality",
+    "bonus_follow_up",
+    "exercise_submitted",
+    "exercise_test_passing",
+    "exercise_good_documentation",
+    "exercise_good_structure",
+    "total",
+    "notes",
+    "mail",
+    "week",
+    "participant_id",
+    "public_id",
+    "student_id",
+    "version",
+    "commit_count",
+    "last_commit_at",
+    "repo_url",
+];
+
+// `?fields`, comma separated. Without it rows keep every field.
+#[derive(Debug, Default, Deserialize)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+}
+
+impl FieldsQuery {
+    fn wanted(&self) -> Vec<&str> {
+        self.fields
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self
+            .wanted()
+            .into_iter()
+            .find(|field| !ROW_FIELDS.contains(field))
+        {
+            Some(field) => Err(format!(
+                "Unknown field {:?}, expected one of {}",
+                field,
+                ROW_FIELDS.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // The rows as JSON with only the selected fields. A field a row leaves
+    // out, such as an unassigned public id, stays out.
+    pub fn select(&self, rows: &[RowData]) -> Result<Value, String> {
+        self.validate()?;
+        let wanted = self.wanted();
+        if wanted.is_empty() {
+            return serde_json::to_value(rows).map_err(|e| e.to_string());
+        }
+        rows.iter()
+            .map(|row| match serde_json::to_value(row) {
+                Ok(Value::Object(mut fields)) => Ok(Value::Object(
+                    wanted
+                        .iter()
+                        .filter_map(|&field| {
+                            fields.remove(field).map(|value| (field.to_string(), value))
+                        })
+                        .collect::<Map<String, Value>>(),
+                )),
+                Ok(other) => Ok(other),
+                Err(e) => Err(e.to_string()),
+            })
+            .collect::<Result<Vec<Value>, String>>()
+            .map(Value::Array)
+    }
+}
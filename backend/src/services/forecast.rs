@@ -0,0 +1,99 @@
+use crate::services::grouping::group_count;
+use crate::utils::types::RowData;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Recent week-over-week changes used when no past cohort reached the week
+const RECENT_WEEKS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastBasis {
+    // Average retention of past cohorts for the same week
+    PastCohorts,
+    // Average retention of the current cohort's latest weeks
+    CurrentCohort,
+    // No history yet, attendance is assumed to stay flat
+    NoHistory,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeekAttendance {
+    pub week: i32,
+    pub attended: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttendanceForecast {
+    pub week: i32,
+    pub basis: ForecastBasis,
+    pub retention_rate: f64,
+    pub projected_attendance: usize,
+    pub groups: usize,
+    pub tas_needed: usize,
+    pub history: Vec<WeekAttendance>,
+}
+
+// Week 0 counts enrolled students, later weeks count students present
+pub fn attendance_by_week(rows: &[RowData]) -> BTreeMap<i32, usize> {
+    let mut counts = BTreeMap::new();
+    for row in rows {
+        if row.week == 0 || row.attendance.as_deref() == Some("yes") {
+            *counts.entry(row.week).or_default() += 1;
+        }
+    }
+    counts
+}
+
+// Share of the previous week's attendance that came back in `week`
+fn retention(counts: &BTreeMap<i32, usize>, week: i32) -> Option<f64> {
+    let previous = *counts.get(&(week - 1))?;
+    let current = *counts.get(&week)?;
+    (previous > 0).then(|| current as f64 / previous as f64)
+}
+
+fn average(rates: &[f64]) -> Option<f64> {
+    (!rates.is_empty()).then(|| rates.iter().sum::<f64>() / rates.len() as f64)
+}
+
+// Projects attendance for the week after the latest recorded one. Past
+// cohorts' attrition at the same point of the program is preferred since
+// drop-off is steepest early on; the current cohort's recent trend is the
+// fallback. Each present student needs a seat, each group a TA and room.
+pub fn forecast_attendance(
+    current: &BTreeMap<i32, usize>,
+    past: &[BTreeMap<i32, usize>],
+    tas: usize,
+) -> AttendanceForecast {
+    let latest = current.keys().next_back().copied().unwrap_or(0);
+    let week = latest + 1;
+
+    let past_rates: Vec<f64> = past.iter().filter_map(|c| retention(c, week)).collect();
+    let recent_rates: Vec<f64> = (1..=latest)
+        .rev()
+        .filter_map(|w| retention(current, w))
+        .take(RECENT_WEEKS)
+        .collect();
+    let (basis, retention_rate) = match (average(&past_rates), average(&recent_rates)) {
+        (Some(rate), _) => (ForecastBasis::PastCohorts, rate),
+        (None, Some(rate)) => (ForecastBasis::CurrentCohort, rate),
+        (None, None) => (ForecastBasis::NoHistory, 1.0),
+    };
+
+    let last_attended = current.get(&latest).copied().unwrap_or(0);
+    let projected_attendance = (last_attended as f64 * retention_rate).round() as usize;
+    let groups = group_count(projected_attendance, tas);
+
+    AttendanceForecast {
+        week,
+        basis,
+        retention_rate,
+        projected_attendance,
+        groups,
+        tas_needed: groups,
+        history: current
+            .iter()
+            .map(|(&week, &attended)| WeekAttendance { week, attended })
+            .collect(),
+    }
+}
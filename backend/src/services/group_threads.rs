@@ -0,0 +1,96 @@
+use crate::utils::types::{GroupThread, RowData};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// What publishing a week's groups does to one group's Discord thread
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ThreadPlan {
+    pub group_id: String,
+    // Thread from an earlier publish; a new one is created when unset
+    pub thread_id: Option<String>,
+    pub ta: Option<String>,
+    pub participants: Vec<String>,
+    // Discord ids of the participants and the TA
+    pub members: Vec<String>,
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+    // Participants and TAs without a Discord handle, who cannot be added
+    pub unmapped: Vec<String>,
+}
+
+// Plans the threads for a week's groups against the threads of an earlier
+// publish, so regenerating groups and publishing again only adds and removes
+// the members that changed. Names are mapped to Discord ids through the
+// handle mapping (discord id -> name), which also covers TAs.
+pub fn plan_group_threads(
+    week_rows: &[RowData],
+    handles: &HashMap<String, String>,
+    existing: &[GroupThread],
+) -> Vec<ThreadPlan> {
+    let mut ids_by_name: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for (discord_id, name) in handles {
+        ids_by_name
+            .entry(name.as_str())
+            .or_default()
+            .insert(discord_id.as_str());
+    }
+
+    let mut groups: BTreeMap<&str, Vec<&RowData>> = BTreeMap::new();
+    for row in week_rows
+        .iter()
+        .filter(|row| !row.group_id.trim().is_empty())
+    {
+        groups.entry(row.group_id.as_str()).or_default().push(row);
+    }
+
+    groups
+        .into_iter()
+        .map(|(group_id, rows)| {
+            let ta = rows
+                .iter()
+                .filter_map(|row| row.ta.as_deref())
+                .find(|ta| !ta.trim().is_empty())
+                .map(str::to_string);
+            let mut participants: Vec<String> = rows.iter().map(|row| row.name.clone()).collect();
+            participants.sort();
+
+            let mut members = BTreeSet::new();
+            let mut unmapped = Vec::new();
+            for name in participants.iter().chain(ta.iter()) {
+                match ids_by_name.get(name.as_str()) {
+                    Some(ids) => members.extend(ids.iter().map(|id| id.to_string())),
+                    None => unmapped.push(name.clone()),
+                }
+            }
+
+            let previous = existing.iter().find(|thread| thread.group_id == group_id);
+            let current: BTreeSet<String> = previous
+                .map(|thread| thread.members.iter().cloned().collect())
+                .unwrap_or_default();
+            ThreadPlan {
+                group_id: group_id.to_string(),
+                thread_id: previous.map(|thread| thread.thread_id.clone()),
+                ta,
+                participants,
+                add: members.difference(&current).cloned().collect(),
+                remove: current.difference(&members).cloned().collect(),
+                members: members.into_iter().collect(),
+                unmapped,
+            }
+        })
+        .collect()
+}
+
+// Default first message of a new group thread
+pub fn default_agenda(week: i32, plan: &ThreadPlan) -> String {
+    let mut agenda = format!(
+        "Week {} discussion, {}\nParticipants: {}",
+        week,
+        plan.group_id,
+        plan.participants.join(", ")
+    );
+    if let Some(ta) = &plan.ta {
+        agenda.push_str(&format!("\nTA: {}", ta));
+    }
+    agenda
+}
@@ -0,0 +1,185 @@
+use crate::handlers::auth::TA;
+use crate::utils::types::RowData;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+
+// The first present students are seated in fixed size groups, everyone
+// after that gets a group of their own
+pub const SEATED_STUDENTS: usize = 30;
+pub const SEATED_GROUP_SIZE: usize = 6;
+
+// Absent students are parked in this group under Setu
+pub const ABSENT_GROUP: &str = "Group 6";
+pub fn absent_ta() -> TA {
+    TA::from_name("Setu").expect("Setu is a founding TA")
+}
+
+// TAs that lead groups, in rotation order
+pub fn rotation_tas() -> Vec<TA> {
+    TA::all_variants()
+        .into_iter()
+        .filter(|ta| *ta != absent_ta())
+        .collect()
+}
+
+// The rotation without offboarded TAs
+pub fn active_tas(inactive: &HashSet<String>) -> Vec<TA> {
+    rotation_tas()
+        .into_iter()
+        .filter(|ta| !inactive.contains(&ta.name()))
+        .collect()
+}
+
+// Hands each group `leaving` leads in `rows` (one week) to the TA leading
+// the fewest groups, then the fewest students, earliest in rotation order
+// on ties. Groups stay together; largest groups are placed first so they
+// spread across TAs. Returns the changed rows.
+pub fn reassign_groups(rows: &[RowData], leaving: &TA, tas: &[TA]) -> Vec<RowData> {
+    let leaving = leaving.name();
+    let mut load: Vec<(TA, HashSet<&str>, usize)> = tas
+        .iter()
+        .filter(|ta| ta.name() != leaving)
+        .map(|ta| {
+            let led: Vec<&RowData> = rows
+                .iter()
+                .filter(|row| row.ta.as_deref() == Some(ta.name().as_str()))
+                .collect();
+            let groups = led.iter().map(|row| row.group_id.as_str()).collect();
+            (ta.clone(), groups, led.len())
+        })
+        .collect();
+    if load.is_empty() {
+        return Vec::new();
+    }
+
+    let mut groups: BTreeMap<&str, Vec<&RowData>> = BTreeMap::new();
+    for row in rows
+        .iter()
+        .filter(|row| row.ta.as_deref() == Some(leaving.as_str()))
+    {
+        groups.entry(row.group_id.as_str()).or_default().push(row);
+    }
+    let mut groups: Vec<(&str, Vec<&RowData>)> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, members)| std::cmp::Reverse(members.len()));
+
+    let mut changed = Vec::new();
+    for (group, members) in groups {
+        let (ta, led, students) = load
+            .iter_mut()
+            .min_by_key(|(_, led, students)| (led.len(), *students))
+            .unwrap();
+        led.insert(group);
+        *students += members.len();
+        changed.extend(members.into_iter().map(|row| RowData {
+            ta: Some(ta.name()),
+            ..row.clone()
+        }));
+    }
+    changed
+}
+
+// Number of regular groups `assign_groups` forms for `present` students.
+// Students past the seated ones each open a new group until every TA
+// leads one.
+pub fn group_count(present: usize, tas: usize) -> usize {
+    let seated = present.min(SEATED_STUDENTS).div_ceil(SEATED_GROUP_SIZE);
+    (seated + present.saturating_sub(SEATED_STUDENTS)).min(tas)
+}
+
+// Orders rows for grouping: attended first, then by total, then by name
+pub fn sort_for_grouping(rows: &mut [RowData]) {
+    rows.sort_by(|a, b| {
+        b.attendance
+            .partial_cmp(&a.attendance)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| {
+                b.total
+                    .partial_cmp(&a.total)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| b.name.cmp(&a.name))
+            })
+    });
+}
+
+// The group id of a group given by number, e.g. "3" for "Group 3", or as is
+pub fn group_id(group: &str) -> String {
+    match group.trim().parse::<u32>() {
+        Ok(number) => format!("Group {}", number),
+        Err(_) => group.trim().to_string(),
+    }
+}
+
+// Assigns groups and TAs for `week` based on the previous week's rows.
+// Rows are sorted first; TAs rotate across groups from week to week.
+// Rows without recorded attendance keep their previous group.
+pub fn assign_groups(mut rows: Vec<RowData>, week: i32, tas: &[TA]) -> Vec<RowData> {
+    sort_for_grouping(&mut rows);
+
+    let mut group_id: isize = -1;
+    for (index, row) in rows.iter_mut().enumerate() {
+        match row.attendance.as_deref() {
+            Some("no") => {
+                row.group_id = ABSENT_GROUP.to_string();
+                row.ta = Some(absent_ta().name());
+            }
+            Some("yes") => {
+                if index >= SEATED_STUDENTS || index % SEATED_GROUP_SIZE == 0 {
+                    group_id += 1;
+                }
+                let group = (group_id as usize) % tas.len();
+                let assigned_ta = &tas[(group + week as usize - 1) % tas.len()];
+                row.group_id = format!("Group {}", group + 1);
+                row.ta = Some(format!("{:?}", assigned_ta));
+            }
+            _ => {}
+        }
+        row.week = week;
+    }
+    rows
+}
+
+// Where a student sat in one week
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupWeek {
+    pub week: i32,
+    pub group_id: String,
+    pub ta: Option<String>,
+    pub present: bool,
+    // Others seated in the same group; empty for the absent group
+    pub groupmates: Vec<String>,
+}
+
+// The student's group every generated week, oldest first. Week 0 only
+// records enrollment and is left out.
+pub fn group_history(rows: &[RowData], name: &str) -> Vec<GroupWeek> {
+    let mut history: Vec<GroupWeek> = rows
+        .iter()
+        .filter(|row| row.name == name && row.week > 0)
+        .map(|row| {
+            let absent = row.group_id == ABSENT_GROUP;
+            let mut groupmates: Vec<String> = if absent {
+                Vec::new()
+            } else {
+                rows.iter()
+                    .filter(|other| {
+                        other.week == row.week
+                            && other.group_id == row.group_id
+                            && other.name != row.name
+                    })
+                    .map(|other| other.name.clone())
+                    .collect()
+            };
+            groupmates.sort();
+            GroupWeek {
+                week: row.week,
+                group_id: row.group_id.clone(),
+                ta: row.ta.clone(),
+                present: row.attendance.as_deref() == Some("yes"),
+                groupmates,
+            }
+        })
+        .collect();
+    history.sort_by_key(|week| week.week);
+    history
+}
@@ -0,0 +1,129 @@
+use crate::services::invariants::{Violation, check_totals};
+use crate::utils::types::RowData;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+// Weekly rows of a student with no participant of the same name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnknownStudent {
+    pub name: String,
+    pub weeks: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateRow {
+    pub name: String,
+    pub week: i32,
+    pub count: usize,
+}
+
+// Enrolled students without a row in a week that has been generated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissingRows {
+    pub week: i32,
+    pub students: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TotalMismatch {
+    pub name: String,
+    pub week: i32,
+    pub total: u64,
+    pub expected: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityReport {
+    pub checked_at: DateTime<Utc>,
+    pub rows: usize,
+    pub problems: usize,
+    pub unknown_students: Vec<UnknownStudent>,
+    pub duplicate_rows: Vec<DuplicateRow>,
+    pub missing_rows: Vec<MissingRows>,
+    pub total_mismatches: Vec<TotalMismatch>,
+}
+
+// Scans the live rows against the participants. Names are compared
+// case-insensitively, as participant lookups are. Duplicates come from the
+// database, since the in-memory table holds one row per (name, week).
+pub fn integrity_report(
+    rows: &[RowData],
+    participants: &[String],
+    duplicates: Vec<(String, i32, usize)>,
+) -> IntegrityReport {
+    let known: HashSet<String> = participants
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .collect();
+    let mut unknown: BTreeMap<&str, BTreeSet<i32>> = BTreeMap::new();
+    for row in rows {
+        if !known.contains(&row.name.trim().to_lowercase()) {
+            unknown.entry(&row.name).or_default().insert(row.week);
+        }
+    }
+
+    let enrolled: BTreeSet<&str> = rows
+        .iter()
+        .filter(|row| row.week == 0)
+        .map(|row| row.name.as_str())
+        .collect();
+    let mut weeks: BTreeMap<i32, HashSet<&str>> = BTreeMap::new();
+    for row in rows.iter().filter(|row| row.week > 0) {
+        weeks.entry(row.week).or_default().insert(&row.name);
+    }
+    let missing_rows: Vec<MissingRows> = weeks
+        .into_iter()
+        .filter_map(|(week, present)| {
+            let students: Vec<String> = enrolled
+                .iter()
+                .filter(|name| !present.contains(*name))
+                .map(|name| name.to_string())
+                .collect();
+            (!students.is_empty()).then_some(MissingRows { week, students })
+        })
+        .collect();
+
+    let total_mismatches: Vec<TotalMismatch> = check_totals(rows)
+        .into_iter()
+        .filter_map(|violation| match violation {
+            Violation::TotalMismatch {
+                name,
+                week,
+                total,
+                expected,
+            } => Some(TotalMismatch {
+                name,
+                week,
+                total,
+                expected,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    let unknown_students: Vec<UnknownStudent> = unknown
+        .into_iter()
+        .map(|(name, weeks)| UnknownStudent {
+            name: name.to_string(),
+            weeks: weeks.into_iter().collect(),
+        })
+        .collect();
+    let duplicate_rows: Vec<DuplicateRow> = duplicates
+        .into_iter()
+        .map(|(name, week, count)| DuplicateRow { name, week, count })
+        .collect();
+
+    IntegrityReport {
+        checked_at: Utc::now(),
+        rows: rows.len(),
+        problems: unknown_students.len()
+            + duplicate_rows.len()
+            + missing_rows.iter().map(|m| m.students.len()).sum::<usize>()
+            + total_mismatches.len(),
+        unknown_students,
+        duplicate_rows,
+        missing_rows,
+        total_mismatches,
+    }
+}
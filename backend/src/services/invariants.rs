@@ -0,0 +1,127 @@
+use crate::services::grouping::{ABSENT_GROUP, SEATED_GROUP_SIZE, SEATED_STUDENTS, absent_ta};
+use crate::services::scoring::row_total;
+use crate::utils::types::{AppError, RowData};
+use log::error;
+use std::collections::HashMap;
+use std::env;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Violation {
+    #[error("{name} attended but is in {count} group(s)")]
+    NotGroupedOnce { name: String, count: usize },
+    #[error("{name} attended but was placed in {group}")]
+    PresentInAbsentGroup { name: String, group: String },
+    #[error("{group} has {size} student(s), expected {min}..={max}")]
+    GroupSize {
+        group: String,
+        size: usize,
+        min: usize,
+        max: usize,
+    },
+    #[error("{name} week {week} total is {total}, components sum to {expected}")]
+    TotalMismatch {
+        name: String,
+        week: i32,
+        total: u64,
+        expected: u64,
+    },
+}
+
+// Runtime checks are off unless CHECK_INVARIANTS is set, they are meant for
+// debugging grouping or grading issues
+pub fn enabled() -> bool {
+    env::var("CHECK_INVARIANTS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+// Group sizes allowed for a number of present students. Groups are filled
+// while seating (only the last one may fall short), after that the overflow
+// is spread round robin over the existing groups.
+pub fn group_size_bounds(present: usize, groups: usize) -> (usize, usize) {
+    if present <= SEATED_STUDENTS || groups == 0 {
+        return (SEATED_GROUP_SIZE, SEATED_GROUP_SIZE);
+    }
+    let overflow = (present - SEATED_STUDENTS).div_ceil(groups);
+    (SEATED_GROUP_SIZE, SEATED_GROUP_SIZE + overflow)
+}
+
+// Every student marked present in `previous` must appear exactly once in
+// `grouped`, in a regular group led by a rotation TA, and regular groups
+// must stay within size bounds
+pub fn check_grouping(previous: &[RowData], grouped: &[RowData]) -> Vec<Violation> {
+    let is_present = |row: &&RowData| row.attendance.as_deref() == Some("yes");
+    let absent_ta = absent_ta().name();
+    let mut violations = Vec::new();
+
+    for row in previous.iter().filter(is_present) {
+        let placed: Vec<&RowData> = grouped.iter().filter(|r| r.name == row.name).collect();
+        if placed.len() != 1 {
+            violations.push(Violation::NotGroupedOnce {
+                name: row.name.clone(),
+                count: placed.len(),
+            });
+        } else if placed[0].group_id == ABSENT_GROUP
+            || placed[0].ta.as_deref() == Some(absent_ta.as_str())
+        {
+            violations.push(Violation::PresentInAbsentGroup {
+                name: row.name.clone(),
+                group: placed[0].group_id.clone(),
+            });
+        }
+    }
+
+    let mut sizes: HashMap<&str, usize> = HashMap::new();
+    for row in grouped.iter().filter(is_present) {
+        *sizes.entry(row.group_id.as_str()).or_default() += 1;
+    }
+    let (min, max) = group_size_bounds(previous.iter().filter(is_present).count(), sizes.len());
+    let mut sizes: Vec<(&str, usize)> = sizes.into_iter().collect();
+    sizes.sort();
+    let mut short_groups = 0;
+    for (group, size) in sizes {
+        if size < min {
+            short_groups += 1;
+        }
+        if size > max || (size < min && short_groups > 1) {
+            violations.push(Violation::GroupSize {
+                group: group.to_string(),
+                size,
+                min,
+                max,
+            });
+        }
+    }
+
+    violations
+}
+
+// Each row's total must equal the sum of its components
+pub fn check_totals(rows: &[RowData]) -> Vec<Violation> {
+    rows.iter()
+        .filter_map(|row| {
+            let expected = row_total(row);
+            let total = row.total.unwrap_or(0);
+            (total != expected).then(|| Violation::TotalMismatch {
+                name: row.name.clone(),
+                week: row.week,
+                total,
+                expected,
+            })
+        })
+        .collect()
+}
+
+// Logs every violation and fails if there was any
+pub fn enforce(context: &str, violations: Vec<Violation>) -> Result<(), AppError> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    for violation in &violations {
+        error!("Invariant violated in {}: {}", context, violation);
+    }
+    Err(AppError::Invariant(format!(
+        "{} violation(s) in {}",
+        violations.len(),
+        context
+    )))
+}
@@ -0,0 +1,166 @@
+use crate::services::grouping::ABSENT_GROUP;
+use crate::utils::types::{ConstraintKind, GroupingConstraint, RowData};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub type SpokenLanguages = HashMap<String, BTreeSet<String>>;
+
+// Spoken-language makeup of one group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupLanguages {
+    pub group_id: String,
+    pub ta: Option<String>,
+    // Members speaking each language
+    pub languages: BTreeMap<String, usize>,
+    // Members with no languages recorded
+    pub unrecorded: usize,
+}
+
+// A student who shares no language with anyone else in their group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoneSpeaker {
+    pub name: String,
+    pub group_id: String,
+    pub languages: Vec<String>,
+}
+
+// A constraint that would seat a lone speaker with someone they can talk to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConstraintSuggestion {
+    pub kind: ConstraintKind,
+    pub first: String,
+    pub second: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageReport {
+    pub week: i32,
+    pub groups: Vec<GroupLanguages>,
+    pub warnings: Vec<LoneSpeaker>,
+    pub suggestions: Vec<ConstraintSuggestion>,
+}
+
+// Trimmed and lowercased, without blanks or duplicates
+pub fn normalize_languages(languages: &[String]) -> BTreeSet<String> {
+    languages
+        .iter()
+        .map(|language| language.trim().to_lowercase())
+        .filter(|language| !language.is_empty())
+        .collect()
+}
+
+fn is_seated(row: &RowData) -> bool {
+    row.attendance.as_deref() == Some("yes") && row.group_id != ABSENT_GROUP
+}
+
+// Composition of the week's groups, the students left without a shared
+// language, and for each of them a together constraint with a student of
+// another group who speaks one of their languages. Students without recorded
+// languages are never flagged, and pairs that already have a constraint are
+// not suggested again.
+pub fn language_report(
+    rows: &[RowData],
+    week: i32,
+    spoken: &SpokenLanguages,
+    constraints: &[GroupingConstraint],
+) -> LanguageReport {
+    let seated: Vec<&RowData> = rows
+        .iter()
+        .filter(|row| row.week == week && is_seated(row))
+        .collect();
+    let languages_of = |name: &str| spoken.get(name).filter(|l| !l.is_empty());
+
+    let mut groups: BTreeMap<&str, GroupLanguages> = BTreeMap::new();
+    for row in &seated {
+        let group = groups
+            .entry(row.group_id.as_str())
+            .or_insert_with(|| GroupLanguages {
+                group_id: row.group_id.clone(),
+                ta: row.ta.clone(),
+                languages: BTreeMap::new(),
+                unrecorded: 0,
+            });
+        match languages_of(&row.name) {
+            Some(languages) => {
+                for language in languages {
+                    *group.languages.entry(language.clone()).or_insert(0) += 1;
+                }
+            }
+            None => group.unrecorded += 1,
+        }
+    }
+
+    let shares_language = |a: &str, b: &str| match (languages_of(a), languages_of(b)) {
+        (Some(a), Some(b)) => !a.is_disjoint(b),
+        _ => false,
+    };
+    let constrained = |a: &str, b: &str| {
+        constraints
+            .iter()
+            .any(|c| (c.first == a && c.second == b) || (c.first == b && c.second == a))
+    };
+
+    let mut warnings = Vec::new();
+    let mut suggestions = Vec::new();
+    for row in &seated {
+        let Some(languages) = languages_of(&row.name) else {
+            continue;
+        };
+        let alone = seated
+            .iter()
+            .filter(|other| other.group_id == row.group_id && other.name != row.name)
+            .all(|other| !shares_language(&row.name, &other.name));
+        if !alone {
+            continue;
+        }
+        warnings.push(LoneSpeaker {
+            name: row.name.clone(),
+            group_id: row.group_id.clone(),
+            languages: languages.iter().cloned().collect(),
+        });
+
+        let partner = seated
+            .iter()
+            .filter(|other| other.group_id != row.group_id)
+            .filter(|other| shares_language(&row.name, &other.name))
+            .filter(|other| !constrained(&row.name, &other.name))
+            .min_by(|a, b| (&a.group_id, &a.name).cmp(&(&b.group_id, &b.name)));
+        if let Some(partner) = partner {
+            let shared: Vec<&str> = languages
+                .intersection(&spoken[&partner.name])
+                .map(String::as_str)
+                .collect();
+            let mut pair = [row.name.clone(), partner.name.clone()];
+            pair.sort();
+            let [first, second] = pair;
+            // Two lone speakers may each be the other's partner
+            if suggestions
+                .iter()
+                .any(|s: &ConstraintSuggestion| s.first == first && s.second == second)
+            {
+                continue;
+            }
+            suggestions.push(ConstraintSuggestion {
+                kind: ConstraintKind::Together,
+                first,
+                second,
+                reason: format!(
+                    "{} shares no language with the rest of {}; {} in {} speaks {}",
+                    row.name,
+                    row.group_id,
+                    partner.name,
+                    partner.group_id,
+                    shared.join(", ")
+                ),
+            });
+        }
+    }
+
+    LanguageReport {
+        week,
+        groups: groups.into_values().collect(),
+        warnings,
+        suggestions,
+    }
+}
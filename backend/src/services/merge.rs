@@ -0,0 +1,100 @@
+//! Merging a student entered twice under different names, e.g. "Jon" and
+//! "John", into the rows of the canonical name.
+
+use crate::services::scoring::row_total;
+use crate::services::weekly::carry_over_grades;
+use crate::utils::types::RowData;
+use serde::{Deserialize, Serialize};
+
+// How grades are combined in a week both names have a row for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    // Each score and yes/no answer at the best of both rows
+    #[default]
+    Max,
+    // The canonical row's grades are kept
+    Canonical,
+    // The duplicate row's grades replace the canonical row's
+    Duplicate,
+}
+
+fn either_yes(a: &Option<String>, b: &Option<String>) -> Option<String> {
+    match (a.as_deref(), b.as_deref()) {
+        (Some("yes"), _) | (_, Some("yes")) => Some("yes".to_string()),
+        _ => a.clone().or_else(|| b.clone()),
+    }
+}
+
+fn best_grades(row: &mut RowData, other: &RowData) {
+    for (score, theirs) in [
+        (&mut row.fa, other.fa),
+        (&mut row.fb, other.fb),
+        (&mut row.fc, other.fc),
+        (&mut row.fd, other.fd),
+        (&mut row.bonus_attempt, other.bonus_attempt),
+        (&mut row.bonus_answer_quality, other.bonus_answer_quality),
+        (&mut row.bonus_follow_up, other.bonus_follow_up),
+    ] {
+        *score = (*score).max(theirs);
+    }
+    row.attendance = either_yes(&row.attendance, &other.attendance);
+    row.exercise_submitted = either_yes(&row.exercise_submitted, &other.exercise_submitted);
+    row.exercise_test_passing =
+        either_yes(&row.exercise_test_passing, &other.exercise_test_passing);
+    row.exercise_good_documentation = either_yes(
+        &row.exercise_good_documentation,
+        &other.exercise_good_documentation,
+    );
+    row.exercise_good_structure =
+        either_yes(&row.exercise_good_structure, &other.exercise_good_structure);
+    row.notes = match (row.notes.take(), other.notes.clone()) {
+        (Some(ours), Some(theirs)) if ours != theirs => Some(format!("{}\n{}", ours, theirs)),
+        (ours, theirs) => ours.or(theirs),
+    };
+    row.commit_count = row.commit_count.max(other.commit_count);
+    row.last_commit_at = row.last_commit_at.clone().max(other.last_commit_at.clone());
+    row.repo_url = row.repo_url.take().or_else(|| other.repo_url.clone());
+    row.total = Some(row_total(row));
+}
+
+// The canonical rows to write for every week the duplicate has a row. A
+// week only the duplicate has moves over to the canonical student as is.
+pub fn merge_student_rows(
+    rows: &[RowData],
+    duplicate: &str,
+    canonical: &str,
+    strategy: MergeStrategy,
+) -> Vec<RowData> {
+    let identity = rows.iter().find(|row| row.name == canonical);
+    rows.iter()
+        .filter(|row| row.name == duplicate)
+        .map(|extra| {
+            let existing = rows
+                .iter()
+                .find(|row| row.name == canonical && row.week == extra.week);
+            match existing {
+                Some(existing) => {
+                    let mut merged = existing.clone();
+                    match strategy {
+                        MergeStrategy::Max => best_grades(&mut merged, extra),
+                        MergeStrategy::Canonical => {}
+                        MergeStrategy::Duplicate => carry_over_grades(&mut merged, extra),
+                    }
+                    merged
+                }
+                None => RowData {
+                    name: canonical.to_string(),
+                    mail: identity.map_or_else(|| extra.mail.clone(), |row| row.mail.clone()),
+                    participant_id: identity
+                        .and_then(|row| row.participant_id.clone())
+                        .or_else(|| extra.participant_id.clone()),
+                    public_id: None,
+                    student_id: None,
+                    version: None,
+                    ..extra.clone()
+                },
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,24 @@
+pub mod calibration;
+pub mod cohort_window;
+pub mod compensation;
+pub mod constraints;
+pub mod curriculum;
+pub mod exercises;
+pub mod export;
+pub mod fields;
+pub mod forecast;
+pub mod group_threads;
+pub mod grouping;
+pub mod integrity;
+pub mod invariants;
+pub mod languages;
+pub mod merge;
+pub mod paging;
+pub mod program_stats;
+pub mod read_model;
+pub mod scoring;
+pub mod search;
+pub mod submission_events;
+pub mod sync_slo;
+pub mod validation;
+pub mod weekly;
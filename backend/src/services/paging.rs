@@ -0,0 +1,188 @@
+//! Server-side filtering, sorting and paging of weekly rows for the admin
+//! table.
+
+use crate::services::grouping::group_id;
+use crate::utils::types::RowData;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
+// Columns the rows can be sorted by, named as in the JSON rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Name,
+    GroupId,
+    Ta,
+    Attendance,
+    Fa,
+    Fb,
+    Fc,
+    Fd,
+    BonusAttempt,
+    BonusAnswerQuality,
+    BonusFollowUp,
+    ExerciseSubmitted,
+    ExerciseTestPassing,
+    ExerciseGoodDocumentation,
+    ExerciseGoodStructure,
+    Total,
+}
+
+impl SortField {
+    fn compare(self, a: &RowData, b: &RowData) -> Ordering {
+        match self {
+            SortField::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortField::GroupId => a.group_id.cmp(&b.group_id),
+            SortField::Ta => a.ta.cmp(&b.ta),
+            SortField::Attendance => a.attendance.cmp(&b.attendance),
+            SortField::Fa => a.fa.cmp(&b.fa),
+            SortField::Fb => a.fb.cmp(&b.fb),
+            SortField::Fc => a.fc.cmp(&b.fc),
+            SortField::Fd => a.fd.cmp(&b.fd),
+            SortField::BonusAttempt => a.bonus_attempt.cmp(&b.bonus_attempt),
+            SortField::BonusAnswerQuality => a.bonus_answer_quality.cmp(&b.bonus_answer_quality),
+            SortField::BonusFollowUp => a.bonus_follow_up.cmp(&b.bonus_follow_up),
+            SortField::ExerciseSubmitted => a.exercise_submitted.cmp(&b.exercise_submitted),
+            SortField::ExerciseTestPassing => a.exercise_test_passing.cmp(&b.exercise_test_passing),
+            SortField::ExerciseGoodDocumentation => a
+                .exercise_good_documentation
+                .cmp(&b.exercise_good_documentation),
+            SortField::ExerciseGoodStructure => {
+                a.exercise_good_structure.cmp(&b.exercise_good_structure)
+            }
+            SortField::Total => a.total.cmp(&b.total),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// `?page`, `?per_page`, `?sort_by` and `?order`. Without `page` or
+// `per_page` every row is returned, as before paging existed.
+//
+// `?attendance`, `?ta`, `?group` and `?exercise_submitted` keep only the
+// matching rows, before paging. A row with no attendance or submission
+// recorded counts as "no".
+#[derive(Debug, Default, Deserialize)]
+pub struct RowsQuery {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    pub sort_by: Option<SortField>,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub attendance: Option<String>,
+    pub ta: Option<String>,
+    // "Group 3" or just 3
+    pub group: Option<String>,
+    pub exercise_submitted: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PageMeta {
+    // Matching rows across all pages
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub pages: usize,
+}
+
+fn yes_or_no(value: Option<&str>) -> &str {
+    match value {
+        Some(value) if value.eq_ignore_ascii_case("yes") => "yes",
+        _ => "no",
+    }
+}
+
+impl RowsQuery {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.page == Some(0) {
+            return Err("page starts at 1".to_string());
+        }
+        for (param, value) in [
+            ("attendance", &self.attendance),
+            ("exercise_submitted", &self.exercise_submitted),
+        ] {
+            if let Some(value) = value
+                && !["yes", "no"].contains(&value.to_ascii_lowercase().as_str())
+            {
+                return Err(format!("{} must be yes or no, not {:?}", param, value));
+            }
+        }
+        if self
+            .per_page
+            .is_some_and(|n| !(1..=MAX_PER_PAGE).contains(&n))
+        {
+            return Err(format!("per_page must be between 1 and {}", MAX_PER_PAGE));
+        }
+        Ok(())
+    }
+
+    fn matches(&self, row: &RowData) -> bool {
+        let answered = |filter: &Option<String>, value: Option<&str>| {
+            filter
+                .as_deref()
+                .is_none_or(|filter| yes_or_no(Some(filter)) == yes_or_no(value))
+        };
+        answered(&self.attendance, row.attendance.as_deref())
+            && answered(&self.exercise_submitted, row.exercise_submitted.as_deref())
+            && self.ta.as_deref().is_none_or(|ta| {
+                row.ta
+                    .as_deref()
+                    .is_some_and(|row_ta| row_ta.eq_ignore_ascii_case(ta.trim()))
+            })
+            && self
+                .group
+                .as_deref()
+                .is_none_or(|group| row.group_id.eq_ignore_ascii_case(&group_id(group)))
+    }
+
+    // The requested page of the matching rows, sorted first. Ties keep name
+    // order so pages do not shift between requests.
+    pub fn apply(&self, rows: &[RowData]) -> Result<(Vec<RowData>, PageMeta), String> {
+        self.validate()?;
+        let mut sorted: Vec<&RowData> = rows.iter().filter(|row| self.matches(row)).collect();
+        let total = sorted.len();
+        let page = self.page.unwrap_or(1);
+        let per_page = match self.per_page {
+            Some(n) => n,
+            None if self.page.is_some() => DEFAULT_PER_PAGE,
+            None => total.max(1),
+        };
+
+        if let Some(field) = self.sort_by {
+            sorted.sort_by(|a, b| {
+                let order = field.compare(a, b);
+                let order = match self.order {
+                    SortOrder::Asc => order,
+                    SortOrder::Desc => order.reverse(),
+                };
+                order.then_with(|| SortField::Name.compare(a, b))
+            });
+        }
+
+        let data = sorted
+            .into_iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .cloned()
+            .collect();
+        Ok((
+            data,
+            PageMeta {
+                total,
+                page,
+                per_page,
+                pages: total.div_ceil(per_page),
+            },
+        ))
+    }
+}
@@ -0,0 +1,114 @@
+//! Anonymized program numbers for the public website.
+//!
+//! Only counts and rates leave the server. Countries are listed once enough
+//! participants come from them that no one can be singled out; the rest are
+//! only counted.
+
+use crate::utils::types::RowData;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Share of the weeks held a student must attend to count as completing
+const COMPLETION_ATTENDANCE: f64 = 0.75;
+// Fewest participants from a country for it to be named
+const MIN_COUNTRY_PARTICIPANTS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CountryCount {
+    pub country: String,
+    pub participants: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgramStats {
+    pub participants: usize,
+    pub weeks_held: usize,
+    // Share of participants attending at least three quarters of the weeks
+    // held, None before the first week
+    pub completion_rate: Option<f64>,
+    pub exercises_submitted: usize,
+    pub countries_represented: usize,
+    pub countries: Vec<CountryCount>,
+    pub generated_at: DateTime<Utc>,
+}
+
+// The country of a free-text location, taken as its last comma separated
+// part, e.g. "Pune, Maharashtra, India"
+fn country(location: &str) -> Option<String> {
+    let country = location.rsplit(',').next()?.trim();
+    (!country.is_empty()).then(|| {
+        let mut chars = country.chars();
+        chars
+            .next()
+            .map(|first| {
+                first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+            })
+            .into_iter()
+            .flatten()
+            .collect()
+    })
+}
+
+pub fn program_stats(rows: &[RowData], locations: &[String]) -> ProgramStats {
+    let enrolled: BTreeSet<&str> = rows
+        .iter()
+        .filter(|row| row.week == 0)
+        .map(|row| row.name.as_str())
+        .collect();
+    let weeks_held = rows
+        .iter()
+        .filter(|row| row.week >= 1)
+        .map(|row| row.week)
+        .collect::<BTreeSet<_>>()
+        .len();
+
+    let mut attended: BTreeMap<&str, usize> = BTreeMap::new();
+    for row in rows
+        .iter()
+        .filter(|row| row.week >= 1 && row.attendance.as_deref() == Some("yes"))
+    {
+        *attended.entry(&row.name).or_insert(0) += 1;
+    }
+    let completed = enrolled
+        .iter()
+        .filter(|name| {
+            let weeks = attended.get(*name).copied().unwrap_or(0);
+            weeks as f64 >= weeks_held as f64 * COMPLETION_ATTENDANCE
+        })
+        .count();
+    let completion_rate =
+        (weeks_held > 0 && !enrolled.is_empty()).then(|| completed as f64 / enrolled.len() as f64);
+
+    let exercises_submitted = rows
+        .iter()
+        .filter(|row| row.week >= 1 && row.exercise_submitted.as_deref() == Some("yes"))
+        .count();
+
+    let mut by_country: BTreeMap<String, usize> = BTreeMap::new();
+    for country in locations.iter().filter_map(|location| country(location)) {
+        *by_country.entry(country).or_insert(0) += 1;
+    }
+    let mut countries: Vec<CountryCount> = by_country
+        .iter()
+        .filter(|(_, count)| **count >= MIN_COUNTRY_PARTICIPANTS)
+        .map(|(country, count)| CountryCount {
+            country: country.clone(),
+            participants: *count,
+        })
+        .collect();
+    countries.sort_by_key(|c| Reverse(c.participants));
+
+    ProgramStats {
+        participants: enrolled.len(),
+        weeks_held,
+        completion_rate,
+        exercises_submitted,
+        countries_represented: by_country.len(),
+        countries,
+        generated_at: Utc::now(),
+    }
+}
@@ -0,0 +1,94 @@
+//! Denormalized copy of the leaderboard and attendance stats.
+//!
+//! These endpoints are polled far more often than the table changes, so they
+//! are served from a snapshot rebuilt on a short interval instead of taking
+//! the state lock on every request.
+
+use crate::services::scoring::{StudentTotal, student_totals};
+use crate::utils::types::{RowData, Table};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+const DEFAULT_REFRESH_SECS: u64 = 15;
+
+#[derive(Debug, Serialize)]
+pub struct PublicStats {
+    pub leaderboard: Vec<StudentTotal>,
+    // Students enrolled, i.e. with a week 0 row
+    pub student_count: usize,
+    // Students marked present per week
+    pub attended_by_week: BTreeMap<i32, usize>,
+    pub refreshed_at: DateTime<Utc>,
+}
+
+impl PublicStats {
+    pub fn from_rows(rows: &[RowData]) -> Self {
+        let mut attended_by_week = BTreeMap::new();
+        for row in rows
+            .iter()
+            .filter(|r| r.attendance.as_deref() == Some("yes"))
+        {
+            *attended_by_week.entry(row.week).or_insert(0) += 1;
+        }
+        PublicStats {
+            leaderboard: student_totals(rows),
+            student_count: rows.iter().filter(|row| row.week == 0).count(),
+            attended_by_week,
+            refreshed_at: Utc::now(),
+        }
+    }
+}
+
+pub struct ReadModel {
+    current: RwLock<Arc<PublicStats>>,
+}
+
+impl ReadModel {
+    pub fn new(rows: &[RowData]) -> Self {
+        ReadModel {
+            current: RwLock::new(Arc::new(PublicStats::from_rows(rows))),
+        }
+    }
+
+    // The latest snapshot; the read lock is only held to clone the Arc
+    pub fn stats(&self) -> Arc<PublicStats> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn refresh(&self, rows: &[RowData]) {
+        let stats = Arc::new(PublicStats::from_rows(rows));
+        *self.current.write().unwrap() = stats;
+    }
+}
+
+pub fn refresh_interval_from_env() -> Result<Duration, String> {
+    match env::var("READ_MODEL_REFRESH_SECS") {
+        Ok(value) if !value.trim().is_empty() => match value.trim().parse() {
+            Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+            _ => Err("READ_MODEL_REFRESH_SECS must be a positive number".to_string()),
+        },
+        _ => Ok(Duration::from_secs(DEFAULT_REFRESH_SECS)),
+    }
+}
+
+// Rebuilds the snapshot from the table. The rows are copied under the state
+// lock and aggregated after it is released.
+pub fn start_read_model_thread(
+    state: Arc<Mutex<Table>>,
+    model: Arc<ReadModel>,
+    interval: Duration,
+) {
+    info!("Refreshing leaderboard and stats every {:?}", interval);
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            let rows = state.lock().unwrap().rows.clone();
+            model.refresh(&rows);
+        }
+    });
+}
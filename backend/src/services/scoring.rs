@@ -0,0 +1,227 @@
+use crate::utils::classroom::Assignment;
+use crate::utils::types::RowData;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Exercise columns derived from a classroom submission
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExerciseResult {
+    pub submitted: bool,
+    pub tests_passing: bool,
+    // Repo activity, left as it was when the forge did not report it
+    pub commit_count: Option<u64>,
+    pub last_commit_at: Option<String>,
+    pub repo_url: Option<String>,
+}
+
+// Points per component, matching the grading sheet in the frontend
+const GD_POINTS: [u64; 4] = [6, 6, 4, 4];
+const BONUS_POINTS: u64 = 10;
+const EXERCISE_POINTS: [u64; 4] = [10, 50, 20, 20];
+
+// Highest score a TA can give for a discussion or bonus criterion
+pub const MAX_CRITERION_SCORE: u64 = 5;
+
+// How a rubric criterion is scored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CriterionScale {
+    // 0 to `max`, each step worth the criterion's points
+    Score { max: u64 },
+    // "yes" earns the points, stored as score 1
+    YesNo,
+}
+
+impl CriterionScale {
+    pub fn max_score(&self) -> u64 {
+        match self {
+            CriterionScale::Score { max } => *max,
+            CriterionScale::YesNo => 1,
+        }
+    }
+}
+
+// A graded column of the students table
+#[derive(Debug, Clone, Serialize)]
+pub struct Criterion {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub section: &'static str,
+    pub scale: CriterionScale,
+    pub points: u64,
+}
+
+// The rubric in table order, with the weights used by `row_total`
+pub fn criteria() -> Vec<Criterion> {
+    let score = CriterionScale::Score {
+        max: MAX_CRITERION_SCORE,
+    };
+    let gd = [
+        ("fa", "Communication"),
+        ("fb", "Depth Of Answer"),
+        ("fc", "Technical Bitcoin Fluency"),
+        ("fd", "Engagement"),
+    ]
+    .into_iter()
+    .zip(GD_POINTS)
+    .map(|((key, label), points)| Criterion {
+        key,
+        label,
+        section: "group_discussion",
+        scale: score,
+        points,
+    });
+    let bonus = [
+        ("bonus_attempt", "Attempt"),
+        ("bonus_answer_quality", "Good"),
+        ("bonus_follow_up", "Follow Up"),
+    ]
+    .into_iter()
+    .map(|(key, label)| Criterion {
+        key,
+        label,
+        section: "bonus",
+        scale: score,
+        points: BONUS_POINTS,
+    });
+    let exercise = [
+        ("exercise_submitted", "Submitted"),
+        ("exercise_test_passing", "Github Test"),
+        ("exercise_good_documentation", "Good doc"),
+        ("exercise_good_structure", "Good Structure"),
+    ]
+    .into_iter()
+    .zip(EXERCISE_POINTS)
+    .map(|((key, label), points)| Criterion {
+        key,
+        label,
+        section: "exercise",
+        scale: CriterionScale::YesNo,
+        points,
+    });
+    gd.chain(bonus).chain(exercise).collect()
+}
+
+pub fn is_yes(value: &Option<String>) -> bool {
+    value.as_deref() == Some("yes")
+}
+
+fn yes_no(value: bool) -> Option<String> {
+    Some(if value { "yes" } else { "no" }.to_string())
+}
+
+pub fn gd_score(row: &RowData) -> u64 {
+    [row.fa, row.fb, row.fc, row.fd]
+        .iter()
+        .zip(GD_POINTS)
+        .map(|(score, points)| score.unwrap_or(0) * points)
+        .sum()
+}
+
+pub fn bonus_score(row: &RowData) -> u64 {
+    [
+        row.bonus_attempt,
+        row.bonus_answer_quality,
+        row.bonus_follow_up,
+    ]
+    .iter()
+    .map(|score| score.unwrap_or(0) * BONUS_POINTS)
+    .sum()
+}
+
+pub fn exercise_score(row: &RowData) -> u64 {
+    [
+        &row.exercise_submitted,
+        &row.exercise_test_passing,
+        &row.exercise_good_documentation,
+        &row.exercise_good_structure,
+    ]
+    .iter()
+    .zip(EXERCISE_POINTS)
+    .filter(|(value, _)| is_yes(value))
+    .map(|(_, points)| points)
+    .sum()
+}
+
+// The weekly total is the sum of its components
+pub fn row_total(row: &RowData) -> u64 {
+    gd_score(row) + bonus_score(row) + exercise_score(row)
+}
+
+// Result for a submission, if it belongs to `week`
+pub fn exercise_result(assignment: &Assignment, week: i32) -> Option<ExerciseResult> {
+    if assignment.get_week_pattern() != Some(week as u32) {
+        return None;
+    }
+    Some(ExerciseResult {
+        submitted: true,
+        tests_passing: assignment.tests_passing(),
+        commit_count: assignment.commit_count,
+        last_commit_at: assignment.last_commit_at.clone(),
+        repo_url: Some(assignment.student_repository_url.clone()).filter(|url| !url.is_empty()),
+    })
+}
+
+// Writes an exercise result into the row and recomputes its total
+pub fn apply_exercise_result(row: &mut RowData, result: &ExerciseResult) {
+    let submitted = yes_no(result.submitted);
+    let tests_passing = yes_no(result.tests_passing);
+    if result.commit_count.is_some() {
+        row.commit_count = result.commit_count;
+    }
+    if result.last_commit_at.is_some() {
+        row.last_commit_at = result.last_commit_at.clone();
+    }
+    if result.repo_url.is_some() {
+        row.repo_url = result.repo_url.clone();
+    }
+    if row.exercise_submitted == submitted && row.exercise_test_passing == tests_passing {
+        return;
+    }
+    row.exercise_submitted = submitted;
+    row.exercise_test_passing = tests_passing;
+    row.total = Some(row_total(row));
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StudentTotal {
+    pub name: String,
+    pub email: String,
+    pub total_score: u64,
+    pub exercise_total_score: u8,
+}
+
+// Sums weekly totals and passing exercises per student, highest total first.
+// The email is taken from the student's latest week.
+pub fn student_totals(rows: &[RowData]) -> Vec<StudentTotal> {
+    let mut students: HashMap<&str, (&RowData, StudentTotal)> = HashMap::new();
+
+    for row in rows {
+        let passing = u8::from(is_yes(&row.exercise_test_passing));
+        let (latest, total) = students.entry(&row.name).or_insert_with(|| {
+            (
+                row,
+                StudentTotal {
+                    name: row.name.clone(),
+                    email: row.mail.clone(),
+                    total_score: 0,
+                    exercise_total_score: 0,
+                },
+            )
+        });
+        total.total_score += row.total.unwrap_or(0);
+        total.exercise_total_score += passing;
+        if row.week > latest.week {
+            *latest = row;
+            total.email = row.mail.clone();
+        }
+    }
+
+    let mut totals: Vec<StudentTotal> = students.into_values().map(|(_, t)| t).collect();
+    totals.sort_by(|a, b| {
+        b.total_score
+            .cmp(&a.total_score)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    totals
+}
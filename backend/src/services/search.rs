@@ -0,0 +1,55 @@
+//! Student search for the frontend's search box.
+
+use crate::utils::types::{ParticipantMatch, RowData};
+use serde::Serialize;
+
+// A student's most recent row, as shown next to a search result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WeekSummary {
+    pub week: i32,
+    pub group_id: String,
+    pub ta: Option<String>,
+    pub attendance: Option<String>,
+    pub exercise_submitted: Option<String>,
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StudentMatch {
+    #[serde(flatten)]
+    pub participant: ParticipantMatch,
+    // None for participants without rows yet
+    pub latest_week: Option<WeekSummary>,
+}
+
+fn belongs_to(row: &RowData, participant: &ParticipantMatch) -> bool {
+    match &row.participant_id {
+        Some(id) => *id == participant.id,
+        None => row.name.eq_ignore_ascii_case(&participant.name),
+    }
+}
+
+// Each participant with the summary of their latest week in `rows`
+pub fn with_latest_weeks(matches: Vec<ParticipantMatch>, rows: &[RowData]) -> Vec<StudentMatch> {
+    matches
+        .into_iter()
+        .map(|participant| {
+            let latest_week = rows
+                .iter()
+                .filter(|row| belongs_to(row, &participant))
+                .max_by_key(|row| row.week)
+                .map(|row| WeekSummary {
+                    week: row.week,
+                    group_id: row.group_id.clone(),
+                    ta: row.ta.clone(),
+                    attendance: row.attendance.clone(),
+                    exercise_submitted: row.exercise_submitted.clone(),
+                    total: row.total,
+                });
+            StudentMatch {
+                participant,
+                latest_week,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,141 @@
+//! Exercise submissions pushed by GitHub webhooks instead of polled.
+//!
+//! Classroom repos are named `<assignment>-<student login>`, e.g.
+//! `week-2-exercise-alice`. A push by the student marks the week's exercise
+//! submitted; a finished run of the autograder workflow also tells whether
+//! the tests pass. Pushes by anyone else, such as a TA fixing the starter code,
+//! are not submissions.
+
+use crate::utils::classroom::week_in_name;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+
+// A submission read from a webhook delivery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmissionEvent {
+    pub repo: String,
+    pub github: String,
+    pub week: i32,
+    // Known once the autograder finished
+    pub tests_passing: Option<bool>,
+    // When the pushed head commit was made
+    pub committed_at: Option<String>,
+    pub repo_url: Option<String>,
+    // Commit the autograder ran on
+    pub head_sha: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    name: String,
+    html_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeadCommit {
+    timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PushPayload {
+    repository: Repository,
+    sender: Account,
+    // Pushes deleting a branch carry no work
+    #[serde(default)]
+    deleted: bool,
+    head_commit: Option<HeadCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkflowRun {
+    name: String,
+    conclusion: Option<String>,
+    actor: Account,
+    #[serde(default)]
+    head_sha: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkflowRunPayload {
+    action: String,
+    repository: Repository,
+    workflow_run: WorkflowRun,
+}
+
+// Pushes carry the committer's offset; rows keep UTC like the Classroom API
+fn utc_timestamp(timestamp: &str) -> Option<String> {
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|at| {
+        at.with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+    })
+}
+
+fn submission(
+    repository: Repository,
+    login: String,
+    tests_passing: Option<bool>,
+    committed_at: Option<String>,
+) -> Option<SubmissionEvent> {
+    let repo = repository.name;
+    let suffix = format!("-{}", login.to_ascii_lowercase());
+    if !repo.to_ascii_lowercase().ends_with(&suffix) {
+        return None;
+    }
+    Some(SubmissionEvent {
+        week: i32::try_from(week_in_name(&repo[..repo.len() - suffix.len()])?).ok()?,
+        repo,
+        github: login,
+        tests_passing,
+        committed_at,
+        repo_url: repository.html_url,
+        head_sha: None,
+    })
+}
+
+// The submission a delivery of the `X-GitHub-Event` type reports, if any.
+// Runs of workflows other than `autograder` report none. Fails only on a
+// payload that does not parse.
+pub fn parse_submission_event(
+    event: &str,
+    body: &[u8],
+    autograder: &str,
+) -> Result<Option<SubmissionEvent>, serde_json::Error> {
+    match event {
+        "push" => {
+            let push: PushPayload = serde_json::from_slice(body)?;
+            if push.deleted {
+                return Ok(None);
+            }
+            Ok(submission(
+                push.repository,
+                push.sender.login,
+                None,
+                push.head_commit
+                    .and_then(|commit| utc_timestamp(&commit.timestamp)),
+            ))
+        }
+        "workflow_run" => {
+            let run: WorkflowRunPayload = serde_json::from_slice(body)?;
+            if run.action != "completed" || run.workflow_run.name != autograder {
+                return Ok(None);
+            }
+            let passing = run.workflow_run.conclusion.as_deref() == Some("success");
+            Ok(submission(
+                run.repository,
+                run.workflow_run.actor.login,
+                Some(passing),
+                None,
+            )
+            .map(|event| SubmissionEvent {
+                head_sha: run.workflow_run.head_sha,
+                ..event
+            }))
+        }
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,172 @@
+//! Service level of the exercise sync pipeline.
+//!
+//! Every fetch of a week's submissions is recorded as a sync run. Over a
+//! rolling window this reports the share of runs that reached the forge
+//! against a target, and how long ago each week still being synced last got
+//! fresh data. Weeks no longer synced inside the window are left out, or the
+//! finished weeks of a cohort would drag the mean staleness up forever.
+
+use crate::utils::types::SyncRun;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncSlo {
+    pub window_hours: i64,
+    // Share of runs expected to succeed
+    pub target: f64,
+    // How stale exercise data may get before alerting
+    pub freshness_budget_mins: i64,
+}
+
+impl Default for SyncSlo {
+    fn default() -> Self {
+        SyncSlo {
+            window_hours: 168,
+            target: 0.95,
+            freshness_budget_mins: 1440,
+        }
+    }
+}
+
+impl SyncSlo {
+    pub fn from_env() -> Result<Self, String> {
+        fn positive(var: &str, default: i64) -> Result<i64, String> {
+            match env::var(var) {
+                Ok(value) if !value.trim().is_empty() => match value.trim().parse::<i64>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(format!("{} must be a positive number", var)),
+                },
+                _ => Ok(default),
+            }
+        }
+
+        let defaults = SyncSlo::default();
+        let target = match env::var("SYNC_SLO_TARGET") {
+            Ok(value) if !value.trim().is_empty() => match value.trim().parse::<f64>() {
+                Ok(n) if n > 0.0 && n < 1.0 => n,
+                _ => return Err("SYNC_SLO_TARGET must be between 0 and 1".to_string()),
+            },
+            _ => defaults.target,
+        };
+        Ok(SyncSlo {
+            window_hours: positive("SYNC_SLO_WINDOW_HOURS", defaults.window_hours)?,
+            target,
+            freshness_budget_mins: positive(
+                "SYNC_FRESHNESS_BUDGET_MINS",
+                defaults.freshness_budget_mins,
+            )?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WeekFreshness {
+    pub week: i32,
+    pub last_success: Option<String>,
+    // Minutes since the last successful run, if there was one
+    pub staleness_mins: Option<i64>,
+    // Failed runs since the last successful one
+    pub failures_since_success: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SloReport {
+    pub slo: SyncSlo,
+    pub runs: usize,
+    pub failures: usize,
+    // None until a run is recorded in the window
+    pub success_rate: Option<f64>,
+    // Share of the allowed failures still unspent; negative once overspent
+    pub error_budget_remaining: Option<f64>,
+    pub weeks: Vec<WeekFreshness>,
+    pub mean_staleness_mins: Option<f64>,
+    pub alerts: Vec<String>,
+    pub last_error: Option<String>,
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+// Runs must be oldest first, as storage returns them
+pub fn slo_report(runs: &[SyncRun], slo: &SyncSlo, now: DateTime<Utc>) -> SloReport {
+    let window_start = now - Duration::hours(slo.window_hours);
+    let in_window: Vec<&SyncRun> = runs
+        .iter()
+        .filter(|run| parse_time(&run.finished_at).is_some_and(|at| at >= window_start))
+        .collect();
+    let failures = in_window.iter().filter(|run| !run.succeeded).count();
+    let success_rate = (!in_window.is_empty())
+        .then(|| (in_window.len() - failures) as f64 / in_window.len() as f64);
+    let error_budget_remaining = success_rate.map(|rate| 1.0 - (1.0 - rate) / (1.0 - slo.target));
+
+    let mut weeks: BTreeMap<i32, WeekFreshness> = BTreeMap::new();
+    for run in &in_window {
+        weeks.entry(run.week).or_insert_with(|| WeekFreshness {
+            week: run.week,
+            last_success: None,
+            staleness_mins: None,
+            failures_since_success: 0,
+        });
+    }
+    // The last success may predate the window, so scan every run
+    for run in runs {
+        let Some(week) = weeks.get_mut(&run.week) else {
+            continue;
+        };
+        if run.succeeded {
+            week.last_success = Some(run.finished_at.clone());
+            week.staleness_mins =
+                parse_time(&run.finished_at).map(|at| (now - at).num_minutes().max(0));
+            week.failures_since_success = 0;
+        } else {
+            week.failures_since_success += 1;
+        }
+    }
+
+    let staleness: Vec<i64> = weeks.values().filter_map(|w| w.staleness_mins).collect();
+    let mean_staleness_mins = (!staleness.is_empty())
+        .then(|| staleness.iter().sum::<i64>() as f64 / staleness.len() as f64);
+
+    let mut alerts = Vec::new();
+    if let Some(mean) = mean_staleness_mins
+        && mean > slo.freshness_budget_mins as f64
+    {
+        alerts.push(format!(
+            "Exercise data is {:.0} minutes stale on average, over the {} minute budget",
+            mean, slo.freshness_budget_mins
+        ));
+    }
+    for week in weeks.values().filter(|w| w.last_success.is_none()) {
+        alerts.push(format!(
+            "Week {} has not synced successfully ({} failed runs)",
+            week.week, week.failures_since_success
+        ));
+    }
+    if let Some(rate) = success_rate
+        && rate < slo.target
+    {
+        alerts.push(format!(
+            "Sync success rate {:.1}% is below the {:.1}% target",
+            rate * 100.0,
+            slo.target * 100.0
+        ));
+    }
+
+    SloReport {
+        slo: slo.clone(),
+        runs: in_window.len(),
+        failures,
+        success_rate,
+        error_budget_remaining,
+        weeks: weeks.into_values().collect(),
+        mean_staleness_mins,
+        alerts,
+        last_error: in_window.iter().rev().find_map(|run| run.error.clone()),
+    }
+}
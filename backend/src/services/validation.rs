@@ -0,0 +1,154 @@
+//! Checks of submitted weekly rows before they are applied.
+//!
+//! Unlike calibration flags these block the write: a row scored above the
+//! rubric, led by a TA nobody knows or filed under another week than the one
+//! posted to would otherwise be stored as is. Every problem is reported with
+//! the row and field it was found in, so the frontend can mark the cells.
+
+use crate::handlers::auth::TA;
+use crate::services::scoring::{CriterionScale, criteria};
+use crate::utils::types::RowData;
+use actix_web::error::JsonPayloadError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Values of attendance and the exercise columns
+pub const YES_NO: [&str; 2] = ["yes", "no"];
+// Longest note on a row, in characters
+pub const MAX_NOTES_LENGTH: usize = 1000;
+
+// One problem with one field of a submitted row
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    // Position of the row in the submitted batch
+    pub row: usize,
+    pub name: String,
+    pub field: String,
+    pub message: String,
+}
+
+// A batch turned away because some of its rows are invalid
+#[derive(thiserror::Error, Debug)]
+#[error("{} invalid field(s) in the submitted rows", .0.len())]
+pub struct InvalidRows(pub Vec<FieldError>);
+
+impl ResponseError for InvalidRows {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "status": "error",
+            "message": self.to_string(),
+            "errors": self.0
+        }))
+    }
+}
+
+// The problems of each invalid row, by its position in the batch, for
+// writes that skip invalid rows instead of turning the batch away
+pub fn row_failures(errors: &[FieldError]) -> BTreeMap<usize, String> {
+    let mut failures: BTreeMap<usize, String> = BTreeMap::new();
+    for error in errors {
+        let problem = format!("{} {}", error.field, error.message);
+        failures
+            .entry(error.row)
+            .and_modify(|reason| {
+                reason.push_str("; ");
+                reason.push_str(&problem);
+            })
+            .or_insert(problem);
+    }
+    failures
+}
+
+// Every problem with the rows posted for `week`
+pub fn validate_rows(rows: &[RowData], week: i32) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        let mut invalid = |field: &str, message: String| {
+            errors.push(FieldError {
+                row: index,
+                name: row.name.clone(),
+                field: field.to_string(),
+                message,
+            })
+        };
+
+        if row.name.trim().is_empty() {
+            invalid("name", "must not be empty".to_string());
+        }
+        if row.week != week {
+            invalid(
+                "week",
+                format!("is {} but the rows were posted for week {}", row.week, week),
+            );
+        }
+        if let Some(ta) = row.ta.as_deref().filter(|ta| !ta.is_empty())
+            && TA::from_name(ta).is_none()
+        {
+            invalid("ta", format!("{:?} is not a known TA", ta));
+        }
+        if let Some(attendance) = row.attendance.as_deref()
+            && !YES_NO.contains(&attendance)
+        {
+            invalid(
+                "attendance",
+                format!("must be \"yes\" or \"no\", not {:?}", attendance),
+            );
+        }
+        if let Some(notes) = row.notes.as_deref()
+            && notes.chars().count() > MAX_NOTES_LENGTH
+        {
+            invalid(
+                "notes",
+                format!("must be at most {} characters", MAX_NOTES_LENGTH),
+            );
+        }
+
+        let values = serde_json::to_value(row).unwrap_or_default();
+        for criterion in criteria() {
+            let value = values.get(criterion.key).unwrap_or(&Value::Null);
+            match (criterion.scale, value) {
+                (_, Value::Null) => {}
+                (CriterionScale::Score { max }, Value::Number(score))
+                    if score.as_u64().is_some_and(|score| score > max) =>
+                {
+                    invalid(
+                        criterion.key,
+                        format!("is {} but must be between 0 and {}", score, max),
+                    );
+                }
+                (CriterionScale::YesNo, Value::String(answer))
+                    if !YES_NO.contains(&answer.as_str()) =>
+                {
+                    invalid(
+                        criterion.key,
+                        format!("must be \"yes\" or \"no\", not {:?}", answer),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+    errors
+}
+
+// A JSON body that does not fit the expected shape, such as a negative
+// score, as a 422 like the other validation errors rather than a bare 400
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Deserialize(e) => {
+            let response = HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "status": "error",
+                "message": format!("Invalid request body: {}", e)
+            }));
+            actix_web::error::InternalError::from_response(e, response).into()
+        }
+        other => other.into(),
+    }
+}
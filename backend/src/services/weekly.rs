@@ -0,0 +1,140 @@
+use crate::handlers::auth::TA;
+use crate::services::constraints::{ConstraintViolation, apply_constraints};
+use crate::services::grouping::assign_groups;
+use crate::services::scoring::{apply_exercise_result, exercise_result};
+use crate::utils::classroom::Assignment;
+use crate::utils::types::{GroupingConstraint, RowData};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+
+pub fn rows_for_week(rows: &[RowData], week: i32) -> Vec<RowData> {
+    rows.iter()
+        .filter(|row| row.week == week)
+        .cloned()
+        .collect()
+}
+
+// The rows of each week in the range, weeks without rows included
+pub fn rows_by_week(rows: &[RowData], weeks: RangeInclusive<i32>) -> BTreeMap<i32, Vec<RowData>> {
+    let mut by_week: BTreeMap<i32, Vec<RowData>> =
+        weeks.clone().map(|week| (week, Vec::new())).collect();
+    for row in rows.iter().filter(|row| weeks.contains(&row.week)) {
+        by_week.entry(row.week).or_default().push(row.clone());
+    }
+    by_week
+}
+
+// Copies grading already entered for the week into a freshly grouped row
+pub fn carry_over_grades(row: &mut RowData, existing: &RowData) {
+    row.attendance = existing.attendance.clone();
+    row.fa = existing.fa;
+    row.fb = existing.fb;
+    row.fc = existing.fc;
+    row.fd = existing.fd;
+    row.bonus_attempt = existing.bonus_attempt;
+    row.bonus_answer_quality = existing.bonus_answer_quality;
+    row.bonus_follow_up = existing.bonus_follow_up;
+    row.exercise_submitted = existing.exercise_submitted.clone();
+    row.exercise_test_passing = existing.exercise_test_passing.clone();
+    row.exercise_good_documentation = existing.exercise_good_documentation.clone();
+    row.exercise_good_structure = existing.exercise_good_structure.clone();
+    row.total = existing.total;
+    row.notes = existing.notes.clone();
+    row.commit_count = existing.commit_count;
+    row.last_commit_at = existing.last_commit_at.clone();
+    row.repo_url = existing.repo_url.clone();
+}
+
+// Ungraded defaults for a student's first row in a week
+pub fn reset_grades(row: &mut RowData) {
+    let no = || Some("no".to_string());
+    row.attendance = no();
+    row.fa = Some(0);
+    row.fb = Some(0);
+    row.fc = Some(0);
+    row.fd = Some(0);
+    row.bonus_attempt = Some(0);
+    row.bonus_answer_quality = Some(0);
+    row.bonus_follow_up = Some(0);
+    row.exercise_submitted = no();
+    row.exercise_test_passing = no();
+    row.exercise_good_documentation = no();
+    row.exercise_good_structure = no();
+    row.total = Some(0);
+    row.notes = None;
+    row.commit_count = None;
+    row.last_commit_at = None;
+    row.repo_url = None;
+}
+
+// Rows for `week` seeded from another week's without syncing: the same
+// students in the same groups and with the same TAs, ungraded. Each copy
+// gets its own row id and version when inserted.
+pub fn copy_week_rows(from: &[RowData], week: i32) -> Vec<RowData> {
+    from.iter()
+        .map(|row| {
+            let mut copy = RowData {
+                week,
+                public_id: None,
+                version: None,
+                ..row.clone()
+            };
+            reset_grades(&mut copy);
+            copy
+        })
+        .collect()
+}
+
+// Rows of `week` whose exercise columns the submissions, keyed by
+// participant name, change. Rows without a submission are left alone.
+pub fn apply_submissions(
+    rows: &[RowData],
+    week: i32,
+    submissions: &HashMap<String, Assignment>,
+) -> Vec<RowData> {
+    rows.iter()
+        .filter(|row| row.week == week)
+        .filter_map(|row| {
+            let result = exercise_result(submissions.get(&row.name)?, week)?;
+            let mut updated = row.clone();
+            apply_exercise_result(&mut updated, &result);
+            (updated != *row).then_some(updated)
+        })
+        .collect()
+}
+
+// Builds the rows for `week` from the previous week: students are regrouped
+// within the grouping constraints, grades already entered for the week are
+// kept and matched classroom submissions (keyed by participant name) update
+// the exercise columns. Constraints that could not be honored are returned.
+pub fn build_week_rows(
+    prev_week: Vec<RowData>,
+    current_week: &[RowData],
+    week: i32,
+    tas: &[TA],
+    constraints: &[GroupingConstraint],
+    submissions: &HashMap<String, &Assignment>,
+) -> (Vec<RowData>, Vec<ConstraintViolation>) {
+    let existing: HashMap<&str, &RowData> = current_week
+        .iter()
+        .map(|row| (row.name.as_str(), row))
+        .collect();
+
+    let mut rows = assign_groups(prev_week, week, tas);
+    let violations = apply_constraints(&mut rows, constraints);
+    for row in &mut rows {
+        match existing.get(row.name.as_str()) {
+            Some(existing) => carry_over_grades(row, existing),
+            None => reset_grades(row),
+        }
+
+        let result = submissions
+            .get(&row.name)
+            .and_then(|assignment| exercise_result(assignment, week));
+        if let Some(result) = result {
+            apply_exercise_result(row, &result);
+        }
+    }
+
+    (rows, violations)
+}
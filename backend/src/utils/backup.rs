@@ -1,65 +1,367 @@
-use chrono::{Datelike, Local};
-use log::error;
+//! Snapshots of the live SQLite database.
+//!
+//! Backups are written on a schedule and before destructive operations, as
+//! `<db stem>_<UTC timestamp>_<reason>.db` in the backup directory. Snapshots
+//! older than the retention period are pruned, but the newest few are always
+//! kept so a quiet cohort is never left without a backup.
+
+use crate::database::bootstrap::table_exists;
+use crate::database::paths::data_paths;
+use crate::database::pool::{open_connection, open_read_only};
+use crate::database::schema::run_migrations;
+use crate::utils::types::AppError;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use log::{error, info};
+use rusqlite::backup::Progress;
+use rusqlite::{DatabaseName, params};
+use serde::Serialize;
+use std::env;
 use std::fs;
-use std::path::Path;
-use thiserror::Error;
+use std::path::{Path, PathBuf};
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// Security state and the TA roster taken from the live database on restore,
+// so restoring an older backup cannot bring back revoked tokens, undo TOTP
+// enrollment or an offboarding, or drop TAs who joined since
+const KEPT_ON_RESTORE: &[&str] = &[
+    "revoked_tokens",
+    "admin_totp",
+    "onboarded_tas",
+    "inactive_tas",
+    "ta_invites",
+];
+
+// Secrets left out of exported copies
+const CLEARED_ON_EXPORT: &[&str] = &["admin_totp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupReason {
+    Scheduled,
+    BeforeWeekDeletion,
+    BeforeStudentRemoval,
+    BeforeRestore,
+}
+
+impl BackupReason {
+    pub const ALL: [BackupReason; 4] = [
+        BackupReason::Scheduled,
+        BackupReason::BeforeWeekDeletion,
+        BackupReason::BeforeStudentRemoval,
+        BackupReason::BeforeRestore,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackupReason::Scheduled => "scheduled",
+            BackupReason::BeforeWeekDeletion => "before_week_deletion",
+            BackupReason::BeforeStudentRemoval => "before_student_removal",
+            BackupReason::BeforeRestore => "before_restore",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        BackupReason::ALL.into_iter().find(|r| r.as_str() == value)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub file: String,
+    pub reason: BackupReason,
+    pub created_at: DateTime<Utc>,
+    pub size_bytes: u64,
+}
 
-#[derive(Debug, Error)]
-pub enum DbError {
-    #[error("Database error: {0}")]
-    DatabaseError(String),
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupPolicy {
+    pub dir: PathBuf,
+    pub interval_hours: u32,
+    pub retention_days: u32,
+    // Newest backups kept regardless of age
+    pub keep_min: usize,
 }
 
-fn cleanup_old_backups(db_name: &str, days_ago: i64) {
-    let target_month = (Local::now() - chrono::Duration::days(days_ago))
-        .format("%Y-%m")
-        .to_string();
-    glob::glob(&format!("backup/{}*{}*.db", db_name, target_month))
-        .unwrap()
-        .for_each(|file| {
-            fs::remove_file(file.unwrap()).ok();
-        });
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            dir: data_paths().backup_dir(),
+            interval_hours: 24,
+            retention_days: 35,
+            keep_min: 5,
+        }
+    }
 }
 
-fn backup(db_name: &str) -> Result<(), DbError> {
-    let db_path = Path::new("./").join(db_name);
-
-    if db_path.exists() {
-        let backup_dir = Path::new("./backup");
-        fs::create_dir_all(Path::new("./backup")).unwrap();
-
-        let now = Local::now();
-        let day_of_week = now.format("%A");
-        let date_time = now.format("%Y-%m-%d");
-        let backup_file = backup_dir.join(format!("{}_{}_{}.db", db_name, day_of_week, date_time));
-        fs::copy(&db_path, &backup_file).unwrap();
-
-        cleanup_old_backups(db_name, 35);
-    } else {
-        return Err(DbError::DatabaseError(format!(
-            "Database file '{}' not found in project root.",
-            db_name
-        )));
+impl BackupPolicy {
+    pub fn from_env() -> Result<Self, String> {
+        fn number<T: std::str::FromStr>(var: &str, default: T) -> Result<T, String> {
+            match env::var(var) {
+                Ok(value) if !value.trim().is_empty() => value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{} must be a non-negative number", var)),
+                _ => Ok(default),
+            }
+        }
+
+        let defaults = BackupPolicy::default();
+        let dir = match env::var("BACKUP_DIR") {
+            Ok(dir) if !dir.trim().is_empty() => PathBuf::from(dir.trim()),
+            _ => defaults.dir,
+        };
+        let interval_hours = number("BACKUP_INTERVAL_HOURS", defaults.interval_hours)?;
+        if interval_hours == 0 {
+            return Err("BACKUP_INTERVAL_HOURS must be at least 1".to_string());
+        }
+        Ok(BackupPolicy {
+            dir,
+            interval_hours,
+            retention_days: number("BACKUP_RETENTION_DAYS", defaults.retention_days)?,
+            keep_min: number("BACKUP_KEEP_MIN", defaults.keep_min)?,
+        })
     }
+}
 
-    Ok(())
+// Backups of one database file. Without a file (the live cohort is in
+// Postgres) snapshots are skipped and the list stays empty.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    db: Option<PathBuf>,
+    policy: BackupPolicy,
 }
 
-pub fn start_backup_thread() {
-    std::thread::spawn(|| {
-        loop {
-            let now = chrono::Local::now();
-            let weekday = now.date_naive().weekday();
-            if weekday == chrono::Weekday::Mon || weekday == chrono::Weekday::Sat {
-                let db_name = "classroom.db";
-                let result: std::result::Result<(), DbError> = backup(&db_name);
-                if let Err(e) = result {
-                    error!("Failed to backup database: {}", e);
+impl Backups {
+    pub fn new(db: Option<PathBuf>, policy: BackupPolicy) -> Self {
+        Backups { db, policy }
+    }
+
+    pub fn policy(&self) -> &BackupPolicy {
+        &self.policy
+    }
+
+    fn prefix(db: &Path) -> String {
+        let stem = db
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("database");
+        format!("{}_", stem)
+    }
+
+    pub fn path(&self, backup: &BackupInfo) -> PathBuf {
+        self.policy.dir.join(&backup.file)
+    }
+
+    // Writes a consistent snapshot of the database. A file copy would miss
+    // commits still in the WAL, so SQLite writes the copy itself.
+    pub fn snapshot(&self, reason: BackupReason) -> Result<Option<BackupInfo>, AppError> {
+        let Some(db) = &self.db else {
+            return Ok(None);
+        };
+        if !db.exists() {
+            return Err(AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Database file '{}' not found", db.display()),
+            )));
+        }
+        fs::create_dir_all(&self.policy.dir)?;
+
+        let created_at = Utc::now();
+        let file = format!(
+            "{}{}_{}.db",
+            Self::prefix(db),
+            created_at.format(TIMESTAMP_FORMAT),
+            reason.as_str()
+        );
+        let path = self.policy.dir.join(&file);
+        let _ = fs::remove_file(&path);
+        let conn = open_connection(db)?;
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+
+        Ok(Some(BackupInfo {
+            file,
+            reason,
+            created_at,
+            size_bytes: fs::metadata(&path)?.len(),
+        }))
+    }
+
+    // A consistent copy of the live database for download, taken with
+    // SQLite's online backup API so concurrent writes cannot tear it. The
+    // copy is staged in the temp directory and removed once read.
+    pub fn export(&self) -> Result<Vec<u8>, AppError> {
+        let Some(db) = &self.db else {
+            return Err(AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Exporting is only supported for SQLite",
+            )));
+        };
+        let path = env::temp_dir().join(format!(
+            "{}export_{}_{}.db",
+            Self::prefix(db),
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        let copy = || -> Result<Vec<u8>, AppError> {
+            open_read_only(db)?.backup(DatabaseName::Main, &path, None)?;
+            let conn = open_connection(&path)?;
+            for table in CLEARED_ON_EXPORT {
+                if table_exists(&conn, table)? {
+                    conn.execute(&format!("DELETE FROM {}", table), [])?;
                 }
-                std::thread::sleep(std::time::Duration::from_secs(60 * 60 * 24));
-            } else {
-                std::thread::sleep(std::time::Duration::from_secs(60 * 60));
             }
+            // Back to a single file, without a WAL to ship alongside
+            conn.pragma_update(None, "journal_mode", "DELETE")?;
+            drop(conn);
+            Ok(fs::read(&path)?)
+        };
+        let bytes = copy();
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        bytes
+    }
+
+    // Backups in the backup directory, newest first. Files not written by
+    // this module are ignored.
+    pub fn list(&self) -> Result<Vec<BackupInfo>, AppError> {
+        let Some(db) = &self.db else {
+            return Ok(Vec::new());
+        };
+        if !self.policy.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let prefix = Self::prefix(db);
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(&self.policy.dir)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let Some((timestamp, reason)) = name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".db"))
+                .and_then(|rest| rest.split_once('_'))
+            else {
+                continue;
+            };
+            let (Ok(created_at), Some(reason)) = (
+                NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT),
+                BackupReason::parse(reason),
+            ) else {
+                continue;
+            };
+            backups.push(BackupInfo {
+                reason,
+                created_at: created_at.and_utc(),
+                size_bytes: entry.metadata()?.len(),
+                file: name,
+            });
+        }
+        backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+        Ok(backups)
+    }
+
+    // Overwrites the live database with a backup through SQLite's backup
+    // API, which holds the database lock while copying, so pooled
+    // connections see either the old or the restored database. The live
+    // database is snapshotted first so a restore can itself be undone; that
+    // snapshot is returned.
+    pub fn restore(&self, backup: &BackupInfo) -> Result<BackupInfo, AppError> {
+        let (Some(db), Some(previous)) = (&self.db, self.snapshot(BackupReason::BeforeRestore)?)
+        else {
+            return Err(AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Restoring is only supported for SQLite",
+            )));
+        };
+
+        let mut conn = open_connection(db)?;
+        conn.restore(
+            DatabaseName::Main,
+            self.policy.dir.join(&backup.file),
+            None::<fn(Progress)>,
+        )?;
+        drop(conn);
+        // Backups from before a migration are brought up to date
+        run_migrations(db)?;
+
+        let conn = open_connection(db)?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS live",
+            params![self.policy.dir.join(&previous.file).to_string_lossy()],
+        )?;
+        for table in KEPT_ON_RESTORE {
+            if table_exists(&conn, table)? {
+                conn.execute_batch(&format!(
+                    "DELETE FROM main.{table}; INSERT INTO main.{table} SELECT * FROM live.{table};"
+                ))?;
+            }
+        }
+        conn.execute("DETACH DATABASE live", [])?;
+        Ok(previous)
+    }
+
+    // Removes backups past the retention period. Returns the number removed.
+    pub fn prune(&self, now: DateTime<Utc>) -> Result<usize, AppError> {
+        let backups = self.list()?;
+        let expired = expired_backups(&backups, &self.policy, now);
+        for backup in &expired {
+            fs::remove_file(self.policy.dir.join(&backup.file))?;
+        }
+        Ok(expired.len())
+    }
+
+    // Takes a scheduled snapshot when the last one is older than the interval
+    fn run_schedule(&self) -> Result<(), AppError> {
+        let now = Utc::now();
+        let last = self
+            .list()?
+            .into_iter()
+            .find(|b| b.reason == BackupReason::Scheduled)
+            .map(|b| b.created_at);
+        let due =
+            last.is_none_or(|at| now - at >= Duration::hours(self.policy.interval_hours.into()));
+        if due {
+            if let Some(backup) = self.snapshot(BackupReason::Scheduled)? {
+                info!("Database backed up to {}", backup.file);
+            }
+            let pruned = self.prune(now)?;
+            if pruned > 0 {
+                info!("Pruned {} expired backup(s)", pruned);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Backups older than the retention period, sparing the newest `keep_min`.
+// Expects backups newest first, as returned by `Backups::list`.
+pub fn expired_backups<'a>(
+    backups: &'a [BackupInfo],
+    policy: &BackupPolicy,
+    now: DateTime<Utc>,
+) -> Vec<&'a BackupInfo> {
+    let cutoff = now - Duration::days(policy.retention_days.into());
+    backups
+        .iter()
+        .skip(policy.keep_min)
+        .filter(|backup| backup.created_at < cutoff)
+        .collect()
+}
+
+// Checks hourly whether a scheduled snapshot is due, so restarts do not
+// delay or repeat backups
+pub fn start_backup_thread(backups: Backups) {
+    info!("Database backups: {:?}", backups.policy);
+    std::thread::spawn(move || {
+        loop {
+            if let Err(e) = backups.run_schedule() {
+                error!("Failed to backup database: {}", e);
+            }
+            std::thread::sleep(std::time::Duration::from_secs(60 * 60));
         }
     });
 }
@@ -0,0 +1,129 @@
+use crate::utils::discord_voice::bot_api_url;
+use crate::utils::outbox::{OutboxChannel, outbox};
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+
+#[derive(Debug, Deserialize)]
+struct Thread {
+    id: String,
+}
+
+// Records a bot call in the outbox instead of making it, when notifications
+// are captured. Returns the id of the captured message.
+fn captured(endpoint: String, body: &str) -> Option<u64> {
+    outbox().map(|outbox| outbox.capture(OutboxChannel::Discord, &endpoint, None, body))
+}
+
+// Channel the private group threads are created in
+pub fn group_thread_channel() -> Option<String> {
+    env::var("DISCORD_GROUP_CHANNEL_ID")
+        .ok()
+        .filter(|id| !id.trim().is_empty())
+}
+
+// Channel cohort-wide announcements are posted to
+pub fn announcement_channel() -> Option<String> {
+    env::var("DISCORD_ANNOUNCEMENT_CHANNEL_ID")
+        .ok()
+        .filter(|id| !id.trim().is_empty())
+}
+
+pub async fn post_channel_message(channel_id: &str, content: &str) -> Result<(), reqwest::Error> {
+    if captured(format!("channels/{}/messages", channel_id), content).is_some() {
+        return Ok(());
+    }
+    reqwest::Client::new()
+        .post(format!(
+            "{}/bot/channels/{}/messages",
+            bot_api_url(),
+            channel_id
+        ))
+        .json(&json!({ "content": content }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// Creates a private thread and returns its id
+pub async fn create_private_thread(channel_id: &str, name: &str) -> Result<String, reqwest::Error> {
+    if let Some(id) = captured(format!("channels/{}/threads", channel_id), name) {
+        return Ok(format!("outbox-{}", id));
+    }
+    let thread = reqwest::Client::new()
+        .post(format!(
+            "{}/bot/channels/{}/threads",
+            bot_api_url(),
+            channel_id
+        ))
+        .json(&json!({ "name": name, "private": true }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Thread>()
+        .await?;
+    Ok(thread.id)
+}
+
+// Adding a member already in the thread is a no-op for Discord
+pub async fn add_thread_member(thread_id: &str, discord_id: &str) -> Result<(), reqwest::Error> {
+    if captured(
+        format!("threads/{}/members/{}", thread_id, discord_id),
+        "add",
+    )
+    .is_some()
+    {
+        return Ok(());
+    }
+    reqwest::Client::new()
+        .put(format!(
+            "{}/bot/threads/{}/members/{}",
+            bot_api_url(),
+            thread_id,
+            discord_id
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+pub async fn remove_thread_member(thread_id: &str, discord_id: &str) -> Result<(), reqwest::Error> {
+    if captured(
+        format!("threads/{}/members/{}", thread_id, discord_id),
+        "remove",
+    )
+    .is_some()
+    {
+        return Ok(());
+    }
+    reqwest::Client::new()
+        .delete(format!(
+            "{}/bot/threads/{}/members/{}",
+            bot_api_url(),
+            thread_id,
+            discord_id
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+pub async fn post_thread_message(thread_id: &str, content: &str) -> Result<(), reqwest::Error> {
+    if captured(format!("threads/{}/messages", thread_id), content).is_some() {
+        return Ok(());
+    }
+    reqwest::Client::new()
+        .post(format!(
+            "{}/bot/threads/{}/messages",
+            bot_api_url(),
+            thread_id
+        ))
+        .json(&json!({ "content": content }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
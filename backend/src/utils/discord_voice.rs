@@ -0,0 +1,144 @@
+use crate::database::storage::{Storage, blocking};
+use crate::utils::types::{Member, Table};
+use actix_web::web;
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+use log::{error, info};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+// A recurring session slot, e.g. `Sat 15:00-17:00` in server local time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionWindow {
+    pub weekday: Weekday,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl SessionWindow {
+    pub fn contains(&self, now: &DateTime<Local>) -> bool {
+        let time = now.time();
+        now.weekday() == self.weekday && time >= self.start && time < self.end
+    }
+}
+
+// Parses a comma separated list like `Sat 15:00-17:00, Sun 15:00-17:00`
+pub fn parse_session_windows(spec: &str) -> Result<Vec<SessionWindow>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| {
+            let invalid = || format!("Invalid session window: {}", entry);
+            let (day, range) = entry.split_once(' ').ok_or_else(invalid)?;
+            let (start, end) = range.trim().split_once('-').ok_or_else(invalid)?;
+            Ok(SessionWindow {
+                weekday: day.parse::<Weekday>().map_err(|_| invalid())?,
+                start: NaiveTime::parse_from_str(start, "%H:%M").map_err(|_| invalid())?,
+                end: NaiveTime::parse_from_str(end, "%H:%M").map_err(|_| invalid())?,
+            })
+        })
+        .collect()
+}
+
+pub fn bot_api_url() -> String {
+    env::var("BOT_API_URL").unwrap_or_else(|_| "http://localhost:8080".to_string())
+}
+
+// Asks the Discord bot who is currently connected to the voice channel
+pub async fn fetch_voice_members(channel_id: &str) -> Result<Vec<Member>, reqwest::Error> {
+    reqwest::Client::new()
+        .get(format!(
+            "{}/bot/voice/{}/members",
+            bot_api_url(),
+            channel_id
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<Member>>()
+        .await
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+// Maps a voice attendee to a participant name: an explicit handle mapping
+// wins, otherwise the Discord name must match a participant name exactly
+// once punctuation, spacing and case are ignored
+pub fn match_participant<'a>(
+    discord_id: &str,
+    discord_name: &str,
+    handles: &'a HashMap<String, String>,
+    names: &'a [String],
+) -> Option<&'a String> {
+    if let Some(name) = handles.get(discord_id) {
+        return Some(name);
+    }
+    let wanted = normalize(discord_name);
+    if wanted.is_empty() {
+        return None;
+    }
+    let mut matches = names.iter().filter(|name| normalize(name) == wanted);
+    match (matches.next(), matches.next()) {
+        (Some(name), None) => Some(name),
+        _ => None,
+    }
+}
+
+// Periodically snapshots the session voice channel while a session is running.
+// Snapshots are attributed to the latest week present in the table.
+pub fn start_voice_snapshot_task(state: web::Data<Mutex<Table>>, db: web::Data<dyn Storage>) {
+    let channel_id = match env::var("ATTENDANCE_VOICE_CHANNEL_ID") {
+        Ok(id) if !id.is_empty() => id,
+        _ => return,
+    };
+    let windows = match parse_session_windows(&env::var("SESSION_WINDOWS").unwrap_or_default()) {
+        Ok(windows) if !windows.is_empty() => windows,
+        Ok(_) => return,
+        Err(e) => {
+            error!("Voice attendance disabled: {}", e);
+            return;
+        }
+    };
+    let interval_mins: u64 = env::var("VOICE_SNAPSHOT_INTERVAL_MINS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+
+    info!(
+        "Voice attendance snapshots enabled for {} session window(s)",
+        windows.len()
+    );
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(interval_mins * 60));
+        loop {
+            interval.tick().await;
+            let now = Local::now();
+            if !windows.iter().any(|w| w.contains(&now)) {
+                continue;
+            }
+
+            let week = state.lock().unwrap().rows.iter().map(|r| r.week).max();
+            let Some(week) = week.filter(|w| *w >= 1) else {
+                continue;
+            };
+
+            match fetch_voice_members(&channel_id).await {
+                Ok(members) => {
+                    let recorded =
+                        blocking(&db, move |db| db.record_voice_snapshot(week, &members)).await;
+                    if let Err(e) = recorded {
+                        error!("Failed to store voice snapshot: {}", e);
+                    }
+                }
+                Err(e) => error!("Failed to fetch voice channel members: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,107 @@
+use crate::utils::classroom::{Assignment, ClassroomError};
+use crate::utils::forge::ForgeProvider;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_CACHE_SECS: u64 = 300;
+
+// Week submissions as last fetched, so regenerating a week or a burst of
+// page loads does not list every student repo again and burn API quota
+#[derive(Debug)]
+pub struct ForgeCache {
+    ttl: Duration,
+    weeks: Mutex<HashMap<i32, (Instant, Vec<Assignment>)>>,
+}
+
+impl ForgeCache {
+    pub fn new(ttl: Duration) -> Self {
+        ForgeCache {
+            ttl,
+            weeks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // TTL from FORGE_CACHE_SECS; 0 fetches every time
+    pub fn from_env() -> Result<Self, String> {
+        let secs = match env::var("FORGE_CACHE_SECS") {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse()
+                .map_err(|_| "FORGE_CACHE_SECS must be a number of seconds")?,
+            _ => DEFAULT_CACHE_SECS,
+        };
+        Ok(ForgeCache::new(Duration::from_secs(secs)))
+    }
+
+    fn fresh(&self, week: i32) -> Option<Vec<Assignment>> {
+        self.weeks
+            .lock()
+            .unwrap()
+            .get(&week)
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, assignments)| assignments.clone())
+    }
+
+    fn store(&self, week: i32, assignments: Vec<Assignment>) {
+        if !self.ttl.is_zero() {
+            self.weeks
+                .lock()
+                .unwrap()
+                .insert(week, (Instant::now(), assignments));
+        }
+    }
+
+    // Forgets the week, or every week. Returns how many weeks were cached.
+    pub fn bust(&self, week: Option<i32>) -> usize {
+        let mut weeks = self.weeks.lock().unwrap();
+        match week {
+            Some(week) => usize::from(weeks.remove(&week).is_some()),
+            None => {
+                let cached = weeks.len();
+                weeks.clear();
+                cached
+            }
+        }
+    }
+}
+
+// A forge answering week listings from the cache while they are fresh.
+// Failed fetches are not cached.
+pub struct CachedForge {
+    inner: Arc<dyn ForgeProvider>,
+    cache: Arc<ForgeCache>,
+}
+
+impl CachedForge {
+    pub fn new(inner: Arc<dyn ForgeProvider>, cache: Arc<ForgeCache>) -> Self {
+        CachedForge { inner, cache }
+    }
+}
+
+#[async_trait]
+impl ForgeProvider for CachedForge {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn fetch_week_submissions(&self, week: i32) -> Result<Vec<Assignment>, ClassroomError> {
+        if let Some(assignments) = self.cache.fresh(week) {
+            return Ok(assignments);
+        }
+        let assignments = self.inner.fetch_week_submissions(week).await?;
+        self.cache.store(week, assignments.clone());
+        Ok(assignments)
+    }
+
+    // Always fetched: a re-check follows a push the cache cannot know of
+    async fn fetch_student_submission(
+        &self,
+        week: i32,
+        username: &str,
+    ) -> Result<Option<Assignment>, ClassroomError> {
+        self.inner.fetch_student_submission(week, username).await
+    }
+}
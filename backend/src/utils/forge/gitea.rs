@@ -0,0 +1,189 @@
+use crate::utils::classroom::{Assignment, ClassroomError};
+use crate::utils::forge::{ForgeProvider, RepoConvention, SubmissionMarker, get_optional_json};
+use async_trait::async_trait;
+use log::info;
+use serde::Deserialize;
+
+const PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct Repo {
+    name: String,
+    html_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagCommit {
+    sha: String,
+    created: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tag {
+    commit: TagCommit,
+}
+
+#[derive(Debug, Deserialize)]
+struct BranchCommit {
+    id: String,
+    timestamp: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Branch {
+    commit: BranchCommit,
+    protected: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CombinedStatus {
+    state: String,
+}
+
+// Gitea organisation holding one repo per student per week
+pub struct GiteaForge {
+    client: reqwest::Client,
+    base_url: String,
+    token: String,
+    org: String,
+    convention: RepoConvention,
+}
+
+impl GiteaForge {
+    pub fn new(base_url: String, token: String, org: String, convention: RepoConvention) -> Self {
+        GiteaForge {
+            client: reqwest::Client::new(),
+            base_url,
+            token,
+            org,
+            convention,
+        }
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .get(format!("{}/api/v1{}", self.base_url, path))
+            .header("Authorization", format!("token {}", self.token))
+    }
+
+    async fn list_org_repos(&self) -> Result<Vec<Repo>, ClassroomError> {
+        let mut repos = Vec::new();
+        let mut page = 1;
+        loop {
+            let batch: Vec<Repo> = get_optional_json(self.get(&format!(
+                "/orgs/{}/repos?limit={}&page={}",
+                self.org, PAGE_SIZE, page
+            )))
+            .await?
+            .ok_or_else(|| ClassroomError::Config(format!("Gitea org '{}' not found", self.org)))?;
+
+            let done = batch.len() < PAGE_SIZE;
+            repos.extend(batch);
+            if done {
+                return Ok(repos);
+            }
+            page += 1;
+        }
+    }
+
+    // Returns the submitted commit sha and timestamp, if the repo is submitted
+    async fn submission(&self, repo: &str) -> Result<Option<(String, String)>, ClassroomError> {
+        match &self.convention.submission {
+            SubmissionMarker::Tag(tag) => {
+                let tag: Option<Tag> = get_optional_json(
+                    self.get(&format!("/repos/{}/{}/tags/{}", self.org, repo, tag)),
+                )
+                .await?;
+                Ok(tag.map(|t| (t.commit.sha, t.commit.created.unwrap_or_default())))
+            }
+            SubmissionMarker::ProtectedBranch(branch) => {
+                let branch: Option<Branch> = get_optional_json(
+                    self.get(&format!("/repos/{}/{}/branches/{}", self.org, repo, branch)),
+                )
+                .await?;
+                Ok(branch
+                    .filter(|b| b.protected)
+                    .map(|b| (b.commit.id, b.commit.timestamp.unwrap_or_default())))
+            }
+        }
+    }
+
+    async fn assignment(
+        &self,
+        week: i32,
+        username: String,
+        repo: Repo,
+    ) -> Result<Assignment, ClassroomError> {
+        let (submitted_at, ci_passing) = match self.submission(&repo.name).await? {
+            Some((sha, timestamp)) => {
+                let passing = self.ci_passing(&repo.name, &sha).await?;
+                (Some(timestamp), passing)
+            }
+            None => (None, false),
+        };
+        Ok(self.convention.to_assignment(
+            week,
+            username,
+            repo.name,
+            repo.html_url,
+            submitted_at,
+            ci_passing,
+        ))
+    }
+
+    async fn ci_passing(&self, repo: &str, sha: &str) -> Result<bool, ClassroomError> {
+        let status: Option<CombinedStatus> = get_optional_json(self.get(&format!(
+            "/repos/{}/{}/commits/{}/status",
+            self.org, repo, sha
+        )))
+        .await?;
+        Ok(status.map(|s| s.state == "success").unwrap_or(false))
+    }
+}
+
+#[async_trait]
+impl ForgeProvider for GiteaForge {
+    fn name(&self) -> &'static str {
+        "gitea"
+    }
+
+    async fn fetch_week_submissions(&self, week: i32) -> Result<Vec<Assignment>, ClassroomError> {
+        let mut assignments = Vec::new();
+
+        for repo in self.list_org_repos().await? {
+            let Some(username) = self.convention.student_for_repo(&repo.name, week) else {
+                continue;
+            };
+
+            assignments.push(self.assignment(week, username, repo).await?);
+        }
+
+        if assignments.is_empty() {
+            return Err(ClassroomError::AssignmentNotFound(week));
+        }
+
+        info!(
+            "Fetched {} week {} repos from Gitea org {}",
+            assignments.len(),
+            week,
+            self.org
+        );
+        Ok(assignments)
+    }
+
+    async fn fetch_student_submission(
+        &self,
+        week: i32,
+        username: &str,
+    ) -> Result<Option<Assignment>, ClassroomError> {
+        let name = self.convention.repo_name(week, username);
+        let repo: Option<Repo> =
+            get_optional_json(self.get(&format!("/repos/{}/{}", self.org, name))).await?;
+        match repo {
+            Some(repo) => Ok(Some(
+                self.assignment(week, username.to_string(), repo).await?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
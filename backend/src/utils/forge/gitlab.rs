@@ -0,0 +1,196 @@
+use crate::utils::classroom::{Assignment, ClassroomError};
+use crate::utils::forge::{ForgeProvider, RepoConvention, SubmissionMarker, get_optional_json};
+use async_trait::async_trait;
+use log::info;
+use serde::Deserialize;
+
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    id: u64,
+    path: String,
+    web_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    id: String,
+    committed_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tag {
+    commit: Commit,
+}
+
+#[derive(Debug, Deserialize)]
+struct Branch {
+    commit: Commit,
+    protected: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pipeline {
+    status: String,
+}
+
+// GitLab group (including subgroups) holding one project per student per week
+pub struct GitlabForge {
+    client: reqwest::Client,
+    base_url: String,
+    token: String,
+    group: String,
+    convention: RepoConvention,
+}
+
+impl GitlabForge {
+    pub fn new(base_url: String, token: String, group: String, convention: RepoConvention) -> Self {
+        GitlabForge {
+            client: reqwest::Client::new(),
+            base_url,
+            token,
+            group,
+            convention,
+        }
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .get(format!("{}/api/v4{}", self.base_url, path))
+            .header("PRIVATE-TOKEN", &self.token)
+    }
+
+    async fn list_group_projects(&self) -> Result<Vec<Project>, ClassroomError> {
+        // Group paths like `bitshala/cohort-5` must be URL encoded
+        let group = self.group.replace('/', "%2F");
+        let mut projects = Vec::new();
+        let mut page = 1;
+        loop {
+            let batch: Vec<Project> = get_optional_json(self.get(&format!(
+                "/groups/{}/projects?include_subgroups=true&per_page={}&page={}",
+                group, PAGE_SIZE, page
+            )))
+            .await?
+            .ok_or_else(|| {
+                ClassroomError::Config(format!("GitLab group '{}' not found", self.group))
+            })?;
+
+            let done = batch.len() < PAGE_SIZE;
+            projects.extend(batch);
+            if done {
+                return Ok(projects);
+            }
+            page += 1;
+        }
+    }
+
+    // Returns the submitted commit sha and timestamp, if the project is submitted
+    async fn submission(
+        &self,
+        project_id: u64,
+    ) -> Result<Option<(String, String)>, ClassroomError> {
+        let commit = match &self.convention.submission {
+            SubmissionMarker::Tag(tag) => get_optional_json::<Tag>(
+                self.get(&format!("/projects/{}/repository/tags/{}", project_id, tag)),
+            )
+            .await?
+            .map(|t| t.commit),
+            SubmissionMarker::ProtectedBranch(branch) => get_optional_json::<Branch>(self.get(
+                &format!("/projects/{}/repository/branches/{}", project_id, branch),
+            ))
+            .await?
+            .filter(|b| b.protected)
+            .map(|b| b.commit),
+        };
+        Ok(commit.map(|c| (c.id, c.committed_date.unwrap_or_default())))
+    }
+
+    async fn assignment(
+        &self,
+        week: i32,
+        username: String,
+        project: Project,
+    ) -> Result<Assignment, ClassroomError> {
+        let (submitted_at, ci_passing) = match self.submission(project.id).await? {
+            Some((sha, timestamp)) => {
+                let passing = self.ci_passing(project.id, &sha).await?;
+                (Some(timestamp), passing)
+            }
+            None => (None, false),
+        };
+        Ok(self.convention.to_assignment(
+            week,
+            username,
+            project.path,
+            project.web_url,
+            submitted_at,
+            ci_passing,
+        ))
+    }
+
+    async fn ci_passing(&self, project_id: u64, sha: &str) -> Result<bool, ClassroomError> {
+        let pipelines: Option<Vec<Pipeline>> = get_optional_json(self.get(&format!(
+            "/projects/{}/pipelines?sha={}&per_page=1",
+            project_id, sha
+        )))
+        .await?;
+        Ok(pipelines
+            .and_then(|p| p.into_iter().next())
+            .map(|p| p.status == "success")
+            .unwrap_or(false))
+    }
+}
+
+#[async_trait]
+impl ForgeProvider for GitlabForge {
+    fn name(&self) -> &'static str {
+        "gitlab"
+    }
+
+    async fn fetch_week_submissions(&self, week: i32) -> Result<Vec<Assignment>, ClassroomError> {
+        let mut assignments = Vec::new();
+
+        for project in self.list_group_projects().await? {
+            let Some(username) = self.convention.student_for_repo(&project.path, week) else {
+                continue;
+            };
+
+            assignments.push(self.assignment(week, username, project).await?);
+        }
+
+        if assignments.is_empty() {
+            return Err(ClassroomError::AssignmentNotFound(week));
+        }
+
+        info!(
+            "Fetched {} week {} projects from GitLab group {}",
+            assignments.len(),
+            week,
+            self.group
+        );
+        Ok(assignments)
+    }
+
+    // Projects are addressed by their URL encoded full path. Unlike the
+    // group listing this does not look into subgroups.
+    async fn fetch_student_submission(
+        &self,
+        week: i32,
+        username: &str,
+    ) -> Result<Option<Assignment>, ClassroomError> {
+        let path = format!(
+            "{}/{}",
+            self.group,
+            self.convention.repo_name(week, username)
+        );
+        let project: Option<Project> =
+            get_optional_json(self.get(&format!("/projects/{}", path.replace('/', "%2F")))).await?;
+        match project {
+            Some(project) => Ok(Some(
+                self.assignment(week, username.to_string(), project).await?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
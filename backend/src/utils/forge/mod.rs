@@ -0,0 +1,326 @@
+pub mod cache;
+pub mod gitea;
+pub mod gitlab;
+
+use crate::utils::classroom::{Assignment, ClassroomError, GithubClassroom};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::Arc;
+
+pub use cache::{CachedForge, ForgeCache};
+pub use gitea::GiteaForge;
+pub use gitlab::GitlabForge;
+
+// Source of exercise submissions. GitHub Classroom is the default; self-hosted
+// deployments can use Gitea or GitLab group repositories instead.
+#[async_trait]
+pub trait ForgeProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // All student repos for the week, submitted or not, in the Classroom
+    // `Assignment` shape so downstream grading logic stays forge agnostic
+    async fn fetch_week_submissions(&self, week: i32) -> Result<Vec<Assignment>, ClassroomError>;
+
+    // A single student's repo for the week, None when they have none. Forges
+    // that can address one repo directly avoid listing the whole week.
+    async fn fetch_student_submission(
+        &self,
+        week: i32,
+        username: &str,
+    ) -> Result<Option<Assignment>, ClassroomError> {
+        Ok(self
+            .fetch_week_submissions(week)
+            .await?
+            .into_iter()
+            .find(|a| a.github_username.eq_ignore_ascii_case(username)))
+    }
+}
+
+// A forge refusing requests for a while, answered with a 503 and the
+// seconds after which the client may retry
+#[derive(thiserror::Error, Debug)]
+#[error("{forge} is rate limiting requests, retry in {retry_after} seconds")]
+pub struct ForgeRateLimited {
+    pub forge: &'static str,
+    pub retry_after: u64,
+}
+
+impl ResponseError for ForgeRateLimited {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .insert_header(("Retry-After", self.retry_after.to_string()))
+            .json(serde_json::json!({
+                "status": "error",
+                "message": self.to_string(),
+                "retry_after": self.retry_after
+            }))
+    }
+}
+
+// How a student marks an exercise repo as submitted on a self-hosted forge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmissionMarker {
+    Tag(String),
+    ProtectedBranch(String),
+}
+
+impl SubmissionMarker {
+    // Parses `tag:<name>` or `branch:<name>`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(':') {
+            Some(("tag", name)) if !name.is_empty() => Ok(SubmissionMarker::Tag(name.to_string())),
+            Some(("branch", name)) if !name.is_empty() => {
+                Ok(SubmissionMarker::ProtectedBranch(name.to_string()))
+            }
+            _ => Err(format!(
+                "Invalid FORGE_SUBMISSION '{}', expected tag:<name> or branch:<name>",
+                spec
+            )),
+        }
+    }
+}
+
+// Naming convention for per-student exercise repos inside a group/org,
+// e.g. `week-3-alice` with the pattern `week-{week}`
+#[derive(Debug, Clone)]
+pub struct RepoConvention {
+    pub assignment_pattern: String,
+    pub submission: SubmissionMarker,
+}
+
+impl RepoConvention {
+    pub fn student_for_repo(&self, repo_name: &str, week: i32) -> Option<String> {
+        let prefix = format!(
+            "{}-",
+            self.assignment_pattern.replace("{week}", &week.to_string())
+        );
+        repo_name
+            .strip_prefix(&prefix)
+            .filter(|user| !user.is_empty())
+            .map(|user| user.to_string())
+    }
+
+    pub fn repo_name(&self, week: i32, username: &str) -> String {
+        format!(
+            "{}-{}",
+            self.assignment_pattern.replace("{week}", &week.to_string()),
+            username
+        )
+    }
+
+    // Builds the Classroom shaped record for a self-hosted student repo
+    pub fn to_assignment(
+        &self,
+        week: i32,
+        username: String,
+        repo_name: String,
+        repo_url: String,
+        submitted_at: Option<String>,
+        ci_passing: bool,
+    ) -> Assignment {
+        Assignment {
+            assignment_name: format!("Week {}", week),
+            assignment_url: String::new(),
+            github_username: username,
+            points_available: "100".to_string(),
+            points_awarded: if ci_passing { "100" } else { "0" }.to_string(),
+            roster_identifier: String::new(),
+            starter_code_url: String::new(),
+            student_repository_name: repo_name,
+            student_repository_url: repo_url,
+            // An empty timestamp is how Classroom reports "not submitted"
+            submission_timestamp: Some(submitted_at.unwrap_or_default()),
+            ci_passing: None,
+            commit_count: None,
+            last_commit_at: None,
+        }
+    }
+}
+
+// GET a JSON resource, treating 404 as absent rather than an error
+pub(crate) async fn get_optional_json<T: DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+) -> Result<Option<T>, ClassroomError> {
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok());
+        return Err(ClassroomError::RateLimited(retry_after.unwrap_or(60)));
+    }
+    let response = response.error_for_status()?;
+    Ok(Some(response.json::<T>().await?))
+}
+
+fn required_env(key: &str) -> Result<String, String> {
+    env::var(key).map_err(|_| format!("{} must be set", key))
+}
+
+// Selects the forge from `FORGE_PROVIDER` (github, gitea or gitlab)
+pub fn forge_from_env() -> Result<Arc<dyn ForgeProvider>, String> {
+    let provider = env::var("FORGE_PROVIDER").unwrap_or_else(|_| "github".to_string());
+    if provider == "github" {
+        return Ok(Arc::new(GithubClassroom::from_env()?));
+    }
+
+    let base_url = required_env("FORGE_BASE_URL")?
+        .trim_end_matches('/')
+        .to_string();
+    let token = required_env("FORGE_TOKEN")?;
+    let group = required_env("FORGE_GROUP")?;
+    let convention = RepoConvention {
+        assignment_pattern: env::var("FORGE_ASSIGNMENT_PATTERN")
+            .unwrap_or_else(|_| "week-{week}".to_string()),
+        submission: SubmissionMarker::parse(
+            &env::var("FORGE_SUBMISSION").unwrap_or_else(|_| "tag:submitted".to_string()),
+        )?,
+    };
+
+    match provider.as_str() {
+        "gitea" => Ok(Arc::new(GiteaForge::new(
+            base_url, token, group, convention,
+        ))),
+        "gitlab" => Ok(Arc::new(GitlabForge::new(
+            base_url, token, group, convention,
+        ))),
+        other => Err(format!(
+            "Unknown FORGE_PROVIDER '{}', expected github, gitea or gitlab",
+            other
+        )),
+    }
+}
+
+// Categories of partial data a forge can hand back for a week
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncWarningKind {
+    AssignmentNotFoundForWeek,
+    AssignmentWeekMismatch,
+    RosterMismatch,
+    ClassroomUnavailable,
+    LateSubmission,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncWarning {
+    pub kind: SyncWarningKind,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_username: Option<String>,
+}
+
+impl SyncWarning {
+    pub fn new(kind: SyncWarningKind, message: impl Into<String>) -> Self {
+        SyncWarning {
+            kind,
+            message: message.into(),
+            github_username: None,
+        }
+    }
+
+    pub fn for_user(kind: SyncWarningKind, message: impl Into<String>, github: &str) -> Self {
+        SyncWarning {
+            kind,
+            message: message.into(),
+            github_username: Some(github.to_string()),
+        }
+    }
+}
+
+// Result of fetching a week's submissions, including any warnings
+// explaining why the submission list may be incomplete
+#[derive(Debug, Default)]
+pub struct WeekSync {
+    pub assignments: Vec<Assignment>,
+    pub warnings: Vec<SyncWarning>,
+}
+
+// Warnings for submissions whose last commit came after the deadline. The
+// submission time stands in where the forge reported no commits.
+pub fn late_submissions(assignments: &[Assignment], deadline: DateTime<Utc>) -> Vec<SyncWarning> {
+    assignments
+        .iter()
+        .filter(|a| a.is_submitted())
+        .filter_map(|a| {
+            let at = a
+                .last_commit_at
+                .as_deref()
+                .or(a.submission_timestamp.as_deref())?;
+            let at = DateTime::parse_from_rfc3339(at).ok()?.with_timezone(&Utc);
+            (at > deadline).then(|| {
+                SyncWarning::for_user(
+                    SyncWarningKind::LateSubmission,
+                    format!(
+                        "{} last committed at {}, after the deadline of {}",
+                        a.github_username,
+                        at.to_rfc3339(),
+                        deadline.to_rfc3339()
+                    ),
+                    &a.github_username,
+                )
+            })
+        })
+        .collect()
+}
+
+pub async fn sync_week_assignments(forge: &dyn ForgeProvider, week_number: i32) -> WeekSync {
+    match forge.fetch_week_submissions(week_number).await {
+        Ok(assignments) => {
+            let warnings = assignments
+                .iter()
+                .filter(|a| a.is_submitted() && a.get_week_pattern() != Some(week_number as u32))
+                .map(|a| {
+                    SyncWarning::for_user(
+                        SyncWarningKind::AssignmentWeekMismatch,
+                        format!(
+                            "Assignment '{}' does not look like week {}",
+                            a.assignment_name, week_number
+                        ),
+                        &a.github_username,
+                    )
+                })
+                .collect();
+            WeekSync {
+                assignments,
+                warnings,
+            }
+        }
+        Err(ClassroomError::AssignmentNotFound(_)) => WeekSync {
+            assignments: vec![],
+            warnings: vec![SyncWarning::new(
+                SyncWarningKind::AssignmentNotFoundForWeek,
+                format!(
+                    "{} has no assignment for week {} yet",
+                    forge.name(),
+                    week_number
+                ),
+            )],
+        },
+        Err(e) => WeekSync {
+            assignments: vec![],
+            warnings: vec![SyncWarning::new(
+                SyncWarningKind::ClassroomUnavailable,
+                format!(
+                    "Failed to fetch week {} from {}: {}",
+                    week_number,
+                    forge.name(),
+                    e
+                ),
+            )],
+        },
+    }
+}
@@ -0,0 +1,25 @@
+use chrono::Utc;
+
+// Crockford's base32, the ULID alphabet
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ID_LENGTH: usize = 26;
+
+// A new ULID: 48 bits of milliseconds since the epoch then 80 random bits,
+// as 26 characters. Used as the public id of participants and rows, so URLs
+// do not depend on names.
+pub fn new_public_id() -> String {
+    let millis = (Utc::now().timestamp_millis().max(0) as u128) & ((1 << 48) - 1);
+    let random = rand::random::<u128>() & ((1 << 80) - 1);
+    let value = (millis << 80) | random;
+    (0..ID_LENGTH)
+        .rev()
+        .map(|i| ALPHABET[((value >> (i * 5)) & 31) as usize] as char)
+        .collect()
+}
+
+// Whether `value` has the shape of a public id, as opposed to a name
+pub fn is_public_id(value: &str) -> bool {
+    value.len() == ID_LENGTH
+        && value.as_bytes()[0] <= b'7'
+        && value.bytes().all(|b| ALPHABET.contains(&b))
+}
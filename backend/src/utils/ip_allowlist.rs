@@ -0,0 +1,179 @@
+use crate::utils::reload::Reloadable;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, HttpResponse, web};
+use ipnet::IpNet;
+use log::warn;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+
+// Optional allowlist of CIDR ranges the admin panel may be reached from.
+// An empty allowlist disables the check entirely.
+#[derive(Debug, Clone, Default)]
+pub struct IpAllowlist {
+    ranges: Vec<IpNet>,
+    // Reverse proxies whose forwarded headers are believed
+    trusted_proxies: Vec<IpNet>,
+}
+
+// Parses a comma separated list of CIDR ranges. Bare addresses are accepted
+// and treated as a single-host range.
+fn parse_ranges(spec: &str) -> Result<Vec<IpNet>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|entry| match entry.parse::<IpNet>() {
+            Ok(net) => Ok(net),
+            Err(_) => entry
+                .parse::<IpAddr>()
+                .map(IpNet::from)
+                .map_err(|_| format!("Invalid IP allowlist entry: {}", entry)),
+        })
+        .collect()
+}
+
+// One address of a forwarding chain: a bare address, `ip:port`,
+// `[ipv6]:port` or the quoted `for=` value of a Forwarded header
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    hop.parse::<IpAddr>()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|s| s.ip()))
+        .or_else(|| {
+            hop.strip_prefix('[')
+                .and_then(|rest| rest.split(']').next())
+                .and_then(|ip| ip.parse().ok())
+        })
+}
+
+// Addresses a request passed through, client first, from the Forwarded
+// header or else X-Forwarded-For
+fn forwarded_hops(req: &HttpRequest) -> Vec<IpAddr> {
+    let header = |name: &str| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let forwarded = header("forwarded");
+    if !forwarded.is_empty() {
+        return forwarded
+            .iter()
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim().eq_ignore_ascii_case("for").then_some(value)
+                })
+            })
+            .filter_map(parse_hop)
+            .collect();
+    }
+    header("x-forwarded-for")
+        .iter()
+        .filter_map(|hop| parse_hop(hop))
+        .collect()
+}
+
+impl IpAllowlist {
+    // Takes the allowed ranges and the ranges of trusted reverse proxies,
+    // both comma separated
+    pub fn parse(spec: &str, trusted_proxies: &str) -> Result<Self, String> {
+        Ok(IpAllowlist {
+            ranges: parse_ranges(spec)?,
+            trusted_proxies: parse_ranges(trusted_proxies)?,
+        })
+    }
+
+    // Reads `IP_ALLOWLIST` and `IP_ALLOWLIST_TRUSTED_PROXIES` from the
+    // environment.
+    pub fn from_env() -> Result<Self, String> {
+        let spec = env::var("IP_ALLOWLIST").unwrap_or_default();
+        let trusted_proxies = env::var("IP_ALLOWLIST_TRUSTED_PROXIES").unwrap_or_default();
+        let trust_proxy =
+            env::var("IP_ALLOWLIST_TRUST_PROXY").is_ok_and(|v| v == "true" || v == "1");
+        if trust_proxy && trusted_proxies.trim().is_empty() {
+            return Err(
+                "IP_ALLOWLIST_TRUST_PROXY is replaced by IP_ALLOWLIST_TRUSTED_PROXIES, \
+                 the addresses of the reverse proxies in front of the backend"
+                    .to_string(),
+            );
+        }
+        Self::parse(&spec, &trusted_proxies)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ranges.is_empty()
+    }
+
+    pub fn allows(&self, ip: &IpAddr) -> bool {
+        !self.is_enabled() || self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    fn is_trusted_proxy(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|range| range.contains(ip))
+    }
+
+    // Resolves the client address. Forwarded headers are only read when the
+    // connection comes from a trusted proxy, and then only as far back as
+    // the proxies go: the client is the last address added by something
+    // other than a trusted proxy. Anything left of it is client-supplied.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        if !self.is_trusted_proxy(&peer) {
+            return Some(peer);
+        }
+        let hops = forwarded_hops(req);
+        hops.iter()
+            .rev()
+            .find(|hop| !self.is_trusted_proxy(hop))
+            .or(hops.first())
+            .copied()
+            .or(Some(peer))
+    }
+}
+
+// Client address for a request, using the configured proxy trust setting
+pub fn request_ip(req: &HttpRequest) -> Option<IpAddr> {
+    match req.app_data::<web::Data<Reloadable<IpAllowlist>>>() {
+        Some(allowlist) => allowlist.get().client_ip(req),
+        None => req.peer_addr().map(|addr| addr.ip()),
+    }
+}
+
+pub async fn enforce_ip_allowlist(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let allowlist = req
+        .app_data::<web::Data<Reloadable<IpAllowlist>>>()
+        .map(|allowlist| allowlist.get());
+
+    if let Some(allowlist) = allowlist.filter(|a| a.is_enabled()) {
+        let client_ip = allowlist.client_ip(req.request());
+        let allowed = client_ip.map(|ip| allowlist.allows(&ip)).unwrap_or(false);
+
+        if !allowed {
+            let client = client_ip
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            warn!(
+                target: "audit",
+                "Rejected request from {} not in IP allowlist: {} {}",
+                client,
+                req.method(),
+                req.path()
+            );
+            let response = HttpResponse::Forbidden().json(serde_json::json!({
+                "status": "error",
+                "message": "Forbidden: client address not allowed"
+            }));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+
+    let res = next.call(req).await?;
+    Ok(res.map_into_left_body())
+}
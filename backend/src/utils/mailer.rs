@@ -0,0 +1,91 @@
+use crate::utils::outbox::{Outbox, OutboxChannel, outbox};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::env;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MailerError {
+    #[error("Invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Failed to build message: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+enum Transport {
+    Smtp {
+        transport: Box<AsyncSmtpTransport<Tokio1Executor>>,
+        from: Mailbox,
+    },
+    Outbox(&'static Outbox),
+}
+
+// Outbound email over SMTP, configured by SMTP_HOST, SMTP_PORT,
+// SMTP_USERNAME, SMTP_PASSWORD and MAIL_FROM
+pub struct Mailer {
+    transport: Transport,
+}
+
+impl Mailer {
+    // Returns None when SMTP_HOST is unset, which the server refuses to
+    // start with as TAs sign in through emailed links. When notifications go
+    // to the outbox, mail is captured there whether or not SMTP is configured.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        if let Some(outbox) = outbox() {
+            return Ok(Some(Mailer::capturing(outbox)));
+        }
+        let host = match env::var("SMTP_HOST") {
+            Ok(host) if !host.is_empty() => host,
+            _ => return Ok(None),
+        };
+        let from = env::var("MAIL_FROM")
+            .map_err(|_| "MAIL_FROM must be set when SMTP_HOST is".to_string())?
+            .parse::<Mailbox>()
+            .map_err(|e| format!("Invalid MAIL_FROM: {}", e))?;
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+            .map_err(|e| format!("Invalid SMTP_HOST: {}", e))?;
+        if let Some(port) = env::var("SMTP_PORT").ok().and_then(|p| p.parse().ok()) {
+            builder = builder.port(port);
+        }
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD"))
+        {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Some(Mailer {
+            transport: Transport::Smtp {
+                transport: Box::new(builder.build()),
+                from,
+            },
+        }))
+    }
+
+    // Captures every message in the outbox instead of delivering it
+    pub fn capturing(outbox: &'static Outbox) -> Self {
+        Mailer {
+            transport: Transport::Outbox(outbox),
+        }
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), MailerError> {
+        let to: Mailbox = to.parse()?;
+        match &self.transport {
+            Transport::Smtp { transport, from } => {
+                let message = Message::builder()
+                    .from(from.clone())
+                    .to(to)
+                    .subject(subject)
+                    .body(body)?;
+                transport.send(message).await?;
+            }
+            Transport::Outbox(outbox) => {
+                outbox.capture(OutboxChannel::Email, &to.to_string(), Some(subject), &body);
+            }
+        }
+        Ok(())
+    }
+}
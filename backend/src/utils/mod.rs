@@ -3,4 +3,13 @@ pub mod classroom;
 pub mod constants;
 pub mod csv_dump;
 pub mod discord_auth;
+pub mod discord_threads;
+pub mod discord_voice;
+pub mod forge;
+pub mod ids;
+pub mod ip_allowlist;
+pub mod mailer;
+pub mod outbox;
+pub mod reload;
 pub mod types;
+pub mod webhook;
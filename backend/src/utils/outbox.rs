@@ -0,0 +1,113 @@
+//! Capture store for outbound notifications.
+//!
+//! With NOTIFICATION_SINK=outbox, email and Discord bot messages are kept
+//! here instead of being delivered, so a staging server can exercise every
+//! notification path against real data without reaching real students.
+
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+
+static OUTBOX: OnceLock<Option<Outbox>> = OnceLock::new();
+
+// Older messages are dropped past this many
+const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxChannel {
+    Email,
+    Discord,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutboxMessage {
+    pub id: u64,
+    pub channel: OutboxChannel,
+    // Email address, or the Discord endpoint the bot would have called
+    pub to: String,
+    pub subject: Option<String>,
+    pub body: String,
+    pub captured_at: String,
+}
+
+#[derive(Debug)]
+pub struct Outbox {
+    capacity: usize,
+    // Newest last, with the id the next message gets
+    messages: Mutex<(VecDeque<OutboxMessage>, u64)>,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> Self {
+        Outbox {
+            capacity,
+            messages: Mutex::new((VecDeque::new(), 1)),
+        }
+    }
+
+    // Keeps a message that would have been sent, returning its id
+    pub fn capture(
+        &self,
+        channel: OutboxChannel,
+        to: &str,
+        subject: Option<&str>,
+        body: &str,
+    ) -> u64 {
+        let mut guard = self.messages.lock().unwrap();
+        let (messages, next_id) = &mut *guard;
+        let id = *next_id;
+        *next_id += 1;
+        messages.push_back(OutboxMessage {
+            id,
+            channel,
+            to: to.to_string(),
+            subject: subject.map(str::to_string),
+            body: body.to_string(),
+            captured_at: Utc::now().to_rfc3339(),
+        });
+        while messages.len() > self.capacity {
+            messages.pop_front();
+        }
+        id
+    }
+
+    // Newest first
+    pub fn messages(&self) -> Vec<OutboxMessage> {
+        self.messages
+            .lock()
+            .unwrap()
+            .0
+            .iter()
+            .rev()
+            .cloned()
+            .collect()
+    }
+}
+
+// Reads NOTIFICATION_SINK once at startup: `live` (the default) delivers,
+// `outbox` captures. Returns whether notifications are captured.
+pub fn init_outbox() -> Result<bool, String> {
+    let sink = match env::var("NOTIFICATION_SINK") {
+        Ok(sink) if sink.trim().eq_ignore_ascii_case("outbox") => {
+            Some(Outbox::new(DEFAULT_CAPACITY))
+        }
+        Ok(sink) if sink.trim().is_empty() || sink.trim().eq_ignore_ascii_case("live") => None,
+        Err(_) => None,
+        Ok(sink) => {
+            return Err(format!(
+                "Unknown NOTIFICATION_SINK {}, expected live or outbox",
+                sink
+            ));
+        }
+    };
+    let _ = OUTBOX.set(sink);
+    Ok(outbox().is_some())
+}
+
+// The capture store when notifications are not delivered
+pub fn outbox() -> Option<&'static Outbox> {
+    OUTBOX.get_or_init(|| None).as_ref()
+}
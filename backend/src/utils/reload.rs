@@ -0,0 +1,117 @@
+//! Settings that can change without a restart.
+//!
+//! `POST /admin/config/reload` and SIGHUP re-read the `.env` file over the
+//! process environment and swap in the settings below. Discord channel ids
+//! are read on every use, so they follow the file as well. The storage
+//! backend, secrets, SMTP, notification sink and background schedules are
+//! only read at startup.
+
+use crate::services::cohort_window::CohortWindow;
+use crate::services::compensation::CompensationRates;
+use crate::services::sync_slo::SyncSlo;
+use crate::utils::ip_allowlist::IpAllowlist;
+use actix_web::web;
+use log::{info, warn};
+use std::sync::{Arc, RwLock};
+
+// A setting handlers read through `get`, which a reload replaces whole
+pub struct Reloadable<T> {
+    current: RwLock<Arc<T>>,
+}
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Reloadable {
+            current: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    // The read lock is only held to clone the Arc
+    pub fn get(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, value: T) {
+        *self.current.write().unwrap() = Arc::new(value);
+    }
+}
+
+// Every reloadable setting, as registered with the app
+#[derive(Clone)]
+pub struct LiveConfig {
+    pub compensation_rates: web::Data<Reloadable<CompensationRates>>,
+    pub sync_slo: web::Data<Reloadable<SyncSlo>>,
+    pub cohort_window: web::Data<Reloadable<CohortWindow>>,
+    pub allowlist: web::Data<Reloadable<IpAllowlist>>,
+}
+
+impl LiveConfig {
+    pub fn new(
+        compensation_rates: CompensationRates,
+        sync_slo: SyncSlo,
+        cohort_window: CohortWindow,
+        allowlist: IpAllowlist,
+    ) -> Self {
+        LiveConfig {
+            compensation_rates: web::Data::new(Reloadable::new(compensation_rates)),
+            sync_slo: web::Data::new(Reloadable::new(sync_slo)),
+            cohort_window: web::Data::new(Reloadable::new(cohort_window)),
+            allowlist: web::Data::new(Reloadable::new(allowlist)),
+        }
+    }
+
+    // Re-reads the file and applies it. Every setting is parsed before any
+    // is replaced, so an invalid value leaves the running config as it was.
+    // Returns the names of the settings applied.
+    pub fn reload(&self) -> Result<Vec<&'static str>, String> {
+        if let Err(e) = dotenvy::dotenv_override() {
+            // Without a file the environment is still re-read as it is
+            warn!("Could not re-read .env: {}", e);
+        }
+        let compensation_rates = CompensationRates::from_env()?;
+        let sync_slo = SyncSlo::from_env()?;
+        let cohort_window = CohortWindow::from_env()?;
+        let allowlist = IpAllowlist::from_env()?;
+
+        self.compensation_rates.replace(compensation_rates);
+        self.sync_slo.replace(sync_slo);
+        self.cohort_window.replace(cohort_window);
+        self.allowlist.replace(allowlist);
+        Ok(vec![
+            "compensation_rates",
+            "sync_slo",
+            "cohort_window",
+            "ip_allowlist",
+        ])
+    }
+}
+
+// Reloads on SIGHUP, the way daemons are usually asked to
+#[cfg(unix)]
+pub fn start_sighup_reload(config: LiveConfig) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    actix_web::rt::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                warn!("SIGHUP reload unavailable: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            match config.reload() {
+                Ok(applied) => {
+                    info!(target: "audit", "Config reloaded on SIGHUP: {}", applied.join(", "))
+                }
+                Err(e) => warn!(
+                    "Config reload on SIGHUP failed, keeping current config: {}",
+                    e
+                ),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn start_sighup_reload(_config: LiveConfig) {}
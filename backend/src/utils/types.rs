@@ -1,3 +1,4 @@
+use crate::utils::ids::{is_public_id, new_public_id};
 use actix_web::ResponseError;
 use rusqlite::Row;
 use serde::{Deserialize, Serialize};
@@ -10,6 +11,16 @@ pub enum AppError {
     Io(#[from] std::io::Error),
     #[error("CSV error: {0}")]
     Csv(#[from] csv::Error),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("Postgres error: {0}")]
+    Postgres(#[from] postgres::Error),
+    #[error("Blocking task error: {0}")]
+    Blocking(#[from] actix_web::error::BlockingError),
+    #[error("Invariant violated: {0}")]
+    Invariant(String),
 }
 
 // Implement ResponseError for actix-web compatibility
@@ -22,6 +33,11 @@ impl From<AppError> for std::io::Error {
             AppError::Database(e) => std::io::Error::new(std::io::ErrorKind::Other, e),
             AppError::Io(e) => e,
             AppError::Csv(e) => std::io::Error::new(std::io::ErrorKind::Other, e),
+            AppError::Encryption(e) => std::io::Error::new(std::io::ErrorKind::Other, e),
+            AppError::Pool(e) => std::io::Error::new(std::io::ErrorKind::Other, e),
+            AppError::Postgres(e) => std::io::Error::new(std::io::ErrorKind::Other, e),
+            AppError::Blocking(e) => std::io::Error::new(std::io::ErrorKind::Other, e),
+            AppError::Invariant(e) => std::io::Error::new(std::io::ErrorKind::Other, e),
         }
     }
 }
@@ -31,6 +47,38 @@ pub struct TaLogin {
     pub gmail: String,
 }
 
+// A pending invite for a new TA, stored by the hash of its token
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TaInvite {
+    pub email: String,
+    // Name the TA will have in the `ta` column of weekly rows
+    pub name: String,
+    pub invited_by: String,
+    pub created_at: String,
+    pub expires_at: String,
+}
+
+// What a TA sets up when accepting their invite
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TaSetup {
+    pub github: String,
+    pub discord: String,
+    #[serde(default)]
+    pub notify_by_email: bool,
+    #[serde(default)]
+    pub notify_by_discord: bool,
+}
+
+// A TA who joined through an invite
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TaProfile {
+    pub name: String,
+    pub email: String,
+    #[serde(flatten)]
+    pub setup: TaSetup,
+    pub onboarded_at: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RowData {
     pub name: String,
@@ -49,35 +97,340 @@ pub struct RowData {
     pub exercise_good_documentation: Option<String>,
     pub exercise_good_structure: Option<String>,
     pub total: Option<u64>,
+    // Freeform context from the TA, e.g. "had connectivity issues"
+    #[serde(default)]
+    pub notes: Option<String>,
     pub mail: String,
     pub week: i32,
+    // The participant the row belongs to, which stays the same when they
+    // are renamed. Rows from before the column was added may have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub participant_id: Option<String>,
+    // Public ids of the row and of its student, usable in URLs in place of
+    // the name. Assigned by the server; rows from before they were added
+    // get theirs at startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub student_id: Option<String>,
+    // Raised on every change, so a write based on an older copy of the row
+    // can be turned away, see `handlers::versions`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    // Commits in the week's exercise repo and when the latest was made, as
+    // of the last sync that could read them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_commit_at: Option<String>,
+    // The student's repo for the week's exercise, for graders to open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_url: Option<String>,
+}
+
+impl RowData {
+    // Whether both rows are the same student's, in any week
+    pub fn same_student(&self, other: &RowData) -> bool {
+        match (&self.participant_id, &other.participant_id) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.name == other.name,
+        }
+    }
+
+    // Whether both are the same student's row for the same week, matching
+    // on the participant where both know it so a rename is an update
+    pub fn same_row(&self, other: &RowData) -> bool {
+        self.week == other.week && self.same_student(other)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Table {
     pub rows: Vec<RowData>,
+    // Changes made by insert_or_update, not yet written to the row history
+    #[serde(skip)]
+    pending_history: Vec<RowChange>,
 }
 
 // Move the business logic to a separate implementation
 impl Table {
-    pub fn insert_or_update(&mut self, row: &RowData) -> Result<(), AppError> {
-        let existing_row = self
-            .rows
-            .iter_mut()
-            .find(|r| r.name == row.name && r.week == row.week);
+    pub fn new(rows: Vec<RowData>) -> Self {
+        Table {
+            rows,
+            pending_history: Vec::new(),
+        }
+    }
+
+    // Returns whether the table changed, i.e. the row needs to be persisted.
+    // The row's public ids are filled in: kept from the existing row, or new
+    // for a new row, as rows built from last week's carry its ids. So is its
+    // version, raised when the row changed.
+    pub fn insert_or_update(&mut self, row: &mut RowData) -> Result<bool, AppError> {
+        let changed_at = chrono::Utc::now().to_rfc3339();
+        let existing_row = self.rows.iter_mut().find(|r| r.same_row(row));
         if let Some(existing_row) = existing_row {
-            if *existing_row != *row {
-                println!("Data has changed for {} in week {}", row.name, row.week);
+            row.public_id = existing_row
+                .public_id
+                .clone()
+                .or_else(|| Some(new_public_id()));
+            row.student_id = existing_row.student_id.clone();
+            // Set by syncs with the forge; clients saving a row without
+            // them do not clear them
+            row.commit_count = row.commit_count.or(existing_row.commit_count);
+            row.last_commit_at = row
+                .last_commit_at
+                .take()
+                .or_else(|| existing_row.last_commit_at.clone());
+            row.repo_url = row
+                .repo_url
+                .take()
+                .or_else(|| existing_row.repo_url.clone());
+            let version = existing_row.version.unwrap_or(1);
+            row.version = Some(version);
+            if *existing_row == *row {
+                return Ok(false);
             }
+            row.version = Some(version + 1);
+            println!("Data has changed for {} in week {}", row.name, row.week);
+            self.pending_history
+                .extend(row_changes(Some(existing_row), row, &changed_at));
             *existing_row = row.clone();
         } else {
             println!("Inserting new row for {} in week {}", row.name, row.week);
+            row.public_id = Some(new_public_id());
+            row.version = Some(1);
+            row.student_id = self
+                .rows
+                .iter()
+                .find(|r| r.student_id.is_some() && r.same_student(row))
+                .and_then(|r| r.student_id.clone());
+            self.pending_history
+                .extend(row_changes(None, row, &changed_at));
             self.rows.push(row.clone());
         }
-        Ok(())
+        Ok(true)
+    }
+
+    // The name of the student `key` refers to: a student or row public id,
+    // or else the name itself
+    pub fn student_name(&self, key: &str) -> String {
+        if is_public_id(key)
+            && let Some(row) = self.rows.iter().find(|row| {
+                row.public_id.as_deref() == Some(key) || row.student_id.as_deref() == Some(key)
+            })
+        {
+            return row.name.clone();
+        }
+        key.to_string()
+    }
+
+    // Hands over the changes made since the last call, to be recorded
+    // together with the rows they belong to
+    pub fn take_history(&mut self) -> Vec<RowChange> {
+        std::mem::take(&mut self.pending_history)
+    }
+
+    // The current version of each row a batch is about to write, so the
+    // batch can be undone if persisting it fails
+    pub fn checkpoint(&self, rows: &[RowData]) -> Checkpoint {
+        Checkpoint(
+            rows.iter()
+                .map(|row| {
+                    let current = self.rows.iter().find(|r| r.same_row(row)).cloned();
+                    (row.name.clone(), row.week, current)
+                })
+                .collect(),
+        )
+    }
+
+    // Puts the checkpointed rows back, removing rows the batch inserted
+    pub fn revert(&mut self, checkpoint: Checkpoint) {
+        for (name, week, previous) in checkpoint.0.into_iter().rev() {
+            let position = self
+                .rows
+                .iter()
+                .position(|r| r.name == name && r.week == week);
+            match (position, previous) {
+                (Some(index), Some(previous)) => self.rows[index] = previous,
+                (Some(index), None) => {
+                    self.rows.remove(index);
+                }
+                (None, Some(previous)) => self.rows.push(previous),
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+// Rows as they were before a batch, keyed by (name, week)
+#[derive(Debug)]
+pub struct Checkpoint(Vec<(String, i32, Option<RowData>)>);
+
+// One field of a weekly row changed by `Table::insert_or_update`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowChange {
+    pub name: String,
+    pub week: i32,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_at: String,
+}
+
+// Rows written and rows deleted since a point in time
+#[derive(Debug, Default, Serialize)]
+pub struct ChangedRows {
+    pub rows: Vec<RowData>,
+    pub deleted: Vec<DeletedRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeletedRow {
+    pub name: String,
+    pub week: i32,
+}
+
+// What a bulk write did with one submitted row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Created,
+    Updated,
+    // Same as the stored row, so nothing was written
+    Unchanged,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowResult {
+    // Position of the row in the submitted batch
+    pub row: usize,
+    pub name: String,
+    pub week: i32,
+    pub status: RowStatus,
+    // Why a failed row was not written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    // Version of a written row, to send with the next write of it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+}
+
+// Rows of a bulk write per status
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct RowCounts {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+}
+
+impl RowCounts {
+    pub fn of(results: &[RowResult]) -> Self {
+        let mut counts = RowCounts::default();
+        for result in results {
+            *match result.status {
+                RowStatus::Created => &mut counts.created,
+                RowStatus::Updated => &mut counts.updated,
+                RowStatus::Unchanged => &mut counts.unchanged,
+                RowStatus::Failed => &mut counts.failed,
+            } += 1;
+        }
+        counts
     }
 }
 
+// A recorded change and who made it
+#[derive(Debug, Clone, Serialize)]
+pub struct RowHistoryEntry {
+    pub id: i64,
+    #[serde(flatten)]
+    pub change: RowChange,
+    pub actor: String,
+}
+
+// Fields of a row not kept in the history: the key, and mail, which is
+// encrypted at rest in the students table
+const UNTRACKED_FIELDS: &[&str] = &[
+    "name",
+    "week",
+    "mail",
+    "participant_id",
+    "public_id",
+    "student_id",
+    "version",
+];
+
+// Fields that differ between two versions of a row. A new row records every
+// field that is set.
+pub fn row_changes(old: Option<&RowData>, new: &RowData, changed_at: &str) -> Vec<RowChange> {
+    fn fields(row: &RowData) -> serde_json::Map<String, serde_json::Value> {
+        match serde_json::to_value(row) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => serde_json::Map::new(),
+        }
+    }
+    fn text(value: Option<&serde_json::Value>) -> Option<String> {
+        match value? {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    let old_fields = old.map(fields).unwrap_or_default();
+    fields(new)
+        .iter()
+        .filter(|(field, _)| !UNTRACKED_FIELDS.contains(&field.as_str()))
+        .filter_map(|(field, value)| {
+            let (old_value, new_value) = (text(old_fields.get(field)), text(Some(value)));
+            (old_value != new_value).then(|| RowChange {
+                name: new.name.clone(),
+                week: new.week,
+                field: field.clone(),
+                old_value,
+                new_value,
+                changed_at: changed_at.to_string(),
+            })
+        })
+        .collect()
+}
+
+// The row with the fields of `changes` set back to their old values. Values
+// are recorded as text, so a number is tried first where the field takes one.
+pub fn revert_changes(row: &RowData, changes: &[RowChange]) -> Result<RowData, AppError> {
+    let invalid = |e: serde_json::Error| AppError::Io(std::io::Error::other(e));
+    let mut fields = serde_json::to_value(row).map_err(invalid)?;
+    for change in changes {
+        let candidates = match &change.old_value {
+            None => vec![serde_json::Value::Null],
+            Some(text) => text
+                .parse::<u64>()
+                .ok()
+                .map(serde_json::Value::from)
+                .into_iter()
+                .chain([serde_json::Value::from(text.as_str())])
+                .collect(),
+        };
+        let mut reverted = None;
+        for candidate in candidates {
+            let mut attempt = fields.clone();
+            attempt[change.field.as_str()] = candidate;
+            if serde_json::from_value::<RowData>(attempt.clone()).is_ok() {
+                reverted = Some(attempt);
+                break;
+            }
+        }
+        fields = reverted.ok_or_else(|| {
+            AppError::Io(std::io::Error::other(format!(
+                "{} cannot be set back to {:?}",
+                change.field, change.old_value
+            )))
+        })?;
+    }
+    serde_json::from_value(fields).map_err(invalid)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
@@ -94,6 +447,315 @@ pub struct Member {
     pub joined_at: String,
 }
 
+impl Member {
+    pub fn discord_id(&self) -> Option<&str> {
+        self.user.as_ref().map(|u| u.id.as_str())
+    }
+
+    // Server nickname if set, otherwise the account username
+    pub fn display_name(&self) -> String {
+        self.nick
+            .clone()
+            .or_else(|| self.user.as_ref().map(|u| u.username.clone()))
+            .unwrap_or_default()
+    }
+}
+
+// A Discord user seen in the session voice channel during a week
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VoiceAttendee {
+    pub discord_id: String,
+    pub discord_name: String,
+    pub snapshots_seen: u32,
+}
+
+// Self description a participant gave when registering
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BackgroundData {
+    pub describe_yourself: String,
+    pub background: String,
+    pub skills: String,
+    pub location: String,
+    pub why: String,
+    pub year: String,
+    pub book: String,
+}
+
+// Program name shown until an admin configures the cohort branding
+pub const DEFAULT_PROGRAM_NAME: &str = "Learning Bitcoin From Command Line";
+
+// Cohort branding consumed by exports and certificates. Images are stored
+// as https URLs or `data:image/...` URIs so they can be changed without a
+// rebuild.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Branding {
+    pub program_name: String,
+    pub logo: Option<String>,
+    pub signature: Option<String>,
+    pub certificate_text: Option<String>,
+    #[serde(default)]
+    pub updated_by: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+impl Default for Branding {
+    fn default() -> Self {
+        Branding {
+            program_name: DEFAULT_PROGRAM_NAME.to_string(),
+            logo: None,
+            signature: None,
+            certificate_text: None,
+            updated_by: None,
+            updated_at: None,
+        }
+    }
+}
+
+// Channel of an outbound message sent to a participant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommunicationKind {
+    Email,
+    Reminder,
+    InviteNudge,
+    Other,
+}
+
+impl CommunicationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommunicationKind::Email => "email",
+            CommunicationKind::Reminder => "reminder",
+            CommunicationKind::InviteNudge => "invite_nudge",
+            CommunicationKind::Other => "other",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Self {
+        match kind {
+            "email" => CommunicationKind::Email,
+            "reminder" => CommunicationKind::Reminder,
+            "invite_nudge" => CommunicationKind::InviteNudge,
+            _ => CommunicationKind::Other,
+        }
+    }
+}
+
+// A message an organizer or TA sent to a participant
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Communication {
+    pub id: i64,
+    pub participant: String,
+    pub kind: CommunicationKind,
+    pub subject: String,
+    pub note: Option<String>,
+    pub sent_by: String,
+    pub sent_at: String,
+}
+
+// One fetch of a week's exercise submissions from the forge. A run fails
+// when the forge could not be reached; a week without an assignment yet
+// still counts as a successful run.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SyncRun {
+    pub week: i32,
+    pub started_at: String,
+    pub finished_at: String,
+    pub succeeded: bool,
+    pub error: Option<String>,
+}
+
+// Outcome of a VACUUM and ANALYZE of the live database. Sizes are of the
+// database as the backend reports it, in bytes.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    pub started_at: String,
+    pub finished_at: String,
+    pub size_before: u64,
+    pub size_after: u64,
+    pub reclaimed_bytes: u64,
+}
+
+// A notice broadcast to the whole cohort. Critical ones are the ones
+// organizers chase up when students have not read them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Announcement {
+    pub id: i64,
+    pub title: String,
+    pub body: String,
+    pub critical: bool,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+// Whether a pair of students must share a group or never share one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintKind {
+    Together,
+    Apart,
+}
+
+impl ConstraintKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConstraintKind::Together => "together",
+            ConstraintKind::Apart => "apart",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Self {
+        match kind {
+            "together" => ConstraintKind::Together,
+            _ => ConstraintKind::Apart,
+        }
+    }
+}
+
+// A hard grouping constraint between two participants. Pairs are stored
+// with the names in sorted order so each pair has at most one constraint.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct GroupingConstraint {
+    pub id: i64,
+    pub kind: ConstraintKind,
+    pub first: String,
+    pub second: String,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+// A state of a student's exercise repo observed during a weekly sync. Each
+// distinct `submitted_at` is a resubmission that reran CI; it is empty while
+// the repo exists but has not been submitted. `recorded_at` is when the sync
+// first saw this state.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ExerciseAttempt {
+    pub week: i32,
+    pub github: String,
+    pub submitted_at: String,
+    pub passing: bool,
+    pub recorded_at: String,
+}
+
+// Graded exercise outcomes of one week
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExerciseOutcome {
+    pub submitted: usize,
+    pub passing: usize,
+}
+
+// Grading guidance for one score of a rubric criterion, e.g. what earns
+// 2 points for communication
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RubricNote {
+    pub criterion: String,
+    pub score: u64,
+    pub note: String,
+    pub updated_by: String,
+    pub updated_at: String,
+}
+
+// Private Discord thread created for a group when the week's groups were
+// published, with the Discord ids last added to it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct GroupThread {
+    pub week: i32,
+    pub group_id: String,
+    pub thread_id: String,
+    pub members: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// What a TA noted reviewing their group's exercises for a week, for the
+// curriculum feedback report. A later submission for the group replaces it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ReviewSummary {
+    pub week: i32,
+    pub group_id: String,
+    pub ta: String,
+    pub common_mistakes: Vec<String>,
+    pub standout_solutions: Vec<String>,
+    pub notes: Option<String>,
+    pub submitted_at: String,
+}
+
+// A week closed for writes once grading is done
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WeekLock {
+    pub week: i32,
+    pub locked_by: String,
+    pub locked_at: String,
+}
+
+// A response to a write sent with an Idempotency-Key, replayed when the
+// same caller sends the same request under that key again
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct IdempotentResponse {
+    pub key: String,
+    pub actor: String,
+    // Hash of the request, so a key reused for another request is caught
+    pub fingerprint: String,
+    pub status: u16,
+    pub body: String,
+    pub created_at: String,
+}
+
+// Recurring operational step organizers complete every week
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeekTask {
+    SyncRoster,
+    GenerateGroups,
+    PublishAnnouncement,
+    SendReminders,
+    FinalizeGrades,
+}
+
+impl WeekTask {
+    // In the order they are usually done during a week
+    pub const ALL: [WeekTask; 5] = [
+        WeekTask::SyncRoster,
+        WeekTask::GenerateGroups,
+        WeekTask::PublishAnnouncement,
+        WeekTask::SendReminders,
+        WeekTask::FinalizeGrades,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeekTask::SyncRoster => "sync_roster",
+            WeekTask::GenerateGroups => "generate_groups",
+            WeekTask::PublishAnnouncement => "publish_announcement",
+            WeekTask::SendReminders => "send_reminders",
+            WeekTask::FinalizeGrades => "finalize_grades",
+        }
+    }
+
+    pub fn parse(task: &str) -> Option<Self> {
+        WeekTask::ALL.into_iter().find(|t| t.as_str() == task)
+    }
+}
+
+// Checklist entry for a week; completion fields are unset while pending
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChecklistItem {
+    pub task: WeekTask,
+    pub completed_at: Option<String>,
+    pub completed_by: Option<String>,
+}
+
+// A participant found by `GET /search`
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ParticipantMatch {
+    pub id: String,
+    pub name: String,
+    pub email: Option<String>,
+    pub github: Option<String>,
+    pub public_id: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CohortParticipant {
     pub name: String,
@@ -141,28 +803,65 @@ pub struct FeedbackResponse {
 
 impl FeedbackResponse {
     pub fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
-        Ok(FeedbackResponse {
-            timestamp: row.get::<_, String>("Timestamp").unwrap_or_default(),
-            discord_name: row.get::<_, String>("Discord_Name").unwrap_or_default(),
-            name_on_certificate: row.get::<_, String>("Name_on_certificate").unwrap_or_default(),
-            academic_background: row.get::<_, String>("Academic_background").unwrap_or_default(),
-            skills: row.get::<_, String>("Skills").unwrap_or_default(),
-            session_instructions: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Session_Instructions_]").unwrap_or_default(),
-            study_material: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Study_Material_(book_&_questions)_]").unwrap_or_default(),
-            group_discussions: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Group_Discussions]").unwrap_or_default(),
-            lounge_discussions: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Lounge_Discussions]").unwrap_or_default(),
-            deputy: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Deputy]").unwrap_or_default(),
-            teaching_assistants: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Teaching_Assistants]").unwrap_or_default(),
-            bitshala_clubs: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Bitshala_clubs]").unwrap_or_default(),
-            bitdev_meetups: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Bitdev_Meetups]").unwrap_or_default(),
-            bitspace: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Bitspace]").unwrap_or_default(),
-            fellowships: row.get::<_, String>("Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Fellowships_]").unwrap_or_default(),
-            expectations: row.get::<_, String>("What_were_your_expectations_from_the_cohort?").unwrap_or_default(),
-            improvement_ideas: row.get::<_, String>("What_could_we_do_help_you_have_a_better_Cohort_experience._Please_give_us_your_ideas,_we_need_'em.").unwrap_or_default(),
-            bitcoin_opportunities: row.get::<_, String>("What_kind_of_opportunities_do_you_wish_to_pursue_in_Bitcoin?_").unwrap_or_default(),
-            fellowship_projects: row.get::<_, String>("Any_project_amongst_our_fellowships_(https://bitshala.org/fellowship/)_excite_you?_").unwrap_or_default(),
-            ideal_project: row.get::<_, String>("(Optional)_What_would_be_your_ideal_bitcoin_project_and_your_role_in_it?_You_may_or_may_not_chose_an_existing_bitcoin/bitshala_project,_or_even_choose_your_own_project.\nP.S._-_We_have_a_few_internships_at_Bitshala_and_fellowships_at_Bitshala_incubated_projects!").unwrap_or_default(),
-            testimonial: row.get::<_, String>("(Optional)_We'd_really_appreciate_it_if_you_can_share_a_testimonial._It_might_go_at_Bitshala_website.").unwrap_or_default(),
-        })
+        Ok(FeedbackResponse::from_fields(|column| {
+            row.get::<_, String>(column).unwrap_or_default()
+        }))
+    }
+
+    // Builds a response from the sheet columns (headers with spaces replaced)
+    pub fn from_fields(field: impl Fn(&str) -> String) -> Self {
+        FeedbackResponse {
+            timestamp: field("Timestamp"),
+            discord_name: field("Discord_Name"),
+            name_on_certificate: field("Name_on_certificate"),
+            academic_background: field("Academic_background"),
+            skills: field("Skills"),
+            session_instructions: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Session_Instructions_]",
+            ),
+            study_material: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Study_Material_(book_&_questions)_]",
+            ),
+            group_discussions: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Group_Discussions]",
+            ),
+            lounge_discussions: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Lounge_Discussions]",
+            ),
+            deputy: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Deputy]",
+            ),
+            teaching_assistants: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Teaching_Assistants]",
+            ),
+            bitshala_clubs: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Bitshala_clubs]",
+            ),
+            bitdev_meetups: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Bitdev_Meetups]",
+            ),
+            bitspace: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Bitspace]",
+            ),
+            fellowships: field(
+                "Which_component_of_cohort_did_you_find_of_help_(or_not_so_much)?_[Fellowships_]",
+            ),
+            expectations: field("What_were_your_expectations_from_the_cohort?"),
+            improvement_ideas: field(
+                "What_could_we_do_help_you_have_a_better_Cohort_experience._Please_give_us_your_ideas,_we_need_'em.",
+            ),
+            bitcoin_opportunities: field(
+                "What_kind_of_opportunities_do_you_wish_to_pursue_in_Bitcoin?_",
+            ),
+            fellowship_projects: field(
+                "Any_project_amongst_our_fellowships_(https://bitshala.org/fellowship/)_excite_you?_",
+            ),
+            ideal_project: field(
+                "(Optional)_What_would_be_your_ideal_bitcoin_project_and_your_role_in_it?_You_may_or_may_not_chose_an_existing_bitcoin/bitshala_project,_or_even_choose_your_own_project.\nP.S._-_We_have_a_few_internships_at_Bitshala_and_fellowships_at_Bitshala_incubated_projects!",
+            ),
+            testimonial: field(
+                "(Optional)_We'd_really_appreciate_it_if_you_can_share_a_testimonial._It_might_go_at_Bitshala_website.",
+            ),
+        }
     }
 }
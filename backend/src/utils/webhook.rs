@@ -0,0 +1,102 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Window in which a signed payload is accepted and its nonce remembered
+const TOLERANCE_SECS: i64 = 300;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WebhookError {
+    #[error("Missing webhook signature")]
+    MissingSignature,
+    #[error("Malformed webhook signature")]
+    MalformedSignature,
+    #[error("Webhook signature does not match")]
+    InvalidSignature,
+    #[error("Webhook timestamp is outside the accepted window")]
+    StaleTimestamp,
+    #[error("Webhook payload was already delivered")]
+    Replayed,
+    #[error("Webhook payload has neither a timestamp nor a delivery id")]
+    MissingDeliveryId,
+}
+
+// An inbound payload with the signature material sent alongside it.
+// With a timestamp the signed content is `<timestamp>.<body>`, otherwise
+// the body alone (GitHub style). The nonce is a per-delivery id such as
+// `X-GitHub-Delivery`; the signature itself is used when there is none.
+// A payload without a timestamp must carry a nonce, which the receiver
+// remembers for longer than the window checked here.
+#[derive(Debug, Clone, Copy)]
+pub struct SignedPayload<'a> {
+    pub body: &'a [u8],
+    pub signature: Option<&'a str>,
+    pub timestamp: Option<i64>,
+    pub nonce: Option<&'a str>,
+}
+
+// HMAC-SHA256 verifier with replay protection, one per integration secret
+pub struct WebhookVerifier {
+    secret: Vec<u8>,
+    tolerance: Duration,
+    seen: HashMap<String, DateTime<Utc>>,
+}
+
+impl WebhookVerifier {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        WebhookVerifier {
+            secret: secret.into(),
+            tolerance: Duration::seconds(TOLERANCE_SECS),
+            seen: HashMap::new(),
+        }
+    }
+
+    pub fn verify(
+        &mut self,
+        payload: &SignedPayload,
+        now: DateTime<Utc>,
+    ) -> Result<(), WebhookError> {
+        let signature = payload.signature.ok_or(WebhookError::MissingSignature)?;
+        let hex_signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+        let expected =
+            hex::decode(hex_signature.trim()).map_err(|_| WebhookError::MalformedSignature)?;
+        if payload.timestamp.is_none() && payload.nonce.is_none() {
+            return Err(WebhookError::MissingDeliveryId);
+        }
+
+        if let Some(timestamp) = payload.timestamp {
+            let sent_at =
+                DateTime::from_timestamp(timestamp, 0).ok_or(WebhookError::StaleTimestamp)?;
+            if (now - sent_at).abs() > self.tolerance {
+                return Err(WebhookError::StaleTimestamp);
+            }
+        }
+
+        // Constant-time comparison
+        self.mac(payload.body, payload.timestamp)
+            .verify_slice(&expected)
+            .map_err(|_| WebhookError::InvalidSignature)?;
+
+        self.seen.retain(|_, expires| *expires > now);
+        let nonce = payload.nonce.unwrap_or(hex_signature).to_string();
+        if self.seen.contains_key(&nonce) {
+            return Err(WebhookError::Replayed);
+        }
+        self.seen.insert(nonce, now + self.tolerance);
+        Ok(())
+    }
+
+    fn mac(&self, body: &[u8], timestamp: Option<i64>) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        if let Some(timestamp) = timestamp {
+            mac.update(format!("{}.", timestamp).as_bytes());
+        }
+        mac.update(body);
+        mac
+    }
+}
@@ -1,7 +1,89 @@
-use backend::handlers::auth::TA;
-use backend::utils::types::RowData;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::from_fn;
+use actix_web::{App, web};
+use backend::database::encryption::FieldCipher;
+use backend::database::migrate::CORE_TABLES;
+use backend::database::operations::SqliteStorage;
+use backend::database::paths::DataPaths;
+use backend::database::pool::{create_pool, create_read_only_pool, open_connection};
+use backend::database::schema::run_migrations;
+use backend::database::storage::{StartupRetry, Storage, StorageBackend, degraded_storage};
+use backend::handlers::announcements::ReadLinks;
+use backend::handlers::auth::{
+    self, ClientInfo, LockoutTracker, MagicLinks, RevocationList, Roster, SessionStore, TA,
+};
+use backend::handlers::backfill::parse_week_range;
+use backend::handlers::backups;
+use backend::handlers::checklist;
+use backend::handlers::idempotency::{IdempotencyKey, fingerprint};
+use backend::handlers::jobs::{JobState, Jobs};
+use backend::handlers::periodic_sync::week_to_sync;
+use backend::handlers::students::{individual, weekly_data};
+use backend::handlers::two_factor::{self, TOTP_HEADER};
+use backend::handlers::versions::{IfMatch, VersionConflict, check_versions};
+use backend::handlers::week_locks::{self, WeekLocks};
+use backend::services::calibration::{calibration_report, grading_flags};
+use backend::services::cohort_window::CohortWindow;
+use backend::services::compensation::{CompensationRates, ta_workload, workload_csv};
+use backend::services::constraints::apply_constraints;
+use backend::services::curriculum::{clean_points, curriculum_report};
+use backend::services::exercises::{CohortExercises, backfilled_attempts, exercise_stats};
+use backend::services::export::{ExportBody, ExportTable, exporter};
+use backend::services::fields::FieldsQuery;
+use backend::services::forecast::{ForecastBasis, forecast_attendance};
+use backend::services::group_threads::plan_group_threads;
+use backend::services::grouping::{
+    active_tas, assign_groups, group_count, group_history, reassign_groups, rotation_tas,
+};
+use backend::services::integrity::integrity_report;
+use backend::services::languages::{language_report, normalize_languages};
+use backend::services::merge::{MergeStrategy, merge_student_rows};
+use backend::services::paging::{RowsQuery, SortField, SortOrder};
+use backend::services::program_stats::program_stats;
+use backend::services::read_model::ReadModel;
+use backend::services::scoring::{apply_exercise_result, exercise_result, student_totals};
+use backend::services::search::with_latest_weeks;
+use backend::services::submission_events::parse_submission_event;
+use backend::services::sync_slo::{SyncSlo, slo_report};
+use backend::services::validation::{row_failures, validate_rows};
+use backend::services::weekly::{
+    apply_submissions, carry_over_grades, copy_week_rows, rows_by_week,
+};
+use backend::utils::backup::{BackupPolicy, BackupReason, Backups, expired_backups};
+use backend::utils::classroom::{
+    Assignment, Backoff, CheckRun, RateLimit, backoff, ci_outcome, merge_week_assignments,
+    parse_assignment_ids, week_in_name,
+};
+use backend::utils::constants::{TA_EMAILS, get_auth_token};
+use backend::utils::discord_voice::{match_participant, parse_session_windows};
+use backend::utils::forge::{
+    CachedForge, ForgeCache, ForgeProvider, RepoConvention, SubmissionMarker, SyncWarningKind,
+    late_submissions,
+};
+use backend::utils::ids::{is_public_id, new_public_id};
+use backend::utils::ip_allowlist::IpAllowlist;
+use backend::utils::mailer::Mailer;
+use backend::utils::outbox::{Outbox, OutboxChannel};
+use backend::utils::reload::Reloadable;
+use backend::utils::types::{
+    ConstraintKind, DeletedRow, ExerciseAttempt, ExerciseOutcome, GroupThread, GroupingConstraint,
+    IdempotentResponse, ReviewSummary, RowChange, RowData, SyncRun, TaInvite, TaSetup, Table,
+    WeekLock, revert_changes, row_changes,
+};
+use backend::utils::webhook::{SignedPayload, WebhookError, WebhookVerifier};
+use hmac::{Hmac, Mac};
 use rand::seq::SliceRandom;
 use rand::{Rng, thread_rng};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// A founding TA by name
+fn ta(name: &str) -> TA {
+    TA::from_name(name).unwrap()
+}
 
 #[test]
 fn test_student_data_generation_and_sorting() {
@@ -72,8 +154,16 @@ fn test_student_data_generation_and_sorting() {
                 "no".to_string()
             }),
             total: Some(rng.gen_range(0..100)),
+            notes: None,
             mail: emails[i].to_string(),
             week: rng.gen_range(1..5),
+            participant_id: None,
+            public_id: None,
+            student_id: None,
+            version: None,
+            commit_count: None,
+            last_commit_at: None,
+            repo_url: None,
         });
     }
 
@@ -100,10 +190,10 @@ fn test_student_data_generation_and_sorting() {
         let (group_id, assigned_ta) = if row.attendance.as_deref() == Some("yes") {
             (
                 format!("Group {}", (idx / 5) + 1),
-                tas[(idx / 5) % tas.len()],
+                tas[(idx / 5) % tas.len()].clone(),
             )
         } else {
-            ("Group 6".to_string(), TA::Setu)
+            ("Group 6".to_string(), ta("Setu"))
         };
 
         println!("{} - {} - {:?}", row.name, group_id, assigned_ta);
@@ -114,3 +204,3976 @@ fn test_student_data_generation_and_sorting() {
     assert!(sorted_rows.windows(2).all(|w| w[0].total >= w[1].total));
     assert_eq!(tas.len(), 6);
 }
+
+#[test]
+fn test_ip_allowlist_matching() {
+    let allowlist = IpAllowlist::parse("10.0.0.0/8, 192.168.1.5", "").unwrap();
+    assert!(allowlist.is_enabled());
+    assert!(allowlist.allows(&"10.20.30.40".parse().unwrap()));
+    assert!(allowlist.allows(&"192.168.1.5".parse().unwrap()));
+    assert!(!allowlist.allows(&"192.168.1.6".parse().unwrap()));
+
+    // An empty allowlist lets everything through
+    let open = IpAllowlist::parse("", "").unwrap();
+    assert!(!open.is_enabled());
+    assert!(open.allows(&"8.8.8.8".parse().unwrap()));
+
+    assert!(IpAllowlist::parse("not-an-ip", "").is_err());
+    assert!(IpAllowlist::parse("", "not-a-proxy").is_err());
+
+    // Forwarded headers count only on connections from a trusted proxy, and
+    // the client is the address the proxies saw, not what it claims
+    let behind_proxy = IpAllowlist::parse("", "10.0.0.1, 10.0.1.0/24").unwrap();
+    let request = |peer: &str, header: (&str, &str)| {
+        actix_web::test::TestRequest::default()
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(header)
+            .to_http_request()
+    };
+    let ip = |s: &str| Some(s.parse::<std::net::IpAddr>().unwrap());
+    let spoofed = ("X-Forwarded-For", "1.2.3.4, 203.0.113.7, 10.0.1.5");
+    assert_eq!(
+        behind_proxy.client_ip(&request("10.0.0.1:443", spoofed)),
+        ip("203.0.113.7")
+    );
+    assert_eq!(
+        behind_proxy.client_ip(&request("198.51.100.2:443", spoofed)),
+        ip("198.51.100.2")
+    );
+    assert_eq!(
+        behind_proxy.client_ip(&request(
+            "10.0.0.1:443",
+            (
+                "Forwarded",
+                "for=1.2.3.4, for=\"[2001:db8::17]:4711\";proto=https"
+            )
+        )),
+        ip("2001:db8::17")
+    );
+    let direct = IpAllowlist::parse("", "").unwrap();
+    assert_eq!(
+        direct.client_ip(&request("10.0.0.1:443", spoofed)),
+        ip("10.0.0.1")
+    );
+}
+
+#[test]
+fn test_forge_repo_convention() {
+    let convention = RepoConvention {
+        assignment_pattern: "week-{week}".to_string(),
+        submission: SubmissionMarker::parse("branch:submission").unwrap(),
+    };
+    assert_eq!(
+        convention.submission,
+        SubmissionMarker::ProtectedBranch("submission".to_string())
+    );
+    assert_eq!(
+        convention.student_for_repo("week-3-alice", 3),
+        Some("alice".to_string())
+    );
+    assert_eq!(convention.student_for_repo("week-3-alice", 4), None);
+    assert_eq!(convention.student_for_repo("week-3-", 3), None);
+    assert_eq!(convention.repo_name(3, "alice"), "week-3-alice");
+    assert_eq!(
+        convention.student_for_repo(&convention.repo_name(5, "bob"), 5),
+        Some("bob".to_string())
+    );
+
+    let assignment = convention.to_assignment(
+        3,
+        "alice".to_string(),
+        "week-3-alice".to_string(),
+        String::new(),
+        None,
+        false,
+    );
+    assert!(!assignment.is_submitted());
+    assert_eq!(assignment.get_week_pattern(), Some(3));
+
+    assert!(SubmissionMarker::parse("release:v1").is_err());
+}
+
+#[test]
+fn test_schema_migrations_are_idempotent() {
+    let path: PathBuf = std::env::temp_dir().join(format!("schema_test_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    run_migrations(&path).unwrap();
+    run_migrations(&path).unwrap();
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    assert!(version >= 1);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_participant_lookup_indexes() {
+    let path: PathBuf = std::env::temp_dir().join(format!("index_test_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // Core tables as created by the migrate binary
+    let conn = open_connection(&path).unwrap();
+    conn.execute_batch(
+        r#"CREATE TABLE participants ("ID" TEXT PRIMARY KEY, "Name" TEXT, "Email" TEXT, "GitHub" TEXT);
+           CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, week INTEGER);
+           INSERT INTO participants VALUES ('1', 'Alice Doe', 'a@example.com', 'https://github.com/Alice-D/');
+           INSERT INTO participants VALUES ('2', 'Bob', 'b@example.com', 'bob');"#,
+    )
+    .unwrap();
+    drop(conn);
+    run_migrations(&path).unwrap();
+    run_migrations(&path).unwrap();
+
+    let storage = SqliteStorage::new(create_pool(&path).unwrap());
+    assert_eq!(
+        storage.github_to_name("alice-d").unwrap().as_deref(),
+        Some("Alice Doe")
+    );
+    assert_eq!(
+        storage.github_to_name("BOB").unwrap().as_deref(),
+        Some("Bob")
+    );
+    assert_eq!(
+        storage.github_username("alice doe").unwrap().as_deref(),
+        Some("https://github.com/Alice-D/")
+    );
+    assert_eq!(storage.github_to_name("carol").unwrap(), None);
+    let mut names = storage.read_participant_names().unwrap();
+    names.sort();
+    assert_eq!(names, vec!["Alice Doe", "Bob"]);
+
+    // Search matches any part of the name, email or GitHub, ignoring case
+    let found = |query: &str| -> Vec<String> {
+        storage
+            .search_participants(query, 25)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect()
+    };
+    assert_eq!(found("ALICE"), vec!["Alice Doe"]);
+    assert_eq!(found("example.com"), vec!["Alice Doe", "Bob"]);
+    assert_eq!(found("github.com/alice"), vec!["Alice Doe"]);
+    assert!(found("%").is_empty());
+    assert_eq!(storage.search_participants("example", 1).unwrap().len(), 1);
+
+    let rows = vec![
+        RowData {
+            participant_id: Some("2".to_string()),
+            ..graded_row("Bob", 1, "yes", 10)
+        },
+        RowData {
+            participant_id: Some("2".to_string()),
+            ..graded_row("Bob", 3, "no", 0)
+        },
+        graded_row("Someone else", 4, "yes", 5),
+    ];
+    let results = with_latest_weeks(storage.search_participants("b", 25).unwrap(), &rows);
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].latest_week, None);
+    let bob = results[1].latest_week.as_ref().unwrap();
+    assert_eq!((bob.week, bob.attendance.as_deref()), (3, Some("no")));
+
+    let conn = open_connection(&path).unwrap();
+    let plan = |sql: &str| -> String {
+        conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .join(" ")
+    };
+    assert!(
+        plan("SELECT Name FROM participants WHERE github_login = 'bob'")
+            .contains("idx_participants_github_login")
+    );
+    assert!(
+        plan("SELECT * FROM students WHERE name = 'Bob' AND week = 1")
+            .contains("idx_students_name_week")
+    );
+
+    // The migrate binary's students table has no (name, week) key
+    conn.execute_batch(
+        "INSERT INTO students (name, group_id, week) VALUES ('Bob', 'Group 1', 1), ('Bob', 'Group 2', 1), ('Bob', 'Group 1', 2);",
+    )
+    .unwrap();
+    assert_eq!(
+        storage.read_duplicate_rows().unwrap(),
+        vec![("Bob".to_string(), 1, 2)]
+    );
+    drop(conn);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
+
+#[test]
+fn test_integrity_report() {
+    let mut wrong_total = graded_row("Bob", 2, "yes", 10);
+    wrong_total.total = Some(3);
+    let rows = vec![
+        graded_row("Alice", 0, "yes", 0),
+        graded_row("Bob", 0, "yes", 0),
+        graded_row("Ghost", 0, "yes", 0),
+        graded_row("Alice", 1, "yes", 0),
+        graded_row("Bob", 1, "yes", 0),
+        graded_row("Ghost", 1, "no", 0),
+        wrong_total,
+        graded_row("Ghost", 2, "no", 0),
+    ];
+    let participants = vec!["alice".to_string(), "Bob ".to_string()];
+
+    let report = integrity_report(&rows, &participants, vec![("Bob".to_string(), 1, 2)]);
+    assert_eq!(report.rows, 8);
+    assert_eq!(report.unknown_students.len(), 1);
+    assert_eq!(report.unknown_students[0].name, "Ghost");
+    assert_eq!(report.unknown_students[0].weeks, vec![0, 1, 2]);
+    assert_eq!(report.duplicate_rows[0].count, 2);
+    assert_eq!(report.missing_rows.len(), 1);
+    assert_eq!(report.missing_rows[0].week, 2);
+    assert_eq!(report.missing_rows[0].students, vec!["Alice"]);
+    assert_eq!(report.total_mismatches.len(), 1);
+    assert_eq!(report.total_mismatches[0].total, 3);
+    assert_eq!(report.problems, 4);
+
+    let clean = integrity_report(&rows[..2], &participants, Vec::new());
+    assert_eq!(clean.problems, 0);
+}
+
+#[test]
+fn test_voice_attendance_matching() {
+    let windows = parse_session_windows("Sat 15:00-17:00, Sun 09:30-11:00").unwrap();
+    assert_eq!(windows.len(), 2);
+    assert!(parse_session_windows("Saturday afternoon").is_err());
+
+    let names = vec!["Jane_Doe".to_string(), "John Smith".to_string()];
+    let mut handles = HashMap::new();
+    handles.insert("42".to_string(), "John Smith".to_string());
+
+    assert_eq!(
+        match_participant("1", "jane doe", &handles, &names),
+        Some(&names[0])
+    );
+    assert_eq!(
+        match_participant("42", "jsmith", &handles, &names),
+        Some(&handles["42"])
+    );
+    assert_eq!(match_participant("7", "someone", &handles, &names), None);
+}
+
+#[test]
+fn test_webhook_signature_verification() {
+    let body = br#"{"action":"completed"}"#;
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+    mac.update(body);
+    let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+    let mut verifier = WebhookVerifier::new("secret");
+    let now = chrono::Utc::now();
+    let payload = SignedPayload {
+        body,
+        signature: Some(&signature),
+        timestamp: None,
+        nonce: Some("delivery-1"),
+    };
+    assert_eq!(verifier.verify(&payload, now), Ok(()));
+    assert_eq!(verifier.verify(&payload, now), Err(WebhookError::Replayed));
+
+    let tampered = SignedPayload {
+        body: b"{}",
+        nonce: Some("delivery-2"),
+        ..payload
+    };
+    assert_eq!(
+        verifier.verify(&tampered, now),
+        Err(WebhookError::InvalidSignature)
+    );
+
+    let stale = SignedPayload {
+        timestamp: Some(now.timestamp() - 3600),
+        nonce: Some("delivery-3"),
+        ..payload
+    };
+    assert_eq!(
+        verifier.verify(&stale, now),
+        Err(WebhookError::StaleTimestamp)
+    );
+
+    // Without a timestamp only a delivery id tells a replay apart
+    let anonymous = SignedPayload {
+        nonce: None,
+        ..payload
+    };
+    assert_eq!(
+        verifier.verify(&anonymous, now),
+        Err(WebhookError::MissingDeliveryId)
+    );
+
+    // Delivery ids outlive the verifier's window in the database, until
+    // they are pruned or a failed delivery is forgotten
+    let storage = SqliteStorage::in_memory().unwrap();
+    let month = chrono::Duration::days(30);
+    assert!(
+        storage
+            .record_webhook_delivery("delivery-1", now, now - month)
+            .unwrap()
+    );
+    let later = now + chrono::Duration::days(2);
+    assert!(
+        !storage
+            .record_webhook_delivery("delivery-1", later, later - month)
+            .unwrap()
+    );
+    storage.forget_webhook_delivery("delivery-1").unwrap();
+    assert!(
+        storage
+            .record_webhook_delivery("delivery-1", later, later - month)
+            .unwrap()
+    );
+    let much_later = later + chrono::Duration::days(31);
+    assert!(
+        storage
+            .record_webhook_delivery("delivery-1", much_later, much_later - month)
+            .unwrap()
+    );
+}
+
+// Name of the autograder workflow in the webhook payloads below
+const AUTOGRADER: &str = "GitHub Classroom Workflow";
+
+#[test]
+fn test_submission_events() {
+    let push = |repo: &str, login: &str| {
+        serde_json::json!({
+            "repository": { "name": repo },
+            "sender": { "login": login }
+        })
+        .to_string()
+    };
+    let event = parse_submission_event(
+        "push",
+        push("week-2-exercise-Alice", "Alice").as_bytes(),
+        AUTOGRADER,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        (event.github.as_str(), event.week, event.tests_passing),
+        ("Alice", 2, None)
+    );
+    // A TA pushing to the student's repo is no submission
+    let by_ta = push("week-2-exercise-alice", "bala");
+    assert_eq!(
+        parse_submission_event("push", by_ta.as_bytes(), AUTOGRADER).unwrap(),
+        None
+    );
+    let no_week = push("bitcoin-notes-alice", "alice");
+    assert_eq!(
+        parse_submission_event("push", no_week.as_bytes(), AUTOGRADER).unwrap(),
+        None
+    );
+
+    let run = |workflow: &str, action: &str, conclusion: &str| {
+        serde_json::json!({
+            "action": action,
+            "repository": { "name": "week_3-alice" },
+            "workflow_run": {
+                "name": workflow,
+                "conclusion": conclusion,
+                "actor": { "login": "alice" },
+                "head_sha": "abc123"
+            }
+        })
+        .to_string()
+    };
+    let graded = run(AUTOGRADER, "completed", "failure");
+    let graded = parse_submission_event("workflow_run", graded.as_bytes(), AUTOGRADER)
+        .unwrap()
+        .unwrap();
+    assert_eq!((graded.week, graded.tests_passing), (3, Some(false)));
+    assert_eq!(graded.head_sha.as_deref(), Some("abc123"));
+    let running = run(AUTOGRADER, "requested", "success");
+    assert_eq!(
+        parse_submission_event("workflow_run", running.as_bytes(), AUTOGRADER).unwrap(),
+        None
+    );
+    // Other workflows, such as a linter, do not grade the exercise
+    let lint = run("Lint", "completed", "failure");
+    assert_eq!(
+        parse_submission_event("workflow_run", lint.as_bytes(), AUTOGRADER).unwrap(),
+        None
+    );
+
+    assert_eq!(
+        parse_submission_event("ping", b"{}", AUTOGRADER).unwrap(),
+        None
+    );
+    assert!(parse_submission_event("push", b"{}", AUTOGRADER).is_err());
+}
+
+// Forge counting its week listings, failing while `down` is set
+#[derive(Default)]
+struct CountingForge {
+    fetches: std::sync::atomic::AtomicUsize,
+    down: std::sync::atomic::AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl ForgeProvider for CountingForge {
+    fn name(&self) -> &'static str {
+        "counting"
+    }
+
+    async fn fetch_week_submissions(
+        &self,
+        week: i32,
+    ) -> Result<Vec<Assignment>, backend::utils::classroom::ClassroomError> {
+        use std::sync::atomic::Ordering;
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        if self.down.load(Ordering::SeqCst) {
+            return Err(backend::utils::classroom::ClassroomError::AssignmentNotFound(week));
+        }
+        Ok(Vec::new())
+    }
+}
+
+#[test]
+fn test_forge_cache() {
+    use std::sync::atomic::Ordering;
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let inner = Arc::new(CountingForge::default());
+    let cache = Arc::new(ForgeCache::new(std::time::Duration::from_secs(60)));
+    let forge = CachedForge::new(inner.clone(), cache.clone());
+
+    runtime.block_on(async {
+        forge.fetch_week_submissions(2).await.unwrap();
+        forge.fetch_week_submissions(2).await.unwrap();
+        forge.fetch_week_submissions(3).await.unwrap();
+    });
+    assert_eq!(inner.fetches.load(Ordering::SeqCst), 2);
+
+    // Busting one week only refetches that week
+    assert_eq!(cache.bust(Some(2)), 1);
+    assert_eq!(cache.bust(Some(2)), 0);
+    runtime.block_on(async {
+        forge.fetch_week_submissions(2).await.unwrap();
+        forge.fetch_week_submissions(3).await.unwrap();
+    });
+    assert_eq!(inner.fetches.load(Ordering::SeqCst), 3);
+
+    // Failures are not cached
+    assert_eq!(cache.bust(None), 2);
+    inner.down.store(true, Ordering::SeqCst);
+    runtime.block_on(async {
+        assert!(forge.fetch_week_submissions(2).await.is_err());
+        inner.down.store(false, Ordering::SeqCst);
+        forge.fetch_week_submissions(2).await.unwrap();
+    });
+    assert_eq!(inner.fetches.load(Ordering::SeqCst), 5);
+
+    // Without a TTL every listing is fetched
+    let uncached = CachedForge::new(inner.clone(), Arc::new(ForgeCache::new(Default::default())));
+    runtime.block_on(async {
+        uncached.fetch_week_submissions(2).await.unwrap();
+        uncached.fetch_week_submissions(2).await.unwrap();
+    });
+    assert_eq!(inner.fetches.load(Ordering::SeqCst), 7);
+}
+
+#[test]
+fn test_classroom_backoff() {
+    use std::time::Duration;
+    let now = 1_700_000_000;
+    let none = RateLimit::default();
+
+    assert_eq!(backoff(200, &none, 0, now), Backoff::Done);
+    assert_eq!(backoff(404, &none, 0, now), Backoff::Done);
+    // Server errors back off exponentially, then give up
+    assert_eq!(
+        backoff(502, &none, 0, now),
+        Backoff::Retry(Duration::from_millis(500))
+    );
+    assert_eq!(
+        backoff(502, &none, 1, now),
+        Backoff::Retry(Duration::from_secs(1))
+    );
+    assert_eq!(backoff(502, &none, 2, now), Backoff::Done);
+
+    // A short rate limit is waited out, a long one reported
+    let short = RateLimit {
+        retry_after: Some(3),
+        ..none
+    };
+    assert_eq!(
+        backoff(429, &short, 0, now),
+        Backoff::Retry(Duration::from_secs(3))
+    );
+    let exhausted = RateLimit {
+        remaining: Some(0),
+        reset: Some(now + 900),
+        ..none
+    };
+    assert_eq!(backoff(403, &exhausted, 0, now), Backoff::RateLimited(900));
+    // A 403 with quota left is a permission error, not a rate limit
+    let forbidden = RateLimit {
+        remaining: Some(4000),
+        ..none
+    };
+    assert_eq!(backoff(403, &forbidden, 0, now), Backoff::Done);
+    assert_eq!(backoff(429, &none, 0, now), Backoff::RateLimited(60));
+}
+
+#[test]
+fn test_week_assignments() {
+    let ids = parse_assignment_ids("1=812582, 2=814648+901234").unwrap();
+    assert_eq!(ids[&1], vec![812582]);
+    assert_eq!(ids[&2], vec![814648, 901234]);
+    assert!(parse_assignment_ids("2=abc").is_err());
+    assert!(parse_assignment_ids("0=1").is_err());
+    assert!(parse_assignment_ids("812582").is_err());
+
+    assert_eq!(week_in_name("Week 12 quiz"), Some(12));
+    assert_eq!(week_in_name("week-2-exercise"), Some(2));
+    assert_eq!(week_in_name("Weekly quiz"), None);
+
+    let graded = |name: &str, github: &str, awarded: &str, submitted_at: &str| Assignment {
+        assignment_name: name.to_string(),
+        assignment_url: String::new(),
+        github_username: github.to_string(),
+        points_available: "10".to_string(),
+        points_awarded: awarded.to_string(),
+        roster_identifier: github.to_string(),
+        starter_code_url: String::new(),
+        student_repository_name: format!("{}-{}", name, github),
+        student_repository_url: String::new(),
+        submission_timestamp: Some(submitted_at.to_string()),
+        ci_passing: None,
+        commit_count: None,
+        last_commit_at: None,
+    };
+    let exercise = vec![
+        graded("exercise", "alice", "10", "2024-05-01T10:00:00Z"),
+        graded("exercise", "bob", "10", "2024-05-01T11:00:00Z"),
+    ];
+    let quiz = vec![
+        graded("quiz", "Alice", "10", "2024-05-02T09:00:00Z"),
+        graded("quiz", "bob", "5", "2024-05-01T09:00:00Z"),
+    ];
+
+    // A single assignment is passed through as is
+    assert_eq!(merge_week_assignments(2, vec![exercise.clone()]).len(), 2);
+
+    let merged = merge_week_assignments(2, vec![exercise.clone(), quiz]);
+    assert_eq!(merged.len(), 2);
+    let alice = &merged[0];
+    assert_eq!(alice.assignment_name, "Week 2: exercise + quiz");
+    assert_eq!(alice.get_week_pattern(), Some(2));
+    assert_eq!(alice.student_repository_name, "exercise-alice");
+    assert_eq!(alice.points_awarded, "100");
+    assert_eq!(
+        alice.submission_timestamp.as_deref(),
+        Some("2024-05-02T09:00:00Z")
+    );
+    assert_eq!(merged[1].points_awarded, "75");
+
+    // Nothing handed in for the quiz, the week is not submitted
+    let merged = merge_week_assignments(2, vec![exercise, vec![]]);
+    assert!(merged.iter().all(|a| !a.is_submitted()));
+}
+
+#[test]
+fn test_ci_outcome() {
+    let run = |status: &str, conclusion: Option<&str>| CheckRun {
+        status: status.to_string(),
+        conclusion: conclusion.map(str::to_string),
+    };
+    assert_eq!(ci_outcome(&[]), None);
+    assert_eq!(
+        ci_outcome(&[
+            run("completed", Some("success")),
+            run("completed", Some("skipped"))
+        ]),
+        Some(true)
+    );
+    assert_eq!(
+        ci_outcome(&[
+            run("completed", Some("success")),
+            run("completed", Some("failure"))
+        ]),
+        Some(false)
+    );
+    assert_eq!(ci_outcome(&[run("in_progress", None)]), None);
+
+    // Points decide only while CI is unknown
+    let mut assignment = Assignment {
+        assignment_name: "Week 1".to_string(),
+        assignment_url: String::new(),
+        github_username: "alice".to_string(),
+        points_available: "100".to_string(),
+        points_awarded: "100".to_string(),
+        roster_identifier: String::new(),
+        starter_code_url: String::new(),
+        student_repository_name: "week-1-alice".to_string(),
+        student_repository_url: String::new(),
+        submission_timestamp: Some("2024-05-01T10:00:00Z".to_string()),
+        ci_passing: None,
+        commit_count: None,
+        last_commit_at: None,
+    };
+    assert!(assignment.tests_passing());
+    assignment.ci_passing = Some(false);
+    assert!(!assignment.tests_passing());
+    assignment.points_awarded = "0".to_string();
+    assignment.ci_passing = Some(true);
+    assert!(assignment.tests_passing());
+    assert!(exercise_result(&assignment, 1).unwrap().tests_passing);
+}
+
+#[test]
+fn test_mail_field_encryption() {
+    let cipher = FieldCipher::from_hex_key(&"ab".repeat(32)).unwrap();
+    assert!(FieldCipher::from_hex_key("too-short").is_err());
+
+    let encrypted = cipher.encrypt("alice@example.com");
+    assert!(encrypted.starts_with("enc:v1:"));
+    assert!(!encrypted.contains("alice"));
+    // Deterministic so equality lookups still work
+    assert_eq!(encrypted, cipher.encrypt("alice@example.com"));
+    assert_ne!(encrypted, cipher.encrypt("bob@example.com"));
+    assert_eq!(cipher.encrypt(&encrypted), encrypted);
+
+    assert_eq!(cipher.decrypt(&encrypted).unwrap(), "alice@example.com");
+    assert_eq!(
+        cipher.decrypt("plain@example.com").unwrap(),
+        "plain@example.com"
+    );
+
+    let other = FieldCipher::from_hex_key(&"cd".repeat(32)).unwrap();
+    assert!(other.decrypt(&encrypted).is_err());
+}
+
+fn graded_row(name: &str, week: i32, attendance: &str, total: u64) -> RowData {
+    RowData {
+        name: name.to_string(),
+        group_id: "Group 1".to_string(),
+        ta: None,
+        attendance: Some(attendance.to_string()),
+        fa: Some(0),
+        fb: Some(0),
+        fc: Some(0),
+        fd: Some(0),
+        bonus_attempt: Some(0),
+        bonus_answer_quality: Some(0),
+        bonus_follow_up: Some(0),
+        exercise_submitted: Some("no".to_string()),
+        exercise_test_passing: Some("no".to_string()),
+        exercise_good_documentation: Some("no".to_string()),
+        exercise_good_structure: Some("no".to_string()),
+        total: Some(total),
+        notes: None,
+        mail: format!("{}@example.com", name.to_lowercase()),
+        week,
+        participant_id: None,
+        public_id: None,
+        student_id: None,
+        version: None,
+        commit_count: None,
+        last_commit_at: None,
+        repo_url: None,
+    }
+}
+
+#[test]
+fn test_weekly_grouping_and_totals() {
+    let mut rows: Vec<RowData> = (0..8)
+        .map(|i| graded_row(&format!("Present{}", i), 1, "yes", 10 * i))
+        .collect();
+    rows.push(graded_row("Absent0", 1, "no", 100));
+    rows.push(graded_row("Absent1", 1, "no", 0));
+
+    let tas = rotation_tas();
+    assert!(!tas.contains(&ta("Setu")));
+
+    let grouped = assign_groups(rows.clone(), 2, &tas);
+    assert_eq!(grouped.len(), 10);
+    assert!(grouped.iter().all(|r| r.week == 2));
+
+    // Attended students come first, highest total first, six to a group
+    assert_eq!(grouped[0].name, "Present7");
+    let group_size = |g: &str| grouped.iter().filter(|r| r.group_id == g).count();
+    assert_eq!(group_size("Group 1"), 6);
+    assert_eq!(group_size("Group 2"), 2);
+    for row in grouped
+        .iter()
+        .filter(|r| r.attendance.as_deref() == Some("no"))
+    {
+        assert_eq!(row.group_id, "Group 6");
+        assert_eq!(row.ta.as_deref(), Some("Setu"));
+    }
+    // TAs rotate by one group each week
+    let week_3 = assign_groups(rows.clone(), 3, &tas);
+    assert_ne!(grouped[0].ta, week_3[0].ta);
+
+    rows.push(graded_row("Present7", 2, "yes", 5));
+    let totals = student_totals(&rows);
+    assert_eq!(totals[0].name, "Absent0");
+    assert_eq!(totals[1].name, "Present7");
+    assert_eq!(totals[1].total_score, 75);
+}
+
+fn constraint(id: i64, kind: ConstraintKind, first: &str, second: &str) -> GroupingConstraint {
+    GroupingConstraint {
+        id,
+        kind,
+        first: first.to_string(),
+        second: second.to_string(),
+        created_by: "admin".to_string(),
+        created_at: String::new(),
+    }
+}
+
+#[test]
+fn test_grouping_constraints() {
+    let mut rows: Vec<RowData> = (0..10)
+        .map(|i| graded_row(&format!("Present{}", i), 1, "yes", 10 * i))
+        .collect();
+    rows.push(graded_row("Absent0", 1, "no", 100));
+    let group_of = |rows: &[RowData], name: &str| {
+        rows.iter()
+            .find(|r| r.name == name)
+            .map(|r| r.group_id.clone())
+            .unwrap()
+    };
+
+    let mut grouped = assign_groups(rows.clone(), 2, &rotation_tas());
+    assert_ne!(
+        group_of(&grouped, "Present0"),
+        group_of(&grouped, "Present9")
+    );
+    let sizes_before: Vec<usize> = ["Group 1", "Group 2"]
+        .iter()
+        .map(|g| grouped.iter().filter(|r| r.group_id == *g).count())
+        .collect();
+
+    let constraints = vec![
+        constraint(1, ConstraintKind::Together, "Present0", "Present9"),
+        constraint(2, ConstraintKind::Apart, "Present5", "Present6"),
+        // Absent students are never seated, so this does not apply
+        constraint(3, ConstraintKind::Together, "Absent0", "Present1"),
+    ];
+    assert!(apply_constraints(&mut grouped, &constraints).is_empty());
+    assert_eq!(
+        group_of(&grouped, "Present0"),
+        group_of(&grouped, "Present9")
+    );
+    assert_ne!(
+        group_of(&grouped, "Present5"),
+        group_of(&grouped, "Present6")
+    );
+    let sizes_after: Vec<usize> = ["Group 1", "Group 2"]
+        .iter()
+        .map(|g| grouped.iter().filter(|r| r.group_id == *g).count())
+        .collect();
+    assert_eq!(sizes_before, sizes_after);
+    assert_eq!(group_of(&grouped, "Absent0"), "Group 6");
+
+    // Three students that must all be apart cannot fit in two groups
+    let mut grouped = assign_groups(rows, 2, &rotation_tas());
+    let impossible = vec![
+        constraint(1, ConstraintKind::Apart, "Present1", "Present2"),
+        constraint(2, ConstraintKind::Apart, "Present2", "Present3"),
+        constraint(3, ConstraintKind::Apart, "Present1", "Present3"),
+    ];
+    let violations = apply_constraints(&mut grouped, &impossible);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].kind, ConstraintKind::Apart);
+}
+
+#[test]
+fn test_language_report() {
+    let seat = |name: &str, group: &str| RowData {
+        group_id: group.to_string(),
+        ..graded_row(name, 3, "yes", 0)
+    };
+    let rows = vec![
+        seat("Ana", "Group 1"),
+        seat("Ben", "Group 1"),
+        seat("Chen", "Group 1"),
+        seat("Dara", "Group 2"),
+        seat("Eli", "Group 2"),
+        seat("Finn", "Group 2"),
+    ];
+    let speaks = |languages: &[&str]| {
+        normalize_languages(&languages.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+    };
+    let spoken = HashMap::from([
+        ("Ana".to_string(), speaks(&["English", " spanish "])),
+        ("Ben".to_string(), speaks(&["english"])),
+        // Only speaks a language nobody else in Group 1 does
+        ("Chen".to_string(), speaks(&["Mandarin"])),
+        ("Dara".to_string(), speaks(&["mandarin", "English"])),
+        ("Eli".to_string(), speaks(&["English"])),
+    ]);
+
+    let report = language_report(&rows, 3, &spoken, &[]);
+    assert_eq!(report.groups.len(), 2);
+    assert_eq!(report.groups[0].languages.get("english"), Some(&2));
+    assert_eq!(report.groups[0].languages.get("spanish"), Some(&1));
+    assert_eq!(report.groups[1].unrecorded, 1);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(report.warnings[0].name, "Chen");
+    assert_eq!(report.suggestions.len(), 1);
+    assert_eq!(report.suggestions[0].kind, ConstraintKind::Together);
+    assert_eq!(
+        (
+            report.suggestions[0].first.as_str(),
+            report.suggestions[0].second.as_str()
+        ),
+        ("Chen", "Dara")
+    );
+
+    // A pair that already has a constraint is not suggested again
+    let existing = vec![constraint(1, ConstraintKind::Apart, "Chen", "Dara")];
+    let report = language_report(&rows, 3, &spoken, &existing);
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.suggestions.is_empty());
+}
+
+#[test]
+fn test_attendance_forecast() {
+    assert_eq!(group_count(0, 5), 0);
+    assert_eq!(group_count(13, 5), 3);
+    assert_eq!(group_count(30, 6), 5);
+    assert_eq!(group_count(31, 6), 6);
+    assert_eq!(group_count(40, 6), 6);
+
+    let current = BTreeMap::from([(0, 40), (1, 30), (2, 24)]);
+    let forecast = forecast_attendance(&current, &[], 5);
+    assert_eq!(forecast.week, 3);
+    assert_eq!(forecast.basis, ForecastBasis::CurrentCohort);
+    // Average of 0.75 and 0.8
+    assert_eq!(forecast.projected_attendance, 19);
+    assert_eq!(forecast.groups, 4);
+    assert_eq!(forecast.tas_needed, 4);
+
+    // Past cohorts that reached week 3 take precedence
+    let past = vec![
+        BTreeMap::from([(2, 20), (3, 10)]),
+        BTreeMap::from([(2, 10), (3, 5)]),
+        BTreeMap::from([(0, 10), (1, 8)]),
+    ];
+    let forecast = forecast_attendance(&current, &past, 5);
+    assert_eq!(forecast.basis, ForecastBasis::PastCohorts);
+    assert_eq!(forecast.projected_attendance, 12);
+    assert_eq!(forecast.groups, 2);
+
+    let forecast = forecast_attendance(&BTreeMap::new(), &[], 5);
+    assert_eq!(forecast.week, 1);
+    assert_eq!(forecast.basis, ForecastBasis::NoHistory);
+    assert_eq!(forecast.projected_attendance, 0);
+}
+
+#[test]
+fn test_exercise_analytics() {
+    let attempt = |github: &str, submitted_at: &str, recorded_at: &str| ExerciseAttempt {
+        week: 1,
+        github: github.to_string(),
+        submitted_at: submitted_at.to_string(),
+        passing: false,
+        recorded_at: recorded_at.to_string(),
+    };
+    let current = CohortExercises {
+        outcomes: BTreeMap::from([(
+            1,
+            ExerciseOutcome {
+                submitted: 4,
+                passing: 1,
+            },
+        )]),
+        attempts: vec![
+            // Seen unsubmitted, then submitted twice: 2 attempts after 6 hours
+            attempt("alice", "", "2024-01-01T00:00:00Z"),
+            attempt("alice", "2024-01-01T06:00:00Z", "2024-01-01T07:00:00Z"),
+            attempt("alice", "2024-01-01T09:00:00Z", "2024-01-01T10:00:00Z"),
+            // Already submitted when first seen, time unknown
+            attempt("bob", "2024-01-01T01:00:00Z", "2024-01-01T02:00:00Z"),
+            // Never submitted
+            attempt("carol", "", "2024-01-01T00:00:00Z"),
+        ],
+    };
+    let past = CohortExercises {
+        outcomes: BTreeMap::from([
+            (
+                1,
+                ExerciseOutcome {
+                    submitted: 6,
+                    passing: 5,
+                },
+            ),
+            (2, ExerciseOutcome::default()),
+        ]),
+        attempts: Vec::new(),
+    };
+
+    let stats = exercise_stats(&[current, past]);
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].week, 1);
+    assert_eq!(stats[0].cohorts, 2);
+    assert_eq!(stats[0].pass_rate, Some(0.6));
+    assert_eq!(stats[0].avg_ci_attempts, Some(1.5));
+    assert_eq!(stats[0].avg_hours_to_submission, Some(6.0));
+    assert_eq!(stats[1].pass_rate, None);
+    assert_eq!(stats[1].avg_ci_attempts, None);
+}
+
+#[test]
+fn test_sqlite_connection_settings() {
+    let path: PathBuf = std::env::temp_dir().join(format!("pragma_test_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let conn = open_connection(&path).unwrap();
+    let mode: String = conn
+        .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+        .unwrap();
+    let timeout: u32 = conn
+        .query_row("PRAGMA busy_timeout", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(mode, "wal");
+    assert_eq!(timeout, 5000);
+
+    drop(conn);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
+
+#[test]
+fn test_database_backups() {
+    let dir = std::env::temp_dir().join(format!("backup_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    run_migrations(&db).unwrap();
+
+    let policy = BackupPolicy {
+        dir: dir.join("backup"),
+        retention_days: 7,
+        keep_min: 1,
+        ..BackupPolicy::default()
+    };
+    let backups = Backups::new(Some(db.clone()), policy.clone());
+    let snapshot = backups
+        .snapshot(BackupReason::BeforeWeekDeletion)
+        .unwrap()
+        .unwrap();
+    assert!(snapshot.file.starts_with("classroom_"));
+    std::fs::write(policy.dir.join("notes.txt"), "not a backup").unwrap();
+
+    let listed = backups.list().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].file, snapshot.file);
+    assert_eq!(listed[0].reason, BackupReason::BeforeWeekDeletion);
+    assert!(listed[0].size_bytes > 0);
+
+    // Past the retention period, but the newest backup is always kept
+    let later = snapshot.created_at + chrono::Duration::days(30);
+    assert!(expired_backups(&listed, &policy, later).is_empty());
+    let mut older = listed[0].clone();
+    older.created_at -= chrono::Duration::days(1);
+    let both = vec![listed[0].clone(), older];
+    assert_eq!(expired_backups(&both, &policy, later).len(), 1);
+    assert!(expired_backups(&both, &policy, snapshot.created_at).is_empty());
+
+    // Restoring brings back the backed up data but keeps revoked tokens and
+    // offboarded TAs
+    let conn = open_connection(&db).unwrap();
+    conn.execute_batch(
+        "INSERT INTO discord_handles (discord_id, name) VALUES ('1', 'Alice');
+         INSERT INTO revoked_tokens (token_hash, revoked_by, revoked_at) VALUES ('abc', 'admin', 'now');
+         INSERT INTO inactive_tas (ta, deactivated_by, deactivated_at) VALUES ('Setu', 'admin', 'now');",
+    )
+    .unwrap();
+    drop(conn);
+    let previous = backups.restore(&listed[0]).unwrap();
+    assert_eq!(previous.reason, BackupReason::BeforeRestore);
+    let conn = open_connection(&db).unwrap();
+    let count = |table: &str| -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    };
+    assert_eq!(count("discord_handles"), 0);
+    assert_eq!(count("revoked_tokens"), 1);
+    assert_eq!(count("inactive_tas"), 1);
+    drop(conn);
+    assert_eq!(backups.list().unwrap().len(), 2);
+
+    // Exports are complete SQLite files without the TOTP secret
+    let conn = open_connection(&db).unwrap();
+    conn.execute(
+        "INSERT INTO admin_totp (id, secret, created_at) VALUES (1, 'SECRET', 'now')",
+        [],
+    )
+    .unwrap();
+    drop(conn);
+    let exported = backups.export().unwrap();
+    assert!(exported.starts_with(b"SQLite format 3\0"));
+    let copy = dir.join("exported.db");
+    std::fs::write(&copy, &exported).unwrap();
+    let conn = open_connection(&copy).unwrap();
+    let count = |table: &str| -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    };
+    assert_eq!(count("admin_totp"), 0);
+    assert_eq!(count("revoked_tokens"), 1);
+    drop(conn);
+    assert_eq!(backups.list().unwrap().len(), 2);
+
+    // Nothing to snapshot when the live cohort is not a SQLite file
+    let postgres = Backups::new(None, policy);
+    assert!(
+        postgres
+            .snapshot(BackupReason::Scheduled)
+            .unwrap()
+            .is_none()
+    );
+    assert!(postgres.list().unwrap().is_empty());
+    assert!(postgres.export().is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+#[test]
+fn test_degraded_startup() {
+    let dir = std::env::temp_dir().join(format!("degraded_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    let retry = StartupRetry {
+        attempts: 2,
+        initial_delay: std::time::Duration::from_millis(1),
+    };
+
+    // A missing file is not replaced by an empty database
+    let backend = StorageBackend::Sqlite(db.clone());
+    assert!(backend.open_with_retry(&retry).is_err());
+    assert!(!db.exists());
+
+    let policy = BackupPolicy {
+        dir: dir.join("backup"),
+        ..BackupPolicy::default()
+    };
+    let backups = Backups::new(Some(db.clone()), policy);
+    let (empty, serving) = degraded_storage(&backups).unwrap();
+    assert!(serving.is_none());
+    assert!(empty.read_from_db().unwrap().rows.is_empty());
+
+    open_connection(&db)
+        .unwrap()
+        .execute_batch(CORE_TABLES)
+        .unwrap();
+    let storage = backend.open_with_retry(&retry).unwrap();
+    storage
+        .upsert_rows(&[graded_row("Alice", 1, "yes", 7)])
+        .unwrap();
+    let snapshot = backups.snapshot(BackupReason::Scheduled).unwrap().unwrap();
+    drop(storage);
+
+    // The newest backup is served from memory, leaving the file untouched
+    let (copy, serving) = degraded_storage(&backups).unwrap();
+    assert_eq!(serving.unwrap().file, snapshot.file);
+    copy.upsert_rows(&[graded_row("Bob", 1, "yes", 3)]).unwrap();
+    assert_eq!(copy.read_from_db().unwrap().rows.len(), 2);
+    let reopened = backend.open_with_retry(&retry).unwrap();
+    assert_eq!(reopened.read_from_db().unwrap().rows.len(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_soft_delete_and_restore() {
+    let dir = std::env::temp_dir().join(format!("soft_delete_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+
+    // Created by the migrate binary, without the deleted_at column
+    let conn = open_connection(&db).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, ta TEXT, attendance TEXT, fa REAL, fb REAL, fc REAL, fd REAL, bonus_attempt REAL, bonus_answer_quality REAL, bonus_follow_up REAL, exercise_submitted TEXT, exercise_test_passing TEXT, exercise_good_documentation TEXT, exercise_good_structure TEXT, total REAL, mail TEXT, GitHub TEXT, week INTEGER);",
+    )
+    .unwrap();
+    drop(conn);
+    run_migrations(&db).unwrap();
+    run_migrations(&db).unwrap();
+
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    let rows = vec![
+        graded_row("Alice", 1, "yes", 10),
+        graded_row("Alice", 2, "yes", 12),
+    ];
+    storage.upsert_rows(&rows).unwrap();
+
+    assert_eq!(storage.delete_rows("Alice", Some(1)).unwrap(), 1);
+    assert_eq!(storage.delete_rows("Alice", Some(1)).unwrap(), 0);
+    let live = storage.read_from_db().unwrap();
+    assert_eq!(live.rows.len(), 1);
+    assert_eq!(live.rows[0].week, 2);
+
+    let deleted = storage.read_deleted_row("Alice", 1).unwrap().unwrap();
+    assert_eq!(deleted.total, Some(10));
+    assert!(storage.read_deleted_row("Alice", 2).unwrap().is_none());
+
+    assert!(storage.restore_row("Alice", 1).unwrap());
+    assert!(!storage.restore_row("Alice", 1).unwrap());
+    assert_eq!(storage.read_from_db().unwrap().rows.len(), 2);
+
+    // Writing a deleted row again brings it back with the new data
+    storage.delete_rows("Alice", None).unwrap();
+    assert!(storage.read_from_db().unwrap().rows.is_empty());
+    storage
+        .upsert_rows(&[graded_row("Alice", 2, "no", 3)])
+        .unwrap();
+    let live = storage.read_from_db().unwrap();
+    assert_eq!(live.rows.len(), 1);
+    assert_eq!(live.rows[0].total, Some(3));
+
+    // Purging also removes soft-deleted rows
+    assert_eq!(storage.purge_rows("Alice", None).unwrap(), 2);
+    assert!(storage.read_deleted_row("Alice", 1).unwrap().is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_row_history() {
+    let mut table = Table::new(Vec::new());
+    let mut row = graded_row("Alice", 1, "no", 0);
+    assert!(table.insert_or_update(&mut row).unwrap());
+    let created = table.take_history();
+    // Every set field except name, week and mail; ta is unset
+    assert_eq!(created.len(), 14);
+    assert!(created.iter().all(|c| c.old_value.is_none()));
+
+    let mut updated = row.clone();
+    updated.attendance = Some("yes".to_string());
+    updated.total = Some(5);
+    updated.mail = "new@example.com".to_string();
+    assert!(table.insert_or_update(&mut updated).unwrap());
+    assert!(!table.insert_or_update(&mut updated).unwrap());
+    let changes = table.take_history();
+    let fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+    assert_eq!(fields, vec!["attendance", "total"]);
+    assert_eq!(changes[0].old_value.as_deref(), Some("no"));
+    assert_eq!(changes[1].new_value.as_deref(), Some("5"));
+    assert!(table.take_history().is_empty());
+    assert_eq!(
+        row_changes(Some(&updated), &updated, "now"),
+        Vec::<RowChange>::new()
+    );
+
+    let dir = std::env::temp_dir().join(format!("row_history_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    run_migrations(&db).unwrap();
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    storage.write_rows(&[], &created, "sync:admin").unwrap();
+    storage.write_rows(&[], &changes, "Raj").unwrap();
+    storage.write_rows(&[], &changes[..1], "admin").unwrap();
+
+    let history = storage.read_row_history("Alice", 1).unwrap();
+    assert_eq!(history.len(), 17);
+    assert_eq!(history[0].actor, "admin");
+    assert_eq!(history[0].change.field, "attendance");
+    assert!(storage.read_row_history("Alice", 2).unwrap().is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_public_ids() {
+    let id = new_public_id();
+    assert!(is_public_id(&id));
+    assert_ne!(id, new_public_id());
+    assert!(!is_public_id("Alice Doe"));
+    assert!(!is_public_id(&id.to_lowercase()));
+
+    let mut table = Table::new(Vec::new());
+    let mut row = graded_row("Alice", 1, "no", 0);
+    table.insert_or_update(&mut row).unwrap();
+    let row_id = row.public_id.clone().unwrap();
+    assert!(is_public_id(&row_id));
+    let mut updated = graded_row("Alice", 1, "yes", 3);
+    table.insert_or_update(&mut updated).unwrap();
+    assert_eq!(updated.public_id.as_deref(), Some(row_id.as_str()));
+    assert_eq!(table.rows[0].public_id, updated.public_id);
+    assert_eq!(table.student_name(&row_id), "Alice");
+    assert_eq!(table.student_name("Bob"), "Bob");
+
+    let dir = std::env::temp_dir().join(format!("public_ids_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    let conn = open_connection(&db).unwrap();
+    conn.execute_batch(
+        r#"CREATE TABLE participants ("ID" TEXT PRIMARY KEY, "Name" TEXT, "Email" TEXT, "GitHub" TEXT);
+           CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, ta TEXT, attendance TEXT, fa REAL, fb REAL, fc REAL, fd REAL, bonus_attempt REAL, bonus_answer_quality REAL, bonus_follow_up REAL, exercise_submitted TEXT, exercise_test_passing TEXT, exercise_good_documentation TEXT, exercise_good_structure TEXT, total REAL, mail TEXT, week INTEGER);
+           INSERT INTO participants VALUES ('1', 'Alice', 'alice@example.com', 'alice');"#,
+    )
+    .unwrap();
+    drop(conn);
+    run_migrations(&db).unwrap();
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    let participant = storage.search_participants("alice", 25).unwrap().remove(0);
+    let student_id = participant.public_id.unwrap();
+    assert!(is_public_id(&student_id));
+
+    storage
+        .upsert_rows(&[RowData {
+            participant_id: Some("1".to_string()),
+            ..updated
+        }])
+        .unwrap();
+    // Migrating again keeps the ids already handed out
+    run_migrations(&db).unwrap();
+    let stored = storage.read_from_db().unwrap();
+    assert_eq!(stored.rows[0].public_id.as_deref(), Some(row_id.as_str()));
+    assert_eq!(
+        stored.rows[0].student_id.as_deref(),
+        Some(student_id.as_str())
+    );
+    assert_eq!(stored.student_name(&student_id), "Alice");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+#[test]
+fn test_row_versions() {
+    let mut table = Table::new(Vec::new());
+    let mut row = graded_row("Alice", 1, "no", 0);
+    table.insert_or_update(&mut row).unwrap();
+    assert_eq!(row.version, Some(1));
+    let mut graded = graded_row("Alice", 1, "yes", 5);
+    table.insert_or_update(&mut graded).unwrap();
+    assert_eq!(graded.version, Some(2));
+    assert!(!table.insert_or_update(&mut graded.clone()).unwrap());
+    assert_eq!(table.rows[0].version, Some(2));
+
+    // Updates must be based on the current version, new rows need none
+    let new_row = graded_row("Bob", 1, "yes", 3);
+    assert!(check_versions(&table.rows, std::slice::from_ref(&new_row)).is_ok());
+    let mut edit = graded_row("Alice", 1, "yes", 6);
+    match check_versions(&table.rows, &[edit.clone(), new_row.clone()]) {
+        Err(VersionConflict::Missing(current)) => assert_eq!(current, vec![graded.clone()]),
+        other => panic!("expected a missing version, got {:?}", other),
+    }
+    IfMatch(Some(1)).apply(std::slice::from_mut(&mut edit));
+    match check_versions(&table.rows, &[edit.clone()]) {
+        Err(VersionConflict::Stale(current)) => assert_eq!(current[0].version, Some(2)),
+        other => panic!("expected a stale version, got {:?}", other),
+    }
+    // The header does not override a version in the row, nor apply to batches
+    IfMatch(Some(2)).apply(std::slice::from_mut(&mut edit));
+    assert_eq!(edit.version, Some(1));
+    let mut batch = vec![graded_row("Alice", 1, "yes", 6), new_row];
+    IfMatch(Some(2)).apply(&mut batch);
+    assert_eq!(batch[0].version, None);
+    edit.version = Some(2);
+    assert!(check_versions(&table.rows, &[edit]).is_ok());
+
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage.upsert_rows(&[graded.clone()]).unwrap();
+    assert_eq!(storage.read_from_db().unwrap().rows[0].version, Some(2));
+    // Writes that do not know the version raise the stored one
+    storage
+        .upsert_rows(&[RowData {
+            version: None,
+            ..graded
+        }])
+        .unwrap();
+    assert_eq!(storage.read_from_db().unwrap().rows[0].version, Some(3));
+}
+
+#[test]
+fn test_announcement_reads() {
+    let links = ReadLinks::from_env();
+    let token = links.issue("Alice Doe");
+    assert_eq!(links.verify(&token).as_deref(), Some("Alice Doe"));
+    let forged = token.replacen(&hex::encode("Alice Doe"), &hex::encode("Bob"), 1);
+    assert_eq!(links.verify(&forged), None);
+    assert_eq!(links.verify("not-a-token"), None);
+
+    let dir = std::env::temp_dir().join(format!("announcements_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    run_migrations(&db).unwrap();
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    let first = storage
+        .store_announcement("Welcome", "Week 1 starts Monday", false, "admin")
+        .unwrap();
+    let second = storage
+        .store_announcement("Room change", "Use the new link", true, "admin")
+        .unwrap();
+    let listed = storage.read_announcements().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0].id, second.id);
+    assert!(listed[0].critical);
+
+    storage
+        .mark_announcements_read("Alice Doe", &[first.id])
+        .unwrap();
+    let reads = storage.read_announcement_reads().unwrap();
+    let first_read = reads[&first.id]["Alice Doe"].clone();
+    // Reading again keeps the first read time
+    storage
+        .mark_announcements_read("Alice Doe", &[first.id, second.id])
+        .unwrap();
+    let reads = storage.read_announcement_reads().unwrap();
+    assert_eq!(reads[&first.id]["Alice Doe"], first_read);
+    assert_eq!(reads[&second.id].len(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_batch_writes_are_atomic() {
+    let mut table = Table::new(vec![graded_row("Alice", 1, "no", 0)]);
+    let mut batch = vec![
+        graded_row("Alice", 1, "yes", 4),
+        graded_row("Bob", 1, "yes", 2),
+    ];
+    let checkpoint = table.checkpoint(&batch);
+    for row in &mut batch {
+        assert!(table.insert_or_update(row).unwrap());
+    }
+    let history = table.take_history();
+    table.revert(checkpoint);
+    assert_eq!(table.rows, vec![graded_row("Alice", 1, "no", 0)]);
+
+    // Without the history table the whole write is rolled back
+    let dir = std::env::temp_dir().join(format!("atomic_write_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    let conn = open_connection(&db).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, ta TEXT, attendance TEXT, fa REAL, fb REAL, fc REAL, fd REAL, bonus_attempt REAL, bonus_answer_quality REAL, bonus_follow_up REAL, exercise_submitted TEXT, exercise_test_passing TEXT, exercise_good_documentation TEXT, exercise_good_structure TEXT, total REAL, mail TEXT, week INTEGER, deleted_at TEXT, participant_id TEXT, public_id TEXT, version INTEGER NOT NULL DEFAULT 1, notes TEXT, commit_count INTEGER, last_commit_at TEXT, repo_url TEXT);",
+    )
+    .unwrap();
+    drop(conn);
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    assert!(storage.write_rows(&batch, &history, "admin").is_err());
+    assert!(storage.read_from_db().unwrap().rows.is_empty());
+
+    run_migrations(&db).unwrap();
+    storage.write_rows(&batch, &history, "admin").unwrap();
+    assert_eq!(storage.read_from_db().unwrap().rows.len(), 2);
+    assert_eq!(
+        storage.read_row_history("Bob", 1).unwrap().len(),
+        history.iter().filter(|c| c.name == "Bob").count()
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_read_model_snapshot() {
+    let mut rows = vec![
+        graded_row("Alice", 0, "yes", 0),
+        graded_row("Bob", 0, "yes", 0),
+        graded_row("Alice", 1, "yes", 12),
+        graded_row("Bob", 1, "no", 0),
+    ];
+    let model = ReadModel::new(&rows);
+    let stats = model.stats();
+    assert_eq!(stats.student_count, 2);
+    assert_eq!(stats.attended_by_week.get(&1), Some(&1));
+    assert_eq!(stats.leaderboard[0].name, "Alice");
+
+    // Readers keep the snapshot they took until the next refresh
+    rows.push(graded_row("Bob", 2, "yes", 20));
+    model.refresh(&rows);
+    assert_eq!(stats.leaderboard[0].name, "Alice");
+    let refreshed = model.stats();
+    assert_eq!(refreshed.leaderboard[0].name, "Bob");
+    assert_eq!(refreshed.attended_by_week.get(&2), Some(&1));
+}
+
+#[test]
+fn test_data_paths() {
+    let paths = DataPaths::default();
+    assert_eq!(paths.cohort_db("BPD"), PathBuf::from("./classroom_bpd.db"));
+    assert_eq!(paths.backup_dir(), PathBuf::from("./backup"));
+
+    // Cohort archives follow the live database, backups the data directory
+    let paths = DataPaths {
+        database: PathBuf::from("/srv/db/classroom.db"),
+        data_dir: PathBuf::from("/mnt/data"),
+    };
+    assert_eq!(
+        paths.cohort_db("pb"),
+        PathBuf::from("/srv/db/classroom_pb.db")
+    );
+    assert_eq!(paths.backup_dir(), PathBuf::from("/mnt/data/backup"));
+}
+
+#[test]
+fn test_ta_compensation() {
+    let row = |name: &str, week: i32, attendance: &str, group: &str, ta: &str| RowData {
+        group_id: group.to_string(),
+        ta: Some(ta.to_string()),
+        ..graded_row(name, week, attendance, 0)
+    };
+    let rows = vec![
+        // Enrollment rows are not sessions
+        row("Alice", 0, "yes", "Group 1", "Bala"),
+        row("Alice", 1, "yes", "Group 1", "Bala"),
+        row("Bob", 1, "yes", "Group 1", "Bala"),
+        row("Carol", 1, "yes", "Group 2", "Delcin"),
+        row("Alice", 2, "yes", "Group 1", "Bala"),
+        // Absent students are neither graded nor make a session
+        row("Bob", 2, "no", "Group 2", "Delcin"),
+        row("Carol", 2, "no", "Group 6", "Setu"),
+    ];
+    let rates = CompensationRates {
+        session_hours: 2.0,
+        grading_minutes_per_student: 15.0,
+        hourly_rate: 20.0,
+        currency: "EUR".to_string(),
+    };
+
+    let workload = ta_workload(&rows, &rates);
+    assert_eq!(workload.len(), 2);
+    assert_eq!(workload[0].ta, "Bala");
+    assert_eq!(workload[0].sessions, 2);
+    assert_eq!(workload[0].students_graded, 3);
+    assert_eq!(workload[0].hours, 4.75);
+    assert_eq!(workload[0].amount, 95.0);
+    assert_eq!(workload[1].ta, "Delcin");
+    assert_eq!(workload[1].sessions, 1);
+    assert_eq!(workload[1].hours, 2.25);
+
+    let csv = workload_csv(&workload, &rates).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "ta,sessions,students_graded,hours,hourly_rate,amount,currency"
+    );
+    assert_eq!(lines[1], "Bala,2,3,4.75,20.00,95.00,EUR");
+    assert_eq!(lines.len(), 3);
+}
+#[test]
+fn test_exports() {
+    let mut alice = graded_row("Alice | A.", 2, "yes", 9);
+    alice.ta = Some("Raj".to_string());
+    let rows = vec![alice, graded_row("Bob, Jr.", 2, "no", 0)];
+    let table = ExportTable::from_items(&["name", "ta", "total", "week"], &rows).unwrap();
+    assert_eq!(table.columns, vec!["name", "ta", "total", "week"]);
+    assert_eq!(table.rows[1][1], serde_json::Value::Null);
+
+    let err = table.clone().select("name,grade").unwrap_err();
+    assert!(err.contains("\"grade\""));
+    let table = table.select(" total, name ").unwrap();
+    assert_eq!(table.columns, vec!["total", "name"]);
+    let export = |format: &str| {
+        ExportBody::new(exporter(format).unwrap(), table.clone())
+            .into_string()
+            .unwrap()
+    };
+
+    assert_eq!(export("csv"), "total,name\n9,Alice | A.\n0,\"Bob, Jr.\"\n");
+    let json: serde_json::Value = serde_json::from_str(&export("json")).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            { "total": 9, "name": "Alice | A." },
+            { "total": 0, "name": "Bob, Jr." }
+        ])
+    );
+    assert!(export("json").starts_with("[{\"total\":9,"));
+    assert_eq!(
+        export("markdown"),
+        "| total | name |\n| --- | --- |\n| 9 | Alice \\| A. |\n| 0 | Bob, Jr. |\n"
+    );
+    assert!(exporter("parquet").is_none());
+
+    let empty = ExportTable::from_items::<RowData>(&["name"], &[]).unwrap();
+    let json = ExportBody::new(exporter("json").unwrap(), empty)
+        .into_string()
+        .unwrap();
+    assert_eq!(json, "[]");
+}
+
+#[test]
+fn test_group_thread_plan() {
+    let row = |name: &str, group: &str| RowData {
+        group_id: group.to_string(),
+        ta: Some("Bala".to_string()),
+        ..graded_row(name, 2, "yes", 0)
+    };
+    let rows = vec![
+        row("Alice", "Group 1"),
+        row("Bob", "Group 1"),
+        row("Carol", "Group 2"),
+    ];
+    let handles: HashMap<String, String> =
+        [("1", "Alice"), ("2", "Bob"), ("3", "Carol"), ("9", "Bala")]
+            .into_iter()
+            .map(|(id, name)| (id.to_string(), name.to_string()))
+            .collect();
+
+    // First publish creates every thread with all members and the TA
+    let plans = plan_group_threads(&rows, &handles, &[]);
+    assert_eq!(plans.len(), 2);
+    assert!(plans.iter().all(|plan| plan.thread_id.is_none()));
+    assert_eq!(plans[0].ta.as_deref(), Some("Bala"));
+    assert_eq!(plans[0].add, vec!["1", "2", "9"]);
+    assert!(plans[0].remove.is_empty());
+
+    // Regrouped: Bob moved to Group 2 and Dave has no Discord handle
+    let existing: Vec<GroupThread> = plans
+        .iter()
+        .map(|plan| GroupThread {
+            week: 2,
+            group_id: plan.group_id.clone(),
+            thread_id: format!("thread-{}", plan.group_id),
+            members: plan.members.clone(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        })
+        .collect();
+    let rows = vec![
+        row("Alice", "Group 1"),
+        row("Dave", "Group 1"),
+        row("Bob", "Group 2"),
+        row("Carol", "Group 2"),
+    ];
+    let plans = plan_group_threads(&rows, &handles, &existing);
+    assert_eq!(plans[0].thread_id.as_deref(), Some("thread-Group 1"));
+    assert!(plans[0].add.is_empty());
+    assert_eq!(plans[0].remove, vec!["2"]);
+    assert_eq!(plans[0].unmapped, vec!["Dave"]);
+    assert_eq!(plans[1].add, vec!["2"]);
+    assert!(plans[1].remove.is_empty());
+
+    // Once applied, publishing the same groups again changes nothing
+    let applied: Vec<GroupThread> = existing
+        .into_iter()
+        .zip(&plans)
+        .map(|(thread, plan)| GroupThread {
+            members: plan.members.clone(),
+            ..thread
+        })
+        .collect();
+    let again = plan_group_threads(&rows, &handles, &applied);
+    assert!(
+        again
+            .iter()
+            .all(|plan| plan.add.is_empty() && plan.remove.is_empty())
+    );
+}
+
+#[test]
+fn test_ta_offboarding() {
+    let inactive = ["Bala".to_string()].into_iter().collect();
+    let remaining = active_tas(&inactive);
+    assert!(!remaining.contains(&ta("Bala")));
+    assert_eq!(remaining.len(), rotation_tas().len() - 1);
+
+    let row = |name: &str, group: &str, ta: TA| RowData {
+        group_id: group.to_string(),
+        ta: Some(ta.name()),
+        ..graded_row(name, 3, "yes", 0)
+    };
+    let rows = vec![
+        row("Alice", "Group 1", ta("Bala")),
+        row("Bob", "Group 1", ta("Bala")),
+        row("Carol", "Group 1", ta("Bala")),
+        row("Dave", "Group 2", ta("Raj")),
+        row("Erin", "Group 3", ta("AnmolSharma")),
+        row("Frank", "Group 4", ta("Bala")),
+    ];
+
+    // Each group moves whole to a TA without groups, in rotation order
+    let changed = reassign_groups(&rows, &ta("Bala"), &remaining);
+    assert_eq!(changed.len(), 4);
+    let ta_of = |name: &str| {
+        changed
+            .iter()
+            .find(|row| row.name == name)
+            .and_then(|row| row.ta.clone())
+    };
+    assert_eq!(ta_of("Alice"), Some(ta("Delcin").name()));
+    assert_eq!(ta_of("Carol"), Some(ta("Delcin").name()));
+    assert_eq!(ta_of("Frank"), Some(ta("Beulah").name()));
+    assert!(changed.iter().all(|row| row.group_id != "Group 2"));
+
+    // Nothing to hand over once the TA leads no groups
+    assert!(reassign_groups(&rows, &ta("Beulah"), &remaining).is_empty());
+}
+
+#[test]
+fn test_sync_slo() {
+    let now = chrono::Utc::now();
+    let ago = |hours: i64| (now - chrono::Duration::hours(hours)).to_rfc3339();
+    let run = |week: i32, hours: i64, error: Option<&str>| SyncRun {
+        week,
+        started_at: ago(hours),
+        finished_at: ago(hours),
+        succeeded: error.is_none(),
+        error: error.map(str::to_string),
+    };
+
+    let dir = std::env::temp_dir().join(format!("sync_slo_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    run_migrations(&db).unwrap();
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    for run in [
+        // Week 1's last success predates the window but still sets staleness
+        run(1, 400, None),
+        run(1, 30, Some("rate limited")),
+        run(2, 10, None),
+        run(2, 4, None),
+        run(3, 2, Some("forge down")),
+    ] {
+        storage.record_sync_run(&run).unwrap();
+    }
+    let runs = storage.read_sync_runs().unwrap();
+    assert_eq!(runs.len(), 5);
+    assert_eq!(runs[0].week, 1);
+    assert_eq!(runs[4].error.as_deref(), Some("forge down"));
+
+    let slo = SyncSlo {
+        window_hours: 48,
+        target: 0.5,
+        freshness_budget_mins: 600,
+    };
+    let report = slo_report(&runs, &slo, now);
+    assert_eq!(report.runs, 4);
+    assert_eq!(report.failures, 2);
+    assert_eq!(report.success_rate, Some(0.5));
+    assert_eq!(report.error_budget_remaining, Some(0.0));
+    assert_eq!(report.weeks.len(), 3);
+    assert_eq!(report.weeks[0].staleness_mins, Some(400 * 60));
+    assert_eq!(report.weeks[0].failures_since_success, 1);
+    assert_eq!(report.weeks[1].staleness_mins, Some(4 * 60));
+    assert_eq!(report.weeks[2].last_success, None);
+    assert_eq!(report.mean_staleness_mins, Some(202.0 * 60.0));
+    assert_eq!(report.last_error.as_deref(), Some("forge down"));
+    // Over the freshness budget, and week 3 never synced
+    assert_eq!(report.alerts.len(), 2);
+
+    let relaxed = SyncSlo {
+        freshness_budget_mins: 400 * 60,
+        ..slo
+    };
+    assert_eq!(slo_report(&runs, &relaxed, now).alerts.len(), 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_group_history() {
+    let row = |name: &str, week: i32, attendance: &str, group: &str, ta: &str| RowData {
+        group_id: group.to_string(),
+        ta: Some(ta.to_string()),
+        ..graded_row(name, week, attendance, 0)
+    };
+    let rows = vec![
+        row("Alice", 0, "yes", "Group 1", "Bala"),
+        row("Carol", 2, "yes", "Group 1", "Delcin"),
+        row("Alice", 2, "no", "Group 6", "Setu"),
+        row("Dave", 2, "no", "Group 6", "Setu"),
+        row("Alice", 1, "yes", "Group 1", "Bala"),
+        row("Bob", 1, "yes", "Group 1", "Bala"),
+        row("Carol", 1, "yes", "Group 2", "Delcin"),
+    ];
+
+    let history = group_history(&rows, "Alice");
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].week, 1);
+    assert_eq!(history[0].ta.as_deref(), Some("Bala"));
+    assert!(history[0].present);
+    assert_eq!(history[0].groupmates, vec!["Bob".to_string()]);
+    // Absent students are not grouped with each other
+    assert_eq!(history[1].group_id, "Group 6");
+    assert!(!history[1].present);
+    assert!(history[1].groupmates.is_empty());
+    assert!(group_history(&rows, "Eve").is_empty());
+}
+
+#[test]
+fn test_participant_link() {
+    let dir = std::env::temp_dir().join(format!("participant_link_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("classroom.db");
+    let conn = open_connection(&path).unwrap();
+    conn.execute_batch(
+        r#"CREATE TABLE participants ("ID" TEXT PRIMARY KEY, "Name" TEXT, "Email" TEXT, "GitHub" TEXT);
+           CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, ta TEXT, attendance TEXT, fa REAL, fb REAL, fc REAL, fd REAL, bonus_attempt REAL, bonus_answer_quality REAL, bonus_follow_up REAL, exercise_submitted TEXT, exercise_test_passing TEXT, exercise_good_documentation TEXT, exercise_good_structure TEXT, total REAL, mail TEXT, GitHub TEXT, week INTEGER);
+           INSERT INTO participants VALUES ('1', 'Alice', 'a@example.com', 'https://github.com/alice');
+           INSERT INTO students (name, group_id, mail, week) VALUES ('alice', 'Group 1', '', 0);
+           INSERT INTO students (name, group_id, mail, week) VALUES ('Nobody', 'Group 1', '', 0);"#,
+    )
+    .unwrap();
+    drop(conn);
+    run_migrations(&path).unwrap();
+
+    let storage = SqliteStorage::new(create_pool(&path).unwrap());
+    let linked = |storage: &SqliteStorage| {
+        let mut rows = storage.read_from_db().unwrap().rows;
+        rows.sort_by(|a, b| a.name.cmp(&b.name));
+        rows.into_iter()
+            .map(|row| (row.name, row.participant_id))
+            .collect::<Vec<_>>()
+    };
+    // Linked by name regardless of case, and renamed to the participant's name
+    assert_eq!(
+        linked(&storage),
+        vec![
+            ("Alice".to_string(), Some("1".to_string())),
+            ("Nobody".to_string(), None)
+        ]
+    );
+
+    // Renaming the participant renames their rows on the next start
+    open_connection(&path)
+        .unwrap()
+        .execute("UPDATE participants SET Name = 'Alice Smith'", [])
+        .unwrap();
+    run_migrations(&path).unwrap();
+    assert_eq!(linked(&storage)[0].0, "Alice Smith");
+
+    // A row written under another name updates the linked row
+    let mut table = storage.read_from_db().unwrap();
+    let mut row = table
+        .rows
+        .iter()
+        .find(|row| row.participant_id.is_some())
+        .unwrap()
+        .clone();
+    row.name = "Alice S.".to_string();
+    row.attendance = Some("yes".to_string());
+    assert!(table.insert_or_update(&mut row).unwrap());
+    assert_eq!(table.rows.len(), 2);
+    storage.upsert_rows(&[row]).unwrap();
+    assert_eq!(linked(&storage).len(), 2);
+    assert_eq!(linked(&storage)[0].0, "Alice S.");
+    assert_eq!(
+        storage.github_username("Alice S.").unwrap().as_deref(),
+        Some("https://github.com/alice")
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_vacuum_and_analyze() {
+    let dir = std::env::temp_dir().join(format!("vacuum_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    run_migrations(&db).unwrap();
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    let error = "x".repeat(4096);
+    for week in 0..200 {
+        storage
+            .record_sync_run(&SyncRun {
+                week,
+                started_at: String::new(),
+                finished_at: String::new(),
+                succeeded: false,
+                error: Some(error.clone()),
+            })
+            .unwrap();
+    }
+    open_connection(&db)
+        .unwrap()
+        .execute("DELETE FROM sync_runs", [])
+        .unwrap();
+
+    let report = storage.vacuum_and_analyze().unwrap();
+    assert!(report.reclaimed_bytes > 200 * 4096);
+    assert_eq!(
+        report.size_before - report.size_after,
+        report.reclaimed_bytes
+    );
+    assert!(storage.read_sync_runs().unwrap().is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_outbox() {
+    let outbox = Outbox::new(2);
+    let first = outbox.capture(
+        OutboxChannel::Email,
+        "alice@example.com",
+        Some("Welcome"),
+        "Hi",
+    );
+    outbox.capture(
+        OutboxChannel::Discord,
+        "channels/1/messages",
+        None,
+        "Week 1",
+    );
+    let last = outbox.capture(OutboxChannel::Discord, "threads/2/messages", None, "Hello");
+    assert!(last > first);
+
+    // Newest first, and only the most recent ones are kept
+    let messages = outbox.messages();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].id, last);
+    assert_eq!(messages[0].to, "threads/2/messages");
+    assert_eq!(messages[1].body, "Week 1");
+    assert!(messages.iter().all(|m| m.id != first));
+}
+
+#[test]
+fn test_reloadable_setting() {
+    let rates = Reloadable::new(CompensationRates::default());
+    let before = rates.get();
+    rates.replace(CompensationRates {
+        hourly_rate: 25.0,
+        ..CompensationRates::default()
+    });
+    // A request holding the old value keeps it until it finishes
+    assert_eq!(before.hourly_rate, 0.0);
+    assert_eq!(rates.get().hourly_rate, 25.0);
+}
+
+#[test]
+fn test_read_only_pool() {
+    let dir = std::env::temp_dir().join(format!("read_only_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    run_migrations(&db).unwrap();
+    let reader = create_read_only_pool(&db).unwrap();
+    let storage = SqliteStorage::new(create_pool(&db).unwrap()).with_reader(reader.clone());
+
+    // Writes go through the read-write pool and are seen by the reads
+    storage.deactivate_ta("Bob", "admin").unwrap();
+    assert!(storage.read_inactive_tas().unwrap().contains("Bob"));
+
+    let conn = reader.get().unwrap();
+    assert!(conn.execute("DELETE FROM inactive_tas", []).is_err());
+    assert!(storage.read_inactive_tas().unwrap().contains("Bob"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_exercise_results_split() {
+    let dir = std::env::temp_dir().join(format!("exercise_results_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("classroom.db");
+    let conn = open_connection(&path).unwrap();
+    conn.execute_batch(
+        r#"CREATE TABLE participants ("ID" TEXT PRIMARY KEY, "Name" TEXT, "Email" TEXT, "GitHub" TEXT);
+           CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, ta TEXT, attendance TEXT, fa REAL, fb REAL, fc REAL, fd REAL, bonus_attempt REAL, bonus_answer_quality REAL, bonus_follow_up REAL, exercise_submitted TEXT, exercise_test_passing TEXT, exercise_good_documentation TEXT, exercise_good_structure TEXT, total REAL, mail TEXT, GitHub TEXT, week INTEGER);
+           INSERT INTO participants VALUES ('1', 'Alice', 'a@example.com', 'https://github.com/alice');
+           INSERT INTO students (name, group_id, exercise_submitted, mail, week) VALUES ('Alice', 'Group 1', 'yes', '', 1);
+           INSERT INTO students (name, group_id, exercise_submitted, mail, week) VALUES ('Nobody', 'Group 1', 'yes', '', 1);"#,
+    )
+    .unwrap();
+    drop(conn);
+    run_migrations(&path).unwrap();
+
+    // Linked rows keep their exercise fields in exercise_results only
+    let stored = |name: &str| -> (Option<String>, Option<String>) {
+        let conn = open_connection(&path).unwrap();
+        let column = conn
+            .query_row(
+                "SELECT exercise_submitted FROM students WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let result = conn
+            .query_row(
+                "SELECT e.submitted FROM exercise_results e JOIN students s ON s.public_id = e.student_id WHERE s.name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .ok();
+        (column, result)
+    };
+    assert_eq!(stored("Alice"), (None, Some("yes".to_string())));
+    assert_eq!(stored("Nobody"), (Some("yes".to_string()), None));
+
+    // Reads join them back into the row
+    let storage = SqliteStorage::new(create_pool(&path).unwrap());
+    let mut rows = storage.read_from_db().unwrap().rows;
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    assert!(
+        rows.iter()
+            .all(|row| row.exercise_submitted.as_deref() == Some("yes"))
+    );
+
+    let mut alice = rows[0].clone();
+    alice.exercise_submitted = Some("no".to_string());
+    storage.upsert_rows(&[alice]).unwrap();
+    assert_eq!(stored("Alice"), (None, Some("no".to_string())));
+    let alice = storage.read_from_db().unwrap().rows;
+    assert!(
+        alice
+            .iter()
+            .any(|row| row.name == "Alice" && row.exercise_submitted.as_deref() == Some("no"))
+    );
+
+    storage.purge_rows("Alice", None).unwrap();
+    let conn = open_connection(&path).unwrap();
+    let left: i64 = conn
+        .query_row("SELECT COUNT(*) FROM exercise_results", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(left, 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cohort_window() {
+    let date = |s: &str| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    let window = CohortWindow {
+        start: Some(date("2025-03-03")),
+        end: Some(date("2025-04-27")),
+        enforce: true,
+        max_week: Some(8),
+    };
+    assert_eq!(window.week_start(1), Some(date("2025-03-03")));
+    assert_eq!(window.week_start(3), Some(date("2025-03-17")));
+
+    // During week 2 the next week can be prepared, the one after cannot
+    let today = date("2025-03-12");
+    assert!(window.check(0, today).is_ok());
+    assert!(window.check(1, today).is_ok());
+    assert!(window.check(3, today).is_ok());
+    assert!(window.check(4, today).is_err());
+    // Weeks past the end never open
+    assert!(window.check(9, date("2025-04-25")).is_err());
+    // Once the cohort ends every week is archived
+    assert!(window.check(0, date("2025-04-28")).is_err());
+    assert!(window.check(8, date("2025-04-28")).is_err());
+
+    let unenforced = CohortWindow {
+        enforce: false,
+        ..window
+    };
+    assert!(unenforced.check(8, date("2026-01-01")).is_ok());
+
+    // The last week bounds the weeks either way
+    assert!(unenforced.check_range(8).is_ok());
+    let err = unenforced.check_range(19).unwrap_err();
+    assert!(err.contains("last week is 8"), "{}", err);
+    assert!(unenforced.check_range(-1).is_err());
+    assert!(CohortWindow::default().check_range(19).is_ok());
+}
+
+#[test]
+fn test_program_stats() {
+    let mut rows: Vec<RowData> = (0..4)
+        .map(|i| graded_row(&format!("Student{}", i), 0, "no", 0))
+        .collect();
+    for week in 1..=4 {
+        for i in 0..4 {
+            // Student3 only attends the first week
+            let present = i < 3 || week == 1;
+            let mut row = graded_row(
+                &format!("Student{}", i),
+                week,
+                if present { "yes" } else { "no" },
+                0,
+            );
+            if present && i == 0 {
+                row.exercise_submitted = Some("yes".to_string());
+            }
+            rows.push(row);
+        }
+    }
+    let mut locations: Vec<String> = (0..5).map(|_| "Pune, India".to_string()).collect();
+    locations.push("Berlin, germany".to_string());
+    locations.push("  ".to_string());
+
+    let stats = program_stats(&rows, &locations);
+    assert_eq!(stats.participants, 4);
+    assert_eq!(stats.weeks_held, 4);
+    assert_eq!(stats.completion_rate, Some(0.75));
+    assert_eq!(stats.exercises_submitted, 4);
+    // Germany has too few participants to be named
+    assert_eq!(stats.countries_represented, 2);
+    assert_eq!(stats.countries.len(), 1);
+    assert_eq!(stats.countries[0].country, "India");
+    assert_eq!(stats.countries[0].participants, 5);
+
+    assert_eq!(program_stats(&[], &[]).completion_rate, None);
+}
+
+#[test]
+fn test_in_memory_storage() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    let other = SqliteStorage::in_memory().unwrap();
+    assert!(storage.read_from_db().unwrap().rows.is_empty());
+
+    let rows = vec![
+        graded_row("Alice", 0, "no", 0),
+        graded_row("Alice", 1, "yes", 7),
+    ];
+    storage.upsert_rows(&rows).unwrap();
+    let mut stored = storage.read_from_db().unwrap().rows;
+    stored.sort_by_key(|row| row.week);
+    // Rows written without a public id or version are given them
+    assert!(stored.iter().all(|row| row.public_id.is_some()));
+    assert!(stored.iter().all(|row| row.version == Some(1)));
+    for row in &mut stored {
+        row.public_id = None;
+        row.version = None;
+    }
+    assert_eq!(stored, rows);
+
+    // Every instance is a database of its own
+    assert!(other.read_from_db().unwrap().rows.is_empty());
+    storage.deactivate_ta("Bob", "admin").unwrap();
+    assert!(other.read_inactive_tas().unwrap().is_empty());
+}
+
+#[test]
+fn test_rows_paging() {
+    let rows: Vec<RowData> = ["dave", "Alice", "carol", "Bob", "eve"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| graded_row(name, 1, "yes", (i as u64 % 2) * 10))
+        .collect();
+
+    // Without paging every row comes back, in stored order
+    let (all, meta) = RowsQuery::default().apply(&rows).unwrap();
+    assert_eq!(all, rows);
+    assert_eq!((meta.total, meta.page, meta.pages), (5, 1, 1));
+
+    let query = RowsQuery {
+        page: Some(2),
+        per_page: Some(2),
+        sort_by: Some(SortField::Name),
+        order: SortOrder::Asc,
+        ..RowsQuery::default()
+    };
+    let (page, meta) = query.apply(&rows).unwrap();
+    let names: Vec<&str> = page.iter().map(|row| row.name.as_str()).collect();
+    assert_eq!(names, vec!["carol", "dave"]);
+    assert_eq!((meta.total, meta.per_page, meta.pages), (5, 2, 3));
+
+    // Ties are broken by name
+    let query = RowsQuery {
+        sort_by: Some(SortField::Total),
+        order: SortOrder::Desc,
+        ..RowsQuery::default()
+    };
+    let (sorted, _) = query.apply(&rows).unwrap();
+    let names: Vec<&str> = sorted.iter().map(|row| row.name.as_str()).collect();
+    assert_eq!(names, vec!["Alice", "Bob", "carol", "dave", "eve"]);
+
+    let past_end = RowsQuery {
+        page: Some(9),
+        ..RowsQuery::default()
+    };
+    assert!(past_end.apply(&rows).unwrap().0.is_empty());
+    assert!(
+        RowsQuery {
+            page: Some(0),
+            ..RowsQuery::default()
+        }
+        .apply(&rows)
+        .is_err()
+    );
+}
+
+#[test]
+fn test_rows_filtering() {
+    let row =
+        |name: &str, group: &str, ta: &str, attendance: &str, submitted: Option<&str>| RowData {
+            group_id: group.to_string(),
+            ta: Some(ta.to_string()),
+            exercise_submitted: submitted.map(str::to_string),
+            ..graded_row(name, 1, attendance, 0)
+        };
+    let rows = vec![
+        row("Ana", "Group 1", "Bala", "yes", Some("yes")),
+        row("Ben", "Group 1", "Bala", "yes", Some("no")),
+        row("Cai", "Group 3", "Raj", "yes", None),
+        row("Dee", "Group 3", "Raj", "no", None),
+        row("Eli", "Group 10", "Raj", "yes", Some("yes")),
+    ];
+    let names = |query: RowsQuery| {
+        let (rows, meta) = query.apply(&rows).unwrap();
+        let names: Vec<String> = rows.into_iter().map(|row| row.name).collect();
+        (names, meta.total)
+    };
+
+    // A missing submission counts as not submitted
+    let query = RowsQuery {
+        exercise_submitted: Some("no".to_string()),
+        ..RowsQuery::default()
+    };
+    assert_eq!(
+        names(query),
+        (vec!["Ben".into(), "Cai".into(), "Dee".into()], 3)
+    );
+
+    let query = RowsQuery {
+        group: Some("3".to_string()),
+        attendance: Some("YES".to_string()),
+        ..RowsQuery::default()
+    };
+    assert_eq!(names(query), (vec!["Cai".into()], 1));
+
+    // Filters apply before paging, so the total counts matching rows only
+    let query = RowsQuery {
+        ta: Some("raj".to_string()),
+        page: Some(2),
+        per_page: Some(2),
+        ..RowsQuery::default()
+    };
+    assert_eq!(names(query), (vec!["Eli".into()], 3));
+
+    let query = RowsQuery {
+        attendance: Some("maybe".to_string()),
+        ..RowsQuery::default()
+    };
+    assert!(query.apply(&rows).is_err());
+}
+
+#[test]
+fn test_ta_invites() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    let now = chrono::Utc::now();
+    let invite = |email: &str, name: &str, expires_at: chrono::DateTime<chrono::Utc>| TaInvite {
+        email: email.to_string(),
+        name: name.to_string(),
+        invited_by: "admin".to_string(),
+        created_at: now.to_rfc3339(),
+        expires_at: expires_at.to_rfc3339(),
+    };
+    let setup = TaSetup {
+        github: "priya-dev".to_string(),
+        discord: "priya#1234".to_string(),
+        notify_by_email: true,
+        notify_by_discord: false,
+    };
+
+    storage
+        .create_ta_invite(
+            "first",
+            &invite(
+                "priya@example.com",
+                "Priya",
+                now + chrono::Duration::days(7),
+            ),
+        )
+        .unwrap();
+    // A second invite to the same address replaces the pending one
+    storage
+        .create_ta_invite(
+            "second",
+            &invite(
+                "Priya@example.com",
+                "Priya",
+                now + chrono::Duration::days(7),
+            ),
+        )
+        .unwrap();
+    assert_eq!(storage.accept_ta_invite("first", &setup).unwrap(), None);
+
+    let profile = storage.accept_ta_invite("second", &setup).unwrap().unwrap();
+    assert_eq!(
+        (profile.name.as_str(), profile.setup.clone()),
+        ("Priya", setup.clone())
+    );
+    assert_eq!(storage.read_onboarded_tas().unwrap(), vec![profile]);
+    // Invites work once
+    assert_eq!(storage.accept_ta_invite("second", &setup).unwrap(), None);
+
+    storage
+        .create_ta_invite(
+            "expired",
+            &invite("sam@example.com", "Sam", now - chrono::Duration::hours(1)),
+        )
+        .unwrap();
+    assert_eq!(storage.accept_ta_invite("expired", &setup).unwrap(), None);
+    assert_eq!(storage.read_onboarded_tas().unwrap().len(), 1);
+
+    // Names resolve to the founding TAs without adding to the roster. A
+    // roster of the test's own, as the server's is shared by every test.
+    let roster = Roster::default();
+    assert_eq!(roster.register("bala@example.com", "bala"), ta("Bala"));
+    assert_eq!(roster.all(), TA::all_variants());
+    assert_eq!(TA::from_name("SETU"), Some(ta("Setu")));
+    assert_eq!(format!("{:?}", ta("Setu")), "Setu");
+    // Sign-in emails match regardless of case, founding or onboarded
+    assert_eq!(roster.by_email(" Raj@Bitshala.org"), Some(ta("Raj")));
+    let onboarded = roster.register("Priya.N@Example.com", "Priya");
+    assert_eq!(roster.by_email("priya.n@example.com"), Some(onboarded));
+    assert_eq!(roster.all().len(), TA::all_variants().len() + 1);
+    assert_eq!(TA::from_email("priya.n@example.com"), None);
+}
+
+#[test]
+fn test_grading_flags() {
+    let row = |name: &str, ta: &str, fa: u64, total: u64| RowData {
+        ta: Some(ta.to_string()),
+        fa: Some(fa),
+        ..graded_row(name, 1, "yes", total)
+    };
+    let mut week: Vec<RowData> = (0..6)
+        .map(|i| row(&format!("Ref{}", i), "Bala", 3 + i % 2, 40 + i))
+        .collect();
+    let typed_zeros: Vec<RowData> = (0..3)
+        .map(|i| row(&format!("Raj{}", i), "Raj", 0, 41 + i))
+        .collect();
+    week.extend(typed_zeros.clone());
+
+    let flags = grading_flags(&week, &typed_zeros);
+    assert_eq!(flags.len(), 3);
+    assert!(
+        flags
+            .iter()
+            .all(|flag| flag.column == "fa" && flag.value == 0)
+    );
+    assert!(flags[0].reason.contains("Communication is 0 for all 3"));
+
+    // Ordinary grades and absent students raise nothing
+    let ordinary = vec![row("Ok0", "Raj", 3, 42), row("Ok1", "Raj", 4, 43)];
+    assert!(grading_flags(&week, &ordinary).is_empty());
+    let absent = vec![graded_row("Gone", 1, "no", 0)];
+    assert!(grading_flags(&week, &absent).is_empty());
+
+    let outlier = vec![row("Low", "Raj", 3, 0)];
+    let flags = grading_flags(&week, &outlier);
+    assert_eq!(flags.len(), 1);
+    assert_eq!((flags[0].column.as_str(), flags[0].value), ("total", 0));
+    assert!(flags[0].reason.contains("below"));
+
+    // The report judges each TA's rows of a week as one batch
+    let report = calibration_report(&week);
+    let names: Vec<&str> = report.iter().map(|flag| flag.name.as_str()).collect();
+    assert_eq!(names, vec!["Raj0", "Raj1", "Raj2"]);
+}
+
+#[test]
+fn test_row_details_split() {
+    let dir = std::env::temp_dir().join(format!("row_details_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("classroom.db");
+    let conn = open_connection(&path).unwrap();
+    conn.execute_batch(
+        r#"CREATE TABLE participants ("ID" TEXT PRIMARY KEY, "Name" TEXT, "Email" TEXT, "GitHub" TEXT);
+           CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, ta TEXT, attendance TEXT, fa REAL, fb REAL, fc REAL, fd REAL, bonus_attempt REAL, bonus_answer_quality REAL, bonus_follow_up REAL, exercise_submitted TEXT, exercise_test_passing TEXT, exercise_good_documentation TEXT, exercise_good_structure TEXT, total REAL, mail TEXT, GitHub TEXT, week INTEGER);
+           INSERT INTO participants VALUES ('1', 'Alice', 'a@example.com', 'https://github.com/alice');
+           INSERT INTO students (name, group_id, attendance, fa, fb, mail, week) VALUES ('Alice', 'Group 1', 'yes', 4, 2, '', 1);
+           INSERT INTO students (name, group_id, attendance, fa, mail, week) VALUES ('Nobody', 'Group 1', 'yes', 3, '', 1);"#,
+    )
+    .unwrap();
+    drop(conn);
+    run_migrations(&path).unwrap();
+
+    let scores = |name: &str| -> Vec<(String, i64)> {
+        let conn = open_connection(&path).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT c.criterion, c.score FROM scores c JOIN students s ON s.public_id = c.student_id WHERE s.name = ?1 ORDER BY c.criterion",
+            )
+            .unwrap();
+        stmt.query_map([name], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    };
+    // Linked rows keep attendance and scores in their own tables only
+    assert_eq!(
+        scores("Alice"),
+        vec![("fa".to_string(), 4), ("fb".to_string(), 2)]
+    );
+    assert!(scores("Nobody").is_empty());
+    let conn = open_connection(&path).unwrap();
+    let (attendance, fa): (Option<String>, Option<f64>) = conn
+        .query_row(
+            "SELECT attendance, fa FROM students WHERE name = 'Alice'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((attendance, fa), (None, None));
+    let status: String = conn
+        .query_row("SELECT status FROM attendance", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(status, "yes");
+    drop(conn);
+
+    // Reads give back the wide row, linked or not
+    let storage = SqliteStorage::new(create_pool(&path).unwrap());
+    let mut rows = storage.read_from_db().unwrap().rows;
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(
+        (rows[0].fa, rows[0].fb, rows[0].fc),
+        (Some(4), Some(2), None)
+    );
+    assert_eq!(rows[1].fa, Some(3));
+    assert!(
+        rows.iter()
+            .all(|row| row.attendance.as_deref() == Some("yes"))
+    );
+
+    // A cleared score is dropped rather than kept from before
+    let mut alice = rows[0].clone();
+    alice.fb = None;
+    alice.fd = Some(5);
+    alice.attendance = Some("no".to_string());
+    storage.upsert_rows(std::slice::from_ref(&alice)).unwrap();
+    assert_eq!(
+        scores("Alice"),
+        vec![("fa".to_string(), 4), ("fd".to_string(), 5)]
+    );
+    let stored = storage.read_from_db().unwrap().rows;
+    assert!(stored.contains(&alice));
+
+    storage.purge_rows("Alice", None).unwrap();
+    let conn = open_connection(&path).unwrap();
+    let left: i64 = conn
+        .query_row(
+            "SELECT (SELECT COUNT(*) FROM scores) + (SELECT COUNT(*) FROM attendance)",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(left, 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_row_details_keyed_by_row() {
+    let dir = std::env::temp_dir().join(format!("row_details_keys_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("classroom.db");
+    let conn = open_connection(&path).unwrap();
+    // Two rows of one participant in week 1, and attendance of week 2 in
+    // the earlier layout keyed by participant and week
+    conn.execute_batch(
+        r#"CREATE TABLE participants ("ID" TEXT PRIMARY KEY, "Name" TEXT, "Email" TEXT, "GitHub" TEXT);
+           CREATE TABLE students (name TEXT NOT NULL, group_id TEXT, ta TEXT, attendance TEXT, fa REAL, fb REAL, fc REAL, fd REAL, bonus_attempt REAL, bonus_answer_quality REAL, bonus_follow_up REAL, exercise_submitted TEXT, exercise_test_passing TEXT, exercise_good_documentation TEXT, exercise_good_structure TEXT, total REAL, mail TEXT, GitHub TEXT, week INTEGER);
+           CREATE TABLE attendance (participant_id TEXT NOT NULL, week INTEGER NOT NULL, status TEXT, PRIMARY KEY (participant_id, week));
+           INSERT INTO participants VALUES ('1', 'Alice', 'a@example.com', 'https://github.com/alice');
+           INSERT INTO students (name, group_id, attendance, fa, mail, week) VALUES ('Alice', 'Group 1', 'yes', 4, '', 1);
+           INSERT INTO students (name, group_id, attendance, fa, mail, week) VALUES ('alice', 'Group 2', 'no', 1, '', 1);
+           INSERT INTO students (name, group_id, mail, week) VALUES ('Alice', 'Group 1', '', 2);
+           INSERT INTO attendance VALUES ('1', 2, 'yes');"#,
+    )
+    .unwrap();
+    drop(conn);
+    run_migrations(&path).unwrap();
+
+    let storage = SqliteStorage::new(create_pool(&path).unwrap());
+    let row = |name: &str, week: i32| {
+        storage
+            .read_from_db()
+            .unwrap()
+            .rows
+            .into_iter()
+            .find(|row| row.name == name && row.week == week)
+            .unwrap()
+    };
+    // Each row keeps its own grades, and earlier details follow their row
+    assert_eq!(
+        row("Alice", 1).participant_id,
+        row("alice", 1).participant_id
+    );
+    assert_eq!(
+        (row("Alice", 1).fa, row("Alice", 1).attendance.as_deref()),
+        (Some(4), Some("yes"))
+    );
+    assert_eq!(
+        (row("alice", 1).fa, row("alice", 1).attendance.as_deref()),
+        (Some(1), Some("no"))
+    );
+    assert_eq!(row("Alice", 2).attendance.as_deref(), Some("yes"));
+
+    let mut duplicate = row("alice", 1);
+    duplicate.fa = Some(2);
+    storage.upsert_rows(&[duplicate]).unwrap();
+    assert_eq!((row("Alice", 1).fa, row("alice", 1).fa), (Some(4), Some(2)));
+
+    // Hard deletes take the rows' details with them: attendance, a score
+    // and exercise results for each
+    let details = || -> i64 {
+        open_connection(&path)
+            .unwrap()
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM attendance) + (SELECT COUNT(*) FROM scores) + (SELECT COUNT(*) FROM exercise_results)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+    };
+    let before = details();
+    storage.purge_rows("alice", Some(1)).unwrap();
+    assert_eq!(details(), before - 3);
+    storage.delete_week(1, true).unwrap();
+    assert_eq!(details(), before - 6);
+    assert_eq!(row("Alice", 2).attendance.as_deref(), Some("yes"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_backfill_weeks_and_jobs() {
+    assert_eq!(parse_week_range("3"), Ok(3..=3));
+    assert_eq!(parse_week_range("1..6"), Ok(1..=6));
+    assert_eq!(parse_week_range("2..=4"), Ok(2..=4));
+    for invalid in ["", "0..2", "5..3", "a..b", "1..60"] {
+        assert!(parse_week_range(invalid).is_err(), "{}", invalid);
+    }
+
+    let assignment = |github: &str, submitted_at: Option<&str>| Assignment {
+        assignment_name: "Week 2 exercise".to_string(),
+        assignment_url: String::new(),
+        github_username: github.to_string(),
+        points_available: "100".to_string(),
+        points_awarded: "100".to_string(),
+        roster_identifier: github.to_string(),
+        starter_code_url: String::new(),
+        student_repository_name: format!("week-2-{}", github),
+        student_repository_url: String::new(),
+        submission_timestamp: submitted_at.map(str::to_string),
+        ci_passing: None,
+        commit_count: None,
+        last_commit_at: None,
+    };
+    let attempts = backfilled_attempts(
+        &[
+            assignment("alice", Some("2025-03-10T12:00:00Z")),
+            assignment("bob", None),
+        ],
+        2,
+    );
+    assert_eq!(attempts.len(), 1);
+    assert_eq!(attempts[0].recorded_at, "2025-03-10T12:00:00Z");
+    assert!(attempts[0].passing);
+
+    // One backfill at a time; progress and outcome are kept on the job
+    let mut jobs = Jobs::default();
+    let job = jobs.start("backfill", 3).unwrap();
+    assert!(jobs.start("backfill", 1).is_none());
+    jobs.progress(&job.id, 1, "Backfilling week 2".to_string());
+    assert_eq!(jobs.get(&job.id).unwrap().done, 1);
+    jobs.finish(&job.id, Ok(serde_json::json!({ "weeks": [] })));
+    let finished = jobs.get(&job.id).unwrap();
+    assert_eq!((finished.state, finished.done), (JobState::Succeeded, 3));
+    assert!(jobs.start("backfill", 1).is_some());
+    assert_eq!(jobs.list()[1].id, job.id);
+}
+
+#[test]
+fn test_review_summaries() {
+    let points = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        clean_points(
+            "common_mistakes",
+            points(&["  unwrap   everywhere ", "", " "])
+        )
+        .unwrap(),
+        points(&["unwrap everywhere"])
+    );
+    assert!(clean_points("common_mistakes", points(&["x"; 11])).is_err());
+    assert!(clean_points("standout_solutions", vec!["y".repeat(281)]).is_err());
+
+    let storage = SqliteStorage::in_memory().unwrap();
+    let summary = |group: &str, ta: &str, mistakes: &[&str], notes: Option<&str>| ReviewSummary {
+        week: 2,
+        group_id: group.to_string(),
+        ta: ta.to_string(),
+        common_mistakes: points(mistakes),
+        standout_solutions: Vec::new(),
+        notes: notes.map(str::to_string),
+        submitted_at: "2026-01-01T00:00:00+00:00".to_string(),
+    };
+    storage
+        .store_review_summary(&summary("Group 1", "Bala", &["Off by one"], None))
+        .unwrap();
+    // A second submission for the group replaces the first
+    let first = summary(
+        "Group 1",
+        "Bala",
+        &["Unwrap on user input", "Off by one"],
+        Some("Week felt long"),
+    );
+    storage.store_review_summary(&first).unwrap();
+    let second = summary("Group 2", "Raj", &["off by one.", "No tests"], None);
+    storage.store_review_summary(&second).unwrap();
+    assert_eq!(
+        storage.read_review_summaries().unwrap(),
+        vec![first.clone(), second.clone()]
+    );
+
+    let seat = |name: &str, group: &str| RowData {
+        group_id: group.to_string(),
+        ..graded_row(name, 2, "yes", 10)
+    };
+    let rows = vec![
+        seat("Ana", "Group 1"),
+        seat("Ben", "Group 2"),
+        seat("Cai", "Group 3"),
+    ];
+    let report = curriculum_report(&[first, second], &rows);
+    assert_eq!(report.len(), 1);
+    let week = &report[0];
+    assert_eq!((week.week, week.groups_reviewed), (2, 2));
+    assert_eq!(week.missing, vec!["Group 3"]);
+    // Raised by both groups, so listed first under the first wording
+    assert_eq!(week.common_mistakes[0].text, "Off by one");
+    assert_eq!(week.common_mistakes[0].groups, vec!["Group 1", "Group 2"]);
+    assert_eq!(week.common_mistakes.len(), 3);
+    assert_eq!(week.notes.len(), 1);
+    assert_eq!(week.notes[0].note, "Week felt long");
+}
+
+#[test]
+fn test_idempotent_responses() {
+    let rows = vec![graded_row("Alice", 1, "yes", 5)];
+    let request = fingerprint(&(1, None::<i32>, &rows));
+    assert_eq!(request, fingerprint(&(1, None::<i32>, &rows.clone())));
+    assert_eq!(request.len(), 64);
+    // Another week, version or grade is another request
+    assert_ne!(request, fingerprint(&(2, None::<i32>, &rows)));
+    assert_ne!(request, fingerprint(&(1, Some(1), &rows)));
+    let regraded = vec![graded_row("Alice", 1, "yes", 6)];
+    assert_ne!(request, fingerprint(&(1, None::<i32>, &regraded)));
+
+    let storage = SqliteStorage::in_memory().unwrap();
+    let now = chrono::Utc::now();
+    let response =
+        |key: &str, actor: &str, created_at: chrono::DateTime<chrono::Utc>| IdempotentResponse {
+            key: key.to_string(),
+            actor: actor.to_string(),
+            fingerprint: request.clone(),
+            status: 200,
+            body: r#"{"message":"ok"}"#.to_string(),
+            created_at: created_at.to_rfc3339(),
+        };
+    let old = response("retry-1", "admin", now - chrono::Duration::hours(30));
+    storage
+        .store_idempotent_response(&old, now - chrono::Duration::hours(48))
+        .unwrap();
+    let fresh = response("retry-2", "admin", now);
+    storage
+        .store_idempotent_response(&fresh, now - chrono::Duration::hours(24))
+        .unwrap();
+    // Storing prunes responses older than the cutoff
+    assert_eq!(
+        storage
+            .read_idempotent_response("retry-1", "admin")
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        storage
+            .read_idempotent_response("retry-2", "admin")
+            .unwrap(),
+        Some(fresh)
+    );
+    // Keys are per caller
+    assert_eq!(
+        storage.read_idempotent_response("retry-2", "Bala").unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_row_validation() {
+    let valid = RowData {
+        ta: Some("Bala".to_string()),
+        fa: Some(5),
+        ..graded_row("Alice", 2, "yes", 6)
+    };
+    assert_eq!(validate_rows(std::slice::from_ref(&valid), 2), vec![]);
+    // An empty TA is the frontend's "no TA"
+    let untaught = RowData {
+        ta: Some(String::new()),
+        ..valid.clone()
+    };
+    assert_eq!(validate_rows(&[untaught], 2), vec![]);
+
+    let invalid = RowData {
+        name: " ".to_string(),
+        ta: Some("Nobody".to_string()),
+        attendance: Some("maybe".to_string()),
+        fb: Some(9),
+        exercise_submitted: Some("Y".to_string()),
+        week: 3,
+        ..valid.clone()
+    };
+    let errors = validate_rows(&[valid, invalid], 2);
+    let fields: Vec<(usize, &str)> = errors
+        .iter()
+        .map(|error| (error.row, error.field.as_str()))
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            (1, "name"),
+            (1, "week"),
+            (1, "ta"),
+            (1, "attendance"),
+            (1, "fb"),
+            (1, "exercise_submitted")
+        ]
+    );
+    assert_eq!(errors[4].message, "is 9 but must be between 0 and 5");
+
+    // A batch skipping invalid rows reports each one with all its problems
+    let failures = row_failures(&errors);
+    assert_eq!(failures.keys().copied().collect::<Vec<_>>(), vec![1]);
+    assert!(
+        failures[&1].starts_with("name must not be empty; week "),
+        "{}",
+        failures[&1]
+    );
+    assert_eq!(failures[&1].matches("; ").count(), 5);
+}
+
+#[test]
+fn test_revert_changes() {
+    let before = RowData {
+        ta: None,
+        group_id: "3".to_string(),
+        ..graded_row("Alice", 2, "no", 0)
+    };
+    let after = RowData {
+        ta: Some("Bala".to_string()),
+        fa: Some(4),
+        attendance: Some("yes".to_string()),
+        ..before.clone()
+    };
+    let changes = row_changes(Some(&before), &after, "2026-01-01T00:00:00+00:00");
+    assert_eq!(changes.len(), 3);
+    // Numbers and text come back as their field's type, missing values as none
+    assert_eq!(revert_changes(&after, &changes).unwrap(), before);
+    // The whole-number group id stays text
+    let regrouped = RowData {
+        group_id: "Group 4".to_string(),
+        ..before.clone()
+    };
+    let regrouping = row_changes(Some(&before), &regrouped, "2026-01-02T00:00:00+00:00");
+    assert_eq!(revert_changes(&regrouped, &regrouping).unwrap(), before);
+}
+
+#[test]
+fn test_week_locks() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    let lock = |week: i32, at: &str| WeekLock {
+        week,
+        locked_by: "admin".to_string(),
+        locked_at: at.to_string(),
+    };
+    storage
+        .lock_week(&lock(3, "2026-01-03T00:00:00+00:00"))
+        .unwrap();
+    storage
+        .lock_week(&lock(1, "2026-01-01T00:00:00+00:00"))
+        .unwrap();
+    // Locking again keeps the first lock
+    storage
+        .lock_week(&lock(3, "2026-02-01T00:00:00+00:00"))
+        .unwrap();
+    assert_eq!(
+        storage.read_week_locks().unwrap(),
+        vec![
+            lock(1, "2026-01-01T00:00:00+00:00"),
+            lock(3, "2026-01-03T00:00:00+00:00")
+        ]
+    );
+    assert_eq!(
+        WeekLocks::load(&storage)
+            .unwrap()
+            .weeks()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec![1, 3]
+    );
+
+    assert!(storage.unlock_week(3).unwrap());
+    assert!(!storage.unlock_week(3).unwrap());
+    assert_eq!(storage.read_week_locks().unwrap().len(), 1);
+}
+
+#[test]
+fn test_copy_week_rows() {
+    let mut table = Table::new(Vec::new());
+    let mut graded = RowData {
+        ta: Some("Bala".to_string()),
+        group_id: "Group 2".to_string(),
+        fa: Some(5),
+        ..graded_row("Alice", 3, "yes", 6)
+    };
+    table.insert_or_update(&mut graded).unwrap();
+
+    let mut copies = copy_week_rows(&table.rows, 4);
+    assert_eq!(copies.len(), 1);
+    let copy = &copies[0];
+    assert_eq!((copy.week, copy.group_id.as_str()), (4, "Group 2"));
+    assert_eq!(copy.ta.as_deref(), Some("Bala"));
+    assert_eq!((copy.fa, copy.total), (Some(0), Some(0)));
+    assert_eq!(copy.attendance.as_deref(), Some("no"));
+
+    // The copy is a row of its own, for the same student
+    table.insert_or_update(&mut copies[0]).unwrap();
+    assert_eq!(table.rows.len(), 2);
+    assert_ne!(table.rows[1].public_id, table.rows[0].public_id);
+    assert_eq!(table.rows[1].student_id, table.rows[0].student_id);
+    assert_eq!(table.rows[1].version, Some(1));
+}
+
+#[test]
+fn test_delete_week() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage
+        .upsert_rows(&[
+            graded_row("Alice", 1, "yes", 10),
+            graded_row("Bob", 1, "no", 0),
+            graded_row("Alice", 2, "yes", 12),
+        ])
+        .unwrap();
+
+    // Soft-deleted rows of the week can still be restored one by one
+    assert_eq!(storage.delete_week(1, false).unwrap(), 2);
+    assert_eq!(storage.delete_week(1, false).unwrap(), 0);
+    let live = storage.read_from_db().unwrap();
+    assert_eq!(live.rows.len(), 1);
+    assert_eq!(live.rows[0].week, 2);
+    assert!(storage.read_deleted_row("Bob", 1).unwrap().is_some());
+
+    // Deleting permanently also removes the soft-deleted rows
+    assert_eq!(storage.delete_week(1, true).unwrap(), 2);
+    assert!(storage.read_deleted_row("Bob", 1).unwrap().is_none());
+    assert_eq!(storage.read_from_db().unwrap().rows.len(), 1);
+}
+
+#[test]
+fn test_replace_week() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage
+        .upsert_rows(&[
+            graded_row("Alice", 1, "yes", 10),
+            graded_row("Bob", 1, "no", 0),
+            graded_row("Bob", 2, "yes", 4),
+        ])
+        .unwrap();
+
+    let rows = [
+        graded_row("Alice", 1, "yes", 12),
+        graded_row("Carol", 1, "yes", 7),
+    ];
+    assert_eq!(storage.replace_week(1, &rows, &[], "admin").unwrap(), 1);
+
+    let mut live: Vec<(String, i32, Option<u64>)> = storage
+        .read_from_db()
+        .unwrap()
+        .rows
+        .into_iter()
+        .map(|row| (row.name, row.week, row.total))
+        .collect();
+    live.sort();
+    assert_eq!(
+        live,
+        vec![
+            ("Alice".to_string(), 1, Some(12)),
+            ("Bob".to_string(), 2, Some(4)),
+            ("Carol".to_string(), 1, Some(7)),
+        ]
+    );
+    // The dropped row is soft-deleted, so it can be restored
+    assert!(storage.read_deleted_row("Bob", 1).unwrap().is_some());
+}
+
+#[test]
+fn test_merge_rows() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    let rows = vec![
+        RowData {
+            fa: Some(3),
+            ..graded_row("John", 1, "no", 3)
+        },
+        RowData {
+            fa: Some(5),
+            ..graded_row("Jon", 1, "yes", 5)
+        },
+        graded_row("Jon", 2, "yes", 4),
+        graded_row("Mary", 1, "yes", 8),
+    ];
+    storage.upsert_rows(&rows).unwrap();
+
+    // Only the duplicate's weeks are written, all under the canonical name
+    let kept = merge_student_rows(&rows, "Jon", "John", MergeStrategy::Canonical);
+    assert_eq!(kept[0].fa, Some(3));
+    let merged = merge_student_rows(&rows, "Jon", "John", MergeStrategy::Max);
+    assert_eq!(merged.len(), 2);
+    assert!(merged.iter().all(|row| row.name == "John"));
+    assert_eq!(merged[0].fa, Some(5));
+    assert_eq!(merged[0].attendance.as_deref(), Some("yes"));
+    assert_eq!(merged[0].mail, "john@example.com");
+    assert_eq!(merged[1].total, Some(4));
+
+    assert_eq!(storage.merge_rows("Jon", &merged, &[], "admin").unwrap(), 2);
+    let mut live: Vec<(String, i32, Option<u64>)> = storage
+        .read_from_db()
+        .unwrap()
+        .rows
+        .into_iter()
+        .map(|row| (row.name, row.week, row.fa))
+        .collect();
+    live.sort();
+    assert_eq!(
+        live,
+        vec![
+            ("John".to_string(), 1, Some(5)),
+            ("John".to_string(), 2, Some(0)),
+            ("Mary".to_string(), 1, Some(0)),
+        ]
+    );
+}
+
+#[test]
+fn test_rows_by_week() {
+    let rows = vec![
+        graded_row("Alice", 1, "yes", 10),
+        graded_row("Bob", 1, "no", 0),
+        graded_row("Alice", 3, "yes", 6),
+        graded_row("Alice", 6, "yes", 8),
+    ];
+    let by_week = rows_by_week(&rows, 1..=5);
+    let counts: Vec<(i32, usize)> = by_week
+        .iter()
+        .map(|(week, rows)| (*week, rows.len()))
+        .collect();
+    // Weeks without rows are listed empty, weeks outside the range left out
+    assert_eq!(counts, vec![(1, 2), (2, 0), (3, 1), (4, 0), (5, 0)]);
+    assert_eq!(by_week[&3][0].total, Some(6));
+}
+
+#[test]
+fn test_field_selection() {
+    let rows = vec![graded_row("Alice", 2, "yes", 10)];
+    let fields = |fields: &str| FieldsQuery {
+        fields: Some(fields.to_string()),
+    };
+
+    let selected = fields("name, attendance,total").select(&rows).unwrap();
+    assert_eq!(
+        selected,
+        serde_json::json!([{ "name": "Alice", "attendance": "yes", "total": 10 }])
+    );
+    // Fields the row leaves out stay out
+    let selected = fields("name,public_id").select(&rows).unwrap();
+    assert_eq!(selected, serde_json::json!([{ "name": "Alice" }]));
+
+    // Without fields, or with an empty list, rows are returned whole
+    let whole = serde_json::to_value(&rows).unwrap();
+    assert_eq!(FieldsQuery::default().select(&rows).unwrap(), whole);
+    assert_eq!(fields("").select(&rows).unwrap(), whole);
+
+    let err = fields("name,score").select(&rows).unwrap_err();
+    assert!(err.contains("\"score\""), "{}", err);
+}
+
+#[test]
+fn test_row_notes() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    let noted = RowData {
+        notes: Some("had connectivity issues".to_string()),
+        ..graded_row("Alice", 2, "yes", 10)
+    };
+    storage.upsert_rows(std::slice::from_ref(&noted)).unwrap();
+    let stored = storage.read_from_db().unwrap().rows;
+    assert_eq!(stored[0].notes, noted.notes);
+
+    // Kept when the week is regenerated, not carried into the next week
+    let mut regrouped = graded_row("Alice", 2, "no", 0);
+    carry_over_grades(&mut regrouped, &noted);
+    assert_eq!(regrouped.notes, noted.notes);
+    assert_eq!(copy_week_rows(&stored, 3)[0].notes, None);
+
+    let long = RowData {
+        notes: Some("x".repeat(1001)),
+        ..noted
+    };
+    let errors = validate_rows(&[long], 2);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "notes");
+}
+
+#[test]
+fn test_commit_metadata() {
+    let submission = |github: &str, last_commit_at: Option<&str>| Assignment {
+        assignment_name: "Week 2 exercise".to_string(),
+        assignment_url: String::new(),
+        github_username: github.to_string(),
+        points_available: "100".to_string(),
+        points_awarded: "100".to_string(),
+        roster_identifier: github.to_string(),
+        starter_code_url: String::new(),
+        student_repository_name: format!("week-2-{}", github),
+        student_repository_url: String::new(),
+        submission_timestamp: Some("2025-03-14T09:00:00Z".to_string()),
+        ci_passing: None,
+        commit_count: last_commit_at.map(|_| 7),
+        last_commit_at: last_commit_at.map(str::to_string),
+    };
+
+    // Written to the row and kept by the storage
+    let mut row = graded_row("Alice", 2, "yes", 10);
+    let result = exercise_result(&submission("alice", Some("2025-03-15T18:30:00Z")), 2).unwrap();
+    apply_exercise_result(&mut row, &result);
+    assert_eq!(row.commit_count, Some(7));
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage.upsert_rows(std::slice::from_ref(&row)).unwrap();
+    let stored = storage.read_from_db().unwrap().rows;
+    assert_eq!(stored[0].commit_count, Some(7));
+    assert_eq!(
+        stored[0].last_commit_at.as_deref(),
+        Some("2025-03-15T18:30:00Z")
+    );
+    // A sync that could not read the repo leaves them as they were
+    let mut unknown = result.clone();
+    unknown.commit_count = None;
+    unknown.last_commit_at = None;
+    apply_exercise_result(&mut row, &unknown);
+    assert_eq!(row.commit_count, Some(7));
+
+    // Week 2 of a cohort starting 2025-03-03 is due by the end of the 16th
+    let window = CohortWindow {
+        start: chrono::NaiveDate::from_ymd_opt(2025, 3, 3),
+        ..CohortWindow::default()
+    };
+    let deadline = window.week_deadline(2).unwrap();
+    assert_eq!(deadline.to_rfc3339(), "2025-03-17T00:00:00+00:00");
+    let late = late_submissions(
+        &[
+            submission("alice", Some("2025-03-15T18:30:00Z")),
+            submission("bob", Some("2025-03-17T08:00:00Z")),
+            // Without commits the submission time decides
+            submission("carol", None),
+        ],
+        deadline,
+    );
+    assert_eq!(late.len(), 1);
+    assert_eq!(late[0].kind, SyncWarningKind::LateSubmission);
+    assert_eq!(late[0].github_username.as_deref(), Some("bob"));
+
+    // Pushes report the head commit's time in UTC
+    let push = serde_json::json!({
+        "repository": { "name": "week-2-exercise-alice" },
+        "sender": { "login": "alice" },
+        "head_commit": { "timestamp": "2025-03-16T23:30:00-02:00" }
+    })
+    .to_string();
+    let event = parse_submission_event("push", push.as_bytes(), AUTOGRADER)
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.committed_at.as_deref(), Some("2025-03-17T01:30:00Z"));
+}
+
+#[test]
+fn test_row_repo_url() {
+    let url = "https://github.com/bitcoin-dev/week-2-exercise-alice";
+    let submission = Assignment {
+        assignment_name: "Week 2 exercise".to_string(),
+        assignment_url: String::new(),
+        github_username: "alice".to_string(),
+        points_available: "100".to_string(),
+        points_awarded: "100".to_string(),
+        roster_identifier: "alice".to_string(),
+        starter_code_url: String::new(),
+        student_repository_name: "week-2-exercise-alice".to_string(),
+        student_repository_url: url.to_string(),
+        submission_timestamp: Some("2025-03-14T09:00:00Z".to_string()),
+        ci_passing: None,
+        commit_count: Some(3),
+        last_commit_at: Some("2025-03-14T08:55:00Z".to_string()),
+    };
+    let mut synced = graded_row("Alice", 2, "yes", 10);
+    apply_exercise_result(&mut synced, &exercise_result(&submission, 2).unwrap());
+    assert_eq!(synced.repo_url.as_deref(), Some(url));
+
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage.upsert_rows(std::slice::from_ref(&synced)).unwrap();
+    let stored = storage.read_from_db().unwrap().rows;
+    assert_eq!(stored[0].repo_url.as_deref(), Some(url));
+
+    // A TA saving the row without the forge's fields keeps them
+    let mut table = Table::new(stored);
+    let mut saved = RowData {
+        notes: Some("good tests".to_string()),
+        commit_count: None,
+        last_commit_at: None,
+        repo_url: None,
+        ..table.rows[0].clone()
+    };
+    assert!(table.insert_or_update(&mut saved).unwrap());
+    assert_eq!(table.rows[0].repo_url.as_deref(), Some(url));
+    assert_eq!(table.rows[0].commit_count, Some(3));
+
+    // Pushes carry the repo's link too
+    let push = serde_json::json!({
+        "repository": { "name": "week-2-exercise-alice", "html_url": url },
+        "sender": { "login": "alice" }
+    })
+    .to_string();
+    let event = parse_submission_event("push", push.as_bytes(), AUTOGRADER)
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.repo_url.as_deref(), Some(url));
+}
+
+#[test]
+fn test_periodic_sync() {
+    let date = |s: &str| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    let window = CohortWindow {
+        start: Some(date("2025-03-03")),
+        end: Some(date("2025-04-27")),
+        enforce: false,
+        max_week: Some(8),
+    };
+    assert_eq!(window.current_week(date("2025-03-02")), None);
+    assert_eq!(window.current_week(date("2025-03-03")), Some(1));
+    assert_eq!(window.current_week(date("2025-03-16")), Some(2));
+    assert_eq!(window.current_week(date("2025-04-28")), None);
+
+    let rows = vec![
+        graded_row("Alice", 1, "yes", 0),
+        graded_row("Alice", 2, "yes", 0),
+        graded_row("Bob", 2, "yes", 0),
+    ];
+    let none = BTreeSet::new();
+    assert_eq!(
+        week_to_sync(&window, &rows, &none, date("2025-03-12")),
+        Some(2)
+    );
+    // Week 3 was not generated yet, and locked weeks are left alone
+    assert_eq!(
+        week_to_sync(&window, &rows, &none, date("2025-03-17")),
+        None
+    );
+    let locked = BTreeSet::from([2]);
+    assert_eq!(
+        week_to_sync(&window, &rows, &locked, date("2025-03-12")),
+        None
+    );
+    // Without dates the latest week is synced
+    let today = date("2030-01-01");
+    assert_eq!(
+        week_to_sync(&CohortWindow::default(), &rows, &none, today),
+        Some(2)
+    );
+
+    let submission = Assignment {
+        assignment_name: "Week 2 exercise".to_string(),
+        assignment_url: String::new(),
+        github_username: "alice".to_string(),
+        points_available: "100".to_string(),
+        points_awarded: "100".to_string(),
+        roster_identifier: "alice".to_string(),
+        starter_code_url: String::new(),
+        student_repository_name: "week-2-exercise-alice".to_string(),
+        student_repository_url: String::new(),
+        submission_timestamp: Some("2025-03-14T09:00:00Z".to_string()),
+        ci_passing: None,
+        commit_count: None,
+        last_commit_at: None,
+    };
+    let submissions = HashMap::from([("Alice".to_string(), submission)]);
+    let changed = apply_submissions(&rows, 2, &submissions);
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].name, "Alice");
+    assert_eq!(changed[0].week, 2);
+    assert_eq!(changed[0].exercise_test_passing.as_deref(), Some("yes"));
+    // Nothing left to change once applied
+    assert!(apply_submissions(&changed, 2, &submissions).is_empty());
+}
+
+#[test]
+fn test_changed_rows() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage
+        .upsert_rows(&[
+            graded_row("Alice", 1, "yes", 10),
+            graded_row("Bob", 1, "no", 0),
+            graded_row("Bob", 2, "yes", 4),
+        ])
+        .unwrap();
+    let before = chrono::Utc::now() - chrono::Duration::seconds(1);
+    let changes = storage.read_changed_rows(before, None).unwrap();
+    assert_eq!((changes.rows.len(), changes.deleted.len()), (3, 0));
+
+    let since = chrono::Utc::now();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    storage
+        .upsert_rows(&[graded_row("Alice", 1, "yes", 12)])
+        .unwrap();
+    storage.delete_rows("Bob", Some(1)).unwrap();
+
+    let changes = storage.read_changed_rows(since, None).unwrap();
+    assert_eq!(changes.rows.len(), 1);
+    let alice = &changes.rows[0];
+    assert_eq!((alice.name.as_str(), alice.total), ("Alice", Some(12)));
+    assert_eq!(
+        changes.deleted,
+        vec![DeletedRow {
+            name: "Bob".to_string(),
+            week: 1
+        }]
+    );
+
+    // Only the asked week, and nothing after the last change
+    let changes = storage.read_changed_rows(since, Some(2)).unwrap();
+    assert!(changes.rows.is_empty() && changes.deleted.is_empty());
+    let changes = storage.read_changed_rows(chrono::Utc::now(), None).unwrap();
+    assert!(changes.rows.is_empty() && changes.deleted.is_empty());
+}
+
+#[actix_web::test]
+async fn test_login_only_sends_link() {
+    let outbox: &'static Outbox = Box::leak(Box::new(Outbox::new(10)));
+    let db: Arc<dyn Storage> = Arc::new(SqliteStorage::in_memory().unwrap());
+    let db = web::Data::from(db);
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(Mutex::new(LockoutTracker::default())))
+            .app_data(web::Data::new(Mutex::new(MagicLinks::from_env())))
+            .app_data(web::Data::new(Mailer::capturing(outbox)))
+            .service(auth::login),
+    )
+    .await;
+
+    let (ta_email, _) = TA_EMAILS[0];
+    let mut answers = Vec::new();
+    for email in [ta_email, "stranger@example.com"] {
+        let req = actix_web::test::TestRequest::post()
+            .uri("/login")
+            .set_json(serde_json::json!({ "gmail": email }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        let status = resp.status();
+        let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+        assert!(body.get("token").is_none());
+        answers.push((status, body));
+    }
+
+    // TAs and strangers get the same answer; only the TA is mailed a link
+    assert_eq!(answers[0].0, StatusCode::ACCEPTED);
+    assert_eq!(answers[0], answers[1]);
+    let messages = outbox.messages();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].to.contains(ta_email));
+    assert!(messages[0].body.contains("?magic="));
+
+    // The sent link shows in the TA's communication history
+    let ta = TA::from_email(ta_email).unwrap().name();
+    let sent = db.read_communications(&ta).unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].subject, "Your sign-in link");
+}
+
+// The authenticated API over an in-memory database, for tests that go
+// through the handlers and extractors
+struct TestApi {
+    db: web::Data<dyn Storage>,
+    state: web::Data<Mutex<Table>>,
+    sessions: web::Data<Mutex<SessionStore>>,
+    lockouts: web::Data<Mutex<LockoutTracker>>,
+    week_locks: web::Data<Mutex<WeekLocks>>,
+}
+
+impl TestApi {
+    fn new(storage: SqliteStorage) -> Self {
+        let state = web::Data::new(Mutex::new(storage.read_from_db().unwrap()));
+        let week_locks = web::Data::new(Mutex::new(WeekLocks::load(&storage).unwrap()));
+        let db: Arc<dyn Storage> = Arc::new(storage);
+        TestApi {
+            db: web::Data::from(db),
+            state,
+            sessions: web::Data::new(Mutex::new(SessionStore::default())),
+            lockouts: web::Data::new(Mutex::new(LockoutTracker::default())),
+            week_locks,
+        }
+    }
+
+    fn ta_token(&self, ta: TA) -> String {
+        self.sessions
+            .lock()
+            .unwrap()
+            .create(ta, ClientInfo::default())
+    }
+
+    fn app(
+        &self,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody + use<>>,
+            Error = actix_web::Error,
+            InitError = (),
+        > + use<>,
+    > {
+        App::new()
+            .app_data(self.db.clone())
+            .app_data(self.state.clone())
+            .app_data(self.sessions.clone())
+            .app_data(self.lockouts.clone())
+            .app_data(self.week_locks.clone())
+            .app_data(web::Data::new(Mutex::new(RevocationList::default())))
+            .app_data(web::Data::new(Backups::new(None, BackupPolicy::default())))
+            .wrap(from_fn(auth::require_auth))
+    }
+}
+
+fn group_row(name: &str, week: i32, ta: &str, group: &str) -> RowData {
+    RowData {
+        ta: Some(ta.to_string()),
+        group_id: group.to_string(),
+        ..graded_row(name, week, "yes", 0)
+    }
+}
+
+#[actix_web::test]
+async fn test_routes_require_auth() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage
+        .upsert_rows(&[group_row("Alice", 1, "Bala", "Group 1")])
+        .unwrap();
+    let api = TestApi::new(storage);
+    let token = api.ta_token(ta("Bala"));
+    let admin = get_auth_token();
+    let app = actix_web::test::init_service(
+        api.app()
+            .service(weekly_data::get_weekly_data_or_common)
+            .service(weekly_data::add_weekly_data)
+            .service(weekly_data::delete_data)
+            .service(week_locks::lock_week),
+    )
+    .await;
+    let request = |method: Method, uri: &str, token: Option<&str>| {
+        let req = actix_web::test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .set_json(vec![group_row("Alice", 1, "Bala", "Group 1")]);
+        match token {
+            Some(token) => req.insert_header(("Authorization", token)),
+            None => req,
+        }
+        .to_request()
+    };
+
+    // Reads and writes alike are refused without a valid token
+    for (method, uri) in [
+        (Method::GET, "/weekly_data/1"),
+        (Method::POST, "/weekly_data/1"),
+        (Method::POST, "/del/1"),
+    ] {
+        for token in [None, Some("not-a-token")] {
+            let resp =
+                actix_web::test::call_service(&app, request(method.clone(), uri, token)).await;
+            assert_eq!(
+                resp.status(),
+                StatusCode::UNAUTHORIZED,
+                "{} {}",
+                method,
+                uri
+            );
+        }
+    }
+    assert_eq!(api.db.read_from_db().unwrap().rows.len(), 1);
+
+    // TAs and the admin get through, admin routes stay with the admin
+    for token in [token.as_str(), admin.as_str()] {
+        let resp = actix_web::test::call_service(
+            &app,
+            request(Method::GET, "/weekly_data/1", Some(token)),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let resp =
+        actix_web::test::call_service(&app, request(Method::POST, "/weeks/1/lock", Some(&token)))
+            .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::POST, "/weeks/1/lock", Some(&admin)))
+            .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_student_weekly_data() {
+    let api = TestApi::new(SqliteStorage::in_memory().unwrap());
+    let admin = get_auth_token();
+    // Out of week order, to see the handler sort them
+    api.state.lock().unwrap().rows.extend([
+        graded_row("Alice", 3, "yes", 30),
+        graded_row("Alice", 0, "no", 0),
+        graded_row("Bob", 2, "yes", 5),
+        graded_row("Alice", 1, "yes", 10),
+        graded_row("Alice", 2, "no", 0),
+    ]);
+    let app =
+        actix_web::test::init_service(api.app().service(individual::get_student_weekly_data)).await;
+    let request = |name: &str| {
+        actix_web::test::TestRequest::get()
+            .uri(&format!("/students/{}/weekly_data", name))
+            .insert_header(("Authorization", admin.as_str()))
+            .to_request()
+    };
+
+    // Oldest week first, without the week 0 enrollment row or other students
+    let resp = actix_web::test::call_service(&app, request("Alice")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["name"], "Alice");
+    let weeks: Vec<(i64, u64)> = body["weeks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row["week"].as_i64().unwrap(),
+                row["total"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(weeks, vec![(1, 10), (2, 0), (3, 30)]);
+
+    let resp = actix_web::test::call_service(&app, request("Nobody")).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_ta_writes_limited_to_group() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage
+        .upsert_rows(&[
+            group_row("Alice", 1, "Bala", "Group 1"),
+            group_row("Carol", 1, "Raj", "Group 2"),
+        ])
+        .unwrap();
+    let api = TestApi::new(storage);
+    let token = api.ta_token(ta("Bala"));
+    let app = actix_web::test::init_service(
+        api.app()
+            .service(weekly_data::add_weekly_data)
+            .service(weekly_data::delete_data),
+    )
+    .await;
+
+    // The row of the TA's own group is written, the other one fails
+    let rows = vec![
+        RowData {
+            fa: Some(3),
+            version: Some(1),
+            ..group_row("Alice", 1, "Bala", "Group 1")
+        },
+        RowData {
+            fa: Some(3),
+            version: Some(1),
+            ..group_row("Carol", 1, "Raj", "Group 2")
+        },
+    ];
+    let req = actix_web::test::TestRequest::post()
+        .uri("/weekly_data/1")
+        .insert_header(("Authorization", token.as_str()))
+        .set_json(&rows)
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["results"][0]["status"], "updated");
+    assert_eq!(body["results"][0]["version"], 2);
+    assert_eq!(body["results"][1]["status"], "failed");
+    assert!(body["results"][1].get("version").is_none());
+    assert_eq!(
+        body["results"][1]["reason"],
+        "row outside your assigned group"
+    );
+    let stored = api.db.read_from_db().unwrap().rows;
+    let fa = |name: &str| stored.iter().find(|row| row.name == name).unwrap().fa;
+    assert_eq!((fa("Alice"), fa("Carol")), (Some(3), Some(0)));
+
+    // Deleting a row of another group is refused outright
+    let req = actix_web::test::TestRequest::post()
+        .uri("/del/1")
+        .insert_header(("Authorization", token.as_str()))
+        .set_json(group_row("Carol", 1, "Raj", "Group 2"))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(api.state.lock().unwrap().rows.len(), 2);
+    assert_eq!(api.db.read_from_db().unwrap().rows.len(), 2);
+
+    // A row of their own group goes from memory and the database alike
+    let req = actix_web::test::TestRequest::post()
+        .uri("/del/1")
+        .insert_header(("Authorization", token.as_str()))
+        .set_json(group_row("Alice", 1, "Bala", "Group 1"))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(api.state.lock().unwrap().rows.len(), 1);
+    assert_eq!(api.db.read_from_db().unwrap().rows.len(), 1);
+}
+
+#[actix_web::test]
+async fn test_locked_week_writes() {
+    let storage = SqliteStorage::in_memory().unwrap();
+    storage
+        .upsert_rows(&[group_row("Alice", 1, "Bala", "Group 1")])
+        .unwrap();
+    let api = TestApi::new(storage);
+    let token = api.ta_token(ta("Bala"));
+    let admin = get_auth_token();
+    let app = actix_web::test::init_service(
+        api.app()
+            .service(week_locks::lock_week)
+            .service(weekly_data::add_weekly_data),
+    )
+    .await;
+    let req = actix_web::test::TestRequest::post()
+        .uri("/weeks/1/lock")
+        .insert_header(("Authorization", admin.as_str()))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let write = |uri: &str, token: &str, fa: u64| {
+        actix_web::test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Authorization", token))
+            .set_json(vec![RowData {
+                fa: Some(fa),
+                version: Some(1),
+                ..group_row("Alice", 1, "Bala", "Group 1")
+            }])
+            .to_request()
+    };
+    let fa = || api.db.read_from_db().unwrap().rows[0].fa;
+
+    // Writes to the locked week are turned away, the TA's override included
+    let resp = actix_web::test::call_service(&app, write("/weekly_data/1", &token, 3)).await;
+    assert_eq!(resp.status(), StatusCode::LOCKED);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["locked"], true);
+    let resp =
+        actix_web::test::call_service(&app, write("/weekly_data/1?override_lock=true", &token, 3))
+            .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = actix_web::test::call_service(&app, write("/weekly_data/1", &admin, 3)).await;
+    assert_eq!(resp.status(), StatusCode::LOCKED);
+    assert_eq!(fa(), Some(0));
+
+    // An admin writes it anyway with the override
+    let resp =
+        actix_web::test::call_service(&app, write("/weekly_data/1?override_lock=true", &admin, 4))
+            .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(fa(), Some(4));
+}
+
+#[actix_web::test]
+async fn test_admin_totp() {
+    let api = TestApi::new(SqliteStorage::in_memory().unwrap());
+    let admin = get_auth_token();
+    let token = api.ta_token(ta("Raj"));
+    let app = actix_web::test::init_service(
+        api.app()
+            .service(two_factor::get_totp_status)
+            .service(two_factor::enroll_totp)
+            .service(two_factor::verify_totp)
+            .service(two_factor::disable_totp),
+    )
+    .await;
+    let request = |method: Method, uri: &str, token: &str, code: Option<&str>| {
+        let req = actix_web::test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .insert_header(("Authorization", token));
+        match code {
+            Some(code) => req.insert_header((TOTP_HEADER, code)),
+            None => req,
+        }
+        .to_request()
+    };
+    let status = || async {
+        let resp =
+            actix_web::test::call_service(&app, request(Method::GET, "/admin/totp", &admin, None))
+                .await;
+        let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+        (body["enrolled"].clone(), body["confirmed"].clone())
+    };
+
+    let resp = actix_web::test::call_service(
+        &app,
+        request(Method::POST, "/admin/totp/enroll", &admin, None),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let secret = totp_rs::Secret::Encoded(body["secret"].as_str().unwrap().to_string())
+        .to_bytes()
+        .unwrap();
+    let totp = totp_rs::TOTP::new(totp_rs::Algorithm::SHA1, 6, 1, 30, secret).unwrap();
+    let code = totp.generate_current().unwrap();
+    assert_eq!(status().await, (true.into(), false.into()));
+
+    // Only the current code confirms the enrollment
+    let verify = |code: &str| {
+        actix_web::test::TestRequest::post()
+            .uri("/admin/totp/verify")
+            .peer_addr("203.0.113.7:5000".parse().unwrap())
+            .insert_header(("Authorization", admin.as_str()))
+            .set_json(serde_json::json!({ "code": code }))
+            .to_request()
+    };
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    let resp = actix_web::test::call_service(&app, verify(wrong)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let failures = || {
+        api.lockouts
+            .lock()
+            .unwrap()
+            .entries()
+            .iter()
+            .map(|e| e.failures)
+            .max()
+    };
+    assert_eq!(failures(), Some(1));
+    let resp = actix_web::test::call_service(&app, verify(&code)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(status().await, (true.into(), true.into()));
+    assert_eq!(failures(), None);
+
+    // Guarded operations then need the code, and stay with the admin
+    let resp =
+        actix_web::test::call_service(&app, request(Method::DELETE, "/admin/totp", &token, None))
+            .await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    for code in [None, Some(wrong)] {
+        let resp = actix_web::test::call_service(
+            &app,
+            request(Method::DELETE, "/admin/totp", &admin, code),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(status().await, (true.into(), true.into()));
+
+    // A code already used cannot be replayed, the next one goes through
+    let resp = actix_web::test::call_service(
+        &app,
+        request(Method::DELETE, "/admin/totp", &admin, Some(&code)),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let next = totp.generate(totp.next_step_current().unwrap());
+    let resp = actix_web::test::call_service(
+        &app,
+        request(Method::DELETE, "/admin/totp", &admin, Some(&next)),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(status().await, (false.into(), false.into()));
+}
+
+#[actix_web::test]
+async fn test_session_listing() {
+    let api = TestApi::new(SqliteStorage::in_memory().unwrap());
+    let admin = get_auth_token();
+    let (bala, raj) = (api.ta_token(ta("Bala")), api.ta_token(ta("Raj")));
+    let app = actix_web::test::init_service(
+        api.app()
+            .service(auth::get_sessions)
+            .service(auth::revoke_session),
+    )
+    .await;
+    let request = |method: Method, uri: &str, token: &str| {
+        actix_web::test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .peer_addr("203.0.113.7:5000".parse().unwrap())
+            .insert_header(("Authorization", token))
+            .insert_header(("User-Agent", "grading-laptop"))
+            .to_request()
+    };
+
+    // Sessions stay with the admin, while recording where TAs were last seen
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/auth/sessions", &raj)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/auth/sessions", &admin)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let sessions = body["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0]["ta"], "Raj");
+    assert_eq!(sessions[0]["ip"], "203.0.113.7");
+    assert_eq!(sessions[0]["user_agent"], "grading-laptop");
+    assert_eq!(sessions[1]["ta"], "Bala");
+    assert_eq!(body["admin"]["user_agent"], "grading-laptop");
+
+    // A revoked session's token stops working, the others carry on
+    let uri = format!("/auth/sessions/{}", sessions[0]["id"].as_str().unwrap());
+    let resp = actix_web::test::call_service(&app, request(Method::DELETE, &uri, &admin)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = actix_web::test::call_service(&app, request(Method::DELETE, &uri, &admin)).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/auth/sessions", &raj)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/auth/sessions", &bala)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_logout_revokes_token() {
+    let api = TestApi::new(SqliteStorage::in_memory().unwrap());
+    let admin = get_auth_token();
+    let token = api.ta_token(ta("Delcin"));
+    let app =
+        actix_web::test::init_service(api.app().service(auth::logout).service(auth::get_sessions))
+            .await;
+    let request = |method: Method, uri: &str, token: &str| {
+        actix_web::test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .insert_header(("Authorization", token))
+            .to_request()
+    };
+
+    let resp =
+        actix_web::test::call_service(&app, request(Method::POST, "/auth/logout", &token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::POST, "/auth/logout", &token)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/auth/sessions", &admin)).await;
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert!(body["sessions"].as_array().unwrap().is_empty());
+
+    // The admin token is revoked too, and both stay revoked after a restart
+    let resp =
+        actix_web::test::call_service(&app, request(Method::POST, "/auth/logout", &admin)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/auth/sessions", &admin)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let reloaded = RevocationList::load(api.db.get_ref()).unwrap();
+    assert!(reloaded.is_revoked(&token) && reloaded.is_revoked(&admin));
+    assert!(!reloaded.is_revoked("another-token"));
+}
+
+#[actix_web::test]
+async fn test_restore_reloads_week_locks() {
+    let dir = std::env::temp_dir().join(format!("restore_locks_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("classroom.db");
+    open_connection(&db)
+        .unwrap()
+        .execute_batch(CORE_TABLES)
+        .unwrap();
+    run_migrations(&db).unwrap();
+    let storage = SqliteStorage::new(create_pool(&db).unwrap());
+    let lock = |week: i32| WeekLock {
+        week,
+        locked_by: "admin".to_string(),
+        locked_at: "2026-01-01T00:00:00+00:00".to_string(),
+    };
+
+    // Week 1 was locked when the backup was taken, week 2 is locked now
+    storage.lock_week(&lock(1)).unwrap();
+    let backups = Backups::new(
+        Some(db.clone()),
+        BackupPolicy {
+            dir: dir.join("backup"),
+            ..BackupPolicy::default()
+        },
+    );
+    let backup = backups.snapshot(BackupReason::Scheduled).unwrap().unwrap();
+    storage.unlock_week(1).unwrap();
+    storage.lock_week(&lock(2)).unwrap();
+    let api = TestApi::new(storage);
+    let app = actix_web::test::init_service(
+        api.app()
+            .app_data(web::Data::new(backups))
+            .service(backups::restore_backup),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::post()
+        .uri(&format!("/admin/restore/{}", backup.file))
+        .insert_header(("Authorization", get_auth_token()))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(api.week_locks.lock().unwrap().weeks(), BTreeSet::from([1]));
+    assert_eq!(api.db.read_week_locks().unwrap(), vec![lock(1)]);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[actix_web::test]
+async fn test_checklist_records_caller() {
+    let api = TestApi::new(SqliteStorage::in_memory().unwrap());
+    let token = api.ta_token(ta("Raj"));
+    let app = actix_web::test::init_service(
+        api.app()
+            .service(checklist::complete_checklist_task)
+            .service(checklist::reopen_checklist_task),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::put()
+        .uri("/checklist/2/send_reminders")
+        .insert_header(("Authorization", token.as_str()))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let item = body["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["task"] == "send_reminders")
+        .unwrap();
+    assert_eq!(item["completed_by"], "Raj");
+    assert_eq!(body["pending"], 4);
+
+    // Reopening a task stays with organizers
+    let req = actix_web::test::TestRequest::delete()
+        .uri("/checklist/2/send_reminders")
+        .insert_header(("Authorization", token.as_str()))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_idempotency_key_reserved_while_applied() {
+    use actix_web::FromRequest;
+    let api = TestApi::new(SqliteStorage::in_memory().unwrap());
+    let key = || async {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header(("Idempotency-Key", "retry-1"))
+            .to_http_request();
+        IdempotencyKey::extract(&req).await.unwrap()
+    };
+
+    // A retry while the first try is applied is turned away with a 409
+    let mut first = key().await;
+    assert!(
+        first
+            .replay(&api.db, "Bala", "abc")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    let mut retry = key().await;
+    let conflict = retry.replay(&api.db, "Bala", "abc").await.unwrap().unwrap();
+    assert_eq!(conflict.status(), StatusCode::CONFLICT);
+    // Keys are per caller
+    let mut other = key().await;
+    assert!(other.replay(&api.db, "Raj", "abc").await.unwrap().is_none());
+
+    // Once answered, the retry gets the recorded response
+    first
+        .remember(
+            &api.db,
+            "Bala",
+            "abc".to_string(),
+            StatusCode::OK,
+            &serde_json::json!({ "message": "done" }),
+        )
+        .await;
+    drop(first);
+    let mut retry = key().await;
+    let replayed = retry.replay(&api.db, "Bala", "abc").await.unwrap().unwrap();
+    assert_eq!(replayed.status(), StatusCode::OK);
+    assert_eq!(
+        replayed.headers().get("idempotent-replayed").unwrap(),
+        "true"
+    );
+
+    // A try that failed before answering frees its key
+    drop(other);
+    let mut again = key().await;
+    assert!(again.replay(&api.db, "Raj", "abc").await.unwrap().is_none());
+}
+
+#[test]
+fn test_lockout_backoff() {
+    let mut lockouts = LockoutTracker::default();
+    let keys = vec!["ip:203.0.113.7".to_string()];
+    let start = chrono::Utc::now();
+
+    // Locked from the fifth failure on, twice as long with every further one
+    for _ in 0..4 {
+        lockouts.record_failure_at(&keys, start);
+    }
+    assert_eq!(lockouts.locked_until(&keys), None);
+    lockouts.record_failure_at(&keys, start);
+    assert_eq!(
+        lockouts.locked_until(&keys),
+        Some(start + chrono::Duration::seconds(30))
+    );
+    lockouts.record_failure_at(&keys, start);
+    assert_eq!(
+        lockouts.locked_until(&keys),
+        Some(start + chrono::Duration::seconds(60))
+    );
+    for _ in 0..20 {
+        lockouts.record_failure_at(&keys, start);
+    }
+    assert_eq!(
+        lockouts.locked_until(&keys),
+        Some(start + chrono::Duration::hours(1))
+    );
+
+    // Session-shaped guesses lock out the address too, just later
+    let address = vec!["ip:198.51.100.4".to_string()];
+    for _ in 0..19 {
+        lockouts.record_stale_session_at(&address, start);
+    }
+    assert_eq!(lockouts.locked_until(&address), None);
+    lockouts.record_stale_session_at(&address, start);
+    assert_eq!(
+        lockouts.locked_until(&address),
+        Some(start + chrono::Duration::seconds(30))
+    );
+    lockouts.record_success(&address);
+
+    // Success clears a key, and records outlive their longest lockout only
+    let other = vec!["credential:0000000000000001".to_string()];
+    lockouts.record_failure_at(&other, start);
+    lockouts.record_success(&keys);
+    assert_eq!(lockouts.locked_until(&keys), None);
+    lockouts.record_failure_at(&keys, start + chrono::Duration::hours(2));
+    let entries = lockouts.entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        (entries[0].key.as_str(), entries[0].failures),
+        ("ip:203.0.113.7", 1)
+    );
+}
+
+#[actix_web::test]
+async fn test_lockout_endpoints() {
+    let api = TestApi::new(SqliteStorage::in_memory().unwrap());
+    let app = actix_web::test::init_service(
+        api.app()
+            .service(auth::get_lockouts)
+            .service(auth::clear_lockout)
+            .service(auth::clear_all_lockouts),
+    )
+    .await;
+    let admin = get_auth_token();
+    let from = |peer: &str, method: Method, uri: &str, token: &str| {
+        actix_web::test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .peer_addr(format!("{}:5000", peer).parse().unwrap())
+            .insert_header(("Authorization", token))
+            .to_request()
+    };
+    let request = |method: Method, uri: &str, token: &str| from("203.0.113.7", method, uri, token);
+
+    // Stale session tokens count against themselves, and against the address
+    // only under the higher stale session threshold
+    for tab in 0..6 {
+        let stale = format!("{:064x}", tab);
+        let resp =
+            actix_web::test::call_service(&app, request(Method::GET, "/admin/lockouts", &stale))
+                .await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+    let address = api
+        .lockouts
+        .lock()
+        .unwrap()
+        .entries()
+        .into_iter()
+        .find(|entry| entry.key == "ip:203.0.113.7")
+        .unwrap();
+    assert_eq!((address.failures, address.stale_sessions), (0, 6));
+    assert_eq!(address.locked_until, None);
+    // The admin's own request then clears the address
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/admin/lockouts", &admin)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let entries: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    let keys: Vec<&str> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys.len(), 6);
+    assert!(keys.iter().all(|key| key.starts_with("credential:")));
+    let credential_key = keys[0].to_string();
+
+    // Guessed tokens lock out the address, until an admin clears it
+    for _ in 0..5 {
+        actix_web::test::call_service(&app, request(Method::GET, "/admin/lockouts", "guess")).await;
+    }
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/admin/lockouts", &admin)).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp.headers().contains_key("Retry-After"));
+
+    let clear = |key: &str| {
+        from(
+            "198.51.100.9",
+            Method::DELETE,
+            &format!("/admin/lockouts/{}", key),
+            &admin,
+        )
+    };
+    let resp = actix_web::test::call_service(&app, clear("ip:203.0.113.7")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = actix_web::test::call_service(&app, clear(&credential_key)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = actix_web::test::call_service(&app, clear("ip:203.0.113.7")).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::GET, "/admin/lockouts", &admin)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Clearing everything reports how many records went
+    api.lockouts
+        .lock()
+        .unwrap()
+        .record_failure(&["ip:198.51.100.1".to_string(), "ip:198.51.100.2".to_string()]);
+    let resp =
+        actix_web::test::call_service(&app, request(Method::DELETE, "/admin/lockouts", &admin))
+            .await;
+    let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+    assert_eq!(body["cleared"], 8);
+    assert!(api.lockouts.lock().unwrap().entries().is_empty());
+}
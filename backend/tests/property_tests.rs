@@ -0,0 +1,162 @@
+use backend::handlers::auth::TA;
+use backend::services::grouping::{assign_groups, rotation_tas};
+use backend::services::invariants::{check_grouping, check_totals};
+use backend::services::scoring::{
+    CriterionScale, ExerciseResult, apply_exercise_result, criteria, row_total,
+};
+use backend::services::weekly::build_week_rows;
+use backend::utils::types::RowData;
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+fn yes_no(value: bool) -> Option<String> {
+    Some(if value { "yes" } else { "no" }.to_string())
+}
+
+// Attendance as recorded in the sheet, including rows never marked
+fn attendance() -> impl Strategy<Value = Option<String>> {
+    prop_oneof![
+        6 => Just(Some("yes".to_string())),
+        3 => Just(Some("no".to_string())),
+        1 => Just(None),
+    ]
+}
+
+// A graded row whose total matches its components
+fn graded_row() -> impl Strategy<Value = RowData> {
+    (
+        attendance(),
+        prop::array::uniform4(0u64..=5),
+        prop::array::uniform3(0u64..=5),
+        prop::array::uniform4(any::<bool>()),
+    )
+        .prop_map(|(attendance, gd, bonus, exercise)| {
+            let mut row = RowData {
+                name: String::new(),
+                group_id: "Group 1".to_string(),
+                ta: None,
+                attendance,
+                fa: Some(gd[0]),
+                fb: Some(gd[1]),
+                fc: Some(gd[2]),
+                fd: Some(gd[3]),
+                bonus_attempt: Some(bonus[0]),
+                bonus_answer_quality: Some(bonus[1]),
+                bonus_follow_up: Some(bonus[2]),
+                exercise_submitted: yes_no(exercise[0]),
+                exercise_test_passing: yes_no(exercise[1]),
+                exercise_good_documentation: yes_no(exercise[2]),
+                exercise_good_structure: yes_no(exercise[3]),
+                total: None,
+                notes: None,
+                mail: String::new(),
+                week: 1,
+                participant_id: None,
+                public_id: None,
+                student_id: None,
+                version: None,
+                commit_count: None,
+                last_commit_at: None,
+                repo_url: None,
+            };
+            row.total = Some(row_total(&row));
+            row
+        })
+}
+
+// A cohort of uniquely named students for one week
+fn cohort(max_size: usize) -> impl Strategy<Value = Vec<RowData>> {
+    prop::collection::vec(graded_row(), 0..max_size).prop_map(|rows| {
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, mut row)| {
+                row.name = format!("Student{:03}", i);
+                row.mail = format!("student{:03}@example.com", i);
+                row
+            })
+            .collect()
+    })
+}
+
+// Score a row has for a rubric criterion, yes/no columns counting as 0 or 1
+fn criterion_score(row: &RowData, key: &str) -> u64 {
+    let yes = |value: &Option<String>| u64::from(value.as_deref() == Some("yes"));
+    match key {
+        "fa" => row.fa.unwrap_or(0),
+        "fb" => row.fb.unwrap_or(0),
+        "fc" => row.fc.unwrap_or(0),
+        "fd" => row.fd.unwrap_or(0),
+        "bonus_attempt" => row.bonus_attempt.unwrap_or(0),
+        "bonus_answer_quality" => row.bonus_answer_quality.unwrap_or(0),
+        "bonus_follow_up" => row.bonus_follow_up.unwrap_or(0),
+        "exercise_submitted" => yes(&row.exercise_submitted),
+        "exercise_test_passing" => yes(&row.exercise_test_passing),
+        "exercise_good_documentation" => yes(&row.exercise_good_documentation),
+        "exercise_good_structure" => yes(&row.exercise_good_structure),
+        other => panic!("unknown criterion {}", other),
+    }
+}
+
+proptest! {
+    #[test]
+    fn rubric_matches_totals(row in graded_row()) {
+        let rubric = criteria();
+        let total: u64 = rubric.iter().map(|c| criterion_score(&row, c.key) * c.points).sum();
+        prop_assert_eq!(total, row_total(&row));
+        for criterion in &rubric {
+            prop_assert!(criterion_score(&row, criterion.key) <= criterion.scale.max_score());
+            if criterion.key.starts_with("exercise_") {
+                prop_assert_eq!(criterion.scale, CriterionScale::YesNo);
+            }
+        }
+    }
+
+    #[test]
+    fn every_present_student_gets_one_group(rows in cohort(80), week in 1i32..20) {
+        let grouped = assign_groups(rows.clone(), week, &rotation_tas());
+        prop_assert_eq!(grouped.len(), rows.len());
+        prop_assert_eq!(check_grouping(&rows, &grouped), vec![]);
+    }
+
+    #[test]
+    fn absent_students_go_to_setu(rows in cohort(40), week in 1i32..20) {
+        let setu = format!("{:?}", TA::from_name("Setu").unwrap());
+        for row in assign_groups(rows, week, &rotation_tas()) {
+            if row.attendance.as_deref() == Some("no") {
+                prop_assert_eq!(row.ta.as_deref(), Some(setu.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn totals_equal_component_sums(mut rows in cohort(40), passing in prop::collection::vec(any::<bool>(), 40)) {
+        prop_assert_eq!(check_totals(&rows), vec![]);
+
+        // Classroom results keep totals in line with the exercise columns
+        for (row, tests_passing) in rows.iter_mut().zip(passing) {
+            apply_exercise_result(row, &ExerciseResult {
+                submitted: true,
+                tests_passing,
+                commit_count: None,
+                last_commit_at: None,
+                repo_url: None,
+            });
+        }
+        prop_assert_eq!(check_totals(&rows), vec![]);
+    }
+
+    #[test]
+    fn new_weeks_start_consistent(rows in cohort(60), week in 1i32..20) {
+        let (built, _) = build_week_rows(rows.clone(), &[], week + 1, &rotation_tas(), &[], &HashMap::new());
+        prop_assert_eq!(built.len(), rows.len());
+        prop_assert_eq!(check_grouping(&rows, &built), vec![]);
+        prop_assert_eq!(check_totals(&built), vec![]);
+    }
+    #[test]
+    fn regenerating_a_week_changes_nothing(rows in cohort(60), week in 1i32..20) {
+        let tas = rotation_tas();
+        let (built, _) = build_week_rows(rows.clone(), &[], week + 1, &tas, &[], &HashMap::new());
+        let (again, _) = build_week_rows(rows, &built, week + 1, &tas, &[], &HashMap::new());
+        prop_assert_eq!(again, built);
+    }
+}
@@ -0,0 +1,112 @@
+import React, { useEffect, useState } from 'react';
+import { Mail, Send } from 'lucide-react';
+import { fetchCommunications, logCommunication } from '../../services/studentService';
+import type { Communication, CommunicationKind } from '../../types/student';
+
+interface CommunicationLogProps {
+  participantName: string;
+}
+
+const KIND_LABELS: Record<CommunicationKind, string> = {
+  email: 'Email',
+  reminder: 'Reminder',
+  invite_nudge: 'Invite nudge',
+  other: 'Other',
+};
+
+export const CommunicationLog = ({ participantName }: CommunicationLogProps) => {
+  const [communications, setCommunications] = useState<Communication[]>([]);
+  const [kind, setKind] = useState<CommunicationKind>('email');
+  const [subject, setSubject] = useState('');
+  const [note, setNote] = useState('');
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    fetchCommunications(participantName)
+      .then(setCommunications)
+      .catch((err) => {
+        console.warn('Failed to fetch communications:', err);
+        setError('Could not load communication history');
+      });
+  }, [participantName]);
+
+  const handleSubmit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    if (!subject.trim()) return;
+    try {
+      const entry = await logCommunication(participantName, kind, subject, note);
+      setCommunications((prev) => [entry, ...prev]);
+      setSubject('');
+      setNote('');
+      setError(null);
+    } catch (err) {
+      console.error('Failed to log communication:', err);
+      setError('Could not save communication');
+    }
+  };
+
+  return (
+    <div className="mb-8 bg-zinc-800 rounded-lg p-6">
+      <h2 className="text-lg font-semibold text-zinc-100 mb-4 flex items-center">
+        <Mail className="h-5 w-5 mr-2" />
+        Communications
+      </h2>
+
+      <form onSubmit={handleSubmit} className="flex flex-wrap gap-2 mb-4">
+        <select
+          value={kind}
+          onChange={(e) => setKind(e.target.value as CommunicationKind)}
+          className="bg-zinc-700 text-zinc-100 rounded-md px-2 py-1 text-sm"
+        >
+          {Object.entries(KIND_LABELS).map(([value, label]) => (
+            <option key={value} value={value}>{label}</option>
+          ))}
+        </select>
+        <input
+          value={subject}
+          onChange={(e) => setSubject(e.target.value)}
+          placeholder="Subject"
+          className="flex-1 min-w-48 bg-zinc-700 text-zinc-100 rounded-md px-2 py-1 text-sm"
+        />
+        <input
+          value={note}
+          onChange={(e) => setNote(e.target.value)}
+          placeholder="Note (optional)"
+          className="flex-1 min-w-48 bg-zinc-700 text-zinc-100 rounded-md px-2 py-1 text-sm"
+        />
+        <button
+          type="submit"
+          className="flex items-center space-x-1 px-3 py-1 bg-amber-500 text-white rounded-md hover:bg-amber-600 transition-colors text-sm"
+        >
+          <Send className="h-4 w-4" />
+          <span>Log</span>
+        </button>
+      </form>
+
+      {error && <p className="text-sm text-red-400 mb-2">{error}</p>}
+
+      {communications.length === 0 ? (
+        <p className="text-sm text-zinc-500">No communications logged yet.</p>
+      ) : (
+        <ul className="divide-y divide-zinc-700">
+          {communications.map((c) => (
+            <li key={c.id} className="py-2 text-sm">
+              <div className="flex justify-between text-zinc-300">
+                <span>
+                  <span className="inline-flex px-2 py-0.5 mr-2 rounded-md text-xs bg-zinc-700">
+                    {KIND_LABELS[c.kind]}
+                  </span>
+                  {c.subject}
+                </span>
+                <span className="text-zinc-500">
+                  {new Date(c.sent_at).toLocaleString()} · {c.sent_by}
+                </span>
+              </div>
+              {c.note && <p className="text-zinc-400 mt-1">{c.note}</p>}
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+};
@@ -1,6 +1,7 @@
 import React from 'react';
 import { computeTotal } from '../../utils/calculations';
 import type { TableRowData } from '../../types/student';
+import { authHeaders } from '../../services/auth';
 const baseUrl = import.meta.env.VITE_API_BASE_URL;
 
 
@@ -120,9 +121,14 @@ export const StudentRow: React.FC<StudentRowProps> = ({
   };
 
   const fetchStudentRepoLink = async (week: number, studentName: string) => {
+    if (person.repoUrl) {
+      window.open(person.repoUrl, '_blank');
+      return;
+    }
     try {
       const response = await fetch(
-        `${baseUrl}/students/${week}/${encodeURIComponent(studentName)}`
+        `${baseUrl}/students/${week}/${encodeURIComponent(studentName)}`,
+        { headers: authHeaders() }
       );
 
       if (!response.ok) {
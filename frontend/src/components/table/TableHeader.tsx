@@ -18,6 +18,7 @@ interface TableHeaderProps {
   isEditing: boolean;
   onEdit: () => void;
   onSave: () => void;
+  onGenerate: () => void;
   onAddNew: () => void;
   onDownloadCSV: () => void;
   onClearFilters: () => void;
@@ -25,6 +26,7 @@ interface TableHeaderProps {
 }
 
 export const TableHeader: React.FC<TableHeaderProps> = ({
+  week,
   onWeekChange,
   searchTerm,
   onSearchChange,
@@ -41,6 +43,7 @@ export const TableHeader: React.FC<TableHeaderProps> = ({
   isEditing,
   onEdit,
   onSave,
+  onGenerate,
   onAddNew,
   onDownloadCSV,
   onClearFilters,
@@ -143,6 +146,15 @@ export const TableHeader: React.FC<TableHeaderProps> = ({
         </div>
 
         <div className="flex gap-2">
+          {week >= 1 && (
+            <button
+              onClick={onGenerate}
+              disabled={isEditing}
+              className="cursor-pointer px-4 py-2 bg-orange-400 hover:bg-orange-500 text-white rounded disabled:opacity-50"
+            >
+              Generate Week
+            </button>
+          )}
           <button
             onClick={onAddNew}
             className="cursor-pointer px-4 py-2 bg-orange-400 hover:bg-orange-500 text-white rounded"
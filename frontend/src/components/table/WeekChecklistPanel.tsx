@@ -0,0 +1,100 @@
+import { useCallback, useEffect, useState } from 'react';
+import { CheckCircle2, Circle } from 'lucide-react';
+import { authHeaders } from '../../services/auth';
+import type { WeekChecklist, WeekTask } from '../../types/student';
+
+const baseUrl = import.meta.env.VITE_API_BASE_URL;
+
+const TASK_LABELS: Record<WeekTask, string> = {
+  sync_roster: 'Sync roster',
+  generate_groups: 'Generate groups',
+  publish_announcement: 'Publish announcement',
+  send_reminders: 'Send reminders',
+  finalize_grades: 'Finalize grades',
+};
+
+interface WeekChecklistPanelProps {
+  week: number;
+}
+
+export const WeekChecklistPanel = ({ week }: WeekChecklistPanelProps) => {
+  const [checklist, setChecklist] = useState<WeekChecklist | null>(null);
+  const [pendingWeeks, setPendingWeeks] = useState<WeekChecklist[]>([]);
+
+  const loadPending = useCallback(() => {
+    fetch(`${baseUrl}/checklist`, { headers: authHeaders() })
+      .then((res) => (res.ok ? res.json() : []))
+      .then(setPendingWeeks)
+      .catch((err) => console.warn('Failed to fetch pending checklists:', err));
+  }, []);
+
+  useEffect(() => {
+    loadPending();
+    if (week < 1) {
+      setChecklist(null);
+      return;
+    }
+    fetch(`${baseUrl}/checklist/${week}`, { headers: authHeaders() })
+      .then((res) => (res.ok ? res.json() : null))
+      .then(setChecklist)
+      .catch((err) => console.warn(`Failed to fetch checklist for week ${week}:`, err));
+  }, [week, loadPending]);
+
+  const toggleTask = (task: WeekTask, completed: boolean) => {
+    fetch(`${baseUrl}/checklist/${week}/${task}`, {
+      method: completed ? 'DELETE' : 'PUT',
+      headers: authHeaders(),
+    })
+      .then((res) => {
+        if (!res.ok) throw new Error(`Server error: ${res.status}`);
+        return res.json();
+      })
+      .then((updated: WeekChecklist) => {
+        setChecklist(updated);
+        loadPending();
+      })
+      .catch((err) => console.error(`Failed to update task ${task}:`, err));
+  };
+
+  const otherPending = pendingWeeks.filter((w) => w.week !== week);
+
+  return (
+    <div className="my-4 bg-zinc-800 rounded-lg p-4 text-sm">
+      {checklist && (
+        <div className="flex flex-wrap gap-4">
+          {checklist.items.map((item) => {
+            const completed = item.completed_at !== null;
+            return (
+              <button
+                key={item.task}
+                onClick={() => toggleTask(item.task, completed)}
+                title={
+                  completed
+                    ? `Done by ${item.completed_by} at ${new Date(item.completed_at!).toLocaleString()}`
+                    : 'Pending'
+                }
+                className="flex items-center space-x-1 hover:text-zinc-100"
+              >
+                {completed ? (
+                  <CheckCircle2 className="h-4 w-4 text-green-400" />
+                ) : (
+                  <Circle className="h-4 w-4 text-zinc-500" />
+                )}
+                <span>{TASK_LABELS[item.task]}</span>
+              </button>
+            );
+          })}
+        </div>
+      )}
+
+      {otherPending.length > 0 && (
+        <p className="mt-2 text-amber-400">
+          Pending:{' '}
+          {otherPending
+            .map((w) => `Week ${w.week} (${w.pending})`)
+            .join(', ')}
+        </p>
+      )}
+    </div>
+  );
+};
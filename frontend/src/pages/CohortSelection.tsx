@@ -1,6 +1,7 @@
 import { useEffect } from 'react';
 import { useNavigate } from 'react-router-dom';
 import CohortCard from '../components/CohortCard';
+import { logout } from '../services/auth';
 
 export const CohortSelection = () => {
   const navigate = useNavigate();
@@ -34,6 +35,11 @@ export const CohortSelection = () => {
     navigate('/admin', { state: { token } });
   };
 
+  const handleLogout = async () => {
+    await logout();
+    navigate('/');
+  };
+
   return (
     <div className="min-h-screen bg-zinc-900 font-mono flex flex-col items-center justify-center">
       <div className="text-center mb-8">
@@ -54,6 +60,13 @@ export const CohortSelection = () => {
           />
         ))}
       </div>
+
+      <button
+        onClick={handleLogout}
+        className="mt-8 text-sm text-zinc-400 hover:text-zinc-200 transition-colors"
+      >
+        Sign out
+      </button>
     </div>
   );
 };
@@ -1,5 +1,6 @@
 import { useState, useEffect } from 'react';
 import DataTable from '../components/DataTable.tsx';
+import { authHeaders } from '../services/auth';
 
 const FeedbackTable = () => {
   const [feedbacks, setFeedbacks] = useState<any[]>([]);
@@ -129,7 +130,9 @@ const FeedbackTable = () => {
         // Replace with your actual API call
         console.log('Fetching feedbacks from API...');
         const baseUrl = import.meta.env.VITE_API_BASE_URL;
-        const response = await fetch(`${baseUrl}/feedback/lbtcl`);
+        const response = await fetch(`${baseUrl}/feedback/lbtcl`, {
+          headers: authHeaders(),
+        });
         
         const data = await response.json();
 
@@ -2,13 +2,15 @@ import { useEffect, useState } from 'react';
 import { useNavigate, useLocation } from 'react-router-dom';
 import {
   redirectToDiscordAuth,
-  loginWithEmail,
   handleDiscordCallback,
+  requestMagicLink,
+  verifyMagicLink,
 } from '../services/auth';
 
 function Login() {
   const [email, setEmail] = useState('');
   const [error, setError] = useState<string | null>(null);
+  const [notice, setNotice] = useState<string | null>(null);
   const navigate = useNavigate();
   const location = useLocation();
 
@@ -17,12 +19,27 @@ function Login() {
     handleDiscordCallback(location, navigate);
   }, [location, navigate]);
 
-  const handleEmailLogin = async () => {
-    const result = await loginWithEmail(email);
-    if (result.success && result.token) {
-      navigate('/select', { state: { token: result.token } });
+  // Handle an emailed sign-in link
+  useEffect(() => {
+    const magicToken = new URLSearchParams(location.search).get('magic');
+    if (!magicToken) return;
+    verifyMagicLink(magicToken).then(result => {
+      if (result.success && result.token) {
+        navigate('/select', { state: { token: result.token } });
+      } else {
+        setError(result.error || 'Login failed');
+      }
+    });
+  }, [location.search, navigate]);
+
+  const handleMagicLink = async () => {
+    const result = await requestMagicLink(email);
+    if (result.success) {
+      setError(null);
+      setNotice('Check your inbox for a sign-in link');
     } else {
-      setError(result.error || 'Login failed');
+      setNotice(null);
+      setError(result.error || 'Could not send sign-in link');
     }
   };
 
@@ -68,7 +85,7 @@ function Login() {
               placeholder="Enter your email address"
               onKeyPress={e => {
                 if (e.key === 'Enter') {
-                  handleEmailLogin();
+                  handleMagicLink();
                 }
               }}
             />
@@ -80,7 +97,7 @@ function Login() {
           {/* Email Sign In Button */}
           <button
             className="b-0 w-full py-4 text-base font-semibold bg-orange-500 text-white rounded-lg hover:bg-orange-600 transition-all duration-200 shadow-lg hover:shadow-orange-500/20 flex items-center justify-center space-x-3"
-            onClick={handleEmailLogin}
+            onClick={handleMagicLink}
           >
             <svg
               className="w-5 h-5"
@@ -95,9 +112,15 @@ function Login() {
                 d="M3 8l7.89 4.26a2 2 0 002.22 0L21 8M5 19h14a2 2 0 002-2V7a2 2 0 00-2-2H5a2 2 0 00-2 2v10a2 2 0 002 2z"
               />
             </svg>
-            <span>Sign In with Email</span>
+            <span>Email me a sign-in link</span>
           </button>
 
+          {notice && (
+            <div className="bg-green-500/10 border border-green-500/30 rounded-lg p-4 text-sm text-green-400">
+              {notice}
+            </div>
+          )}
+
           {/* Error message */}
           {error && (
             <div className="bg-red-500/10 border border-red-500/30 rounded-lg p-4 animate-pulse">
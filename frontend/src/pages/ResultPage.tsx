@@ -1,5 +1,6 @@
 import { useState, useEffect, type JSX } from 'react';
 import { useNavigate } from 'react-router-dom';
+import { authHeaders } from '../services/auth';
 // API Response Types (matching your Rust RowData struct)
 
 // Frontend Display Types
@@ -28,7 +29,9 @@ export const ResultPage: React.FC<ResultPageProps> = () => {
     const fetchResults = async (): Promise<void> => {
       try {
         setLoading(true);
-        const response: Response = await fetch(`${baseUrl}/students/total_scores`);
+        const response: Response = await fetch(`${baseUrl}/students/total_scores`, {
+          headers: authHeaders(),
+        });
         
         if (!response.ok) {
           throw new Error('Failed to fetch results');
@@ -7,6 +7,7 @@ import { StudentBackground } from '../components/student/StudentBackground';
 import { StudentSummary } from '../components/student/StudentSummary';
 import { WeeklyProgressChart } from '../components/student/WeeklyProgressChart';
 import { WeeklyBreakdownCard } from '../components/student/WeeklyBreakdownCard';
+import { CommunicationLog } from '../components/student/CommunicationLog';
 
 import { fetchGithubUsername, fetchStudentData, fetchStudentBackgroundData } from '../services/studentService';
 import { getStudentNameFromUrl, exportStudentData } from '../utils/studentUtils';
@@ -200,6 +201,9 @@ const StudentDetailPage = () => {
         {/* Summary Stats */}
         <StudentSummary stats={stats} />
         
+        {/* Communications */}
+        <CommunicationLog participantName={getStudentName() ?? student.name} />
+
         {/* Progress Chart */}
         <WeeklyProgressChart weeklyData={validWeeks} />
         
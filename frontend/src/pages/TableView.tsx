@@ -5,11 +5,13 @@ import { TableHeader } from '../components/table/TableHeader';
 import { StudentTableGrid } from '../components/table/StudentTableGrid';
 import { AddStudentModal } from '../components/table/AddStudentModal';
 import { TableContextMenu } from '../components/table/TableContextMenu';
+import { WeekChecklistPanel } from '../components/table/WeekChecklistPanel';
 
 import { computeTotal } from '../utils/calculations';
 import type { TableRowData } from '../types/student';
+import { authHeaders, fetchWithSecondFactor } from '../services/auth';
+import { fetchBranding } from '../services/studentService';
 
-const AUTH_TOKEN = import.meta.env.VITE_AUTH_TOKEN;
 
 // API interface for the table view
 interface ApiStudentEntry {
@@ -31,6 +33,43 @@ interface ApiStudentEntry {
   exercise_good_structure?: string;
   week: number;
   total?: number;
+  notes?: string | null;
+  commit_count?: number | null;
+  last_commit_at?: string | null;
+  repo_url?: string | null;
+  version?: number | null;
+}
+
+interface RowResult {
+  row: number;
+  name: string;
+  week: number;
+  status: 'created' | 'updated' | 'unchanged' | 'failed';
+  reason?: string;
+  version?: number;
+}
+
+interface SyncWarning {
+  kind: string;
+  message: string;
+  github_username?: string;
+}
+
+interface ConstraintViolation {
+  constraint_id: number;
+  kind: 'together' | 'apart';
+  first: string;
+  second: string;
+  reason: string;
+}
+
+interface WeeklyDataResponse {
+  data: ApiStudentEntry[];
+  meta: {
+    week: number;
+    warnings: SyncWarning[];
+    constraint_violations: ConstraintViolation[];
+  };
 }
 
 const TableView: React.FC = () => {
@@ -57,6 +96,8 @@ const TableView: React.FC = () => {
     targetId: number | null;
   }>({ visible: false, x: 0, y: 0, targetId: null });
   const [totalCount, setTotalCount] = useState<number | null>(null);
+  const [notice, setNotice] = useState<string | null>(null);
+  const [programName, setProgramName] = useState('Learning Bitcoin From Command Line');
   const [weeklyData, setWeeklyData] = useState<{
     week: number;
     attended: number;
@@ -71,74 +112,113 @@ const TableView: React.FC = () => {
   const baseUrl = import.meta.env.VITE_API_BASE_URL;
 
   // --- DATA FETCHING ---
-  const fetchWeeklyData = useCallback((selectedWeek: number) => {
-    fetch(`${baseUrl}/weekly_data/${selectedWeek}`, {
-      headers: { Authorization: `${AUTH_TOKEN}` },
-    })
-      .then(response => {
-        if (!response.ok) {
-          return response.text().then(text => {
-            let errorDetail = text;
-            try {
-              const jsonError = JSON.parse(text);
-              errorDetail = jsonError.message || text;
-            } catch {
-              /* ignore */
-            }
-            throw new Error(
-              `Server error: ${response.status} - ${errorDetail}`
-            );
+  // Shows the rows of a week as answered by `request`. Failures are shown to
+  // the user; a failed load empties the table, a failed generation keeps the
+  // stored rows on screen.
+  const showRows = useCallback(
+    (
+      selectedWeek: number,
+      request: Promise<Response>,
+      clearOnError: boolean
+    ) => {
+      request
+        .then(response => {
+          if (!response.ok) {
+            return response.text().then(text => {
+              let errorDetail = text;
+              try {
+                const jsonError = JSON.parse(text);
+                errorDetail = jsonError.message || text;
+              } catch {
+                /* ignore */
+              }
+              throw new Error(
+                errorDetail || `Server error: ${response.status}`
+              );
+            });
+          }
+          return response.json();
+        })
+        .then(({ data: apiData, meta }: WeeklyDataResponse) => {
+          meta.warnings.forEach(w =>
+            console.warn(`Classroom sync (${w.kind}): ${w.message}`)
+          );
+          meta.constraint_violations.forEach(v =>
+            console.warn(`Grouping constraint not honored: ${v.reason}`)
+          );
+          const formattedData = apiData.map((person, index) => {
+            const gdScore = {
+              fa: person.fa || 0,
+              fb: person.fb || 0,
+              fc: person.fc || 0,
+              fd: person.fd || 0,
+            };
+            const bonusScore = {
+              attempt: person.bonus_attempt || 0,
+              good: person.bonus_answer_quality || 0,
+              followUp: person.bonus_follow_up || 0,
+            };
+            const exerciseScore = {
+              Submitted: person.exercise_submitted === 'yes',
+              privateTest: person.exercise_test_passing === 'yes',
+              goodStructure: person.exercise_good_structure === 'yes',
+              goodDoc: person.exercise_good_documentation === 'yes',
+            };
+            const rowDataShape: Omit<TableRowData, 'id' | 'total'> = {
+              name: person.name,
+              email: person.mail || '',
+              group: person.group_id,
+              ta: person.ta || 'N/A',
+              attendance: person.attendance === 'yes',
+              gdScore,
+              bonusScore,
+              exerciseScore,
+              week: selectedWeek,
+              notes: person.notes ?? undefined,
+              repoUrl: person.repo_url ?? undefined,
+              version: person.version ?? undefined,
+            };
+            const rowData: TableRowData = {
+              id: index + 1,
+              ...rowDataShape,
+              total: computeTotal(rowDataShape),
+            };
+            return rowData;
           });
-        }
-        return response.json();
-      })
-      .then((apiData: ApiStudentEntry[]) => {
-        const formattedData = apiData.map((person, index) => {
-          const gdScore = {
-            fa: person.fa || 0,
-            fb: person.fb || 0,
-            fc: person.fc || 0,
-            fd: person.fd || 0,
-          };
-          const bonusScore = {
-            attempt: person.bonus_attempt || 0,
-            good: person.bonus_answer_quality || 0,
-            followUp: person.bonus_follow_up || 0,
-          };
-          const exerciseScore = {
-            Submitted: person.exercise_submitted === 'yes',
-            privateTest: person.exercise_test_passing === 'yes',
-            goodStructure: person.exercise_good_structure === 'yes',
-            goodDoc: person.exercise_good_documentation === 'yes',
-          };
-          const rowDataShape: Omit<TableRowData, 'id' | 'total'> = {
-            name: person.name,
-            email: person.mail || '',
-            group: person.group_id,
-            ta: person.ta || 'N/A',
-            attendance: person.attendance === 'yes',
-            gdScore,
-            bonusScore,
-            exerciseScore,
-            week: selectedWeek,
-          };
-          const rowData: TableRowData = {
-            id: index + 1,
-            ...rowDataShape,
-            total: computeTotal(rowDataShape),
-          };
-          return rowData;
+          setData(formattedData);
+          setNotice(
+            formattedData.length === 0 && selectedWeek >= 1
+              ? `Week ${selectedWeek} has no rows yet. Generate it to build the groups.`
+              : null
+          );
+        })
+        .catch(error => {
+          console.error(`Error fetching data for week ${selectedWeek}:`, error);
+          setNotice(error instanceof Error ? error.message : String(error));
+          if (clearOnError) setData([]);
         });
-        setData(formattedData);
-      })
-      .catch(error => {
-        console.error(`Error fetching data for week ${selectedWeek}:`, error);
-        setData([]);
-      });
-  }, []);
+    },
+    []
+  );
+
+  // Loading only reads the stored rows, generation is explicit
+  const fetchWeeklyData = useCallback(
+    (selectedWeek: number) => {
+      showRows(
+        selectedWeek,
+        fetch(`${baseUrl}/weekly_data/${selectedWeek}`, {
+          headers: authHeaders(),
+        }),
+        true
+      );
+    },
+    [showRows]
+  );
 
   const getWeeklyData = useCallback((week: number) => {
-    fetch(`${baseUrl}/attendance/weekly_counts/${week}`)
+    fetch(`${baseUrl}/attendance/weekly_counts/${week}`, {
+      headers: authHeaders(),
+    })
       .then(res => res.json())
       .then(apiData => {
         if (Array.isArray(apiData)) {
@@ -166,12 +246,18 @@ const TableView: React.FC = () => {
   }, [fetchWeeklyData, getWeeklyData, week]);
 
   useEffect(() => {
-    fetch(`${baseUrl}/students/count`)
+    fetch(`${baseUrl}/students/count`, { headers: authHeaders() })
       .then(res => res.json())
       .then(data => setTotalCount(data.count))
       .catch(err => console.error('Error fetching total count:', err));
   }, []);
 
+  useEffect(() => {
+    fetchBranding()
+      .then(branding => setProgramName(branding.program_name))
+      .catch(err => console.warn('Failed to fetch branding:', err));
+  }, []);
+
   // --- COMPUTED DATA ---
   const taOptions = useMemo(() => {
     if (!data || data.length === 0) return ['All TAs'];
@@ -243,6 +329,21 @@ const TableView: React.FC = () => {
     setEditedRows([]);
   };
 
+  // Syncs and regroups the week, answered with its rows like the GET
+  const handleGenerate = () => {
+    showRows(
+      week,
+      fetch(`${baseUrl}/weekly_data/${week}/generate`, {
+        method: 'POST',
+        headers: authHeaders(),
+      }),
+      false
+    );
+    setIsEditing(false);
+    setEditedRows([]);
+    getWeeklyData(week);
+  };
+
   const handleStudentClick = (studentName: string) => {
     navigate(`/student?student=${encodeURIComponent(studentName)}`);
   };
@@ -276,11 +377,13 @@ const TableView: React.FC = () => {
       exercise_good_documentation: p.exerciseScore.goodDoc ? 'yes' : 'no',
       exercise_good_structure: p.exerciseScore.goodStructure ? 'yes' : 'no',
       total: computeTotal(p),
+      notes: p.notes,
+      version: p.version,
     }));
 
     fetch(`${baseUrl}/weekly_data/${week}`, {
       method: 'POST',
-      headers: { 'Content-Type': 'application/json' },
+      headers: { 'Content-Type': 'application/json', ...authHeaders() },
       body: JSON.stringify(payload),
     })
       .then(r => {
@@ -290,6 +393,25 @@ const TableView: React.FC = () => {
         getWeeklyData(week);
         return r.json();
       })
+      .then((body: { results?: RowResult[] }) => {
+        const results = body.results ?? [];
+        // Later saves of the same rows need the versions just written
+        const versions = new Map<number, number>();
+        results.forEach(r => {
+          const saved = editedRows[r.row];
+          if (saved && r.version !== undefined)
+            versions.set(saved.id, r.version);
+        });
+        if (versions.size > 0) {
+          setData(prevData =>
+            prevData.map(p =>
+              versions.has(p.id) ? { ...p, version: versions.get(p.id) } : p
+            )
+          );
+        }
+        const failed = results.filter(r => r.status === 'failed');
+        if (failed.length > 0) console.error('Some rows were not saved', failed);
+      })
       .catch(e => console.error('Save failed', e));
   };
 
@@ -321,11 +443,12 @@ const TableView: React.FC = () => {
         ? 'yes'
         : 'no',
       total: computeTotal(studentData),
+      notes: studentData.notes,
     };
 
     fetch(`${baseUrl}/weekly_data/${week}`, {
       method: 'POST',
-      headers: { 'Content-Type': 'application/json' },
+      headers: { 'Content-Type': 'application/json', ...authHeaders() },
       body: JSON.stringify([payload]),
     })
       .then(r => {
@@ -373,9 +496,9 @@ const TableView: React.FC = () => {
       total: computeTotal(rowToDelete),
     };
 
-    fetch(`${baseUrl}/del/${week}`, {
+    fetchWithSecondFactor(`${baseUrl}/del/${week}`, {
       method: 'POST',
-      headers: { 'Content-Type': 'application/json' },
+      headers: { 'Content-Type': 'application/json', ...authHeaders() },
       body: JSON.stringify(payload),
     })
       .then(response => {
@@ -442,7 +565,8 @@ const TableView: React.FC = () => {
     const blob = new Blob([csvContent], { type: 'text/csv;charset=utf-8;' });
     const link = document.createElement('a');
     link.setAttribute('href', URL.createObjectURL(blob));
-    link.setAttribute('download', `cohort-week-${week}.csv`);
+    const slug = programName.toLowerCase().replace(/[^a-z0-9]+/g, '-').replace(/^-|-$/g, '');
+    link.setAttribute('download', `${slug || 'cohort'}-week-${week}.csv`);
     document.body.appendChild(link);
     link.click();
     document.body.removeChild(link);
@@ -451,7 +575,7 @@ const TableView: React.FC = () => {
   return (
     <div className="p-4 sm:p-6 lg:p-8 bg-zinc-900 text-zinc-300/90 min-h-screen">
       <div className="max-w-full mx-auto">
-        <h1>Learning Bitcoin From Command Line</h1>
+        <h1>{programName}</h1>
         <h2 className="font-light">30th May - 27th july</h2>
         <h2 className="font-light">Github Classroom Master Repository</h2>
         <h3>Cohort Participants</h3>
@@ -474,6 +598,7 @@ const TableView: React.FC = () => {
           isEditing={isEditing}
           onEdit={() => setIsEditing(true)}
           onSave={handleSave}
+          onGenerate={handleGenerate}
           onAddNew={() => setShowAddStudentModal(true)}
           onDownloadCSV={handleDownloadCSV}
           onClearFilters={() => {
@@ -485,6 +610,14 @@ const TableView: React.FC = () => {
           navigate={navigate}
         />
 
+        {notice && (
+          <div className="mb-4 px-4 py-3 rounded border border-orange-400 text-orange-300">
+            {notice}
+          </div>
+        )}
+
+        <WeekChecklistPanel week={week} />
+
         <StudentTableGrid
           data={processedData}
           week={week}
@@ -1,10 +1,11 @@
 const API_BASE_URL = import.meta.env.VITE_API_BASE_URL;
-const AUTH_TOKEN = import.meta.env.VITE_AUTH_TOKEN;
 const DISCORD_CLIENT_ID = import.meta.env.VITE_DISCORD_CLIENT_ID;
 const DISCORD_REDIRECT_URI = import.meta.env.VITE_DISCORD_REDIRECT_URI;
 
+// Tokens are either the organizer token or a per-TA session token issued by
+// /login; the backend validates them on every request.
 export const isAuthenticated = (token?: string): boolean => {
-  return token === AUTH_TOKEN;
+  return !!token;
 };
 
 export const getTokenFromLocation = (location: {
@@ -30,6 +31,18 @@ export const getStoredToken = (): string | null => {
   return localStorage.getItem('bitshala_token');
 };
 
+// Revokes the current token on the backend before forgetting it locally
+export const logout = async (): Promise<void> => {
+  const token = getStoredToken();
+  if (token) {
+    await fetch(`${API_BASE_URL}/auth/logout`, {
+      method: 'POST',
+      headers: { Authorization: token },
+    }).catch(err => console.warn('Failed to revoke token:', err));
+  }
+  clearToken();
+};
+
 export const redirectToDiscordAuth = (): void => {
   const SCOPES = encodeURIComponent('identify guilds');
   const encodedRedirectUri = encodeURIComponent(DISCORD_REDIRECT_URI);
@@ -52,7 +65,7 @@ export const handleDiscordCallback = (
   const authSource = params.get('auth');
   const token = params.get('token');
 
-  if (authSource === 'discord' && token === AUTH_TOKEN) {
+  if (authSource === 'discord' && token) {
     navigate('/select', { state: { token: token ?? undefined } });
     return true;
   }
@@ -60,32 +73,54 @@ export const handleDiscordCallback = (
   return false;
 };
 
-export const loginWithEmail = async (
+// Asks the backend to email a one-time sign-in link. The backend answers the
+// same way whether or not the address belongs to a TA.
+export const requestMagicLink = async (
   email: string
-): Promise<{ success: boolean; error?: string; token?: string }> => {
+): Promise<{ success: boolean; error?: string }> => {
   if (!email) {
     return { success: false, error: 'Please enter your email address' };
   }
 
   try {
-    const response = await fetch(`${API_BASE_URL}/login`, {
+    const response = await fetch(`${API_BASE_URL}/login/magic`, {
       method: 'POST',
       headers: { 'Content-Type': 'application/json' },
       body: JSON.stringify({ gmail: email }),
     });
 
+    if (response.status === 503) {
+      return { success: false, error: 'Email sign-in is not available' };
+    }
     if (!response.ok) {
-      throw new Error('Access denied');
+      throw new Error('Could not send sign-in link');
     }
+    return { success: true };
+  } catch (err) {
+    const error = err instanceof Error ? err.message : 'Could not send sign-in link';
+    return { success: false, error };
+  }
+};
 
-    const data = await response.json();
-    const token = data.token;
+// Exchanges the `?magic=` token from an emailed link for a session token
+export const verifyMagicLink = async (
+  magicToken: string
+): Promise<{ success: boolean; error?: string; token?: string }> => {
+  try {
+    const response = await fetch(`${API_BASE_URL}/login/magic/verify`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ token: magicToken }),
+    });
 
-    if (token === AUTH_TOKEN) {
-      return { success: true, token };
-    } else {
-      return { success: false, error: 'Invalid token returned from server.' };
+    if (!response.ok) {
+      throw new Error('Sign-in link is invalid or expired');
     }
+
+    const data = await response.json();
+    return data.token
+      ? { success: true, token: data.token }
+      : { success: false, error: 'Invalid token returned from server.' };
   } catch (err) {
     const error = err instanceof Error ? err.message : 'Login failed';
     return { success: false, error };
@@ -101,9 +136,35 @@ export const checkAuthentication = (location: {
 };
 
 export const getAuthToken = (): string => {
-  return AUTH_TOKEN;
+  return getStoredToken() ?? '';
 };
 
+// Authorization header for backend API calls
+export const authHeaders = (): Record<string, string> => ({
+  Authorization: getStoredToken() ?? '',
+});
+
 export const getApiBaseUrl = (): string => {
   return API_BASE_URL;
 };
+
+// Retries a destructive request with a TOTP code when the backend asks for
+// the admin second factor
+export const fetchWithSecondFactor = async (
+  url: string,
+  init: RequestInit
+): Promise<Response> => {
+  const response = await fetch(url, init);
+  if (response.status !== 401) return response;
+
+  const body = await response.clone().json().catch(() => null);
+  if (!body?.totp_required) return response;
+
+  const code = window.prompt('Enter your authenticator code');
+  if (!code) return response;
+
+  return fetch(url, {
+    ...init,
+    headers: { ...(init.headers as Record<string, string>), 'X-TOTP-Code': code.trim() },
+  });
+};
@@ -1,5 +1,6 @@
 // src/services/studentService.ts
-import type { ApiStudentRecord, StudentData, StudentBackground } from '../types/student';
+import type { ApiStudentRecord, StudentData, StudentBackground, Communication, CommunicationKind, Branding } from '../types/student';
+import { authHeaders } from './auth';
 const baseUrl = import.meta.env.VITE_API_BASE_URL;
 // Helper to transform raw API response into a structured StudentData object
 const transformStudentData = (rawData: ApiStudentRecord[]): StudentData | null => {
@@ -43,7 +44,9 @@ const transformStudentData = (rawData: ApiStudentRecord[]): StudentData | null =
 export const fetchStudentData = async (name: string): Promise<StudentData | null> => {
   try {
     const encodedName = encodeURIComponent(name);
-    const response = await fetch(`${baseUrl}/students/${encodedName}`);
+    const response = await fetch(`${baseUrl}/students/${encodedName}`, {
+      headers: authHeaders(),
+    });
 
     if (!response.ok) {
       if (response.status === 404) {
@@ -64,7 +67,9 @@ export const fetchStudentData = async (name: string): Promise<StudentData | null
 
 export const fetchStudentBackgroundData = async (email: string): Promise<StudentBackground | null> => {
   try {
-    const response = await fetch(`${baseUrl}/data/${email}`);
+    const response = await fetch(`${baseUrl}/data/${email}`, {
+      headers: authHeaders(),
+    });
 
     if (!response.ok) {
       if (response.status === 404) {
@@ -86,7 +91,9 @@ export const fetchStudentRepoLink = async (week: number, studentName: string): P
   try {
     // Assuming your backend endpoint looks like /api/student-repo/week/name
     // Adjust the URL if your actual API endpoint is different
-    const response = await fetch(`${baseUrl}/students/${week}/${encodeURIComponent(studentName)}`);
+    const response = await fetch(`${baseUrl}/students/${week}/${encodeURIComponent(studentName)}`, {
+      headers: authHeaders(),
+    });
 
     if (!response.ok) {
       console.error(`Server Error ${response.status} fetching repo link for week ${week}, student ${studentName}`);
@@ -106,7 +113,9 @@ export const fetchStudentRepoLink = async (week: number, studentName: string): P
     try {
       // Assuming your backend endpoint looks like /api/student-repo/week/name
       // Adjust the URL if your actual API endpoint is different
-      const response = await fetch(`${baseUrl}/student/github/${encodeURIComponent(name)}`);
+      const response = await fetch(`${baseUrl}/student/github/${encodeURIComponent(name)}`, {
+        headers: authHeaders(),
+      });
 
       if (!response.ok) {
         console.error(`Server Error ${response.status} fetching github for student ${name}`);
@@ -121,4 +130,38 @@ export const fetchStudentRepoLink = async (week: number, studentName: string): P
       return null;
     }
 }
-  
\ No newline at end of file
+  
+export const fetchCommunications = async (name: string): Promise<Communication[]> => {
+  const response = await fetch(`${baseUrl}/communications/${encodeURIComponent(name)}`, {
+    headers: authHeaders(),
+  });
+  if (!response.ok) {
+    throw new Error(`Failed to fetch communications: ${response.status}`);
+  }
+  return response.json();
+};
+
+export const logCommunication = async (
+  name: string,
+  kind: CommunicationKind,
+  subject: string,
+  note?: string
+): Promise<Communication> => {
+  const response = await fetch(`${baseUrl}/communications/${encodeURIComponent(name)}`, {
+    method: 'POST',
+    headers: { ...authHeaders(), 'Content-Type': 'application/json' },
+    body: JSON.stringify({ kind, subject, note: note || null }),
+  });
+  if (!response.ok) {
+    throw new Error(`Failed to log communication: ${response.status}`);
+  }
+  return response.json();
+};
+
+export const fetchBranding = async (): Promise<Branding> => {
+  const response = await fetch(`${baseUrl}/branding`, { headers: authHeaders() });
+  if (!response.ok) {
+    throw new Error(`Failed to fetch branding: ${response.status}`);
+  }
+  return response.json();
+};
@@ -21,6 +21,11 @@ export interface ApiStudentRecord {
   exercise_good_structure: string;
   exercise_good_documentation: string;
   total: number;
+  notes?: string | null;
+  commit_count?: number | null;
+  last_commit_at?: string | null;
+  repo_url?: string | null;
+  version?: number | null;
 }
 
 // Score breakdowns
@@ -57,6 +62,10 @@ export interface TableRowData {
   exerciseScore: ExerciseScore;
   week?: number;
   total: number;
+  notes?: string;
+  repoUrl?: string;
+  // Sent back with every write so a stale copy is turned away
+  version?: number;
 }
 
 // Weekly data for student detail view
@@ -97,3 +106,46 @@ export interface StudentResult {
   total_score: number;
   exercise_total_score?: number;
 }
+
+// Communication log types
+export type CommunicationKind = 'email' | 'reminder' | 'invite_nudge' | 'other';
+
+export interface Communication {
+  id: number;
+  participant: string;
+  kind: CommunicationKind;
+  subject: string;
+  note?: string | null;
+  sent_by: string;
+  sent_at: string;
+}
+
+// Cohort branding used by exports and certificates
+export interface Branding {
+  program_name: string;
+  logo?: string | null;
+  signature?: string | null;
+  certificate_text?: string | null;
+  updated_by?: string | null;
+  updated_at?: string | null;
+}
+
+// Weekly organizer checklist types
+export type WeekTask =
+  | 'sync_roster'
+  | 'generate_groups'
+  | 'publish_announcement'
+  | 'send_reminders'
+  | 'finalize_grades';
+
+export interface ChecklistItem {
+  task: WeekTask;
+  completed_at: string | null;
+  completed_by: string | null;
+}
+
+export interface WeekChecklist {
+  week: number;
+  items: ChecklistItem[];
+  pending: number;
+}